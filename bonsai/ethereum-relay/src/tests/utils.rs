@@ -20,8 +20,10 @@ pub(crate) mod tests {
         SessionId,
     };
     use ethers::types::{Address, Bytes, H256};
-    use risc0_zkvm::{receipt_metadata::MaybePruned, sha::Digest, ExitCode, ReceiptMetadata};
-    use risc0_zkvm::{InnerReceipt, Journal, Receipt};
+    use risc0_zkvm::{
+        receipt_metadata::MaybePruned, sha::Digest, ExitCode, InnerReceipt, Journal, Receipt,
+        ReceiptMetadata,
+    };
     use uuid::Uuid;
     use wiremock::{
         matchers::{method, path},
@@ -55,6 +57,7 @@ pub(crate) mod tests {
                     output: None.into(),
                 },
             },
+            annotations: Default::default(),
         };
 
         let create_snark_res = CreateSessRes {
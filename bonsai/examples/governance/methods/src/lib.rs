@@ -40,7 +40,7 @@ mod test {
     #[test]
     fn process_basic_finalization_input() {
         let env = ExecutorEnv::builder()
-            .write_slice(&TEST_INPUT)
+            .write_bytes(TEST_INPUT)
             .build()
             .unwrap();
         let exec = default_executor();
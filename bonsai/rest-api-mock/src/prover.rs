@@ -95,7 +95,7 @@ impl Prover {
                 };
 
                 let env = ExecutorEnv::builder()
-                    .write_slice(&input)
+                    .write_bytes(&input)
                     .session_limit(None)
                     .segment_limit_po2(20)
                     .build()
@@ -118,6 +118,7 @@ impl Prover {
                         },
                     },
                     journal: session.journal,
+                    annotations: Default::default(),
                 };
                 let receipt_bytes = bincode::serialize(&receipt)?;
                 self.storage
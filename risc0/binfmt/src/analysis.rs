@@ -0,0 +1,317 @@
+// Copyright 2023 RISC Zero, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+extern crate alloc;
+
+use alloc::{string::String, vec::Vec};
+
+use anyhow::{anyhow, Result};
+use elf::{endian::LittleEndian, ElfBytes};
+use risc0_zkvm_platform::WORD_SIZE;
+
+use crate::elf::Program;
+
+/// The kind of expensive routine flagged by [Program::analyze].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum SymbolKind {
+    /// A compiler-rt software floating point routine. The zkVM has no floating
+    /// point unit, so every float operation in the guest calls one of these.
+    SoftFloat,
+
+    /// A bulk memory routine such as `memcpy`.
+    Memory,
+
+    /// Part of the `core::fmt` formatting machinery.
+    Formatting,
+}
+
+/// A function in a guest ELF that is known to be expensive to run in the
+/// zkVM.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FlaggedSymbol {
+    /// The symbol name, as found in the ELF symbol table.
+    pub name: String,
+
+    /// Why this symbol was flagged.
+    pub kind: SymbolKind,
+
+    /// The address of the first instruction.
+    pub start: u32,
+
+    /// The size of the function, in bytes.
+    pub size: u32,
+}
+
+impl FlaggedSymbol {
+    /// A rough estimate of the cycles spent in a single call, based on the
+    /// number of instructions in the function.
+    pub fn estimated_cycles(&self) -> u32 {
+        self.size / WORD_SIZE as u32
+    }
+
+    /// Returns `true` if `pc` falls within this function.
+    pub fn contains(&self, pc: u32) -> bool {
+        pc >= self.start && pc - self.start < self.size
+    }
+}
+
+/// A function in the symbol table of a guest ELF, as listed by
+/// [Program::functions].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FunctionSymbol {
+    /// The symbol name, as found in the ELF symbol table.
+    pub name: String,
+
+    /// The address of the first instruction.
+    pub start: u32,
+
+    /// The size of the function, in bytes.
+    pub size: u32,
+}
+
+impl FunctionSymbol {
+    /// Returns `true` if `pc` falls within this function.
+    pub fn contains(&self, pc: u32) -> bool {
+        pc >= self.start && pc - self.start < self.size
+    }
+}
+
+/// The result of [Program::analyze].
+///
+/// This is purely advisory: it points out routines linked into the guest that
+/// tend to dominate the cycle count when used unintentionally, most notably
+/// soft-float emulation pulled in by stray floating point arithmetic.
+#[derive(Clone, Debug, Default)]
+pub struct GuestAnalysis {
+    /// The flagged functions, sorted by start address.
+    pub symbols: Vec<FlaggedSymbol>,
+}
+
+impl GuestAnalysis {
+    /// Returns `true` if any soft-float routines are linked into the guest.
+    pub fn has_soft_float(&self) -> bool {
+        self.symbols_of(SymbolKind::SoftFloat).next().is_some()
+    }
+
+    /// Iterate over the flagged functions of the given kind.
+    pub fn symbols_of(&self, kind: SymbolKind) -> impl Iterator<Item = &FlaggedSymbol> {
+        self.symbols.iter().filter(move |sym| sym.kind == kind)
+    }
+
+    /// Find the flagged function containing `pc`, if any.
+    pub fn symbol_at(&self, pc: u32) -> Option<&FlaggedSymbol> {
+        let idx = self.symbols.partition_point(|sym| sym.start <= pc);
+        self.symbols[..idx].last().filter(|sym| sym.contains(pc))
+    }
+}
+
+// Classify a symbol name, returning `None` for symbols that are not known to
+// be expensive.
+fn classify(name: &str) -> Option<SymbolKind> {
+    const SOFT_FLOAT_PREFIXES: &[&str] = &["__float", "__fix", "__extend", "__trunc"];
+    const SOFT_FLOAT_SUFFIXES: &[&str] = &["sf2", "sf3", "df2", "df3", "tf2", "tf3"];
+    const MEMORY: &[&str] = &["memcpy", "memmove", "memset", "memcmp", "bcmp"];
+
+    if let Some(rest) = name.strip_prefix("__") {
+        let is_float_op = SOFT_FLOAT_SUFFIXES.iter().any(|s| rest.ends_with(s))
+            || SOFT_FLOAT_PREFIXES.iter().any(|p| name.starts_with(p));
+        let is_float_type = ["sf", "df", "tf"].iter().any(|t| rest.contains(t));
+        if is_float_op && is_float_type {
+            return Some(SymbolKind::SoftFloat);
+        }
+    }
+    if MEMORY.contains(&name) {
+        return Some(SymbolKind::Memory);
+    }
+    // Matches both legacy mangled names (`_ZN4core3fmt...`) and demangled ones.
+    if name.contains("4core3fmt") || name.starts_with("core::fmt") {
+        return Some(SymbolKind::Formatting);
+    }
+    None
+}
+
+// List the functions with a nonzero size in the symbol table of `elf`, along
+// with their names, or `None` if the ELF has been stripped.
+fn read_functions(elf: &[u8]) -> Result<Option<Vec<FunctionSymbol>>> {
+    let elf = ElfBytes::<LittleEndian>::minimal_parse(elf)
+        .map_err(|err| anyhow!("Elf parse error: {err}"))?;
+    let Some((symtab, strtab)) = elf
+        .symbol_table()
+        .map_err(|err| anyhow!("Elf parse error: {err}"))?
+    else {
+        return Ok(None);
+    };
+
+    let mut functions = Vec::new();
+    for sym in symtab.iter() {
+        if sym.st_symtype() != elf::abi::STT_FUNC || sym.st_size == 0 {
+            continue;
+        }
+        let name = strtab
+            .get(sym.st_name as usize)
+            .map_err(|err| anyhow!("Elf parse error: {err}"))?;
+        functions.push(FunctionSymbol {
+            name: name.into(),
+            start: sym.st_value as u32,
+            size: sym.st_size as u32,
+        });
+    }
+    Ok(Some(functions))
+}
+
+// Sort symbols by start address, keeping only the shortest name of functions
+// with more than one.
+fn sort_by_start<T>(symbols: &mut Vec<T>, key: impl Fn(&T) -> (u32, &str)) {
+    symbols.sort_by(|a, b| {
+        let (a, b) = (key(a), key(b));
+        (a.0, a.1.len()).cmp(&(b.0, b.1.len()))
+    });
+    symbols.dedup_by(|a, b| key(a).0 == key(b).0);
+}
+
+impl Program {
+    /// Scan the symbol table of a guest ELF for routines that are known to be
+    /// expensive in the zkVM.
+    ///
+    /// The ELF must not be stripped. Functions with more than one name are
+    /// reported once, under the shortest name.
+    pub fn analyze(elf: &[u8]) -> Result<GuestAnalysis> {
+        let functions = read_functions(elf)?.ok_or_else(|| anyhow!("Missing symbol table"))?;
+        let mut symbols: Vec<_> = functions
+            .into_iter()
+            .filter_map(|sym| {
+                Some(FlaggedSymbol {
+                    kind: classify(&sym.name)?,
+                    name: sym.name,
+                    start: sym.start,
+                    size: sym.size,
+                })
+            })
+            .collect();
+        sort_by_start(&mut symbols, |sym| (sym.start, &sym.name));
+
+        Ok(GuestAnalysis { symbols })
+    }
+
+    /// List the functions in the symbol table of a guest ELF, sorted by start
+    /// address.
+    ///
+    /// Returns an empty list if the ELF has been stripped. Functions with more
+    /// than one name are listed once, under the shortest name.
+    pub fn functions(elf: &[u8]) -> Result<Vec<FunctionSymbol>> {
+        let mut functions = read_functions(elf)?.unwrap_or_default();
+        sort_by_start(&mut functions, |sym| (sym.start, &sym.name));
+        Ok(functions)
+    }
+
+    /// Find the address of the symbol `name` in the symbol table of a guest
+    /// ELF, e.g. a `#[no_mangle]` function or `__global_pointer$`.
+    ///
+    /// Returns `None` if there is no such symbol, or the ELF has been
+    /// stripped.
+    pub fn symbol(elf: &[u8], name: &str) -> Result<Option<u32>> {
+        let elf = ElfBytes::<LittleEndian>::minimal_parse(elf)
+            .map_err(|err| anyhow!("Elf parse error: {err}"))?;
+        let Some((symtab, strtab)) = elf
+            .symbol_table()
+            .map_err(|err| anyhow!("Elf parse error: {err}"))?
+        else {
+            return Ok(None);
+        };
+
+        for sym in symtab.iter() {
+            if sym.st_shndx == elf::abi::SHN_UNDEF {
+                continue;
+            }
+            let sym_name = strtab
+                .get(sym.st_name as usize)
+                .map_err(|err| anyhow!("Elf parse error: {err}"))?;
+            if sym_name == name {
+                return Ok(Some(sym.st_value as u32));
+            }
+        }
+        Ok(None)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use risc0_zkvm_methods::MULTI_TEST_ELF;
+
+    use super::{classify, SymbolKind};
+    use crate::Program;
+
+    #[test]
+    fn classify_symbols() {
+        for name in [
+            "__adddf3",
+            "__mulsf3",
+            "__ltdf2",
+            "__floatsidf",
+            "__fixdfsi",
+        ] {
+            assert_eq!(classify(name), Some(SymbolKind::SoftFloat), "{name}");
+        }
+        assert_eq!(classify("memcpy"), Some(SymbolKind::Memory));
+        assert_eq!(
+            classify("_ZN4core3fmt9Formatter3pad17h0123456789abcdefE"),
+            Some(SymbolKind::Formatting)
+        );
+        for name in ["__divsi3", "__muldi3", "main", "__start"] {
+            assert_eq!(classify(name), None, "{name}");
+        }
+    }
+
+    #[test]
+    fn analyze_float_guest() {
+        // The multi_test guest formats a float, which links in both soft-float
+        // arithmetic and the float formatting machinery.
+        let analysis = Program::analyze(MULTI_TEST_ELF).unwrap();
+        assert!(analysis.has_soft_float());
+        assert!(analysis
+            .symbols_of(SymbolKind::Formatting)
+            .any(|sym| sym.name.contains("float")));
+
+        for sym in analysis.symbols.iter() {
+            assert!(sym.estimated_cycles() > 0);
+            assert_eq!(analysis.symbol_at(sym.start), Some(sym));
+            assert_eq!(analysis.symbol_at(sym.start + sym.size - 1), Some(sym));
+        }
+        assert_eq!(analysis.symbol_at(0), None);
+    }
+
+    #[test]
+    fn functions() {
+        let functions = Program::functions(MULTI_TEST_ELF).unwrap();
+        assert!(functions.windows(2).all(|x| x[0].start < x[1].start));
+        assert!(functions.iter().any(|sym| sym.name == "__start"));
+    }
+
+    #[test]
+    fn symbol() {
+        let functions = Program::functions(MULTI_TEST_ELF).unwrap();
+        let start = functions.iter().find(|sym| sym.name == "__start").unwrap();
+        assert_eq!(
+            Program::symbol(MULTI_TEST_ELF, "__start").unwrap(),
+            Some(start.start)
+        );
+        assert!(Program::symbol(MULTI_TEST_ELF, "__global_pointer$")
+            .unwrap()
+            .is_some());
+        assert_eq!(
+            Program::symbol(MULTI_TEST_ELF, "no_such_symbol").unwrap(),
+            None
+        );
+    }
+}
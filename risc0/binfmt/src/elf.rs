@@ -20,6 +20,8 @@ use anyhow::{anyhow, bail, Context, Result};
 use elf::{endian::LittleEndian, file::Class, ElfBytes};
 use risc0_zkvm_platform::WORD_SIZE;
 
+use crate::platform::{check_platform, PlatformCheck};
+
 /// A RISC Zero program
 pub struct Program {
     /// The entrypoint of the program
@@ -44,6 +46,10 @@ impl Program {
         if elf.ehdr.e_type != elf::abi::ET_EXEC {
             bail!("Invalid ELF type, must be executable");
         }
+        match check_platform(&elf)? {
+            PlatformCheck::Compatible => {}
+            check => tracing::warn!("{check}"),
+        }
         let entry: u32 = elf
             .ehdr
             .e_entry
@@ -106,3 +112,20 @@ impl Program {
         Ok(Program { entry, image })
     }
 }
+
+/// The contents of the section of `elf` called `name`, if it has one.
+pub(crate) fn section_data<'a>(
+    elf: &ElfBytes<'a, LittleEndian>,
+    name: &str,
+) -> Result<Option<&'a [u8]>> {
+    let Some(header) = elf
+        .section_header_by_name(name)
+        .map_err(|err| anyhow!("Elf parse error: {err}"))?
+    else {
+        return Ok(None);
+    };
+    let (data, _) = elf
+        .section_data(&header)
+        .map_err(|err| anyhow!("Elf parse error: {err}"))?;
+    Ok(Some(data))
+}
@@ -15,19 +15,24 @@
 extern crate alloc;
 
 use alloc::{collections::BTreeMap, vec, vec::Vec};
+use core::ops::Range;
 
 use anyhow::Result;
 use risc0_zkp::core::{
     digest::Digest,
-    hash::sha::{cpu::Impl, Sha256, BLOCK_BYTES, SHA256_INIT},
+    hash::sha::{cpu::Impl, BLOCK_BYTES},
 };
 use risc0_zkvm_platform::{
-    memory::{GUEST_MAX_MEM, MEM_SIZE, PAGE_TABLE},
+    memory::{GUEST_MAX_MEM, MEM_SIZE, PAGE_TABLE, TEXT_START},
     syscall::DIGEST_BYTES,
 };
 use serde::{Deserialize, Serialize};
 
-use crate::{elf::Program, Digestible, SystemState};
+use crate::{
+    elf::Program,
+    image_hasher::{default_image_hasher, ImageHasher},
+    Digestible, SystemState,
+};
 
 /// An image of a zkVM guest's memory
 ///
@@ -46,6 +51,69 @@ pub struct MemoryImage {
     pub pc: u32,
 }
 
+/// The difference between two [MemoryImage]s that share a page table layout.
+///
+/// Produced by [MemoryImage::diff] and consumed by [MemoryImage::apply_delta].
+/// Only the pages that changed are carried, including the page table pages
+/// whose entries changed as a result, so a delta between consecutive segments
+/// is typically a small fraction of the full image.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct ImageDelta {
+    /// The changed pages as a map from page index to the new page contents.
+    pub pages: BTreeMap<u32, Vec<u8>>,
+
+    /// The program counter of the new image.
+    pub pc: u32,
+
+    /// The root of the page table Merkle tree of the image the delta applies
+    /// to.
+    pub base_root: Digest,
+
+    /// The root of the page table Merkle tree of the new image.
+    pub root: Digest,
+}
+
+impl ImageDelta {
+    /// The total size of the changed pages, in bytes.
+    pub fn size(&self) -> usize {
+        self.pages.values().map(|page| page.len()).sum()
+    }
+}
+
+/// What a page of a [MemoryImage] holds, going by where it lies in memory.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PageKind {
+    /// Guest memory below the program, which holds the stack unless the guest
+    /// was given another layout.
+    Stack,
+
+    /// Guest memory from the start of the program: its code and data,
+    /// followed by the heap.
+    Program,
+
+    /// Memory reserved for the zkVM above guest memory.
+    System,
+
+    /// The page table, including its root.
+    PageTable,
+}
+
+/// A page present in a [MemoryImage]; see [MemoryImage::pages].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PageInfo {
+    /// The index of the page.
+    pub idx: u32,
+
+    /// The addresses the page covers.
+    pub addr_range: Range<u32>,
+
+    /// The digest of the contents of the page.
+    pub digest: Digest,
+
+    /// What the page holds.
+    pub kind: PageKind,
+}
+
 #[derive(Clone, Serialize, Deserialize)]
 struct PersistentPageTableInfo {
     page_size: u32,
@@ -165,6 +233,16 @@ impl MemoryImage {
     /// execution not yet begun), and with the page table Merkle tree
     /// constructed.
     pub fn new(program: &Program, page_size: u32) -> Result<Self> {
+        Self::new_with_hasher(program, page_size, default_image_hasher())
+    }
+
+    /// Construct the initial memory image for `program`, hashing its pages
+    /// with `hasher`.
+    pub fn new_with_hasher(
+        program: &Program,
+        page_size: u32,
+        hasher: &dyn ImageHasher,
+    ) -> Result<Self> {
         // Compute the page table hashes except for the very last root hash.
         let info = PageTableInfo::new(PAGE_TABLE.start() as u32, page_size);
         let mut img = Self {
@@ -181,10 +259,35 @@ impl MemoryImage {
             img.store_region_in_page(addr, &data.to_le_bytes());
         }
 
-        img.hash_pages();
+        img.hash_pages_with(hasher);
         Ok(img)
     }
 
+    /// List the pages present in this image, in order of index.
+    ///
+    /// Pages that are not present are zero pages, which the guest has not
+    /// written.
+    pub fn pages(&self) -> impl Iterator<Item = PageInfo> + '_ {
+        self.pages.keys().map(|&idx| {
+            let start = self.info.get_page_addr(idx);
+            let kind = if start >= self.info.page_table_addr {
+                PageKind::PageTable
+            } else if start as usize >= GUEST_MAX_MEM {
+                PageKind::System
+            } else if start < TEXT_START {
+                PageKind::Stack
+            } else {
+                PageKind::Program
+            };
+            PageInfo {
+                idx,
+                addr_range: start..start + self.info.page_size,
+                digest: self.page_digest(idx),
+                kind,
+            }
+        })
+    }
+
     /// Load a page specified by page_idx. If no page is found, a zero page is
     /// returned.
     pub fn load_page(&self, page_idx: u32) -> Vec<u8> {
@@ -232,22 +335,39 @@ impl MemoryImage {
 
     /// Calculate and update the image merkle tree within this image.
     pub fn hash_pages(&mut self) {
-        self.hash_pages_iter(0..self.info.num_pages);
+        self.hash_pages_with(default_image_hasher());
+    }
+
+    /// Calculate and update the image merkle tree within this image, hashing
+    /// its pages with `hasher`.
+    pub fn hash_pages_with(&mut self, hasher: &dyn ImageHasher) {
+        for page_idx in 0..self.info.num_pages {
+            let digest = self.page_digest_with(page_idx, hasher);
+            let entry_addr = self.info.get_page_entry_addr(page_idx);
+            self.store_region_in_page(entry_addr, digest.as_bytes());
+        }
     }
 
     /// Calculate and update the image merkle tree within this image based on
     /// the supplied page indicies.
     pub fn hash_pages_iter<I: Iterator<Item = u32>>(&mut self, iter: I) {
         for page_idx in iter {
-            let digest = self.hash_page(page_idx);
+            let digest = self.page_digest(page_idx);
             let entry_addr = self.info.get_page_entry_addr(page_idx);
             self.store_region_in_page(entry_addr, digest.as_bytes());
         }
     }
 
-    fn hash_page(&self, page_idx: u32) -> Digest {
+    /// Compute the digest of the page at `page_idx` from its contents.
+    ///
+    /// Pages that are not present in the image are zero pages.
+    pub fn page_digest(&self, page_idx: u32) -> Digest {
+        self.page_digest_with(page_idx, default_image_hasher())
+    }
+
+    fn page_digest_with(&self, page_idx: u32, hasher: &dyn ImageHasher) -> Digest {
         if let Some(page) = self.pages.get(&page_idx) {
-            hash_page_bytes(page)
+            hasher.hash_page(page)
         } else {
             self.info.zero_page_hash
         }
@@ -263,7 +383,7 @@ impl MemoryImage {
         let mut page_idx = self.info.get_page_index(addr);
         while page_idx < self.info.root_idx {
             let page_addr = self.info.get_page_addr(page_idx);
-            let expected = self.hash_page(page_idx);
+            let expected = self.page_digest(page_idx);
             let entry_addr = self.info.get_page_entry_addr(page_idx);
             let mut entry = [0_u8; DIGEST_BYTES];
             self.load_region_in_page(entry_addr, &mut entry);
@@ -290,6 +410,41 @@ impl MemoryImage {
         Ok(())
     }
 
+    /// Verify that every page hashes to its entry in the page table.
+    ///
+    /// The page table pages are themselves checked against their parent
+    /// entries, up to the root page, so any page whose contents no longer
+    /// match the root of this image is detected. Returns the first page, in
+    /// order of index, that fails the check.
+    pub fn verify_integrity(&self) -> Result<(), ImageError> {
+        let page_size = self.info.page_size as usize;
+        if let Some((&page_idx, page)) = self.pages.iter().find(|(_, x)| x.len() != page_size) {
+            return Err(ImageError::InvalidPageSize {
+                page_idx,
+                len: page.len(),
+            });
+        }
+        if !self.pages.contains_key(&self.info.root_idx) {
+            return Err(ImageError::MissingRootPage);
+        }
+
+        for page_idx in 0..self.info.root_idx {
+            let entry_addr = self.info.get_page_entry_addr(page_idx);
+            let mut entry = [0_u8; DIGEST_BYTES];
+            self.load_region_in_page(entry_addr, &mut entry);
+            let expected = Digest::from(entry);
+            let actual = self.page_digest(page_idx);
+            if expected != actual {
+                return Err(ImageError::PageMismatch {
+                    page_idx,
+                    expected,
+                    actual,
+                });
+            }
+        }
+        Ok(())
+    }
+
     /// Compute and return the root merkle entry of this image.
     pub fn compute_root_hash(&self) -> Digest {
         let root_page = self
@@ -303,29 +458,288 @@ impl MemoryImage {
     pub fn compute_id(&self) -> Digest {
         compute_image_id(&self.compute_root_hash(), self.pc)
     }
+
+    /// Compute the [ImageDelta] that turns this image into `other`.
+    ///
+    /// Both images must have been built with the same page table layout.
+    pub fn diff(&self, other: &MemoryImage) -> ImageDelta {
+        assert_eq!(self.info.page_size, other.info.page_size);
+        let zero_page = vec![0; self.info.page_size as usize];
+        let mut pages = BTreeMap::new();
+        for &page_idx in other.pages.keys().chain(self.pages.keys()) {
+            let before = self.pages.get(&page_idx).unwrap_or(&zero_page);
+            let after = other.pages.get(&page_idx).unwrap_or(&zero_page);
+            if before != after {
+                pages.insert(page_idx, after.clone());
+            }
+        }
+        ImageDelta {
+            pages,
+            pc: other.pc,
+            base_root: self.compute_root_hash(),
+            root: other.compute_root_hash(),
+        }
+    }
+
+    /// Apply an [ImageDelta] produced by [MemoryImage::diff].
+    ///
+    /// This image must be the one the delta was computed against, each changed
+    /// page must hash to its entry in the page table, and the resulting root
+    /// must match the root recorded in the delta. Otherwise an error is
+    /// returned and this image is left untouched.
+    pub fn apply_delta(&mut self, delta: &ImageDelta) -> Result<()> {
+        let base_root = self.compute_root_hash();
+        if base_root != delta.base_root {
+            anyhow::bail!("Image root mismatch: {} != {}", base_root, delta.base_root);
+        }
+
+        let page_size = self.info.page_size as usize;
+        let lookup = |page_idx: u32| delta.pages.get(&page_idx).or(self.pages.get(&page_idx));
+
+        for (&page_idx, page) in delta.pages.iter() {
+            if page.len() != page_size {
+                anyhow::bail!("Invalid page size in delta for page 0x{page_idx:08x}");
+            }
+            if page_idx >= self.info.root_idx {
+                continue;
+            }
+            let entry_addr = self.info.get_page_entry_addr(page_idx);
+            let entry_idx = self.info.get_page_index(entry_addr);
+            let offset = (entry_addr - self.info.get_page_addr(entry_idx)) as usize;
+            let actual = match lookup(entry_idx) {
+                Some(entry_page) => Digest::try_from(&entry_page[offset..offset + DIGEST_BYTES])?,
+                None => Digest::ZERO,
+            };
+            let expected = hash_page_bytes(page);
+            if expected != actual {
+                anyhow::bail!(
+                    "Invalid page table entry for page 0x{page_idx:08x}: {expected} != {actual}"
+                );
+            }
+        }
+
+        let root_page =
+            lookup(self.info.root_idx).ok_or_else(|| anyhow::anyhow!("Missing root page?"))?;
+        let root = hash_page_bytes(
+            &root_page[..(self.info.root_addr - self.info.root_page_addr) as usize],
+        );
+        if root != delta.root {
+            anyhow::bail!("Image root mismatch: {} != {}", root, delta.root);
+        }
+
+        self.pages
+            .extend(delta.pages.iter().map(|(&idx, page)| (idx, page.clone())));
+        self.pc = delta.pc;
+        Ok(())
+    }
+}
+
+/// The first bytes of a file written by [MemoryImage::save].
+#[cfg(feature = "std")]
+const IMAGE_FILE_MAGIC: &[u8; 8] = b"R0IMAGE\0";
+
+/// The version of the layout of files written by [MemoryImage::save].
+#[cfg(feature = "std")]
+const IMAGE_FILE_VERSION: u32 = 1;
+
+/// Written in little-endian order after the version, so that a file written
+/// with another byte order is rejected rather than misread.
+#[cfg(feature = "std")]
+const IMAGE_FILE_BYTE_ORDER: u32 = 0x0102_0304;
+
+#[cfg(feature = "std")]
+impl MemoryImage {
+    /// Save this image to the file at `path`, replacing any existing file.
+    ///
+    /// Loading the image with [MemoryImage::load] is faster than building it
+    /// from an ELF again, since the page table is not recomputed.
+    ///
+    /// The file starts with a header giving its format version and byte
+    /// order, the page table layout, the program counter and the root of the
+    /// page table, followed by each page with its index. All integers are
+    /// little-endian.
+    pub fn save(&self, path: impl AsRef<std::path::Path>) -> Result<()> {
+        use std::io::Write;
+
+        let mut file = std::io::BufWriter::new(std::fs::File::create(path)?);
+        file.write_all(IMAGE_FILE_MAGIC)?;
+        for word in [
+            IMAGE_FILE_VERSION,
+            IMAGE_FILE_BYTE_ORDER,
+            self.info.page_size,
+            self.info.page_table_addr,
+            self.pc,
+        ] {
+            file.write_all(&word.to_le_bytes())?;
+        }
+        file.write_all(self.compute_root_hash().as_bytes())?;
+        file.write_all(&(self.pages.len() as u32).to_le_bytes())?;
+        for (page_idx, page) in self.pages.iter() {
+            file.write_all(&page_idx.to_le_bytes())?;
+            file.write_all(page)?;
+        }
+        file.flush()?;
+        Ok(())
+    }
+
+    /// Load an image saved with [MemoryImage::save].
+    ///
+    /// Files written by an incompatible version of this crate, or with a
+    /// different byte order, are rejected, as are files whose root page does
+    /// not hash to the root recorded in the header. The other pages are not
+    /// checked here; see [MemoryImage::verify_integrity].
+    pub fn load(path: impl AsRef<std::path::Path>) -> Result<Self> {
+        let bytes = std::fs::read(path)?;
+        let mut rest = bytes.as_slice();
+
+        if take(&mut rest, IMAGE_FILE_MAGIC.len())? != IMAGE_FILE_MAGIC {
+            anyhow::bail!("Not a memory image file");
+        }
+        let version = take_word(&mut rest)?;
+        if version != IMAGE_FILE_VERSION {
+            anyhow::bail!(
+                "Unsupported memory image file version: {version}, expected {IMAGE_FILE_VERSION}"
+            );
+        }
+        if take_word(&mut rest)? != IMAGE_FILE_BYTE_ORDER {
+            anyhow::bail!("Memory image file was written with a different byte order");
+        }
+        let page_size = take_word(&mut rest)?;
+        let page_table_addr = take_word(&mut rest)?;
+        if !page_size.is_power_of_two()
+            || page_size < BLOCK_BYTES as u32
+            || page_table_addr != PAGE_TABLE.start() as u32
+        {
+            anyhow::bail!(
+                "Unsupported page table layout: page size {page_size}, page table at 0x{page_table_addr:08x}"
+            );
+        }
+        let pc = take_word(&mut rest)?;
+        let root = Digest::try_from(take(&mut rest, DIGEST_BYTES)?)?;
+        let num_pages = take_word(&mut rest)?;
+
+        let info = PageTableInfo::new(page_table_addr, page_size);
+        let mut pages = BTreeMap::new();
+        for _ in 0..num_pages {
+            let page_idx = take_word(&mut rest)?;
+            if page_idx > info.root_idx {
+                anyhow::bail!("Invalid page index in memory image file: 0x{page_idx:08x}");
+            }
+            pages.insert(page_idx, take(&mut rest, page_size as usize)?.to_vec());
+        }
+        if !rest.is_empty() {
+            anyhow::bail!("Unexpected data after the last page of the memory image file");
+        }
+
+        let image = Self { pages, info, pc };
+        if !image.pages.contains_key(&image.info.root_idx) {
+            return Err(ImageError::MissingRootPage.into());
+        }
+        let actual = image.compute_root_hash();
+        if actual != root {
+            anyhow::bail!("Image root mismatch: {actual} != {root}");
+        }
+        Ok(image)
+    }
+}
+
+// Split the first `len` bytes off `rest`.
+#[cfg(feature = "std")]
+fn take<'b>(rest: &mut &'b [u8], len: usize) -> Result<&'b [u8]> {
+    if rest.len() < len {
+        anyhow::bail!("Truncated memory image file");
+    }
+    let (head, tail) = rest.split_at(len);
+    *rest = tail;
+    Ok(head)
+}
+
+#[cfg(feature = "std")]
+fn take_word(rest: &mut &[u8]) -> Result<u32> {
+    Ok(u32::from_le_bytes(take(rest, 4)?.try_into()?))
+}
+
+/// An inconsistency found by [MemoryImage::verify_integrity].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ImageError {
+    /// The contents of a page do not hash to its entry in the page table.
+    PageMismatch {
+        /// The index of the page.
+        page_idx: u32,
+        /// The digest recorded in the page table.
+        expected: Digest,
+        /// The digest of the contents of the page.
+        actual: Digest,
+    },
+
+    /// A page does not have the size given by the page table layout.
+    InvalidPageSize {
+        /// The index of the page.
+        page_idx: u32,
+        /// The size of the page, in bytes.
+        len: usize,
+    },
+
+    /// The image does not contain the root page of the page table.
+    MissingRootPage,
 }
 
+impl core::fmt::Display for ImageError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            ImageError::PageMismatch {
+                page_idx,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "Corrupted memory image: page 0x{page_idx:08x} hashes to {actual} but the page table records {expected}"
+            ),
+            ImageError::InvalidPageSize { page_idx, len } => write!(
+                f,
+                "Corrupted memory image: page 0x{page_idx:08x} has {len} bytes"
+            ),
+            ImageError::MissingRootPage => {
+                write!(f, "Corrupted memory image: missing root page")
+            }
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for ImageError {}
+
 fn hash_page_bytes(page: &[u8]) -> Digest {
-    let mut state = SHA256_INIT;
-    assert!(page.len() % BLOCK_BYTES == 0);
-    for block in page.chunks_exact(BLOCK_BYTES) {
-        let block1 = Digest::try_from(&block[0..DIGEST_BYTES]).unwrap();
-        let block2 = Digest::try_from(&block[DIGEST_BYTES..BLOCK_BYTES]).unwrap();
-        state = *Impl::compress(&state, &block1, &block2);
-    }
-    state
+    default_image_hasher().hash_page(page)
 }
 
 #[cfg(test)]
 mod tests {
+    use std::collections::BTreeMap;
+
     use risc0_zkvm_methods::MULTI_TEST_ELF;
     use risc0_zkvm_platform::{
         memory::{GUEST_MAX_MEM, PAGE_TABLE, STACK_TOP, SYSTEM, TEXT_START},
         syscall::DIGEST_BYTES,
+        PAGE_SIZE, WORD_SIZE,
     };
     use test_log::test;
 
-    use crate::{elf::Program, image::PageTableInfo, MemoryImage};
+    use crate::{
+        elf::Program, image::PageTableInfo, AcceleratedImageHasher, ImageError, ImageHasher,
+        MemoryImage, PageKind, ScalarImageHasher,
+    };
+
+    fn touch_pages(image: &MemoryImage, pages: &[u32]) -> MemoryImage {
+        let mut image = image.clone();
+        for &page_idx in pages {
+            let addr = image.info.get_page_addr(page_idx);
+            image.store_region_in_page(addr, &0xdeadbeef_u32.to_le_bytes());
+        }
+        image.hash_pages();
+        image.pc += 4;
+        image
+    }
 
     fn page_table_size(max_mem: u32, page_size: u32) -> u32 {
         PageTableInfo::new(max_mem, page_size)._page_table_size
@@ -348,6 +762,152 @@ mod tests {
         image.check(image.info.root_page_addr).unwrap();
     }
 
+    #[test]
+    fn save_and_load() {
+        const PAGE_SIZE: u32 = 1024;
+        let program = Program::load_elf(MULTI_TEST_ELF, GUEST_MAX_MEM as u32).unwrap();
+        let image = MemoryImage::new(&program, PAGE_SIZE).unwrap();
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("multi_test.image");
+        image.save(&path).unwrap();
+
+        let loaded = MemoryImage::load(&path).unwrap();
+        assert_eq!(loaded.pc, image.pc);
+        assert_eq!(loaded.pages, image.pages);
+        assert_eq!(loaded.compute_id(), image.compute_id());
+        loaded.verify_integrity().unwrap();
+
+        let bytes = std::fs::read(&path).unwrap();
+        let corrupt = |offset: usize, byte: u8| {
+            let mut bytes = bytes.clone();
+            bytes[offset] = byte;
+            std::fs::write(&path, bytes).unwrap();
+            MemoryImage::load(&path).err().unwrap().to_string()
+        };
+        // The header is the magic, five words and the root, then the page
+        // count; each page follows its index.
+        assert!(corrupt(0, b'X').contains("Not a memory image file"));
+        assert!(corrupt(8, 2).contains("Unsupported memory image file version"));
+        assert!(corrupt(12, 1).contains("different byte order"));
+        assert!(corrupt(16, 3).contains("Unsupported page table layout"));
+        assert!(corrupt(32, !bytes[32]).contains("Image root mismatch"));
+
+        let root_page = image.info.root_idx;
+        let root_offset = 64 + (image.pages.len() - 1) * (4 + PAGE_SIZE as usize) + 4;
+        assert_eq!(bytes[root_offset - 4..root_offset], root_page.to_le_bytes());
+        assert!(corrupt(root_offset, !bytes[root_offset]).contains("Image root mismatch"));
+
+        std::fs::write(&path, &bytes[..bytes.len() - 1]).unwrap();
+        assert!(MemoryImage::load(&path)
+            .err()
+            .unwrap()
+            .to_string()
+            .contains("Truncated"));
+    }
+
+    #[test]
+    fn verify_integrity() {
+        const PAGE_SIZE: u32 = 1024;
+        let program = Program::load_elf(MULTI_TEST_ELF, GUEST_MAX_MEM as u32).unwrap();
+        let mut image = MemoryImage::new(&program, PAGE_SIZE).unwrap();
+        image.verify_integrity().unwrap();
+
+        let page_idx = image.info.get_page_index(TEXT_START);
+        let expected = image.page_digest(page_idx);
+        image.pages.get_mut(&page_idx).unwrap()[7] ^= 1;
+        assert_eq!(
+            image.verify_integrity(),
+            Err(ImageError::PageMismatch {
+                page_idx,
+                expected,
+                actual: image.page_digest(page_idx),
+            })
+        );
+
+        image.pages.remove(&image.info.root_idx);
+        assert_eq!(image.verify_integrity(), Err(ImageError::MissingRootPage));
+    }
+
+    #[test]
+    fn pages() {
+        const PAGE_SIZE: u32 = 1024;
+        let program = Program::load_elf(MULTI_TEST_ELF, GUEST_MAX_MEM as u32).unwrap();
+        let image = MemoryImage::new(&program, PAGE_SIZE).unwrap();
+        let pages: Vec<_> = image.pages().collect();
+        assert_eq!(pages.len(), image.pages.len());
+
+        let entry = image.info.get_page_index(program.entry);
+        let page = pages.iter().find(|page| page.idx == entry).unwrap();
+        assert_eq!(page.kind, PageKind::Program);
+        assert!(page.addr_range.contains(&program.entry));
+        assert_eq!(page.addr_range.len(), PAGE_SIZE as usize);
+        assert_eq!(page.digest, image.page_digest(entry));
+
+        let root = pages.last().unwrap();
+        assert_eq!(root.idx, image.info.root_idx);
+        assert_eq!(root.kind, PageKind::PageTable);
+        assert!(pages
+            .iter()
+            .filter(|page| page.kind == PageKind::PageTable)
+            .all(|page| page.addr_range.start >= PAGE_TABLE.start() as u32));
+    }
+
+    #[test]
+    fn image_delta() {
+        const PAGE_SIZE: u32 = 1024;
+        let program = Program::load_elf(MULTI_TEST_ELF, GUEST_MAX_MEM as u32).unwrap();
+        let image = MemoryImage::new(&program, PAGE_SIZE).unwrap();
+        let first_page = image.info.get_page_index(STACK_TOP) - 3;
+        let touched = [first_page, first_page + 1, first_page + 2];
+        let next = touch_pages(&image, &touched);
+
+        let delta = image.diff(&next);
+        for page_idx in touched {
+            assert!(delta.pages.contains_key(&page_idx));
+        }
+        // The touched pages plus at most one page table page per layer.
+        assert!(delta.pages.len() <= touched.len() + image.info._layers.len());
+        assert!(delta.size() * 100 < image.pages.len() * PAGE_SIZE as usize);
+        assert_eq!(delta.root, next.compute_root_hash());
+
+        let mut applied = image.clone();
+        applied.apply_delta(&delta).unwrap();
+        assert_eq!(applied.compute_root_hash(), next.compute_root_hash());
+        assert_eq!(applied.compute_id(), next.compute_id());
+        applied.check(STACK_TOP - PAGE_SIZE).unwrap();
+        assert_eq!(image.diff(&applied).size(), delta.size());
+        assert!(next.diff(&applied).pages.is_empty());
+    }
+
+    #[test]
+    fn image_delta_root_mismatch() {
+        const PAGE_SIZE: u32 = 1024;
+        let program = Program::load_elf(MULTI_TEST_ELF, GUEST_MAX_MEM as u32).unwrap();
+        let image = MemoryImage::new(&program, PAGE_SIZE).unwrap();
+        let page_idx = image.info.get_page_index(STACK_TOP) - 1;
+        let next = touch_pages(&image, &[page_idx]);
+
+        // The delta only applies to the image it was computed against.
+        let delta = image.diff(&next);
+        let mut applied = next.clone();
+        let err = applied.apply_delta(&delta).unwrap_err();
+        assert!(err.to_string().contains("Image root mismatch"));
+        assert_eq!(applied.compute_id(), next.compute_id());
+
+        let mut delta = image.diff(&next);
+        delta.root = image.compute_root_hash();
+        let mut applied = image.clone();
+        let err = applied.apply_delta(&delta).unwrap_err();
+        assert!(err.to_string().contains("Image root mismatch"));
+        assert_eq!(applied.compute_id(), image.compute_id());
+
+        // Tampering with a data page is caught by its page table entry.
+        let mut delta = image.diff(&next);
+        delta.pages.get_mut(&page_idx).unwrap()[0] ^= 1;
+        let err = applied.apply_delta(&delta).unwrap_err();
+        assert!(err.to_string().contains("Invalid page table entry"));
+    }
+
     #[test]
     fn page_table_info() {
         const PAGE_SIZE_1K: u32 = 1024;
@@ -451,6 +1011,52 @@ mod tests {
         );
     }
 
+    #[test]
+    fn image_hashers_agree() {
+        // A xorshift generator, so the images are random but reproducible.
+        let mut state = 0x2545_f491_4f6c_dd1d_u64;
+        let mut next = move || {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            state
+        };
+
+        for _ in 0..8 {
+            let mut image = MemoryImage {
+                pages: BTreeMap::new(),
+                info: PageTableInfo::new(PAGE_TABLE.start() as u32, PAGE_SIZE as u32),
+                pc: 0,
+            };
+            // Scatter words over memory, and fill some pages completely.
+            for _ in 0..256 {
+                let addr = (next() % GUEST_MAX_MEM as u64) as u32 & !3;
+                image.store_region_in_page(addr, &(next() as u32).to_le_bytes());
+            }
+            for _ in 0..16 {
+                let page_idx =
+                    (next() % image.info.get_page_index(GUEST_MAX_MEM as u32) as u64) as u32;
+                let page_addr = image.info.get_page_addr(page_idx);
+                for offset in (0..PAGE_SIZE as u32).step_by(WORD_SIZE) {
+                    image.store_region_in_page(page_addr + offset, &(next() as u32).to_le_bytes());
+                }
+            }
+
+            for page in image.pages.values() {
+                assert_eq!(
+                    AcceleratedImageHasher.hash_page(page),
+                    ScalarImageHasher.hash_page(page)
+                );
+            }
+            let mut scalar = image.clone();
+            scalar.hash_pages_with(&ScalarImageHasher);
+            image.hash_pages_with(&AcceleratedImageHasher);
+            assert_eq!(image.pages, scalar.pages);
+            assert_eq!(image.compute_id(), scalar.compute_id());
+            image.verify_integrity().unwrap();
+        }
+    }
+
     #[test]
     #[should_panic(expected = "exceeds maximum address for guest programs")]
     fn test_fuzzing_oob_idx_bug() {
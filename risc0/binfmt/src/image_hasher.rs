@@ -0,0 +1,116 @@
+// Copyright 2023 RISC Zero, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use risc0_zkp::core::{
+    digest::{Digest, DIGEST_WORDS},
+    hash::sha::{cpu::Impl, Sha256, BLOCK_BYTES, SHA256_INIT},
+};
+use risc0_zkvm_platform::syscall::DIGEST_BYTES;
+use sha2::digest::generic_array::GenericArray;
+
+/// Hashes the pages of a [MemoryImage](crate::MemoryImage) to build its
+/// Merkle tree.
+///
+/// A page is hashed by running the SHA-256 compression function over each of
+/// its 64-byte blocks in turn, starting from the SHA-256 initial state, with
+/// no padding. Implementations differ only in how fast they do this: every
+/// one must produce the same digests, and so the same image IDs.
+pub trait ImageHasher {
+    /// A short name for the implementation, e.g. for benchmarks.
+    fn name(&self) -> &'static str;
+
+    /// Hash a page, whose length is a multiple of 64 bytes.
+    fn hash_page(&self, page: &[u8]) -> Digest;
+}
+
+/// Hashes a page one block at a time through [Sha256::compress]. This is the
+/// reference that other implementations are checked against.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct ScalarImageHasher;
+
+impl ImageHasher for ScalarImageHasher {
+    fn name(&self) -> &'static str {
+        "scalar"
+    }
+
+    fn hash_page(&self, page: &[u8]) -> Digest {
+        let mut state = SHA256_INIT;
+        assert!(page.len() % BLOCK_BYTES == 0);
+        for block in page.chunks_exact(BLOCK_BYTES) {
+            let block1 = Digest::try_from(&block[0..DIGEST_BYTES]).unwrap();
+            let block2 = Digest::try_from(&block[DIGEST_BYTES..BLOCK_BYTES]).unwrap();
+            state = *Impl::compress(&state, &block1, &block2);
+        }
+        state
+    }
+}
+
+/// Hashes a whole page with one call to the `sha2` crate's compression
+/// function, which uses the SHA extensions on x86-64 and the SHA2
+/// instructions on AArch64 when the CPU has them.
+///
+/// Unlike [ScalarImageHasher], this neither copies nor allocates per block.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct AcceleratedImageHasher;
+
+impl ImageHasher for AcceleratedImageHasher {
+    fn name(&self) -> &'static str {
+        "accelerated"
+    }
+
+    fn hash_page(&self, page: &[u8]) -> Digest {
+        assert!(page.len() % BLOCK_BYTES == 0);
+        // Digests hold the state as big-endian words; sha2 works on native
+        // ones.
+        let mut state: [u32; DIGEST_WORDS] = SHA256_INIT.into();
+        for word in state.iter_mut() {
+            *word = word.to_be();
+        }
+        for block in page.chunks_exact(BLOCK_BYTES).map(GenericArray::from_slice) {
+            sha2::compress256(&mut state, core::slice::from_ref(block));
+        }
+        for word in state.iter_mut() {
+            *word = word.to_be();
+        }
+        Digest::from(state)
+    }
+}
+
+/// Returns the fastest [ImageHasher] for this CPU: the
+/// [AcceleratedImageHasher] if the CPU has SHA-256 instructions, and the
+/// [ScalarImageHasher] otherwise.
+pub fn default_image_hasher() -> &'static dyn ImageHasher {
+    if has_sha_instructions() {
+        &AcceleratedImageHasher
+    } else {
+        &ScalarImageHasher
+    }
+}
+
+#[cfg(all(feature = "std", target_arch = "x86_64"))]
+fn has_sha_instructions() -> bool {
+    std::is_x86_feature_detected!("sha")
+        && std::is_x86_feature_detected!("sse4.1")
+        && std::is_x86_feature_detected!("ssse3")
+}
+
+#[cfg(all(feature = "std", target_arch = "aarch64"))]
+fn has_sha_instructions() -> bool {
+    std::arch::is_aarch64_feature_detected!("sha2")
+}
+
+#[cfg(not(all(feature = "std", any(target_arch = "x86_64", target_arch = "aarch64"))))]
+fn has_sha_instructions() -> bool {
+    false
+}
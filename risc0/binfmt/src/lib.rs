@@ -16,16 +16,36 @@
 
 #![cfg_attr(all(not(feature = "std"), not(test)), no_std)]
 
+#[cfg(not(target_os = "zkvm"))]
+mod analysis;
 mod elf;
 mod hash;
 #[cfg(not(target_os = "zkvm"))]
 mod image;
+#[cfg(not(target_os = "zkvm"))]
+mod image_hasher;
+mod platform;
+#[cfg(not(target_os = "zkvm"))]
+mod schema;
 mod sys_state;
 
 #[cfg(not(target_os = "zkvm"))]
-pub use crate::image::{compute_image_id, MemoryImage, PageTableInfo};
+pub use crate::analysis::{FlaggedSymbol, FunctionSymbol, GuestAnalysis, SymbolKind};
+#[cfg(not(target_os = "zkvm"))]
+pub use crate::image::{
+    compute_image_id, ImageDelta, ImageError, MemoryImage, PageInfo, PageKind, PageTableInfo,
+};
+#[cfg(not(target_os = "zkvm"))]
+pub use crate::image_hasher::{
+    default_image_hasher, AcceleratedImageHasher, ImageHasher, ScalarImageHasher,
+};
+#[cfg(not(target_os = "zkvm"))]
+pub use crate::schema::{
+    input_schema_digest, JournalSchema, INPUT_SCHEMA_SECTION, JOURNAL_SCHEMA_SECTION,
+};
 pub use crate::{
     elf::Program,
     hash::{tagged_list, tagged_list_cons, tagged_struct, Digestible},
+    platform::{PlatformCheck, PLATFORM_VERSION_SECTION},
     sys_state::{read_sha_halfs, write_sha_halfs, SystemState},
 };
@@ -0,0 +1,253 @@
+// Copyright 2023 RISC Zero, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+extern crate alloc;
+
+use alloc::string::{String, ToString};
+use core::fmt;
+
+use anyhow::{anyhow, Result};
+use elf::{endian::LittleEndian, ElfBytes};
+
+use crate::elf::{section_data, Program};
+
+/// The name of the ELF section in which a guest records the version of
+/// `risc0-zkvm-platform` it was built with.
+///
+/// The section holds the version followed by a NUL byte.
+pub const PLATFORM_VERSION_SECTION: &str = ".risc0.platform_version";
+
+/// How the version of `risc0-zkvm-platform` that a guest was built with
+/// compares with the one this host expects; see [Program::check_platform].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum PlatformCheck {
+    /// The guest was built with a compatible version.
+    Compatible,
+
+    /// The guest was built with a version whose syscalls or memory layout
+    /// may differ from this host's.
+    Incompatible {
+        /// The version the guest was built with.
+        guest: String,
+    },
+
+    /// The guest does not record a version, e.g. because it was built by an
+    /// older toolchain.
+    Unknown,
+}
+
+impl fmt::Display for PlatformCheck {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let host = risc0_zkvm_platform::VERSION;
+        match self {
+            PlatformCheck::Compatible => {
+                write!(f, "guest platform is compatible with risc0-zkvm-platform {host}")
+            }
+            PlatformCheck::Incompatible { guest } => write!(
+                f,
+                "guest was built with risc0-zkvm-platform {guest}, which is not compatible with {host}"
+            ),
+            PlatformCheck::Unknown => write!(
+                f,
+                "guest was built with an unknown platform: it has no {PLATFORM_VERSION_SECTION} section, so it may predate risc0-zkvm-platform {host}"
+            ),
+        }
+    }
+}
+
+impl Program {
+    /// Read the version of `risc0-zkvm-platform` that a guest ELF was built
+    /// with, if it records one.
+    pub fn platform_version(elf: &[u8]) -> Result<Option<String>> {
+        let elf = ElfBytes::<LittleEndian>::minimal_parse(elf)
+            .map_err(|err| anyhow!("Elf parse error: {err}"))?;
+        platform_version(&elf)
+    }
+
+    /// Compare the version of `risc0-zkvm-platform` that a guest ELF was
+    /// built with against the one this host expects.
+    ///
+    /// Versions are compatible if they are equal, or if neither is a
+    /// pre-release and they have the same major and minor version.
+    /// [Program::load_elf] logs a warning for a guest that is not
+    /// compatible.
+    pub fn check_platform(elf: &[u8]) -> Result<PlatformCheck> {
+        let elf = ElfBytes::<LittleEndian>::minimal_parse(elf)
+            .map_err(|err| anyhow!("Elf parse error: {err}"))?;
+        check_platform(&elf)
+    }
+}
+
+pub(crate) fn platform_version(elf: &ElfBytes<LittleEndian>) -> Result<Option<String>> {
+    let Some(data) = section_data(elf, PLATFORM_VERSION_SECTION)? else {
+        return Ok(None);
+    };
+    let version = data.split(|&b| b == 0).next().unwrap_or_default();
+    let version =
+        core::str::from_utf8(version).map_err(|err| anyhow!("Invalid platform version: {err}"))?;
+    Ok(Some(version.to_string()))
+}
+
+pub(crate) fn check_platform(elf: &ElfBytes<LittleEndian>) -> Result<PlatformCheck> {
+    Ok(match platform_version(elf)? {
+        None => PlatformCheck::Unknown,
+        Some(guest) if is_compatible(&guest, risc0_zkvm_platform::VERSION) => {
+            PlatformCheck::Compatible
+        }
+        Some(guest) => PlatformCheck::Incompatible { guest },
+    })
+}
+
+fn is_compatible(guest: &str, host: &str) -> bool {
+    if guest == host {
+        return true;
+    }
+    // Everything before the patch version, for a version that is not a
+    // pre-release.
+    let major_minor = |version: &str| {
+        if version.contains('-') {
+            return None;
+        }
+        let (major_minor, _patch) = version.rsplit_once('.')?;
+        Some(major_minor.to_string())
+    };
+    matches!((major_minor(guest), major_minor(host)), (Some(a), Some(b)) if a == b)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{is_compatible, PlatformCheck, PLATFORM_VERSION_SECTION};
+    use crate::Program;
+
+    // Build a minimal RISC-V executable with one word of code and, if given,
+    // a platform version section holding `version`.
+    fn fixture_elf(version: Option<&str>) -> Vec<u8> {
+        const EHDR: usize = 52;
+        const PHDR: usize = 32;
+        const SHDR: usize = 40;
+        let code_offset = EHDR + PHDR;
+        let mut shstrtab = b"\0.shstrtab\0".to_vec();
+        let mut sections = Vec::new();
+        let data_offset = code_offset + 4;
+        let mut data = Vec::new();
+        if let Some(version) = version {
+            sections.push((
+                shstrtab.len(),
+                1u32,
+                data_offset + data.len(),
+                version.len() + 1,
+            ));
+            shstrtab.extend_from_slice(PLATFORM_VERSION_SECTION.as_bytes());
+            shstrtab.push(0);
+            data.extend_from_slice(version.as_bytes());
+            data.push(0);
+        }
+        let shstrtab_offset = data_offset + data.len();
+        sections.push((1, 3, shstrtab_offset, shstrtab.len()));
+        let shoff = shstrtab_offset + shstrtab.len();
+
+        let mut elf = Vec::new();
+        let half = |elf: &mut Vec<u8>, x: u16| elf.extend_from_slice(&x.to_le_bytes());
+        let word = |elf: &mut Vec<u8>, x: u32| elf.extend_from_slice(&x.to_le_bytes());
+        elf.extend_from_slice(b"\x7fELF\x01\x01\x01\0\0\0\0\0\0\0\0\0");
+        half(&mut elf, elf::abi::ET_EXEC);
+        half(&mut elf, elf::abi::EM_RISCV);
+        word(&mut elf, 1);
+        word(&mut elf, 0x1000); // e_entry
+        word(&mut elf, EHDR as u32); // e_phoff
+        word(&mut elf, shoff as u32);
+        word(&mut elf, 0); // e_flags
+        half(&mut elf, EHDR as u16);
+        half(&mut elf, PHDR as u16);
+        half(&mut elf, 1);
+        half(&mut elf, SHDR as u16);
+        half(&mut elf, sections.len() as u16 + 1);
+        half(&mut elf, sections.len() as u16); // e_shstrndx
+
+        word(&mut elf, elf::abi::PT_LOAD);
+        for x in [code_offset as u32, 0x1000, 0x1000, 4, 4, 5, 4] {
+            word(&mut elf, x);
+        }
+        word(&mut elf, 0x00000073); // ecall
+
+        elf.extend_from_slice(&data);
+        elf.extend_from_slice(&shstrtab);
+        elf.extend_from_slice(&[0; SHDR]);
+        for (name, kind, offset, size) in sections {
+            for x in [
+                name as u32,
+                kind,
+                0,
+                0,
+                offset as u32,
+                size as u32,
+                0,
+                0,
+                1,
+                0,
+            ] {
+                word(&mut elf, x);
+            }
+        }
+        elf
+    }
+
+    #[test]
+    fn check_platform() {
+        let host = risc0_zkvm_platform::VERSION;
+        let matching = fixture_elf(Some(host));
+        assert_eq!(
+            Program::platform_version(&matching).unwrap().as_deref(),
+            Some(host)
+        );
+        assert_eq!(
+            Program::check_platform(&matching).unwrap(),
+            PlatformCheck::Compatible
+        );
+
+        let mismatching = fixture_elf(Some("0.1.0"));
+        assert_eq!(
+            Program::check_platform(&mismatching).unwrap(),
+            PlatformCheck::Incompatible {
+                guest: "0.1.0".into()
+            }
+        );
+
+        let absent = fixture_elf(None);
+        assert_eq!(Program::platform_version(&absent).unwrap(), None);
+        assert_eq!(
+            Program::check_platform(&absent).unwrap(),
+            PlatformCheck::Unknown
+        );
+        assert!(PlatformCheck::Unknown
+            .to_string()
+            .contains("unknown platform"));
+
+        // A version check never stops an ELF from loading.
+        for elf in [matching, mismatching, absent] {
+            let program = Program::load_elf(&elf, 0x10000).unwrap();
+            assert_eq!(program.image[&0x1000], 0x00000073);
+        }
+    }
+
+    #[test]
+    fn compatible_versions() {
+        assert!(is_compatible("0.20.0-alpha.1", "0.20.0-alpha.1"));
+        assert!(!is_compatible("0.20.0-alpha.1", "0.20.0-alpha.2"));
+        assert!(!is_compatible("0.20.0", "0.20.0-alpha.1"));
+        assert!(is_compatible("0.20.0", "0.20.3"));
+        assert!(!is_compatible("0.19.1", "0.20.0"));
+        assert!(!is_compatible("1.0.0", "2.0.0"));
+    }
+}
@@ -0,0 +1,144 @@
+// Copyright 2023 RISC Zero, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+extern crate alloc;
+
+use alloc::{string::String, vec::Vec};
+
+use anyhow::{anyhow, bail, Result};
+use elf::{endian::LittleEndian, ElfBytes};
+use risc0_zkp::core::{
+    digest::Digest,
+    hash::sha::{cpu::Impl, Sha256},
+};
+
+use crate::elf::{section_data, Program};
+
+/// The name of the ELF section in which a guest declares the schema of the
+/// input it expects.
+///
+/// The section holds the schema descriptions, each followed by a NUL byte.
+pub const INPUT_SCHEMA_SECTION: &str = ".risc0.input_schema";
+
+/// The name of the ELF section in which a guest declares the type it commits
+/// to the journal.
+///
+/// The section holds the type names and schema descriptions, each followed by
+/// a NUL byte, in pairs.
+pub const JOURNAL_SCHEMA_SECTION: &str = ".risc0.journal_schema";
+
+/// Hash the description of an input schema.
+///
+/// Two types are considered compatible when the digests of their
+/// descriptions match.
+pub fn input_schema_digest(schema: &str) -> Digest {
+    *Impl::hash_bytes(schema.as_bytes())
+}
+
+/// The type a guest declares it commits to the journal.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct JournalSchema {
+    /// The name of the type, as written in the guest.
+    pub type_name: String,
+
+    /// The [input_schema_digest] of the description of the type.
+    pub digest: Digest,
+}
+
+impl Program {
+    /// Read the digest of the input schema declared by a guest ELF, if any.
+    ///
+    /// Returns an error if the guest declares more than one distinct schema.
+    pub fn input_schema(elf: &[u8]) -> Result<Option<Digest>> {
+        let elf = ElfBytes::<LittleEndian>::minimal_parse(elf)
+            .map_err(|err| anyhow!("Elf parse error: {err}"))?;
+        let Some(data) = section_data(&elf, INPUT_SCHEMA_SECTION)? else {
+            return Ok(None);
+        };
+
+        // Every place the guest reads a checked input adds an entry, so the
+        // same schema can appear more than once.
+        let mut schemas: Vec<&[u8]> = data.split(|&b| b == 0).filter(|x| !x.is_empty()).collect();
+        schemas.sort();
+        schemas.dedup();
+        match schemas[..] {
+            [] => Ok(None),
+            [schema] => {
+                let schema = core::str::from_utf8(schema)
+                    .map_err(|err| anyhow!("Invalid input schema: {err}"))?;
+                Ok(Some(input_schema_digest(schema)))
+            }
+            _ => bail!("Guest declares {} different input schemas", schemas.len()),
+        }
+    }
+
+    /// Read the journal schema declared by a guest ELF, if any.
+    ///
+    /// Returns an error if the guest declares more than one distinct schema.
+    pub fn journal_schema(elf: &[u8]) -> Result<Option<JournalSchema>> {
+        let elf = ElfBytes::<LittleEndian>::minimal_parse(elf)
+            .map_err(|err| anyhow!("Elf parse error: {err}"))?;
+        let Some(data) = section_data(&elf, JOURNAL_SCHEMA_SECTION)? else {
+            return Ok(None);
+        };
+
+        // As with input schemas, every place the guest commits a checked
+        // journal adds an entry.
+        let fields: Vec<&[u8]> = data.split(|&b| b == 0).filter(|x| !x.is_empty()).collect();
+        if fields.len() % 2 != 0 {
+            bail!("Invalid journal schema section");
+        }
+        let mut entries: Vec<&[&[u8]]> = fields.chunks(2).collect();
+        entries.sort();
+        entries.dedup();
+        match entries[..] {
+            [] => Ok(None),
+            [[type_name, schema]] => {
+                let type_name = core::str::from_utf8(type_name)
+                    .map_err(|err| anyhow!("Invalid journal schema: {err}"))?;
+                let schema = core::str::from_utf8(schema)
+                    .map_err(|err| anyhow!("Invalid journal schema: {err}"))?;
+                Ok(Some(JournalSchema {
+                    type_name: type_name.into(),
+                    digest: input_schema_digest(schema),
+                }))
+            }
+            _ => bail!("Guest declares {} different journal schemas", entries.len()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use risc0_zkvm_methods::{HELLO_COMMIT_ELF, MULTI_TEST_ELF};
+
+    use crate::{input_schema_digest, Program};
+
+    #[test]
+    fn input_schema() {
+        assert!(Program::input_schema(MULTI_TEST_ELF).unwrap().is_some());
+        assert_eq!(Program::input_schema(HELLO_COMMIT_ELF).unwrap(), None);
+    }
+
+    #[test]
+    fn journal_schema() {
+        let schema = Program::journal_schema(MULTI_TEST_ELF).unwrap().unwrap();
+        assert_eq!(schema.type_name, "CheckedPoint");
+        assert_eq!(
+            schema.digest,
+            input_schema_digest(risc0_zkvm_methods::multi_test::CHECKED_POINT_SCHEMA)
+        );
+        assert_eq!(Program::journal_schema(HELLO_COMMIT_ELF).unwrap(), None);
+    }
+}
@@ -16,7 +16,7 @@ use std::{fs, io, path::PathBuf, rc::Rc};
 
 use clap::{Args, Parser, ValueEnum};
 use risc0_zkvm::{
-    get_prover_server, ApiServer, ExecutorEnv, ExecutorImpl, ProverOpts, ProverServer,
+    get_prover_server_for, ApiServer, ExecutorEnv, ExecutorImpl, ProveOpts, ProverServer,
     VerifierContext,
 };
 
@@ -182,12 +182,11 @@ impl Cli {
             HashFn::Sha256 => "sha-256",
             HashFn::Poseidon => "poseidon",
         };
-        let opts = ProverOpts {
-            hashfn: hashfn.to_string(),
-            prove_guest_errors: self.prove_guest_errors,
-        };
+        let opts = ProveOpts::default()
+            .with_hashfn(hashfn)
+            .with_prove_guest_errors(self.prove_guest_errors);
 
-        get_prover_server(&opts).unwrap()
+        get_prover_server_for(&opts).unwrap()
     }
 }
 
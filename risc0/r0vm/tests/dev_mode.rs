@@ -12,10 +12,15 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use assert_cmd::Command;
+use assert_cmd::{cargo::cargo_bin, Command};
 use assert_fs::{fixture::PathChild, TempDir};
-use risc0_zkvm::{serde::to_vec, Receipt};
-use risc0_zkvm_methods::{multi_test::MultiTestSpec, MULTI_TEST_PATH};
+use risc0_zkvm::{
+    serde::to_vec, ExecutorEnv, ExternalProver, InnerReceipt, ProveOpts, Prover, Receipt,
+    SecurityPolicy, VerifierContext,
+};
+use risc0_zkvm_methods::{
+    multi_test::MultiTestSpec, MULTI_TEST_ELF, MULTI_TEST_ID, MULTI_TEST_PATH,
+};
 
 fn run_dev_mode() -> Receipt {
     let temp = TempDir::new().unwrap();
@@ -68,3 +73,34 @@ fn dev_mode_verify_fail() {
 fn dev_mode_panic() {
     run_dev_mode();
 }
+
+#[test]
+#[cfg(not(feature = "disable-dev-mode"))]
+fn enforced_rejects_fake_receipt() {
+    let receipt = run_dev_mode();
+    temp_env::with_var("RISC0_DEV_MODE", Some("1"), || {
+        let ctx = VerifierContext::default().with_security_policy(SecurityPolicy::Enforced);
+        receipt
+            .verify_with_context(&ctx, MULTI_TEST_ID)
+            .expect_err("Expecting error");
+    });
+}
+
+#[test]
+#[cfg(not(feature = "disable-dev-mode"))]
+fn enforced_ignores_dev_mode() {
+    temp_env::with_var("RISC0_DEV_MODE", Some("1"), || {
+        let env = ExecutorEnv::builder()
+            .write(&MultiTestSpec::DoNothing)
+            .unwrap()
+            .build()
+            .unwrap();
+        let ctx = VerifierContext::default().with_security_policy(SecurityPolicy::Enforced);
+        let opts = ProveOpts::default().with_security_policy(SecurityPolicy::Enforced);
+        let prover = ExternalProver::new("r0vm", cargo_bin("r0vm"));
+        let receipt = prover
+            .prove_elf_with_opts(env, &ctx, MULTI_TEST_ELF, &opts)
+            .unwrap();
+        assert!(!matches!(receipt.inner, InnerReceipt::Fake { .. }));
+    });
+}
@@ -25,7 +25,7 @@ use ndarray::{ArrayView, ArrayViewMut, Axis};
 use rayon::prelude::*;
 use risc0_core::field::{Elem, ExtElem, Field};
 
-use super::{Buffer, Hal, TRACKER};
+use super::{pool, Buffer, Hal, TRACKER};
 use crate::{
     core::{
         digest::Digest,
@@ -66,7 +66,9 @@ impl Region {
     }
 }
 
-struct TrackedVec<T>(Vec<T>);
+// A vector counted by the memory tracker, and the function that returns it to
+// the buffer pool when dropped, if it came from there.
+struct TrackedVec<T>(Vec<T>, Option<fn(Vec<T>)>);
 
 impl<T> TrackedVec<T> {
     pub fn new(vec: Vec<T>) -> Self {
@@ -74,7 +76,15 @@ impl<T> TrackedVec<T> {
             .lock()
             .unwrap()
             .alloc(vec.capacity() * std::mem::size_of::<T>());
-        Self(vec)
+        Self(vec, None)
+    }
+}
+
+impl<T: Send + 'static> TrackedVec<T> {
+    fn pooled(vec: Vec<T>) -> Self {
+        let mut tracked = Self::new(vec);
+        tracked.1 = Some(pool::release::<T>);
+        tracked
     }
 }
 
@@ -84,6 +94,9 @@ impl<T> Drop for TrackedVec<T> {
             .lock()
             .unwrap()
             .free(self.0.capacity() * std::mem::size_of::<T>());
+        if let Some(release) = self.1 {
+            release(std::mem::take(&mut self.0));
+        }
     }
 }
 
@@ -190,6 +203,45 @@ impl<T: Default + Clone + Pod> CpuBuffer<T> {
         }
     }
 
+    /// Allocate a buffer of `size` copies of `fill`, reusing a vector from the
+    /// [buffer pool](pool) if pooling is enabled and the buffer is large
+    /// enough to be pooled.
+    pub fn pooled(size: usize, fill: T) -> Self
+    where
+        T: Send + 'static,
+    {
+        Self::from_pool(size, |vec| vec.resize(size, fill))
+    }
+
+    /// Like [CpuBuffer::pooled], copying the contents of `slice`.
+    pub fn pooled_copy_from(slice: &[T]) -> Self
+    where
+        T: Send + 'static,
+    {
+        Self::from_pool(slice.len(), |vec| vec.extend_from_slice(slice))
+    }
+
+    fn from_pool<F>(size: usize, init: F) -> Self
+    where
+        T: Send + 'static,
+        F: FnOnce(&mut Vec<T>),
+    {
+        let tracked =
+            if !pool::enabled() || size * std::mem::size_of::<T>() < pool::MIN_POOLED_BYTES {
+                let mut vec = Vec::with_capacity(size);
+                init(&mut vec);
+                TrackedVec::new(vec)
+            } else {
+                let mut vec = pool::take(size).unwrap_or_else(|| Vec::with_capacity(size));
+                init(&mut vec);
+                TrackedVec::pooled(vec)
+            };
+        CpuBuffer {
+            buf: Rc::new(RefCell::new(tracked)),
+            region: Region(0, size),
+        }
+    }
+
     pub fn as_slice(&self) -> Ref<'_, [T]> {
         let vec = self.buf.borrow();
         Ref::map(vec, |vec| {
@@ -255,7 +307,7 @@ impl<F: Field> Hal for CpuHal<F> {
     type Buffer<T: Clone + Debug + PartialEq + Pod> = CpuBuffer<T>;
 
     fn alloc_elem(&self, _name: &'static str, size: usize) -> Self::Buffer<Self::Elem> {
-        CpuBuffer::new(size)
+        CpuBuffer::pooled(size, Self::Elem::default())
     }
 
     fn copy_from_elem(
@@ -263,7 +315,7 @@ impl<F: Field> Hal for CpuHal<F> {
         _name: &'static str,
         slice: &[Self::Elem],
     ) -> Self::Buffer<Self::Elem> {
-        CpuBuffer::copy_from(slice)
+        CpuBuffer::pooled_copy_from(slice)
     }
 
     fn alloc_extelem(&self, _name: &'static str, size: usize) -> Self::Buffer<Self::ExtElem> {
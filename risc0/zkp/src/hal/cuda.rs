@@ -17,6 +17,7 @@ use std::{cell::RefCell, fmt::Debug, marker::PhantomData, rc::Rc};
 use bytemuck::Pod;
 use cust::{
     device::DeviceAttribute,
+    error::{CudaError, CudaResult},
     function::{BlockSize, GridSize},
     memory::{DevicePointer, GpuBuffer},
     prelude::*,
@@ -28,7 +29,7 @@ use risc0_core::field::{
 };
 use risc0_sys::cuda::*;
 
-use super::{Buffer, Hal, TRACKER};
+use super::{Buffer, Hal, DEVICE_FAULT, OUT_OF_MEMORY, TRACKER};
 use crate::{
     core::{
         digest::Digest,
@@ -105,7 +106,7 @@ impl CudaHash for CudaHashSha256 {
             ))
             .unwrap();
         }
-        stream.synchronize().unwrap();
+        check_device(stream.synchronize());
     }
 
     fn hash_rows(
@@ -130,7 +131,7 @@ impl CudaHash for CudaHashSha256 {
             ))
             .unwrap();
         }
-        stream.synchronize().unwrap();
+        check_device(stream.synchronize());
     }
 
     fn get_hash_suite(&self) -> &HashSuite<BabyBear> {
@@ -189,7 +190,7 @@ impl CudaHash for CudaHashPoseidon {
             ))
             .unwrap();
         }
-        stream.synchronize().unwrap();
+        check_device(stream.synchronize());
     }
 
     fn hash_rows(
@@ -218,7 +219,7 @@ impl CudaHash for CudaHashPoseidon {
             ))
             .unwrap();
         }
-        stream.synchronize().unwrap();
+        check_device(stream.synchronize());
     }
 
     fn get_hash_suite(&self) -> &HashSuite<BabyBear> {
@@ -239,7 +240,7 @@ impl CudaHash for CudaHashPoseidon2 {
             hal.copy_from_elem("round_constants", poseidon2::consts::ROUND_CONSTANTS);
         let m_int_diag_ulvt =
             hal.copy_from_elem("m_int_diag_ulvt", poseidon2::consts::M_INT_DIAG_ULVT);
-        stream.synchronize().unwrap();
+        check_device(stream.synchronize());
         CudaHashPoseidon2 {
             suite: Poseidon2HashSuite::new_suite(),
             round_constants,
@@ -270,7 +271,7 @@ impl CudaHash for CudaHashPoseidon2 {
             ))
             .unwrap();
         }
-        stream.synchronize().unwrap();
+        check_device(stream.synchronize());
     }
 
     fn hash_rows(
@@ -297,7 +298,7 @@ impl CudaHash for CudaHashPoseidon2 {
             ))
             .unwrap();
         }
-        stream.synchronize().unwrap();
+        check_device(stream.synchronize());
     }
 
     fn get_hash_suite(&self) -> &HashSuite<BabyBear> {
@@ -316,6 +317,19 @@ pub type CudaHalSha256 = CudaHal<CudaHashSha256>;
 pub type CudaHalPoseidon = CudaHal<CudaHashPoseidon>;
 pub type CudaHalPoseidon2 = CudaHal<CudaHashPoseidon2>;
 
+// Panic on a failed device operation, marking the failures that may not
+// happen again on a fresh context as device faults.
+fn check_device(result: CudaResult<()>) {
+    if let Err(err) = result {
+        match err {
+            CudaError::EccUncorrectable | CudaError::LaunchFailed | CudaError::LaunchTimeout => {
+                panic!("{DEVICE_FAULT}: {err}")
+            }
+            _ => panic!("device operation failed: {err}"),
+        }
+    }
+}
+
 struct RawBuffer {
     name: &'static str,
     buf: DeviceBuffer<u8>,
@@ -324,11 +338,15 @@ struct RawBuffer {
 impl RawBuffer {
     pub fn new(name: &'static str, size: usize) -> Self {
         tracing::debug!("alloc: {size} bytes, {name}");
+        let buf = match unsafe { DeviceBuffer::uninitialized(size) } {
+            Ok(buf) => buf,
+            Err(CudaError::OutOfMemory) => {
+                panic!("{OUT_OF_MEMORY}: failed to allocate {size} bytes for {name}")
+            }
+            Err(err) => panic!("failed to allocate {size} bytes for {name}: {err}"),
+        };
         TRACKER.lock().unwrap().alloc(size);
-        Self {
-            name,
-            buf: unsafe { DeviceBuffer::uninitialized(size).unwrap() },
-        }
+        Self { name, buf }
     }
 }
 
@@ -616,7 +634,7 @@ impl<CH: CudaHash> Hal for CudaHal<CH> {
             ))
             .unwrap();
         }
-        stream.synchronize().unwrap();
+        check_device(stream.synchronize());
     }
 
     #[tracing::instrument(skip_all)]
@@ -652,7 +670,7 @@ impl<CH: CudaHash> Hal for CudaHal<CH> {
             ))
             .unwrap();
         }
-        stream.synchronize().unwrap();
+        check_device(stream.synchronize());
     }
 
     #[tracing::instrument(skip_all)]
@@ -677,7 +695,7 @@ impl<CH: CudaHash> Hal for CudaHal<CH> {
             ))
             .unwrap();
         }
-        stream.synchronize().unwrap();
+        check_device(stream.synchronize());
     }
 
     fn has_unified_memory(&self) -> bool {
@@ -729,7 +747,7 @@ impl<CH: CudaHash> Hal for CudaHal<CH> {
             ))
             .unwrap();
         }
-        stream.synchronize().unwrap();
+        check_device(stream.synchronize());
     }
 
     #[tracing::instrument(skip_all)]
@@ -755,7 +773,7 @@ impl<CH: CudaHash> Hal for CudaHal<CH> {
             ))
             .unwrap();
         }
-        stream.synchronize().unwrap();
+        check_device(stream.synchronize());
     }
 
     #[tracing::instrument(skip_all)]
@@ -781,7 +799,7 @@ impl<CH: CudaHash> Hal for CudaHal<CH> {
             ))
             .unwrap();
         }
-        stream.synchronize().unwrap();
+        check_device(stream.synchronize());
     }
 
     #[tracing::instrument(skip_all)]
@@ -804,7 +822,7 @@ impl<CH: CudaHash> Hal for CudaHal<CH> {
             ))
             .unwrap();
         }
-        stream.synchronize().unwrap();
+        check_device(stream.synchronize());
     }
 
     #[tracing::instrument(skip_all)]
@@ -831,7 +849,7 @@ impl<CH: CudaHash> Hal for CudaHal<CH> {
             ))
             .unwrap();
         }
-        stream.synchronize().unwrap();
+        check_device(stream.synchronize());
     }
 
     fn hash_fold(&self, io: &Self::Buffer<Digest>, input_size: usize, output_size: usize) {
@@ -16,15 +16,15 @@ use std::{collections::HashMap, ffi::c_void, fmt::Debug, marker::PhantomData, me
 
 use bytemuck::Pod;
 use metal::{
-    Buffer as MetalBuffer, CommandQueue, ComputePipelineDescriptor, Device, MTLResourceOptions,
-    MTLSize, NSRange,
+    Buffer as MetalBuffer, CommandBufferRef, CommandQueue, ComputePipelineDescriptor, Device,
+    MTLCommandBufferStatus, MTLResourceOptions, MTLSize, NSRange,
 };
 use risc0_core::field::{
     baby_bear::{BabyBear, BabyBearElem, BabyBearExtElem},
     Elem, ExtElem, RootsOfUnity,
 };
 
-use super::{Buffer, Hal, TRACKER};
+use super::{Buffer, Hal, DEVICE_FAULT, TRACKER};
 use crate::{
     core::{
         digest::Digest,
@@ -338,7 +338,18 @@ impl<T> BufferImpl<T> {
         blit_encoder.synchronize_resource(&self.buffer.0);
         blit_encoder.end_encoding();
         cmd_buffer.commit();
-        cmd_buffer.wait_until_completed();
+        wait_until_completed(cmd_buffer);
+    }
+}
+
+// Wait for a committed command buffer, panicking if the device failed to run
+// it. Metal does not tell a driver reset or GPU timeout apart from other
+// failures here, so all of them are marked as device faults that may not
+// happen again on a fresh device.
+fn wait_until_completed(cmd_buffer: &CommandBufferRef) {
+    cmd_buffer.wait_until_completed();
+    if matches!(cmd_buffer.status(), MTLCommandBufferStatus::Error) {
+        panic!("{DEVICE_FAULT}: command buffer failed");
     }
 }
 
@@ -460,7 +471,7 @@ impl<MH: MetalHash> MetalHal<MH> {
         cmd_encoder.end_encoding();
 
         cmd_buffer.commit();
-        cmd_buffer.wait_until_completed();
+        wait_until_completed(cmd_buffer);
     }
 }
 
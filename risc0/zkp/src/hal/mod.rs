@@ -20,8 +20,9 @@ pub mod cuda;
 pub mod dual;
 #[cfg(feature = "metal")]
 pub mod metal;
+pub mod pool;
 
-use std::{fmt::Debug, sync::Mutex};
+use std::{any::Any, fmt::Debug, sync::Mutex};
 
 use bytemuck::Pod;
 use lazy_static::lazy_static;
@@ -36,6 +37,68 @@ lazy_static! {
     static ref TRACKER: Mutex<MemoryTracker> = Mutex::new(MemoryTracker::new());
 }
 
+/// The prefix of the panic message raised when a [Hal] fails to allocate a
+/// buffer because the device has run out of memory.
+pub const OUT_OF_MEMORY: &str = "HAL device out of memory";
+
+/// The prefix of the panic message raised when a [Hal] hits a fault in the
+/// device that need not happen again, such as an uncorrectable ECC error or a
+/// driver reset.
+pub const DEVICE_FAULT: &str = "HAL device fault";
+
+/// The kind of failure behind a panic raised while proving with a [Hal].
+///
+/// A [Hal] reports a failure by panicking, and marks the failures that can be
+/// told apart by starting the panic message with [OUT_OF_MEMORY] or
+/// [DEVICE_FAULT].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum HalErrorKind {
+    /// The device ran out of memory; see [OUT_OF_MEMORY].
+    OutOfMemory,
+
+    /// The device faulted; see [DEVICE_FAULT].
+    DeviceFault,
+
+    /// Any other failure, including bugs and bad input.
+    Fatal,
+}
+
+impl HalErrorKind {
+    /// The kind of failure behind `payload`, as caught by
+    /// [std::panic::catch_unwind].
+    pub fn of_panic(payload: &(dyn Any + Send)) -> Self {
+        match panic_message(payload) {
+            Some(msg) if msg.starts_with(OUT_OF_MEMORY) => HalErrorKind::OutOfMemory,
+            Some(msg) if msg.starts_with(DEVICE_FAULT) => HalErrorKind::DeviceFault,
+            _ => HalErrorKind::Fatal,
+        }
+    }
+
+    /// Returns `true` if the same work may succeed when done again on a
+    /// freshly constructed [Hal].
+    ///
+    /// Running out of memory is not retryable: the same work needs the same
+    /// memory.
+    pub fn is_retryable(&self) -> bool {
+        *self == HalErrorKind::DeviceFault
+    }
+}
+
+/// The message of `payload`, as caught by [std::panic::catch_unwind], if it
+/// has one.
+pub fn panic_message(payload: &(dyn Any + Send)) -> Option<&str> {
+    match payload.downcast_ref::<String>() {
+        Some(msg) => Some(msg.as_str()),
+        None => payload.downcast_ref::<&str>().copied(),
+    }
+}
+
+/// Returns `true` if `payload`, as caught by [std::panic::catch_unwind], was
+/// raised by a [Hal] running out of device memory.
+pub fn is_out_of_memory(payload: &(dyn Any + Send)) -> bool {
+    HalErrorKind::of_panic(payload) == HalErrorKind::OutOfMemory
+}
+
 pub trait Buffer<T>: Clone {
     fn size(&self) -> usize;
 
@@ -0,0 +1,303 @@
+// Copyright 2023 RISC Zero, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Pools of the vectors behind large [CpuBuffer](super::cpu::CpuBuffer)s.
+//!
+//! Proving a segment allocates witness buffers of up to hundreds of megabytes
+//! that live only as long as the segment. Allocating them afresh for every
+//! segment fragments the heap of a long-lived process, so once pooling is
+//! enabled with [set_buffer_pool_limit], the CPU HAL takes them from a pool
+//! instead, and returns them to it when they are dropped.
+//!
+//! Each thread has a pool of its own, so threads proving in parallel do not
+//! contend for it. A pool keeps what it is given, up to the limit, until
+//! [trim_memory] is called or its thread exits.
+
+use std::{
+    any::Any,
+    mem,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex, Weak,
+    },
+};
+
+use lazy_static::lazy_static;
+
+/// Vectors smaller than this, in bytes, are not pooled.
+pub(crate) const MIN_POOLED_BYTES: usize = 1 << 20;
+
+// The most bytes the pool of each thread may hold; zero disables pooling.
+static LIMIT: AtomicUsize = AtomicUsize::new(0);
+
+// The bytes held by the pools of all threads, and the most they have held at
+// once.
+static HELD_BYTES: AtomicUsize = AtomicUsize::new(0);
+static HIGH_WATER_BYTES: AtomicUsize = AtomicUsize::new(0);
+
+lazy_static! {
+    // The pool of each thread that has used one, for [trim_memory] and
+    // [buffer_pool_stats].
+    static ref POOLS: Mutex<Vec<Weak<Mutex<BufferPool>>>> = Mutex::new(Vec::new());
+}
+
+thread_local! {
+    // The lock is only contended by [trim_memory] and [buffer_pool_stats].
+    static POOL: Arc<Mutex<BufferPool>> = {
+        let pool = Arc::new(Mutex::new(BufferPool::default()));
+        let mut pools = POOLS.lock().unwrap();
+        pools.retain(|pool| pool.strong_count() > 0);
+        pools.push(Arc::downgrade(&pool));
+        pool
+    };
+}
+
+/// Statistics of the buffer pools, as returned by [buffer_pool_stats] and
+/// [thread_buffer_pool_stats].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct BufferPoolStats {
+    /// The bytes held by the pools, for buffers yet to be allocated.
+    pub held_bytes: usize,
+
+    /// The number of vectors held by the pools.
+    pub buffers: usize,
+
+    /// The most bytes the pools have held at once.
+    pub high_water_bytes: usize,
+}
+
+/// Pool the large buffers of the CPU prover, keeping up to `max_bytes` in the
+/// pool of each thread. A buffer that would take a pool past the limit is
+/// freed instead.
+///
+/// Pooling is disabled by default, and with a limit of zero. Lowering the
+/// limit does not free buffers already pooled; see [trim_memory].
+///
+/// Returns the previous limit.
+pub fn set_buffer_pool_limit(max_bytes: usize) -> usize {
+    LIMIT.swap(max_bytes, Ordering::Relaxed)
+}
+
+/// Free every vector held by the pool of each thread, and return the number
+/// of bytes freed.
+///
+/// Buffers in use are not affected, and return to the pool of their thread
+/// when dropped.
+pub fn trim_memory() -> usize {
+    pools()
+        .iter()
+        .map(|pool| {
+            let entries = pool.lock().unwrap().drain();
+            // Free the vectors after releasing the lock.
+            entries.iter().map(|entry| entry.bytes).sum::<usize>()
+        })
+        .sum()
+}
+
+/// Return statistics of the buffer pools of all threads.
+pub fn buffer_pool_stats() -> BufferPoolStats {
+    let mut stats = BufferPoolStats {
+        high_water_bytes: HIGH_WATER_BYTES.load(Ordering::Relaxed),
+        ..Default::default()
+    };
+    for pool in pools() {
+        let pool = pool.lock().unwrap();
+        stats.held_bytes += pool.stats.held_bytes;
+        stats.buffers += pool.stats.buffers;
+    }
+    stats
+}
+
+/// Return statistics of the buffer pool of the calling thread.
+pub fn thread_buffer_pool_stats() -> BufferPoolStats {
+    POOL.with(|pool| pool.lock().unwrap().stats)
+}
+
+// Returns `true` if large buffers are to be pooled.
+pub(crate) fn enabled() -> bool {
+    LIMIT.load(Ordering::Relaxed) > 0
+}
+
+// Take a vector with room for `len` elements from the pool of this thread, if
+// it holds one.
+pub(crate) fn take<T: Send + 'static>(len: usize) -> Option<Vec<T>> {
+    // The pool is gone while the thread exits.
+    POOL.try_with(|pool| pool.lock().unwrap().take(len))
+        .ok()
+        .flatten()
+}
+
+// Give a vector back to the pool of this thread, or free it if the pool is
+// full.
+pub(crate) fn release<T: Send + 'static>(vec: Vec<T>) {
+    let limit = LIMIT.load(Ordering::Relaxed);
+    let _ = POOL.try_with(|pool| pool.lock().unwrap().put(vec, limit));
+}
+
+// The pools of the threads that are still running.
+fn pools() -> Vec<Arc<Mutex<BufferPool>>> {
+    POOLS
+        .lock()
+        .unwrap()
+        .iter()
+        .filter_map(Weak::upgrade)
+        .collect()
+}
+
+struct Entry {
+    bytes: usize,
+    vec: Box<dyn Any + Send>,
+}
+
+#[derive(Default)]
+struct BufferPool {
+    entries: Vec<Entry>,
+    stats: BufferPoolStats,
+}
+
+impl BufferPool {
+    // Take the smallest held vector of `T`s with room for `len` of them. A
+    // vector more than twice as large as needed is left for a larger buffer.
+    fn take<T: Send + 'static>(&mut self, len: usize) -> Option<Vec<T>> {
+        let (index, _) = self
+            .entries
+            .iter()
+            .enumerate()
+            .filter_map(|(index, entry)| {
+                let capacity = entry.vec.downcast_ref::<Vec<T>>()?.capacity();
+                (len <= capacity && capacity <= len.saturating_mul(2)).then_some((index, capacity))
+            })
+            .min_by_key(|&(_, capacity)| capacity)?;
+        let entry = self.entries.swap_remove(index);
+        self.stats.held_bytes -= entry.bytes;
+        self.stats.buffers -= 1;
+        HELD_BYTES.fetch_sub(entry.bytes, Ordering::Relaxed);
+        entry.vec.downcast().ok().map(|vec| *vec)
+    }
+
+    // Hold `vec`, unless that would take the pool past `limit` bytes.
+    fn put<T: Send + 'static>(&mut self, mut vec: Vec<T>, limit: usize) {
+        let bytes = vec.capacity() * mem::size_of::<T>();
+        if self.stats.held_bytes.saturating_add(bytes) > limit {
+            return;
+        }
+        vec.clear();
+        self.entries.push(Entry {
+            bytes,
+            vec: Box::new(vec),
+        });
+        self.stats.held_bytes += bytes;
+        self.stats.buffers += 1;
+        self.stats.high_water_bytes = self.stats.high_water_bytes.max(self.stats.held_bytes);
+        let held = HELD_BYTES.fetch_add(bytes, Ordering::Relaxed) + bytes;
+        HIGH_WATER_BYTES.fetch_max(held, Ordering::Relaxed);
+    }
+
+    fn drain(&mut self) -> Vec<Entry> {
+        HELD_BYTES.fetch_sub(mem::take(&mut self.stats.held_bytes), Ordering::Relaxed);
+        self.stats.buffers = 0;
+        mem::take(&mut self.entries)
+    }
+}
+
+impl Drop for BufferPool {
+    fn drop(&mut self) {
+        HELD_BYTES.fetch_sub(self.stats.held_bytes, Ordering::Relaxed);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bytemuck::{Pod, Zeroable};
+
+    use super::*;
+    use crate::hal::{cpu::CpuBuffer, Buffer};
+
+    #[test]
+    fn best_fit() {
+        let mut pool = BufferPool::default();
+        pool.put(Vec::<u32>::with_capacity(100), usize::MAX);
+        pool.put(Vec::<u32>::with_capacity(300), usize::MAX);
+        pool.put(Vec::<u64>::with_capacity(150), usize::MAX);
+        assert_eq!(pool.stats.buffers, 3);
+        assert_eq!(pool.stats.held_bytes, 400 + 1200 + 1200);
+
+        // The first is too small, the second more than twice as large as
+        // needed, and the third of the wrong type.
+        assert!(pool.take::<u32>(140).is_none());
+        assert_eq!(pool.take::<u32>(90).unwrap().capacity(), 100);
+        assert!(pool.take::<u32>(90).is_none());
+        assert_eq!(pool.take::<u32>(200).unwrap().capacity(), 300);
+        assert_eq!(pool.take::<u64>(150).unwrap().capacity(), 150);
+
+        assert_eq!(pool.stats.buffers, 0);
+        assert_eq!(pool.stats.held_bytes, 0);
+        assert_eq!(pool.stats.high_water_bytes, 2800);
+    }
+
+    #[test]
+    fn limit() {
+        let mut pool = BufferPool::default();
+        pool.put(Vec::<u32>::with_capacity(100), 600);
+        pool.put(Vec::<u32>::with_capacity(100), 600);
+        // A third vector would take the pool past the limit.
+        pool.put(Vec::<u32>::with_capacity(100), 600);
+        assert_eq!(pool.stats.buffers, 2);
+        assert_eq!(pool.stats.held_bytes, 800);
+        assert!(pool.take::<u32>(100).is_some());
+        pool.put(Vec::<u32>::with_capacity(100), 600);
+        assert_eq!(pool.stats.buffers, 1);
+    }
+
+    #[derive(Clone, Copy, Default, Pod, Zeroable)]
+    #[repr(transparent)]
+    struct TestElem(u32);
+
+    // Restores the previous limit when dropped, so that the other tests keep
+    // running with pooling off whatever their order.
+    struct RestoreLimit(usize);
+
+    impl Drop for RestoreLimit {
+        fn drop(&mut self) {
+            set_buffer_pool_limit(self.0);
+        }
+    }
+
+    #[test]
+    fn trim() {
+        let _limit = RestoreLimit(set_buffer_pool_limit(usize::MAX));
+        let len = MIN_POOLED_BYTES / 4;
+        let buf = CpuBuffer::pooled(len, TestElem(7));
+        assert!(buf.as_slice().iter().all(|x| x.0 == 7));
+        drop(buf);
+        let stats = thread_buffer_pool_stats();
+        assert_eq!(stats.buffers, 1);
+        assert_eq!(stats.held_bytes, MIN_POOLED_BYTES);
+
+        // The vector is reused, and refilled.
+        let buf = CpuBuffer::pooled(len, TestElem(8));
+        assert!(buf.as_slice().iter().all(|x| x.0 == 8));
+        assert_eq!(thread_buffer_pool_stats().buffers, 0);
+        let copy = CpuBuffer::pooled_copy_from(&buf.as_slice());
+        assert_eq!(copy.size(), len);
+        drop((buf, copy));
+        assert_eq!(thread_buffer_pool_stats().held_bytes, 2 * MIN_POOLED_BYTES);
+
+        assert!(trim_memory() >= 2 * MIN_POOLED_BYTES);
+        let stats = thread_buffer_pool_stats();
+        assert_eq!((stats.buffers, stats.held_bytes), (0, 0));
+        assert!(stats.high_water_bytes >= 2 * MIN_POOLED_BYTES);
+        assert!(take::<TestElem>(len).is_none());
+    }
+}
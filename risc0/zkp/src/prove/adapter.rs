@@ -129,7 +129,7 @@ where
             .circuit
             .get_taps()
             .group_size(REGISTER_GROUP_ACCUM);
-        self.accum = CpuBuffer::from_fn(self.steps * accum_size, |_| F::Elem::INVALID);
+        self.accum = CpuBuffer::pooled(self.steps * accum_size, F::Elem::INVALID);
 
         self.compute_accum();
 
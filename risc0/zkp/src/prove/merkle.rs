@@ -159,7 +159,7 @@ mod tests {
             log2_ceil,
         },
         hal::cpu::CpuHal,
-        verify::{MerkleTreeVerifier, ReadIOP, VerificationError},
+        verify::{BranchError, MerkleTreeVerifier, ReadIOP},
     };
 
     fn init_prover<H: Hal>(
@@ -239,7 +239,7 @@ mod tests {
                         false,
                         "Merkle tree wrongly passed verify when tested on the wrong row"
                     ),
-                    Err(VerificationError::InvalidProof) => {}
+                    Err(BranchError::Mismatch { .. }) => {}
                     Err(_) => assert!(
                         false,
                         "Merkle tree failed validation for an unexpected reason"
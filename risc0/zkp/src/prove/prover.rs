@@ -16,13 +16,20 @@ use rayon::prelude::*;
 use risc0_core::field::{Elem, ExtElem, RootsOfUnity};
 
 use crate::{
-    core::poly::{poly_divide, poly_interpolate},
+    core::{
+        digest::Digest,
+        poly::{poly_divide, poly_interpolate},
+    },
     hal::{Buffer, CircuitHal, Hal},
     prove::{fri::fri_prove, poly_group::PolyGroup, write_iop::WriteIOP},
     taps::TapSet,
     INV_RATE,
 };
 
+/// A callback given the name and digest of each commitment made by a
+/// [Prover]; see [Prover::set_commit_hook].
+pub type CommitHook<'a> = Box<dyn FnMut(&str, &Digest) + 'a>;
+
 /// Object to generate a zero-knowledge proof of the execution of some circuit.
 pub struct Prover<'a, H: Hal> {
     hal: &'a H,
@@ -31,6 +38,7 @@ pub struct Prover<'a, H: Hal> {
     groups: Vec<Option<PolyGroup<H>>>,
     cycles: usize,
     po2: usize,
+    commit_hook: Option<CommitHook<'a>>,
 }
 
 fn make_coeffs<H: Hal>(hal: &H, buf: H::Buffer<H::Elem>, count: usize) -> H::Buffer<H::Elem> {
@@ -54,6 +62,25 @@ impl<'a, H: Hal> Prover<'a, H> {
                 .collect(),
             cycles: 0,
             po2: usize::MAX,
+            commit_hook: None,
+        }
+    }
+
+    /// Calls `hook` with each commitment the prover makes, in order: the
+    /// Merkle root of each register group as it is committed, named after
+    /// the group, then the root of the check polynomial (`"check"`) and the
+    /// hash of the evaluations at the DEEP-ALI query point (`"u"`).
+    ///
+    /// Provers given the same trace on different HALs make the same
+    /// commitments, so this shows which phase of proving two HALs disagree
+    /// on.
+    pub fn set_commit_hook(&mut self, hook: impl FnMut(&str, &Digest) + 'a) {
+        self.commit_hook = Some(Box::new(hook));
+    }
+
+    fn observe(&mut self, name: &str, digest: &Digest) {
+        if let Some(hook) = &mut self.commit_hook {
+            hook(name, digest);
         }
     }
 
@@ -95,11 +122,10 @@ impl<'a, H: Hal> Prover<'a, H> {
 
         group_ref.merkle.commit(&mut self.iop);
 
-        tracing::debug!(
-            "{} group root: {}",
-            self.taps.group_name(tap_group_index),
-            group_ref.merkle.root()
-        );
+        let root = *group_ref.merkle.root();
+        let name = self.taps.group_name(tap_group_index);
+        tracing::debug!("{name} group root: {root}");
+        self.observe(name, &root);
     }
 
     /// Generates the proof and returns the seal.
@@ -165,6 +191,7 @@ impl<'a, H: Hal> Prover<'a, H> {
         let check_group = PolyGroup::new(self.hal, check_poly, H::CHECK_SIZE, self.cycles, "check");
         check_group.merkle.commit(&mut self.iop);
         tracing::debug!("checkGroup: {}", check_group.merkle.root());
+        self.observe("check", check_group.merkle.root());
 
         // Now pick a value for Z, which is used as the DEEP-ALI query point.
         let z = self.iop.random_ext_elem();
@@ -246,6 +273,7 @@ impl<'a, H: Hal> Prover<'a, H> {
             .hashfn
             .hash_ext_elem_slice(coeff_u.as_slice());
         self.iop.commit(&hash_u);
+        self.observe("u", &hash_u);
 
         // Set the mix mix value, which is used for FRI batching.
         let mix = self.iop.random_ext_elem();
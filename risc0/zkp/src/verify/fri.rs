@@ -12,7 +12,7 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use alloc::vec::Vec;
+use alloc::{format, vec::Vec};
 
 use risc0_core::field::{Elem, ExtElem, Field, RootsOfUnity};
 
@@ -24,7 +24,10 @@ use crate::{
         log2_ceil,
         ntt::{bit_reverse, interpolate_ntt},
     },
-    verify::{merkle::MerkleTreeVerifier, read_iop::ReadIOP, VerificationError},
+    verify::{
+        merkle::MerkleTreeVerifier, read_iop::ReadIOP, transcript::fmt_elem, VerificationError,
+        VerifyPhase,
+    },
     FRI_FOLD, FRI_FOLD_PO2, FRI_MIN_DEGREE, INV_RATE, QUERIES,
 };
 
@@ -69,9 +72,8 @@ where
         let quot = *pos / round.domain;
         let group = *pos % round.domain;
         // Get the column data
-        let data = round
-            .merkle
-            .verify(iop, self.suite.hashfn.as_ref(), group)?;
+        let offset = iop.offset();
+        let data = self.verify_branch(&round.merkle, iop, group, VerifyPhase::FriQuery, "fold")?;
         let mut data_ext: Vec<F::ExtElem> = (0..FRI_FOLD)
             .map(|i| {
                 let mut inps = Vec::with_capacity(F::ExtElem::EXT_SIZE);
@@ -82,6 +84,18 @@ where
             })
             .collect();
         // Check the existing goal
+        self.record_check(
+            VerifyPhase::FriQuery,
+            offset,
+            || {
+                (
+                    format!("fold at row {group}"),
+                    fmt_elem(goal),
+                    fmt_elem(&data_ext[quot]),
+                )
+            },
+            data_ext[quot] == *goal,
+        );
         if data_ext[quot] != *goal {
             return Err(VerificationError::InvalidProof);
         }
@@ -114,7 +128,15 @@ where
             (log2_ceil((degree + FRI_FOLD - 1) / FRI_FOLD) + FRI_FOLD_PO2 - 1) / FRI_FOLD_PO2;
         let mut rounds = Vec::with_capacity(rounds_capacity);
         while degree > FRI_MIN_DEGREE {
-            rounds.push(VerifyRoundInfo::new(iop, hashfn, domain));
+            let offset = iop.offset();
+            let round = VerifyRoundInfo::new(iop, hashfn, domain);
+            self.record_read(
+                VerifyPhase::FriCommit,
+                offset,
+                &format!("round {} root", rounds.len()),
+                round.merkle.root(),
+            );
+            rounds.push(round);
             domain /= FRI_FOLD;
             degree /= FRI_FOLD;
         }
@@ -128,15 +150,24 @@ where
             rounds_capacity
         );
         // Grab the final coeffs + commit
+        let offset = iop.offset();
         let final_coeffs = iop.read_field_elem_slice(F::ExtElem::EXT_SIZE * degree);
         let final_digest = hashfn.hash_elem_slice(final_coeffs);
+        self.record_read(
+            VerifyPhase::FriCommit,
+            offset,
+            "final coefficients",
+            &final_digest,
+        );
         iop.commit(&final_digest);
         // Get the generator for the final polynomial evaluations
         let gen = <F::Elem as RootsOfUnity>::ROU_FWD[log2_ceil(domain)];
         // Do queries
         let mut poly_buf: Vec<F::ExtElem> = Vec::with_capacity(degree);
-        for _ in 0..QUERIES {
+        for query in 0..QUERIES {
+            let offset = iop.offset();
             let mut pos = iop.random_bits(log2_ceil(orig_domain)) as usize;
+            let row = pos;
             // Do the 'inner' verification for this index
             let mut goal = inner(iop, pos)?;
             // Verify the per-round proofs
@@ -153,6 +184,18 @@ where
                 )
             }));
             let fx = self.poly_eval(poly_buf.as_slice(), F::ExtElem::from_subfield(&x));
+            self.record_check(
+                VerifyPhase::FriQuery,
+                offset,
+                || {
+                    (
+                        format!("query {query} at row {row}"),
+                        fmt_elem(&goal),
+                        fmt_elem(&fx),
+                    )
+                },
+                fx == goal,
+            );
             if fx != goal {
                 return Err(VerificationError::InvalidProof);
             }
@@ -42,6 +42,27 @@ pub(crate) struct MerkleTreeVerifier<'a> {
     rest: Vec<Box<Digest>>,
 }
 
+/// The reason a Merkle branch failed to verify.
+#[derive(Debug)]
+pub(crate) enum BranchError {
+    /// The requested row is outside the tree.
+    OutOfRange { idx: usize, rows: usize },
+
+    /// The branch does not hash to the digest held by the verifier.
+    Mismatch { expected: Digest, actual: Digest },
+}
+
+impl BranchError {
+    pub fn into_verification_error(self) -> VerificationError {
+        match self {
+            BranchError::OutOfRange { idx, rows } => {
+                VerificationError::MerkleQueryOutOfRange { idx, rows }
+            }
+            BranchError::Mismatch { .. } => VerificationError::InvalidProof,
+        }
+    }
+}
+
 // Translates from virtual indexes to indexes in the "top" and "rest" arrays.
 trait SplitMerkleIndex {
     fn idx_to_top(&self, idx: usize) -> usize;
@@ -141,9 +162,9 @@ impl<'a> MerkleTreeVerifier<'a> {
         iop: &mut ReadIOP<'a, F>,
         hashfn: &dyn HashFn<F>,
         mut idx: usize,
-    ) -> Result<&'a [F::Elem], VerificationError> {
+    ) -> Result<&'a [F::Elem], BranchError> {
         if idx >= self.params.row_size {
-            return Err(VerificationError::MerkleQueryOutOfRange {
+            return Err(BranchError::OutOfRange {
                 idx,
                 rows: self.params.row_size,
             });
@@ -181,7 +202,10 @@ impl<'a> MerkleTreeVerifier<'a> {
         if *present_hash == *cur {
             Ok(out)
         } else {
-            Err(VerificationError::InvalidProof)
+            Err(BranchError::Mismatch {
+                expected: *present_hash,
+                actual: *cur,
+            })
         }
     }
 }
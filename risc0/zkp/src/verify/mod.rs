@@ -17,13 +17,21 @@
 mod fri;
 mod merkle;
 mod read_iop;
+mod transcript;
 
-use alloc::{vec, vec::Vec};
+use alloc::{
+    format,
+    string::{String, ToString},
+    vec,
+    vec::Vec,
+};
 use core::{cell::RefCell, fmt, iter::zip};
 
-pub(crate) use merkle::MerkleTreeVerifier;
+pub(crate) use merkle::{BranchError, MerkleTreeVerifier};
 pub use read_iop::ReadIOP;
 use risc0_core::field::{Elem, ExtElem, Field, RootsOfUnity};
+use transcript::fmt_elem;
+pub use transcript::{Transcript, TranscriptStep, VerifyPhase};
 
 use crate::{
     adapter::{CircuitCoreDef, REGISTER_GROUP_ACCUM, REGISTER_GROUP_CODE, REGISTER_GROUP_DATA},
@@ -104,6 +112,7 @@ where
     out: Option<&'a [F::Elem]>,
     mix: Vec<F::Elem>,
     tap_cache: RefCell<Option<TapCache<F>>>,
+    transcript: Option<RefCell<Transcript>>,
 }
 
 impl<'a, F: Field, C> VerifyParams<F> for Verifier<'a, F, C> {}
@@ -122,9 +131,84 @@ where
             out: None,
             mix: Vec::new(),
             tap_cache: RefCell::new(None),
+            transcript: None,
+        }
+    }
+
+    // Add a step to the transcript, if one is being kept.
+    fn record(&self, step: impl FnOnce() -> TranscriptStep) {
+        if let Some(transcript) = &self.transcript {
+            transcript.borrow_mut().push(step());
         }
     }
 
+    // Record a check that compares two values.
+    fn record_check(
+        &self,
+        phase: VerifyPhase,
+        offset: usize,
+        check: impl FnOnce() -> (String, String, String),
+        passed: bool,
+    ) {
+        self.record(|| {
+            let (check, expected, actual) = check();
+            TranscriptStep {
+                phase,
+                check,
+                offset,
+                expected: Some(expected),
+                actual,
+                passed,
+            }
+        });
+    }
+
+    // Record a value read from the seal that is only checked later.
+    fn record_read(&self, phase: VerifyPhase, offset: usize, check: &str, actual: impl ToString) {
+        self.record(|| TranscriptStep {
+            phase,
+            check: check.to_string(),
+            offset,
+            expected: None,
+            actual: actual.to_string(),
+            passed: true,
+        });
+    }
+
+    // Verify a Merkle branch read from the IOP, recording the failure if it
+    // does not verify.
+    fn verify_branch(
+        &self,
+        merkle: &MerkleTreeVerifier<'a>,
+        iop: &mut ReadIOP<'a, F>,
+        idx: usize,
+        phase: VerifyPhase,
+        name: &str,
+    ) -> Result<&'a [F::Elem], VerificationError> {
+        let offset = iop.offset();
+        merkle
+            .verify(iop, self.suite.hashfn.as_ref(), idx)
+            .map_err(|err| {
+                self.record_check(
+                    phase,
+                    offset,
+                    || {
+                        let check = format!("{name} branch at row {idx}");
+                        match &err {
+                            BranchError::OutOfRange { rows, .. } => {
+                                (check, format!("row < {rows}"), format!("row {idx}"))
+                            }
+                            BranchError::Mismatch { expected, actual } => {
+                                (check, expected.to_string(), actual.to_string())
+                            }
+                        }
+                    },
+                    false,
+                );
+                err.into_verification_error()
+            })
+    }
+
     // Compute the FRI verify taps sum.
     #[allow(clippy::too_many_arguments)]
     fn fri_eval_taps(
@@ -221,6 +305,7 @@ where
 
         // Read any execution state
         self.execute(&mut iop);
+        self.record_read(VerifyPhase::Header, 0, "po2", self.po2);
 
         // Get the size
         assert!(self.po2 as usize <= MAX_CYCLES_PO2);
@@ -237,9 +322,19 @@ where
         // The code merkle tree contains the control instructions for the zkVM.
         #[cfg(not(target_os = "zkvm"))]
         tracing::debug!("code_merkle");
+        let offset = iop.offset();
         let code_merkle = MerkleTreeVerifier::new(&mut iop, hashfn, domain, code_size, QUERIES);
         // tracing::debug!("codeRoot = {}", code_merkle.root());
-        check_code(self.po2, code_merkle.root())?;
+        let result = check_code(self.po2, code_merkle.root());
+        self.record(|| TranscriptStep {
+            phase: VerifyPhase::CodeMerkle,
+            check: "code root is an allowed control ID".to_string(),
+            offset,
+            expected: None,
+            actual: code_merkle.root().to_string(),
+            passed: result.is_ok(),
+        });
+        result?;
 
         // Get merkle root for the data merkle tree.
         // The data merkle tree contains the execution trace of the program being run,
@@ -247,7 +342,14 @@ where
         // accesses sorted by location used by PLONK.
         #[cfg(not(target_os = "zkvm"))]
         tracing::debug!("data_merkle");
+        let offset = iop.offset();
         let data_merkle = MerkleTreeVerifier::new(&mut iop, hashfn, domain, data_size, QUERIES);
+        self.record_read(
+            VerifyPhase::DataMerkle,
+            offset,
+            "data root",
+            data_merkle.root(),
+        );
         // tracing::debug!("dataRoot = {}", data_merkle.root());
 
         // Prep accumulation
@@ -267,7 +369,14 @@ where
         // implement a look-up table.
         #[cfg(not(target_os = "zkvm"))]
         tracing::debug!("accum_merkle");
+        let offset = iop.offset();
         let accum_merkle = MerkleTreeVerifier::new(&mut iop, hashfn, domain, accum_size, QUERIES);
+        self.record_read(
+            VerifyPhase::AccumMerkle,
+            offset,
+            "accum root",
+            accum_merkle.root(),
+        );
         // tracing::debug!("accumRoot = {}", accum_merkle.root());
 
         // Get a pseudorandom value with which to mix the constraint polynomials.
@@ -276,8 +385,15 @@ where
 
         #[cfg(not(target_os = "zkvm"))]
         tracing::debug!("check_merkle");
+        let offset = iop.offset();
         let check_merkle =
             MerkleTreeVerifier::new(&mut iop, hashfn, domain, Self::CHECK_SIZE, QUERIES);
+        self.record_read(
+            VerifyPhase::CheckMerkle,
+            offset,
+            "check root",
+            check_merkle.root(),
+        );
         // tracing::debug!("checkRoot = {}", check_merkle.root());
 
         // Get a pseudorandom DEEP query point
@@ -288,6 +404,7 @@ where
 
         // Read the U coeffs (the interpolations of the taps) + commit their hash.
         let num_taps = taps.tap_size();
+        let coeff_offset = iop.offset();
         let coeff_u = iop.read_field_elem_slice(num_taps + Self::CHECK_SIZE);
         let hash_u = self.suite.hashfn.hash_ext_elem_slice(coeff_u);
         iop.commit(&hash_u);
@@ -346,6 +463,18 @@ where
         let three = F::Elem::from_u64(3);
         check *= (F::ExtElem::from_subfield(&three) * z).pow(size) - F::ExtElem::ONE;
         // tracing::debug!("Check = {check:?}");
+        self.record_check(
+            VerifyPhase::Constraints,
+            coeff_offset,
+            || {
+                (
+                    "constraint polynomial at z".to_string(),
+                    fmt_elem(&check),
+                    fmt_elem(&result),
+                )
+            },
+            check == result,
+        );
         if check != result {
             return Err(VerificationError::InvalidProof);
         }
@@ -399,11 +528,12 @@ where
             // tracing::debug!("fri_verify");
             let x = gen.pow(idx);
             let rows = [
-                accum_merkle.verify(iop, hashfn, idx)?,
-                code_merkle.verify(iop, hashfn, idx)?,
-                data_merkle.verify(iop, hashfn, idx)?,
+                self.verify_branch(&accum_merkle, iop, idx, VerifyPhase::FriQuery, "accum")?,
+                self.verify_branch(&code_merkle, iop, idx, VerifyPhase::FriQuery, "code")?,
+                self.verify_branch(&data_merkle, iop, idx, VerifyPhase::FriQuery, "data")?,
             ];
-            let check_row = check_merkle.verify(iop, hashfn, idx)?;
+            let check_row =
+                self.verify_branch(&check_merkle, iop, idx, VerifyPhase::FriQuery, "check")?;
             let ret = self.fri_eval_taps(taps, mix, &combo_u, check_row, back_one, x, z, rows);
             Ok(ret)
         })?;
@@ -447,3 +577,25 @@ where
 {
     Verifier::<F, C>::new(circuit, suite).verify(seal, check_code)
 }
+
+/// Verify a seal as [verify] does, and return the [Transcript] of the checks
+/// made along with the result.
+///
+/// If verification fails, the last step of the transcript is the check that
+/// failed, unless the seal is malformed before its first commitment.
+pub fn verify_with_transcript<F, C, CheckCode>(
+    circuit: &C,
+    suite: &HashSuite<F>,
+    seal: &[u32],
+    check_code: CheckCode,
+) -> (Result<(), VerificationError>, Transcript)
+where
+    F: Field,
+    C: CircuitCoreDef<F>,
+    CheckCode: Fn(u32, &Digest) -> Result<(), VerificationError>,
+{
+    let mut verifier = Verifier::<F, C>::new(circuit, suite);
+    verifier.transcript = Some(RefCell::new(Transcript::new()));
+    let result = verifier.verify(seal, check_code);
+    (result, verifier.transcript.unwrap().into_inner())
+}
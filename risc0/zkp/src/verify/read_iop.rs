@@ -23,6 +23,7 @@ use crate::core::{
 
 pub struct ReadIOP<'a, F: Field> {
     proof: &'a [u32],
+    len: usize,
     rng: Box<dyn Rng<F>>,
 }
 
@@ -30,6 +31,7 @@ impl<'a, F: Field> ReadIOP<'a, F> {
     pub fn new(proof: &'a [u32], rng: &dyn RngFactory<F>) -> Self {
         ReadIOP {
             proof,
+            len: proof.len(),
             rng: rng.new_rng(),
         }
     }
@@ -58,6 +60,11 @@ impl<'a, F: Field> ReadIOP<'a, F> {
         bytemuck::cast_slice(u32s)
     }
 
+    /// The number of words read from this IOP so far.
+    pub fn offset(&self) -> usize {
+        self.len - self.proof.len()
+    }
+
     pub fn commit(&mut self, digest: &Digest) {
         self.rng.mix(digest);
     }
@@ -0,0 +1,82 @@
+// Copyright 2023 RISC Zero, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! A record of the checks made while verifying a seal, for debugging seals
+//! that fail to verify.
+
+use alloc::{format, string::String, vec::Vec};
+
+use risc0_core::field::Elem;
+use serde::{Deserialize, Serialize};
+
+/// The part of the seal that a [TranscriptStep] checks, in the order the
+/// verifier reads them.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum VerifyPhase {
+    /// The circuit outputs and the po2 of the trace.
+    Header,
+
+    /// The commitment to the control columns, which must be one of the
+    /// allowed control IDs.
+    CodeMerkle,
+
+    /// The commitment to the data columns, i.e. the execution trace.
+    DataMerkle,
+
+    /// The commitment to the accumulator columns of the permutation checks.
+    AccumMerkle,
+
+    /// The commitment to the check polynomial.
+    CheckMerkle,
+
+    /// The evaluation of the constraint polynomial at the DEEP query point.
+    Constraints,
+
+    /// The commitments to the FRI folding rounds and the final polynomial.
+    FriCommit,
+
+    /// The FRI queries, each of which opens every Merkle tree at a
+    /// pseudorandom row.
+    FriQuery,
+}
+
+/// One check made while verifying a seal.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TranscriptStep {
+    /// The part of the seal this step checks.
+    pub phase: VerifyPhase,
+
+    /// What was checked, e.g. `data branch at row 1234`.
+    pub check: String,
+
+    /// The index of the first word of the seal read by this step.
+    pub offset: usize,
+
+    /// The value the verifier expected, for checks that compare two values.
+    pub expected: Option<String>,
+
+    /// The value read from, or computed from, the seal.
+    pub actual: String,
+
+    /// Whether the check passed.
+    pub passed: bool,
+}
+
+/// The checks made while verifying a seal, in order. Verification stops at
+/// the first failed check, so only the last step can have failed.
+pub type Transcript = Vec<TranscriptStep>;
+
+pub(crate) fn fmt_elem<E: Elem>(elem: &E) -> String {
+    format!("{:?}", elem.to_u32_words())
+}
@@ -15,12 +15,12 @@
 use criterion::{
     black_box, criterion_group, criterion_main, BatchSize, BenchmarkId, Criterion, Throughput,
 };
-use risc0_zkvm::{get_prover_server, ExecutorEnv, ExecutorImpl, ProverOpts, VerifierContext};
+use risc0_zkvm::{get_prover_server_for, ExecutorEnv, ExecutorImpl, ProveOpts, VerifierContext};
 use risc0_zkvm_methods::FIB_ELF;
 
 fn setup(iterations: u32) -> ExecutorImpl<'static> {
     let env = ExecutorEnv::builder()
-        .write_slice(&[iterations])
+        .write_words(&[iterations])
         .build()
         .unwrap();
     ExecutorImpl::from_elf(env, FIB_ELF).unwrap()
@@ -34,8 +34,8 @@ enum Scope {
 pub fn bench(c: &mut Criterion) {
     let mut group = c.benchmark_group("fib");
 
-    let opts = ProverOpts::default();
-    let prover = get_prover_server(&opts).unwrap();
+    let opts = ProveOpts::default();
+    let prover = get_prover_server_for(&opts).unwrap();
     let ctx = VerifierContext::default();
 
     for iterations in [100, 1000, 10_000] {
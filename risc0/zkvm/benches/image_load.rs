@@ -0,0 +1,72 @@
+// Copyright 2023 RISC Zero, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use criterion::{black_box, criterion_group, criterion_main, BatchSize, Criterion};
+use risc0_zkvm::{
+    AcceleratedImageHasher, ImageHasher, MemoryImage, Program, ScalarImageHasher, GUEST_MAX_MEM,
+    PAGE_SIZE,
+};
+use risc0_zkvm_methods::MULTI_TEST_ELF;
+
+fn image_from_elf() -> MemoryImage {
+    let program = Program::load_elf(MULTI_TEST_ELF, GUEST_MAX_MEM as u32).unwrap();
+    MemoryImage::new(&program, PAGE_SIZE as u32).unwrap()
+}
+
+// An image like that of a large guest, with 48k pages of data (48 MB)
+// above the program.
+fn large_image() -> MemoryImage {
+    let mut image = image_from_elf();
+    let start = image.info.get_page_index(GUEST_MAX_MEM as u32) - 48 * 1024;
+    for page_idx in start..start + 48 * 1024 {
+        let addr = image.info.get_page_addr(page_idx);
+        image.store_region_in_page(addr, &page_idx.to_le_bytes());
+    }
+    image
+}
+
+pub fn bench(c: &mut Criterion) {
+    let mut group = c.benchmark_group("image_load");
+
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("multi_test.image");
+    image_from_elf().save(&path).unwrap();
+
+    group.bench_function("elf", |b| b.iter(|| black_box(image_from_elf())));
+    group.bench_function("file", |b| {
+        b.iter(|| black_box(MemoryImage::load(&path).unwrap()))
+    });
+
+    group.finish();
+
+    let mut group = c.benchmark_group("hash_pages");
+    group.sample_size(10);
+    let image = large_image();
+    let hashers: [&dyn ImageHasher; 2] = [&ScalarImageHasher, &AcceleratedImageHasher];
+    for hasher in hashers {
+        group.bench_function(hasher.name(), |b| {
+            b.iter_batched_ref(
+                || image.clone(),
+                |image| image.hash_pages_with(hasher),
+                BatchSize::LargeInput,
+            )
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(name = benches;
+    config = Criterion::default();
+    targets = bench);
+criterion_main!(benches);
@@ -0,0 +1,51 @@
+// Copyright 2023 RISC Zero, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Compares building an environment with a 512 MB input written with
+//! `write_bytes`, which copies it once, with passing it as a shared
+//! `input_buffer`, which does not copy it at all.
+
+use std::sync::Arc;
+
+use criterion::{black_box, criterion_group, criterion_main, Criterion, Throughput};
+use risc0_zkvm::ExecutorEnv;
+
+const INPUT_BYTES: usize = 512 << 20;
+
+pub fn bench(c: &mut Criterion) {
+    let input: Arc<[u8]> = vec![0x5a_u8; INPUT_BYTES].into();
+
+    let mut group = c.benchmark_group("input");
+    group.sample_size(10);
+    group.throughput(Throughput::Bytes(INPUT_BYTES as u64));
+    group.bench_function("write_bytes", |b| {
+        b.iter(|| black_box(ExecutorEnv::builder().write_bytes(&input).build().unwrap()))
+    });
+    group.bench_function("input_buffer", |b| {
+        b.iter(|| {
+            black_box(
+                ExecutorEnv::builder()
+                    .input_buffer(input.clone())
+                    .build()
+                    .unwrap(),
+            )
+        })
+    });
+    group.finish();
+}
+
+criterion_group!(name = benches;
+    config = Criterion::default();
+    targets = bench);
+criterion_main!(benches);
@@ -0,0 +1,105 @@
+// Copyright 2023 RISC Zero, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! `output_hints` executes a guest that writes a 100 MB journal, with and
+//! without a size hint for it, and prints the number of allocations and the
+//! bytes allocated by each run along with the time it takes.
+
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use risc0_zkvm::{ExecutorEnv, ExecutorImpl};
+use risc0_zkvm_methods::{multi_test::MultiTestSpec, MULTI_TEST_ELF};
+use risc0_zkvm_platform::fileno;
+
+const JOURNAL_BYTES: usize = 100 << 20;
+
+// Counts the allocations made, including reallocations, and the bytes they
+// request.
+struct Counting;
+
+static ALLOCS: AtomicUsize = AtomicUsize::new(0);
+static BYTES: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for Counting {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCS.fetch_add(1, Ordering::Relaxed);
+        BYTES.fetch_add(layout.size(), Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        ALLOCS.fetch_add(1, Ordering::Relaxed);
+        BYTES.fetch_add(new_size, Ordering::Relaxed);
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: Counting = Counting;
+
+fn run(hint: Option<usize>) {
+    let mut builder = ExecutorEnv::builder();
+    builder
+        .write(&MultiTestSpec::WriteZeros {
+            fd: fileno::JOURNAL,
+            bytes: JOURNAL_BYTES as u32,
+            chunk: 1 << 20,
+        })
+        .unwrap();
+    if let Some(hint) = hint {
+        builder.output_size_hints(hint, 0, false);
+    }
+    let env = builder.build().unwrap();
+    let session = ExecutorImpl::from_elf(env, MULTI_TEST_ELF)
+        .unwrap()
+        .run()
+        .unwrap();
+    assert_eq!(session.journal.unwrap().bytes.len(), JOURNAL_BYTES);
+}
+
+pub fn bench(c: &mut Criterion) {
+    let mut group = c.benchmark_group("output_hints");
+    group.sample_size(10);
+    group.throughput(Throughput::Bytes(JOURNAL_BYTES as u64));
+    for (name, hint) in [("none", None), ("exact", Some(JOURNAL_BYTES))] {
+        let (allocs, bytes) = (
+            ALLOCS.load(Ordering::Relaxed),
+            BYTES.load(Ordering::Relaxed),
+        );
+        run(hint);
+        println!(
+            "output_hints/{name}: {} allocations, {} MiB allocated",
+            ALLOCS.load(Ordering::Relaxed) - allocs,
+            (BYTES.load(Ordering::Relaxed) - bytes) >> 20
+        );
+
+        group.bench_with_input(BenchmarkId::from_parameter(name), &hint, |b, &hint| {
+            b.iter(|| black_box(run(hint)))
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(name = benches;
+    config = Criterion::default();
+    targets = bench);
+criterion_main!(benches);
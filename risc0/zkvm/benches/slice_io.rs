@@ -0,0 +1,64 @@
+// Copyright 2023 RISC Zero, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Compares serving a large response from a cache to the guest by copying it
+//! into [Bytes] with serving it borrowed.
+
+use std::borrow::Cow;
+
+use bytes::Bytes;
+use criterion::{black_box, criterion_group, criterion_main, Criterion, Throughput};
+use risc0_zkvm::{ExecutorEnv, ExecutorEnvBuilder, ExecutorImpl};
+use risc0_zkvm_methods::{
+    multi_test::{MultiTestSpec, SYS_MULTI_TEST},
+    MULTI_TEST_ELF,
+};
+
+const RESPONSE_BYTES: usize = 8 << 20;
+
+fn run<'a>(register: impl FnOnce(&mut ExecutorEnvBuilder<'a>)) {
+    let mut builder = ExecutorEnv::builder();
+    builder.write(&MultiTestSpec::Syscall { count: 1 }).unwrap();
+    register(&mut builder);
+    let mut exec = ExecutorImpl::from_elf(builder.build().unwrap(), MULTI_TEST_ELF).unwrap();
+    black_box(exec.run().unwrap());
+}
+
+pub fn bench(c: &mut Criterion) {
+    let cache = vec![0x5a_u8; RESPONSE_BYTES];
+
+    let mut group = c.benchmark_group("slice_io");
+    group.sample_size(10);
+    group.throughput(Throughput::Bytes(RESPONSE_BYTES as u64));
+    group.bench_function("owned", |b| {
+        b.iter(|| {
+            run(|builder| {
+                builder.io_callback(SYS_MULTI_TEST, |_| Ok(Bytes::copy_from_slice(&cache)));
+            })
+        })
+    });
+    group.bench_function("borrowed", |b| {
+        b.iter(|| {
+            run(|builder| {
+                builder.io_callback_borrowed(SYS_MULTI_TEST, |_| Ok(Cow::Borrowed(&cache)));
+            })
+        })
+    });
+    group.finish();
+}
+
+criterion_group!(name = benches;
+    config = Criterion::default();
+    targets = bench);
+criterion_main!(benches);
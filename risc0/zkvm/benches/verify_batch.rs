@@ -0,0 +1,53 @@
+// Copyright 2023 RISC Zero, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use risc0_zkvm::{verify_batch, BatchMode, ExecutorEnv, ProveOpts, SessionProver};
+use risc0_zkvm_methods::{FIB_ELF, FIB_ID};
+
+const RECEIPTS: usize = 64;
+
+pub fn bench(c: &mut Criterion) {
+    let mut group = c.benchmark_group("verify_batch");
+
+    let env = ExecutorEnv::builder().write_words(&[100]).build().unwrap();
+    let receipt = SessionProver::new(FIB_ELF, env)
+        .unwrap()
+        .run(&ProveOpts::default())
+        .unwrap();
+    let receipts = vec![(receipt, FIB_ID.into()); RECEIPTS];
+
+    // Throughput should grow close to linearly until parallelism reaches the
+    // number of cores.
+    let cores = std::thread::available_parallelism().unwrap().get();
+    group.sample_size(10);
+    group.throughput(Throughput::Elements(RECEIPTS as u64));
+    for parallelism in (0..).map(|x| 1 << x).take_while(|&x| x <= cores) {
+        let id = BenchmarkId::from_parameter(parallelism);
+        group.bench_with_input(id, &parallelism, |b, &parallelism| {
+            b.iter(|| {
+                let result = verify_batch(&receipts, parallelism, BatchMode::FailFast);
+                assert!(result.is_ok());
+                black_box(result)
+            })
+        });
+    }
+
+    group.finish();
+}
+
+criterion_group!(name = benches;
+    config = Criterion::default();
+    targets = bench);
+criterion_main!(benches);
@@ -12,12 +12,8 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use std::rc::Rc;
-
 use clap::Parser;
-use risc0_zkvm::{
-    get_prover_server, ExecutorEnv, ExecutorImpl, ProverOpts, ProverServer, VerifierContext,
-};
+use risc0_zkvm::{ExecutorEnv, ProveOpts, SessionProver};
 use risc0_zkvm_methods::FIB_ELF;
 use tracing_subscriber::{prelude::*, EnvFilter};
 
@@ -51,31 +47,29 @@ fn main() {
         .init();
 
     let args = Args::parse();
-    let mut opts = ProverOpts::default();
+    let mut opts = ProveOpts::default();
     if let Some(hashfn) = args.hashfn {
         opts.hashfn = hashfn;
     }
-    let prover = get_prover_server(&opts).unwrap();
-    let metrics = top(prover, args.iterations, args.skip_prover);
+    let metrics = top(&opts, args.iterations, args.skip_prover);
     println!("{metrics:?}");
 }
 
 #[tracing::instrument(skip_all)]
-fn top(prover: Rc<dyn ProverServer>, iterations: u32, skip_prover: bool) -> Metrics {
+fn top(opts: &ProveOpts, iterations: u32, skip_prover: bool) -> Metrics {
     let env = ExecutorEnv::builder()
-        .write_slice(&[iterations])
+        .write_words(&[iterations])
         .build()
         .unwrap();
-    let mut exec = ExecutorImpl::from_elf(env, FIB_ELF).unwrap();
-    let session = exec.run().unwrap();
+    let prover = SessionProver::new(FIB_ELF, env).unwrap();
+    let session = prover.session();
     let segments = session.resolve().unwrap();
     let (total_cycles, user_cycles) = session.get_cycles().unwrap();
     let seal = if skip_prover {
         0
     } else {
-        let ctx = VerifierContext::default();
         prover
-            .prove_session(&ctx, &session)
+            .run(opts)
             .unwrap()
             .inner
             .composite()
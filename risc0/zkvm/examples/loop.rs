@@ -17,7 +17,7 @@ use std::{process::Command, rc::Rc, time::Instant};
 use clap::Parser;
 use human_repr::{HumanCount, HumanDuration};
 use risc0_zkvm::{
-    get_prover_server, ExecutorEnv, ExecutorImpl, ProverOpts, ProverServer, Receipt, Session,
+    get_prover_server_for, ExecutorEnv, ExecutorImpl, ProveOpts, ProverServer, Receipt, Session,
     VerifierContext,
 };
 use risc0_zkvm_methods::{
@@ -66,11 +66,11 @@ fn main() {
             .with(tracing_forest::ForestLayer::default())
             .init();
 
-        let mut opts = ProverOpts::default();
+        let mut opts = ProveOpts::default();
         if let Some(hashfn) = args.hashfn {
             opts.hashfn = hashfn;
         }
-        let prover = get_prover_server(&opts).unwrap();
+        let prover = get_prover_server_for(&opts).unwrap();
 
         let start = Instant::now();
         let (session, receipt) = top(prover.clone(), iterations, args.po2);
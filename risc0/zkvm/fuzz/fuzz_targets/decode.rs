@@ -0,0 +1,31 @@
+// Copyright 2023 RISC Zero, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Decodes arbitrary bytes as each of the values the API server accepts from
+//! its clients. Decoding may fail, but must never panic, abort or exhaust
+//! memory.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use risc0_zkvm::{recursion::SuccinctReceipt, DecodeLimits, Receipt, Segment, SegmentReceipt};
+
+fuzz_target!(|data: &[u8]| {
+    let limits = DecodeLimits::default();
+    let _ = limits.decode::<Segment>(data);
+    let _ = limits.decode::<SegmentReceipt>(data);
+    let _ = limits.decode::<SuccinctReceipt>(data);
+    let _ = limits.decode::<Receipt>(data);
+    let _ = Receipt::from_bytes(data);
+});
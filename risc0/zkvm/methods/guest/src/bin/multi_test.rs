@@ -19,21 +19,27 @@
 
 extern crate alloc;
 
-use alloc::{format, vec};
-use core::arch::asm;
+use alloc::{format, vec, vec::Vec};
+use core::{arch::asm, ptr::null_mut};
 
 use getrandom::getrandom;
 use risc0_zkp::core::hash::sha::testutil::test_sha_impl;
 use risc0_zkvm::{
-    guest::{env, memory_barrier, sha},
+    guest::{
+        env::{self, Read as _},
+        memory_barrier, sha,
+        sha_accel::HostSha256,
+    },
     sha::{Digest, Sha256},
     ReceiptMetadata,
 };
-use risc0_zkvm_methods::multi_test::{MultiTestSpec, SYS_MULTI_TEST};
+use risc0_zkvm_methods::multi_test::{
+    CheckedPoint, FramedHeader, FramedRecord, MultiTestSpec, SYS_MULTI_TEST,
+};
 use risc0_zkvm_platform::{
     fileno,
     memory::{self, SYSTEM},
-    syscall::{bigint, sys_bigint, sys_log, sys_read, sys_read_words, sys_write},
+    syscall::{bigint, nr, sys_bigint, sys_log, sys_read, sys_read_words, sys_write, syscall_1},
 };
 
 risc0_zkvm::entry!(main);
@@ -179,6 +185,126 @@ pub fn main() {
             let meta: ReceiptMetadata = risc0_zkvm::serde::from_slice(&metadata_words).unwrap();
             env::verify_integrity(&meta).unwrap();
         }
+        MultiTestSpec::ReadAssumption { index } => {
+            let digest = sha::Impl::hash_bytes(env::read_assumption(index));
+            env::commit(&digest);
+        }
+        MultiTestSpec::CommitSlices { slices } => {
+            for slice in slices {
+                env::commit_slice(&slice);
+            }
+        }
+        MultiTestSpec::Yield { tags } => {
+            for tag in tags {
+                env::commit(&tag);
+                env::yield_to_host(tag);
+            }
+        }
+        MultiTestSpec::Exec { name, arg } => {
+            let output = env::exec(&name, &arg);
+            env::commit(&(output.image_id, output.journal));
+        }
+        MultiTestSpec::ExecVerify { name, arg } => {
+            let output = env::exec(&name, &arg);
+            env::verify(output.image_id, &output.journal).unwrap();
+            env::commit(&(output.image_id, output.journal));
+        }
+        MultiTestSpec::ReadChecked => {
+            let point = risc0_zkvm::read_checked!(CheckedPoint);
+            env::commit(&point);
+        }
+        MultiTestSpec::CommitChecked { x, y } => {
+            risc0_zkvm::commit_checked!(CheckedPoint, &CheckedPoint { x, y }, prefixed);
+        }
+        MultiTestSpec::WriteFramed { count } => {
+            env::write_framed(&FramedHeader { version: 1, count });
+            let records: Vec<FramedRecord> = (0..count)
+                .map(|id| FramedRecord {
+                    id,
+                    name: format!("record {id}"),
+                })
+                .collect();
+            env::write_framed(&records);
+        }
+        MultiTestSpec::HostSupports => {
+            env::commit(&(
+                env::host_supports(SYS_MULTI_TEST),
+                env::host_supports(nr::SYS_VERIFY),
+            ));
+        }
+        MultiTestSpec::ReadWords { nwords } => {
+            let mut words = vec![0u32; nwords as usize];
+            env::read_slice(&mut words);
+            env::commit_slice(&words);
+        }
+        MultiTestSpec::EnvVar { name } => {
+            env::commit(&env::var(&name));
+        }
+        MultiTestSpec::EnvVarLarge { name } => {
+            let value = env::var_large(&name);
+            env::commit(&value.is_some());
+            env::commit_slice(&value.unwrap_or_default());
+        }
+        MultiTestSpec::ReadNamed { names } => {
+            let values: Vec<u32> = names
+                .iter()
+                .map(|name| env::open_named(name).read())
+                .collect();
+            env::commit(&values);
+        }
+        MultiTestSpec::CommitNonce { value } => {
+            env::commit(&env::nonce());
+            env::commit(&value);
+        }
+        MultiTestSpec::Args => {
+            env::commit(&env::args());
+        }
+        MultiTestSpec::Check { codes } => {
+            env::check(true, 0);
+            for code in codes.iter() {
+                env::check(false, *code);
+            }
+            env::commit(&(codes.len() as u32));
+        }
+        MultiTestSpec::CyclesRemaining { threshold } => {
+            let mut rounds = 0u32;
+            while env::cycles_remaining().is_some_and(|remaining| remaining >= threshold) {
+                rounds += 1;
+            }
+            env::commit(&rounds);
+        }
+        MultiTestSpec::HostCommit {
+            first,
+            second,
+            accept,
+        } => {
+            for value in [first, second] {
+                env::commit(&value);
+                env::send_recv_slice::<u8, u8>(SYS_MULTI_TEST, &[]);
+                if accept {
+                    env::commit_host_data();
+                }
+            }
+        }
+        MultiTestSpec::DivRem { a, b } => {
+            // Use the instructions directly, since Rust checks for these corner cases.
+            let (div, divu, rem, remu): (u32, u32, u32, u32);
+            unsafe {
+                asm!(
+                    "div {div}, {a}, {b}",
+                    "divu {divu}, {a}, {b}",
+                    "rem {rem}, {a}, {b}",
+                    "remu {remu}, {a}, {b}",
+                    a = in(reg) a,
+                    b = in(reg) b,
+                    div = out(reg) div,
+                    divu = out(reg) divu,
+                    rem = out(reg) rem,
+                    remu = out(reg) remu,
+                );
+            }
+            env::commit_slice(&[div, divu, rem, remu]);
+        }
         MultiTestSpec::EchoStdout { nbytes, fd } => {
             // Unaligned buffer size to exercise things a little bit.
             let mut buf = vec![0u8; nbytes as usize];
@@ -224,11 +350,113 @@ pub fn main() {
             }
             env::commit_slice(&result);
         }
+        MultiTestSpec::BigIntModExp {
+            base,
+            exponent,
+            modulus,
+        } => {
+            let result = risc0_zkvm::guest::bigint::modexp(&base, &exponent, &modulus);
+            env::commit_slice(&result);
+        }
+        MultiTestSpec::Multiply { iterations } => {
+            env::commit(&multiply(iterations));
+        }
         MultiTestSpec::LibM => {
             use core::hint::black_box;
             let f = black_box(1.0_f32);
             black_box(f.min(1.0));
         }
+        MultiTestSpec::FormatFloat {
+            numerator,
+            denominator,
+        } => {
+            let value = core::hint::black_box(numerator as f64) / denominator as f64;
+            env::commit(&format!("{value:.3}"));
+        }
+        MultiTestSpec::HeapAlloc { bytes } => {
+            use core::hint::black_box;
+            let _data = black_box(vec![1_u8; bytes as usize]);
+        }
+        MultiTestSpec::StackUsage { bytes } => {
+            env::commit(&use_stack(bytes / STACK_FRAME_BYTES as u32));
+        }
+        MultiTestSpec::CallLeaf { a, b } => {
+            // The halt stub is only reached from the host; taking its address
+            // keeps the linker from discarding it.
+            core::hint::black_box(multi_test_halt as usize);
+            env::commit(&multi_test_leaf(a, b));
+        }
+        MultiTestSpec::CopyMemory { bytes } => {
+            // Buffers of words are aligned, so memset and memcpy take their
+            // word-at-a-time loops.
+            let mut src = vec![0_u32; bytes as usize / 4];
+            let src_bytes: &mut [u8] = bytemuck::cast_slice_mut(&mut src);
+            src_bytes.fill(0x5a);
+            for (i, x) in src_bytes.iter_mut().enumerate().step_by(1021) {
+                *x = i as u8;
+            }
+            let mut dst = vec![0_u32; src.len()];
+            dst.copy_from_slice(&src);
+            let digest = sha::Impl::hash_bytes(bytemuck::cast_slice(&dst));
+            env::commit(&digest);
+        }
+        MultiTestSpec::HashedJournal { count } => {
+            env::hashed_journal();
+            for i in 0..count {
+                env::commit(&i);
+                env::commit_slice(&vec![i as u8; i as usize]);
+            }
+        }
+        MultiTestSpec::LogMany { count } => {
+            for i in 0..count {
+                env::log(&format!("message {i}\n"));
+            }
+        }
+        MultiTestSpec::SweepMemory { bytes, passes } => {
+            use core::hint::black_box;
+            let mut buf = vec![0_u32; bytes as usize / 4];
+            for pass in 0..passes {
+                for x in buf.iter_mut() {
+                    *x = x.wrapping_add(pass);
+                }
+                black_box(&mut buf);
+            }
+            env::commit(&buf.iter().fold(0_u32, |sum, x| sum.wrapping_add(*x)));
+        }
+        MultiTestSpec::PollReadAvail { fd, rounds } => {
+            let mut bytes = vec![0_u8; rounds as usize];
+            for byte in bytes.iter_mut() {
+                while unsafe { syscall_1(nr::SYS_READ_AVAIL, null_mut(), 0, fd) }.0 == 0 {}
+                unsafe { sys_read(fd, byte, 1) };
+            }
+            env::commit_slice(&bytes);
+        }
+        MultiTestSpec::WriteZeros { fd, bytes, chunk } => {
+            let buf = vec![0_u8; chunk as usize];
+            let mut left = bytes as usize;
+            while left > 0 {
+                let len = left.min(buf.len());
+                unsafe { sys_write(fd, buf.as_ptr(), len) };
+                left -= len;
+            }
+        }
+        MultiTestSpec::ShaAccel { len, verify_one_in } => {
+            // Sent a piece at a time, whose length is not a whole number of
+            // blocks, so the full data is never in memory.
+            let pattern: Vec<u8> = (0..4099_u32).map(|i| (i * 31 % 251) as u8).collect();
+            let mut hasher = HostSha256::new().verify_one_in(verify_one_in);
+            let mut remaining = len as usize;
+            while remaining > 0 {
+                let n = remaining.min(pattern.len());
+                hasher.update(&pattern[..n]);
+                remaining -= n;
+            }
+            let reference = (len <= 1 << 16).then(|| {
+                let data: Vec<u8> = pattern.iter().cycle().take(len as usize).copied().collect();
+                *sha::Impl::hash_bytes(&data)
+            });
+            env::commit(&(hasher.finalize(), reference));
+        }
         MultiTestSpec::Oom => {
             use core::hint::black_box;
             // SYSTEM memory starts above the guest memory so this is guaranteed
@@ -269,3 +497,58 @@ pub fn main() {
         },
     }
 }
+
+const STACK_FRAME_BYTES: usize = 64 * 1024;
+
+// Recurses `depth` times with a frame of STACK_FRAME_BYTES, touching a single
+// word of each frame so that the stack is used without filling it.
+#[inline(never)]
+fn use_stack(depth: u32) -> u32 {
+    let mut frame = core::mem::MaybeUninit::<[u32; STACK_FRAME_BYTES / 4]>::uninit();
+    let word = core::hint::black_box(frame.as_mut_ptr() as *mut u32);
+    unsafe { word.write_volatile(depth) };
+    let below = if depth == 0 { 0 } else { use_stack(depth - 1) };
+    below + unsafe { word.read_volatile() }
+}
+
+#[inline(never)]
+fn multiply(iterations: u32) -> u32 {
+    let mut product = 1u32;
+    for i in 0..iterations {
+        product = core::hint::black_box(product.wrapping_mul(i | 1));
+    }
+    product
+}
+
+// Called by the host tests of custom entry points, which start execution here
+// with `a` and `b` in a0 and a1, and ra pointing at `multi_test_halt`. The
+// array is kept on the stack.
+#[inline(never)]
+#[no_mangle]
+extern "C" fn multi_test_leaf(a: u32, b: u32) -> u32 {
+    let words = core::hint::black_box([a, b, a ^ b, a.wrapping_mul(b)]);
+    words.iter().fold(0, |sum, word| sum.wrapping_add(*word))
+}
+
+extern "C" {
+    // Halts with exit code 0 and no output, keeping the value returned by the
+    // function that returned to it in a2.
+    fn multi_test_halt() -> !;
+}
+
+static ZERO_DIGEST: [u32; 8] = [0; 8];
+
+// t0 = ecall::HALT and a0 = halt::TERMINATE, which are both 0.
+core::arch::global_asm!(
+    r#"
+.section .text.multi_test_halt;
+.globl multi_test_halt;
+multi_test_halt:
+    mv a2, a0
+    li t0, 0
+    li a0, 0
+    la a1, {0}
+    ecall
+"#,
+    sym ZERO_DIGEST,
+);
@@ -15,9 +15,9 @@
 // Definitions for test selection codes used by the "multi_test" test.
 extern crate alloc;
 
-use alloc::vec::Vec;
+use alloc::{string::String, vec::Vec};
 
-use risc0_zkvm::{declare_syscall, sha::Digest};
+use risc0_zkvm::{declare_syscall, sha::Digest, InputSchema};
 use risc0_zkvm_platform::syscall::bigint;
 use serde::{Deserialize, Serialize};
 
@@ -63,6 +63,90 @@ pub enum MultiTestSpec {
         // Define this field as a serialized vector to avoid circular dependency issues.
         metadata_words: Vec<u32>,
     },
+    ReadAssumption {
+        index: u32,
+    },
+    CommitSlices {
+        slices: Vec<Vec<u8>>,
+    },
+    Yield {
+        tags: Vec<u32>,
+    },
+    /// Runs the guest registered on the host under `name` with `arg` as its
+    /// stdin, and commits its image ID and journal.
+    Exec {
+        name: String,
+        arg: Vec<u8>,
+    },
+    /// Like [MultiTestSpec::Exec], but also verifies the receipt of the guest
+    /// with `env::verify` before committing.
+    ExecVerify {
+        name: String,
+        arg: Vec<u8>,
+    },
+    /// Reads a [CheckedPoint] with `read_checked!` and commits it.
+    ReadChecked,
+    /// Commits the [CheckedPoint] `{ x, y }` with `commit_checked!`, prefixed
+    /// by the digest of its schema.
+    CommitChecked {
+        x: u32,
+        y: u32,
+    },
+    /// Writes a [FramedHeader] and then `count` [FramedRecord]s to stdout as
+    /// two frames with `env::write_framed`.
+    WriteFramed {
+        count: u32,
+    },
+    /// Commits whether the host supports SYS_MULTI_TEST and SYS_VERIFY.
+    HostSupports,
+    /// Reads the given number of words from stdin and commits them.
+    ReadWords {
+        nwords: u32,
+    },
+    /// Commits the value of the given environment variable, if set.
+    EnvVar {
+        name: String,
+    },
+    /// Reads the given environment variable in pages with `env::var_large`,
+    /// and commits whether it is set followed by its bytes.
+    EnvVarLarge {
+        name: String,
+    },
+    /// Reads a u32 from each of the named inputs, in the given order, and
+    /// commits them.
+    ReadNamed {
+        names: Vec<String>,
+    },
+    /// Commits the nonce provided by the host, followed by `value`.
+    CommitNonce {
+        value: u32,
+    },
+    /// Commits the arguments returned by `env::args`.
+    Args,
+    /// Makes a passing `env::check`, then a failing one with each of `codes`,
+    /// and commits the number of checks that failed.
+    Check {
+        codes: Vec<u32>,
+    },
+    /// Spins while `env::cycles_remaining` is at least `threshold`, then
+    /// commits the number of rounds spun.
+    CyclesRemaining {
+        threshold: u64,
+    },
+    /// Commits `first`, calls `SYS_MULTI_TEST`, commits `second`, and calls
+    /// `SYS_MULTI_TEST` again before exiting. After each call, commits the
+    /// data queued by the host with `env::commit_host_data` if `accept` is
+    /// set.
+    HostCommit {
+        first: u32,
+        second: u32,
+        accept: bool,
+    },
+    /// Commits the results of div, divu, rem and remu on the given operands.
+    DivRem {
+        a: u32,
+        b: u32,
+    },
     EchoStdout {
         nbytes: u32,
         fd: u32,
@@ -76,11 +160,82 @@ pub enum MultiTestSpec {
         y: [u32; bigint::WIDTH_WORDS],
         modulus: [u32; bigint::WIDTH_WORDS],
     },
+    BigIntModExp {
+        base: Vec<u32>,
+        exponent: Vec<u32>,
+        modulus: Vec<u32>,
+    },
     BusyLoop {
         /// Busy loop until the guest has run for at least this number of cycles
         cycles: u32,
     },
+    /// Multiplies `iterations` numbers in a function that is not inlined, so
+    /// that its cycles can be attributed to it, and commits the product.
+    Multiply {
+        iterations: u32,
+    },
     LibM,
+    /// Formats `numerator / denominator` as a float and commits the string.
+    FormatFloat {
+        numerator: u32,
+        denominator: u32,
+    },
+    /// Allocates a [Vec] of `bytes` bytes and fills it.
+    HeapAlloc {
+        bytes: u32,
+    },
+    /// Recurses through frames that use `bytes` bytes of stack in total, and
+    /// commits the sum of their depths.
+    StackUsage {
+        bytes: u32,
+    },
+    /// Commits `multi_test_leaf(a, b)`, the function that the host tests of
+    /// custom entry points call directly.
+    CallLeaf {
+        a: u32,
+        b: u32,
+    },
+    /// Fills a buffer of `bytes` bytes with memset, copies it with memcpy,
+    /// and commits the SHA-256 digest of the copy.
+    CopyMemory {
+        bytes: u32,
+    },
+    /// Switches to a hashed journal with `env::hashed_journal`, and commits
+    /// each of `0..count` followed by a slice of that many bytes.
+    HashedJournal {
+        count: u32,
+    },
+    /// Hashes `len` bytes of a repeating pattern with the host's SHA-256,
+    /// checking one in `verify_one_in` chunks, and commits the digest along
+    /// with the digest computed in the guest, if `len` is small.
+    ShaAccel {
+        len: u32,
+        verify_one_in: u32,
+    },
+    /// Logs `count` numbered messages with `env::log`.
+    LogMany {
+        count: u32,
+    },
+    /// Adds the pass number to every word of a buffer of `bytes` bytes,
+    /// `passes` times over, and commits the sum of the words, so that the
+    /// buffer is the guest's working set throughout.
+    SweepMemory {
+        bytes: u32,
+        passes: u32,
+    },
+    /// Waits for data on `fd` by polling `SYS_READ_AVAIL` until it reports
+    /// some, then reads a byte, `rounds` times over, and commits the bytes.
+    PollReadAvail {
+        fd: u32,
+        rounds: u32,
+    },
+    /// Writes `bytes` zero bytes to `fd` with `SYS_WRITE`, `chunk` bytes at a
+    /// time.
+    WriteZeros {
+        fd: u32,
+        bytes: u32,
+        chunk: u32,
+    },
     Oom,
     OutOfBounds,
     OutOfBoundsEcall,
@@ -90,3 +245,31 @@ pub enum MultiTestSpec {
 }
 
 declare_syscall!(pub SYS_MULTI_TEST);
+
+/// The input read by [MultiTestSpec::ReadChecked].
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq)]
+pub struct CheckedPoint {
+    pub x: u32,
+    pub y: u32,
+}
+
+/// The [InputSchema] of [CheckedPoint].
+pub const CHECKED_POINT_SCHEMA: &str = "CheckedPoint { x: u32, y: u32 }";
+
+impl InputSchema for CheckedPoint {
+    const SCHEMA: &'static str = CHECKED_POINT_SCHEMA;
+}
+
+/// The first frame written by [MultiTestSpec::WriteFramed].
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq)]
+pub struct FramedHeader {
+    pub version: u32,
+    pub count: u32,
+}
+
+/// An element of the second frame written by [MultiTestSpec::WriteFramed].
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq)]
+pub struct FramedRecord {
+    pub id: u32,
+    pub name: String,
+}
@@ -26,6 +26,12 @@ mod libm_extern;
 #[cfg(all(feature = "rust-runtime", target_os = "zkvm"))]
 pub mod rust_rt;
 
+/// The version of this crate.
+///
+/// Guests record the version they were built with in their ELF, so that the
+/// host can tell when a guest was built against a different platform.
+pub const VERSION: &str = env!("CARGO_PKG_VERSION");
+
 /// Size of a zkVM machine word in bytes.
 /// 4 bytes (i.e. 32 bits) as the zkVM is an implementation of the rv32im ISA.
 pub const WORD_SIZE: usize = core::mem::size_of::<u32>();
@@ -46,6 +46,9 @@ mod entrypoint {
     // This version of _start is marked as "weak" so it only gets used if
     // start isn't already defined by e.g. risc0_zkvm::guest which needs
     // to initialize things like the journal.
+    //
+    // A non-zero sp at entry was set by the host, as were a0 and a1, the
+    // bounds of the heap.
 
     static STACK_TOP: u32 = crate::memory::STACK_TOP;
 
@@ -58,13 +61,19 @@ mod entrypoint {
         .option norelax
         la gp, __global_pointer$
         .option pop
+        bnez sp, 1f
         la sp, {0}
         lw sp, 0(sp)
+    1:
+        la t0, {1}
+        sw a0, 0(t0)
+        sw a1, 4(t0)
         call main
         li a1, 0
         call sys_halt
     "#,
-        sym STACK_TOP
+        sym STACK_TOP,
+        sym crate::syscall::HEAP_BOUNDS,
     );
 }
 
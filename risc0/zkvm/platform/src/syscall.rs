@@ -78,6 +78,10 @@ pub const MAX_BUF_BYTES: usize = 4 * 1024;
 pub const MAX_BUF_WORDS: usize = MAX_BUF_BYTES / WORD_SIZE;
 pub const MAX_SHA_COMPRESS_BLOCKS: usize = 1000;
 
+// The message the allocator panics with when the heap is exhausted, which the
+// host recognizes to report the guest's heap usage.
+pub const HEAP_EXHAUSTED_MSG: &str = "Out of memory!";
+
 pub mod bigint {
     pub const OP_MULTIPLY: u32 = 0;
 
@@ -123,18 +127,31 @@ macro_rules! declare_syscall {
 }
 
 pub mod nr {
+    declare_syscall!(pub SYS_ASSUMPTION);
+    declare_syscall!(pub SYS_BIGINT_MODMUL);
+    declare_syscall!(pub SYS_CAPABILITIES);
+    declare_syscall!(pub SYS_CHECK);
     declare_syscall!(pub SYS_CYCLE_COUNT);
+    declare_syscall!(pub SYS_CYCLES_REMAINING);
+    declare_syscall!(pub SYS_EXEC);
     declare_syscall!(pub SYS_GETENV);
+    declare_syscall!(pub SYS_GETENV_PAGE);
+    declare_syscall!(pub SYS_HOST_COMMITS);
+    declare_syscall!(pub SYS_JOURNAL_PREIMAGE);
     declare_syscall!(pub SYS_ARGC);
     declare_syscall!(pub SYS_ARGV);
     declare_syscall!(pub SYS_LOG);
+    declare_syscall!(pub SYS_NAMED_INPUT);
+    declare_syscall!(pub SYS_NONCE);
     declare_syscall!(pub SYS_PANIC);
     declare_syscall!(pub SYS_RANDOM);
     declare_syscall!(pub SYS_READ_AVAIL);
     declare_syscall!(pub SYS_READ);
+    declare_syscall!(pub SYS_SHA256);
     declare_syscall!(pub SYS_WRITE);
     declare_syscall!(pub SYS_VERIFY);
     declare_syscall!(pub SYS_VERIFY_INTEGRITY);
+    declare_syscall!(pub SYS_YIELD);
 }
 
 impl SyscallName {
@@ -411,6 +428,27 @@ pub extern "C" fn sys_cycle_count() -> usize {
     a0 as usize
 }
 
+/// Returns the number of cycles left before the session limit, or
+/// `u64::MAX` if there is none.
+#[cfg_attr(feature = "export-syscalls", no_mangle)]
+pub extern "C" fn sys_cycles_remaining() -> u64 {
+    let Return(lo, hi) = unsafe { syscall_0(nr::SYS_CYCLES_REMAINING, null_mut(), 0) };
+    (hi as u64) << 32 | lo as u64
+}
+
+/// Reports a failed guest check with the given `code` to the host.
+#[cfg_attr(feature = "export-syscalls", no_mangle)]
+pub extern "C" fn sys_check(code: u32) {
+    unsafe { syscall_1(nr::SYS_CHECK, null_mut(), 0, code) };
+}
+
+/// Yields control back to the host at the end of the current segment, passing
+/// it `tag`.
+#[cfg_attr(feature = "export-syscalls", no_mangle)]
+pub extern "C" fn sys_yield(tag: u32) {
+    unsafe { syscall_1(nr::SYS_YIELD, null_mut(), 0, tag) };
+}
+
 /// Reads the given number of bytes into the given buffer, posix-style.  Returns
 /// the number of bytes actually read.  On end of file, returns 0.
 ///
@@ -507,8 +545,9 @@ pub unsafe extern "C" fn sys_read(fd: u32, recv_ptr: *mut u8, nrequested: usize)
 ///
 /// * recv_buf must be word-aligned.
 ///
-/// * All of the buffer is overwritten, even in the case of EOF
-/// mid-way through.
+/// * Reaching EOF mid-way through fails execution on the host, unless
+/// the host allows short reads, in which case the number of bytes read
+/// before EOF is returned.
 ///
 /// # Safety
 ///
@@ -546,6 +585,10 @@ fn sys_read_internal(fd: u32, recv_ptr: *mut u32, nwords: usize, nbytes: usize)
         nbytes_remain -= nread_bytes;
         nread_total_bytes += nread_bytes;
         final_word = last_word;
+        if nread_bytes == 0 {
+            // End of file; the host zero-filled the rest of the buffer.
+            break;
+        }
     }
     (nread_total_bytes, final_word)
 }
@@ -577,7 +620,9 @@ pub unsafe extern "C" fn sys_write(fd: u32, write_ptr: *const u8, nbytes: usize)
 /// out_nwords).
 ///
 /// Returns the length of the value, in bytes, or usize::MAX if the variable is
-/// not set.
+/// not set. The returned length may exceed `out_nwords * WORD_SIZE`, in which
+/// case the value was truncated and the call should be retried with a buffer
+/// of at least the returned length.
 ///
 /// This is normally called twice to read an environment variable:
 /// Once to get the length of the value, and once to fill in allocated
@@ -610,6 +655,57 @@ pub unsafe extern "C" fn sys_getenv(
     }
 }
 
+/// Retrieves part of the value of an environment variable: the bytes of the
+/// value starting at `offset`, as many as fit in the memory at [out_words,
+/// out_words + out_nwords).
+///
+/// Returns the length of the whole value, in bytes, or usize::MAX if the
+/// variable is not set. The number of bytes stored is the smaller of
+/// `out_nwords * WORD_SIZE` and the length past `offset`, so a value of any
+/// size can be read through a fixed buffer by advancing `offset`.
+///
+/// This is a separate syscall from [sys_getenv], since guests built before it
+/// existed do not set the register holding `offset`. Its registers are:
+///
+/// | register | value         |
+/// |----------|---------------|
+/// | a3       | `varname`     |
+/// | a4       | `varname_len` |
+/// | a5       | `offset`      |
+///
+/// and on return a0 holds the length of the value and a1 the number of bytes
+/// stored.
+///
+/// NOTE: Repeated calls to sys_getenv_page are not guaranteed to result in the
+/// same data being returned. Returned data is entirely in the control of the
+/// host.
+///
+/// # Safety
+///
+/// `out_words` and `varname` must be aligned and dereferenceable.
+#[cfg_attr(feature = "export-syscalls", no_mangle)]
+pub unsafe extern "C" fn sys_getenv_page(
+    out_words: *mut u32,
+    out_nwords: usize,
+    varname: *const u8,
+    varname_len: usize,
+    offset: usize,
+) -> usize {
+    let Return(a0, _) = syscall_3(
+        nr::SYS_GETENV_PAGE,
+        out_words,
+        out_nwords,
+        varname as u32,
+        varname_len as u32,
+        offset as u32,
+    );
+    if a0 == u32::MAX {
+        usize::MAX
+    } else {
+        a0 as usize
+    }
+}
+
 /// Retrieves the count of arguments provided to program execution.
 ///
 /// NOTE: Repeated calls to sys_argc are not guaranteed to result in the same
@@ -648,6 +744,14 @@ pub extern "C" fn sys_alloc_words(nwords: usize) -> *mut u32 {
     unsafe { sys_alloc_aligned(WORD_SIZE * nwords, WORD_SIZE) as *mut u32 }
 }
 
+/// The start and end of the heap, which `_start` sets from the initial values
+/// of registers a0 and a1 so that the host can move the heap. Zero selects the
+/// default: the heap starts at the end of the program and ends where SYSTEM
+/// memory begins.
+#[cfg(feature = "export-syscalls")]
+#[doc(hidden)]
+pub static mut HEAP_BOUNDS: [usize; 2] = [0; 2];
+
 #[cfg(feature = "export-syscalls")]
 #[no_mangle]
 pub unsafe extern "C" fn sys_alloc_aligned(bytes: usize, align: usize) -> *mut u8 {
@@ -668,8 +772,14 @@ pub unsafe extern "C" fn sys_alloc_aligned(bytes: usize, align: usize) -> *mut u
     // SAFETY: Single threaded, so nothing else can touch this while we're working.
     let mut heap_pos = unsafe { HEAP_POS };
 
+    // SAFETY: Only written by `_start`, before any allocation.
+    let [heap_base, heap_limit] = unsafe { HEAP_BOUNDS };
+
     if heap_pos == 0 {
-        heap_pos = unsafe { (&_end) as *const u8 as usize };
+        heap_pos = match heap_base {
+            0 => unsafe { (&_end) as *const u8 as usize },
+            base => base,
+        };
     }
 
     let offset = heap_pos & (align - 1);
@@ -683,9 +793,14 @@ pub unsafe extern "C" fn sys_alloc_aligned(bytes: usize, align: usize) -> *mut u
     let ptr = heap_pos as *mut u8;
     heap_pos += bytes;
 
-    // Check to make sure heap doesn't collide with SYSTEM memory.
-    if crate::memory::SYSTEM.start() < heap_pos {
-        const MSG: &[u8] = "Out of memory!".as_bytes();
+    // Check to make sure heap doesn't collide with SYSTEM memory, or with the
+    // stack if the host moved it above the heap.
+    let heap_limit = match heap_limit {
+        0 => crate::memory::SYSTEM.start(),
+        limit => limit,
+    };
+    if heap_limit < heap_pos {
+        const MSG: &[u8] = HEAP_EXHAUSTED_MSG.as_bytes();
         unsafe { sys_panic(MSG.as_ptr(), MSG.len()) };
     }
 
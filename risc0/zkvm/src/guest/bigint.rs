@@ -0,0 +1,155 @@
+// Copyright 2023 RISC Zero, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Host-accelerated modular arithmetic on arbitrary width big integers.
+//!
+//! Modular reduction of wide integers is expensive to perform in the guest.
+//! The functions in this module ask the host to perform the reduction and then
+//! check the untrusted result, which only requires multiplication and
+//! comparison. For a modular multiplication `a * b mod m` the host returns a
+//! quotient `q` and a remainder `r`, and the guest checks that
+//! `a * b == q * m + r` and `r < m`. A host returning an incorrect result
+//! causes the guest to panic.
+//!
+//! The host only answers these calls when enabled with
+//! `ExecutorEnvBuilder::bigint_accel(true)` or
+//! `ProverOpts::with_bigint_accel()`; otherwise the guest fails at the first
+//! call.
+//!
+//! All integers are represented as little-endian slices of `u32` limbs.
+//! Operands passed to the same call must have the same number of limbs, and
+//! results have the same number of limbs as the modulus.
+
+use alloc::{vec, vec::Vec};
+
+use risc0_zkvm_platform::syscall::nr::SYS_BIGINT_MODMUL;
+
+use crate::guest::env;
+
+/// Compute `a * b mod modulus` with the help of the host.
+///
+/// # Panics
+///
+/// Panics if the operands do not have the same number of limbs, if the modulus
+/// is zero, or if the host returns an incorrect result.
+pub fn modmul(a: &[u32], b: &[u32], modulus: &[u32]) -> Vec<u32> {
+    let n = modulus.len();
+    assert!(
+        a.len() == n && b.len() == n,
+        "bigint operands must have the same number of limbs as the modulus"
+    );
+    assert!(modulus.iter().any(|&x| x != 0), "bigint modulus is zero");
+
+    let mut to_host = Vec::with_capacity(3 * n);
+    to_host.extend_from_slice(a);
+    to_host.extend_from_slice(b);
+    to_host.extend_from_slice(modulus);
+    // The host reports the length of its reply in bytes.
+    let from_host =
+        env::send_recv_slice::<u8, u8>(SYS_BIGINT_MODMUL, bytemuck::cast_slice(&to_host));
+    let from_host: &[u32] = bytemuck::cast_slice(from_host);
+    assert_eq!(
+        from_host.len(),
+        3 * n,
+        "host returned a malformed bigint result"
+    );
+    let (quotient, remainder) = from_host.split_at(2 * n);
+
+    // Check that a * b == q * m + r and r < m.
+    let product = mul(a, b);
+    let mut expected = mul(quotient, modulus);
+    let carry = add_assign(&mut expected, remainder);
+    let (low, high) = expected.split_at(product.len());
+    if carry || low != product || high.iter().any(|&x| x != 0) || !less_than(remainder, modulus) {
+        panic!("host returned an incorrect bigint modmul result");
+    }
+
+    remainder.to_vec()
+}
+
+/// Compute `base ^ exponent mod modulus`, with each reduction done by the host.
+///
+/// The exponentiation itself runs in the guest, by square-and-multiply, with
+/// each modular multiplication done by [modmul]. The host only saves the guest
+/// the division of each step: the guest still computes the products it needs
+/// to check the host's results, so this costs about twice the multiplications
+/// of an exponentiation done entirely in the guest, in exchange for no
+/// divisions.
+///
+/// # Panics
+///
+/// Panics if `base` and `modulus` do not have the same number of limbs, if the
+/// modulus is zero, or if the host returns an incorrect result.
+pub fn modexp(base: &[u32], exponent: &[u32], modulus: &[u32]) -> Vec<u32> {
+    let mut one = vec![0u32; modulus.len()];
+    if let Some(x) = one.first_mut() {
+        *x = 1;
+    }
+    // Reduce the base so that it has the same number of limbs as the modulus.
+    let base = modmul(base, &one, modulus);
+
+    let bits = exponent.len() * 32;
+    let Some(top) = (0..bits).rev().find(|&i| bit(exponent, i)) else {
+        // A zero exponent yields one, reduced in case the modulus is one.
+        return modmul(&one, &one, modulus);
+    };
+
+    let mut acc = base.clone();
+    for i in (0..top).rev() {
+        acc = modmul(&acc, &acc, modulus);
+        if bit(exponent, i) {
+            acc = modmul(&acc, &base, modulus);
+        }
+    }
+    acc
+}
+
+fn bit(x: &[u32], i: usize) -> bool {
+    (x[i / 32] >> (i % 32)) & 1 == 1
+}
+
+fn mul(a: &[u32], b: &[u32]) -> Vec<u32> {
+    let mut out = vec![0u32; a.len() + b.len()];
+    for (i, &x) in a.iter().enumerate() {
+        let mut carry = 0u64;
+        for (j, &y) in b.iter().enumerate() {
+            let t = x as u64 * y as u64 + out[i + j] as u64 + carry;
+            out[i + j] = t as u32;
+            carry = t >> 32;
+        }
+        out[i + b.len()] = carry as u32;
+    }
+    out
+}
+
+/// Adds `b` into `a`, returning whether the sum overflowed.
+fn add_assign(a: &mut [u32], b: &[u32]) -> bool {
+    let mut carry = 0u64;
+    for (i, x) in a.iter_mut().enumerate() {
+        let t = *x as u64 + b.get(i).copied().unwrap_or(0) as u64 + carry;
+        *x = t as u32;
+        carry = t >> 32;
+    }
+    carry != 0
+}
+
+/// Returns whether `a < b`, for slices of the same length.
+fn less_than(a: &[u32], b: &[u32]) -> bool {
+    for (x, y) in a.iter().rev().zip(b.iter().rev()) {
+        if x != y {
+            return x < y;
+        }
+    }
+    false
+}
@@ -20,8 +20,10 @@ use bytemuck::Pod;
 use risc0_zkvm_platform::{
     fileno,
     syscall::{
-        self, sys_alloc_words, sys_cycle_count, sys_halt, sys_log, sys_pause, sys_read,
-        sys_read_words, sys_verify, sys_verify_integrity, sys_write, syscall_2, SyscallName,
+        self, nr, sys_alloc_words, sys_argc, sys_argv, sys_check, sys_cycle_count, sys_cycles_remaining,
+        sys_getenv, sys_getenv_page,
+        sys_halt, sys_log, sys_pause, sys_read, sys_read_words, sys_verify, sys_verify_integrity,
+        sys_write, sys_yield, syscall_2, SyscallName,
     },
     WORD_SIZE,
 };
@@ -35,11 +37,18 @@ use crate::{
         rust_crypto::{Digest as _, Sha256},
         Digest, Digestible, DIGEST_WORDS,
     },
-    ExitCode, ReceiptMetadata,
+    ExitCode, InputSchema, ReceiptMetadata,
 };
 
 static mut HASHER: Option<Sha256> = None;
 
+/// The running hash of the items committed since [hashed_journal] was called,
+/// or `None` if the guest commits to the journal itself.
+static mut JOURNAL_CHAIN: Option<Digest> = None;
+
+/// Whether anything has been written to the journal since it was started.
+static mut JOURNAL_WRITTEN: bool = false;
+
 /// Digest of the running list of [Assumptions], generated by the [verify] and
 /// [verify_integrity] calls made by the guest.
 static mut ASSUMPTIONS_DIGEST: MaybePruned<Assumptions> = MaybePruned::Pruned(Digest::ZERO);
@@ -52,11 +61,18 @@ static mut MEMORY_IMAGE_ENTROPY: [u8; 16] = [0u8; 16];
 
 pub(crate) fn init() {
     unsafe { HASHER = Some(Sha256::new()) };
+    unsafe { JOURNAL_WRITTEN = false };
     unsafe { getrandom::getrandom(&mut MEMORY_IMAGE_ENTROPY).unwrap() };
 }
 
 pub(crate) fn finalize(halt: bool, user_exit: u8) {
     unsafe {
+        // A hashed journal holds just the hash of the items, and starts again
+        // from the beginning after a pause.
+        if let Some(chain) = JOURNAL_CHAIN.as_mut() {
+            let chain = core::mem::replace(chain, Digest::ZERO);
+            journal_writer().write_bytes(chain.as_bytes());
+        }
         let hasher = core::mem::take(&mut HASHER);
         let journal_digest: Digest = hasher.unwrap().finalize().as_slice().try_into().unwrap();
         let output = Output {
@@ -90,6 +106,31 @@ pub fn pause(exit_code: u8) {
     init();
 }
 
+/// Yield control back to the host, passing it `tag`.
+///
+/// The host ends the current segment here and returns from `run` with a
+/// session whose `yield_tag` is set to `tag`. Calling `run` again continues
+/// execution right after this call. Unlike [pause], this does not finalize the
+/// journal or change the exit code of the proof; the segment simply ends with
+/// a system split.
+pub fn yield_to_host(tag: u32) {
+    sys_yield(tag)
+}
+
+/// Report to the host that an invariant identified by `code` does not hold,
+/// unless `cond` is true.
+///
+/// Unlike an assertion, a failed check does not stop the guest: the host
+/// records the code along with where it failed, and execution continues. The
+/// host can instead be configured to stop at the first failed check; see
+/// `ExecutorEnvBuilder::check_policy`. A check that passes costs no more than
+/// testing `cond`.
+pub fn check(cond: bool, code: u32) {
+    if !cond {
+        sys_check(code)
+    }
+}
+
 /// Exchange data with the host.
 pub fn syscall(syscall: SyscallName, to_host: &[u8], from_host: &mut [u32]) -> syscall::Return {
     unsafe {
@@ -263,6 +304,55 @@ impl fmt::Display for VerifyIntegrityError {
 #[cfg(feature = "std")]
 impl std::error::Error for VerifyIntegrityError {}
 
+/// Read the encoded receipt for the assumption at the given index.
+///
+/// The host serves the receipts added with `ExecutorEnvBuilder::add_assumption`,
+/// in the order they were added, as produced by `Receipt::into_assumption_bytes`.
+/// Only proven assumptions (i.e. those backed by a receipt) can be read.
+///
+/// Note that reading the bytes of an assumption does not add it to the
+/// assumptions list of this execution. Use [verify] or [verify_integrity] to
+/// do so.
+pub fn read_assumption(index: u32) -> &'static [u8] {
+    send_recv_slice(nr::SYS_ASSUMPTION, &index.to_le_bytes())
+}
+
+/// The result of running a guest with [exec].
+pub struct ExecOutput {
+    /// The image ID of the guest that was run.
+    pub image_id: Digest,
+
+    /// The journal committed by the guest.
+    pub journal: alloc::vec::Vec<u8>,
+}
+
+/// Run the guest the host has registered under `name`, with `arg` as its
+/// stdin, and return its journal.
+///
+/// The host registers guests with `ExecutorEnvBuilder::guest_registry`, and
+/// executes the named guest to completion before this returns. If the host
+/// also proves nested guests, the receipt is added as an assumption and can be
+/// checked with [verify]. Execution fails if the guest is not registered, or
+/// if it exceeds the depth or cycle limits set by the host.
+pub fn exec(name: &str, arg: &[u8]) -> ExecOutput {
+    let request = crate::serde::to_vec(&(name, arg)).unwrap();
+    let response = send_recv_slice::<u8, u8>(nr::SYS_EXEC, bytemuck::cast_slice(&request));
+    let (image_id, journal) = crate::serde::from_slice(response).unwrap();
+    ExecOutput { image_id, journal }
+}
+
+/// Return whether the host has a handler for the given syscall.
+///
+/// This lets a guest check for optional syscalls, such as those registered
+/// with `ExecutorEnvBuilder::io_callback`, before calling them. Each call
+/// queries the host for its full list of syscalls.
+pub fn host_supports(syscall: SyscallName) -> bool {
+    let name = syscall.as_str().as_bytes();
+    send_recv_slice::<u8, u8>(nr::SYS_CAPABILITIES, &[])
+        .split(|byte| *byte == 0)
+        .any(|supported| supported == name)
+}
+
 /// Exchanges slices of plain old data with the host.
 ///
 /// This makes two calls to the given syscall; the first gets the length of the
@@ -278,6 +368,88 @@ pub fn send_recv_slice<T: Pod, U: Pod>(syscall_name: SyscallName, to_host: &[T])
     &bytemuck::cast_slice(from_host_buf)[..nelem as usize]
 }
 
+/// Return the value of the environment variable `name` set by the host, or
+/// `None` if it is not set.
+///
+/// The value is first read into a small buffer; if the host reports that it
+/// did not fit, the read is retried with a buffer of the reported size.
+pub fn var(name: &str) -> Option<&'static str> {
+    let mut nwords = 16;
+    loop {
+        let buf = unsafe { core::slice::from_raw_parts_mut(sys_alloc_words(nwords), nwords) };
+        let len = unsafe { sys_getenv(buf.as_mut_ptr(), nwords, name.as_ptr(), name.len()) };
+        if len == usize::MAX {
+            return None;
+        }
+        if len <= nwords * WORD_SIZE {
+            let bytes = &bytemuck::cast_slice::<u32, u8>(buf)[..len];
+            return Some(core::str::from_utf8(bytes).expect("environment variable is not UTF-8"));
+        }
+        nwords = align_up(len, WORD_SIZE) / WORD_SIZE;
+    }
+}
+
+/// Return the value of the environment variable `name` set by the host, as
+/// bytes, or `None` if it is not set.
+///
+/// Unlike [var], which reads the whole value in one call, this reads the value
+/// in pages of [VAR_PAGE_BYTES] through a single buffer, so only the returned
+/// vector needs room for the whole value. Use it for large values, such as a
+/// configuration passed as JSON.
+pub fn var_large(name: &str) -> Option<alloc::vec::Vec<u8>> {
+    let mut page = alloc::vec![0u32; VAR_PAGE_BYTES / WORD_SIZE];
+    let mut value = alloc::vec::Vec::new();
+    loop {
+        let offset = value.len();
+        let len = unsafe {
+            sys_getenv_page(page.as_mut_ptr(), page.len(), name.as_ptr(), name.len(), offset)
+        };
+        if len == usize::MAX {
+            return None;
+        }
+        if offset == 0 {
+            // The guest's allocator does not free, so avoid growing the vector.
+            value.reserve_exact(len);
+        }
+        let nbytes = len.saturating_sub(offset).min(VAR_PAGE_BYTES);
+        value.extend_from_slice(&bytemuck::cast_slice::<u32, u8>(&page)[..nbytes]);
+        if nbytes == 0 || value.len() == len {
+            return Some(value);
+        }
+    }
+}
+
+/// The size of the pages in which [var_large] reads a value.
+pub const VAR_PAGE_BYTES: usize = 1024;
+
+/// Return the arguments set by the host with `ExecutorEnvBuilder::args`, in
+/// order, or an empty vector if there are none.
+///
+/// Like [var], each argument is first read into a small buffer and read again
+/// with a buffer of the reported size if it did not fit, so arguments of any
+/// length are received whole. Arguments are copied into the guest's heap,
+/// which is the only limit on their total size.
+pub fn args() -> alloc::vec::Vec<alloc::string::String> {
+    let argc = unsafe { sys_argc() };
+    (0..argc)
+        .map(|index| {
+            let mut nwords = 16;
+            loop {
+                let buf =
+                    unsafe { core::slice::from_raw_parts_mut(sys_alloc_words(nwords), nwords) };
+                let len = unsafe { sys_argv(buf.as_mut_ptr(), nwords, index) };
+                if len <= nwords * WORD_SIZE {
+                    let bytes = &bytemuck::cast_slice::<u32, u8>(buf)[..len];
+                    return core::str::from_utf8(bytes)
+                        .expect("argument is not UTF-8")
+                        .into();
+                }
+                nwords = align_up(len, WORD_SIZE) / WORD_SIZE;
+            }
+        })
+        .collect()
+}
+
 /// Read private data from the host and deserializes it.
 pub fn read<T: DeserializeOwned>() -> T {
     stdin().read()
@@ -297,6 +469,19 @@ pub fn write<T: Serialize>(data: &T) {
     stdout().write(data)
 }
 
+/// Serialize the given data and write it to the STDOUT of the zkVM as a
+/// single frame, prefixed by its length in words.
+///
+/// Unlike with [write], the host can tell apart the values written by
+/// successive calls and decode each one as a different type, e.g. with
+/// `FramedOutput`.
+pub fn write_framed<T: Serialize>(data: &T) {
+    let mut frame = alloc::vec![0];
+    data.serialize(&mut Serializer::new(&mut frame)).unwrap();
+    frame[0] = (frame.len() - 1) as u32;
+    stdout().write_slice(&frame);
+}
+
 /// Write the given slice to the STDOUT of the zkVM.
 ///
 /// This is available to the host as the private output on the prover.
@@ -311,7 +496,11 @@ pub fn write_slice<T: Pod>(slice: &[T]) {
 /// Data in the journal is included in the receipt and is available to the
 /// verifier. It is considered "public" data.
 pub fn commit<T: Serialize>(data: &T) {
-    journal().write(data)
+    if unsafe { JOURNAL_CHAIN.is_some() } {
+        commit_item(bytemuck::cast_slice(&crate::serde::to_vec(data).unwrap()));
+    } else {
+        journal().write(data)
+    }
 }
 
 /// Commit the given slice to the journal.
@@ -319,7 +508,46 @@ pub fn commit<T: Serialize>(data: &T) {
 /// Data in the journal is included in the receipt and is available to the
 /// verifier. It is considered "public" data.
 pub fn commit_slice<T: Pod>(slice: &[T]) {
-    journal().write_slice(slice);
+    commit_item(bytemuck::cast_slice(slice));
+}
+
+/// Commit `data` as [commit] does, after the digest of the [InputSchema] of
+/// `T` if `prefixed` is true.
+///
+/// Use [commit_checked!](crate::commit_checked), which also declares the
+/// schema in the ELF, rather than calling this directly.
+#[doc(hidden)]
+pub fn commit_checked<T: Serialize + InputSchema>(data: &T, prefixed: bool) {
+    if prefixed {
+        let digest = <crate::sha::Impl as crate::sha::Sha256>::hash_bytes(T::SCHEMA.as_bytes());
+        commit_slice(digest.as_bytes());
+    }
+    commit(data)
+}
+
+/// Commit to a hash chain of the items committed from now on, rather than to
+/// the items themselves.
+///
+/// Each later call to [commit] or [commit_slice] commits one item, which is
+/// sent to the host instead of being written to the journal. The journal of
+/// the receipt is then the 32-byte hash of the chain of items; see
+/// [hashed_journal](crate::hashed_journal) for how it is computed. The host
+/// collects the items, for example with
+/// `ExecutorEnvBuilder::hashed_journal(true)` and `Prover::journal_preimages`,
+/// and a verifier given them checks them against the receipt.
+///
+/// This keeps the journal a constant size for a guest that commits a lot of
+/// data that the verifier can get elsewhere. Panics if anything was already
+/// committed to the journal. With a hashed journal, [journal] cannot be
+/// used.
+pub fn hashed_journal() {
+    unsafe {
+        assert!(
+            !JOURNAL_WRITTEN,
+            "env::hashed_journal must be called before anything is committed"
+        );
+        JOURNAL_CHAIN.get_or_insert(Digest::ZERO);
+    }
 }
 
 /// Return the number of processor cycles that have occurred since the guest
@@ -328,6 +556,17 @@ pub fn get_cycle_count() -> usize {
     sys_cycle_count()
 }
 
+/// Return the number of cycles left before the host stops the guest at its
+/// session limit, or `None` if the host set no limit.
+///
+/// A guest can use this to stop early and commit a partial result. The value
+/// comes from the host and is not attested to by the receipt, so it must only
+/// be used as advice.
+pub fn cycles_remaining() -> Option<u64> {
+    let remaining = sys_cycles_remaining();
+    (remaining != u64::MAX).then_some(remaining)
+}
+
 /// Print a message to the debug console.
 pub fn log(msg: &str) {
     let msg = msg.as_bytes();
@@ -347,17 +586,88 @@ pub fn stderr() -> FdWriter<impl for<'a> Fn(&'a [u8])> {
 }
 
 /// Return a writer for the JOURNAL.
+///
+/// Panics if the guest switched to a hashed journal with [hashed_journal].
 pub fn journal() -> FdWriter<impl for<'a> Fn(&'a [u8])> {
+    assert!(
+        unsafe { JOURNAL_CHAIN.is_none() },
+        "env::journal cannot be used with a hashed journal; use env::commit"
+    );
+    journal_writer()
+}
+
+fn journal_writer() -> FdWriter<impl for<'a> Fn(&'a [u8])> {
     FdWriter::new(fileno::JOURNAL, |bytes| {
         unsafe { HASHER.as_mut().unwrap_unchecked().update(bytes) };
+        unsafe { JOURNAL_WRITTEN = true };
     })
 }
 
+// Commit `bytes` as one item, to the journal or to its hash chain.
+fn commit_item(bytes: &[u8]) {
+    match unsafe { JOURNAL_CHAIN.as_mut() } {
+        Some(chain) => {
+            send_recv_slice::<u8, u8>(nr::SYS_JOURNAL_PREIMAGE, bytes);
+            *chain = crate::hashed_journal::link(chain, bytes);
+        }
+        None => journal_writer().write_bytes(bytes),
+    }
+}
+
+/// Commit the data that host I/O handlers have asked to have committed since
+/// the last call, as one item.
+///
+/// Nothing the host queues with `HostCommits` reaches the journal unless the
+/// guest calls this, so only a guest that is willing to have its journal
+/// extended by the host should call it, at the points where it accepts host
+/// data. The host chooses the bytes; a verifier must not trust them more than
+/// the host that ran the guest. See `HostCommits` on the host for the trust
+/// model.
+pub fn commit_host_data() {
+    let bytes = send_recv_slice::<u8, u8>(nr::SYS_HOST_COMMITS, &[]);
+    if !bytes.is_empty() {
+        commit_item(bytes);
+    }
+}
+
 /// Reaturn a reader for the standard input
 pub fn stdin() -> FdReader {
     FdReader::new(fileno::STDIN)
 }
 
+/// Return a reader for the input the host added under `name` with
+/// `ExecutorEnvBuilder::named_input`.
+///
+/// Each named input has a file descriptor of its own, so named inputs can be
+/// read in any order, independently of [stdin] and of each other. Panics if
+/// the host did not add an input with this name.
+pub fn open_named(name: &str) -> FdReader {
+    let fd = send_recv_slice::<u8, u8>(nr::SYS_NAMED_INPUT, name.as_bytes());
+    let fd: [u8; WORD_SIZE] = fd
+        .try_into()
+        .unwrap_or_else(|_| panic!("no input named {name:?}"));
+    FdReader::new(u32::from_le_bytes(fd))
+}
+
+/// Return the nonce the host provided with `ExecutorEnvBuilder::nonce`.
+///
+/// Commit the nonce as the first item of the journal, before anything else,
+/// to bind the receipt to it; the verifier then checks it with
+/// `Receipt::verify_with_nonce`. Panics if the host did not provide a nonce.
+///
+/// # Example
+///
+/// ```rust,ignore
+/// use risc0_zkvm::guest::env;
+///
+/// env::commit(&env::nonce());
+/// ```
+pub fn nonce() -> [u8; 32] {
+    send_recv_slice::<u8, u8>(nr::SYS_NONCE, &[])
+        .try_into()
+        .expect("the host did not provide a nonce")
+}
+
 /// Reads and deserializes objects
 pub trait Read {
     /// Read data from the host.
@@ -443,13 +753,13 @@ impl WordRead for FdReader {
             return Err(crate::serde::Error::DeserializeUnexpectedEnd);
         }
 
+        // Discard the padding. As with [crate::serde::from_bytes], padding
+        // trimmed from the end of the stream is not an error.
         let unaligned = bytes.len() % WORD_SIZE;
         if unaligned != 0 {
             let pad_bytes = WORD_SIZE - unaligned;
             let mut padding = [0u8; WORD_SIZE];
-            if self.read_bytes_all(&mut padding[..pad_bytes]) != pad_bytes {
-                return Err(crate::serde::Error::DeserializeUnexpectedEnd);
-            }
+            self.read_bytes_all(&mut padding[..pad_bytes]);
         }
         Ok(())
     }
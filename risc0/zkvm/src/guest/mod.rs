@@ -71,8 +71,10 @@
 
 #![deny(missing_docs)]
 
+pub mod bigint;
 pub mod env;
 pub mod sha;
+pub mod sha_accel;
 
 #[cfg(target_os = "zkvm")]
 use core::arch::asm;
@@ -129,9 +131,19 @@ macro_rules! entry {
     };
 }
 
+// The version of risc0-zkvm-platform this guest was built with, which the
+// host checks when it loads the ELF. The section name must match
+// risc0_binfmt::PLATFORM_VERSION_SECTION.
+#[cfg(target_os = "zkvm")]
+#[link_section = ".risc0.platform_version"]
+static PLATFORM_VERSION: [u8; risc0_zkvm_platform::VERSION.len() + 1] =
+    crate::input_schema::input_schema_entry(risc0_zkvm_platform::VERSION);
+
 #[cfg(target_os = "zkvm")]
 #[no_mangle]
 unsafe extern "C" fn __start() {
+    // Reference the version so the linker keeps it.
+    let _ = core::hint::black_box(&PLATFORM_VERSION);
     env::init();
 
     {
@@ -150,6 +162,10 @@ static STACK_TOP: u32 = risc0_zkvm_platform::memory::STACK_TOP;
 // Entry point; sets up global pointer and stack pointer and passes
 // to zkvm_start.  TODO: when asm_const is stablized, use that here
 // instead of defining a symbol and dereferencing it.
+//
+// Registers start out zero unless the host overrides the layout of the guest
+// (see `GuestLayout`): a non-zero sp is then the top of the stack, and a0 and
+// a1 are the bounds of the heap.
 #[cfg(target_os = "zkvm")]
 core::arch::global_asm!(
     r#"
@@ -160,11 +176,17 @@ _start:
     .option norelax;
     la gp, __global_pointer$;
     .option pop;
+    bnez sp, 1f
     la sp, {0}
     lw sp, 0(sp)
+1:
+    la t0, {1}
+    sw a0, 0(t0)
+    sw a1, 4(t0)
     jal ra, __start;
 "#,
-    sym STACK_TOP
+    sym STACK_TOP,
+    sym risc0_zkvm_platform::syscall::HEAP_BOUNDS,
 );
 
 /// Require that accesses to behind the given pointer before the memory
@@ -0,0 +1,145 @@
+// Copyright 2023 RISC Zero, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! SHA-256 hashes computed by the host.
+//!
+//! Hashing in the guest costs cycles for every block hashed. [HostSha256]
+//! instead streams the data to the host, which hashes it and returns the
+//! digest, for a small fraction of the cycles. The host must enable this with
+//! `ExecutorEnvBuilder::sha_accel(true)`; otherwise the guest fails at the first
+//! call.
+//!
+//! **The digest is asserted by the host, not proven.** Nothing in the receipt
+//! shows that it is the hash of the data, and a dishonest host can return any
+//! digest it likes. [HostSha256::verify_one_in] has the guest recompute a
+//! random sample of the data's blocks, which catches a faulty host, or a
+//! dishonest one that does not know which blocks will be checked. The sample
+//! is drawn from randomness supplied by the host, so only checking every
+//! block, with `verify_one_in(1)`, makes the digest as trustworthy as one
+//! computed with [sha](super::sha), at the same cost.
+//!
+//! ```no_run
+//! use risc0_zkvm::guest::sha_accel::HostSha256;
+//!
+//! let mut hasher = HostSha256::new().verify_one_in(16);
+//! hasher.update(b"hello ");
+//! hasher.update(b"world");
+//! let digest = hasher.finalize();
+//! ```
+
+use alloc::vec::Vec;
+
+use risc0_zkvm_platform::syscall::nr::SYS_SHA256;
+
+use super::env::send_recv_slice;
+use crate::{
+    sha::Digest,
+    sha_accel::{encode_request, Sha256Stream, MAX_CHUNK_BYTES, OP_FINAL, OP_INIT, OP_UPDATE},
+};
+
+/// A SHA-256 hash computed by the host from data streamed to it by the
+/// guest; see the [module documentation](self).
+pub struct HostSha256 {
+    id: u32,
+    // Mirrors the host's stream, so that a chunk can be checked from the
+    // state the host reported before it.
+    stream: Sha256Stream,
+    verify_one_in: u32,
+}
+
+impl Default for HostSha256 {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl HostSha256 {
+    /// Start a hash on the host.
+    pub fn new() -> Self {
+        let id = send_recv_slice::<u8, u8>(SYS_SHA256, &encode_request(OP_INIT, 0, &[]));
+        Self {
+            id: u32::from_le_bytes(id.try_into().expect("malformed SYS_SHA256 response")),
+            stream: Sha256Stream::new(),
+            verify_one_in: 0,
+        }
+    }
+
+    /// Recompute about one in every `n` chunks of the data sent to the host,
+    /// and the final padding of the hash, in the guest, and panic if the host
+    /// got any of them wrong.
+    ///
+    /// Data is sent to the host in chunks of at most
+    /// [MAX_CHUNK_BYTES](crate::sha_accel::MAX_CHUNK_BYTES). `n` of 1 checks
+    /// everything, and 0, the default, checks nothing.
+    pub fn verify_one_in(mut self, n: u32) -> Self {
+        self.verify_one_in = n;
+        self
+    }
+
+    /// Add `data` to the hash.
+    pub fn update(&mut self, data: &[u8]) {
+        for chunk in data.chunks(MAX_CHUNK_BYTES) {
+            let state = self.call(OP_UPDATE, chunk);
+            if self.sampled() {
+                assert_eq!(
+                    self.stream.update(chunk),
+                    state,
+                    "host returned a wrong SHA-256 state"
+                );
+            } else {
+                self.stream.advance(chunk, state);
+            }
+        }
+    }
+
+    /// The digest of the data added to the hash.
+    pub fn finalize(self) -> Digest {
+        let digest = self.call(OP_FINAL, &[]);
+        if self.verify_one_in != 0 {
+            assert_eq!(
+                self.stream.finalize(),
+                digest,
+                "host returned a wrong SHA-256 digest"
+            );
+        }
+        digest
+    }
+
+    fn call(&self, op: u32, data: &[u8]) -> Digest {
+        let request: Vec<u8> = encode_request(op, self.id, data);
+        let response = send_recv_slice::<u8, u8>(SYS_SHA256, &request);
+        Digest::try_from(response).expect("malformed SYS_SHA256 response")
+    }
+
+    // Whether to check the next chunk.
+    fn sampled(&self) -> bool {
+        match self.verify_one_in {
+            0 => false,
+            1 => true,
+            n => {
+                let mut word = [0; 4];
+                getrandom::getrandom(&mut word).unwrap();
+                u32::from_le_bytes(word) % n == 0
+            }
+        }
+    }
+}
+
+/// The digest of `data`, computed by the host without any checks; see the
+/// [module documentation](self).
+pub fn hash(data: &[u8]) -> Digest {
+    let mut hasher = HostSha256::new();
+    hasher.update(data);
+    hasher.finalize()
+}
@@ -0,0 +1,66 @@
+// Copyright 2023 RISC Zero, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Journals that commit to a hash chain of items sent out of band.
+//!
+//! A guest that calls `env::hashed_journal` commits each item to a running
+//! hash rather than to the journal, and sends the item to the host. Its
+//! journal is then the final 32-byte hash, whatever it committed, and the
+//! host hands out the items themselves, for example with
+//! `Prover::journal_preimages`. A verifier recomputes the hash from the items
+//! with [chain], or checks a receipt with `Receipt::verify_with_preimages`.
+//!
+//! The hash starts at [Digest::ZERO], and each item is added with [link].
+
+use crate::sha::{
+    rust_crypto::{Digest as _, Sha256},
+    Digest,
+};
+
+/// The hash chain `prev` extended by `item`: the SHA-256 hash of `prev`
+/// followed by `item`.
+pub fn link(prev: &Digest, item: &[u8]) -> Digest {
+    let mut hasher = Sha256::new();
+    hasher.update(prev.as_bytes());
+    hasher.update(item);
+    hasher.finalize().as_slice().try_into().unwrap()
+}
+
+/// The journal of a guest that committed `items`, in order, with a hashed
+/// journal.
+pub fn chain<I: AsRef<[u8]>>(items: impl IntoIterator<Item = I>) -> Digest {
+    items
+        .into_iter()
+        .fold(Digest::ZERO, |prev, item| link(&prev, item.as_ref()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sha::{Impl, Sha256 as _};
+
+    #[test]
+    fn chain_of_links() {
+        assert_eq!(chain::<&[u8]>([]), Digest::ZERO);
+
+        let items: [&[u8]; 3] = [b"", b"abc", &[7; 100]];
+        let mut expected = Digest::ZERO;
+        for item in items {
+            let mut preimage = expected.as_bytes().to_vec();
+            preimage.extend_from_slice(item);
+            expected = *Impl::hash_bytes(&preimage);
+        }
+        assert_eq!(chain(items), expected);
+    }
+}
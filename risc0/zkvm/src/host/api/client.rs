@@ -19,9 +19,11 @@ use bytes::Bytes;
 use prost::Message;
 
 use super::{
-    malformed_err, pb, Asset, AssetRequest, Binary, ConnectionWrapper, Connector,
+    malformed_err, pb, Asset, AssetRequest, Binary, ConnectionWrapper, Connector, HeapStats,
     ParentProcessConnector, SessionInfo,
 };
+#[allow(deprecated)]
+use crate::ProverOpts;
 use crate::{
     get_version,
     host::{
@@ -29,7 +31,7 @@ use crate::{
         client::prove::get_r0vm_path,
         receipt::{SegmentReceipt, SuccinctReceipt},
     },
-    ExecutorEnv, Journal, ProverOpts, Receipt,
+    CheckPolicy, EntryPoint, ExecutorEnv, Journal, Receipt,
 };
 
 /// A client implementation for interacting with a zkVM server.
@@ -68,6 +70,7 @@ impl Client {
     }
 
     /// Prove the specified [Binary].
+    #[allow(deprecated)]
     pub fn prove(
         &self,
         env: &ExecutorEnv<'_>,
@@ -136,6 +139,7 @@ impl Client {
     }
 
     /// Prove the specified segment.
+    #[allow(deprecated)]
     pub fn prove_segment(
         &self,
         opts: ProverOpts,
@@ -176,6 +180,7 @@ impl Client {
     }
 
     /// Lift a [SegmentReceipt] into a [SuccinctReceipt].
+    #[allow(deprecated)]
     pub fn lift(
         &self,
         opts: ProverOpts,
@@ -214,6 +219,7 @@ impl Client {
     }
 
     /// Recursively join two receipts into a [SuccinctReceipt].
+    #[allow(deprecated)]
     pub fn join(
         &self,
         opts: ProverOpts,
@@ -254,6 +260,7 @@ impl Client {
     }
 
     /// Convert a [SuccinctReceipt] with a poseidon hash function that uses a 254-bit field
+    #[allow(deprecated)]
     pub fn identity_p254(
         &self,
         opts: ProverOpts,
@@ -343,6 +350,32 @@ impl Client {
             segment_limit_po2: env.segment_limit_po2,
             session_limit: env.session_limit,
             trace_events: env.trace.is_empty().not().then_some(()),
+            named_inputs: env.named_inputs.iter().cloned().collect(),
+            nonce: env.nonce.map(|nonce| nonce.to_vec()),
+            fail_fast_checks: env.check_policy == CheckPolicy::FailFast,
+            stack_size: env.layout.stack_size,
+            heap_base: env.layout.heap_base,
+            entry_symbol: match &env.layout.entry {
+                Some(EntryPoint::Symbol(symbol)) => Some(symbol.clone()),
+                _ => None,
+            },
+            entry_address: match env.layout.entry {
+                Some(EntryPoint::Address(addr)) => Some(addr),
+                _ => None,
+            },
+            registers: env
+                .layout
+                .registers
+                .iter()
+                .map(|(&idx, &value)| (idx as u32, value))
+                .collect(),
+            derived_randomness: env.derived_randomness,
+            sha_accel: env.sha_accel,
+            bigint_accel: env.bigint_accel,
+            isa_audit: env.isa_audit,
+            hashed_journal: env.hashed_journal,
+            log_limits: env.posix_io.borrow().log_limits.map(Into::into),
+            output_size_hints: env.posix_io.borrow().output_size_hints.map(Into::into),
         }
     }
 
@@ -398,6 +431,9 @@ impl Client {
                                         .exit_code
                                         .ok_or(malformed_err())?
                                         .try_into()?,
+                                    heap_stats: HeapStats {
+                                        peak_bytes: session.heap_peak_bytes,
+                                    },
                                 }),
                                 None => Err(malformed_err()),
                             }
@@ -499,8 +535,7 @@ impl Client {
             .inner
             .get(name)
             .ok_or(anyhow!("Unknown I/O channel name: {name}"))?;
-        let result = slice_io.borrow_mut().handle_io(name, from_guest)?;
-        Ok(result)
+        slice_io.handle_io(name, from_guest)
     }
 
     fn on_trace(&self, env: &ExecutorEnv<'_>, event: pb::api::TraceEvent) -> Result<()> {
@@ -12,21 +12,25 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use std::{collections::BTreeMap, path::PathBuf};
+use std::{collections::BTreeMap, path::PathBuf, time::Duration};
 
-use anyhow::{anyhow, bail, Result};
+use anyhow::{anyhow, bail, ensure, Result};
+use bincode::Options;
 use prost::{Message, Name};
 use risc0_binfmt::{MemoryImage, PageTableInfo, SystemState};
 use risc0_zkp::core::digest::Digest;
 
 use super::{malformed_err, path_to_string, pb, Asset, AssetRequest, Binary, BinaryKind};
+#[allow(deprecated)]
+use crate::ProverOpts;
 use crate::{
     host::{
-        receipt::{CompositeReceipt, InnerReceipt, SegmentReceipt},
+        receipt::{annotated, CompositeReceipt, InnerReceipt, SegmentReceipt},
         recursion::SuccinctReceipt,
     },
     receipt_metadata::{Assumptions, MaybePruned, Output},
-    ExitCode, Journal, ProverOpts, Receipt, ReceiptMetadata, TraceEvent,
+    ExitCode, Journal, LogLimits, LogPolicy, OutputSizeHints, ProveOpts, Receipt, ReceiptMetadata,
+    SecurityPolicy, TraceEvent, VerifyPolicy,
 };
 
 mod ver {
@@ -38,6 +42,67 @@ mod ver {
     pub const SUCCINCT_RECEIPT: CompatVersion = CompatVersion { value: 1 };
 }
 
+/// The tag at the start of a [Receipt] encoded with [Receipt::to_bytes].
+pub(crate) const RECEIPT_BYTES_TAG: &[u8; 4] = b"R0RC";
+
+/// The version of the encoding produced by [Receipt::to_bytes], which follows
+/// [RECEIPT_BYTES_TAG].
+///
+/// This must change whenever [receipt_bincode_options] does.
+pub const RECEIPT_FORMAT_VERSION: u8 = 1;
+
+// The bincode configuration of encoded receipts. Integers, including lengths,
+// are encoded with a fixed size and in little-endian order, so the encoding
+// does not depend on the pointer width or byte order of the host. This is the
+// configuration used by `bincode::serialize` as well.
+pub(crate) fn receipt_bincode_options() -> impl Options {
+    bincode::DefaultOptions::new()
+        .with_fixint_encoding()
+        .with_little_endian()
+}
+
+impl Receipt {
+    /// Encode the receipt in a binary format that is the same on every host.
+    ///
+    /// The encoding starts with a tag and [RECEIPT_FORMAT_VERSION], followed
+    /// by the receipt encoded with bincode using fixed-size little-endian
+    /// integers, and then its [Receipt::annotations]. Decode it with
+    /// [Receipt::from_bytes].
+    pub fn to_bytes(&self) -> Result<Vec<u8>> {
+        let mut bytes = RECEIPT_BYTES_TAG.to_vec();
+        bytes.push(RECEIPT_FORMAT_VERSION);
+        receipt_bincode_options().serialize_into(&mut bytes, &annotated::Ref::from(self))?;
+        Ok(bytes)
+    }
+
+    /// Decode a receipt encoded with [Receipt::to_bytes].
+    ///
+    /// Receipts encoded with `bincode::serialize`, which have no tag or
+    /// version, are accepted as well; they have no annotations. Any length
+    /// prefix claiming more data than `bytes` holds is rejected.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self> {
+        let limit = bytes.len() as u64;
+        let Some(rest) = bytes.strip_prefix(RECEIPT_BYTES_TAG) else {
+            return Ok(receipt_bincode_options()
+                .with_limit(limit)
+                .allow_trailing_bytes()
+                .deserialize(bytes)?);
+        };
+        let (&version, body) = rest
+            .split_first()
+            .ok_or_else(|| anyhow!("Receipt bytes end before the format version"))?;
+        ensure!(
+            version == RECEIPT_FORMAT_VERSION,
+            "Unsupported receipt format version: {version}, expected {RECEIPT_FORMAT_VERSION}"
+        );
+        let receipt: annotated::Owned = receipt_bincode_options()
+            .with_limit(limit)
+            .reject_trailing_bytes()
+            .deserialize(body)?;
+        Ok(receipt.into())
+    }
+}
+
 impl TryFrom<AssetRequest> for pb::api::AssetRequest {
     type Error = anyhow::Error;
 
@@ -193,20 +258,94 @@ impl From<pb::api::GenericError> for anyhow::Error {
     }
 }
 
-impl From<pb::api::ProverOpts> for ProverOpts {
+impl From<pb::api::ProverOpts> for ProveOpts {
     fn from(opts: pb::api::ProverOpts) -> Self {
+        let security_policy = match opts.security_policy() {
+            pb::api::prover_opts::SecurityPolicy::DevAllowed => SecurityPolicy::DevAllowed,
+            pb::api::prover_opts::SecurityPolicy::Enforced => SecurityPolicy::Enforced,
+            pb::api::prover_opts::SecurityPolicy::DevForced => SecurityPolicy::DevForced,
+        };
         Self {
             hashfn: opts.hashfn,
             prove_guest_errors: opts.prove_guest_errors,
+            annotations: opts.annotations.into_iter().collect(),
+            auto_po2_downshift: opts.auto_po2_downshift,
+            segment_retries: opts.segment_retries,
+            segment_retry_backoff: Duration::from_millis(opts.segment_retry_backoff_ms),
+            security_policy,
+            verify_policy: match opts.verify_one_in {
+                None => VerifyPolicy::Always,
+                Some(0) => VerifyPolicy::Never,
+                Some(n) => VerifyPolicy::SampleOneIn(n),
+            },
+            ..Default::default()
+        }
+    }
+}
+
+#[allow(deprecated)]
+impl From<pb::api::ProverOpts> for ProverOpts {
+    fn from(opts: pb::api::ProverOpts) -> Self {
+        let (bigint_accel, isa_audit) = (opts.bigint_accel, opts.isa_audit);
+        Self {
+            bigint_accel,
+            isa_audit,
+            ..ProveOpts::from(opts).into()
         }
     }
 }
 
+#[allow(deprecated)]
 impl From<ProverOpts> for pb::api::ProverOpts {
     fn from(opts: ProverOpts) -> Self {
         Self {
             hashfn: opts.hashfn,
             prove_guest_errors: opts.prove_guest_errors,
+            annotations: opts.annotations.into_iter().collect(),
+            auto_po2_downshift: opts.auto_po2_downshift,
+            security_policy: match opts.security_policy {
+                SecurityPolicy::DevAllowed => pb::api::prover_opts::SecurityPolicy::DevAllowed,
+                SecurityPolicy::Enforced => pb::api::prover_opts::SecurityPolicy::Enforced,
+                SecurityPolicy::DevForced => pb::api::prover_opts::SecurityPolicy::DevForced,
+            }
+            .into(),
+            verify_one_in: match opts.verify_policy {
+                VerifyPolicy::Always => None,
+                VerifyPolicy::Never => Some(0),
+                VerifyPolicy::SampleOneIn(n) => Some(n),
+            },
+            isa_audit: opts.isa_audit,
+            bigint_accel: opts.bigint_accel,
+            segment_retries: opts.segment_retries,
+            segment_retry_backoff_ms: opts
+                .segment_retry_backoff
+                .as_millis()
+                .try_into()
+                .unwrap_or(u64::MAX),
+        }
+    }
+}
+
+impl From<LogLimits> for pb::api::LogLimits {
+    fn from(limits: LogLimits) -> Self {
+        Self {
+            max_messages: limits.max_messages,
+            max_total_bytes: limits.max_total_bytes,
+            on_exceed: match limits.on_exceed {
+                LogPolicy::Drop => pb::api::log_limits::Policy::Drop,
+                LogPolicy::Error => pb::api::log_limits::Policy::Error,
+            }
+            .into(),
+        }
+    }
+}
+
+impl From<OutputSizeHints> for pb::api::OutputSizeHints {
+    fn from(hints: OutputSizeHints) -> Self {
+        Self {
+            journal: hints.journal as u64,
+            stdout: hints.stdout as u64,
+            strict: hints.strict,
         }
     }
 }
@@ -326,6 +465,7 @@ impl From<Receipt> for pb::core::Receipt {
             version: Some(ver::RECEIPT),
             inner: Some(value.inner.into()),
             journal: value.journal.bytes,
+            annotations: value.annotations.into_iter().collect(),
         }
     }
 }
@@ -341,6 +481,7 @@ impl TryFrom<pb::core::Receipt> for Receipt {
         Ok(Self {
             inner: value.inner.ok_or(malformed_err())?.try_into()?,
             journal: Journal::new(value.journal),
+            annotations: value.annotations.into_iter().collect(),
         })
     }
 }
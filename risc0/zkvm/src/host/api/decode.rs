@@ -0,0 +1,330 @@
+// Copyright 2023 RISC Zero, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Decoding of bincode encoded values from untrusted sources.
+//!
+//! bincode bounds how much it reads, but not how deeply values nest, and some
+//! types nest without bound: the assumptions of a [ReceiptMetadata] hold
+//! further metadata. [DecodeLimits] wraps the bincode deserializer to count
+//! the nesting of the value being decoded, so that a hostile body is rejected
+//! with an error instead of overflowing the stack.
+//!
+//! [ReceiptMetadata]: crate::ReceiptMetadata
+
+use std::{cell::Cell, fmt};
+
+use anyhow::{anyhow, ensure, Result};
+use bincode::Options;
+use serde::de::{
+    self, DeserializeOwned, DeserializeSeed, Deserializer, EnumAccess, MapAccess, SeqAccess,
+    VariantAccess, Visitor,
+};
+
+/// Limits on the bincode encoded values accepted by [DecodeLimits::decode].
+///
+/// The API server decodes the segments and receipts sent by its clients with
+/// the default limits.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct DecodeLimits {
+    /// The largest encoding accepted, in bytes. No length prefix inside the
+    /// encoding may claim more data than it holds either, so this also bounds
+    /// what is allocated while decoding.
+    pub max_bytes: u64,
+
+    /// How deeply sequences, maps, structs, enums and options may nest.
+    pub max_depth: usize,
+}
+
+impl Default for DecodeLimits {
+    fn default() -> Self {
+        Self {
+            max_bytes: super::MAX_MESSAGE_BYTES as u64,
+            max_depth: 128,
+        }
+    }
+}
+
+impl DecodeLimits {
+    /// Decode a value encoded with `bincode::serialize` from `bytes`, which
+    /// may have been produced by an adversary.
+    ///
+    /// Returns an error, rather than allocating or recursing without bound,
+    /// if `bytes` exceeds these limits or is not a valid encoding.
+    pub fn decode<T: DeserializeOwned>(&self, bytes: &[u8]) -> Result<T> {
+        ensure!(
+            bytes.len() as u64 <= self.max_bytes,
+            "Untrusted body of {} bytes exceeds the limit of {} bytes",
+            bytes.len(),
+            self.max_bytes
+        );
+        let options = bincode::DefaultOptions::new()
+            .with_fixint_encoding()
+            .allow_trailing_bytes()
+            .with_limit(bytes.len() as u64);
+        let mut deserializer = bincode::Deserializer::from_slice(bytes, options);
+        let depth = Cell::new(self.max_depth);
+        T::deserialize(Limited::new(&mut deserializer, &depth))
+            .map_err(|err| anyhow!("Untrusted body rejected: {err}"))
+    }
+}
+
+// Wraps each part of a deserializer, and the visitors and seeds passed to it,
+// so that every nested value it produces counts against `depth`, the number
+// of levels of nesting left.
+struct Limited<'d, T> {
+    inner: T,
+    depth: &'d Cell<usize>,
+}
+
+impl<'d, T> Limited<'d, T> {
+    fn new(inner: T, depth: &'d Cell<usize>) -> Self {
+        Self { inner, depth }
+    }
+
+    fn wrap<U>(&self, inner: U) -> Limited<'d, U> {
+        Limited::new(inner, self.depth)
+    }
+}
+
+// Visit one level deeper with `visit`.
+fn nested<R, E: de::Error>(
+    depth: &Cell<usize>,
+    visit: impl FnOnce() -> Result<R, E>,
+) -> Result<R, E> {
+    let left = depth.get();
+    if left == 0 {
+        return Err(E::custom("value nests too deeply"));
+    }
+    depth.set(left - 1);
+    let result = visit();
+    depth.set(left);
+    result
+}
+
+macro_rules! forward_deserialize {
+    ($($method:ident($($arg:ident: $ty:ty),*)),* $(,)?) => {
+        $(
+            fn $method<V: Visitor<'de>>(self, $($arg: $ty,)* visitor: V) -> Result<V::Value, D::Error> {
+                let visitor = self.wrap(visitor);
+                self.inner.$method($($arg,)* visitor)
+            }
+        )*
+    };
+}
+
+impl<'de, 'd, D: Deserializer<'de>> Deserializer<'de> for Limited<'d, D> {
+    type Error = D::Error;
+
+    forward_deserialize! {
+        deserialize_any(),
+        deserialize_bool(),
+        deserialize_i8(),
+        deserialize_i16(),
+        deserialize_i32(),
+        deserialize_i64(),
+        deserialize_i128(),
+        deserialize_u8(),
+        deserialize_u16(),
+        deserialize_u32(),
+        deserialize_u64(),
+        deserialize_u128(),
+        deserialize_f32(),
+        deserialize_f64(),
+        deserialize_char(),
+        deserialize_str(),
+        deserialize_string(),
+        deserialize_bytes(),
+        deserialize_byte_buf(),
+        deserialize_option(),
+        deserialize_unit(),
+        deserialize_unit_struct(name: &'static str),
+        deserialize_newtype_struct(name: &'static str),
+        deserialize_seq(),
+        deserialize_tuple(len: usize),
+        deserialize_tuple_struct(name: &'static str, len: usize),
+        deserialize_map(),
+        deserialize_struct(name: &'static str, fields: &'static [&'static str]),
+        deserialize_enum(name: &'static str, variants: &'static [&'static str]),
+        deserialize_identifier(),
+        deserialize_ignored_any(),
+    }
+
+    fn is_human_readable(&self) -> bool {
+        self.inner.is_human_readable()
+    }
+}
+
+macro_rules! forward_visit {
+    ($($method:ident($ty:ty)),* $(,)?) => {
+        $(
+            fn $method<E: de::Error>(self, v: $ty) -> Result<Self::Value, E> {
+                self.inner.$method(v)
+            }
+        )*
+    };
+}
+
+impl<'de, 'd, V: Visitor<'de>> Visitor<'de> for Limited<'d, V> {
+    type Value = V::Value;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        self.inner.expecting(formatter)
+    }
+
+    forward_visit! {
+        visit_bool(bool),
+        visit_i8(i8),
+        visit_i16(i16),
+        visit_i32(i32),
+        visit_i64(i64),
+        visit_i128(i128),
+        visit_u8(u8),
+        visit_u16(u16),
+        visit_u32(u32),
+        visit_u64(u64),
+        visit_u128(u128),
+        visit_f32(f32),
+        visit_f64(f64),
+        visit_char(char),
+        visit_str(&str),
+        visit_borrowed_str(&'de str),
+        visit_string(String),
+        visit_bytes(&[u8]),
+        visit_borrowed_bytes(&'de [u8]),
+        visit_byte_buf(Vec<u8>),
+    }
+
+    fn visit_none<E: de::Error>(self) -> Result<Self::Value, E> {
+        self.inner.visit_none()
+    }
+
+    fn visit_unit<E: de::Error>(self) -> Result<Self::Value, E> {
+        self.inner.visit_unit()
+    }
+
+    fn visit_some<D: Deserializer<'de>>(self, deserializer: D) -> Result<Self::Value, D::Error> {
+        let deserializer = self.wrap(deserializer);
+        nested(self.depth, || self.inner.visit_some(deserializer))
+    }
+
+    fn visit_newtype_struct<D: Deserializer<'de>>(
+        self,
+        deserializer: D,
+    ) -> Result<Self::Value, D::Error> {
+        let deserializer = self.wrap(deserializer);
+        nested(self.depth, || self.inner.visit_newtype_struct(deserializer))
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, seq: A) -> Result<Self::Value, A::Error> {
+        let seq = self.wrap(seq);
+        nested(self.depth, || self.inner.visit_seq(seq))
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, map: A) -> Result<Self::Value, A::Error> {
+        let map = self.wrap(map);
+        nested(self.depth, || self.inner.visit_map(map))
+    }
+
+    fn visit_enum<A: EnumAccess<'de>>(self, data: A) -> Result<Self::Value, A::Error> {
+        let data = self.wrap(data);
+        nested(self.depth, || self.inner.visit_enum(data))
+    }
+}
+
+impl<'de, 'd, T: DeserializeSeed<'de>> DeserializeSeed<'de> for Limited<'d, T> {
+    type Value = T::Value;
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<Self::Value, D::Error> {
+        let deserializer = self.wrap(deserializer);
+        self.inner.deserialize(deserializer)
+    }
+}
+
+impl<'de, 'd, A: SeqAccess<'de>> SeqAccess<'de> for Limited<'d, A> {
+    type Error = A::Error;
+
+    fn next_element_seed<T: DeserializeSeed<'de>>(
+        &mut self,
+        seed: T,
+    ) -> Result<Option<T::Value>, A::Error> {
+        let seed = self.wrap(seed);
+        self.inner.next_element_seed(seed)
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        self.inner.size_hint()
+    }
+}
+
+impl<'de, 'd, A: MapAccess<'de>> MapAccess<'de> for Limited<'d, A> {
+    type Error = A::Error;
+
+    fn next_key_seed<K: DeserializeSeed<'de>>(
+        &mut self,
+        seed: K,
+    ) -> Result<Option<K::Value>, A::Error> {
+        let seed = self.wrap(seed);
+        self.inner.next_key_seed(seed)
+    }
+
+    fn next_value_seed<V: DeserializeSeed<'de>>(&mut self, seed: V) -> Result<V::Value, A::Error> {
+        let seed = self.wrap(seed);
+        self.inner.next_value_seed(seed)
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        self.inner.size_hint()
+    }
+}
+
+impl<'de, 'd, A: EnumAccess<'de>> EnumAccess<'de> for Limited<'d, A> {
+    type Error = A::Error;
+    type Variant = Limited<'d, A::Variant>;
+
+    fn variant_seed<V: DeserializeSeed<'de>>(
+        self,
+        seed: V,
+    ) -> Result<(V::Value, Self::Variant), A::Error> {
+        let seed = self.wrap(seed);
+        let (value, variant) = self.inner.variant_seed(seed)?;
+        Ok((value, Limited::new(variant, self.depth)))
+    }
+}
+
+impl<'de, 'd, A: VariantAccess<'de>> VariantAccess<'de> for Limited<'d, A> {
+    type Error = A::Error;
+
+    fn unit_variant(self) -> Result<(), A::Error> {
+        self.inner.unit_variant()
+    }
+
+    fn newtype_variant_seed<T: DeserializeSeed<'de>>(self, seed: T) -> Result<T::Value, A::Error> {
+        let seed = self.wrap(seed);
+        self.inner.newtype_variant_seed(seed)
+    }
+
+    fn tuple_variant<V: Visitor<'de>>(self, len: usize, visitor: V) -> Result<V::Value, A::Error> {
+        let visitor = self.wrap(visitor);
+        self.inner.tuple_variant(len, visitor)
+    }
+
+    fn struct_variant<V: Visitor<'de>>(
+        self,
+        fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, A::Error> {
+        let visitor = self.wrap(visitor);
+        self.inner.struct_variant(fields, visitor)
+    }
+}
@@ -14,13 +14,17 @@
 
 pub(crate) mod client;
 pub(crate) mod convert;
+pub(crate) mod decode;
 #[cfg(feature = "prove")]
 pub(crate) mod server;
+// The tests also cover the deprecated ProverOpts facade.
 #[cfg(test)]
 #[cfg(feature = "prove")]
+#[allow(deprecated)]
 mod tests;
 
 use std::{
+    fmt,
     io::{Read, Write},
     net::{TcpListener, TcpStream},
     path::{Path, PathBuf},
@@ -34,9 +38,10 @@ use std::{
     time::Duration,
 };
 
-use anyhow::{anyhow, Context, Result};
+use anyhow::{anyhow, bail, Context, Result};
 use bytes::{Buf, BufMut, Bytes};
 use prost::Message;
+use serde::{Deserialize, Serialize};
 
 use crate::{ExitCode, Journal};
 
@@ -54,6 +59,12 @@ mod pb {
 
 const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
 
+/// The largest message that will be accepted from the other end of a connection.
+///
+/// The length prefix of a message is checked against this limit before any
+/// buffer is allocated for it.
+const MAX_MESSAGE_BYTES: usize = 1 << 30;
+
 trait RootMessage: Message {}
 
 pub trait Connection {
@@ -98,10 +109,17 @@ impl ConnectionWrapper {
     }
 
     fn recv<T: Default + RootMessage>(&mut self) -> Result<T> {
+        self.recv_limited(MAX_MESSAGE_BYTES)
+    }
+
+    fn recv_limited<T: Default + RootMessage>(&mut self, max_bytes: usize) -> Result<T> {
         let mut stream = self.inner.stream();
         self.buf.resize(4, 0);
         stream.read_exact(&mut self.buf)?;
         let len = self.buf.as_slice().get_u32_le() as usize;
+        if len > max_bytes {
+            bail!("Message of {len} bytes exceeds the limit of {max_bytes} bytes");
+        }
         self.buf.resize(len, 0);
         stream.read_exact(&mut self.buf)?;
         Ok(T::decode(self.buf.as_slice())?)
@@ -257,6 +275,13 @@ fn malformed_err() -> anyhow::Error {
     anyhow!("Malformed error")
 }
 
+/// Deserialize a bincode encoded value received from a peer, within the
+/// default [DecodeLimits](decode::DecodeLimits).
+#[cfg(feature = "prove")]
+pub(crate) fn bincode_deserialize<T: serde::de::DeserializeOwned>(bytes: &[u8]) -> Result<T> {
+    decode::DecodeLimits::default().decode(bytes)
+}
+
 impl pb::api::Asset {
     fn as_bytes(&self) -> Result<Bytes> {
         let bytes = match self.kind.as_ref().ok_or(malformed_err())? {
@@ -309,8 +334,49 @@ pub struct SessionInfo {
 
     /// The [ExitCode] of the session.
     pub exit_code: ExitCode,
+
+    /// How much of the heap the guest used.
+    pub heap_stats: HeapStats,
+}
+
+/// Provides information about the heap usage of a guest.
+///
+/// The heap starts right after the pages of the program and grows up towards
+/// `SYSTEM` memory. Since the guest allocator never frees memory, the peak is
+/// measured as the distance from the start of the heap to the highest address
+/// the guest wrote within it. Memory that is allocated but never written is
+/// not counted.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HeapStats {
+    /// The number of bytes of heap in use at the peak of execution.
+    pub peak_bytes: u64,
 }
 
+/// A check made by the guest with `env::check` that failed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FailedCheck {
+    /// The code the guest passed to `env::check`.
+    pub code: u32,
+
+    /// The program counter of the syscall that reported the failure.
+    pub pc: u32,
+
+    /// The session cycle at which the failure was reported.
+    pub cycle: u64,
+}
+
+impl fmt::Display for FailedCheck {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "guest check {} failed at pc 0x{:08x}, cycle {}",
+            self.code, self.pc, self.cycle
+        )
+    }
+}
+
+impl std::error::Error for FailedCheck {}
+
 /// Provides information about a segment of execution.
 #[derive(Clone)]
 pub struct SegmentInfo {
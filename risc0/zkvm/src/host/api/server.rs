@@ -21,19 +21,21 @@ use std::{
 use anyhow::{anyhow, bail, Result};
 use bytes::Bytes;
 use prost::Message;
-use risc0_binfmt::{MemoryImage, Program};
-use risc0_zkvm_platform::{memory::GUEST_MAX_MEM, PAGE_SIZE};
+use risc0_binfmt::MemoryImage;
 use serde::{Deserialize, Serialize};
 
-use super::{malformed_err, path_to_string, pb, ConnectionWrapper, Connector, TcpConnector};
+use super::{
+    bincode_deserialize, malformed_err, path_to_string, pb, ConnectionWrapper, Connector,
+    TcpConnector,
+};
 use crate::{
-    get_prover_server, get_version,
+    get_prover_server_for, get_version,
     host::{
-        client::{env::TraceCallback, slice_io::SliceIo},
+        client::{env::TraceCallback, provenance, slice_io::SliceIo},
         recursion::SuccinctReceipt,
     },
-    ExecutorEnv, ExecutorImpl, ProverOpts, Segment, SegmentReceipt, SegmentRef, TraceEvent,
-    VerifierContext,
+    CheckPolicy, ExecutorEnv, ExecutorImpl, GuestLayout, LogPolicy, ProveOpts, Segment,
+    SegmentReceipt, SegmentRef, TraceEvent, VerifierContext,
 };
 
 /// A server implementation for handling requests by clients of the zkVM.
@@ -42,7 +44,7 @@ pub struct Server {
 }
 
 #[derive(Clone, Serialize, Deserialize)]
-struct EmptySegmentRef;
+pub(crate) struct EmptySegmentRef;
 
 #[typetag::serde]
 impl SegmentRef for EmptySegmentRef {
@@ -52,15 +54,12 @@ impl SegmentRef for EmptySegmentRef {
 }
 
 impl pb::api::Binary {
-    fn as_image(&self) -> Result<MemoryImage> {
+    fn as_image(&self, layout: &GuestLayout) -> Result<MemoryImage> {
         let bytes = self.asset.as_ref().ok_or(malformed_err())?.as_bytes()?;
         let image = match self.kind() {
             pb::api::binary::Kind::Unspecified => bail!(malformed_err()),
             pb::api::binary::Kind::Image => pb::core::MemoryImage::decode(bytes)?.try_into()?,
-            pb::api::binary::Kind::Elf => {
-                let program = Program::load_elf(&bytes, GUEST_MAX_MEM as u32)?;
-                MemoryImage::new(&program, PAGE_SIZE as u32)?
-            }
+            pb::api::binary::Kind::Elf => layout.load_elf(&bytes)?,
         };
         Ok(image)
     }
@@ -290,7 +289,7 @@ impl Server {
             let env = build_env(&conn, &env_request)?;
 
             let binary = env_request.binary.ok_or(malformed_err())?;
-            let image = binary.as_image()?;
+            let image = binary.as_image(&env.layout)?;
             let segments_out = request.segments_out.ok_or(malformed_err())?;
 
             let mut exec = ExecutorImpl::new(env, image)?;
@@ -336,6 +335,7 @@ impl Server {
                                 segments: session.segments.len().try_into()?,
                                 journal: session.journal.unwrap_or_default().bytes,
                                 exit_code: Some(session.exit_code.into()),
+                                heap_peak_bytes: session.heap_stats.peak_bytes,
                             }),
                         },
                     )),
@@ -359,15 +359,19 @@ impl Server {
             request: pb::api::ProveRequest,
         ) -> Result<pb::api::ServerReply> {
             let env_request = request.env.ok_or(malformed_err())?;
-            let env = build_env(&conn, &env_request)?;
+            let mut env = build_env(&conn, &env_request)?;
 
             let binary = env_request.binary.ok_or(malformed_err())?;
-            let image = binary.as_image()?;
+            let image = binary.as_image(&env.layout)?;
 
-            let opts: ProverOpts = request.opts.ok_or(malformed_err())?.into();
-            let prover = get_prover_server(&opts)?;
-            let ctx = VerifierContext::default();
-            let receipt = prover.prove(env, &ctx, image)?;
+            let opts = request.opts.ok_or(malformed_err())?;
+            env.isa_audit |= opts.isa_audit;
+            env.bigint_accel |= opts.bigint_accel;
+            let opts = ProveOpts::from(opts);
+            let prover = get_prover_server_for(&opts)?;
+            let ctx = VerifierContext::default().with_security_policy(opts.security_policy);
+            let mut receipt = prover.prove(env, &ctx, image)?;
+            provenance::annotate(&mut receipt, &opts.annotations);
 
             let receipt_pb: pb::core::Receipt = receipt.into();
             let receipt_bytes = receipt_pb.encode_to_vec();
@@ -404,12 +408,12 @@ impl Server {
         request: pb::api::ProveSegmentRequest,
     ) -> Result<()> {
         fn inner(request: pb::api::ProveSegmentRequest) -> Result<pb::api::ProveSegmentReply> {
-            let opts: ProverOpts = request.opts.ok_or(malformed_err())?.into();
+            let opts: ProveOpts = request.opts.ok_or(malformed_err())?.into();
             let segment_bytes = request.segment.ok_or(malformed_err())?.as_bytes()?;
-            let segment: Segment = bincode::deserialize(&segment_bytes)?;
+            let segment: Segment = bincode_deserialize(&segment_bytes)?;
 
-            let prover = get_prover_server(&opts)?;
-            let ctx = VerifierContext::default();
+            let prover = get_prover_server_for(&opts)?;
+            let ctx = VerifierContext::default().with_security_policy(opts.security_policy);
             let receipt = prover.prove_segment(&ctx, &segment)?;
 
             let receipt_pb: pb::core::SegmentReceipt = receipt.into();
@@ -443,11 +447,11 @@ impl Server {
 
     fn on_lift(&self, mut conn: ConnectionWrapper, request: pb::api::LiftRequest) -> Result<()> {
         fn inner(request: pb::api::LiftRequest) -> Result<pb::api::LiftReply> {
-            let opts: ProverOpts = request.opts.ok_or(malformed_err())?.into();
+            let opts: ProveOpts = request.opts.ok_or(malformed_err())?.into();
             let receipt_bytes = request.receipt.ok_or(malformed_err())?.as_bytes()?;
-            let segment_receipt: SegmentReceipt = bincode::deserialize(&receipt_bytes)?;
+            let segment_receipt: SegmentReceipt = bincode_deserialize(&receipt_bytes)?;
 
-            let prover = get_prover_server(&opts)?;
+            let prover = get_prover_server_for(&opts)?;
             let receipt = prover.lift(&segment_receipt)?;
 
             let succinct_receipt_pb: pb::core::SuccinctReceipt = receipt.into();
@@ -477,14 +481,14 @@ impl Server {
 
     fn on_join(&self, mut conn: ConnectionWrapper, request: pb::api::JoinRequest) -> Result<()> {
         fn inner(request: pb::api::JoinRequest) -> Result<pb::api::JoinReply> {
-            let opts: ProverOpts = request.opts.ok_or(malformed_err())?.into();
+            let opts: ProveOpts = request.opts.ok_or(malformed_err())?.into();
             let left_receipt_bytes = request.left_receipt.ok_or(malformed_err())?.as_bytes()?;
-            let left_succinct_receipt: SuccinctReceipt = bincode::deserialize(&left_receipt_bytes)?;
+            let left_succinct_receipt: SuccinctReceipt = bincode_deserialize(&left_receipt_bytes)?;
             let right_receipt_bytes = request.right_receipt.ok_or(malformed_err())?.as_bytes()?;
             let right_succinct_receipt: SuccinctReceipt =
-                bincode::deserialize(&right_receipt_bytes)?;
+                bincode_deserialize(&right_receipt_bytes)?;
 
-            let prover = get_prover_server(&opts)?;
+            let prover = get_prover_server_for(&opts)?;
             let receipt = prover.join(&left_succinct_receipt, &right_succinct_receipt)?;
 
             let succinct_receipt_pb: pb::core::SuccinctReceipt = receipt.into();
@@ -518,11 +522,11 @@ impl Server {
         request: pb::api::IdentityP254Request,
     ) -> Result<()> {
         fn inner(request: pb::api::IdentityP254Request) -> Result<pb::api::IdentityP254Reply> {
-            let opts: ProverOpts = request.opts.ok_or(malformed_err())?.into();
+            let opts: ProveOpts = request.opts.ok_or(malformed_err())?.into();
             let receipt_bytes = request.receipt.ok_or(malformed_err())?.as_bytes()?;
-            let succinct_receipt: SuccinctReceipt = bincode::deserialize(&receipt_bytes)?;
+            let succinct_receipt: SuccinctReceipt = bincode_deserialize(&receipt_bytes)?;
 
-            let prover = get_prover_server(&opts)?;
+            let prover = get_prover_server_for(&opts)?;
             let receipt = prover.identity_p254(&succinct_receipt)?;
 
             let succinct_receipt_pb: pb::core::SuccinctReceipt = receipt.into();
@@ -565,25 +569,84 @@ fn build_env<'a>(
     for fd in request.read_fds.iter() {
         let proxy = PosixIoProxy::new(*fd, conn.try_clone()?);
         let reader = BufReader::new(proxy);
-        env_builder.read_fd(*fd, reader);
+        if request.write_fds.contains(fd) {
+            let proxy = PosixIoProxy::new(*fd, conn.try_clone()?);
+            env_builder.duplex_fd(*fd, reader, proxy);
+        } else {
+            env_builder.read_fd(*fd, reader);
+        }
     }
     for fd in request.write_fds.iter() {
-        let proxy = PosixIoProxy::new(*fd, conn.try_clone()?);
-        env_builder.write_fd(*fd, proxy);
+        if !request.read_fds.contains(fd) {
+            let proxy = PosixIoProxy::new(*fd, conn.try_clone()?);
+            env_builder.write_fd(*fd, proxy);
+        }
     }
     let proxy = SliceIoProxy::new(conn.try_clone()?);
     for name in request.slice_ios.iter() {
+        // The client's environment was built, so any override of a built-in
+        // was acknowledged there.
+        env_builder.override_builtin(name);
         env_builder.slice_io(&name, proxy.try_clone()?);
     }
     if let Some(segment_limit_po2) = request.segment_limit_po2 {
         env_builder.segment_limit_po2(segment_limit_po2);
     }
     env_builder.session_limit(request.session_limit);
+    if let Some(nonce) = &request.nonce {
+        env_builder.nonce(nonce.as_slice().try_into()?);
+    }
+    if request.fail_fast_checks {
+        env_builder.check_policy(CheckPolicy::FailFast);
+    }
+    if let Some(stack_size) = request.stack_size {
+        env_builder.stack_size(stack_size);
+    }
+    if let Some(heap_base) = request.heap_base {
+        env_builder.heap_base(heap_base);
+    }
+    if let Some(symbol) = &request.entry_symbol {
+        env_builder.entry(symbol.as_str());
+    }
+    if let Some(addr) = request.entry_address {
+        env_builder.entry(addr);
+    }
+    let registers: Vec<_> = request
+        .registers
+        .iter()
+        .map(|(&idx, &value)| (idx as usize, value))
+        .collect();
+    env_builder.initial_registers(&registers);
+    env_builder.derived_randomness(request.derived_randomness);
+    env_builder.sha_accel(request.sha_accel);
+    env_builder.bigint_accel(request.bigint_accel);
+    env_builder.isa_audit(request.isa_audit);
+    env_builder.hashed_journal(request.hashed_journal);
+    if let Some(limits) = &request.log_limits {
+        let on_exceed = match limits.on_exceed() {
+            pb::api::log_limits::Policy::Drop => LogPolicy::Drop,
+            pb::api::log_limits::Policy::Error => LogPolicy::Error,
+        };
+        env_builder.log_limits(limits.max_messages, limits.max_total_bytes, on_exceed);
+    }
+    if let Some(hints) = &request.output_size_hints {
+        env_builder.output_size_hints(
+            hints.journal.try_into()?,
+            hints.stdout.try_into()?,
+            hints.strict,
+        );
+    }
     if let Some(_) = request.trace_events {
         let proxy = TraceProxy::new(conn.try_clone()?);
         env_builder.trace_callback(proxy);
     }
-    env_builder.build()
+    let mut env = env_builder.build()?;
+    env.named_inputs = request
+        .named_inputs
+        .iter()
+        .map(|(name, fd)| (name.clone(), *fd))
+        .collect();
+    Ok(env)
 }
 
 trait IoOtherError<T> {
@@ -13,13 +13,15 @@
 // limitations under the License.
 
 use std::{
-    net::{SocketAddr, TcpListener},
+    io::Write,
+    net::{SocketAddr, TcpListener, TcpStream},
     path::PathBuf,
     thread,
+    time::Duration,
 };
 
 use anyhow::Result;
-use risc0_binfmt::{MemoryImage, Program};
+use risc0_binfmt::{MemoryImage, Program, SystemState};
 use risc0_zkvm_methods::{
     multi_test::MultiTestSpec, MULTI_TEST_ELF, MULTI_TEST_ID, MULTI_TEST_PATH,
 };
@@ -27,10 +29,16 @@ use risc0_zkvm_platform::{memory::GUEST_MAX_MEM, PAGE_SIZE};
 use tempfile::{tempdir, TempDir};
 use test_log::test;
 
-use super::{Asset, AssetRequest, Binary, ConnectionWrapper, Connector, TcpConnection};
+use super::{
+    bincode_deserialize, pb, Asset, AssetRequest, Binary, ConnectionWrapper, Connector,
+    TcpConnection,
+};
 use crate::{
-    recursion::SuccinctReceipt, ApiClient, ApiServer, ExecutorEnv, InnerReceipt, ProverOpts,
-    Receipt, SegmentReceipt, SessionInfo, VerifierContext,
+    receipt_metadata::{Assumptions, MaybePruned},
+    recursion::SuccinctReceipt,
+    sha::Digest,
+    ApiClient, ApiServer, DecodeLimits, ExecutorEnv, ExitCode, InnerReceipt, Output, ProverOpts,
+    Receipt, ReceiptMetadata, SegmentReceipt, SessionInfo, VerifierContext, RECEIPT_FORMAT_VERSION,
 };
 
 struct TestClientConnector {
@@ -173,6 +181,34 @@ fn prove_elf() {
     receipt.verify(MULTI_TEST_ID).unwrap();
 }
 
+#[test]
+fn prove_isa_audit() {
+    let opts = ProverOpts::default().with_isa_audit(true);
+    let opts_pb: pb::api::ProverOpts = opts.clone().into();
+    assert!(ProverOpts::from(opts_pb).isa_audit);
+    let opts_pb: pb::api::ProverOpts = ProverOpts::default().with_bigint_accel().into();
+    assert!(ProverOpts::from(opts_pb).bigint_accel);
+    let opts_pb: pb::api::ProverOpts = ProverOpts::default()
+        .with_segment_retries(2, Duration::from_millis(10))
+        .into();
+    let retry_opts = ProverOpts::from(opts_pb);
+    assert_eq!(retry_opts.segment_retries, 2);
+    assert_eq!(retry_opts.segment_retry_backoff, Duration::from_millis(10));
+
+    // i32::MIN / -1 runs under audit on the server.
+    let env = ExecutorEnv::builder()
+        .write(&MultiTestSpec::DivRem {
+            a: i32::MIN as u32,
+            b: u32::MAX,
+        })
+        .unwrap()
+        .build()
+        .unwrap();
+    let binary = Binary::new_elf_path(MULTI_TEST_PATH);
+    let receipt = TestClient::new().prove(env, opts, binary);
+    receipt.verify(MULTI_TEST_ID).unwrap();
+}
+
 #[test]
 fn prove_segment_elf() {
     let env = ExecutorEnv::builder()
@@ -247,3 +283,231 @@ fn guest_error_forwarding() {
     let binary = Binary::new_elf_path(MULTI_TEST_PATH);
     TestClient::new().execute(env, binary);
 }
+
+#[test]
+fn recv_oversized_message() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let mut stream = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+    let (server_stream, _) = listener.accept().unwrap();
+
+    // A forged length prefix must be rejected before anything is allocated for it.
+    stream.write_all(&u32::MAX.to_le_bytes()).unwrap();
+    let mut conn = ConnectionWrapper::new(Box::new(TcpConnection::new(server_stream)));
+    let err = conn.recv::<pb::api::ServerRequest>().unwrap_err();
+    assert!(err.to_string().contains("exceeds the limit"));
+}
+
+#[test]
+fn bincode_forged_length() {
+    // A `Vec<u8>` claiming u64::MAX elements, followed by a single byte.
+    let mut bytes = u64::MAX.to_le_bytes().to_vec();
+    bytes.push(0);
+    assert!(bincode_deserialize::<Vec<u8>>(&bytes).is_err());
+
+    let value = vec![1u8, 2, 3];
+    let bytes = bincode::serialize(&value).unwrap();
+    assert_eq!(bincode_deserialize::<Vec<u8>>(&bytes).unwrap(), value);
+}
+
+// Metadata whose assumptions nest `depth` levels of further metadata.
+fn nested_metadata(depth: usize) -> ReceiptMetadata {
+    let mut metadata = fixture_receipt().get_metadata().unwrap();
+    for _ in 0..depth {
+        let assumption = MaybePruned::Value(metadata.clone());
+        metadata.output = MaybePruned::Value(Some(Output {
+            journal: MaybePruned::Pruned(Digest::ZERO),
+            assumptions: MaybePruned::Value(Assumptions(vec![assumption])),
+        }));
+    }
+    metadata
+}
+
+#[test]
+fn decode_limits() {
+    let limits = DecodeLimits::default();
+
+    let metadata = nested_metadata(2);
+    let bytes = bincode::serialize(&metadata).unwrap();
+    assert_eq!(limits.decode::<ReceiptMetadata>(&bytes).unwrap(), metadata);
+
+    // Deeply nested values are rejected instead of overflowing the stack.
+    let bytes = bincode::serialize(&nested_metadata(100)).unwrap();
+    let err = limits.decode::<ReceiptMetadata>(&bytes).err().unwrap();
+    assert!(err.to_string().contains("nests too deeply"));
+    let deeper = DecodeLimits {
+        max_depth: 10_000,
+        ..limits
+    };
+    assert!(deeper.decode::<ReceiptMetadata>(&bytes).is_ok());
+
+    let small = DecodeLimits {
+        max_bytes: bytes.len() as u64 - 1,
+        ..limits
+    };
+    let err = small.decode::<ReceiptMetadata>(&bytes).err().unwrap();
+    assert!(err.to_string().contains("exceeds the limit"));
+
+    // Truncated bodies are rejected rather than read past the end.
+    let bytes = bincode::serialize(&fixture_receipt()).unwrap();
+    for len in 0..bytes.len() {
+        assert!(limits.decode::<Receipt>(&bytes[..len]).is_err());
+    }
+    // Every length prefix in these claims far more data than follows.
+    for byte in [0x7f, 0xff] {
+        let body = vec![byte; 256];
+        assert!(limits.decode::<SegmentReceipt>(&body).is_err());
+        assert!(limits.decode::<SuccinctReceipt>(&body).is_err());
+    }
+}
+
+#[test]
+fn prover_opts_serde_defaults() {
+    // Options serialized before any field was added to ProverOpts.
+    let opts: ProverOpts =
+        serde_json::from_str(r#"{"hashfn":"sha-256","prove_guest_errors":true}"#).unwrap();
+    assert_eq!(opts.hashfn, "sha-256");
+    assert!(opts.prove_guest_errors);
+    let defaults = ProverOpts::default();
+    assert_eq!(opts.annotations, defaults.annotations);
+    assert_eq!(opts.auto_po2_downshift, defaults.auto_po2_downshift);
+    assert_eq!(opts.security_policy, defaults.security_policy);
+    assert_eq!(opts.verify_policy, defaults.verify_policy);
+    assert_eq!(opts.segment_retries, defaults.segment_retries);
+}
+
+// The receipt encoded in testdata/receipt_v1.bin and testdata/receipt_fake.cbor,
+// and without its annotations in testdata/receipt_legacy.bin.
+fn fixture_receipt() -> Receipt {
+    let journal = b"fixture journal".to_vec();
+    let metadata = ReceiptMetadata {
+        pre: MaybePruned::Value(SystemState {
+            pc: 0x0020_0800,
+            merkle_root: Digest::new([1; 8]),
+        }),
+        post: MaybePruned::Value(SystemState {
+            pc: 0x0020_0900,
+            merkle_root: Digest::new([2; 8]),
+        }),
+        exit_code: ExitCode::Halted(0),
+        input: Digest::ZERO,
+        output: MaybePruned::Value(Some(Output {
+            journal: MaybePruned::Value(journal.clone()),
+            assumptions: MaybePruned::Pruned(Digest::new([3; 8])),
+        })),
+    };
+    let mut receipt = Receipt::new(InnerReceipt::Fake { metadata }, journal);
+    receipt
+        .annotations
+        .insert("guest".to_string(), "fixture".to_string());
+    receipt
+}
+
+#[test]
+fn receipt_bytes_golden() {
+    let receipt = fixture_receipt();
+
+    // The encoding must be byte for byte the same on every host.
+    let golden = include_bytes!("testdata/receipt_v1.bin");
+    assert_eq!(receipt.to_bytes().unwrap(), golden);
+    assert_eq!(Receipt::from_bytes(golden).unwrap(), receipt);
+
+    // Receipts encoded with `bincode::serialize` by releases before
+    // annotations, which still decode, without them. The serde encoding of a
+    // receipt leaves the annotations out, so it is still the same.
+    let legacy = include_bytes!("testdata/receipt_legacy.bin");
+    let mut unannotated = receipt.clone();
+    unannotated.annotations.clear();
+    assert_eq!(Receipt::from_bytes(legacy).unwrap(), unannotated);
+    assert_eq!(
+        bincode::deserialize::<Receipt>(legacy).unwrap(),
+        unannotated
+    );
+    assert_eq!(bincode::serialize(&receipt).unwrap(), legacy);
+
+    let mut bytes = golden.to_vec();
+    bytes[4] = RECEIPT_FORMAT_VERSION + 1;
+    let err = Receipt::from_bytes(&bytes).err().unwrap();
+    assert!(err
+        .to_string()
+        .contains("Unsupported receipt format version"));
+
+    // Truncated receipts are rejected rather than read past the end.
+    assert!(Receipt::from_bytes(&golden[..golden.len() - 1]).is_err());
+}
+
+// The receipt encoded in testdata/receipt_composite.cbor. Its seals are not
+// valid.
+#[cfg(feature = "cbor")]
+fn fixture_composite_receipt() -> Receipt {
+    let segments = (0..2)
+        .map(|index| SegmentReceipt {
+            seal: (0..16).map(|x| x * 0x0101_0101 + index).collect(),
+            index,
+            hashfn: "sha-256".to_string(),
+        })
+        .collect();
+    let inner = crate::CompositeReceipt {
+        segments,
+        assumptions: vec![],
+        journal_digest: Some(Digest::new([4; 8])),
+    };
+    Receipt::new(InnerReceipt::Composite(inner), b"fixture journal".to_vec())
+}
+
+#[cfg(feature = "cbor")]
+#[test]
+fn receipt_cbor_golden() {
+    use ciborium::Value;
+
+    for (golden, receipt) in [
+        (
+            include_bytes!("testdata/receipt_fake.cbor").as_slice(),
+            fixture_receipt(),
+        ),
+        (
+            include_bytes!("testdata/receipt_composite.cbor").as_slice(),
+            fixture_composite_receipt(),
+        ),
+    ] {
+        assert_eq!(Receipt::from_cbor(golden).unwrap(), receipt);
+        let bytes = receipt.to_cbor().unwrap();
+        assert_eq!(Receipt::from_cbor(&bytes).unwrap(), receipt);
+
+        // Fields added by later versions are ignored, at any depth.
+        let mut value: Value = ciborium::from_reader(golden).unwrap();
+        let Value::Tag(_, envelope) = &mut value else {
+            panic!("untagged receipt");
+        };
+        let Value::Map(envelope) = envelope.as_mut() else {
+            panic!("envelope is not a map");
+        };
+        for (key, field) in envelope.iter_mut() {
+            if let (Value::Text(key), Value::Map(receipt)) = (key, field) {
+                if key == "receipt" {
+                    receipt.push(("future".into(), Value::Bool(true)));
+                }
+            }
+        }
+        envelope.push(("future".into(), Value::Integer(1.into())));
+        let mut bytes = Vec::new();
+        ciborium::into_writer(&value, &mut bytes).unwrap();
+        assert_eq!(Receipt::from_cbor(&bytes).unwrap(), receipt);
+    }
+
+    let golden = include_bytes!("testdata/receipt_composite.cbor");
+    let header = golden
+        .windows(b"sha-256".len())
+        .position(|x| x == b"sha-256")
+        .unwrap();
+    let mut bytes = golden.to_vec();
+    bytes[header..header + 7].copy_from_slice(b"sha-512");
+    let err = Receipt::from_cbor(&bytes).err().unwrap();
+    assert!(err.to_string().contains("header does not match"));
+
+    // The version follows the tag (3 bytes), the map header and the key.
+    let mut bytes = golden.to_vec();
+    assert_eq!(&bytes[4..12], b"\x67version");
+    bytes[12] = 2;
+    let err = Receipt::from_cbor(&bytes).err().unwrap();
+    assert!(err.to_string().contains("Unsupported receipt CBOR version"));
+}
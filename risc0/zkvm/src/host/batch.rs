@@ -0,0 +1,125 @@
+// Copyright 2023 RISC Zero, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Verification of many receipts across a pool of threads.
+
+use std::{
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        mpsc,
+    },
+    thread,
+};
+
+use risc0_zkp::verify::VerificationError;
+
+use crate::{sha::Digest, Receipt, VerifierContext};
+
+/// How [verify_batch] reacts to a receipt that fails to verify.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BatchMode {
+    /// Stop handing out receipts as soon as one fails. Receipts already being
+    /// verified by other threads still complete.
+    FailFast,
+
+    /// Verify every receipt and report all failures.
+    CollectAll,
+}
+
+/// The outcome of [verify_batch].
+#[derive(Debug)]
+pub struct BatchResult {
+    /// The result for each receipt, in the order they were given. `None` for
+    /// receipts that were skipped because [BatchMode::FailFast] stopped the
+    /// batch early.
+    pub results: Vec<Option<Result<(), VerificationError>>>,
+}
+
+impl BatchResult {
+    /// Returns `true` if every receipt was verified successfully.
+    pub fn is_ok(&self) -> bool {
+        self.results.iter().all(|x| matches!(x, Some(Ok(()))))
+    }
+
+    /// Iterate over the index and error of every receipt that failed to
+    /// verify.
+    pub fn errors(&self) -> impl Iterator<Item = (usize, &VerificationError)> {
+        self.results
+            .iter()
+            .enumerate()
+            .filter_map(|(idx, x)| match x {
+                Some(Err(err)) => Some((idx, err)),
+                _ => None,
+            })
+    }
+}
+
+/// Verify each receipt against its image ID, using up to `parallelism`
+/// threads.
+///
+/// Each thread sets up the hash suites once and then verifies receipts in
+/// turn, so the cost of the setup does not grow with the size of the batch.
+/// The receipts are borrowed, not copied.
+///
+/// # Example
+///
+/// ```no_run
+/// use risc0_zkvm::{verify_batch, BatchMode, Receipt, sha::Digest};
+///
+/// # let receipts: Vec<(Receipt, Digest)> = Vec::new();
+/// let result = verify_batch(&receipts, 8, BatchMode::CollectAll);
+/// for (idx, err) in result.errors() {
+///     println!("receipt {idx}: {err}");
+/// }
+/// ```
+pub fn verify_batch(
+    receipts: &[(Receipt, Digest)],
+    parallelism: usize,
+    mode: BatchMode,
+) -> BatchResult {
+    let mut results: Vec<_> = receipts.iter().map(|_| None).collect();
+    let next = &AtomicUsize::new(0);
+    let failed = &AtomicBool::new(false);
+    let (tx, rx) = mpsc::channel();
+
+    thread::scope(|scope| {
+        for _ in 0..parallelism.clamp(1, receipts.len().max(1)) {
+            let tx = tx.clone();
+            scope.spawn(move || {
+                // Hash suites cannot be shared across threads.
+                let ctx = VerifierContext::default();
+                while mode == BatchMode::CollectAll || !failed.load(Ordering::Relaxed) {
+                    let idx = next.fetch_add(1, Ordering::Relaxed);
+                    let Some((receipt, image_id)) = receipts.get(idx) else {
+                        break;
+                    };
+                    let result = receipt.verify_with_context(&ctx, *image_id);
+                    if result.is_err() {
+                        failed.store(true, Ordering::Relaxed);
+                    }
+                    if tx.send((idx, result)).is_err() {
+                        break;
+                    }
+                }
+            });
+        }
+        drop(tx);
+
+        for (idx, result) in rx {
+            results[idx] = Some(result);
+        }
+    });
+
+    BatchResult { results }
+}
@@ -0,0 +1,218 @@
+// Copyright 2023 RISC Zero, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! A small harness for measuring the throughput of execution, proving and
+//! verification.
+//!
+//! Each bench function returns a [BenchResult], which can be written out with
+//! [write_json] to track regressions in CI. Any guest ELF can be used as a
+//! workload; the guests in `risc0-zkvm-methods` make good defaults.
+
+use std::{
+    io::Write,
+    time::{Duration, Instant},
+};
+
+use anyhow::{ensure, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    get_prover_server_for, host::client::prove::report::seal_bytes, ExecutorEnv, ExecutorImpl,
+    InnerReceipt, ProveOpts, Receipt, Session, SimpleSegmentRef, VerifierContext,
+};
+
+/// The measurements taken by one of the bench functions.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct BenchResult {
+    /// The operation that was measured: `"execute"`, `"prove"` or `"verify"`.
+    pub name: String,
+
+    /// The number of measured iterations.
+    pub iters: usize,
+
+    /// The number of user cycles in the session, excluding padding. Zero for
+    /// `"verify"`, since receipts do not record it.
+    pub cycles: u64,
+
+    /// The number of segments in the session.
+    pub segments: usize,
+
+    /// The total size of the segment seals, in bytes. Zero for `"execute"`.
+    pub seal_bytes: usize,
+
+    /// The wall time of a single iteration.
+    pub wall: WallTime,
+
+    /// User cycles per second, based on the median wall time.
+    pub cycles_per_sec: f64,
+
+    /// Segments per second, based on the median wall time.
+    pub segments_per_sec: f64,
+}
+
+/// Percentiles of the wall time across iterations.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct WallTime {
+    /// The fastest iteration.
+    pub min: Duration,
+
+    /// The median iteration.
+    pub p50: Duration,
+
+    /// The 90th percentile.
+    pub p90: Duration,
+
+    /// The slowest iteration.
+    pub max: Duration,
+}
+
+impl WallTime {
+    fn new(mut samples: Vec<Duration>) -> Self {
+        samples.sort();
+        let percentile = |p: usize| samples[(samples.len() - 1) * p / 100];
+        Self {
+            min: percentile(0),
+            p50: percentile(50),
+            p90: percentile(90),
+            max: percentile(100),
+        }
+    }
+}
+
+impl BenchResult {
+    fn new(
+        name: &str,
+        cycles: u64,
+        segments: usize,
+        seal_bytes: usize,
+        samples: Vec<Duration>,
+    ) -> Self {
+        let iters = samples.len();
+        let wall = WallTime::new(samples);
+        let secs = wall.p50.as_secs_f64();
+        Self {
+            name: name.to_string(),
+            iters,
+            cycles,
+            segments,
+            seal_bytes,
+            cycles_per_sec: cycles as f64 / secs,
+            segments_per_sec: segments as f64 / secs,
+            wall,
+        }
+    }
+}
+
+// Execute `elf` with `input` on stdin, returning the number of user cycles and
+// segments along with the session.
+fn execute(elf: &[u8], input: &[u8]) -> Result<(u64, usize, Session)> {
+    let env = ExecutorEnv::builder().write_bytes(input).build()?;
+    let mut exec = ExecutorImpl::from_elf(env, elf)?;
+    let mut cycles = 0;
+    let session = exec.run_with_callback(|segment| {
+        cycles += segment.cycles as u64;
+        Ok(Box::new(SimpleSegmentRef::new(segment)))
+    })?;
+    Ok((cycles, session.segments.len(), session))
+}
+
+/// Execute `elf` with `input` on stdin `iters` times.
+pub fn bench_execute(elf: &[u8], input: &[u8], iters: usize) -> Result<BenchResult> {
+    ensure!(iters > 0, "iters must be at least 1");
+    let mut samples = Vec::with_capacity(iters);
+    let mut stats = (0, 0);
+    for _ in 0..iters {
+        let start = Instant::now();
+        let (cycles, segments, _) = execute(elf, input)?;
+        samples.push(start.elapsed());
+        stats = (cycles, segments);
+    }
+    Ok(BenchResult::new("execute", stats.0, stats.1, 0, samples))
+}
+
+/// Prove `elf` with `input` on stdin once, using the prover selected by
+/// `opts`.
+///
+/// Only proving is timed; execution happens beforehand. The [Receipt] is
+/// returned as well, e.g. for use with [bench_verify].
+pub fn bench_prove(elf: &[u8], input: &[u8], opts: &ProveOpts) -> Result<(BenchResult, Receipt)> {
+    let (cycles, segments, session) = execute(elf, input)?;
+    let prover = get_prover_server_for(opts)?;
+    let start = Instant::now();
+    let receipt = prover.prove_session(&VerifierContext::default(), &session)?;
+    let elapsed = start.elapsed();
+    let seal_bytes = seal_bytes(&receipt);
+    let result = BenchResult::new("prove", cycles, segments, seal_bytes, vec![elapsed]);
+    Ok((result, receipt))
+}
+
+/// Verify the integrity of `receipt` `iters` times.
+pub fn bench_verify(receipt: &Receipt, iters: usize) -> Result<BenchResult> {
+    ensure!(iters > 0, "iters must be at least 1");
+    let ctx = VerifierContext::default();
+    let mut samples = Vec::with_capacity(iters);
+    for _ in 0..iters {
+        let start = Instant::now();
+        receipt.verify_integrity_with_context(&ctx)?;
+        samples.push(start.elapsed());
+    }
+    let segments = match &receipt.inner {
+        InnerReceipt::Composite(inner) => inner.segments.len(),
+        _ => 0,
+    };
+    Ok(BenchResult::new(
+        "verify",
+        0,
+        segments,
+        seal_bytes(receipt),
+        samples,
+    ))
+}
+
+/// Write `results` to `writer` as a JSON array.
+pub fn write_json(results: &[BenchResult], writer: impl Write) -> Result<()> {
+    Ok(serde_json::to_writer_pretty(writer, results)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use risc0_zkvm_methods::HELLO_COMMIT_ELF;
+
+    use super::{bench_execute, bench_prove, bench_verify, write_json, BenchResult};
+    use crate::ProveOpts;
+
+    #[test]
+    fn hello_commit() {
+        let execute = bench_execute(HELLO_COMMIT_ELF, &[], 1).unwrap();
+        assert_eq!(execute.iters, 1);
+        assert!(execute.cycles > 0);
+        assert_eq!(execute.segments, 1);
+        assert!(execute.cycles_per_sec > 0.0);
+
+        let opts = ProveOpts::default().with_hashfn("sha-256");
+        let (prove, receipt) = bench_prove(HELLO_COMMIT_ELF, &[], &opts).unwrap();
+        assert_eq!(prove.cycles, execute.cycles);
+        assert!(prove.seal_bytes > 0);
+        assert!(prove.wall.p50 > std::time::Duration::ZERO);
+
+        let verify = bench_verify(&receipt, 1).unwrap();
+        assert_eq!(verify.seal_bytes, prove.seal_bytes);
+        assert!(verify.segments_per_sec > 0.0);
+
+        let mut json = Vec::new();
+        write_json(&[execute, prove, verify], &mut json).unwrap();
+        let results: Vec<BenchResult> = serde_json::from_slice(&json).unwrap();
+        assert_eq!(results.len(), 3);
+    }
+}
@@ -0,0 +1,291 @@
+// Copyright 2023 RISC Zero, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! A receipt packaged with everything needed to verify it offline.
+
+use std::fmt;
+
+use anyhow::{anyhow, bail, ensure, Result};
+use bincode::Options;
+use risc0_zkp::verify::VerificationError;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+use super::api::convert::receipt_bincode_options;
+use crate::{get_version, sha::Digest, InputSchema, Receipt, ReceiptMetadata, VERSION};
+
+/// The tag at the start of a [VerificationBundle] encoded with
+/// [VerificationBundle::to_bytes].
+pub(crate) const BUNDLE_BYTES_TAG: &[u8; 4] = b"R0VB";
+
+/// The version of the encoding produced by [VerificationBundle::to_bytes].
+///
+/// This is bumped whenever a field is added, removed, or changes meaning.
+pub const VERIFICATION_BUNDLE_VERSION: u8 = 1;
+
+/// A [Receipt] together with the image ID it must be verified against and a
+/// description of how it was produced, so that a relying party can verify it
+/// from this one artifact.
+///
+/// Only the receipt is authenticated, by its seal. The other fields say what
+/// the receipt should be, and [VerificationBundle::verify] checks the receipt
+/// against each of them, so a bundle in which any of them was changed fails
+/// with a [BundleError] naming it. The journal schema is the exception: it is
+/// a hint for decoding the journal, checked only by
+/// [VerificationBundle::decode_journal].
+///
+/// # Example
+///
+/// ```no_run
+/// use risc0_zkvm::{Receipt, VerificationBundle};
+///
+/// # fn main() -> anyhow::Result<()> {
+/// # let receipt: Receipt = todo!();
+/// # let image_id = [0u32; 8];
+/// let bytes = VerificationBundle::new(receipt, image_id).to_bytes()?;
+///
+/// // On the relying party's side:
+/// let metadata = VerificationBundle::from_bytes(&bytes)?.verify()?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct VerificationBundle {
+    /// The receipt.
+    #[serde(with = "super::receipt::annotated")]
+    pub receipt: Receipt,
+
+    /// The image ID of the guest that the receipt must prove was run.
+    pub image_id: Digest,
+
+    /// The version of `risc0-zkvm` that made the bundle, which fixes the
+    /// versions of the circuits and the platform.
+    pub zkvm_version: String,
+
+    /// The circuit that produced the seals of the receipt: `"rv32im"`,
+    /// `"recursion"`, or `None` for a fake receipt.
+    pub circuit: Option<String>,
+
+    /// The hash suite the receipt was proven with, e.g. `"sha-256"` or
+    /// `"poseidon"`, or `None` for a fake receipt.
+    pub hashfn: Option<String>,
+
+    /// The [InputSchema::SCHEMA] of the type committed to the journal, if
+    /// known.
+    pub journal_schema: Option<String>,
+}
+
+/// The part of a [VerificationBundle] that failed to verify.
+#[derive(Debug, PartialEq)]
+pub enum BundleError {
+    /// The bundle was made by a version of `risc0-zkvm` whose circuits this
+    /// one cannot verify.
+    ZkvmVersion {
+        /// The version recorded in the bundle.
+        bundle: String,
+    },
+
+    /// The circuit recorded in the bundle is not the one that produced the
+    /// receipt.
+    Circuit {
+        /// The circuit recorded in the bundle.
+        bundle: Option<String>,
+        /// The circuit that produced the receipt.
+        receipt: Option<String>,
+    },
+
+    /// The hash suite recorded in the bundle is not the one that the receipt
+    /// was proven with.
+    HashSuite {
+        /// The hash suite recorded in the bundle.
+        bundle: Option<String>,
+        /// The hash suite of the receipt.
+        receipt: Option<String>,
+    },
+
+    /// The receipt does not prove a run of the guest with the bundle's image
+    /// ID.
+    ImageId(Digest),
+
+    /// The journal is not the one committed to by the receipt.
+    Journal,
+
+    /// The receipt itself failed to verify.
+    Receipt(VerificationError),
+}
+
+impl BundleError {
+    /// The name of the field of [VerificationBundle] that failed to verify.
+    pub fn component(&self) -> &'static str {
+        match self {
+            BundleError::ZkvmVersion { .. } => "zkvm_version",
+            BundleError::Circuit { .. } => "circuit",
+            BundleError::HashSuite { .. } => "hashfn",
+            BundleError::ImageId(_) => "image_id",
+            BundleError::Journal => "journal",
+            BundleError::Receipt(_) => "receipt",
+        }
+    }
+}
+
+impl fmt::Display for BundleError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "bundle {} is invalid: ", self.component())?;
+        match self {
+            BundleError::ZkvmVersion { bundle } => {
+                write!(f, "made by version {bundle}, not compatible with {VERSION}")
+            }
+            BundleError::Circuit { bundle, receipt } => {
+                write!(f, "expected {bundle:?}, receipt is from {receipt:?}")
+            }
+            BundleError::HashSuite { bundle, receipt } => {
+                write!(
+                    f,
+                    "expected {bundle:?}, receipt was proven with {receipt:?}"
+                )
+            }
+            BundleError::ImageId(image_id) => {
+                write!(f, "receipt does not prove a run of {image_id}")
+            }
+            BundleError::Journal => write!(f, "journal does not match the receipt"),
+            BundleError::Receipt(err) => write!(f, "{err}"),
+        }
+    }
+}
+
+impl std::error::Error for BundleError {}
+
+impl VerificationBundle {
+    /// Bundle `receipt` with the `image_id` it must be verified against, and
+    /// the versions, circuit and hash suite it was produced with.
+    pub fn new(receipt: Receipt, image_id: impl Into<Digest>) -> Self {
+        Self {
+            image_id: image_id.into(),
+            zkvm_version: VERSION.to_string(),
+            circuit: receipt.inner.circuit().map(str::to_string),
+            hashfn: receipt.inner.hashfn().map(str::to_string),
+            journal_schema: None,
+            receipt,
+        }
+    }
+
+    /// Record the type committed to the journal, as a hint for
+    /// [VerificationBundle::decode_journal].
+    pub fn with_journal_schema<T: InputSchema>(mut self) -> Self {
+        self.journal_schema = Some(T::SCHEMA.to_string());
+        self
+    }
+
+    /// Verify the receipt, checking that it is what the rest of the bundle
+    /// says it is, and return its [ReceiptMetadata].
+    ///
+    /// The version of the bundle is checked first, so a bundle from an
+    /// incompatible release fails with [BundleError::ZkvmVersion] rather than
+    /// an error about its seal. A version of `risc0-zkvm` can verify bundles
+    /// made by any release with the same major and minor version, or, for a
+    /// pre-release, only its own bundles.
+    pub fn verify(&self) -> Result<ReceiptMetadata, BundleError> {
+        if !is_compatible(&self.zkvm_version) {
+            return Err(BundleError::ZkvmVersion {
+                bundle: self.zkvm_version.clone(),
+            });
+        }
+        let circuit = self.receipt.inner.circuit();
+        if self.circuit.as_deref() != circuit {
+            return Err(BundleError::Circuit {
+                bundle: self.circuit.clone(),
+                receipt: circuit.map(str::to_string),
+            });
+        }
+        let hashfn = self.receipt.inner.hashfn();
+        if self.hashfn.as_deref() != hashfn {
+            return Err(BundleError::HashSuite {
+                bundle: self.hashfn.clone(),
+                receipt: hashfn.map(str::to_string),
+            });
+        }
+
+        let result = match &self.hashfn {
+            Some(hashfn) => self.receipt.verify_with_hashfn(self.image_id, hashfn),
+            None => self.receipt.verify(self.image_id),
+        };
+        result.map_err(|err| match err {
+            VerificationError::ImageVerificationError => BundleError::ImageId(self.image_id),
+            VerificationError::JournalDigestMismatch => BundleError::Journal,
+            VerificationError::InvalidHashSuite => BundleError::HashSuite {
+                bundle: self.hashfn.clone(),
+                receipt: hashfn.map(str::to_string),
+            },
+            err => BundleError::Receipt(err),
+        })?;
+        self.receipt.get_metadata().map_err(BundleError::Receipt)
+    }
+
+    /// Decode the journal as a `T`, after checking that the bundle's journal
+    /// schema, if any, is that of `T`.
+    ///
+    /// This does not verify the receipt; call [VerificationBundle::verify]
+    /// first.
+    pub fn decode_journal<T: DeserializeOwned + InputSchema>(&self) -> Result<T> {
+        if let Some(schema) = &self.journal_schema {
+            ensure!(
+                schema == T::SCHEMA,
+                "bundle journal_schema is invalid: journal holds {schema:?}, not {:?}",
+                T::SCHEMA
+            );
+        }
+        Ok(self.receipt.journal.decode()?)
+    }
+
+    /// Encode the bundle in a binary format that is the same on every host.
+    ///
+    /// The encoding starts with a tag and [VERIFICATION_BUNDLE_VERSION],
+    /// followed by the bundle encoded like a receipt in
+    /// [Receipt::to_bytes]. Decode it with [VerificationBundle::from_bytes].
+    pub fn to_bytes(&self) -> Result<Vec<u8>> {
+        let mut bytes = BUNDLE_BYTES_TAG.to_vec();
+        bytes.push(VERIFICATION_BUNDLE_VERSION);
+        receipt_bincode_options().serialize_into(&mut bytes, self)?;
+        Ok(bytes)
+    }
+
+    /// Decode a bundle encoded with [VerificationBundle::to_bytes].
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self> {
+        let Some(rest) = bytes.strip_prefix(BUNDLE_BYTES_TAG) else {
+            bail!("Not a verification bundle");
+        };
+        let (&version, body) = rest
+            .split_first()
+            .ok_or_else(|| anyhow!("Verification bundle ends before the format version"))?;
+        ensure!(
+            version == VERIFICATION_BUNDLE_VERSION,
+            "Unsupported verification bundle version: {version}, expected {VERIFICATION_BUNDLE_VERSION}"
+        );
+        Ok(receipt_bincode_options()
+            .with_limit(bytes.len() as u64)
+            .reject_trailing_bytes()
+            .deserialize(body)?)
+    }
+}
+
+// Whether receipts made by `version` can be verified by this version.
+fn is_compatible(version: &str) -> bool {
+    let (Ok(ours), Ok(theirs)) = (get_version(), semver::Version::parse(version)) else {
+        return false;
+    };
+    if ours.pre.is_empty() {
+        ours.major == theirs.major && ours.minor == theirs.minor && theirs.pre.is_empty()
+    } else {
+        ours == theirs
+    }
+}
@@ -0,0 +1,110 @@
+// Copyright 2023 RISC Zero, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! A self-describing CBOR encoding of receipts, for verifiers not written in
+//! Rust.
+//!
+//! A receipt is encoded as a map, tagged with the self-described CBOR tag
+//! (55799), holding a header for the verifier to dispatch on and the receipt
+//! itself. In [CDDL](https://www.rfc-editor.org/rfc/rfc8610):
+//!
+//! ```cddl
+//! receipt-cbor = #6.55799({
+//!   "version": uint,             ; RECEIPT_CBOR_VERSION
+//!   "zkvm_version": tstr,        ; the version of the zkVM and its circuits
+//!   "circuit": tstr / null,      ; "rv32im", "recursion", or null if fake
+//!   "hashfn": tstr / null,       ; e.g. "sha-256" or "poseidon"
+//!   "receipt": receipt,
+//! })
+//! ```
+//!
+//! The `receipt` follows the fields of [Receipt] and the types it contains,
+//! with each struct encoded as a map keyed by field name, in declaration
+//! order. Enums are encoded as a map from the name of the variant to its
+//! contents, or as the name alone for a variant without contents. A
+//! [Digest](crate::sha::Digest) is an array of 8 words, a seal is an array of
+//! words, byte vectors such as the journal are arrays of integers, and `None`
+//! is `null`. Integers always take their shortest form and every length is
+//! definite, so a receipt has exactly one encoding.
+//!
+//! Decoders ignore map keys they do not know, so fields may be added to the
+//! encoding without changing [RECEIPT_CBOR_VERSION].
+//!
+//! The files `src/host/api/testdata/receipt_*.cbor` are encodings of known
+//! receipts for testing decoders against.
+
+use anyhow::{ensure, Result};
+use ciborium::tag::Required;
+use serde::{Deserialize, Serialize};
+
+use super::receipt::annotated;
+use crate::{Receipt, VERSION};
+
+/// The version of the CBOR encoding of receipts produced by
+/// [Receipt::to_cbor].
+///
+/// This is bumped whenever a field is removed, renamed, or changes meaning.
+pub const RECEIPT_CBOR_VERSION: u32 = 1;
+
+// The self-described CBOR tag, which also identifies the data as CBOR.
+pub(crate) const SELF_DESCRIBED_TAG: u64 = 55799;
+
+#[derive(Serialize, Deserialize)]
+pub(crate) struct Envelope<R> {
+    pub(crate) version: u32,
+    pub(crate) zkvm_version: String,
+    pub(crate) circuit: Option<String>,
+    pub(crate) hashfn: Option<String>,
+    pub(crate) receipt: R,
+}
+
+impl Receipt {
+    /// Encode the receipt as self-describing CBOR.
+    ///
+    /// See the [module documentation](self) for the layout. Decode it with
+    /// [Receipt::from_cbor].
+    pub fn to_cbor(&self) -> Result<Vec<u8>> {
+        let envelope = Envelope {
+            version: RECEIPT_CBOR_VERSION,
+            zkvm_version: VERSION.to_string(),
+            circuit: self.inner.circuit().map(str::to_string),
+            hashfn: self.inner.hashfn().map(str::to_string),
+            receipt: annotated::Ref::from(self),
+        };
+        let mut bytes = Vec::new();
+        ciborium::into_writer(&Required::<_, SELF_DESCRIBED_TAG>(envelope), &mut bytes)?;
+        Ok(bytes)
+    }
+
+    /// Decode a receipt encoded with [Receipt::to_cbor].
+    ///
+    /// Unknown fields are ignored. The header must agree with the receipt it
+    /// describes.
+    pub fn from_cbor(bytes: &[u8]) -> Result<Self> {
+        let Required::<Envelope<annotated::Owned>, SELF_DESCRIBED_TAG>(envelope) =
+            ciborium::from_reader(bytes)?;
+        ensure!(
+            envelope.version == RECEIPT_CBOR_VERSION,
+            "Unsupported receipt CBOR version: {}, expected {RECEIPT_CBOR_VERSION}",
+            envelope.version
+        );
+        let receipt = Receipt::from(envelope.receipt);
+        ensure!(
+            envelope.circuit.as_deref() == receipt.inner.circuit()
+                && envelope.hashfn.as_deref() == receipt.inner.hashfn(),
+            "Receipt CBOR header does not match the receipt"
+        );
+        Ok(receipt)
+    }
+}
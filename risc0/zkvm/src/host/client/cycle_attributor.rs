@@ -0,0 +1,150 @@
+// Copyright 2023 RISC Zero, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Attribution of executed cycles to the functions of the guest.
+
+use anyhow::Result;
+use risc0_binfmt::{FunctionSymbol, Program};
+
+use super::{env::TraceCallback, symbol_stats::PcCycles};
+use crate::TraceEvent;
+
+/// The size of the address ranges that cycles are attributed to when the pc
+/// is not inside a known function.
+const RANGE_SIZE: u32 = 0x1000;
+
+/// Where the cycles of an instruction are counted.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Bucket {
+    /// The function with the given index in the symbol table.
+    Function(usize),
+
+    /// The [RANGE_SIZE] bytes of code starting at the given address.
+    Range(u32),
+}
+
+/// One line of a [CycleAttributor::report].
+#[derive(Clone, Debug, PartialEq)]
+pub struct CycleReportEntry {
+    /// The function name, as found in the ELF symbol table, or the address
+    /// range for code outside any known function.
+    pub name: String,
+
+    /// The cycles spent in the function.
+    pub cycles: u64,
+
+    /// The share of all the attributed cycles spent in the function, from 0
+    /// to 100.
+    pub percent: f64,
+}
+
+/// Counts the cycles the guest spends inside each of its functions, for a
+/// quick "top functions by cycles" report.
+///
+/// Unlike [SymbolStats](crate::SymbolStats), which only counts the functions
+/// flagged by [Program::analyze], this covers every function in the symbol
+/// table.
+///
+/// Install it with
+/// [ExecutorEnvBuilder::trace_callback](crate::ExecutorEnvBuilder::trace_callback)
+/// and call [CycleAttributor::report] once execution is done. Cycles are
+/// attributed by program counter to the function containing it, so time spent
+/// in a callee is not counted towards its caller. If the ELF is stripped, or
+/// the pc is outside every function, cycles are attributed to fixed-size
+/// address ranges instead.
+///
+/// # Example
+///
+/// ```no_run
+/// use risc0_zkvm::{default_executor, CycleAttributor, ExecutorEnv};
+///
+/// # let elf: &[u8] = &[];
+/// let mut attributor = CycleAttributor::new(elf).unwrap();
+/// let env = ExecutorEnv::builder()
+///     .trace_callback(&mut attributor)
+///     .build()
+///     .unwrap();
+/// default_executor().execute_elf(env, elf).unwrap();
+/// for entry in attributor.report(10) {
+///     println!("{:>6.2}% {:>10} {}", entry.percent, entry.cycles, entry.name);
+/// }
+/// ```
+pub struct CycleAttributor {
+    functions: Vec<FunctionSymbol>,
+    cycles: PcCycles<Bucket>,
+}
+
+impl CycleAttributor {
+    /// Read the symbol table of `elf` and prepare to count cycles while it
+    /// executes.
+    pub fn new(elf: &[u8]) -> Result<Self> {
+        Ok(Self {
+            functions: Program::functions(elf)?,
+            cycles: PcCycles::new(),
+        })
+    }
+
+    /// The `n` functions with the most cycles, from most to fewest.
+    pub fn report(&self, n: usize) -> Vec<CycleReportEntry> {
+        let total = self.cycles.total();
+        let mut entries: Vec<_> = self
+            .cycles
+            .iter()
+            .map(|(&bucket, cycles)| CycleReportEntry {
+                name: self.bucket_name(bucket),
+                cycles,
+                percent: cycles as f64 * 100.0 / total as f64,
+            })
+            .collect();
+        entries.sort_by(|a, b| b.cycles.cmp(&a.cycles));
+        entries.truncate(n);
+        entries
+    }
+
+    /// The total number of cycles attributed so far.
+    pub fn total_cycles(&self) -> u64 {
+        self.cycles.total()
+    }
+
+    fn bucket_name(&self, bucket: Bucket) -> String {
+        match bucket {
+            Bucket::Function(idx) => self.functions[idx].name.clone(),
+            Bucket::Range(start) => format!("0x{start:08x}..0x{:08x}", start + RANGE_SIZE),
+        }
+    }
+}
+
+// Find the bucket containing `pc`, given the functions sorted by start address.
+fn bucket_at(functions: &[FunctionSymbol], pc: u32) -> Bucket {
+    let idx = functions.partition_point(|sym| sym.start <= pc);
+    match idx.checked_sub(1) {
+        Some(idx) if functions[idx].contains(pc) => Bucket::Function(idx),
+        _ => Bucket::Range(pc - pc % RANGE_SIZE),
+    }
+}
+
+impl TraceCallback for CycleAttributor {
+    fn trace_callback(&mut self, event: TraceEvent) -> Result<()> {
+        let functions = &self.functions;
+        self.cycles
+            .record(event, |pc| Some(bucket_at(functions, pc)));
+        Ok(())
+    }
+}
+
+impl TraceCallback for &mut CycleAttributor {
+    fn trace_callback(&mut self, event: TraceEvent) -> Result<()> {
+        (*self).trace_callback(event)
+    }
+}
@@ -0,0 +1,264 @@
+// Copyright 2023 RISC Zero, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Re-run a guest each time it is rebuilt, during development.
+
+use std::{
+    fmt,
+    path::{Path, PathBuf},
+    rc::Rc,
+    thread,
+    time::Duration,
+};
+
+use anyhow::{Context, Result};
+
+use crate::{
+    sha::{Digest, Impl, Sha256 as _},
+    Executor, ExecutorEnv, ExitCode, GuestEnvManifest,
+};
+
+/// Runs a guest ELF again whenever the file changes, and reports how the run
+/// differs from the one before.
+///
+/// Every run executes the guest, without proving, in the environment recorded
+/// in a [GuestEnvManifest], so each build of the guest sees the same input,
+/// environment variables, arguments and nonce. Named inputs and slice I/O
+/// handlers cannot be restored from a manifest on their own, so a run with a
+/// manifest that has either fails.
+///
+/// ```no_run
+/// use std::time::Duration;
+///
+/// use risc0_zkvm::{DevSession, ExecutorEnv};
+///
+/// # fn main() -> anyhow::Result<()> {
+/// let env = ExecutorEnv::builder().write(&42u32)?.build()?;
+/// let mut session = DevSession::new("target/riscv-guest/release/guest", env.manifest());
+/// session.watch(Duration::from_millis(500), |report| {
+///     match report {
+///         Ok(report) => println!("{report}"),
+///         Err(err) => println!("run failed: {err:?}"),
+///     }
+///     true
+/// });
+/// # Ok(())
+/// # }
+/// ```
+pub struct DevSession {
+    path: PathBuf,
+    manifest: GuestEnvManifest,
+    executor: Rc<dyn Executor>,
+    // The digest of the ELF last run, whether or not the run succeeded.
+    elf_digest: Option<Digest>,
+    last: Option<DevRun>,
+}
+
+/// The outcome of running one build of a guest in a [DevSession].
+#[derive(Clone, Debug, PartialEq)]
+pub struct DevRun {
+    /// The image ID of the guest.
+    pub image_id: Digest,
+
+    /// The journal committed by the guest.
+    pub journal: Vec<u8>,
+
+    /// The number of user cycles the guest ran for, over all segments.
+    pub cycles: u64,
+
+    /// How the guest exited.
+    pub exit_code: ExitCode,
+}
+
+/// A run of a [DevSession], compared with the previous successful run.
+#[derive(Clone, Debug)]
+pub struct DevReport {
+    /// This run.
+    pub run: DevRun,
+
+    /// The previous successful run, if any.
+    pub previous: Option<DevRun>,
+}
+
+/// Where the journal of a run first differs from that of the previous run.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct JournalDiff {
+    /// The offset of the first byte that differs, or the length of the
+    /// shorter journal if one is a prefix of the other.
+    pub offset: usize,
+
+    /// The length of the previous journal.
+    pub previous_len: usize,
+
+    /// The length of this journal.
+    pub len: usize,
+}
+
+impl DevReport {
+    /// How the journal changed since the previous run, if it did.
+    pub fn journal_diff(&self) -> Option<JournalDiff> {
+        let (previous, journal) = (&self.previous.as_ref()?.journal, &self.run.journal);
+        if previous == journal {
+            return None;
+        }
+        let offset = previous
+            .iter()
+            .zip(journal)
+            .position(|(a, b)| a != b)
+            .unwrap_or(previous.len().min(journal.len()));
+        Some(JournalDiff {
+            offset,
+            previous_len: previous.len(),
+            len: journal.len(),
+        })
+    }
+
+    /// The change in user cycles since the previous run.
+    pub fn cycle_delta(&self) -> Option<i64> {
+        let previous = self.previous.as_ref()?;
+        Some(self.run.cycles as i64 - previous.cycles as i64)
+    }
+}
+
+impl fmt::Display for DevReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}: {:?} after {} cycles",
+            self.run.image_id, self.run.exit_code, self.run.cycles
+        )?;
+        let Some(previous) = &self.previous else {
+            return Ok(());
+        };
+        if let Some(delta) = self.cycle_delta() {
+            write!(f, " ({delta:+})")?;
+        }
+        if previous.exit_code != self.run.exit_code {
+            write!(f, ", was {:?}", previous.exit_code)?;
+        }
+        match self.journal_diff() {
+            None => write!(f, ", journal unchanged"),
+            Some(diff) => write!(
+                f,
+                ", journal differs from byte {} ({} bytes, was {})",
+                diff.offset, diff.len, diff.previous_len
+            ),
+        }
+    }
+}
+
+impl DevSession {
+    /// Run the guest ELF at `path` in the environment recorded in `manifest`,
+    /// with the [default_executor](crate::default_executor).
+    pub fn new(path: impl Into<PathBuf>, manifest: GuestEnvManifest) -> Self {
+        Self::with_executor(path, manifest, crate::default_executor())
+    }
+
+    /// Run the guest ELF at `path` in the environment recorded in `manifest`,
+    /// with `executor`.
+    pub fn with_executor(
+        path: impl Into<PathBuf>,
+        manifest: GuestEnvManifest,
+        executor: Rc<dyn Executor>,
+    ) -> Self {
+        Self {
+            path: path.into(),
+            manifest,
+            executor,
+            elf_digest: None,
+            last: None,
+        }
+    }
+
+    /// The path of the guest ELF.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// The last successful run, if any.
+    pub fn last_run(&self) -> Option<&DevRun> {
+        self.last.as_ref()
+    }
+
+    /// Run the guest, whether or not it changed since the last run.
+    pub fn run(&mut self) -> Result<DevReport> {
+        let elf = self.read_elf()?;
+        self.run_elf(&elf)
+    }
+
+    /// Run the guest if the ELF changed since the last run, or was never run.
+    ///
+    /// Changes are found by comparing the contents of the file, so an ELF
+    /// that is rebuilt without changing is not run again. An ELF that failed
+    /// to run is not retried until it changes.
+    pub fn poll(&mut self) -> Result<Option<DevReport>> {
+        let elf = self.read_elf()?;
+        if self.elf_digest == Some(*Impl::hash_bytes(&elf)) {
+            return Ok(None);
+        }
+        self.run_elf(&elf).map(Some)
+    }
+
+    /// [Poll](DevSession::poll) for changes to the ELF every `interval`, and
+    /// pass the result of each run to `on_report`, until it returns `false`.
+    ///
+    /// An ELF that is missing, e.g. while the guest is being rebuilt, is
+    /// waited for. One that is only partly written may fail to run, and is run
+    /// again once it is complete.
+    pub fn watch(
+        &mut self,
+        interval: Duration,
+        mut on_report: impl FnMut(Result<DevReport>) -> bool,
+    ) {
+        loop {
+            let report = match self.read_elf() {
+                Ok(elf) if self.elf_digest != Some(*Impl::hash_bytes(&elf)) => {
+                    Some(self.run_elf(&elf))
+                }
+                _ => None,
+            };
+            if let Some(report) = report {
+                if !on_report(report) {
+                    return;
+                }
+            }
+            thread::sleep(interval);
+        }
+    }
+
+    fn read_elf(&self) -> Result<Vec<u8>> {
+        std::fs::read(&self.path).with_context(|| format!("Failed to read {:?}", self.path))
+    }
+
+    fn run_elf(&mut self, elf: &[u8]) -> Result<DevReport> {
+        self.elf_digest = Some(*Impl::hash_bytes(elf));
+        let env = ExecutorEnv::builder()
+            .apply_manifest(&self.manifest, |_| None)?
+            .build()?;
+        let image_id = env.layout.image_id(elf)?;
+        let info = self.executor.execute_elf(env, elf)?;
+        let run = DevRun {
+            image_id,
+            journal: info.journal.bytes,
+            cycles: info
+                .segments
+                .iter()
+                .map(|segment| segment.cycles as u64)
+                .sum(),
+            exit_code: info.exit_code,
+        };
+        let previous = self.last.replace(run.clone());
+        Ok(DevReport { run, previous })
+    }
+}
@@ -15,34 +15,59 @@
 //! This module defines the [ExecutorEnv] and [ExecutorEnvBuilder].
 
 use std::{
+    borrow::Cow,
     cell::RefCell,
-    collections::HashMap,
+    collections::{BTreeMap, HashMap, HashSet},
     io::{BufRead, BufReader, Cursor, Read, Write},
     mem,
+    panic::{self, AssertUnwindSafe},
     path::{Path, PathBuf},
     rc::Rc,
+    sync::Arc,
 };
 
-use anyhow::Result;
+use anyhow::{anyhow, bail, Context, Result};
 use bytemuck::Pod;
 use bytes::Bytes;
-use risc0_zkvm_platform::{self, fileno};
+use risc0_binfmt::{input_schema_digest, Program};
+use risc0_zkvm_platform::{self, fileno, WORD_SIZE};
 use serde::Serialize;
 
-use crate::serde::to_vec;
+#[cfg(feature = "prove")]
+use crate::ProveOpts;
 use crate::{
     host::client::{
         exec::TraceEvent,
-        posix_io::PosixIo,
-        slice_io::{slice_io_from_fn, SliceIo, SliceIoTable},
+        framed::FramedOutput,
+        host_commit::HostCommits,
+        layout::{EntryPoint, GuestLayout},
+        line_writer::LineWriter,
+        manifest::GuestEnvManifest,
+        posix_io::{
+            BufferedWriter, LazyFd, LazyFdContext, LogLimits, LogPolicy, OutputSizeHints,
+            OverflowPolicy, PosixIo, WriteBackoff,
+        },
+        prove::{
+            setup::{OptsError, SetupErrors},
+            MAX_SEGMENT_LIMIT_PO2, MIN_SEGMENT_LIMIT_PO2,
+        },
+        slice_io::{
+            is_builtin, slice_io_from_cow_fn, slice_io_from_fn, SliceIo, SliceIoFill, SliceIoTable,
+            SyscallGuard, SyscallSource, BUILTIN_SYSCALLS,
+        },
     },
-    Assumption,
+    serde::to_vec,
+    sha::{Digest, Digestible},
+    Assumption, InputSchema,
 };
 
 /// A builder pattern used to construct an [ExecutorEnv].
 #[derive(Default)]
 pub struct ExecutorEnvBuilder<'a> {
     inner: ExecutorEnv<'a>,
+    // The input written so far, handed to the environment without a copy
+    // when it is built.
+    input: Vec<u8>,
 }
 
 /// A callback used to collect [TraceEvent]s.
@@ -59,6 +84,20 @@ where
     }
 }
 
+/// What the executor does when the guest reports a failed check with
+/// `env::check`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum CheckPolicy {
+    /// Record the failed check and continue execution. The checks that failed
+    /// are listed in [Session::failed_checks](crate::Session::failed_checks).
+    #[default]
+    Record,
+
+    /// Stop execution with an error at the first failed check. The error can
+    /// be downcast to the [FailedCheck](crate::FailedCheck).
+    FailFast,
+}
+
 /// Container for assumptions in the executor environment.
 #[derive(Debug, Default)]
 pub(crate) struct Assumptions {
@@ -67,6 +106,45 @@ pub(crate) struct Assumptions {
     pub(crate) accessed: Vec<Assumption>,
 }
 
+/// Guests that a running guest may execute through `env::exec`, along with
+/// the limits that apply to them.
+#[derive(Clone)]
+pub(crate) struct GuestRegistry {
+    pub(crate) guests: HashMap<String, Arc<[u8]>>,
+    pub(crate) depth_limit: u32,
+    pub(crate) session_limit: Option<u64>,
+    pub(crate) prove: bool,
+    // The options the nested guests are proven with. The prover of the
+    // calling guest sets them to its own, so that the receipts can be
+    // assumptions of its session.
+    #[cfg(feature = "prove")]
+    pub(crate) prove_opts: ProveOpts,
+    // How deeply the guest using this registry is itself nested.
+    #[cfg(feature = "prove")]
+    pub(crate) depth: u32,
+}
+
+impl Default for GuestRegistry {
+    fn default() -> Self {
+        Self {
+            guests: HashMap::new(),
+            depth_limit: DEFAULT_NESTED_DEPTH_LIMIT,
+            session_limit: None,
+            prove: false,
+            #[cfg(feature = "prove")]
+            prove_opts: ProveOpts::default(),
+            #[cfg(feature = "prove")]
+            depth: 0,
+        }
+    }
+}
+
+const DEFAULT_NESTED_DEPTH_LIMIT: u32 = 4;
+
+// The file descriptor of the first input added with
+// [ExecutorEnvBuilder::named_input]; each further input takes the next one.
+const FIRST_NAMED_INPUT_FD: u32 = 0x1000;
+
 /// The [crate::Executor] is configured from this object.
 ///
 /// The executor environment holds configuration details that inform how the
@@ -78,11 +156,41 @@ pub struct ExecutorEnv<'a> {
     pub(crate) segment_limit_po2: Option<u32>,
     pub(crate) session_limit: Option<u64>,
     pub(crate) posix_io: Rc<RefCell<PosixIo<'a>>>,
+    pub(crate) lazy_fds: Rc<RefCell<Vec<(u32, LazyFd<'a>)>>>,
     pub(crate) slice_io: Rc<RefCell<SliceIoTable<'a>>>,
-    pub(crate) input: Vec<u8>,
+    pub(crate) input: Bytes,
+    pub(crate) input_buffer: Option<Arc<[u8]>>,
     pub(crate) trace: Vec<Rc<RefCell<dyn TraceCallback + 'a>>>,
+    pub(crate) trace_file: Option<PathBuf>,
+    pub(crate) journal_callbacks: Vec<Rc<RefCell<dyn FnMut(&[u8]) + 'a>>>,
+    pub(crate) journal_tees: Vec<Rc<RefCell<dyn Write + 'a>>>,
+    pub(crate) syscall_audit: Option<Rc<RefCell<dyn Write + 'a>>>,
+    pub(crate) host_commits: HostCommits,
+    // The items committed by a guest with a hashed journal.
+    #[cfg(feature = "prove")]
+    pub(crate) journal_preimages: Rc<RefCell<Vec<Vec<u8>>>>,
+    // Called with each segment as soon as execution reaches its end; an error
+    // stops the execution.
+    #[cfg(feature = "prove")]
+    pub(crate) segment_callbacks: Vec<Rc<RefCell<dyn FnMut(&crate::Segment) -> Result<()> + 'a>>>,
     pub(crate) assumptions: Rc<RefCell<Assumptions>>,
     pub(crate) segment_path: Option<PathBuf>,
+    pub(crate) isa_audit: bool,
+    pub(crate) exec_acceleration: bool,
+    pub(crate) derived_randomness: bool,
+    pub(crate) sha_accel: bool,
+    pub(crate) bigint_accel: bool,
+    pub(crate) hashed_journal: bool,
+    pub(crate) guest_registry: GuestRegistry,
+    pub(crate) input_schema: Option<Digest>,
+    pub(crate) skip_image_integrity_check: bool,
+    pub(crate) named_inputs: Vec<(String, u32)>,
+    pub(crate) nonce: Option<[u8; 32]>,
+    pub(crate) check_policy: CheckPolicy,
+    pub(crate) layout: GuestLayout,
+    // Set when the guest can read from a source that cannot be digested, such
+    // as a custom reader or a slice I/O handler.
+    pub(crate) opaque_input: bool,
 }
 
 impl<'a> ExecutorEnv<'a> {
@@ -98,6 +206,146 @@ impl<'a> ExecutorEnv<'a> {
     pub fn builder() -> ExecutorEnvBuilder<'a> {
         ExecutorEnvBuilder::default()
     }
+
+    /// A digest of everything the guest can read from this environment: its
+    /// input, environment variables, arguments, registered guests and
+    /// assumptions.
+    ///
+    /// Returns `None` if the guest can also read from a source that cannot be
+    /// digested, such as a reader passed to [ExecutorEnvBuilder::stdin] or a
+    /// [SliceIo] handler.
+    pub(crate) fn input_digest(&self) -> Option<Digest> {
+        if self.opaque_input {
+            return None;
+        }
+        let mut env_vars: Vec<_> = self.env_vars.iter().collect();
+        env_vars.sort();
+        let mut guests: Vec<_> = self
+            .guest_registry
+            .guests
+            .iter()
+            .map(|(name, elf)| (name, elf.digest()))
+            .collect();
+        guests.sort();
+        let assumptions = self
+            .assumptions
+            .borrow()
+            .cached
+            .iter()
+            .map(|x| Some(x.get_metadata().ok()?.digest()))
+            .collect::<Option<Vec<_>>>()?;
+        let words = to_vec(&(
+            &*self.input,
+            self.input_buffer.as_deref(),
+            env_vars,
+            &self.args,
+            guests,
+            assumptions,
+            self.nonce,
+        ))
+        .ok()?;
+        Some(bytemuck::cast_slice::<u32, u8>(&words).digest())
+    }
+
+    /// A [GuestEnvManifest] of this environment, from which
+    /// [ExecutorEnvBuilder::apply_manifest] can set up the same environment
+    /// again.
+    pub fn manifest(&self) -> GuestEnvManifest {
+        GuestEnvManifest {
+            env_vars: self.env_vars.clone().into_iter().collect(),
+            args: self.args.clone(),
+            input: match &self.input_buffer {
+                Some(buffer) => buffer.to_vec(),
+                None => self.input.to_vec(),
+            },
+            named_inputs: self
+                .named_inputs
+                .iter()
+                .map(|(name, _)| name.clone())
+                .collect(),
+            slice_io: self.slice_io.borrow().inner.keys().cloned().collect(),
+            segment_limit_po2: self.segment_limit_po2,
+            session_limit: self.session_limit,
+            nonce: self.nonce,
+        }
+    }
+
+    /// The syscalls the guest can make in this environment, each with where
+    /// its handler comes from, for debugging.
+    pub fn syscall_handlers(&self) -> BTreeMap<String, SyscallSource> {
+        let mut handlers: BTreeMap<_, _> = BUILTIN_SYSCALLS
+            .iter()
+            .map(|syscall| (syscall.as_str().to_string(), SyscallSource::Builtin))
+            .collect();
+        for channel in self.slice_io.borrow().inner.keys() {
+            let source = if is_builtin(channel) {
+                SyscallSource::Override
+            } else {
+                SyscallSource::User
+            };
+            handlers.insert(channel.clone(), source);
+        }
+        handlers
+    }
+
+    /// Make the readers and writers of the descriptors added with
+    /// [ExecutorEnvBuilder::lazy_read_fd] and
+    /// [ExecutorEnvBuilder::lazy_write_fd] for a run of the guest with
+    /// `image_id`.
+    ///
+    /// Each factory is called at most once, so this does nothing after the
+    /// first call. A factory that fails or panics fails the run.
+    pub(crate) fn resolve_lazy_fds(&self, image_id: Digest) -> Result<()> {
+        let lazy_fds = mem::take(&mut *self.lazy_fds.borrow_mut());
+        let ctx = LazyFdContext {
+            image_id,
+            env: self,
+        };
+        for (fd, lazy_fd) in lazy_fds {
+            match lazy_fd {
+                LazyFd::Read(factory) => {
+                    let reader = call_factory("read", fd, || factory(&ctx))?;
+                    self.posix_io.borrow_mut().with_read_fd(fd, reader);
+                }
+                LazyFd::Write(factory) => {
+                    let writer = call_factory("write", fd, || factory(&ctx))?;
+                    self.posix_io.borrow_mut().with_write_fd(fd, writer);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Check the input written with [ExecutorEnvBuilder::write_checked]
+    /// against the input schema declared by `elf`.
+    pub(crate) fn check_input_schema(&self, elf: &[u8]) -> Result<()> {
+        SetupErrors(self.input_schema_error(elf)?.into_iter().collect()).check()
+    }
+
+    // The mismatch between the schema of the input written by the host and
+    // the one declared by the guest in `elf`, if any.
+    pub(crate) fn input_schema_error(&self, elf: &[u8]) -> Result<Option<OptsError>> {
+        let Some(host) = self.input_schema else {
+            return Ok(None);
+        };
+        let guest = Program::input_schema(elf)?;
+        Ok((guest != Some(host)).then_some(OptsError::InputSchemaMismatch { host, guest }))
+    }
+}
+
+fn call_factory<T>(kind: &str, fd: u32, factory: impl FnOnce() -> Result<T>) -> Result<T> {
+    panic::catch_unwind(AssertUnwindSafe(factory))
+        .map_err(|_| anyhow!("the factory for lazy {kind} fd {fd} panicked"))?
+        .with_context(|| format!("the factory for lazy {kind} fd {fd} failed"))
+}
+
+/// Check that `po2` is between [MIN_SEGMENT_LIMIT_PO2] and
+/// [MAX_SEGMENT_LIMIT_PO2] (inclusive).
+pub(crate) fn check_segment_limit_po2(po2: u32) -> Result<()> {
+    if !(MIN_SEGMENT_LIMIT_PO2..=MAX_SEGMENT_LIMIT_PO2).contains(&po2) {
+        return SetupErrors(vec![OptsError::InvalidSegmentLimitPo2(po2)]).check();
+    }
+    Ok(())
 }
 
 impl<'a> ExecutorEnvBuilder<'a> {
@@ -113,10 +361,68 @@ impl<'a> ExecutorEnvBuilder<'a> {
     ///
     /// After calling `build`, the [ExecutorEnvBuilder] will be reset to
     /// default.
+    ///
+    /// Fails with [SetupErrors] listing every conflict between the settings
+    /// of the builder, not just the first one found.
     pub fn build(&mut self) -> Result<ExecutorEnv<'a>> {
-        let inner = mem::take(&mut self.inner);
+        let mut inner = mem::take(&mut self.inner);
+        inner.input = mem::take(&mut self.input).into();
+        let mut errs = Vec::new();
+
+        if let Some(name) = inner
+            .env_vars
+            .keys()
+            .find(|name| name.is_empty() || name.contains('\0'))
+        {
+            errs.push(OptsError::InvalidEnvVarName(name.clone()));
+        }
+
+        if inner
+            .posix_io
+            .borrow()
+            .write_fds
+            .contains_key(&fileno::JOURNAL)
+            || inner
+                .lazy_fds
+                .borrow()
+                .iter()
+                .any(|(fd, lazy_fd)| *fd == fileno::JOURNAL && matches!(lazy_fd, LazyFd::Write(_)))
+        {
+            errs.push(OptsError::JournalRedirected);
+        }
 
-        if !inner.input.is_empty() {
+        if let Some(fd) = inner
+            .posix_io
+            .borrow()
+            .conflicting_fd(&inner.lazy_fds.borrow())
+        {
+            errs.push(OptsError::ConflictingFd(fd));
+        }
+
+        if let Err(err) = inner.slice_io.borrow().check() {
+            errs.push(OptsError::SliceIo(err.to_string()));
+        }
+
+        let mut names = HashSet::new();
+        if let Some((name, _)) = inner
+            .named_inputs
+            .iter()
+            .find(|(name, _)| !names.insert(name))
+        {
+            errs.push(OptsError::DuplicateNamedInput(name.clone()));
+        }
+
+        if inner.input_buffer.is_some() && !inner.input.is_empty() {
+            errs.push(OptsError::InputBufferWithWrite);
+        }
+        SetupErrors(errs).check()?;
+
+        if let Some(buffer) = &inner.input_buffer {
+            inner
+                .posix_io
+                .borrow_mut()
+                .with_read_fd(fileno::STDIN, Cursor::new(buffer.clone()));
+        } else if !inner.input.is_empty() {
             let reader = Cursor::new(inner.input.clone());
             inner
                 .posix_io
@@ -129,13 +435,23 @@ impl<'a> ExecutorEnvBuilder<'a> {
 
     /// Set a segment limit, specified in powers of 2 cycles.
     ///
-    /// Given value must be between [risc0_zkp::MIN_CYCLES_PO2] and
-    /// [risc0_zkp::MAX_CYCLES_PO2] (inclusive).
+    /// Given value must be between [MIN_SEGMENT_LIMIT_PO2] and
+    /// [MAX_SEGMENT_LIMIT_PO2] (inclusive). Otherwise the executor fails to
+    /// start; use [ExecutorEnvBuilder::try_segment_limit_po2] to catch this
+    /// here instead.
     pub fn segment_limit_po2(&mut self, limit: u32) -> &mut Self {
         self.inner.segment_limit_po2 = Some(limit);
         self
     }
 
+    /// Set a segment limit, specified in powers of 2 cycles, returning an
+    /// error if it is not between [MIN_SEGMENT_LIMIT_PO2] and
+    /// [MAX_SEGMENT_LIMIT_PO2] (inclusive).
+    pub fn try_segment_limit_po2(&mut self, limit: u32) -> Result<&mut Self> {
+        check_segment_limit_po2(limit)?;
+        Ok(self.segment_limit_po2(limit))
+    }
+
     /// Set a session limit, specified in number of cycles.
     ///
     /// # Example
@@ -153,7 +469,13 @@ impl<'a> ExecutorEnvBuilder<'a> {
         self
     }
 
-    /// Add environment variables to the guest environment.
+    /// Set the environment variables of the guest environment, replacing any
+    /// set before.
+    ///
+    /// Accepts any iterator of name/value pairs, such as a [HashMap] or
+    /// `std::env::vars()`. Names must be non-empty and must not contain NUL
+    /// bytes; otherwise [ExecutorEnvBuilder::build] returns an error. To keep
+    /// the variables already set, use [ExecutorEnvBuilder::extend_env_vars].
     ///
     /// # Example
     ///
@@ -170,13 +492,42 @@ impl<'a> ExecutorEnvBuilder<'a> {
     ///     .build()
     ///     .unwrap();
     /// ```
-    pub fn env_vars(&mut self, vars: HashMap<String, String>) -> &mut Self {
-        self.inner.env_vars = vars;
+    pub fn env_vars(&mut self, vars: impl IntoIterator<Item = (String, String)>) -> &mut Self {
+        self.inner.env_vars = vars.into_iter().collect();
+        self
+    }
+
+    /// Add environment variables to the guest environment, keeping those set
+    /// before unless they have the same name.
+    ///
+    /// The same rules as for [ExecutorEnvBuilder::env_vars] apply to the
+    /// names.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use risc0_zkvm::ExecutorEnv;
+    ///
+    /// let env = ExecutorEnv::builder()
+    ///     .env_var("VAR1", "SOME_VALUE")
+    ///     .extend_env_vars(std::env::vars().filter(|(name, _)| name.starts_with("APP_")))
+    ///     .build()
+    ///     .unwrap();
+    /// ```
+    pub fn extend_env_vars(
+        &mut self,
+        vars: impl IntoIterator<Item = (String, String)>,
+    ) -> &mut Self {
+        self.inner.env_vars.extend(vars);
         self
     }
 
     /// Add an argument array to the guest environment.
     ///
+    /// The guest reads them with `env::args`, or with `std::env::args` if it
+    /// uses the standard library. Each argument is copied into guest memory
+    /// when it is read, so their size is only limited by the guest's heap.
+    ///
     /// # Example
     /// ```
     /// # use risc0_zkvm::ExecutorEnv;
@@ -191,6 +542,28 @@ impl<'a> ExecutorEnvBuilder<'a> {
         self
     }
 
+    /// Provide a nonce that the guest reads with
+    /// [env::nonce](crate::guest::env::nonce).
+    ///
+    /// A guest that commits the nonce as the first item of its journal binds
+    /// the receipt to it, so that a verifier who chose the nonce can reject a
+    /// replayed receipt with [Receipt::verify_with_nonce](crate::Receipt::verify_with_nonce).
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use risc0_zkvm::ExecutorEnv;
+    ///
+    /// let env = ExecutorEnv::builder()
+    ///     .nonce([7; 32])
+    ///     .build()
+    ///     .unwrap();
+    /// ```
+    pub fn nonce(&mut self, nonce: [u8; 32]) -> &mut Self {
+        self.inner.nonce = Some(nonce);
+        self
+    }
+
     /// Add an environment variable to the guest environment.
     ///
     /// # Example
@@ -214,7 +587,9 @@ impl<'a> ExecutorEnvBuilder<'a> {
     ///
     /// This function will serialize `data` using a zkVM-optimized codec that
     /// can be deserialized in the guest with a corresponding `env::read` with
-    /// the same data type.
+    /// the same data type. This is the preferred way to pass input to the
+    /// guest: the encoding is little-endian, as the guest expects, whatever
+    /// the byte order of the host.
     ///
     /// # Example
     ///
@@ -237,36 +612,512 @@ impl<'a> ExecutorEnvBuilder<'a> {
     ///     .unwrap();
     /// ```
     pub fn write<T: Serialize>(&mut self, data: &T) -> Result<&mut Self> {
-        Ok(self.write_slice(&to_vec(data)?))
+        Ok(self.write_words(&to_vec(data)?))
+    }
+
+    /// Write input data to the zkVM guest stdin, like [Self::write], and check
+    /// that the guest expects it.
+    ///
+    /// The guest must read the data with
+    /// [read_checked!](crate::read_checked), which declares the expected
+    /// [InputSchema] in the guest ELF. Execution then refuses to start unless
+    /// both schemas match. Only one checked input is supported per
+    /// execution, and only executions started from an ELF are checked.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use risc0_zkvm::{ExecutorEnv, InputSchema};
+    /// use serde::Serialize;
+    ///
+    /// #[derive(Serialize)]
+    /// struct Point {
+    ///     x: u32,
+    ///     y: u32,
+    /// }
+    ///
+    /// impl InputSchema for Point {
+    ///     const SCHEMA: &'static str = "Point { x: u32, y: u32 }";
+    /// }
+    ///
+    /// let env = ExecutorEnv::builder()
+    ///     .write_checked(&Point { x: 1, y: 2 })
+    ///     .unwrap()
+    ///     .build()
+    ///     .unwrap();
+    /// ```
+    pub fn write_checked<T: InputSchema + Serialize>(&mut self, data: &T) -> Result<&mut Self> {
+        let schema = input_schema_digest(T::SCHEMA);
+        if let Some(prev) = self.inner.input_schema.filter(|prev| *prev != schema) {
+            bail!("Input schema {schema} conflicts with the previously written {prev}");
+        }
+        self.inner.input_schema = Some(schema);
+        self.write(data)
     }
 
     /// Write input data to the zkVM guest stdin.
     ///
-    /// This function writes a slice directly to the underlying buffer. A
-    /// corresponding `env::read_slice` can be used within the guest to read the
-    /// data.
+    /// This function writes a slice directly to the underlying buffer, in the
+    /// host's own byte order. On a big-endian host, the guest would see the
+    /// bytes of each element of more than one byte reversed.
+    #[deprecated(
+        note = "use `write` for typed input, or `write_words` or `write_bytes`, \
+        which do not depend on the byte order of the host, or `input_buffer` \
+        for a large input, which is not copied"
+    )]
+    pub fn write_slice<T: Pod>(&mut self, slice: &[T]) -> &mut Self {
+        self.write_bytes(bytemuck::cast_slice(slice))
+    }
+
+    /// Write words to the zkVM guest stdin, in little-endian byte order.
+    ///
+    /// A corresponding `env::read_slice` of `u32`s can be used within the
+    /// guest to read the words, which are the same whatever the byte order of
+    /// the host.
     ///
     /// # Example
     ///
     /// ```
     /// use risc0_zkvm::ExecutorEnv;
     ///
-    /// let slice1 = [0, 1, 2, 3];
-    /// let slice2 = [3, 2, 1, 0];
     /// let env = ExecutorEnv::builder()
-    ///     .write_slice(&slice1)
-    ///     .write_slice(&slice2)
+    ///     .write_words(&[0, 1, 2, 3])
+    ///     .write_words(&[3, 2, 1, 0])
     ///     .build()
     ///     .unwrap();
     /// ```
-    pub fn write_slice<T: Pod>(&mut self, slice: &[T]) -> &mut Self {
+    pub fn write_words(&mut self, words: &[u32]) -> &mut Self {
+        let input = &mut self.input;
+        input.reserve(words.len() * WORD_SIZE);
+        for word in words {
+            input.extend_from_slice(&word.to_le_bytes());
+        }
+        self
+    }
+
+    /// Write bytes to the zkVM guest stdin, as they are.
+    ///
+    /// A corresponding `env::read_slice` of `u8`s can be used within the guest
+    /// to read the bytes.
+    ///
+    /// The bytes are copied once, into the input that
+    /// [ExecutorEnvBuilder::build] hands to the guest. A large input that is
+    /// the only one can instead be passed with
+    /// [ExecutorEnvBuilder::input_buffer], which does not copy it.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use risc0_zkvm::ExecutorEnv;
+    ///
+    /// let env = ExecutorEnv::builder()
+    ///     .write_bytes(b"hello")
+    ///     .build()
+    ///     .unwrap();
+    /// ```
+    pub fn write_bytes(&mut self, bytes: &[u8]) -> &mut Self {
+        self.input.extend_from_slice(bytes);
+        self
+    }
+
+    /// Use a shared buffer as the zkVM guest stdin.
+    ///
+    /// Unlike [ExecutorEnvBuilder::write_bytes], the buffer is handed to the
+    /// guest as-is, without being copied into an intermediate buffer. This is
+    /// useful for large inputs. It cannot be combined with
+    /// [ExecutorEnvBuilder::write] or the other `write_*` methods;
+    /// [ExecutorEnvBuilder::build] returns an error if both are used.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use std::sync::Arc;
+    ///
+    /// use risc0_zkvm::ExecutorEnv;
+    ///
+    /// let input: Arc<[u8]> = vec![0u8; 1024].into();
+    /// let env = ExecutorEnv::builder()
+    ///     .input_buffer(input.clone())
+    ///     .build()
+    ///     .unwrap();
+    /// ```
+    pub fn input_buffer(&mut self, buffer: impl Into<Arc<[u8]>>) -> &mut Self {
+        self.inner.input_buffer = Some(buffer.into());
+        self
+    }
+
+    /// Enable or disable the ISA audit mode.
+    ///
+    /// In audit mode, the executor checks the result of each instruction
+    /// against an independent reference implementation of the rv32im
+    /// semantics, and fails with a description of the first divergence. This
+    /// roughly doubles the cost of execution, so it is off by default.
+    pub fn isa_audit(&mut self, enable: bool) -> &mut Self {
+        self.inner.isa_audit = enable;
+        self
+    }
+
+    /// Enable or disable exec acceleration.
+    ///
+    /// With acceleration, the executor runs the copy and fill loops of the
+    /// guest's `memcpy` and `memset` several iterations at a time, which makes
+    /// guests that move a lot of memory much faster to execute. The session is
+    /// identical either way, down to the cycle counts and the memory images of
+    /// every segment, and the proving of segments is unaffected.
+    ///
+    /// Acceleration needs the symbols of the guest, so it only applies to
+    /// executors constructed with [ExecutorImpl::from_elf](crate::ExecutorImpl::from_elf),
+    /// and it is ignored when a [TraceCallback] is installed or
+    /// [ExecutorEnvBuilder::isa_audit] is enabled, since those see every
+    /// instruction. It is off by default.
+    pub fn exec_acceleration(&mut self, enable: bool) -> &mut Self {
+        self.inner.exec_acceleration = enable;
+        self
+    }
+
+    /// Enable or disable derived randomness.
+    ///
+    /// The random bytes the guest asks for, e.g. through `getrandom`, then
+    /// come from a deterministic stream seeded by the image ID and the digest
+    /// of the input, so that two runs of the same guest with the same input
+    /// see the same bytes, and runs with different inputs see different ones.
+    /// This makes executions that use randomness reproducible, e.g. in tests.
+    ///
+    /// The bytes are NOT secret: anyone who knows the guest and its input can
+    /// compute them, including the host. This also applies to the entropy the
+    /// guest mixes into its memory image to hide the post-state digest. The
+    /// input must be digestible, so that the stream is bound to it; the
+    /// executor fails to start if the guest can read from a custom reader or
+    /// a slice I/O handler. It is off by default.
+    pub fn derived_randomness(&mut self, enable: bool) -> &mut Self {
+        self.inner.derived_randomness = enable;
+        self
+    }
+
+    /// Enable or disable the `SYS_SHA256` syscall, with which the guest has
+    /// the host compute SHA-256 hashes; see
+    /// [guest::sha_accel](crate::guest::sha_accel).
+    ///
+    /// The digests are asserted by the host: the receipt does not show that
+    /// they are correct, unless the guest checks them itself with
+    /// [HostSha256::verify_one_in](crate::guest::sha_accel::HostSha256::verify_one_in).
+    /// It is off by default, so a guest that uses it fails.
+    pub fn sha_accel(&mut self, enable: bool) -> &mut Self {
+        self.inner.sha_accel = enable;
+        self
+    }
+
+    /// Enable or disable the `SYS_BIGINT_MODMUL` syscall, with which the guest
+    /// has the host reduce products of big integers; see
+    /// [guest::bigint](crate::guest::bigint).
+    ///
+    /// The guest checks every result it receives, so a host that returns a
+    /// wrong one makes the guest panic rather than prove a wrong result. It
+    /// is off by default, so a guest that uses it fails.
+    pub fn bigint_accel(&mut self, enable: bool) -> &mut Self {
+        self.inner.bigint_accel = enable;
+        self
+    }
+
+    /// Expect the guest to commit a hashed journal with
+    /// `env::hashed_journal`, and keep the items it commits.
+    ///
+    /// The journal is then the 32-byte hash chain of the items, which are
+    /// available from `Session::journal_preimages` after execution, and from
+    /// [Prover::journal_preimages](crate::Prover::journal_preimages) after
+    /// proving with `LocalProver`; see [hashed_journal](crate::hashed_journal).
+    /// Execution fails if the journal is not the hash chain of the items the
+    /// guest committed, for example because the guest did not switch to a
+    /// hashed journal. Receipts are never taken from a
+    /// [ProofCache](crate::ProofCache), which does not keep the items. It is
+    /// off by default.
+    pub fn hashed_journal(&mut self, enable: bool) -> &mut Self {
+        self.inner.hashed_journal = enable;
+        self
+    }
+
+    /// Set what the executor does when the guest reports a failed check with
+    /// `env::check`; see [CheckPolicy]. By default failed checks are recorded
+    /// and execution continues.
+    pub fn check_policy(&mut self, policy: CheckPolicy) -> &mut Self {
+        self.inner.check_policy = policy;
+        self
+    }
+
+    /// Enable or disable the integrity check of images passed to
+    /// [ExecutorImpl::new](crate::ExecutorImpl::new).
+    ///
+    /// The check runs [MemoryImage::verify_integrity](crate::MemoryImage::verify_integrity)
+    /// so that an image that was corrupted on disk is refused up front, rather
+    /// than producing a receipt for the wrong image ID. It is on by default;
+    /// callers that already trust the image can turn it off to save the cost
+    /// of hashing every page.
+    pub fn image_integrity_check(&mut self, enable: bool) -> &mut Self {
+        self.inner.skip_image_integrity_check = !enable;
+        self
+    }
+
+    /// Allow reads that run past the end of an input to return fewer bytes
+    /// than requested.
+    ///
+    /// By default, a guest read that asks for more data than the input has
+    /// left fails execution with `GuestIoError::UnexpectedEof`. When enabled,
+    /// the read instead returns the bytes that were available and zero-fills
+    /// the rest of the guest's buffer, for guests that handle short reads
+    /// themselves.
+    pub fn short_reads(&mut self, enable: bool) -> &mut Self {
+        self.inner.posix_io.borrow_mut().short_reads = enable;
+        self
+    }
+
+    /// Limit how much the guest can log with
+    /// [env::log](crate::guest::env::log), so that a guest logging in a
+    /// tight loop cannot flood the host.
+    ///
+    /// Once the guest has logged `max_messages` messages, or a message would
+    /// take the total past `max_total_bytes` bytes, further messages are
+    /// handled as `on_exceed` says. A panic message is never limited. There
+    /// are no limits by default.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use risc0_zkvm::{ExecutorEnv, LogPolicy};
+    ///
+    /// let env = ExecutorEnv::builder()
+    ///     .log_limits(1000, 1 << 20, LogPolicy::Drop)
+    ///     .build()
+    ///     .unwrap();
+    /// ```
+    pub fn log_limits(
+        &mut self,
+        max_messages: u64,
+        max_total_bytes: u64,
+        on_exceed: LogPolicy,
+    ) -> &mut Self {
+        self.inner.posix_io.borrow_mut().log_limits = Some(LogLimits {
+            max_messages,
+            max_total_bytes,
+            on_exceed,
+        });
+        self
+    }
+
+    /// Give the expected size, in bytes, of the journal and of the guest's
+    /// output to standard output.
+    ///
+    /// The journal's buffer is allocated at `journal` bytes up front rather
+    /// than grown as the guest commits, which avoids repeated reallocation
+    /// and copying for large journals. Wrong hints only cost memory, unless
+    /// `strict` is set: a guest that writes more than a hint to either
+    /// descriptor then fails with
+    /// [GuestIoError::OutputSizeExceeded](crate::GuestIoError::OutputSizeExceeded),
+    /// which bounds its output. The writer of standard output belongs to the
+    /// caller, so its hint is only used as such a bound.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use risc0_zkvm::ExecutorEnv;
+    ///
+    /// let env = ExecutorEnv::builder()
+    ///     .output_size_hints(1 << 20, 0, true)
+    ///     .build()
+    ///     .unwrap();
+    /// ```
+    pub fn output_size_hints(&mut self, journal: usize, stdout: usize, strict: bool) -> &mut Self {
+        self.inner.posix_io.borrow_mut().output_size_hints = Some(OutputSizeHints {
+            journal,
+            stdout,
+            strict,
+        });
+        self
+    }
+
+    /// Stop execution with [GuestIoError::Stalled](crate::GuestIoError::Stalled)
+    /// once the guest has polled the same file descriptor with
+    /// `SYS_READ_AVAIL` and found it empty more than `polls` times in a row,
+    /// or never with `None`.
+    ///
+    /// A guest waiting in such a loop for input that will never arrive would
+    /// otherwise spin until the session limit. Reading, writing, or finding
+    /// data on any descriptor starts the count again. The default is 1000
+    /// polls.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use risc0_zkvm::ExecutorEnv;
+    ///
+    /// let env = ExecutorEnv::builder()
+    ///     .stall_threshold(Some(100))
+    ///     .build()
+    ///     .unwrap();
+    /// ```
+    pub fn stall_threshold(&mut self, polls: Option<u32>) -> &mut Self {
+        self.inner.posix_io.borrow_mut().stall_threshold = polls;
+        self
+    }
+
+    /// Set how the guest's writes wait for a writer that is not ready.
+    ///
+    /// A writer registered with [ExecutorEnvBuilder::write_fd], such as a
+    /// non-blocking socket, may return [std::io::ErrorKind::WouldBlock]. The
+    /// write is then retried after a wait that doubles from
+    /// [WriteBackoff::initial] up to [WriteBackoff::max], and the execution
+    /// fails with
+    /// [GuestIoError::WriteTimedOut](crate::GuestIoError::WriteTimedOut) if
+    /// it has not completed by [WriteBackoff::deadline]. Short writes are
+    /// continued until the writer has accepted all of the guest's buffer. The
+    /// default waits from 1 ms up to 100 ms between retries, for up to 30
+    /// seconds.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use std::time::Duration;
+    ///
+    /// use risc0_zkvm::{ExecutorEnv, WriteBackoff};
+    ///
+    /// let env = ExecutorEnv::builder()
+    ///     .write_backoff(WriteBackoff {
+    ///         initial: Duration::from_micros(100),
+    ///         max: Duration::from_millis(10),
+    ///         deadline: Duration::from_secs(5),
+    ///     })
+    ///     .build()
+    ///     .unwrap();
+    /// ```
+    pub fn write_backoff(&mut self, backoff: WriteBackoff) -> &mut Self {
+        self.inner.posix_io.borrow_mut().write_backoff = backoff;
+        self
+    }
+
+    /// Give the guest a stack of `bytes` bytes at the top of guest memory,
+    /// for guests that need more than the default of about 2 MB.
+    ///
+    /// The heap then ends where the stack begins. This changes the image ID of
+    /// the guest; see [GuestLayout] for what that means for verification.
+    /// Loading the ELF fails if `bytes` is not a multiple of 16, or if the
+    /// stack would overlap the program.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use risc0_zkvm::ExecutorEnv;
+    ///
+    /// let env = ExecutorEnv::builder()
+    ///     .stack_size(16 * 1024 * 1024)
+    ///     .build()
+    ///     .unwrap();
+    /// ```
+    pub fn stack_size(&mut self, bytes: u32) -> &mut Self {
+        self.inner.layout.stack_size = Some(bytes);
+        self
+    }
+
+    /// Start the guest's heap at `addr` rather than at the end of the program.
+    ///
+    /// This changes the image ID of the guest; see [GuestLayout] for what that
+    /// means for verification. Loading the ELF fails if `addr` is not word
+    /// aligned, overlaps the program, or is not below the stack set by
+    /// [ExecutorEnvBuilder::stack_size].
+    pub fn heap_base(&mut self, addr: u32) -> &mut Self {
+        self.inner.layout.heap_base = Some(addr);
+        self
+    }
+
+    /// Start execution at `entry`, a symbol of the ELF or the address of one
+    /// of its instructions, rather than at `_start`, e.g. to test a single
+    /// function of the guest.
+    ///
+    /// This skips the initialization of the guest's runtime and changes the
+    /// image ID; see [GuestLayout].
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use risc0_zkvm::ExecutorEnv;
+    /// use risc0_zkvm_platform::syscall::reg_abi::{REG_A0, REG_A1};
+    ///
+    /// let env = ExecutorEnv::builder()
+    ///     .entry("my_function")
+    ///     .initial_registers(&[(REG_A0, 6), (REG_A1, 7)])
+    ///     .build()
+    ///     .unwrap();
+    /// ```
+    pub fn entry(&mut self, entry: impl Into<EntryPoint>) -> &mut Self {
+        self.inner.layout.entry = Some(entry.into());
+        self
+    }
+
+    /// Set the values of registers, by index, when execution starts, e.g. the
+    /// arguments of the function set with [ExecutorEnvBuilder::entry].
+    ///
+    /// Like the other settings of [GuestLayout], these apply when an ELF is
+    /// loaded and are part of the image ID.
+    pub fn initial_registers(&mut self, registers: &[(usize, u32)]) -> &mut Self {
         self.inner
-            .input
-            .extend_from_slice(bytemuck::cast_slice(slice));
+            .layout
+            .registers
+            .extend(registers.iter().copied());
         self
     }
 
+    /// Set up the environment recorded in `manifest` by
+    /// [ExecutorEnv::manifest].
+    ///
+    /// `resolve` is called with the name of each named input in the manifest
+    /// and returns its reader. The handlers of the slice I/O channels in the
+    /// manifest must already be registered with
+    /// [ExecutorEnvBuilder::slice_io]. Returns an error listing every named
+    /// input and channel that could not be resolved, in which case the
+    /// builder is left unchanged.
+    pub fn apply_manifest(
+        &mut self,
+        manifest: &GuestEnvManifest,
+        mut resolve: impl FnMut(&str) -> Option<Box<dyn Read + 'a>>,
+    ) -> Result<&mut Self> {
+        let mut inputs = Vec::new();
+        let mut unresolved = Vec::new();
+        for name in manifest.named_inputs.iter() {
+            match resolve(name) {
+                Some(reader) => inputs.push((name, reader)),
+                None => unresolved.push(format!("named input {name:?}")),
+            }
+        }
+        let slice_io = self.inner.slice_io.borrow();
+        for name in manifest.slice_io.iter() {
+            if !slice_io.inner.contains_key(name) {
+                unresolved.push(format!("slice I/O channel {name:?}"));
+            }
+        }
+        drop(slice_io);
+        if !unresolved.is_empty() {
+            bail!("unresolved manifest entries: {}", unresolved.join(", "));
+        }
+
+        self.extend_env_vars(manifest.env_vars.clone())
+            .args(&manifest.args)
+            .write_bytes(&manifest.input);
+        for (name, reader) in inputs {
+            self.named_input(name, reader);
+        }
+        if let Some(limit) = manifest.segment_limit_po2 {
+            self.segment_limit_po2(limit);
+        }
+        self.session_limit(manifest.session_limit);
+        if let Some(nonce) = manifest.nonce {
+            self.nonce(nonce);
+        }
+        Ok(self)
+    }
+
     /// Add a posix-style standard input.
+    ///
+    /// The guest reads the bytes of `reader` unchanged, on every host: line
+    /// endings are not translated, so a [File](std::fs::File) can be passed
+    /// as it is on Windows too.
     pub fn stdin(&mut self, reader: impl Read + 'a) -> &mut Self {
         self.read_fd(fileno::STDIN, BufReader::new(reader))
     }
@@ -276,25 +1127,217 @@ impl<'a> ExecutorEnvBuilder<'a> {
         self.write_fd(fileno::STDOUT, writer)
     }
 
+    /// Collect the frames the guest writes to standard output with
+    /// [env::write_framed](crate::guest::env::write_framed).
+    ///
+    /// This replaces any writer set with [ExecutorEnvBuilder::stdout].
+    pub fn stdout_framed(&mut self, output: &FramedOutput) -> &mut Self {
+        self.stdout(output.clone())
+    }
+
+    /// Pass each line the guest writes to standard output to `callback`.
+    ///
+    /// Output is buffered until the end of each line and invalid UTF-8 is
+    /// replaced; see [LineWriter]. This replaces any writer set with
+    /// [ExecutorEnvBuilder::stdout].
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use risc0_zkvm::ExecutorEnv;
+    ///
+    /// let env = ExecutorEnv::builder()
+    ///     .stdout_lines(|line| println!("guest: {line}"))
+    ///     .build()
+    ///     .unwrap();
+    /// ```
+    pub fn stdout_lines(&mut self, callback: impl FnMut(&str) + 'a) -> &mut Self {
+        self.stdout(LineWriter::new(callback))
+    }
+
     /// Add a posix-style standard error.
     pub fn stderr(&mut self, writer: impl Write + 'a) -> &mut Self {
         self.write_fd(fileno::STDERR, writer)
     }
 
+    /// Pass each line the guest writes to standard error to `callback`, as
+    /// [ExecutorEnvBuilder::stdout_lines] does for standard output.
+    pub fn stderr_lines(&mut self, callback: impl FnMut(&str) + 'a) -> &mut Self {
+        self.stderr(LineWriter::new(callback))
+    }
+
     /// Add a posix-style file descriptor for reading.
+    ///
+    /// A descriptor that is also registered for writing, including
+    /// [fileno::STDOUT] and [fileno::STDERR] which are by default, makes
+    /// [ExecutorEnvBuilder::build] fail; see [ExecutorEnvBuilder::duplex_fd].
     pub fn read_fd(&mut self, fd: u32, reader: impl BufRead + 'a) -> &mut Self {
+        self.inner.opaque_input = true;
         self.inner.posix_io.borrow_mut().with_read_fd(fd, reader);
         self
     }
 
     /// Add a posix-style file descriptor for writing.
+    ///
+    /// The journal's descriptor, [fileno::JOURNAL], is reserved, since the
+    /// receipt is built from what the guest writes to it; registering it here
+    /// makes [ExecutorEnvBuilder::build] fail. Use
+    /// [ExecutorEnvBuilder::journal_tee] to receive a copy instead. As with
+    /// [ExecutorEnvBuilder::read_fd], a descriptor that is also registered
+    /// for reading makes [ExecutorEnvBuilder::build] fail.
     pub fn write_fd(&mut self, fd: u32, writer: impl Write + 'a) -> &mut Self {
         self.inner.posix_io.borrow_mut().with_write_fd(fd, writer);
         self
     }
 
+    /// Add a posix-style file descriptor for writing, as
+    /// [ExecutorEnvBuilder::write_fd] does, whose output is held in a buffer
+    /// of `capacity` bytes rather than written to `writer` as the guest
+    /// produces it.
+    ///
+    /// This keeps a slow `writer`, such as a network socket, from stalling the
+    /// executor on every write. The buffer is drained into `writer` when the
+    /// run ends, whether or not it succeeds, and when the guest writes more
+    /// than it can hold under [OverflowPolicy::Block]. Under
+    /// [OverflowPolicy::Error], such a write fails the run with
+    /// [GuestIoError::BackpressureExceeded](crate::GuestIoError::BackpressureExceeded),
+    /// and under [OverflowPolicy::DropOldest] the oldest output is discarded
+    /// and a warning logged. The journal's descriptor is reserved, as for
+    /// [ExecutorEnvBuilder::write_fd].
+    pub fn write_fd_buffered(
+        &mut self,
+        fd: u32,
+        writer: impl Write + 'a,
+        capacity: usize,
+        policy: OverflowPolicy,
+    ) -> &mut Self {
+        self.write_fd(fd, BufferedWriter::new(fd, writer, capacity, policy))
+    }
+
+    /// Add a posix-style file descriptor that the guest both reads from and
+    /// writes to, e.g. for a bidirectional channel with the host.
+    ///
+    /// Reads on `fd` go to `reader` and writes go to `writer`. This is the
+    /// only way to register a descriptor in both directions.
+    pub fn duplex_fd(
+        &mut self,
+        fd: u32,
+        reader: impl BufRead + 'a,
+        writer: impl Write + 'a,
+    ) -> &mut Self {
+        self.inner.opaque_input = true;
+        self.inner
+            .posix_io
+            .borrow_mut()
+            .with_duplex_fd(fd, reader, writer);
+        self
+    }
+
+    /// Add a posix-style file descriptor for reading, whose reader is made
+    /// by `factory` just before the guest runs.
+    ///
+    /// This is for readers that depend on the guest, e.g. on its image ID,
+    /// which is not known until the prover loads it; see [LazyFdContext].
+    /// The reader replaces any added with [ExecutorEnvBuilder::read_fd] for
+    /// the same descriptor. If `factory` returns an error or panics, the run
+    /// fails with that error.
+    pub fn lazy_read_fd(
+        &mut self,
+        fd: u32,
+        factory: impl FnOnce(&LazyFdContext<'_, 'a>) -> Result<Box<dyn BufRead + 'a>> + 'a,
+    ) -> &mut Self {
+        self.inner.opaque_input = true;
+        self.inner
+            .lazy_fds
+            .borrow_mut()
+            .push((fd, LazyFd::Read(Box::new(factory))));
+        self
+    }
+
+    /// Add a posix-style file descriptor for writing, whose writer is made by
+    /// `factory` just before the guest runs, as
+    /// [ExecutorEnvBuilder::lazy_read_fd] does for reading.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use std::fs::File;
+    ///
+    /// use risc0_zkvm::ExecutorEnv;
+    ///
+    /// let dir = std::env::temp_dir();
+    /// let env = ExecutorEnv::builder()
+    ///     .lazy_write_fd(3, move |ctx| {
+    ///         let path = dir.join(format!("{}.log", ctx.image_id()));
+    ///         Ok(Box::new(File::create(path)?))
+    ///     })
+    ///     .build()
+    ///     .unwrap();
+    /// ```
+    pub fn lazy_write_fd(
+        &mut self,
+        fd: u32,
+        factory: impl FnOnce(&LazyFdContext<'_, 'a>) -> Result<Box<dyn Write + 'a>> + 'a,
+    ) -> &mut Self {
+        self.inner
+            .lazy_fds
+            .borrow_mut()
+            .push((fd, LazyFd::Write(Box::new(factory))));
+        self
+    }
+
+    /// Add an input that the guest opens by name with
+    /// [env::open_named](crate::guest::env::open_named).
+    ///
+    /// Each named input is read from a file descriptor of its own, so the
+    /// guest can read several inputs independently and in any order. The
+    /// descriptors are assigned in the order the inputs are added, starting
+    /// well above those used by [ExecutorEnvBuilder::stdin] and friends. Adding
+    /// two inputs with the same name makes [ExecutorEnvBuilder::build] fail.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use risc0_zkvm::ExecutorEnv;
+    ///
+    /// # let config: &[u8] = &[];
+    /// # let witness: &[u8] = &[];
+    /// let env = ExecutorEnv::builder()
+    ///     .named_input("config", config)
+    ///     .named_input("witness", witness)
+    ///     .build()
+    ///     .unwrap();
+    /// ```
+    pub fn named_input(&mut self, name: &str, reader: impl Read + 'a) -> &mut Self {
+        let fd = FIRST_NAMED_INPUT_FD + self.inner.named_inputs.len() as u32;
+        self.inner.named_inputs.push((name.to_string(), fd));
+        self.read_fd(fd, BufReader::new(reader))
+    }
+
+    /// Allow a handler to be registered for the built-in `syscall`, such as
+    /// [SYS_READ](risc0_zkvm_platform::syscall::nr::SYS_READ), in place of
+    /// the executor's own.
+    ///
+    /// Without this, [ExecutorEnvBuilder::build] fails if a handler is
+    /// registered under the name of a syscall in
+    /// `risc0_zkvm_platform::syscall::nr`, so that a name collision cannot
+    /// silently change how the guest runs.
+    pub fn override_builtin<C: AsRef<str>>(&mut self, syscall: C) -> &mut Self {
+        self.inner
+            .slice_io
+            .borrow_mut()
+            .overrides
+            .insert(syscall.as_ref().to_string());
+        self
+    }
+
     /// Add a handler for simple I/O handling.
+    ///
+    /// [ExecutorEnvBuilder::build] fails if more than one handler is added for
+    /// `channel`, or if `channel` is a built-in syscall that was not passed
+    /// to [ExecutorEnvBuilder::override_builtin].
     pub fn slice_io(&mut self, channel: &str, handler: impl SliceIo + 'a) -> &mut Self {
+        self.inner.opaque_input = true;
         self.inner
             .slice_io
             .borrow_mut()
@@ -308,6 +1351,7 @@ impl<'a> ExecutorEnvBuilder<'a> {
         channel: C,
         callback: impl Fn(Bytes) -> Result<Bytes> + 'a,
     ) -> &mut Self {
+        self.inner.opaque_input = true;
         self.inner
             .slice_io
             .borrow_mut()
@@ -315,6 +1359,95 @@ impl<'a> ExecutorEnvBuilder<'a> {
         self
     }
 
+    /// Add a handler for simple I/O handling that writes its response
+    /// straight into guest memory; see [SliceIoFill].
+    pub fn slice_io_fill<C: AsRef<str>>(
+        &mut self,
+        channel: C,
+        handler: impl SliceIoFill + 'a,
+    ) -> &mut Self {
+        self.inner.opaque_input = true;
+        self.inner
+            .slice_io
+            .borrow_mut()
+            .with_fill_handler(channel.as_ref(), handler);
+        self
+    }
+
+    /// Add a handler for simple I/O handling that may return data it
+    /// borrows, which is copied once, straight into guest memory.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use std::borrow::Cow;
+    ///
+    /// use risc0_zkvm::ExecutorEnv;
+    ///
+    /// let chunk = vec![0u8; 8 << 20];
+    /// let env = ExecutorEnv::builder()
+    ///     .io_callback_borrowed("chunk", |_| Ok(Cow::Borrowed(&chunk)))
+    ///     .build()
+    ///     .unwrap();
+    /// ```
+    pub fn io_callback_borrowed<C: AsRef<str>>(
+        &mut self,
+        channel: C,
+        callback: impl Fn(Bytes) -> Result<Cow<'a, [u8]>> + 'a,
+    ) -> &mut Self {
+        self.slice_io_fill(channel, slice_io_from_cow_fn(callback))
+    }
+
+    /// Add a handler for simple I/O handling that can be run on a helper
+    /// thread, so that it can be given a [SyscallGuard::timeout].
+    pub fn io_callback_send<C: AsRef<str>>(
+        &mut self,
+        channel: C,
+        callback: impl Fn(Bytes) -> Result<Bytes> + Send + Sync + 'static,
+    ) -> &mut Self {
+        self.inner.opaque_input = true;
+        self.inner
+            .slice_io
+            .borrow_mut()
+            .with_threaded_handler(channel.as_ref(), Arc::new(callback));
+        self
+    }
+
+    /// Limit how long the I/O handler for `channel` may take and how much it
+    /// may return; see [SyscallGuard].
+    ///
+    /// Guards are enforced by the local executor. [ExecutorEnvBuilder::build]
+    /// fails if `channel` has no handler, or if the guard has a timeout and
+    /// the handler was not added with [ExecutorEnvBuilder::io_callback_send].
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use std::time::Duration;
+    ///
+    /// use risc0_zkvm::{ExecutorEnv, SyscallGuard};
+    ///
+    /// let env = ExecutorEnv::builder()
+    ///     .io_callback_send("oracle", |from_guest| Ok(from_guest))
+    ///     .syscall_guard(
+    ///         "oracle",
+    ///         SyscallGuard {
+    ///             timeout: Some(Duration::from_secs(1)),
+    ///             max_response_bytes: Some(1 << 20),
+    ///         },
+    ///     )
+    ///     .build()
+    ///     .unwrap();
+    /// ```
+    pub fn syscall_guard<C: AsRef<str>>(&mut self, channel: C, guard: SyscallGuard) -> &mut Self {
+        self.inner
+            .slice_io
+            .borrow_mut()
+            .guards
+            .insert(channel.as_ref().to_string(), guard);
+        self
+    }
+
     /// Add an [Assumption] to the [ExecutorEnv] associated assumptions.
     ///
     /// During execution, when the guest calls `env::verify` or
@@ -325,12 +1458,147 @@ impl<'a> ExecutorEnvBuilder<'a> {
         self
     }
 
+    /// Register guests that the guest being executed may run with
+    /// `env::exec`, keyed by name.
+    ///
+    /// Each call runs the named ELF to completion in the same host process,
+    /// with the bytes passed by the caller as its stdin, and returns its image
+    /// ID and journal to the caller. Nested guests may in turn run registered
+    /// guests, up to [ExecutorEnvBuilder::nested_depth_limit] levels deep.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use risc0_zkvm::ExecutorEnv;
+    ///
+    /// # let child_elf: Vec<u8> = Vec::new();
+    /// let env = ExecutorEnv::builder()
+    ///     .guest_registry([("child".to_string(), child_elf)])
+    ///     .nested_session_limit(Some(1 << 20))
+    ///     .build()
+    ///     .unwrap();
+    /// ```
+    pub fn guest_registry(
+        &mut self,
+        guests: impl IntoIterator<Item = (String, Vec<u8>)>,
+    ) -> &mut Self {
+        let guests = guests.into_iter().map(|(name, elf)| (name, elf.into()));
+        self.inner.guest_registry.guests.extend(guests);
+        self
+    }
+
+    /// Set how deeply guests run with `env::exec` may be nested.
+    ///
+    /// A limit of 1 allows the guest to run registered guests, but not those
+    /// guests to run any further ones. Defaults to 4.
+    pub fn nested_depth_limit(&mut self, limit: u32) -> &mut Self {
+        self.inner.guest_registry.depth_limit = limit;
+        self
+    }
+
+    /// Set a session limit, specified in number of cycles, for each guest run
+    /// with `env::exec`.
+    pub fn nested_session_limit(&mut self, limit: Option<u64>) -> &mut Self {
+        self.inner.guest_registry.session_limit = limit;
+        self
+    }
+
+    /// Prove the guests run with `env::exec`, rather than only executing them.
+    ///
+    /// Each receipt is added as an assumption, so that the calling guest can
+    /// check it with `env::verify`. [crate::LocalProver] proves the nested
+    /// guests with the hash function and options it proves the calling guest
+    /// with; otherwise the defaults of [crate::ProveOpts] are used.
+    pub fn prove_nested(&mut self, enable: bool) -> &mut Self {
+        self.inner.guest_registry.prove = enable;
+        self
+    }
+
     /// Add a callback handler for raw trace messages.
     pub fn trace_callback(&mut self, callback: impl TraceCallback + 'a) -> &mut Self {
         self.inner.trace.push(Rc::new(RefCell::new(callback)));
         self
     }
 
+    /// Write a trace of the session to the file at `path`, in the compact
+    /// binary format read by [TraceReader](crate::TraceReader).
+    ///
+    /// This is much faster and smaller than recording the events passed to a
+    /// [ExecutorEnvBuilder::trace_callback]. The file is created when the
+    /// executor starts, and is complete once the executor is dropped. Only
+    /// the local executor writes trace files.
+    pub fn trace_file<P: AsRef<Path>>(&mut self, path: P) -> &mut Self {
+        self.inner.trace_file = Some(path.as_ref().to_path_buf());
+        self
+    }
+
+    /// Add a callback that receives the data committed to the journal by the
+    /// guest, as it is committed.
+    ///
+    /// The callback is invoked on every write to the journal during
+    /// execution, in commit order. The full journal remains available from
+    /// the resulting [crate::Session] or [crate::Receipt].
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use risc0_zkvm::ExecutorEnv;
+    ///
+    /// let env = ExecutorEnv::builder()
+    ///     .journal_callback(|bytes| println!("committed {} bytes", bytes.len()))
+    ///     .build()
+    ///     .unwrap();
+    /// ```
+    pub fn journal_callback(&mut self, callback: impl FnMut(&[u8]) + 'a) -> &mut Self {
+        self.inner
+            .journal_callbacks
+            .push(Rc::new(RefCell::new(callback)));
+        self
+    }
+
+    /// Copy the data committed to the journal by the guest into `writer`, as
+    /// it is committed.
+    ///
+    /// This is [ExecutorEnvBuilder::journal_callback] for a [Write], e.g. a
+    /// file or a hasher. An error from `writer` fails the execution. The full
+    /// journal remains available from the resulting [crate::Session] or
+    /// [crate::Receipt].
+    pub fn journal_tee(&mut self, writer: impl Write + 'a) -> &mut Self {
+        self.inner.journal_tees.push(Rc::new(RefCell::new(writer)));
+        self
+    }
+
+    /// Write a log of every syscall the host answers for the guest to
+    /// `writer`, e.g. for an audit trail.
+    ///
+    /// Each syscall is written as one line of JSON, in the order they are
+    /// made, with the fields:
+    /// * `cycle`: the number of user cycles the session had executed when the
+    ///   syscall was made, counting the `cycles` of each segment before it.
+    /// * `syscall`: its name, e.g. `risc0_zkvm_platform::syscall::nr::SYS_READ`.
+    /// * `from_guest` and `to_guest`: the number of `bytes` of guest memory
+    ///   read by the host, or returned to the guest, and their `sha256`.
+    ///
+    /// Only digests of the data are logged, so the size of the log does not
+    /// depend on how much data the guest exchanges. The log is flushed when
+    /// the run ends, even if it fails, and an error writing it fails the run.
+    /// It is only written by an executor in this process.
+    pub fn syscall_audit(&mut self, writer: impl Write + 'a) -> &mut Self {
+        self.inner.syscall_audit = Some(Rc::new(RefCell::new(writer)));
+        self
+    }
+
+    /// Allow the I/O handlers holding a clone of `commits` to commit data to
+    /// the journal with [HostCommits::commit].
+    ///
+    /// This changes what a receipt of the guest can claim, so it must be
+    /// allowed explicitly; see [HostCommits] for the trust model.
+    pub fn host_commits(&mut self, commits: &HostCommits) -> &mut Self {
+        commits.allow();
+        self.inner.host_commits = commits.clone();
+        self
+    }
+
     /// Set the path where segments will be stored.
     pub fn segment_path<P: AsRef<Path>>(&mut self, path: P) -> &mut Self {
         self.inner.segment_path = Some(path.as_ref().to_path_buf());
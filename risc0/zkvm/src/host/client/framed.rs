@@ -0,0 +1,147 @@
+// Copyright 2023 RISC Zero, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Decoding of the length-prefixed frames written by
+//! [env::write_framed](crate::guest::env::write_framed).
+
+use std::{cell::RefCell, io::Write, rc::Rc};
+
+use anyhow::{anyhow, bail, ensure, Result};
+use serde::de::DeserializeOwned;
+
+use crate::serde::from_slice;
+
+/// Collects the output a guest writes with
+/// [env::write_framed](crate::guest::env::write_framed).
+///
+/// Pass it to [ExecutorEnvBuilder::stdout_framed](crate::ExecutorEnvBuilder::stdout_framed)
+/// and, once the guest has run, decode the frames into typed slots with
+/// [FramedOutput::decode].
+///
+/// # Example
+///
+/// ```no_run
+/// use risc0_zkvm::{default_executor, ExecutorEnv, FramedOutput};
+///
+/// # let elf: &[u8] = &[];
+/// // In the guest:
+/// //   env::write_framed(&count);
+/// //   env::write_framed(&names);
+/// let output = FramedOutput::new();
+/// let env = ExecutorEnv::builder().stdout_framed(&output).build().unwrap();
+/// default_executor().execute_elf(env, elf).unwrap();
+/// let (count, names): (u32, Vec<String>) = output.decode().unwrap();
+/// ```
+#[derive(Clone, Default)]
+pub struct FramedOutput {
+    buf: Rc<RefCell<Vec<u8>>>,
+}
+
+impl FramedOutput {
+    /// Construct an empty [FramedOutput].
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Split the output collected so far into frames.
+    ///
+    /// Returns an error if the output ends part way through a frame.
+    pub fn frames(&self) -> Result<Vec<Vec<u32>>> {
+        let buf = self.buf.borrow();
+        ensure!(
+            buf.len() % 4 == 0,
+            "Framed output is not a whole number of words: {} bytes",
+            buf.len()
+        );
+        let mut words = buf
+            .chunks_exact(4)
+            .map(|x| u32::from_le_bytes(x.try_into().unwrap()));
+        let mut frames = Vec::new();
+        while let Some(len) = words.next() {
+            let frame: Vec<u32> = words.by_ref().take(len as usize).collect();
+            ensure!(
+                frame.len() == len as usize,
+                "Framed output ends part way through frame {}",
+                frames.len()
+            );
+            frames.push(frame);
+        }
+        Ok(frames)
+    }
+
+    /// Decode the frames into `T`, which is either a tuple with one slot per
+    /// frame or a [Vec] holding any number of frames of the same type.
+    ///
+    /// Returns an error if the guest wrote more or fewer frames than `T` has
+    /// slots, or if a frame does not decode as the type of its slot.
+    pub fn decode<T: FromFrames>(&self) -> Result<T> {
+        T::from_frames(self.frames()?)
+    }
+}
+
+impl Write for FramedOutput {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.buf.borrow_mut().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+/// A set of typed slots that [FramedOutput::decode] fills in order, one frame
+/// per slot.
+pub trait FromFrames: Sized {
+    /// Decode `frames` into the slots.
+    fn from_frames(frames: Vec<Vec<u32>>) -> Result<Self>;
+}
+
+fn decode_frame<T: DeserializeOwned>(idx: usize, frame: &[u32]) -> Result<T> {
+    from_slice(frame).map_err(|err| anyhow!("Failed to decode frame {idx}: {err}"))
+}
+
+impl<T: DeserializeOwned> FromFrames for Vec<T> {
+    fn from_frames(frames: Vec<Vec<u32>>) -> Result<Self> {
+        frames
+            .iter()
+            .enumerate()
+            .map(|(idx, frame)| decode_frame(idx, frame))
+            .collect()
+    }
+}
+
+macro_rules! impl_from_frames {
+    ($len:literal; $($idx:tt: $ty:ident),+) => {
+        impl<$($ty: DeserializeOwned),+> FromFrames for ($($ty,)+) {
+            fn from_frames(frames: Vec<Vec<u32>>) -> Result<Self> {
+                if frames.len() != $len {
+                    bail!(
+                        "Guest wrote {} frames but {} were expected",
+                        frames.len(),
+                        $len
+                    );
+                }
+                Ok(($(decode_frame::<$ty>($idx, &frames[$idx])?,)+))
+            }
+        }
+    };
+}
+
+impl_from_frames!(1; 0: A);
+impl_from_frames!(2; 0: A, 1: B);
+impl_from_frames!(3; 0: A, 1: B, 2: C);
+impl_from_frames!(4; 0: A, 1: B, 2: C, 3: D);
+impl_from_frames!(5; 0: A, 1: B, 2: C, 3: D, 4: E);
+impl_from_frames!(6; 0: A, 1: B, 2: C, 3: D, 4: E, 5: F);
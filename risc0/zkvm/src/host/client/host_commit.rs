@@ -0,0 +1,90 @@
+// Copyright 2023 RISC Zero, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Commitments to the journal requested by host I/O handlers.
+
+use std::{cell::RefCell, mem, rc::Rc};
+
+use anyhow::{ensure, Result};
+use bytes::Bytes;
+
+use super::slice_io::SliceIo;
+
+/// A queue of bytes that host I/O handlers ask to have committed to the
+/// journal, for example the URL and the digest of the response of a fetch, so
+/// that the receipt shows which external data the guest was given.
+///
+/// Clone it into the handlers that commit, and pass it to
+/// [ExecutorEnvBuilder::host_commits](crate::ExecutorEnvBuilder::host_commits)
+/// to allow them to do so; until then, [HostCommits::commit] fails.
+///
+/// # Trust model
+///
+/// The host cannot change the journal of a receipt by itself, since the
+/// journal digest in the claim is computed by the guest. The queued bytes
+/// only reach the journal when the guest opts in by calling
+/// `env::commit_host_data`, which fetches them and commits them as one item
+/// at that point; a guest that never calls it ignores them. The bytes
+/// therefore appear in the journal where the guest chose to accept them, and
+/// a verifier sees them in the journal like any other committed data.
+///
+/// A receipt only shows that the guest committed these bytes, not that a
+/// particular handler produced them: any host running the guest can queue
+/// arbitrary bytes, so a verifier must not trust them more than the host. A
+/// guest that commits data derived from host input should commit it itself.
+///
+/// The bytes are committed as they are. A verifier decoding the journal with
+/// [crate::serde] expects whole words, so commit data that is a multiple of
+/// four bytes long, such as a digest.
+#[derive(Clone, Default)]
+pub struct HostCommits(Rc<RefCell<Queue>>);
+
+#[derive(Default)]
+struct Queue {
+    allowed: bool,
+    pending: Vec<u8>,
+}
+
+impl HostCommits {
+    /// Construct an empty [HostCommits].
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Queue `bytes` to be committed to the journal the next time the guest
+    /// calls `env::commit_host_data`.
+    ///
+    /// Returns an error unless this [HostCommits] was passed to
+    /// [ExecutorEnvBuilder::host_commits](crate::ExecutorEnvBuilder::host_commits).
+    pub fn commit(&self, bytes: &[u8]) -> Result<()> {
+        let mut queue = self.0.borrow_mut();
+        ensure!(
+            queue.allowed,
+            "Host commits are not allowed; pass the HostCommits to ExecutorEnvBuilder::host_commits"
+        );
+        queue.pending.extend_from_slice(bytes);
+        Ok(())
+    }
+
+    pub(crate) fn allow(&self) {
+        self.0.borrow_mut().allowed = true;
+    }
+}
+
+// Hands the queued bytes to the guest, which commits them.
+impl SliceIo for HostCommits {
+    fn handle_io(&mut self, _syscall: &str, _from_guest: Bytes) -> Result<Bytes> {
+        Ok(mem::take(&mut self.0.borrow_mut().pending).into())
+    }
+}
@@ -0,0 +1,271 @@
+// Copyright 2023 RISC Zero, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! The initial stack, heap and registers of a guest.
+
+use std::{collections::BTreeMap, fmt};
+
+use anyhow::{anyhow, ensure, Result};
+use risc0_binfmt::{default_image_hasher, ImageHasher, MemoryImage, Program};
+use risc0_zkvm_platform::{
+    memory::{GUEST_MAX_MEM, STACK_TOP, SYSTEM},
+    syscall::reg_abi::{REG_A0, REG_A1, REG_GP, REG_MAX, REG_SP},
+    PAGE_SIZE, WORD_SIZE,
+};
+
+use crate::sha::Digest;
+
+/// The stack pointer must stay aligned to this many bytes.
+const STACK_ALIGN: u32 = 16;
+
+/// Where the stack and heap of a guest start, and where its execution starts,
+/// when that differs from the platform's defaults.
+///
+/// By default, the stack grows down from
+/// [STACK_TOP](risc0_zkvm_platform::memory::STACK_TOP), below the program, and
+/// has about 2 MB of room. The heap grows up from the end of the program to
+/// the end of guest memory. Setting [GuestLayout::stack_size] moves the stack
+/// to the top of guest memory instead, and ends the heap where the stack
+/// begins. Setting [GuestLayout::heap_base] moves the start of the heap.
+///
+/// Set these through [ExecutorEnvBuilder::stack_size] and
+/// [ExecutorEnvBuilder::heap_base]. They apply when an ELF is loaded, and not
+/// to a [MemoryImage] passed to the executor or prover directly.
+///
+/// # Entry point
+///
+/// Setting [GuestLayout::entry], through [ExecutorEnvBuilder::entry], starts
+/// execution at a function of the guest rather than at `_start`, e.g. to test
+/// that function on its own. This skips the guest's runtime: the host points
+/// sp at the top of the stack and gp at `__global_pointer$`, as `_start`
+/// would, but nothing else is initialized, and [GuestLayout::heap_base]
+/// cannot be set. The function's arguments, and a return address in ra that
+/// halts, are set with [ExecutorEnvBuilder::initial_registers]. A guest that
+/// fails, for example by returning to address 0, fails with an error that
+/// explains what was skipped.
+///
+/// # Image ID
+///
+/// The host passes the layout to the guest in its initial registers, which
+/// are part of the memory image, along with the program counter at which
+/// execution starts. Any override therefore changes the image ID: receipts
+/// must be verified against [GuestLayout::image_id], not the ID of the ELF
+/// alone, so the prover and the verifier must agree on the layout, including
+/// the address returned by [GuestLayout::entry_point]. The guest observes the
+/// stack and heap too, for example in the addresses its allocator returns,
+/// and it must be built with a version of `risc0-zkvm` whose entry point
+/// reads these registers.
+///
+/// [ExecutorEnvBuilder::stack_size]: crate::ExecutorEnvBuilder::stack_size
+/// [ExecutorEnvBuilder::heap_base]: crate::ExecutorEnvBuilder::heap_base
+/// [ExecutorEnvBuilder::entry]: crate::ExecutorEnvBuilder::entry
+/// [ExecutorEnvBuilder::initial_registers]: crate::ExecutorEnvBuilder::initial_registers
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct GuestLayout {
+    /// The size of the stack, in bytes, placed at the top of guest memory.
+    pub stack_size: Option<u32>,
+
+    /// The address at which the heap starts.
+    pub heap_base: Option<u32>,
+
+    /// Where execution starts, instead of the ELF's entry point.
+    pub entry: Option<EntryPoint>,
+
+    /// The values of registers when execution starts, by index; see
+    /// [reg_abi](risc0_zkvm_platform::syscall::reg_abi). These take
+    /// precedence over the registers set for the other fields.
+    pub registers: BTreeMap<usize, u32>,
+}
+
+/// Where execution of a guest starts; see [GuestLayout::entry].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum EntryPoint {
+    /// A symbol of the ELF, such as a `#[no_mangle]` function.
+    Symbol(String),
+
+    /// The address of an instruction of the program.
+    Address(u32),
+}
+
+impl From<&str> for EntryPoint {
+    fn from(symbol: &str) -> Self {
+        Self::Symbol(symbol.to_string())
+    }
+}
+
+impl From<String> for EntryPoint {
+    fn from(symbol: String) -> Self {
+        Self::Symbol(symbol)
+    }
+}
+
+impl From<u32> for EntryPoint {
+    fn from(addr: u32) -> Self {
+        Self::Address(addr)
+    }
+}
+
+impl fmt::Display for EntryPoint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Symbol(symbol) => write!(f, "{symbol}"),
+            Self::Address(addr) => write!(f, "{addr:#010x}"),
+        }
+    }
+}
+
+impl GuestLayout {
+    /// Load the ELF binary `elf` into a [MemoryImage] with this layout.
+    ///
+    /// Returns an error if the stack or heap would overlap the program.
+    pub fn load_elf(&self, elf: &[u8]) -> Result<MemoryImage> {
+        self.load_elf_with_hasher(elf, default_image_hasher())
+    }
+
+    /// Load the ELF binary `elf` into a [MemoryImage] with this layout,
+    /// hashing its pages with `hasher`.
+    pub(crate) fn load_elf_with_hasher(
+        &self,
+        elf: &[u8],
+        hasher: &dyn ImageHasher,
+    ) -> Result<MemoryImage> {
+        let program = Program::load_elf(elf, GUEST_MAX_MEM as u32)?;
+        let mut image = MemoryImage::new_with_hasher(&program, PAGE_SIZE as u32, hasher)?;
+        if *self == Self::default() {
+            return Ok(image);
+        }
+
+        // The words of the program are loaded at addresses in [start, end).
+        let start = program.image.keys().min().copied().unwrap_or_default();
+        let end = program
+            .image
+            .keys()
+            .max()
+            .map_or(0, |&addr| addr + WORD_SIZE as u32);
+        let mut heap_limit = GUEST_MAX_MEM as u32;
+
+        if let Some(size) = self.stack_size {
+            ensure!(
+                size != 0 && size % STACK_ALIGN == 0,
+                "Stack size {size:#x} must be a non-zero multiple of {STACK_ALIGN} bytes"
+            );
+            let top = GUEST_MAX_MEM as u32;
+            ensure!(
+                size <= top - end,
+                "A stack of {size:#x} bytes below {top:#010x} overlaps the program, which is loaded at {start:#010x}..{end:#010x}"
+            );
+            heap_limit = top - size;
+            store_register(&mut image, REG_SP, top);
+            if self.entry.is_none() {
+                store_register(&mut image, REG_A1, heap_limit);
+            }
+        }
+
+        if let Some(base) = self.heap_base {
+            ensure!(
+                self.entry.is_none(),
+                "Heap base cannot be set with a custom entry point, since it is passed to _start"
+            );
+            ensure!(
+                base % WORD_SIZE as u32 == 0,
+                "Heap base {base:#010x} must be word aligned"
+            );
+            ensure!(
+                end <= base && base < heap_limit,
+                "Heap base {base:#010x} must lie between the end of the program at {end:#010x} and the end of the heap at {heap_limit:#010x}"
+            );
+            store_register(&mut image, REG_A0, base);
+        }
+
+        if self.entry.is_some() {
+            image.pc = self.resolve_entry(elf, &program)?;
+            if self.stack_size.is_none() {
+                store_register(&mut image, REG_SP, STACK_TOP);
+            }
+            if let Some(gp) = Program::symbol(elf, "__global_pointer$")? {
+                store_register(&mut image, REG_GP, gp);
+            }
+        }
+
+        for (&idx, &value) in &self.registers {
+            ensure!(0 < idx && idx < REG_MAX, "Register x{idx} cannot be set");
+            store_register(&mut image, idx, value);
+        }
+
+        image.hash_pages_with(hasher);
+        Ok(image)
+    }
+
+    /// The address at which execution of the ELF binary `elf` starts with
+    /// this layout. This is the program counter of its memory image, and so
+    /// part of its image ID.
+    pub fn entry_point(&self, elf: &[u8]) -> Result<u32> {
+        let program = Program::load_elf(elf, GUEST_MAX_MEM as u32)?;
+        self.resolve_entry(elf, &program)
+    }
+
+    fn resolve_entry(&self, elf: &[u8], program: &Program) -> Result<u32> {
+        let (addr, name) = match &self.entry {
+            None => return Ok(program.entry),
+            Some(EntryPoint::Symbol(symbol)) => {
+                let addr = Program::symbol(elf, symbol)?
+                    .ok_or_else(|| anyhow!("Entry point {symbol} is not a symbol of the ELF"))?;
+                (addr, format!("{symbol} at {addr:#010x}"))
+            }
+            Some(EntryPoint::Address(addr)) => (*addr, format!("{addr:#010x}")),
+        };
+        ensure!(
+            program.image.contains_key(&addr),
+            "Entry point {name} is not an instruction of the program"
+        );
+        Ok(addr)
+    }
+
+    /// Explains the failure at `pc` of a guest started at a custom entry
+    /// point, with `gp` as its global pointer, if that is how it started.
+    #[cfg(feature = "prove")]
+    pub(crate) fn entry_context(&self, pc: u32, gp: u32) -> Option<String> {
+        let entry = self.entry.as_ref()?;
+        let cause = if pc == 0 {
+            "the function returned to address 0; set ra to code that halts"
+        } else if gp == 0 {
+            "gp is not set, since the ELF has no __global_pointer$ symbol"
+        } else {
+            "only sp and gp are set up for it, and the rest of the runtime is not initialized"
+        };
+        Some(format!(
+            "Execution from entry point {entry} failed at pc {pc:#010x}, skipping _start: {cause}"
+        ))
+    }
+
+    /// Compute the image ID of the ELF binary `elf` loaded with this layout.
+    pub fn image_id(&self, elf: &[u8]) -> Result<Digest> {
+        Ok(self.load_elf(elf)?.compute_id())
+    }
+
+    /// The start and end of the heap, where they differ from the defaults.
+    #[cfg(feature = "prove")]
+    pub(crate) fn heap_bounds(&self) -> (Option<u32>, Option<u32>) {
+        let limit = self
+            .stack_size
+            .map(|size| (GUEST_MAX_MEM as u32).saturating_sub(size));
+        (self.heap_base, limit)
+    }
+}
+
+// Registers are stored in words at the start of SYSTEM memory.
+fn store_register(image: &mut MemoryImage, idx: usize, value: u32) {
+    let addr = SYSTEM.start() + idx * WORD_SIZE;
+    image.store_region_in_page(addr as u32, &value.to_le_bytes());
+}
@@ -0,0 +1,75 @@
+// Copyright 2023 RISC Zero, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Line-at-a-time delivery of guest output.
+
+use std::io::Write;
+
+/// A [Write] adapter that passes complete lines of text to a callback.
+///
+/// Bytes are buffered until a newline is written, so a line the guest writes
+/// in several pieces is delivered whole, and output from the guest is never
+/// interleaved with host logs mid-line. Each line is passed without its
+/// trailing newline. Invalid UTF-8 is replaced with U+FFFD rather than
+/// treated as an error. Any bytes after the last newline are delivered as a
+/// final line when the writer is flushed, which the executor does at the end
+/// of each run, or dropped.
+///
+/// See [ExecutorEnvBuilder::stdout_lines](crate::ExecutorEnvBuilder::stdout_lines).
+pub struct LineWriter<F: FnMut(&str)> {
+    buf: Vec<u8>,
+    callback: F,
+}
+
+impl<F: FnMut(&str)> LineWriter<F> {
+    /// Construct a [LineWriter] that passes each line to `callback`.
+    pub fn new(callback: F) -> Self {
+        Self {
+            buf: Vec::new(),
+            callback,
+        }
+    }
+
+    fn emit(&mut self, line: &[u8]) {
+        (self.callback)(&String::from_utf8_lossy(line));
+    }
+}
+
+impl<F: FnMut(&str)> Write for LineWriter<F> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.buf.extend_from_slice(buf);
+        if let Some(end) = self.buf.iter().rposition(|&x| x == b'\n') {
+            let rest = self.buf.split_off(end + 1);
+            let lines = std::mem::replace(&mut self.buf, rest);
+            for line in lines[..end].split(|&x| x == b'\n') {
+                self.emit(line);
+            }
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        if !self.buf.is_empty() {
+            let line = std::mem::take(&mut self.buf);
+            self.emit(&line);
+        }
+        Ok(())
+    }
+}
+
+impl<F: FnMut(&str)> Drop for LineWriter<F> {
+    fn drop(&mut self) {
+        let _ = self.flush();
+    }
+}
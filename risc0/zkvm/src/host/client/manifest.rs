@@ -0,0 +1,60 @@
+// Copyright 2023 RISC Zero, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! A serializable record of a guest environment, for reproducing a run.
+
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+
+/// The parts of an [ExecutorEnv](crate::ExecutorEnv) that can be saved and
+/// restored to run a guest again in the same environment.
+///
+/// A manifest is taken with [ExecutorEnv::manifest](crate::ExecutorEnv::manifest)
+/// and restored with
+/// [ExecutorEnvBuilder::apply_manifest](crate::ExecutorEnvBuilder::apply_manifest).
+/// Readers and handlers cannot be serialized, so the manifest only records
+/// the names of the named inputs and of the channels registered with
+/// [ExecutorEnvBuilder::slice_io](crate::ExecutorEnvBuilder::slice_io); they
+/// are supplied again when the manifest is applied. Input read from a
+/// reader passed to [ExecutorEnvBuilder::stdin](crate::ExecutorEnvBuilder::stdin)
+/// is not recorded.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct GuestEnvManifest {
+    /// The environment variables of the guest.
+    pub env_vars: BTreeMap<String, String>,
+
+    /// The arguments of the guest.
+    pub args: Vec<String>,
+
+    /// The bytes written to the guest's stdin with
+    /// [ExecutorEnvBuilder::write](crate::ExecutorEnvBuilder::write) and
+    /// friends.
+    pub input: Vec<u8>,
+
+    /// The names of the named inputs, in the order they were added.
+    pub named_inputs: Vec<String>,
+
+    /// The names of the channels with a slice I/O handler.
+    pub slice_io: Vec<String>,
+
+    /// The segment limit, in powers of 2 cycles.
+    pub segment_limit_po2: Option<u32>,
+
+    /// The session limit, in cycles.
+    pub session_limit: Option<u64>,
+
+    /// The nonce provided to the guest.
+    pub nonce: Option<[u8; 32]>,
+}
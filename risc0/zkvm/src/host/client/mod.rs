@@ -12,8 +12,20 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+pub(crate) mod cycle_attributor;
+#[cfg(feature = "dev")]
+pub(crate) mod dev;
 pub(crate) mod env;
 pub(crate) mod exec;
+pub(crate) mod framed;
+pub(crate) mod host_commit;
+pub(crate) mod layout;
+pub(crate) mod line_writer;
+pub(crate) mod manifest;
+pub(crate) mod page_map;
 pub(crate) mod posix_io;
 pub(crate) mod prove;
+pub(crate) mod provenance;
 pub(crate) mod slice_io;
+pub(crate) mod symbol_stats;
+pub(crate) mod trace_file;
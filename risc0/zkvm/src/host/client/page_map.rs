@@ -0,0 +1,106 @@
+// Copyright 2023 RISC Zero, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! A map of the pages of a guest's memory and how a run used them.
+
+use std::{collections::BTreeSet, io::Write};
+
+use anyhow::Result;
+use risc0_binfmt::{MemoryImage, PageInfo, PageKind};
+use serde::{Deserialize, Serialize};
+
+/// The pages of memory read and written by a run of a guest; see
+/// [Prover::touched_pages](crate::Prover::touched_pages).
+///
+/// A page is read when it is first accessed in a segment, and written when it
+/// is first modified in a segment, so every written page was also read. The
+/// pages of the page table that cover these pages are included.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TouchedPages {
+    /// The indices of the pages read in any segment.
+    pub read: BTreeSet<u32>,
+
+    /// The indices of the pages written in any segment.
+    pub written: BTreeSet<u32>,
+}
+
+impl TouchedPages {
+    /// The pages that were read but never written, such as the guest's code.
+    pub fn read_only(&self) -> impl Iterator<Item = u32> + '_ {
+        self.read.difference(&self.written).copied()
+    }
+}
+
+/// Write a map of the pages present in `image` to `writer`, one line for each
+/// run of consecutive pages of the same [PageKind], e.g.
+///
+/// ```text
+/// 0x00200000..0x00200400 stack          1 page   written
+/// 0x00200800..0x00209c00 program       37 pages  read-only
+/// ```
+///
+/// With `touched`, each run is also split by whether its pages were written,
+/// only read, or not touched at all.
+pub fn dump_layout(
+    image: &MemoryImage,
+    touched: Option<&TouchedPages>,
+    mut writer: impl Write,
+) -> Result<()> {
+    let access = |idx: u32| {
+        touched.map(|touched| {
+            if touched.written.contains(&idx) {
+                "written"
+            } else if touched.read.contains(&idx) {
+                "read-only"
+            } else {
+                "untouched"
+            }
+        })
+    };
+
+    let mut pages = image.pages().peekable();
+    while let Some(first) = pages.next() {
+        let PageInfo {
+            idx,
+            addr_range,
+            kind,
+            ..
+        } = first;
+        let mut last = (idx, addr_range.end);
+        while let Some(next) = pages.next_if(|next| {
+            next.idx == last.0 + 1 && next.kind == kind && access(next.idx) == access(idx)
+        }) {
+            last = (next.idx, next.addr_range.end);
+        }
+
+        let count = last.0 - idx + 1;
+        let kind = match kind {
+            PageKind::Stack => "stack",
+            PageKind::Program => "program",
+            PageKind::System => "system",
+            PageKind::PageTable => "page table",
+        };
+        let plural = if count == 1 { "page " } else { "pages" };
+        write!(
+            writer,
+            "{:#010x}..{:#010x} {kind:<10} {count:>5} {plural}",
+            addr_range.start, last.1
+        )?;
+        if let Some(access) = access(idx) {
+            write!(writer, "  {access}")?;
+        }
+        writeln!(writer)?;
+    }
+    Ok(())
+}
@@ -14,18 +14,50 @@
 
 use std::{
     cell::RefCell,
-    collections::BTreeMap,
-    io::{stderr, stdout, BufRead, Cursor, Write},
+    collections::{BTreeMap, BTreeSet, VecDeque},
+    fmt,
+    io::{self, stderr, stdout, BufRead, Cursor, IsTerminal, Write},
     rc::Rc,
+    time::Duration,
 };
 
+use anyhow::Result;
 use risc0_zkvm_platform::fileno;
+use serde::{Deserialize, Serialize};
+
+use crate::{sha::Digest, ExecutorEnv};
+
+// The number of times in a row the guest may find a descriptor empty with
+// SYS_READ_AVAIL before it is considered stalled.
+const DEFAULT_STALL_THRESHOLD: u32 = 1000;
 
 /// Posix-style I/O
 #[derive(Clone)]
 pub struct PosixIo<'a> {
     pub(crate) read_fds: BTreeMap<u32, Rc<RefCell<dyn BufRead + 'a>>>,
     pub(crate) write_fds: BTreeMap<u32, Rc<RefCell<dyn Write + 'a>>>,
+    // The descriptors intentionally registered for both reading and writing.
+    pub(crate) duplex_fds: BTreeSet<u32>,
+    pub(crate) short_reads: bool,
+    pub(crate) log_limits: Option<LogLimits>,
+    pub(crate) output_size_hints: Option<OutputSizeHints>,
+    // The bytes written so far to each descriptor with a strict size hint.
+    #[cfg(feature = "prove")]
+    pub(crate) output_bytes: BTreeMap<u32, usize>,
+    pub(crate) stall_threshold: Option<u32>,
+    pub(crate) write_backoff: WriteBackoff,
+    // The descriptor last found empty by SYS_READ_AVAIL, and the number of
+    // times in a row it was, since the guest last read, wrote, or found data.
+    #[cfg(feature = "prove")]
+    pub(crate) empty_polls: Option<(u32, u32)>,
+    // The messages logged with `env::log` so far, and those dropped since the
+    // last report.
+    #[cfg(feature = "prove")]
+    pub(crate) log_messages: u64,
+    #[cfg(feature = "prove")]
+    pub(crate) log_bytes: u64,
+    #[cfg(feature = "prove")]
+    pub(crate) log_dropped: u64,
 }
 
 impl<'a> Default for PosixIo<'a> {
@@ -33,10 +65,26 @@ impl<'a> Default for PosixIo<'a> {
         let mut new = Self {
             read_fds: Default::default(),
             write_fds: Default::default(),
+            duplex_fds: Default::default(),
+            short_reads: false,
+            log_limits: None,
+            output_size_hints: None,
+            #[cfg(feature = "prove")]
+            output_bytes: BTreeMap::new(),
+            stall_threshold: Some(DEFAULT_STALL_THRESHOLD),
+            write_backoff: WriteBackoff::default(),
+            #[cfg(feature = "prove")]
+            empty_polls: None,
+            #[cfg(feature = "prove")]
+            log_messages: 0,
+            #[cfg(feature = "prove")]
+            log_bytes: 0,
+            #[cfg(feature = "prove")]
+            log_dropped: 0,
         };
         new.with_read_fd(fileno::STDIN, Cursor::new(vec![]))
-            .with_write_fd(fileno::STDOUT, stdout())
-            .with_write_fd(fileno::STDERR, stderr());
+            .with_write_fd(fileno::STDOUT, HostStdio::new(stdout()))
+            .with_write_fd(fileno::STDERR, HostStdio::new(stderr()));
         new
     }
 }
@@ -51,4 +99,447 @@ impl<'a> PosixIo<'a> {
         self.write_fds.insert(fd, Rc::new(RefCell::new(writer)));
         self
     }
+
+    pub fn with_duplex_fd(
+        &mut self,
+        fd: u32,
+        reader: impl BufRead + 'a,
+        writer: impl Write + 'a,
+    ) -> &mut Self {
+        self.duplex_fds.insert(fd);
+        self.with_read_fd(fd, reader).with_write_fd(fd, writer)
+    }
+
+    /// Write a line to standard output saying how many log messages were
+    /// dropped under [LogPolicy::Drop] since the last call, if any.
+    #[cfg(feature = "prove")]
+    pub(crate) fn report_dropped_logs(&mut self) -> io::Result<()> {
+        if self.log_dropped == 0 {
+            return Ok(());
+        }
+        let dropped = std::mem::take(&mut self.log_dropped);
+        tracing::warn!("{dropped} guest log messages dropped");
+        if let Some(writer) = self.write_fds.get(&fileno::STDOUT) {
+            writeln!(writer.borrow_mut(), "R0VM: {dropped} log messages dropped")?;
+        }
+        Ok(())
+    }
+
+    /// Returns a descriptor registered for both reading and writing other
+    /// than with [PosixIo::with_duplex_fd], if any.
+    ///
+    /// This includes the descriptors in `lazy_fds`, and the journal's, which
+    /// the executor always writes to.
+    pub(crate) fn conflicting_fd(&self, lazy_fds: &[(u32, LazyFd<'a>)]) -> Option<u32> {
+        let mut reads: BTreeSet<u32> = self.read_fds.keys().copied().collect();
+        let mut writes: BTreeSet<u32> = self.write_fds.keys().copied().collect();
+        writes.insert(fileno::JOURNAL);
+        for (fd, lazy_fd) in lazy_fds {
+            match lazy_fd {
+                LazyFd::Read(_) => reads.insert(*fd),
+                LazyFd::Write(_) => writes.insert(*fd),
+            };
+        }
+        reads
+            .intersection(&writes)
+            .find(|fd| !self.duplex_fds.contains(fd))
+            .copied()
+    }
+}
+
+/// What a writer registered with
+/// [ExecutorEnvBuilder::write_fd_buffered](crate::ExecutorEnvBuilder::write_fd_buffered)
+/// does when the guest writes more than its buffer can hold.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OverflowPolicy {
+    /// Write the buffered output to the writer, blocking the guest until the
+    /// writer has accepted it.
+    Block,
+
+    /// Fail the execution with
+    /// [GuestIoError::BackpressureExceeded](crate::GuestIoError::BackpressureExceeded).
+    Error,
+
+    /// Discard the oldest output in the buffer to make room.
+    DropOldest,
+}
+
+/// Limits on the messages a guest logs with
+/// [env::log](crate::guest::env::log); see
+/// [ExecutorEnvBuilder::log_limits](crate::ExecutorEnvBuilder::log_limits).
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LogLimits {
+    /// The number of messages written before the limit is reached.
+    pub max_messages: u64,
+
+    /// The total size of the messages written before the limit is reached,
+    /// in bytes.
+    pub max_total_bytes: u64,
+
+    /// What happens to the messages past the limit.
+    pub on_exceed: LogPolicy,
+}
+
+/// The expected size of the output of a guest; see
+/// [ExecutorEnvBuilder::output_size_hints](crate::ExecutorEnvBuilder::output_size_hints).
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct OutputSizeHints {
+    /// The expected size of the journal, in bytes.
+    pub journal: usize,
+
+    /// The expected size of the output to standard output, in bytes.
+    pub stdout: usize,
+
+    /// Whether output past the hints fails the execution with
+    /// [GuestIoError::OutputSizeExceeded](crate::GuestIoError::OutputSizeExceeded).
+    pub strict: bool,
+}
+
+/// What happens to a message the guest logs past its [LogLimits].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum LogPolicy {
+    /// Discard the message. The number of messages discarded is written to
+    /// standard output at the end of the run.
+    Drop,
+
+    /// Fail the execution with
+    /// [GuestIoError::LogLimitExceeded](crate::GuestIoError::LogLimitExceeded).
+    Error,
+}
+
+/// How `SYS_WRITE` waits for a writer that is not ready, such as a
+/// non-blocking socket returning [io::ErrorKind::WouldBlock]; see
+/// [ExecutorEnvBuilder::write_backoff](crate::ExecutorEnvBuilder::write_backoff).
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct WriteBackoff {
+    /// The time to wait before the first retry.
+    pub initial: Duration,
+
+    /// The longest time to wait between retries. The wait doubles after each
+    /// retry up to this limit, and starts again from
+    /// [WriteBackoff::initial] once the writer accepts some data.
+    pub max: Duration,
+
+    /// The longest time a single write may take, after which the execution
+    /// fails with
+    /// [GuestIoError::WriteTimedOut](crate::GuestIoError::WriteTimedOut).
+    pub deadline: Duration,
+}
+
+impl Default for WriteBackoff {
+    fn default() -> Self {
+        Self {
+            initial: Duration::from_millis(1),
+            max: Duration::from_millis(100),
+            deadline: Duration::from_secs(30),
+        }
+    }
+}
+
+/// The error returned by [write_with_backoff] when the writer is still not
+/// ready at the deadline.
+#[cfg(feature = "prove")]
+#[derive(Debug)]
+pub(crate) struct WriteTimedOut {
+    pub written: usize,
+}
+
+#[cfg(feature = "prove")]
+impl fmt::Display for WriteTimedOut {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "writer not ready after {} bytes", self.written)
+    }
+}
+
+#[cfg(feature = "prove")]
+impl std::error::Error for WriteTimedOut {}
+
+/// Write all of `data` to `writer`, looping over short writes, retrying
+/// writes that are interrupted, and waiting as `backoff` allows while the
+/// writer would block.
+#[cfg(feature = "prove")]
+pub(crate) fn write_with_backoff(
+    writer: &mut dyn Write,
+    data: &[u8],
+    backoff: &WriteBackoff,
+) -> io::Result<()> {
+    let start = std::time::Instant::now();
+    let mut delay = backoff.initial;
+    let mut written = 0;
+    while written < data.len() {
+        match writer.write(&data[written..]) {
+            Ok(0) => return Err(io::ErrorKind::WriteZero.into()),
+            Ok(n) => {
+                written += n;
+                delay = backoff.initial;
+            }
+            Err(err) if err.kind() == io::ErrorKind::Interrupted => {}
+            Err(err) if err.kind() == io::ErrorKind::WouldBlock => {
+                let elapsed = start.elapsed();
+                if elapsed >= backoff.deadline {
+                    return Err(io::Error::new(
+                        io::ErrorKind::TimedOut,
+                        WriteTimedOut { written },
+                    ));
+                }
+                std::thread::sleep(delay.min(backoff.deadline - elapsed));
+                delay = (delay * 2).min(backoff.max);
+            }
+            Err(err) => return Err(err),
+        }
+    }
+    Ok(())
+}
+
+/// A writer that holds the output of the guest in a bounded buffer, which is
+/// drained into the underlying writer when it is flushed or full, as its
+/// [OverflowPolicy] allows.
+pub(crate) struct BufferedWriter<'a> {
+    fd: u32,
+    writer: Box<dyn Write + 'a>,
+    buf: VecDeque<u8>,
+    capacity: usize,
+    policy: OverflowPolicy,
+    dropped: usize,
+}
+
+/// The error returned by a [BufferedWriter] whose buffer is full under
+/// [OverflowPolicy::Error].
+#[derive(Debug)]
+pub(crate) struct BufferFull {
+    pub capacity: usize,
+}
+
+impl fmt::Display for BufferFull {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "output buffer of {} bytes is full", self.capacity)
+    }
+}
+
+impl std::error::Error for BufferFull {}
+
+impl<'a> BufferedWriter<'a> {
+    pub fn new(fd: u32, writer: impl Write + 'a, capacity: usize, policy: OverflowPolicy) -> Self {
+        Self {
+            fd,
+            writer: Box::new(writer),
+            buf: VecDeque::with_capacity(capacity),
+            capacity,
+            policy,
+            dropped: 0,
+        }
+    }
+
+    fn drain(&mut self) -> io::Result<()> {
+        let (front, back) = self.buf.as_slices();
+        self.writer.write_all(front)?;
+        self.writer.write_all(back)?;
+        self.buf.clear();
+        Ok(())
+    }
+}
+
+impl<'a> Write for BufferedWriter<'a> {
+    fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+        let excess = (self.buf.len() + data.len()).saturating_sub(self.capacity);
+        if excess == 0 {
+            self.buf.extend(data);
+            return Ok(data.len());
+        }
+        match self.policy {
+            OverflowPolicy::Block => {
+                self.drain()?;
+                if data.len() > self.capacity {
+                    self.writer.write_all(data)?;
+                } else {
+                    self.buf.extend(data);
+                }
+            }
+            OverflowPolicy::Error => {
+                return Err(io::Error::new(
+                    io::ErrorKind::Other,
+                    BufferFull {
+                        capacity: self.capacity,
+                    },
+                ))
+            }
+            OverflowPolicy::DropOldest => {
+                let from_buf = excess.min(self.buf.len());
+                self.buf.drain(..from_buf);
+                self.buf.extend(&data[excess - from_buf..]);
+                self.dropped += excess;
+            }
+        }
+        Ok(data.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        if self.dropped > 0 {
+            tracing::warn!(
+                "Dropped {} bytes of output written to fd {}",
+                self.dropped,
+                self.fd
+            );
+            self.dropped = 0;
+        }
+        self.drain()?;
+        self.writer.flush()
+    }
+}
+
+/// The host's standard output or error, as the default writer for the
+/// guest's.
+///
+/// A Windows console takes only UTF-8, and fails a write of anything else, so
+/// a guest that prints arbitrary bytes would fail there and nowhere else.
+/// When writing to a console on Windows, the guest's output is therefore
+/// written as text: invalid UTF-8 is replaced with U+FFFD, and a character
+/// split across two writes is held back until it is complete. Otherwise,
+/// including when the output is redirected to a file or pipe on Windows, the
+/// bytes are written unchanged.
+pub(crate) struct HostStdio<W: Write> {
+    inner: W,
+    console: bool,
+    // The start of a character whose remaining bytes are yet to be written.
+    pending: Vec<u8>,
+}
+
+impl<W: Write + IsTerminal> HostStdio<W> {
+    pub fn new(inner: W) -> Self {
+        let console = cfg!(windows) && inner.is_terminal();
+        Self::with_console(inner, console)
+    }
+}
+
+impl<W: Write> HostStdio<W> {
+    fn with_console(inner: W, console: bool) -> Self {
+        Self {
+            inner,
+            console,
+            pending: Vec::new(),
+        }
+    }
+
+    // The text of `data`, after any pending bytes, up to the start of a
+    // character it ends in the middle of.
+    fn console_text(&mut self, data: &[u8]) -> String {
+        self.pending.extend_from_slice(data);
+        let mut text = String::new();
+        let mut rest = self.pending.as_slice();
+        loop {
+            match std::str::from_utf8(rest) {
+                Ok(valid) => {
+                    text.push_str(valid);
+                    rest = &[];
+                    break;
+                }
+                Err(err) => {
+                    let (valid, invalid) = rest.split_at(err.valid_up_to());
+                    text.push_str(&String::from_utf8_lossy(valid));
+                    let Some(len) = err.error_len() else {
+                        rest = invalid;
+                        break;
+                    };
+                    text.push(char::REPLACEMENT_CHARACTER);
+                    rest = &invalid[len..];
+                }
+            }
+        }
+        self.pending = rest.to_vec();
+        text
+    }
+}
+
+impl<W: Write> Write for HostStdio<W> {
+    fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+        if !self.console {
+            return self.inner.write(data);
+        }
+        let text = self.console_text(data);
+        self.inner.write_all(text.as_bytes())?;
+        Ok(data.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+impl<W: Write> Drop for HostStdio<W> {
+    fn drop(&mut self) {
+        if !self.pending.is_empty() {
+            let pending = std::mem::take(&mut self.pending);
+            let _ = self
+                .inner
+                .write_all(String::from_utf8_lossy(&pending).as_bytes());
+        }
+    }
+}
+
+/// What a factory passed to
+/// [ExecutorEnvBuilder::lazy_read_fd](crate::ExecutorEnvBuilder::lazy_read_fd)
+/// or
+/// [ExecutorEnvBuilder::lazy_write_fd](crate::ExecutorEnvBuilder::lazy_write_fd)
+/// can know about the run it is called for.
+pub struct LazyFdContext<'c, 'a> {
+    pub(crate) image_id: Digest,
+    pub(crate) env: &'c ExecutorEnv<'a>,
+}
+
+impl<'c, 'a> LazyFdContext<'c, 'a> {
+    /// The image ID of the guest about to run.
+    pub fn image_id(&self) -> Digest {
+        self.image_id
+    }
+
+    /// The environment the guest runs in. See
+    /// [ExecutorEnv::manifest](crate::ExecutorEnv::manifest) for a
+    /// serializable snapshot of it.
+    pub fn env(&self) -> &'c ExecutorEnv<'a> {
+        self.env
+    }
+}
+
+pub(crate) type LazyReader<'a> =
+    Box<dyn FnOnce(&LazyFdContext<'_, 'a>) -> Result<Box<dyn BufRead + 'a>> + 'a>;
+pub(crate) type LazyWriter<'a> =
+    Box<dyn FnOnce(&LazyFdContext<'_, 'a>) -> Result<Box<dyn Write + 'a>> + 'a>;
+
+/// A file descriptor whose reader or writer is made just before the guest
+/// runs.
+pub(crate) enum LazyFd<'a> {
+    Read(LazyReader<'a>),
+    Write(LazyWriter<'a>),
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use super::HostStdio;
+
+    #[test]
+    fn console_text() {
+        let mut out = Vec::new();
+        {
+            let mut console = HostStdio::with_console(&mut out, true);
+            // A character split across writes, an invalid byte, and a CRLF,
+            // which must not be translated.
+            console.write_all(b"caf\xc3").unwrap();
+            console.write_all(b"\xa9 \xff\r\n").unwrap();
+            // Incomplete when the writer is dropped.
+            console.write_all(b"\xe2\x82").unwrap();
+        }
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "caf\u{e9} \u{fffd}\r\n\u{fffd}"
+        );
+
+        let mut out = Vec::new();
+        let bytes = b"caf\xc3\xa9 \xff\r\n\xe2\x82";
+        HostStdio::with_console(&mut out, false)
+            .write_all(bytes)
+            .unwrap();
+        assert_eq!(out, bytes);
+    }
 }
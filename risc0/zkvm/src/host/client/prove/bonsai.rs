@@ -19,7 +19,9 @@ use bonsai_sdk::alpha::Client;
 use risc0_binfmt::MemoryImage;
 
 use super::Prover;
-use crate::{sha::Digestible, ExecutorEnv, ProverOpts, Receipt, VerifierContext};
+#[allow(deprecated)]
+use crate::ProverOpts;
+use crate::{host::client::provenance, sha::Digestible, ExecutorEnv, Receipt, VerifierContext};
 
 /// An implementation of a [Prover] that runs proof workloads via Bonsai.
 ///
@@ -38,6 +40,7 @@ impl BonsaiProver {
     }
 }
 
+#[allow(deprecated)]
 impl Prover for BonsaiProver {
     fn get_name(&self) -> String {
         self.name.clone()
@@ -50,6 +53,20 @@ impl Prover for BonsaiProver {
         opts: &ProverOpts,
         image: MemoryImage,
     ) -> Result<Receipt> {
+        opts.check_image_id(&image)?;
+        // Bonsai is only sent the image and the input.
+        let posix_io = env.posix_io.borrow();
+        ensure!(
+            !env.derived_randomness
+                && !env.sha_accel
+                && !env.bigint_accel
+                && !env.hashed_journal
+                && posix_io.log_limits.is_none()
+                && posix_io.output_size_hints.is_none(),
+            "BonsaiProver does not support derived randomness, SHA-256 or big integer \
+            acceleration, hashed journals, log limits or output size hints"
+        );
+        drop(posix_io);
         let client = Client::from_env(crate::VERSION)?;
 
         // upload the image
@@ -61,7 +78,11 @@ impl Prover for BonsaiProver {
         client.upload_img(&image_id_hex, image)?;
 
         // upload input data
-        let input_id = client.upload_input(env.input)?;
+        let input = match env.input_buffer {
+            Some(buffer) => buffer.to_vec(),
+            None => env.input.to_vec(),
+        };
+        let input_id = client.upload_input(input)?;
 
         // While this is the executor, we want to start a session on the bonsai prover.
         // By doing so, we can return a session ID so that the prover can use it to
@@ -84,7 +105,8 @@ impl Prover for BonsaiProver {
                     .ok_or(anyhow!("API error, missing receipt on completed session"))?;
 
                 let receipt_buf = client.download(&receipt_url)?;
-                let receipt: Receipt = bincode::deserialize(&receipt_buf)?;
+                let mut receipt = Receipt::from_bytes(&receipt_buf)?;
+                provenance::annotate(&mut receipt, &opts.annotations);
 
                 if opts.prove_guest_errors {
                     receipt.verify_integrity_with_context(ctx)?;
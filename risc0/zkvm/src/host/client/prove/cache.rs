@@ -0,0 +1,239 @@
+// Copyright 2023 RISC Zero, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Reuse of receipts for executions that have already been proven.
+
+use std::{
+    path::PathBuf,
+    rc::Rc,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+};
+
+use anyhow::{ensure, Result};
+use risc0_binfmt::MemoryImage;
+use serde::{Deserialize, Serialize};
+
+#[allow(deprecated)]
+use super::ProverOpts;
+use crate::{
+    host::client::provenance,
+    serde::to_vec,
+    sha::{Digest, Digestible},
+    ExecutorEnv, Receipt, VerifierContext, VERSION,
+};
+
+/// Identifies a proof in a [ProofCache].
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ProofCacheKey {
+    /// The image ID of the guest.
+    pub image_id: Digest,
+
+    /// A digest of the input, environment variables, arguments, registered
+    /// guests and assumptions of the [ExecutorEnv].
+    pub input_digest: Digest,
+
+    /// The hash function used by the prover; see [ProverOpts::hashfn].
+    pub hashfn: String,
+
+    /// The version of the zkVM, which determines the circuit.
+    pub version: String,
+}
+
+impl ProofCacheKey {
+    /// A digest of all the fields of the key, e.g. to use as a file name.
+    pub fn digest(&self) -> Digest {
+        let words = to_vec(self).unwrap();
+        bytemuck::cast_slice::<u32, u8>(&words).digest()
+    }
+}
+
+/// A receipt stored in a [ProofCache], along with the version of the zkVM that
+/// produced it.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct CachedReceipt {
+    /// The version of the zkVM that produced the receipt.
+    pub version: String,
+
+    /// The receipt.
+    #[serde(with = "crate::host::receipt::annotated")]
+    pub receipt: Receipt,
+}
+
+/// A store of receipts for executions that have already been proven; see
+/// [ProverOpts::with_proof_cache].
+pub trait ProofCache {
+    /// Look up the receipt stored under `key`, if any.
+    fn get(&self, key: &ProofCacheKey) -> Result<Option<CachedReceipt>>;
+
+    /// Store `receipt` under `key`, replacing any previous receipt.
+    fn put(&self, key: &ProofCacheKey, receipt: &CachedReceipt) -> Result<()>;
+}
+
+impl<T: ProofCache + ?Sized> ProofCache for Rc<T> {
+    fn get(&self, key: &ProofCacheKey) -> Result<Option<CachedReceipt>> {
+        (**self).get(key)
+    }
+
+    fn put(&self, key: &ProofCacheKey, receipt: &CachedReceipt) -> Result<()> {
+        (**self).put(key, receipt)
+    }
+}
+
+impl<T: ProofCache + ?Sized> ProofCache for Arc<T> {
+    fn get(&self, key: &ProofCacheKey) -> Result<Option<CachedReceipt>> {
+        (**self).get(key)
+    }
+
+    fn put(&self, key: &ProofCacheKey, receipt: &CachedReceipt) -> Result<()> {
+        (**self).put(key, receipt)
+    }
+}
+
+/// A [ProofCache] that stores each receipt in its own file in a directory.
+pub struct FileProofCache {
+    dir: PathBuf,
+    hits: AtomicU64,
+}
+
+impl FileProofCache {
+    /// Construct a [FileProofCache] that stores receipts in `dir`. The
+    /// directory is created when the first receipt is stored.
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self {
+            dir: dir.into(),
+            hits: AtomicU64::new(0),
+        }
+    }
+
+    /// The number of lookups that found a stored receipt.
+    pub fn hits(&self) -> u64 {
+        self.hits.load(Ordering::Relaxed)
+    }
+
+    fn path(&self, key: &ProofCacheKey) -> PathBuf {
+        self.dir.join(format!("{}.receipt", key.digest()))
+    }
+}
+
+impl ProofCache for FileProofCache {
+    fn get(&self, key: &ProofCacheKey) -> Result<Option<CachedReceipt>> {
+        let path = self.path(key);
+        if !path.exists() {
+            return Ok(None);
+        }
+        let bytes = std::fs::read(&path)?;
+        match bincode::deserialize(&bytes) {
+            Ok(receipt) => {
+                self.hits.fetch_add(1, Ordering::Relaxed);
+                Ok(Some(receipt))
+            }
+            Err(err) => {
+                tracing::warn!("Ignoring unreadable cached receipt {path:?}: {err}");
+                Ok(None)
+            }
+        }
+    }
+
+    fn put(&self, key: &ProofCacheKey, receipt: &CachedReceipt) -> Result<()> {
+        std::fs::create_dir_all(&self.dir)?;
+        std::fs::write(self.path(key), bincode::serialize(receipt)?)?;
+        Ok(())
+    }
+}
+
+/// Prove with `prove`, unless the [ProofCache] in `opts` already holds a
+/// valid receipt for the same guest and input. Either way, the image must
+/// have the ID expected by `opts`, if any, and the host environment variables,
+/// assumptions and syscalls selected by `opts` are added to `env`.
+#[allow(deprecated)]
+pub(crate) fn prove_cached<'a>(
+    mut env: ExecutorEnv<'a>,
+    ctx: &VerifierContext,
+    opts: &ProverOpts,
+    image: MemoryImage,
+    prove: impl FnOnce(ExecutorEnv<'a>, MemoryImage) -> Result<Receipt>,
+) -> Result<Receipt> {
+    opts.check_image_id(&image)?;
+    // The guest's environment is part of the key, so the host's is captured
+    // first.
+    opts.capture_env(&mut env);
+    opts.add_assumptions(&mut env)?;
+    env.bigint_accel |= opts.bigint_accel;
+    env.isa_audit |= opts.isa_audit;
+    let Some(cache) = &opts.proof_cache else {
+        return prove(env, image);
+    };
+    if env.hashed_journal {
+        tracing::debug!("Not caching the proof: a cached receipt has no journal preimages");
+        return prove(env, image);
+    }
+    let Some(input_digest) = env.input_digest() else {
+        tracing::debug!("Not caching the proof: the input cannot be digested");
+        return prove(env, image);
+    };
+    let image_id = image.compute_id();
+    let key = ProofCacheKey {
+        image_id,
+        input_digest,
+        hashfn: opts.hashfn.clone(),
+        version: VERSION.to_string(),
+    };
+
+    if let Some(cached) = cache.get(&key)? {
+        match check_cached(&cached, ctx, opts.prove_guest_errors, image_id) {
+            Ok(()) => {
+                let mut receipt = cached.receipt;
+                provenance::annotate(&mut receipt, &opts.annotations);
+                return Ok(receipt);
+            }
+            Err(err) => tracing::debug!("Ignoring cached receipt: {err}"),
+        }
+    }
+
+    let receipt = prove(env, image)?;
+    cache.put(
+        &key,
+        &CachedReceipt {
+            version: VERSION.to_string(),
+            receipt: receipt.clone(),
+        },
+    )?;
+    Ok(receipt)
+}
+
+fn check_cached(
+    cached: &CachedReceipt,
+    ctx: &VerifierContext,
+    prove_guest_errors: bool,
+    image_id: Digest,
+) -> Result<()> {
+    ensure!(
+        cached.version == VERSION,
+        "produced by version {}, expected {VERSION}",
+        cached.version
+    );
+    if prove_guest_errors {
+        cached.receipt.verify_integrity_with_context(ctx)?;
+        ensure!(
+            cached.receipt.get_metadata()?.pre.digest() == image_id,
+            "unexpected image ID"
+        );
+    } else {
+        cached.receipt.verify_with_context(ctx, image_id)?;
+    }
+    Ok(())
+}
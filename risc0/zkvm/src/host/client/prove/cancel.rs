@@ -0,0 +1,250 @@
+// Copyright 2023 RISC Zero, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Stopping a proof part way through.
+
+use std::{
+    fmt,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+};
+
+use crate::SegmentReceipt;
+
+/// A flag that stops a proof in progress; see
+/// [ProverOpts::with_cancel_token](super::ProverOpts::with_cancel_token).
+///
+/// Clones of a token share the flag, so a clone kept by the caller, e.g. in
+/// another thread, can cancel the proof.
+#[derive(Clone, Debug, Default)]
+pub struct CancelToken(pub(crate) Arc<AtomicBool>);
+
+impl CancelToken {
+    /// Construct a [CancelToken] that has not been cancelled.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Cancel the proof using this token. It stops before proving its next
+    /// segment.
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::SeqCst);
+    }
+
+    /// Whether [CancelToken::cancel] has been called on this token or one of
+    /// its clones.
+    pub fn is_cancelled(&self) -> bool {
+        #[cfg(all(feature = "signals", unix))]
+        ctrlc::poll();
+        self.0.load(Ordering::SeqCst)
+    }
+}
+
+/// The error returned by a prover whose [CancelToken] was cancelled, with the
+/// work done before it stopped.
+///
+/// Recover it from the returned error with [anyhow::Error::downcast_ref], or
+/// take ownership of the receipts with [anyhow::Error::downcast]. The
+/// [ExecutionReport](crate::ExecutionReport) of the run can be recovered from
+/// the same error.
+#[derive(Debug)]
+pub struct ProvingCancelled {
+    /// The number of user cycles in the segments that were proven or skipped
+    /// before proving stopped.
+    pub cycles: u64,
+
+    /// The receipts of the segments proven before proving stopped, in order.
+    pub segments: Vec<SegmentReceipt>,
+}
+
+impl fmt::Display for ProvingCancelled {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "proving was cancelled after {} segments covering {} cycles",
+            self.segments.len(),
+            self.cycles
+        )
+    }
+}
+
+impl std::error::Error for ProvingCancelled {}
+
+#[cfg(feature = "signals")]
+pub(crate) use self::ctrlc::CtrlcGuard;
+
+#[cfg(all(feature = "signals", unix))]
+mod ctrlc {
+    use std::{
+        mem, ptr,
+        sync::{
+            atomic::{AtomicBool, AtomicUsize, Ordering},
+            Arc, Mutex,
+        },
+    };
+
+    use anyhow::{bail, Result};
+
+    use super::CancelToken;
+
+    // The number of SIGINTs received by the handler. The handler touches
+    // nothing else, so it never sees memory that a guard may have freed.
+    static SIGINTS: AtomicUsize = AtomicUsize::new(0);
+
+    // The installed guards, shared by all of them: the handler is installed
+    // by the first guard and the previous disposition restored by the last,
+    // whatever order they are dropped in.
+    static REGISTRY: Mutex<Registry> = Mutex::new(Registry {
+        next_id: 0,
+        entries: Vec::new(),
+        previous_action: None,
+    });
+
+    struct Registry {
+        next_id: u64,
+        entries: Vec<Entry>,
+        previous_action: Option<libc::sigaction>,
+    }
+
+    struct Entry {
+        id: u64,
+        flag: Arc<AtomicBool>,
+        // The value of SIGINTS when the guard was installed.
+        sigints: usize,
+    }
+
+    impl Entry {
+        // Cancel the token if a SIGINT arrived since the guard was installed.
+        fn poll(&self) {
+            if SIGINTS.load(Ordering::SeqCst) != self.sigints {
+                self.flag.store(true, Ordering::SeqCst);
+            }
+        }
+    }
+
+    extern "C" fn on_sigint(_: libc::c_int) {
+        SIGINTS.fetch_add(1, Ordering::SeqCst);
+    }
+
+    // Cancel the tokens of the installed guards if a SIGINT has arrived.
+    pub(super) fn poll() {
+        let registry = REGISTRY.lock().unwrap();
+        registry.entries.iter().for_each(Entry::poll);
+    }
+
+    /// Cancels a [CancelToken] on SIGINT while installed. Any number of
+    /// guards can be installed at once, e.g. by concurrent provers; a SIGINT
+    /// cancels all of them, and the previous disposition of SIGINT is
+    /// restored when the last one is dropped.
+    pub(crate) struct CtrlcGuard {
+        id: u64,
+    }
+
+    impl CtrlcGuard {
+        pub(crate) fn install(token: &CancelToken) -> Result<Self> {
+            let mut registry = REGISTRY.lock().unwrap();
+            if registry.entries.is_empty() {
+                // SAFETY: sigaction is plain old data, and the handler only
+                // increments an atomic, which is async-signal-safe.
+                // SA_RESETHAND restores the default disposition on delivery,
+                // so that a second SIGINT terminates the process as usual.
+                let mut previous_action: libc::sigaction = unsafe { mem::zeroed() };
+                let installed = unsafe {
+                    let mut action: libc::sigaction = mem::zeroed();
+                    action.sa_sigaction =
+                        on_sigint as extern "C" fn(libc::c_int) as libc::sighandler_t;
+                    action.sa_flags = libc::SA_RESETHAND;
+                    libc::sigemptyset(&mut action.sa_mask);
+                    libc::sigaction(libc::SIGINT, &action, &mut previous_action)
+                };
+                if installed != 0 {
+                    bail!(
+                        "Failed to install a SIGINT handler: {}",
+                        std::io::Error::last_os_error()
+                    );
+                }
+                registry.previous_action = Some(previous_action);
+            }
+            let id = registry.next_id;
+            registry.next_id += 1;
+            registry.entries.push(Entry {
+                id,
+                flag: token.0.clone(),
+                sigints: SIGINTS.load(Ordering::SeqCst),
+            });
+            Ok(Self { id })
+        }
+    }
+
+    impl Drop for CtrlcGuard {
+        fn drop(&mut self) {
+            let mut registry = REGISTRY.lock().unwrap();
+            let Some(index) = registry.entries.iter().position(|e| e.id == self.id) else {
+                return;
+            };
+            // Keep a SIGINT that arrived while installed, even if nothing
+            // checked the token since.
+            registry.entries.remove(index).poll();
+            if registry.entries.is_empty() {
+                if let Some(previous_action) = registry.previous_action.take() {
+                    // SAFETY: previous_action was filled in by sigaction.
+                    unsafe { libc::sigaction(libc::SIGINT, &previous_action, ptr::null_mut()) };
+                }
+            }
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::{on_sigint, CancelToken, CtrlcGuard, REGISTRY};
+
+        #[test]
+        fn overlapping_guards() {
+            let a = CancelToken::new();
+            let b = CancelToken::new();
+            let guard_a = CtrlcGuard::install(&a).unwrap();
+            let guard_b = CtrlcGuard::install(&b).unwrap();
+
+            // Dropping the first guard leaves the second one working. The
+            // handler is called directly rather than raising a real SIGINT,
+            // which would reach every test in this process.
+            drop(guard_a);
+            on_sigint(libc::SIGINT);
+            assert!(b.is_cancelled());
+            assert!(!a.is_cancelled());
+
+            drop(guard_b);
+            let registry = REGISTRY.lock().unwrap();
+            assert!(registry.entries.is_empty());
+            assert!(registry.previous_action.is_none());
+        }
+    }
+}
+
+#[cfg(all(feature = "signals", not(unix)))]
+mod ctrlc {
+    use anyhow::{bail, Result};
+
+    use super::CancelToken;
+
+    pub(crate) struct CtrlcGuard;
+
+    impl CtrlcGuard {
+        pub(crate) fn install(_token: &CancelToken) -> Result<Self> {
+            bail!("Ctrl-C handling is only supported on Unix")
+        }
+    }
+}
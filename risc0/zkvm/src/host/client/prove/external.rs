@@ -17,7 +17,9 @@ use std::path::{Path, PathBuf};
 use anyhow::{ensure, Result};
 use risc0_binfmt::MemoryImage;
 
-use super::{Executor, Prover, ProverOpts};
+#[allow(deprecated)]
+use super::ProverOpts;
+use super::{cache::prove_cached, Executor, Prover};
 use crate::{
     host::api::AssetRequest, sha::Digestible, ApiClient, ExecutorEnv, Receipt, SessionInfo,
     VerifierContext,
@@ -40,6 +42,7 @@ impl ExternalProver {
     }
 }
 
+#[allow(deprecated)]
 impl Prover for ExternalProver {
     fn prove(
         &self,
@@ -48,24 +51,27 @@ impl Prover for ExternalProver {
         opts: &ProverOpts,
         image: MemoryImage,
     ) -> Result<Receipt> {
-        tracing::debug!("Launching {}", &self.r0vm_path.to_string_lossy());
+        prove_cached(env, ctx, opts, image, |env, image| {
+            tracing::debug!("Launching {}", &self.r0vm_path.to_string_lossy());
 
-        let image_id = image.compute_id();
-        let client = ApiClient::new_sub_process(&self.r0vm_path)?;
-        let receipt = client.prove(&env, opts.clone(), image.into())?;
-        if opts.prove_guest_errors {
-            receipt.verify_integrity_with_context(ctx)?;
-            ensure!(
-                receipt.get_metadata()?.pre.digest() == image_id,
-                "received unexpected image ID: expected {}, found {}",
-                hex::encode(&image_id),
-                hex::encode(&receipt.get_metadata()?.pre.digest())
-            );
-        } else {
-            receipt.verify_with_context(ctx, image_id)?;
-        }
+            let image_id = image.compute_id();
+            env.resolve_lazy_fds(image_id)?;
+            let client = ApiClient::new_sub_process(&self.r0vm_path)?;
+            let receipt = client.prove(&env, opts.clone(), image.into())?;
+            if opts.prove_guest_errors {
+                receipt.verify_integrity_with_context(ctx)?;
+                ensure!(
+                    receipt.get_metadata()?.pre.digest() == image_id,
+                    "received unexpected image ID: expected {}, found {}",
+                    hex::encode(&image_id),
+                    hex::encode(&receipt.get_metadata()?.pre.digest())
+                );
+            } else {
+                receipt.verify_with_context(ctx, image_id)?;
+            }
 
-        Ok(receipt)
+            Ok(receipt)
+        })
     }
 
     fn get_name(&self) -> String {
@@ -75,6 +81,7 @@ impl Prover for ExternalProver {
 
 impl Executor for ExternalProver {
     fn execute(&self, env: ExecutorEnv<'_>, image: MemoryImage) -> Result<SessionInfo> {
+        env.resolve_lazy_fds(image.compute_id())?;
         let client = ApiClient::new_sub_process(&self.r0vm_path)?;
         let segments_out = AssetRequest::Inline;
         client.execute(&env, image.into(), segments_out, |_, _| Ok(()))
@@ -12,19 +12,39 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use anyhow::Result;
+use std::{
+    cell::{Cell, RefCell},
+    collections::BTreeMap,
+    rc::Rc,
+    time::Instant,
+};
+
+use anyhow::{anyhow, Result};
 use risc0_binfmt::MemoryImage;
 
-use super::{Executor, Prover, ProverOpts};
+#[cfg(feature = "signals")]
+use super::cancel::CtrlcGuard;
+#[allow(deprecated)]
+use super::ProverOpts;
+use super::{
+    cache::prove_cached,
+    report::{seal_bytes, ExecutionReport},
+    Executor, Prover,
+};
 use crate::{
-    get_prover_server, ExecutorEnv, ExecutorImpl, Receipt, SegmentInfo, SessionInfo,
-    VerifierContext,
+    get_prover_server,
+    host::{client::provenance, server::prove::execute_with_hook},
+    ExecutorEnv, ExecutorImpl, FailedCheck, ProveOpts, Receipt, Segment, SegmentInfo,
+    SessionEvents, SessionInfo, VerifierContext,
 };
 
 /// A [Prover] implementation that selects a [crate::ProverServer] by calling
 /// [get_prover_server].
 pub struct LocalProver {
     name: String,
+    report: RefCell<Option<ExecutionReport>>,
+    post_image: RefCell<Option<MemoryImage>>,
+    journal_preimages: RefCell<Option<Vec<Vec<u8>>>>,
 }
 
 impl LocalProver {
@@ -32,10 +52,84 @@ impl LocalProver {
     pub fn new(name: &str) -> Self {
         Self {
             name: name.to_string(),
+            report: RefCell::new(None),
+            post_image: RefCell::new(None),
+            journal_preimages: RefCell::new(None),
         }
     }
+
+    #[allow(deprecated)]
+    fn prove_reported(
+        &self,
+        mut env: ExecutorEnv<'_>,
+        ctx: &VerifierContext,
+        opts: &ProverOpts,
+        image: MemoryImage,
+        report: &mut ExecutionReport,
+    ) -> Result<Receipt> {
+        let prover = get_prover_server(opts)?;
+        #[cfg(feature = "signals")]
+        let _ctrlc = match (opts.ctrlc_handling, &opts.cancel_token) {
+            (true, Some(token)) => Some(CtrlcGuard::install(token)?),
+            _ => None,
+        };
+        // Nested guests are proven like this one, so that their receipts can
+        // be assumptions of its session. The hook and observer only concern
+        // the segments of this guest.
+        env.guest_registry.prove_opts = ProveOpts {
+            segment_hook: None,
+            prove_observer: None,
+            ..opts.prove_opts()
+        };
+        let hashed_journal = env.hashed_journal;
+        let start = Instant::now();
+        let session = execute_with_hook(env, image, opts.segment_hook.as_ref());
+        report.phases.execute = start.elapsed();
+        let mut session = session?;
+        report.record_session(&session)?;
+        let verified = Rc::new(Cell::new(false));
+        session.add_hook(VerifiedHook(verified.clone()));
+        let retries = Rc::new(RefCell::new(BTreeMap::new()));
+        session.add_hook(RetryHook(retries.clone()));
+
+        let start = Instant::now();
+        let receipt = prover.prove_session(ctx, &session);
+        report.phases.prove = Some(start.elapsed());
+        for segment in report.segments.iter_mut() {
+            segment.retries = retries.borrow().get(&segment.index).copied().unwrap_or(0);
+        }
+        let mut receipt = receipt?;
+        report.seal_bytes = Some(seal_bytes(&receipt));
+        report.verified = verified.get();
+        provenance::annotate(&mut receipt, &opts.annotations);
+        self.post_image.replace(Some(session.post_image));
+        if hashed_journal {
+            self.journal_preimages
+                .replace(Some(session.journal_preimages));
+        }
+        Ok(receipt)
+    }
+}
+
+// Notes whether the prover checked the receipt against the session.
+struct VerifiedHook(Rc<Cell<bool>>);
+
+impl SessionEvents for VerifiedHook {
+    fn on_post_verify_session(&self, _receipt: &Receipt) {
+        self.0.set(true);
+    }
+}
+
+// Counts the retries of each segment, by index.
+struct RetryHook(Rc<RefCell<BTreeMap<u32, u32>>>);
+
+impl SessionEvents for RetryHook {
+    fn on_segment_retry(&self, segment: &Segment, _retry: u32) {
+        *self.0.borrow_mut().entry(segment.index).or_default() += 1;
+    }
 }
 
+#[allow(deprecated)]
 impl Prover for LocalProver {
     fn prove(
         &self,
@@ -44,12 +138,44 @@ impl Prover for LocalProver {
         opts: &ProverOpts,
         image: MemoryImage,
     ) -> Result<Receipt> {
-        get_prover_server(opts)?.prove(env, ctx, image)
+        self.post_image.take();
+        self.journal_preimages.take();
+        opts.check_setup(&env, None)?;
+        prove_cached(env, ctx, opts, image, |env, image| {
+            let mut report = ExecutionReport::new(&self.name, &opts.hashfn);
+            let result = self.prove_reported(env, ctx, opts, image, &mut report);
+            if let Err(err) = &result {
+                report.error = Some(format!("{err:#}"));
+                if let Some(check) = err.downcast_ref::<FailedCheck>() {
+                    report.failed_checks.push(*check);
+                }
+            }
+            self.report.replace(Some(report.clone()));
+            result.map_err(|err| err.context(report))
+        })
     }
 
     fn get_name(&self) -> String {
         self.name.clone()
     }
+
+    fn execution_report(&self) -> Option<ExecutionReport> {
+        self.report.borrow().clone()
+    }
+
+    fn post_image(&self) -> Result<MemoryImage> {
+        self.post_image.borrow().clone().ok_or_else(|| {
+            anyhow!("No post-state image: the most recent proof did not run the guest")
+        })
+    }
+
+    fn journal_preimages(&self) -> Result<Vec<Vec<u8>>> {
+        self.journal_preimages.borrow().clone().ok_or_else(|| {
+            anyhow!(
+                "No journal preimages: the most recent proof was not made with a hashed journal"
+            )
+        })
+    }
 }
 
 impl Executor for LocalProver {
@@ -68,6 +194,7 @@ impl Executor for LocalProver {
             segments,
             journal: session.journal.unwrap_or_default().into(),
             exit_code: session.exit_code,
+            heap_stats: session.heap_stats,
         })
     }
 }
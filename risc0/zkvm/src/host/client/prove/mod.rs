@@ -13,19 +13,42 @@
 // limitations under the License.
 
 pub(crate) mod bonsai;
+pub(crate) mod cache;
+#[cfg(feature = "prove")]
+pub(crate) mod cancel;
 pub(crate) mod external;
 #[cfg(feature = "prove")]
 pub(crate) mod local;
+pub(crate) mod prove_opts;
+pub(crate) mod report;
+pub(crate) mod setup;
 
-use std::{path::PathBuf, rc::Rc};
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+    rc::Rc,
+    sync::Arc,
+    time::Duration,
+};
 
-use anyhow::Result;
-use risc0_binfmt::{MemoryImage, Program};
-use risc0_zkvm_platform::{memory::GUEST_MAX_MEM, PAGE_SIZE};
+use anyhow::{bail, ensure, Result};
+use risc0_binfmt::{ImageHasher, MemoryImage};
 use serde::{Deserialize, Serialize};
 
-use self::{bonsai::BonsaiProver, external::ExternalProver};
-use crate::{is_dev_mode, ExecutorEnv, Receipt, SessionInfo, VerifierContext};
+use self::{
+    bonsai::BonsaiProver, cache::ProofCache, external::ExternalProver, prove_opts::ProveOpts,
+    report::ExecutionReport,
+};
+#[cfg(feature = "prove")]
+use crate::{
+    host::server::prove::{ProveObserver, SegmentHook},
+    CancelToken, DeterminismReport, Segment, SegmentAction, SegmentPo2Report,
+};
+use crate::{
+    is_dev_mode,
+    sha::{Digest, Digestible},
+    ExecutorEnv, FailedCheck, Receipt, SecurityPolicy, SessionInfo, TouchedPages, VerifierContext,
+};
 
 /// A Prover can execute a given [MemoryImage] or ELF binary and produce a
 /// [Receipt] that can be used to verify correct computation.
@@ -52,12 +75,12 @@ use crate::{is_dev_mode, ExecutorEnv, Receipt, SessionInfo, VerifierContext};
 /// # #[cfg(not(feature = "cuda"))]
 /// # {
 /// // A straightforward case with an ELF binary
-/// let env = ExecutorEnv::builder().write_slice(&[20]).build().unwrap();
+/// let env = ExecutorEnv::builder().write_words(&[20]).build().unwrap();
 /// let receipt = default_prover().prove_elf(env, FIB_ELF).unwrap();
 ///
 /// // Or you can specify a context and options
 /// // (Using the defaults as we do here is equivalent to the above code.)
-/// let env = ExecutorEnv::builder().write_slice(&[20]).build().unwrap();
+/// let env = ExecutorEnv::builder().write_words(&[20]).build().unwrap();
 /// let ctx = VerifierContext::default();
 /// let opts = ProverOpts::default();
 /// let receipt = default_prover().prove_elf_with_ctx(env, &ctx, FIB_ELF, &opts).unwrap();
@@ -67,12 +90,14 @@ use crate::{is_dev_mode, ExecutorEnv, Receipt, SessionInfo, VerifierContext};
 /// // to the above code.)
 /// let program = Program::load_elf(FIB_ELF, GUEST_MAX_MEM as u32).unwrap();
 /// let image = MemoryImage::new(&program, PAGE_SIZE as u32).unwrap();
-/// let env = ExecutorEnv::builder().write_slice(&[20]).build().unwrap();
+/// let env = ExecutorEnv::builder().write_words(&[20]).build().unwrap();
 /// let ctx = VerifierContext::default();
 /// let opts = ProverOpts::default();
 /// let receipt = default_prover().prove(env, &ctx, &opts, image).unwrap();
 /// # }
 /// ```
+// The methods taking ProverOpts are kept until it is removed.
+#[allow(deprecated)]
 pub trait Prover {
     /// Return a name for this [Prover].
     fn get_name(&self) -> String;
@@ -105,6 +130,9 @@ pub trait Prover {
         elf: &[u8],
         opts: &ProverOpts,
     ) -> Result<Receipt> {
+        // Report every problem with the setup before the ELF is loaded.
+        opts.check_setup(&env, Some(elf))?;
+
         #[cfg(feature = "profiler")]
         let mut env = env;
         #[cfg(feature = "profiler")]
@@ -116,8 +144,10 @@ pub trait Prover {
             })
             .transpose()?;
 
-        let program = Program::load_elf(elf, GUEST_MAX_MEM as u32)?;
-        let image = MemoryImage::new(&program, PAGE_SIZE as u32)?;
+        let image = match &opts.image_hasher {
+            Some(hasher) => env.layout.load_elf_with_hasher(elf, hasher.as_ref())?,
+            None => env.layout.load_elf(elf)?,
+        };
         let receipt = self.prove(env, ctx, opts, image)?;
 
         #[cfg(feature = "profiler")]
@@ -130,6 +160,164 @@ pub trait Prover {
 
         Ok(receipt)
     }
+
+    /// Prove zkVM execution starting from the specified ELF binary, with the
+    /// guest configured by `env` and the proof by `opts`.
+    ///
+    /// This is [Prover::prove_elf_with_ctx] for callers that have moved off
+    /// [ProverOpts].
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use risc0_zkvm::{default_prover, ExecutorEnv, ProveOpts, VerifierContext};
+    /// # let elf = &[];
+    ///
+    /// let env = ExecutorEnv::builder().build().unwrap();
+    /// let opts = ProveOpts::default().with_hashfn("sha-256");
+    /// let receipt = default_prover()
+    ///     .prove_elf_with_opts(env, &VerifierContext::default(), elf, &opts)
+    ///     .unwrap();
+    /// ```
+    fn prove_elf_with_opts(
+        &self,
+        env: ExecutorEnv<'_>,
+        ctx: &VerifierContext,
+        elf: &[u8],
+        opts: &ProveOpts,
+    ) -> Result<Receipt> {
+        self.prove_elf_with_ctx(env, ctx, elf, &ProverOpts::from(opts.clone()))
+    }
+
+    /// Prove zkVM execution starting from a [MemoryImage] saved with
+    /// [MemoryImage::save], with the specified [VerifierContext] and
+    /// [ProverOpts].
+    ///
+    /// This skips loading the ELF and building its page table, which can be a
+    /// noticeable part of proving short programs. If `image_id` is given, the
+    /// image must have that ID. The receipt is the same as one produced by
+    /// [Prover::prove_elf_with_ctx] for the ELF the image was built from.
+    fn prove_image_file(
+        &self,
+        env: ExecutorEnv<'_>,
+        ctx: &VerifierContext,
+        path: &Path,
+        image_id: Option<Digest>,
+        opts: &ProverOpts,
+    ) -> Result<Receipt> {
+        let image = MemoryImage::load(path)?;
+        if let Some(expected) = image_id {
+            let actual = image.compute_id();
+            ensure!(
+                actual == expected,
+                "Image ID mismatch for {}: {actual} != {expected}",
+                path.display()
+            );
+        }
+        self.prove(env, ctx, opts, image)
+    }
+
+    /// The [ExecutionReport] of the most recent run of this [Prover], if it
+    /// produces them.
+    ///
+    /// A receipt served from a [ProofCache] does not involve a run, and leaves
+    /// the report of the previous run in place.
+    fn execution_report(&self) -> Option<ExecutionReport> {
+        None
+    }
+
+    /// The [MemoryImage] at the end of the most recent proof by this
+    /// [Prover], for continuing execution from where it stopped.
+    ///
+    /// If the guest paused, the image's root and program counter are the post
+    /// state of the receipt's claim, so a receipt proved from this image, for
+    /// example with [Prover::prove] or after [MemoryImage::save] and
+    /// [Prover::prove_image_file], starts where the first one ended. A guest
+    /// that halted cannot be continued.
+    ///
+    /// Returns an error if the most recent proof failed or was served from a
+    /// [ProofCache], or if this [Prover] does not keep the image.
+    fn post_image(&self) -> Result<MemoryImage> {
+        bail!("{} does not keep post-state images", self.get_name())
+    }
+
+    /// The items committed by the guest of the most recent proof by this
+    /// [Prover], whose guest was executed with
+    /// [ExecutorEnvBuilder::hashed_journal](crate::ExecutorEnvBuilder::hashed_journal).
+    ///
+    /// A verifier given these items checks them against the receipt with
+    /// [Receipt::verify_with_preimages](crate::Receipt::verify_with_preimages).
+    ///
+    /// Returns an error if the most recent proof failed or was not made with
+    /// a hashed journal, or if this [Prover] does not keep the items.
+    fn journal_preimages(&self) -> Result<Vec<Vec<u8>>> {
+        bail!("{} does not keep journal preimages", self.get_name())
+    }
+
+    /// The checks made by the guest with `env::check` that failed during the
+    /// most recent run of this [Prover]; see [ExecutionReport::failed_checks].
+    fn failed_checks(&self) -> Vec<FailedCheck> {
+        self.execution_report()
+            .map(|report| report.failed_checks)
+            .unwrap_or_default()
+    }
+
+    /// The pages of memory read and written by the guest during the most
+    /// recent run of this [Prover]; see [ExecutionReport::touched_pages].
+    fn touched_pages(&self) -> TouchedPages {
+        self.execution_report()
+            .map(|report| report.touched_pages)
+            .unwrap_or_default()
+    }
+
+    /// Execute the ELF binary `elf` `n` times, without proving, and check that
+    /// every run does the same as the first, to diagnose nondeterminism in the
+    /// host.
+    ///
+    /// The runs are compared by where their segments start and end, by their
+    /// journals, and by a transcript of the syscalls answered by the host with
+    /// the data exchanged in each; see [DeterminismReport].
+    ///
+    /// Each run is given the [ExecutorEnv] returned by a call to `make_env`,
+    /// because the readers and handlers of an environment are consumed by a
+    /// run. For the check to be meaningful, every environment must have the
+    /// same options and present the same input: readers that yield the same
+    /// bytes, and handlers that would answer the same requests the same way.
+    /// State shared between the environments, such as a handler counting
+    /// calls across runs, shows up as a divergence. The transcript is taken
+    /// with [ExecutorEnvBuilder::syscall_audit], replacing any audit writer
+    /// set on the environments.
+    ///
+    /// The runs are made in this process for every [Prover].
+    ///
+    /// [ExecutorEnvBuilder::syscall_audit]: crate::ExecutorEnvBuilder::syscall_audit
+    #[cfg(feature = "prove")]
+    fn run_determinism_check<'a>(
+        &self,
+        n: usize,
+        make_env: &mut dyn FnMut() -> Result<ExecutorEnv<'a>>,
+        elf: &[u8],
+    ) -> Result<DeterminismReport> {
+        crate::host::server::exec::determinism::check(n, make_env, elf)
+    }
+
+    /// Execute the ELF binary `elf`, without proving, and estimate how much of
+    /// each segment would be spent paging memory in and out at each segment
+    /// size, to choose
+    /// [ExecutorEnvBuilder::segment_limit_po2](crate::ExecutorEnvBuilder::segment_limit_po2).
+    ///
+    /// Paging takes over once the pages the guest uses in a stretch of cycles
+    /// no longer fit comfortably in a segment, since each segment pages in
+    /// every page it touches. The guest is run once with small segments,
+    /// recording the pages each one touches, and larger segments are
+    /// estimated by merging them; see [SegmentPo2Report]. Any segment limit
+    /// set on `env` is ignored.
+    ///
+    /// The run is made in this process for every [Prover].
+    #[cfg(feature = "prove")]
+    fn recommend_segment_po2(&self, env: ExecutorEnv<'_>, elf: &[u8]) -> Result<SegmentPo2Report> {
+        crate::host::server::exec::working_set::recommend(env, elf)
+    }
 }
 
 /// An Executor can execute a given [MemoryImage] or ELF binary.
@@ -154,8 +342,8 @@ pub trait Executor {
             })
             .transpose()?;
 
-        let program = Program::load_elf(elf, GUEST_MAX_MEM as u32)?;
-        let image = MemoryImage::new(&program, PAGE_SIZE as u32)?;
+        env.check_input_schema(elf)?;
+        let image = env.layout.load_elf(elf)?;
         let session_info = self.execute(env, image)?;
 
         #[cfg(feature = "profiler")]
@@ -170,18 +358,118 @@ pub trait Executor {
     }
 }
 
+/// The smallest segment size, in powers of 2 cycles, accepted by
+/// [ExecutorEnvBuilder::segment_limit_po2](crate::ExecutorEnvBuilder::segment_limit_po2).
+pub const MIN_SEGMENT_LIMIT_PO2: u32 = risc0_zkp::MIN_CYCLES_PO2 as u32;
+
+/// The largest segment size, in powers of 2 cycles, accepted by
+/// [ExecutorEnvBuilder::segment_limit_po2](crate::ExecutorEnvBuilder::segment_limit_po2).
+pub const MAX_SEGMENT_LIMIT_PO2: u32 = risc0_zkp::MAX_CYCLES_PO2 as u32;
+
 /// Options to configure a [Prover].
+///
+/// [ProverOpts] combines the options that decide how a session is proven,
+/// which are also available on their own as
+/// [ProveOpts](crate::ProveOpts), with options that configure the guest and
+/// only take effect when it is executed. New code should configure the guest
+/// with its [ExecutorEnv] and the proof with `ProveOpts`, which allows a
+/// session executed once to be proven several times; see `SessionProver`.
+/// [ProverOpts] is kept for compatibility for one release.
+#[deprecated(
+    since = "0.20.0",
+    note = "configure the guest with ExecutorEnv and the proof with ProveOpts"
+)]
 #[derive(Clone, Serialize, Deserialize)]
 pub struct ProverOpts {
-    /// The hash function to use.
+    /// The hash function to use: `"poseidon"`, `"poseidon2"`, or `"sha-256"`.
     pub hashfn: String,
     /// When false, only prove execution sessions that end in a successful
     /// [crate::ExitCode] (i.e. `Halted(0)` or `Paused(0)`).
     /// When set to true, any completed execution session will be proven, including indicated
     /// errors (e.g. `Halted(1)`) and sessions ending in `Fault`.
     pub prove_guest_errors: bool,
+    /// Key-value annotations to attach to the produced [Receipt]. These are
+    /// not part of the proven claim; see [Receipt::annotations].
+    #[serde(default)]
+    pub annotations: BTreeMap<String, String>,
+    /// When true, a segment that runs out of device memory while being proven
+    /// is split in two and retried; see [ProverOpts::with_auto_po2_downshift].
+    #[serde(default)]
+    pub auto_po2_downshift: bool,
+    /// How many times a segment whose proving hits a device fault is proven
+    /// again; see [ProverOpts::with_segment_retries].
+    #[serde(default)]
+    pub segment_retries: u32,
+    /// How long to wait before the first retry of a segment; see
+    /// [ProverOpts::with_segment_retries].
+    #[serde(default)]
+    pub segment_retry_backoff: Duration,
+    /// Whether proving may fall back to dev mode; see
+    /// [ProverOpts::with_security_policy].
+    #[serde(default)]
+    pub security_policy: SecurityPolicy,
+    /// Which receipts are checked against their session after proving; see
+    /// [ProverOpts::with_verify_policy].
+    #[serde(default)]
+    pub verify_policy: VerifyPolicy,
+    /// When true, an ELF built with an incompatible or unknown version of
+    /// `risc0-zkvm-platform` is not proven; see
+    /// [ProverOpts::with_strict_platform_check].
+    #[serde(default)]
+    pub strict_platform_check: bool,
+    /// The image ID the guest being proven must have; see
+    /// [ProverOpts::with_expected_image_id].
+    #[serde(default)]
+    pub expected_image_id: Option<Digest>,
+    /// Prefixes of the host environment variables copied into the guest's
+    /// environment; see [ProverOpts::with_env_prefix].
+    #[serde(default)]
+    pub env_prefixes: Vec<EnvPrefix>,
+    /// Receipts made available to the guest as proven assumptions; see
+    /// [ProverOpts::with_assumption].
+    #[serde(default)]
+    pub assumptions: Vec<Receipt>,
+    /// When true, the guest may have the host reduce products of big
+    /// integers; see [ProverOpts::with_bigint_accel].
+    #[serde(default)]
+    pub bigint_accel: bool,
+    /// When true, the executor checks each instruction against reference
+    /// rv32im semantics; see [ProverOpts::with_isa_audit].
+    #[serde(default)]
+    pub isa_audit: bool,
+    /// Cache of receipts for executions that have already been proven; see
+    /// [ProverOpts::with_proof_cache].
+    #[serde(skip)]
+    pub(crate) proof_cache: Option<Arc<dyn ProofCache + Send + Sync>>,
+    /// Hashes the pages of the guest's memory image when an ELF is loaded;
+    /// see [ProverOpts::with_image_hasher].
+    #[serde(skip)]
+    pub(crate) image_hasher: Option<Arc<dyn ImageHasher + Send + Sync>>,
+    /// Selects host environment variables to copy into the guest's
+    /// environment; see [ProverOpts::with_env_filter].
+    #[serde(skip)]
+    pub(crate) env_filter: Option<EnvFilter>,
+    /// Callback run on each segment as it is executed; see
+    /// [ProverOpts::with_segment_hook].
+    #[cfg(feature = "prove")]
+    #[serde(skip)]
+    pub(crate) segment_hook: Option<SegmentHook>,
+    /// Callback run before each segment is proven.
+    #[cfg(feature = "prove")]
+    #[serde(skip)]
+    pub(crate) prove_observer: Option<ProveObserver>,
+    /// Stops proving when cancelled; see [ProverOpts::with_cancel_token].
+    #[cfg(feature = "prove")]
+    #[serde(skip)]
+    pub(crate) cancel_token: Option<CancelToken>,
+    /// When true, SIGINT cancels the proof; see
+    /// [ProverOpts::with_ctrlc_handling].
+    #[cfg(feature = "signals")]
+    #[serde(skip)]
+    pub(crate) ctrlc_handling: bool,
 }
 
+#[allow(deprecated)]
 impl Default for ProverOpts {
     /// Return [ProverOpts] with the SHA-256 hash function and
     /// `prove_guest_errors` set to false.
@@ -189,6 +477,546 @@ impl Default for ProverOpts {
         Self {
             hashfn: "poseidon".to_string(),
             prove_guest_errors: false,
+            annotations: BTreeMap::new(),
+            auto_po2_downshift: false,
+            segment_retries: 0,
+            segment_retry_backoff: Duration::ZERO,
+            security_policy: SecurityPolicy::default(),
+            verify_policy: VerifyPolicy::default(),
+            strict_platform_check: false,
+            expected_image_id: None,
+            env_prefixes: Vec::new(),
+            assumptions: Vec::new(),
+            bigint_accel: false,
+            isa_audit: false,
+            proof_cache: None,
+            image_hasher: None,
+            env_filter: None,
+            #[cfg(feature = "prove")]
+            segment_hook: None,
+            #[cfg(feature = "prove")]
+            prove_observer: None,
+            #[cfg(feature = "prove")]
+            cancel_token: None,
+            #[cfg(feature = "signals")]
+            ctrlc_handling: false,
+        }
+    }
+}
+
+#[allow(deprecated)]
+impl ProverOpts {
+    /// Attach an annotation to the produced [Receipt], e.g. the version of
+    /// the guest being proven.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use risc0_zkvm::ProverOpts;
+    ///
+    /// let opts = ProverOpts::default().with_annotation("guest_version", "1.2.3");
+    /// ```
+    pub fn with_annotation(mut self, key: &str, value: &str) -> Self {
+        self.annotations.insert(key.to_string(), value.to_string());
+        self
+    }
+
+    /// Recover from running out of device memory, e.g. on a GPU that is too
+    /// small for the segment size, by splitting the segment and retrying.
+    ///
+    /// Each time a segment fails to allocate, it is split into segments of
+    /// half the size (one less po2) and those are proven instead, down to the
+    /// smallest supported po2. The segment is split from the data recorded
+    /// during execution, so the guest is not executed again. The effective
+    /// po2 of each proof is available from
+    /// [SegmentReceipt::po2](crate::SegmentReceipt::po2).
+    ///
+    /// Only honored by provers running in this process, such as
+    /// [local::LocalProver].
+    pub fn with_auto_po2_downshift(mut self, auto_po2_downshift: bool) -> Self {
+        self.auto_po2_downshift = auto_po2_downshift;
+        self
+    }
+
+    /// Prove a segment again, up to `retries` times, when proving it fails
+    /// with a device fault, e.g. an ECC error or a driver reset on a GPU.
+    ///
+    /// Each retry is made on freshly constructed HALs, after waiting
+    /// `backoff` before the first retry and twice as long before each one
+    /// after it. Only failures the HAL reports as device faults are retried;
+    /// any other failure, such as a guest fault or a receipt that does not
+    /// verify, fails the proof at once. The number of retries of each segment
+    /// is reported in [SegmentReport::retries](crate::SegmentReport::retries).
+    ///
+    /// Honored by [local::LocalProver], [ExternalProver] and the API server.
+    /// The backoff is sent to the API server in whole milliseconds.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use std::time::Duration;
+    ///
+    /// use risc0_zkvm::ProverOpts;
+    ///
+    /// let opts = ProverOpts::default().with_segment_retries(3, Duration::from_secs(1));
+    /// ```
+    pub fn with_segment_retries(mut self, retries: u32, backoff: Duration) -> Self {
+        self.segment_retries = retries;
+        self.segment_retry_backoff = backoff;
+        self
+    }
+
+    /// Set the [SecurityPolicy] that decides whether dev mode is used, in
+    /// place of the `RISC0_DEV_MODE` environment variable alone.
+    ///
+    /// With [SecurityPolicy::Enforced], a real proof is always produced no
+    /// matter how the environment is set up. The receipt is then verified with
+    /// the [SecurityPolicy] of the [VerifierContext] passed to the prover.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use risc0_zkvm::{ProverOpts, SecurityPolicy};
+    ///
+    /// let opts = ProverOpts::default().with_security_policy(SecurityPolicy::Enforced);
+    /// ```
+    pub fn with_security_policy(mut self, security_policy: SecurityPolicy) -> Self {
+        self.security_policy = security_policy;
+        self
+    }
+
+    /// Set the [VerifyPolicy] that decides which receipts are checked
+    /// against their session after proving.
+    ///
+    /// The check verifies the receipt and compares its claim to the one
+    /// computed from the session, which catches faulty hardware or a bug in
+    /// the prover before a receipt leaves the process, at the cost of a
+    /// verification per receipt. Services that produce many receipts can
+    /// sample it instead, and those that verify every receipt downstream can
+    /// turn it off.
+    ///
+    /// Only honored by provers running in this process, such as
+    /// [local::LocalProver], and by `r0vm`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use risc0_zkvm::{ProverOpts, VerifyPolicy};
+    ///
+    /// let opts = ProverOpts::default().with_verify_policy(VerifyPolicy::SampleOneIn(100));
+    /// ```
+    pub fn with_verify_policy(mut self, verify_policy: VerifyPolicy) -> Self {
+        self.verify_policy = verify_policy;
+        self
+    }
+
+    /// Refuse to prove an ELF that was built with a version of
+    /// `risc0-zkvm-platform` that is not compatible with this one, or that
+    /// does not record its version; see
+    /// [Program::check_platform](crate::Program::check_platform).
+    ///
+    /// Such a guest may run, but misbehave where the syscalls or memory
+    /// layout changed. Without this, loading it only logs a warning.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use risc0_zkvm::ProverOpts;
+    ///
+    /// let opts = ProverOpts::default().with_strict_platform_check(true);
+    /// ```
+    pub fn with_strict_platform_check(mut self, strict_platform_check: bool) -> Self {
+        self.strict_platform_check = strict_platform_check;
+        self
+    }
+
+    /// Fail before executing anything unless the guest being proven has the
+    /// image ID `image_id`.
+    ///
+    /// This catches a stale ELF, e.g. one left over from an earlier build,
+    /// being proven while the receipt is to be verified against the ID of the
+    /// current build, which would otherwise only show up as a verification
+    /// failure once proving is over. Honored by the provers in this crate,
+    /// whether they are given an ELF, a [MemoryImage] or an image file.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use risc0_zkvm::{sha::Digest, ProverOpts};
+    ///
+    /// let opts = ProverOpts::default().with_expected_image_id(Digest::ZERO);
+    /// ```
+    pub fn with_expected_image_id(mut self, image_id: impl Into<Digest>) -> Self {
+        self.expected_image_id = Some(image_id.into());
+        self
+    }
+
+    /// Copy every host environment variable whose name starts with `prefix`
+    /// into the guest's environment, under the same name.
+    ///
+    /// The variables are read once, when proving starts, so changes made to
+    /// the host environment afterwards do not reach the guest. Variables set
+    /// on the [ExecutorEnv] with
+    /// [ExecutorEnvBuilder::env_var](crate::ExecutorEnvBuilder::env_var)
+    /// take precedence, and variables whose name or value is not valid
+    /// UTF-8 are skipped with a warning. Honored by [local::LocalProver] and
+    /// [ExternalProver].
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use risc0_zkvm::ProverOpts;
+    ///
+    /// let opts = ProverOpts::default().with_env_prefix("MY_GUEST_");
+    /// ```
+    pub fn with_env_prefix(mut self, prefix: &str) -> Self {
+        self.env_prefixes.push(EnvPrefix {
+            prefix: prefix.to_string(),
+            strip: false,
+        });
+        self
+    }
+
+    /// Copy host environment variables into the guest's environment as
+    /// [ProverOpts::with_env_prefix] does, with `prefix` removed from their
+    /// names, e.g. `MY_GUEST_LEVEL` is seen by the guest as `LEVEL`. A
+    /// variable named `prefix` itself is not copied.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use risc0_zkvm::ProverOpts;
+    ///
+    /// let opts = ProverOpts::default().with_env_prefix_stripped("MY_GUEST_");
+    /// ```
+    pub fn with_env_prefix_stripped(mut self, prefix: &str) -> Self {
+        self.env_prefixes.push(EnvPrefix {
+            prefix: prefix.to_string(),
+            strip: true,
+        });
+        self
+    }
+
+    /// Copy the host environment variables for which `filter` returns true,
+    /// given their name and value, into the guest's environment.
+    ///
+    /// The variables are selected when proving starts, as for
+    /// [ProverOpts::with_env_prefix], and in addition to those selected by
+    /// prefix.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use risc0_zkvm::ProverOpts;
+    ///
+    /// let opts = ProverOpts::default().with_env_filter(|name, _| name.starts_with("RUST_"));
+    /// ```
+    pub fn with_env_filter(
+        mut self,
+        filter: impl Fn(&str, &str) -> bool + Send + Sync + 'static,
+    ) -> Self {
+        self.env_filter = Some(Arc::new(filter));
+        self
+    }
+
+    /// Make `receipt` available to the guest as a proven assumption, e.g. to
+    /// roll up several receipts into one by verifying them in the guest.
+    ///
+    /// The guest reads the receipt, encoded with
+    /// [Receipt::into_assumption_bytes], through `env::read_assumption`, and
+    /// can resolve it with `env::verify`. Proving fails if the receipt was
+    /// not proven with the hash function of these [ProverOpts]. Honored by
+    /// [local::LocalProver] and [ExternalProver].
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use risc0_zkvm::{ProverOpts, Receipt};
+    ///
+    /// fn roll_up(receipts: Vec<Receipt>) -> ProverOpts {
+    ///     receipts
+    ///         .into_iter()
+    ///         .fold(ProverOpts::default(), |opts, receipt| opts.with_assumption(receipt))
+    /// }
+    /// ```
+    pub fn with_assumption(mut self, receipt: Receipt) -> Self {
+        self.assumptions.push(receipt);
+        self
+    }
+
+    /// Let the guest have the host reduce products of big integers with the
+    /// helpers of [guest::bigint](crate::guest::bigint), as
+    /// [ExecutorEnvBuilder::bigint_accel](crate::ExecutorEnvBuilder::bigint_accel)
+    /// does.
+    ///
+    /// Honored by [local::LocalProver], [ExternalProver] and the API server.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use risc0_zkvm::ProverOpts;
+    ///
+    /// let opts = ProverOpts::default().with_bigint_accel();
+    /// ```
+    pub fn with_bigint_accel(mut self) -> Self {
+        self.bigint_accel = true;
+        self
+    }
+
+    /// Execute the guest in ISA audit mode, as
+    /// [ExecutorEnvBuilder::isa_audit](crate::ExecutorEnvBuilder::isa_audit)
+    /// does: each instruction is checked against reference rv32im semantics,
+    /// and proving fails at the first divergence. This roughly doubles the
+    /// cost of execution, but not of proving.
+    ///
+    /// Honored by [local::LocalProver], [ExternalProver] and the API server.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use risc0_zkvm::ProverOpts;
+    ///
+    /// let opts = ProverOpts::default().with_isa_audit(true);
+    /// ```
+    pub fn with_isa_audit(mut self, isa_audit: bool) -> Self {
+        self.isa_audit = isa_audit;
+        self
+    }
+
+    // Add the receipts of [ProverOpts::assumptions] to the assumptions of
+    // `env`, once each is checked to be proven with [ProverOpts::hashfn].
+    pub(crate) fn add_assumptions(&self, env: &mut ExecutorEnv<'_>) -> Result<()> {
+        for receipt in &self.assumptions {
+            receipt.inner.check_hashfn(&self.hashfn)?;
+            env.assumptions
+                .borrow_mut()
+                .cached
+                .push(receipt.clone().into());
+        }
+        Ok(())
+    }
+
+    // Copy the host environment variables selected by
+    // [ProverOpts::env_prefixes] and [ProverOpts::env_filter] into the guest
+    // environment of `env`.
+    pub(crate) fn capture_env(&self, env: &mut ExecutorEnv<'_>) {
+        if self.env_prefixes.is_empty() && self.env_filter.is_none() {
+            return;
+        }
+        for (name, value) in std::env::vars_os() {
+            let (Some(name), Some(value)) = (name.to_str(), value.to_str()) else {
+                let name = name.to_string_lossy();
+                if self.env_filter.is_some()
+                    || self
+                        .env_prefixes
+                        .iter()
+                        .any(|p| name.starts_with(&p.prefix))
+                {
+                    tracing::warn!(
+                        "Not copying host environment variable {name:?} into the guest: it is not valid UTF-8"
+                    );
+                }
+                continue;
+            };
+            let mut names: Vec<&str> = self
+                .env_prefixes
+                .iter()
+                .filter_map(|p| {
+                    let rest = name.strip_prefix(&p.prefix)?;
+                    Some(if p.strip { rest } else { name })
+                })
+                .filter(|name| !name.is_empty())
+                .collect();
+            if self.env_filter.as_ref().map_or(false, |f| f(name, value)) {
+                names.push(name);
+            }
+            for name in names {
+                env.env_vars
+                    .entry(name.to_string())
+                    .or_insert_with(|| value.to_string());
+            }
+        }
+    }
+
+    /// Reuse receipts from `cache` for executions that have already been
+    /// proven.
+    ///
+    /// Before proving, the prover looks up a receipt for the same image ID,
+    /// input (as written to the [ExecutorEnv], along with its environment
+    /// variables, arguments, registered guests and assumptions), hash function
+    /// and zkVM version. A receipt that is found is verified and returned
+    /// without executing the guest. Otherwise the guest is proven and the
+    /// receipt is stored in the cache. Receipts produced by a different
+    /// version of the zkVM are never reused.
+    ///
+    /// Executions whose input cannot be digested, e.g. because the guest
+    /// reads from a reader passed to
+    /// [ExecutorEnvBuilder::stdin](crate::ExecutorEnvBuilder::stdin) or from
+    /// a handler passed to
+    /// [ExecutorEnvBuilder::slice_io](crate::ExecutorEnvBuilder::slice_io),
+    /// are always proven.
+    ///
+    /// Only honored by [local::LocalProver] and [ExternalProver].
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use risc0_zkvm::{FileProofCache, ProverOpts};
+    ///
+    /// let opts = ProverOpts::default().with_proof_cache(FileProofCache::new("target/proofs"));
+    /// ```
+    pub fn with_proof_cache(mut self, cache: impl ProofCache + Send + Sync + 'static) -> Self {
+        self.proof_cache = Some(Arc::new(cache));
+        self
+    }
+
+    /// Hash the pages of the guest's memory image with `hasher` when proving
+    /// an ELF, in place of the
+    /// [default_image_hasher](crate::default_image_hasher) chosen for this
+    /// CPU.
+    ///
+    /// Every [ImageHasher] builds the same Merkle tree, so this changes how
+    /// long it takes to load a large guest, not its image ID. The ELF is
+    /// loaded in this process by every [Prover], including those that prove
+    /// elsewhere.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use risc0_zkvm::{ProverOpts, ScalarImageHasher};
+    ///
+    /// let opts = ProverOpts::default().with_image_hasher(ScalarImageHasher);
+    /// ```
+    pub fn with_image_hasher(mut self, hasher: impl ImageHasher + Send + Sync + 'static) -> Self {
+        self.image_hasher = Some(Arc::new(hasher));
+        self
+    }
+
+    /// Install a hook that is called with each [Segment] as soon as execution
+    /// produces it, while the guest is still running, e.g. to checkpoint
+    /// segments to external storage.
+    ///
+    /// The returned [SegmentAction] decides whether the segment is proven,
+    /// skipped, or whether execution and proving stop altogether. An error
+    /// returned by the hook aborts the run. The hook is only honored by
+    /// provers that execute the guest in this process, such as
+    /// [local::LocalProver]; it is not run when proving a [Session] that has
+    /// already been executed.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use risc0_zkvm::{ProverOpts, SegmentAction};
+    ///
+    /// let opts = ProverOpts::default().with_segment_hook(|segment| {
+    ///     println!("segment {}: {} cycles", segment.index, segment.cycles);
+    ///     Ok(SegmentAction::Prove)
+    /// });
+    /// ```
+    #[cfg(feature = "prove")]
+    pub fn with_segment_hook(
+        mut self,
+        hook: impl FnMut(&Segment) -> Result<SegmentAction> + Send + 'static,
+    ) -> Self {
+        self.segment_hook = Some(Arc::new(std::sync::Mutex::new(hook)));
+        self
+    }
+
+    /// Stop proving when `token` is cancelled.
+    ///
+    /// The token is checked before each segment is proven. A cancelled proof
+    /// fails with a [ProvingCancelled](crate::ProvingCancelled) error holding
+    /// the receipts of the segments proven so far, so that they can be kept.
+    /// The token is only honored by provers running in this process, such as
+    /// [local::LocalProver].
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use risc0_zkvm::{CancelToken, ProverOpts};
+    ///
+    /// let token = CancelToken::new();
+    /// let opts = ProverOpts::default().with_cancel_token(token.clone());
+    /// // Later, e.g. from another thread:
+    /// token.cancel();
+    /// ```
+    #[cfg(feature = "prove")]
+    pub fn with_cancel_token(mut self, token: CancelToken) -> Self {
+        self.cancel_token = Some(token);
+        self
+    }
+
+    /// Cancel the proof when the process receives SIGINT, e.g. from Ctrl-C,
+    /// rather than letting it terminate the process.
+    ///
+    /// The signal cancels the [CancelToken] set with
+    /// [ProverOpts::with_cancel_token], or a new one if none is set, and the
+    /// proof stops as described there. A second SIGINT terminates the process
+    /// as usual. The handler is installed for the duration of each run of
+    /// [local::LocalProver], and the previous disposition of SIGINT is
+    /// restored afterwards. Concurrent runs share the handler, and a SIGINT
+    /// cancels all of them. Only supported on Unix.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use risc0_zkvm::ProverOpts;
+    ///
+    /// let opts = ProverOpts::default().with_ctrlc_handling(true);
+    /// ```
+    #[cfg(feature = "signals")]
+    pub fn with_ctrlc_handling(mut self, enable: bool) -> Self {
+        if enable {
+            self.cancel_token.get_or_insert_with(CancelToken::new);
+        }
+        self.ctrlc_handling = enable;
+        self
+    }
+}
+
+/// A prefix of the names of the host environment variables copied into the
+/// guest's environment; see [ProverOpts::with_env_prefix].
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct EnvPrefix {
+    /// The prefix of the names.
+    pub prefix: String,
+
+    /// Whether the prefix is removed from the names seen by the guest.
+    pub strip: bool,
+}
+
+// Selects host environment variables by name and value.
+type EnvFilter = Arc<dyn Fn(&str, &str) -> bool + Send + Sync>;
+
+/// Decides which receipts a [Prover] checks against their session after
+/// proving; see [ProverOpts::with_verify_policy].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum VerifyPolicy {
+    /// Check every receipt.
+    #[default]
+    Always,
+
+    /// Never check receipts.
+    Never,
+
+    /// Check about one in every `n` receipts, chosen by a hash of the image
+    /// ID and the number of cycles, so that the same execution is always
+    /// either checked or not. `SampleOneIn(0)` checks none.
+    SampleOneIn(u32),
+}
+
+impl VerifyPolicy {
+    /// Returns `true` if this policy checks the receipt of an execution of
+    /// the image with the given ID that ran for `cycles` user cycles.
+    pub fn selects(&self, image_id: impl Into<Digest>, cycles: u64) -> bool {
+        match *self {
+            VerifyPolicy::Always => true,
+            VerifyPolicy::Never | VerifyPolicy::SampleOneIn(0) => false,
+            VerifyPolicy::SampleOneIn(n) => {
+                let image_id = image_id.into();
+                let seed = [image_id.as_bytes(), &cycles.to_le_bytes()].concat();
+                seed.as_slice().digest().as_words()[0] % n == 0
+            }
         }
     }
 }
@@ -0,0 +1,208 @@
+// Copyright 2023 RISC Zero, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Options for how a session is proven, kept apart from the [ExecutorEnv]
+//! it is executed with.
+//!
+//! [ProverOpts] mixes the two: besides the proving options it holds options
+//! that apply to the guest before it runs, such as
+//! [ProverOpts::with_env_prefix], which only take effect when the guest is
+//! executed again for every proof.
+//! [ProveOpts] holds only what the prover needs, so a session executed once
+//! can be proven any number of times with different options; see
+//! `SessionProver`.
+//!
+//! [ExecutorEnv]: crate::ExecutorEnv
+
+use std::{collections::BTreeMap, time::Duration};
+
+use serde::{Deserialize, Serialize};
+
+#[allow(deprecated)]
+use super::ProverOpts;
+use super::VerifyPolicy;
+use crate::SecurityPolicy;
+#[cfg(feature = "prove")]
+use crate::{
+    host::server::prove::{ProveObserver, SegmentHook},
+    CancelToken,
+};
+
+/// Options that decide how a session is proven, but not how it is executed.
+///
+/// Each field means the same as the [ProverOpts] field of the same name,
+/// and each builder method does the same as the [ProverOpts] method of the
+/// same name.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct ProveOpts {
+    /// The hash function to use: `"poseidon"`, `"poseidon2"`, or `"sha-256"`.
+    pub hashfn: String,
+    /// When true, sessions that end in an error or a fault are proven too;
+    /// see [ProverOpts::prove_guest_errors].
+    pub prove_guest_errors: bool,
+    /// Key-value annotations to attach to the produced receipt; see
+    /// [ProverOpts::with_annotation].
+    pub annotations: BTreeMap<String, String>,
+    /// See [ProverOpts::with_auto_po2_downshift].
+    pub auto_po2_downshift: bool,
+    /// See [ProverOpts::with_segment_retries].
+    pub segment_retries: u32,
+    /// See [ProverOpts::with_segment_retries].
+    pub segment_retry_backoff: Duration,
+    /// See [ProverOpts::with_security_policy].
+    pub security_policy: SecurityPolicy,
+    /// See [ProverOpts::with_verify_policy].
+    pub verify_policy: VerifyPolicy,
+    #[cfg(feature = "prove")]
+    #[serde(skip)]
+    pub(crate) cancel_token: Option<CancelToken>,
+    #[cfg(feature = "prove")]
+    #[serde(skip)]
+    pub(crate) segment_hook: Option<SegmentHook>,
+    #[cfg(feature = "prove")]
+    #[serde(skip)]
+    pub(crate) prove_observer: Option<ProveObserver>,
+}
+
+impl Default for ProveOpts {
+    /// Return the proving options of [ProverOpts::default]: the poseidon
+    /// hash function, and only successful sessions are proven.
+    fn default() -> Self {
+        Self {
+            hashfn: "poseidon".to_string(),
+            prove_guest_errors: false,
+            annotations: BTreeMap::new(),
+            auto_po2_downshift: false,
+            segment_retries: 0,
+            segment_retry_backoff: Duration::ZERO,
+            security_policy: SecurityPolicy::default(),
+            verify_policy: VerifyPolicy::default(),
+            #[cfg(feature = "prove")]
+            cancel_token: None,
+            #[cfg(feature = "prove")]
+            segment_hook: None,
+            #[cfg(feature = "prove")]
+            prove_observer: None,
+        }
+    }
+}
+
+impl ProveOpts {
+    /// Set the hash function to use.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use risc0_zkvm::ProveOpts;
+    ///
+    /// let opts = ProveOpts::default().with_hashfn("sha-256");
+    /// ```
+    pub fn with_hashfn(mut self, hashfn: &str) -> Self {
+        self.hashfn = hashfn.to_string();
+        self
+    }
+
+    /// See [ProverOpts::prove_guest_errors].
+    pub fn with_prove_guest_errors(mut self, prove_guest_errors: bool) -> Self {
+        self.prove_guest_errors = prove_guest_errors;
+        self
+    }
+
+    /// See [ProverOpts::with_annotation].
+    pub fn with_annotation(mut self, key: &str, value: &str) -> Self {
+        self.annotations.insert(key.to_string(), value.to_string());
+        self
+    }
+
+    /// See [ProverOpts::with_auto_po2_downshift].
+    pub fn with_auto_po2_downshift(mut self, auto_po2_downshift: bool) -> Self {
+        self.auto_po2_downshift = auto_po2_downshift;
+        self
+    }
+
+    /// See [ProverOpts::with_segment_retries].
+    pub fn with_segment_retries(mut self, retries: u32, backoff: Duration) -> Self {
+        self.segment_retries = retries;
+        self.segment_retry_backoff = backoff;
+        self
+    }
+
+    /// See [ProverOpts::with_security_policy].
+    pub fn with_security_policy(mut self, security_policy: SecurityPolicy) -> Self {
+        self.security_policy = security_policy;
+        self
+    }
+
+    /// See [ProverOpts::with_verify_policy].
+    pub fn with_verify_policy(mut self, verify_policy: VerifyPolicy) -> Self {
+        self.verify_policy = verify_policy;
+        self
+    }
+
+    /// See [ProverOpts::with_cancel_token].
+    #[cfg(feature = "prove")]
+    pub fn with_cancel_token(mut self, token: CancelToken) -> Self {
+        self.cancel_token = Some(token);
+        self
+    }
+}
+
+#[allow(deprecated)]
+impl ProverOpts {
+    /// The proving options of these [ProverOpts], without the ones that
+    /// configure the guest.
+    pub fn prove_opts(&self) -> ProveOpts {
+        ProveOpts {
+            hashfn: self.hashfn.clone(),
+            prove_guest_errors: self.prove_guest_errors,
+            annotations: self.annotations.clone(),
+            auto_po2_downshift: self.auto_po2_downshift,
+            segment_retries: self.segment_retries,
+            segment_retry_backoff: self.segment_retry_backoff,
+            security_policy: self.security_policy,
+            verify_policy: self.verify_policy,
+            #[cfg(feature = "prove")]
+            cancel_token: self.cancel_token.clone(),
+            #[cfg(feature = "prove")]
+            segment_hook: self.segment_hook.clone(),
+            #[cfg(feature = "prove")]
+            prove_observer: self.prove_observer.clone(),
+        }
+    }
+}
+
+#[allow(deprecated)]
+impl From<ProveOpts> for ProverOpts {
+    /// [ProverOpts] with the given proving options, and the defaults for the
+    /// options that configure the guest.
+    fn from(opts: ProveOpts) -> Self {
+        Self {
+            hashfn: opts.hashfn,
+            prove_guest_errors: opts.prove_guest_errors,
+            annotations: opts.annotations,
+            auto_po2_downshift: opts.auto_po2_downshift,
+            segment_retries: opts.segment_retries,
+            segment_retry_backoff: opts.segment_retry_backoff,
+            security_policy: opts.security_policy,
+            verify_policy: opts.verify_policy,
+            #[cfg(feature = "prove")]
+            cancel_token: opts.cancel_token,
+            #[cfg(feature = "prove")]
+            segment_hook: opts.segment_hook,
+            #[cfg(feature = "prove")]
+            prove_observer: opts.prove_observer,
+            ..Default::default()
+        }
+    }
+}
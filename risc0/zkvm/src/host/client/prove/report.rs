@@ -0,0 +1,233 @@
+// Copyright 2023 RISC Zero, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! A machine-readable summary of a run of a [Prover](super::Prover).
+
+use std::{fmt, path::Path, time::Duration};
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+
+#[cfg(feature = "prove")]
+use crate::{host::api::convert::RECEIPT_FORMAT_VERSION, InnerReceipt, Receipt, Session, VERSION};
+use crate::{ExitCode, FailedCheck, HeapStats, TouchedPages};
+
+/// The version of the layout of [ExecutionReport].
+///
+/// Fields may be added to the report without changing its version, but the
+/// version is bumped whenever a field is removed, renamed or changes meaning.
+pub const REPORT_VERSION: u32 = 1;
+
+/// A summary of a run of a [Prover](super::Prover), for tooling and CI.
+///
+/// See [Prover::execution_report](super::Prover::execution_report). A report
+/// is also produced for a run that fails, with whatever was measured before
+/// the failure; the error returned by the prover then carries a copy of the
+/// report, which can be recovered with [anyhow::Error::downcast_ref].
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ExecutionReport {
+    /// The layout of this report; see [REPORT_VERSION].
+    pub report_version: u32,
+
+    /// The version of the zkVM that produced this report.
+    pub version: String,
+
+    /// The version of the encoding of receipts produced by this zkVM.
+    pub receipt_format_version: u8,
+
+    /// The name of the prover, as given by
+    /// [Prover::get_name](super::Prover::get_name).
+    pub prover: String,
+
+    /// The hash function used to prove; see
+    /// [ProverOpts::hashfn](super::ProverOpts::hashfn).
+    pub hashfn: String,
+
+    /// How the guest exited, if execution completed.
+    pub exit_code: Option<ExitCode>,
+
+    /// The number of user cycles, without any overhead for continuations or
+    /// po2 padding.
+    pub total_cycles: u64,
+
+    /// The segments of the session, in order.
+    pub segments: Vec<SegmentReport>,
+
+    /// The data exchanged between the guest and the host.
+    pub io: IoReport,
+
+    /// How much of the heap the guest used.
+    pub heap_stats: HeapStats,
+
+    /// The checks made by the guest with `env::check` that failed.
+    #[serde(default)]
+    pub failed_checks: Vec<FailedCheck>,
+
+    /// The pages of memory the guest read and wrote, over all segments.
+    #[serde(default)]
+    pub touched_pages: TouchedPages,
+
+    /// The total size of the seals of the receipt, in bytes, if proving
+    /// completed.
+    pub seal_bytes: Option<usize>,
+
+    /// Whether the receipt was checked against the session after proving;
+    /// see [ProverOpts::with_verify_policy](super::ProverOpts::with_verify_policy).
+    #[serde(default)]
+    pub verified: bool,
+
+    /// The wall time spent in each phase of the run.
+    pub phases: PhaseTimes,
+
+    /// The error the run failed with, if any.
+    pub error: Option<String>,
+}
+
+/// The part of an [ExecutionReport] describing one segment.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SegmentReport {
+    /// The index of the segment within the session.
+    pub index: u32,
+
+    /// The number of cycles in powers of 2.
+    pub po2: u32,
+
+    /// The number of user cycles without any overhead for continuations or
+    /// po2 padding.
+    pub cycles: u32,
+
+    /// The number of syscalls the guest made within the segment.
+    pub syscalls: usize,
+
+    /// The number of times proving the segment was retried after a device
+    /// fault; see
+    /// [ProverOpts::with_segment_retries](super::ProverOpts::with_segment_retries).
+    #[serde(default)]
+    pub retries: u32,
+}
+
+/// The part of an [ExecutionReport] describing the guest's I/O.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct IoReport {
+    /// The number of syscalls the guest made.
+    pub syscalls: usize,
+
+    /// The number of bytes returned to the guest by syscalls, including
+    /// reads from stdin.
+    pub syscall_bytes: usize,
+
+    /// The number of bytes committed to the journal.
+    pub journal_bytes: usize,
+}
+
+/// The wall time of the phases of a run.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct PhaseTimes {
+    /// The time spent executing the guest.
+    pub execute: Duration,
+
+    /// The time spent proving the session, if proving started.
+    pub prove: Option<Duration>,
+}
+
+impl ExecutionReport {
+    #[cfg(feature = "prove")]
+    pub(crate) fn new(prover: &str, hashfn: &str) -> Self {
+        Self {
+            report_version: REPORT_VERSION,
+            version: VERSION.to_string(),
+            receipt_format_version: RECEIPT_FORMAT_VERSION,
+            prover: prover.to_string(),
+            hashfn: hashfn.to_string(),
+            exit_code: None,
+            total_cycles: 0,
+            segments: Vec::new(),
+            io: IoReport::default(),
+            heap_stats: HeapStats::default(),
+            failed_checks: Vec::new(),
+            touched_pages: TouchedPages::default(),
+            seal_bytes: None,
+            verified: false,
+            phases: PhaseTimes::default(),
+            error: None,
+        }
+    }
+
+    /// Returns `true` if the run completed without error.
+    pub fn is_ok(&self) -> bool {
+        self.error.is_none()
+    }
+
+    /// Write this report to the file at `path` as JSON, replacing any
+    /// existing file.
+    pub fn write_json(&self, path: impl AsRef<Path>) -> Result<()> {
+        let file = std::fs::File::create(path)?;
+        Ok(serde_json::to_writer_pretty(file, self)?)
+    }
+
+    #[cfg(feature = "prove")]
+    pub(crate) fn record_session(&mut self, session: &Session) -> Result<()> {
+        for segment in session.segments.iter() {
+            let segment = segment.resolve()?;
+            self.total_cycles += segment.cycles as u64;
+            self.io.syscalls += segment.syscalls.len();
+            self.io.syscall_bytes += segment
+                .syscalls
+                .iter()
+                .map(|syscall| syscall.to_guest.len() * std::mem::size_of::<u32>())
+                .sum::<usize>();
+            let faults = &segment.faults;
+            self.touched_pages.read.extend(&faults.reads);
+            self.touched_pages.written.extend(&faults.writes);
+            self.segments.push(SegmentReport {
+                index: segment.index,
+                po2: segment.po2,
+                cycles: segment.cycles,
+                syscalls: segment.syscalls.len(),
+                retries: 0,
+            });
+        }
+        self.exit_code = Some(session.exit_code);
+        self.io.journal_bytes = session.journal.as_ref().map_or(0, |x| x.bytes.len());
+        self.heap_stats = session.heap_stats;
+        self.failed_checks = session.failed_checks.clone();
+        Ok(())
+    }
+}
+
+impl fmt::Display for ExecutionReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} prover run failed after {} cycles in {} segments",
+            self.prover,
+            self.total_cycles,
+            self.segments.len()
+        )
+    }
+}
+
+/// The total size of the seals of `receipt`, in bytes.
+#[cfg(feature = "prove")]
+pub(crate) fn seal_bytes(receipt: &Receipt) -> usize {
+    match &receipt.inner {
+        InnerReceipt::Composite(inner) => inner
+            .segments
+            .iter()
+            .map(|segment| segment.get_seal_bytes().len())
+            .sum(),
+        InnerReceipt::Succinct(inner) => inner.get_seal_bytes().len(),
+        InnerReceipt::Fake { .. } => 0,
+    }
+}
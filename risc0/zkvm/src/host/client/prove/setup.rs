@@ -0,0 +1,207 @@
+// Copyright 2023 RISC Zero, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Checks of an [ExecutorEnv] and [ProverOpts] made before anything is
+//! executed, which report every problem found at once.
+
+use std::fmt;
+
+use anyhow::Result;
+use risc0_binfmt::{MemoryImage, PlatformCheck, Program};
+
+#[allow(deprecated)]
+use super::ProverOpts;
+use super::{MAX_SEGMENT_LIMIT_PO2, MIN_SEGMENT_LIMIT_PO2};
+use crate::{sha::Digest, ExecutorEnv};
+
+/// The hash functions accepted by [ProverOpts::hashfn].
+const HASHFNS: &[&str] = &["poseidon", "poseidon2", "sha-256"];
+
+/// A problem with an [ExecutorEnv] or [ProverOpts] that keeps the guest from
+/// being executed or proven; see [SetupErrors].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum OptsError {
+    /// An environment variable has an empty name or one containing a NUL.
+    InvalidEnvVarName(String),
+
+    /// The journal's file descriptor was registered for writing.
+    JournalRedirected,
+
+    /// A file descriptor is registered for both reading and writing, other
+    /// than with
+    /// [ExecutorEnvBuilder::duplex_fd](crate::ExecutorEnvBuilder::duplex_fd).
+    ConflictingFd(u32),
+
+    /// The slice I/O handlers are registered inconsistently.
+    SliceIo(String),
+
+    /// Two named inputs have the same name.
+    DuplicateNamedInput(String),
+
+    /// An input buffer was combined with input written to stdin.
+    InputBufferWithWrite,
+
+    /// [ProverOpts::hashfn] names no supported hash function.
+    UnsupportedHashFn(String),
+
+    /// The segment limit is outside [MIN_SEGMENT_LIMIT_PO2] to
+    /// [MAX_SEGMENT_LIMIT_PO2].
+    InvalidSegmentLimitPo2(u32),
+
+    /// The host wrote input with a schema that the guest does not declare.
+    InputSchemaMismatch {
+        /// The schema of the input the host wrote.
+        host: Digest,
+        /// The schema the guest declares, if any.
+        guest: Option<Digest>,
+    },
+
+    /// The guest failed [ProverOpts::with_strict_platform_check].
+    PlatformCheck(PlatformCheck),
+
+    /// The guest does not have the image ID set with
+    /// [ProverOpts::with_expected_image_id].
+    ImageIdMismatch {
+        /// The image ID expected by the options.
+        expected: Digest,
+        /// The image ID of the guest.
+        actual: Digest,
+    },
+}
+
+impl fmt::Display for OptsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            OptsError::InvalidEnvVarName(name) => {
+                write!(f, "invalid environment variable name: {name:?}")
+            }
+            OptsError::JournalRedirected => write!(
+                f,
+                "the journal file descriptor cannot be redirected; use journal_tee to copy it"
+            ),
+            OptsError::ConflictingFd(fd) => write!(
+                f,
+                "file descriptor {fd} is registered for both reading and writing; use duplex_fd if this is intended"
+            ),
+            OptsError::SliceIo(msg) => write!(f, "{msg}"),
+            OptsError::DuplicateNamedInput(name) => write!(f, "duplicate named input: {name:?}"),
+            OptsError::InputBufferWithWrite => write!(
+                f,
+                "input_buffer cannot be combined with write or the other write_* methods"
+            ),
+            OptsError::UnsupportedHashFn(hashfn) => write!(f, "Unsupported hashfn: {hashfn}"),
+            OptsError::InvalidSegmentLimitPo2(po2) => write!(
+                f,
+                "Invalid segment_limit_po2: {po2}, must be between {MIN_SEGMENT_LIMIT_PO2} and {MAX_SEGMENT_LIMIT_PO2}"
+            ),
+            OptsError::InputSchemaMismatch {
+                host,
+                guest: Some(guest),
+            } => write!(
+                f,
+                "Input schema mismatch: host wrote {host} but the guest expects {guest}"
+            ),
+            OptsError::InputSchemaMismatch { host, guest: None } => write!(
+                f,
+                "Input schema mismatch: host wrote {host} but the guest does not declare an input schema"
+            ),
+            OptsError::PlatformCheck(check) => write!(f, "Strict platform check failed: {check}"),
+            OptsError::ImageIdMismatch { expected, actual } => write!(
+                f,
+                "Image ID mismatch: the guest being proven has image ID {actual}, but the prover options expect {expected}; is the ELF out of date?"
+            ),
+        }
+    }
+}
+
+/// Every problem found with an [ExecutorEnv] or [ProverOpts] before the guest
+/// was executed.
+///
+/// Returned, wrapped in an [anyhow::Error], by
+/// [ExecutorEnvBuilder::build](crate::ExecutorEnvBuilder::build) and by the
+/// provers in this crate before they load the guest or set up a prover, so
+/// that all the problems can be fixed before the next run. Problems that can
+/// only be found from the loaded guest, such as an
+/// [OptsError::ImageIdMismatch], are reported once the others are fixed.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SetupErrors(pub Vec<OptsError>);
+
+impl SetupErrors {
+    // `Err(self)` if any problems were found.
+    pub(crate) fn check(self) -> Result<()> {
+        if self.0.is_empty() {
+            Ok(())
+        } else {
+            Err(self.into())
+        }
+    }
+}
+
+impl fmt::Display for SetupErrors {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.0.as_slice() {
+            [err] => write!(f, "{err}"),
+            errs => {
+                write!(f, "{} problems with the setup:", errs.len())?;
+                for err in errs {
+                    write!(f, "\n  - {err}")?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl std::error::Error for SetupErrors {}
+
+#[allow(deprecated)]
+impl ProverOpts {
+    /// Check these options, and the parts of `env` that are only checked
+    /// when the guest starts, without loading the guest or setting up a
+    /// prover. The ELF of the guest, if it is proven from one, is only
+    /// parsed.
+    pub(crate) fn check_setup(&self, env: &ExecutorEnv<'_>, elf: Option<&[u8]>) -> Result<()> {
+        let mut errs = Vec::new();
+        if !self.security_policy.is_dev_mode() && !HASHFNS.contains(&self.hashfn.as_str()) {
+            errs.push(OptsError::UnsupportedHashFn(self.hashfn.clone()));
+        }
+        if let Some(po2) = env
+            .segment_limit_po2
+            .filter(|po2| !(MIN_SEGMENT_LIMIT_PO2..=MAX_SEGMENT_LIMIT_PO2).contains(po2))
+        {
+            errs.push(OptsError::InvalidSegmentLimitPo2(po2));
+        }
+        if let Some(elf) = elf {
+            errs.extend(env.input_schema_error(elf)?);
+            if self.strict_platform_check {
+                match Program::check_platform(elf)? {
+                    PlatformCheck::Compatible => {}
+                    check => errs.push(OptsError::PlatformCheck(check)),
+                }
+            }
+        }
+        SetupErrors(errs).check()
+    }
+
+    // Check `image` against [ProverOpts::expected_image_id].
+    pub(crate) fn check_image_id(&self, image: &MemoryImage) -> Result<()> {
+        if let Some(expected) = self.expected_image_id {
+            let actual = image.compute_id();
+            if actual != expected {
+                return SetupErrors(vec![OptsError::ImageIdMismatch { expected, actual }]).check();
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,303 @@
+// Copyright 2023 RISC Zero, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Well-known annotations recording when and where a receipt was produced.
+
+use std::{
+    collections::BTreeMap,
+    fmt,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+#[allow(deprecated)]
+use crate::ProverOpts;
+use crate::{ProveOpts, Receipt, VERSION};
+
+/// The annotation holding the time at which a receipt was produced, as an
+/// RFC 3339 timestamp in UTC, e.g. `2023-11-14T22:13:20Z`.
+pub const ZKVM_CREATED_AT: &str = "zkvm_created_at";
+
+/// The annotation naming the service or user that produced a receipt.
+pub const ZKVM_PRODUCER_ID: &str = "zkvm_producer_id";
+
+/// The annotation describing the host that produced a receipt: the version of
+/// this crate and the operating system and architecture it was built for.
+pub const ZKVM_HOST_INFO: &str = "zkvm_host_info";
+
+/// An error in the annotations of a [Receipt].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum AnnotationError {
+    /// A required annotation is not present.
+    Missing {
+        /// The key of the annotation.
+        key: String,
+    },
+
+    /// The [ZKVM_CREATED_AT] annotation is not an RFC 3339 timestamp.
+    MalformedTimestamp {
+        /// The value of the annotation.
+        value: String,
+    },
+}
+
+impl fmt::Display for AnnotationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AnnotationError::Missing { key } => {
+                write!(f, "receipt has no {key} annotation")
+            }
+            AnnotationError::MalformedTimestamp { value } => {
+                write!(
+                    f,
+                    "{ZKVM_CREATED_AT} annotation {value:?} is not an RFC 3339 timestamp"
+                )
+            }
+        }
+    }
+}
+
+impl std::error::Error for AnnotationError {}
+
+impl Receipt {
+    /// The time at which this receipt was produced, from its
+    /// [ZKVM_CREATED_AT] annotation, if it has one.
+    ///
+    /// Like all annotations, this is not authenticated by [Receipt::verify].
+    pub fn created_at(&self) -> Result<Option<SystemTime>, AnnotationError> {
+        self.annotations
+            .get(ZKVM_CREATED_AT)
+            .map(|value| {
+                parse_rfc3339(value).ok_or_else(|| AnnotationError::MalformedTimestamp {
+                    value: value.clone(),
+                })
+            })
+            .transpose()
+    }
+
+    /// The producer of this receipt, from its [ZKVM_PRODUCER_ID] annotation.
+    pub fn producer_id(&self) -> Option<&str> {
+        self.annotations.get(ZKVM_PRODUCER_ID).map(String::as_str)
+    }
+
+    /// The host that produced this receipt, from its [ZKVM_HOST_INFO]
+    /// annotation.
+    pub fn host_info(&self) -> Option<&str> {
+        self.annotations.get(ZKVM_HOST_INFO).map(String::as_str)
+    }
+
+    /// Check that this receipt has an annotation for each key in `required`,
+    /// e.g. `&[ZKVM_CREATED_AT, ZKVM_PRODUCER_ID]`, and that its
+    /// [ZKVM_CREATED_AT] annotation, if any, is well formed.
+    ///
+    /// Receipts need no annotations to verify; this is for consumers whose
+    /// audit policy asks for them.
+    pub fn check_annotations(&self, required: &[&str]) -> Result<(), AnnotationError> {
+        if let Some(key) = required
+            .iter()
+            .find(|key| !self.annotations.contains_key(**key))
+        {
+            return Err(AnnotationError::Missing {
+                key: key.to_string(),
+            });
+        }
+        self.created_at().map(|_| ())
+    }
+}
+
+#[allow(deprecated)]
+impl ProverOpts {
+    /// Record when and where each receipt is produced, in the
+    /// [ZKVM_CREATED_AT], [ZKVM_PRODUCER_ID] and [ZKVM_HOST_INFO]
+    /// annotations.
+    ///
+    /// The time is taken when the receipt is produced, not when these options
+    /// are made. A receipt served from a proof cache keeps the time at which
+    /// it was first produced.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use risc0_zkvm::ProverOpts;
+    ///
+    /// let opts = ProverOpts::default().with_provenance("ci-runner-7");
+    /// ```
+    pub fn with_provenance(self, producer_id: &str) -> Self {
+        self.with_annotation(ZKVM_PRODUCER_ID, producer_id)
+            .with_annotation(ZKVM_HOST_INFO, &host_info())
+    }
+}
+
+impl ProveOpts {
+    /// See [ProverOpts::with_provenance].
+    pub fn with_provenance(self, producer_id: &str) -> Self {
+        self.with_annotation(ZKVM_PRODUCER_ID, producer_id)
+            .with_annotation(ZKVM_HOST_INFO, &host_info())
+    }
+}
+
+// The value of the ZKVM_HOST_INFO annotation for this host.
+fn host_info() -> String {
+    format!(
+        "risc0-zkvm {VERSION} {}/{}",
+        std::env::consts::OS,
+        std::env::consts::ARCH
+    )
+}
+
+/// Replace the annotations of `receipt` with `annotations`, stamping it with
+/// the time it was produced if they record provenance.
+pub(crate) fn annotate(receipt: &mut Receipt, annotations: &BTreeMap<String, String>) {
+    let created_at = receipt.annotations.remove(ZKVM_CREATED_AT);
+    receipt.annotations = annotations.clone();
+    if receipt.annotations.contains_key(ZKVM_PRODUCER_ID) {
+        let created_at = created_at.unwrap_or_else(|| format_rfc3339(SystemTime::now()));
+        receipt
+            .annotations
+            .entry(ZKVM_CREATED_AT.to_string())
+            .or_insert(created_at);
+    }
+}
+
+const SECS_PER_DAY: i64 = 24 * 60 * 60;
+
+// Formats `time` as an RFC 3339 timestamp in UTC, to the second.
+fn format_rfc3339(time: SystemTime) -> String {
+    let secs = time
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs() as i64;
+    let (year, month, day) = civil_from_days(secs.div_euclid(SECS_PER_DAY));
+    let secs = secs.rem_euclid(SECS_PER_DAY);
+    format!(
+        "{year:04}-{month:02}-{day:02}T{:02}:{:02}:{:02}Z",
+        secs / 3600,
+        secs / 60 % 60,
+        secs % 60
+    )
+}
+
+// Parses an RFC 3339 timestamp, e.g. `2023-11-14T22:13:20.5+02:00`.
+fn parse_rfc3339(value: &str) -> Option<SystemTime> {
+    fn number(digits: &str) -> Option<i64> {
+        match digits.bytes().all(|b| b.is_ascii_digit()) {
+            true => digits.parse().ok(),
+            false => None,
+        }
+    }
+
+    let bytes = value.as_bytes();
+    if !value.is_ascii()
+        || bytes.len() < 20
+        || bytes[4] != b'-'
+        || bytes[7] != b'-'
+        || !matches!(bytes[10], b'T' | b't' | b' ')
+        || bytes[13] != b':'
+        || bytes[16] != b':'
+    {
+        return None;
+    }
+    let year = number(&value[0..4])?;
+    let month = number(&value[5..7])?;
+    let day = number(&value[8..10])?;
+    let hour = number(&value[11..13])?;
+    let minute = number(&value[14..16])?;
+    // A leap second is allowed, and counts as the first second of the next
+    // minute.
+    let second = number(&value[17..19])?;
+    if !(1..=12).contains(&month)
+        || day < 1
+        || day > days_in_month(year, month)
+        || hour > 23
+        || minute > 59
+        || second > 60
+    {
+        return None;
+    }
+
+    let mut rest = &value[19..];
+    let mut nanos = 0;
+    if let Some(frac) = rest.strip_prefix('.') {
+        let len = frac.bytes().take_while(u8::is_ascii_digit).count();
+        if len == 0 {
+            return None;
+        }
+        // Digits past nanoseconds are truncated.
+        let digits = &frac[..len.min(9)];
+        nanos = number(digits)? as u32 * 10u32.pow(9 - digits.len() as u32);
+        rest = &frac[len..];
+    }
+    let offset = match rest {
+        "Z" | "z" => 0,
+        _ => {
+            let (sign, offset) = match rest.as_bytes().first()? {
+                b'+' => (1, &rest[1..]),
+                b'-' => (-1, &rest[1..]),
+                _ => return None,
+            };
+            if offset.len() != 5 || offset.as_bytes()[2] != b':' {
+                return None;
+            }
+            let (hours, minutes) = (number(&offset[0..2])?, number(&offset[3..5])?);
+            if hours > 23 || minutes > 59 {
+                return None;
+            }
+            sign * (hours * 3600 + minutes * 60)
+        }
+    };
+
+    let secs =
+        days_from_civil(year, month, day) * SECS_PER_DAY + hour * 3600 + minute * 60 + second
+            - offset;
+    match u64::try_from(secs) {
+        Ok(secs) => UNIX_EPOCH.checked_add(Duration::new(secs, nanos)),
+        Err(_) => UNIX_EPOCH
+            .checked_sub(Duration::from_secs(secs.unsigned_abs()))?
+            .checked_add(Duration::from_nanos(nanos.into())),
+    }
+}
+
+fn days_in_month(year: i64, month: i64) -> i64 {
+    match month {
+        2 if year % 4 == 0 && (year % 100 != 0 || year % 400 == 0) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+// The number of days from 1970-01-01 to the given date of the proleptic
+// Gregorian calendar, after http://howardhinnant.github.io/date_algorithms.html.
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year.rem_euclid(400);
+    let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146097 + day_of_era - 719468
+}
+
+// The inverse of [days_from_civil].
+fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let days = days + 719468;
+    let era = days.div_euclid(146097);
+    let day_of_era = days.rem_euclid(146097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
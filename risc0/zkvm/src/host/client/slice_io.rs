@@ -12,10 +12,18 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use std::{cell::RefCell, collections::BTreeMap, rc::Rc};
+use std::{
+    borrow::Cow,
+    cell::RefCell,
+    collections::{BTreeMap, BTreeSet},
+    rc::Rc,
+    sync::Arc,
+    time::Duration,
+};
 
-use anyhow::Result;
+use anyhow::{bail, ensure, Result};
 use bytes::Bytes;
+use risc0_zkvm_platform::syscall::{nr, SyscallName};
 
 /// An I/O handler that returns arbitrary data to the guest.
 ///
@@ -34,9 +42,147 @@ pub trait SliceIo {
     fn handle_io(&mut self, syscall: &str, from_guest: Bytes) -> Result<Bytes>;
 }
 
+/// An I/O handler that writes its response straight into the buffer the
+/// guest allocates for it.
+///
+/// A [SliceIo] handler returns its response as [Bytes], which is then copied
+/// into guest memory. A handler that already holds its response, e.g. in a
+/// cache, would first have to copy it into [Bytes] that it owns; this trait
+/// lets it copy the response to the guest directly instead.
+///
+/// The guest sees no difference: it calls `env::send_recv_slice` either way.
+pub trait SliceIoFill {
+    /// Handle the data the guest sent in `from_guest`, and return the length,
+    /// in bytes, of the response.
+    fn request(&mut self, syscall: &str, from_guest: Bytes) -> Result<usize>;
+
+    /// Write the response to the last request to the start of `to_guest`,
+    /// and return the number of bytes written, which must be the length
+    /// returned by [SliceIoFill::request].
+    ///
+    /// `to_guest` holds the whole response, rounded up to a whole number of
+    /// words, and the bytes past the response are ignored.
+    fn fill(&mut self, to_guest: &mut [u8]) -> Result<usize>;
+}
+
+/// Limits on a single I/O handler, set with
+/// [ExecutorEnvBuilder::syscall_guard](crate::ExecutorEnvBuilder::syscall_guard).
+///
+/// When a handler breaks a limit, execution fails with a
+/// [SyscallGuardError](crate::SyscallGuardError) naming the handler and the
+/// cycle of the call, instead of hanging or running out of memory.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct SyscallGuard {
+    /// The longest a single call to the handler may take.
+    ///
+    /// Only a handler added with
+    /// [ExecutorEnvBuilder::io_callback_send](crate::ExecutorEnvBuilder::io_callback_send)
+    /// can be given a timeout, because it is run on a helper thread while the
+    /// executor waits. A handler that times out keeps its thread until it
+    /// returns.
+    pub timeout: Option<Duration>,
+
+    /// The largest response, in bytes, the handler may return.
+    pub max_response_bytes: Option<usize>,
+}
+
+pub(crate) type ThreadedCallback = Arc<dyn Fn(Bytes) -> Result<Bytes> + Send + Sync>;
+
+/// A registered I/O handler, of any kind.
+#[derive(Clone)]
+pub(crate) enum SliceIoHandler<'a> {
+    Bytes(Rc<RefCell<dyn SliceIo + 'a>>),
+    Fill(Rc<RefCell<dyn SliceIoFill + 'a>>),
+    // A handler that can be run on another thread.
+    Threaded(ThreadedCallback),
+}
+
+impl<'a> SliceIoHandler<'a> {
+    /// Handle a request, returning the whole response at once.
+    pub(crate) fn handle_io(&self, syscall: &str, from_guest: Bytes) -> Result<Bytes> {
+        match self {
+            SliceIoHandler::Bytes(handler) => handler.borrow_mut().handle_io(syscall, from_guest),
+            SliceIoHandler::Threaded(callback) => callback(from_guest),
+            SliceIoHandler::Fill(handler) => {
+                let mut handler = handler.borrow_mut();
+                let mut response = vec![0; handler.request(syscall, from_guest)?];
+                let len = handler.fill(&mut response)?;
+                check_fill_len(syscall, len, response.len())?;
+                Ok(response.into())
+            }
+        }
+    }
+}
+
+pub(crate) fn check_fill_len(syscall: &str, written: usize, len: usize) -> Result<()> {
+    ensure!(
+        written == len,
+        "I/O handler for {syscall} wrote {written} bytes of a {len} byte response"
+    );
+    Ok(())
+}
+
+/// The syscalls that the executor handles itself.
+pub(crate) const BUILTIN_SYSCALLS: &[SyscallName] = &[
+    nr::SYS_ARGC,
+    nr::SYS_ARGV,
+    nr::SYS_ASSUMPTION,
+    nr::SYS_BIGINT_MODMUL,
+    nr::SYS_CAPABILITIES,
+    nr::SYS_CHECK,
+    nr::SYS_CYCLE_COUNT,
+    nr::SYS_CYCLES_REMAINING,
+    nr::SYS_EXEC,
+    nr::SYS_GETENV,
+    nr::SYS_GETENV_PAGE,
+    nr::SYS_HOST_COMMITS,
+    nr::SYS_JOURNAL_PREIMAGE,
+    nr::SYS_LOG,
+    nr::SYS_NAMED_INPUT,
+    nr::SYS_NONCE,
+    nr::SYS_PANIC,
+    nr::SYS_RANDOM,
+    nr::SYS_READ,
+    nr::SYS_READ_AVAIL,
+    nr::SYS_SHA256,
+    nr::SYS_VERIFY,
+    nr::SYS_VERIFY_INTEGRITY,
+    nr::SYS_WRITE,
+    nr::SYS_YIELD,
+];
+
+// Syscalls named in this namespace are reserved for the executor, whether or
+// not it handles them yet.
+const BUILTIN_NAMESPACE: &str = "risc0_zkvm_platform::syscall::nr::";
+
+/// Whether `channel` names a syscall reserved for the executor.
+pub(crate) fn is_builtin(channel: &str) -> bool {
+    channel.starts_with(BUILTIN_NAMESPACE)
+}
+
+/// Where the handler of a syscall comes from; see
+/// [ExecutorEnv::syscall_handlers](crate::ExecutorEnv::syscall_handlers).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SyscallSource {
+    /// The executor's own handler.
+    Builtin,
+
+    /// A handler registered for a syscall the executor does not handle.
+    User,
+
+    /// A handler registered in place of the executor's own, with
+    /// [ExecutorEnvBuilder::override_builtin](crate::ExecutorEnvBuilder::override_builtin).
+    Override,
+}
+
 #[derive(Clone, Default)]
 pub struct SliceIoTable<'a> {
-    pub(crate) inner: BTreeMap<String, Rc<RefCell<dyn SliceIo + 'a>>>,
+    pub(crate) inner: BTreeMap<String, SliceIoHandler<'a>>,
+    // Channels that more than one handler was registered for.
+    pub(crate) duplicates: BTreeSet<String>,
+    // Built-in syscalls whose handlers may be replaced.
+    pub(crate) overrides: BTreeSet<String>,
+    pub(crate) guards: BTreeMap<String, SyscallGuard>,
 }
 
 type Callback<'a> = Rc<RefCell<dyn Fn(Bytes) -> Result<Bytes> + 'a>>;
@@ -59,12 +205,95 @@ impl<'a> SliceIo for FnWrapper<'a> {
     }
 }
 
+// Holds the response to the last request between the two calls the guest
+// makes for it.
+struct CowWrapper<'a, F> {
+    callback: F,
+    response: Cow<'a, [u8]>,
+}
+
+pub fn slice_io_from_cow_fn<'a>(
+    callback: impl Fn(Bytes) -> Result<Cow<'a, [u8]>> + 'a,
+) -> impl SliceIoFill + 'a {
+    CowWrapper {
+        callback,
+        response: Cow::Borrowed(&[]),
+    }
+}
+
+impl<'a, F: Fn(Bytes) -> Result<Cow<'a, [u8]>>> SliceIoFill for CowWrapper<'a, F> {
+    fn request(&mut self, _syscall: &str, from_guest: Bytes) -> Result<usize> {
+        self.response = (self.callback)(from_guest)?;
+        Ok(self.response.len())
+    }
+
+    fn fill(&mut self, to_guest: &mut [u8]) -> Result<usize> {
+        let response = std::mem::replace(&mut self.response, Cow::Borrowed(&[]));
+        to_guest[..response.len()].copy_from_slice(&response);
+        Ok(response.len())
+    }
+}
+
 impl<'a> SliceIoTable<'a> {
     pub fn with_handler(&mut self, channel: &str, handler: impl SliceIo + 'a) -> &mut Self {
-        self.inner
-            .insert(channel.to_string(), Rc::new(RefCell::new(handler)));
+        self.insert(
+            channel,
+            SliceIoHandler::Bytes(Rc::new(RefCell::new(handler))),
+        )
+    }
+
+    pub fn with_fill_handler(
+        &mut self,
+        channel: &str,
+        handler: impl SliceIoFill + 'a,
+    ) -> &mut Self {
+        self.insert(
+            channel,
+            SliceIoHandler::Fill(Rc::new(RefCell::new(handler))),
+        )
+    }
+
+    pub(crate) fn with_threaded_handler(
+        &mut self,
+        channel: &str,
+        callback: ThreadedCallback,
+    ) -> &mut Self {
+        self.insert(channel, SliceIoHandler::Threaded(callback))
+    }
+
+    fn insert(&mut self, channel: &str, handler: SliceIoHandler<'a>) -> &mut Self {
+        if self.inner.insert(channel.to_string(), handler).is_some() {
+            self.duplicates.insert(channel.to_string());
+        }
         self
     }
+
+    /// Check that no channel has more than one handler, that every handler
+    /// for a built-in syscall was registered as an override, and that every
+    /// guard applies to a handler that can honor it.
+    pub(crate) fn check(&self) -> Result<()> {
+        if let Some(channel) = self.duplicates.first() {
+            bail!("more than one I/O handler registered for {channel}");
+        }
+        if let Some(channel) = self
+            .inner
+            .keys()
+            .find(|channel| is_builtin(channel) && !self.overrides.contains(*channel))
+        {
+            bail!("I/O handler registered for built-in syscall {channel}; use override_builtin to replace it");
+        }
+        for (channel, guard) in self.guards.iter() {
+            match self.inner.get(channel) {
+                None => bail!("guard registered for {channel}, which has no I/O handler"),
+                Some(SliceIoHandler::Threaded(_)) => {}
+                Some(_) => ensure!(
+                    guard.timeout.is_none(),
+                    "I/O handler for {channel} cannot be given a timeout; add it with io_callback_send"
+                ),
+            }
+        }
+        Ok(())
+    }
 }
 
 impl<'a> SliceIo for Rc<RefCell<dyn SliceIo + 'a>> {
@@ -0,0 +1,133 @@
+// Copyright 2023 RISC Zero, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Attribution of executed cycles to the expensive routines found by
+//! [Program::analyze].
+
+use std::collections::BTreeMap;
+
+use anyhow::Result;
+use risc0_binfmt::{FlaggedSymbol, GuestAnalysis, Program, SymbolKind};
+
+use super::env::TraceCallback;
+use crate::TraceEvent;
+
+/// Counts the cycles the guest spends inside each function flagged by
+/// [Program::analyze].
+///
+/// Install it with
+/// [ExecutorEnvBuilder::trace_callback](crate::ExecutorEnvBuilder::trace_callback)
+/// and inspect it once execution is done. Cycles are attributed by program
+/// counter, so time spent in a function called from a flagged function is
+/// only counted if the callee is itself flagged.
+pub struct SymbolStats {
+    analysis: GuestAnalysis,
+
+    // Start address of a flagged function mapped to the cycles spent in it.
+    cycles: PcCycles<u32>,
+}
+
+/// Cycles spent in each of a set of buckets of program counters, counted from
+/// [TraceEvent::InstructionStart] events.
+///
+/// This is the bookkeeping shared by [SymbolStats] and
+/// [CycleAttributor](super::cycle_attributor::CycleAttributor), which differ
+/// only in how they map a pc to a bucket.
+pub(crate) struct PcCycles<K> {
+    cycles: BTreeMap<K, u64>,
+
+    // The pc and starting cycle of the previous instruction.
+    last: Option<(u32, u32)>,
+}
+
+impl<K: Ord> PcCycles<K> {
+    pub(crate) fn new() -> Self {
+        Self {
+            cycles: BTreeMap::new(),
+            last: None,
+        }
+    }
+
+    /// Charge the cycles of the previous instruction to the bucket that
+    /// `bucket` maps its pc to, if any.
+    pub(crate) fn record(&mut self, event: TraceEvent, bucket: impl FnOnce(u32) -> Option<K>) {
+        if let TraceEvent::InstructionStart { cycle, pc, .. } = event {
+            if let Some((last_pc, last_cycle)) = self.last {
+                if let Some(key) = bucket(last_pc) {
+                    let elapsed = cycle.saturating_sub(last_cycle) as u64;
+                    *self.cycles.entry(key).or_default() += elapsed;
+                }
+            }
+            self.last = Some((pc, cycle));
+        }
+    }
+
+    /// The buckets that were charged any cycles, along with their cycles.
+    pub(crate) fn iter(&self) -> impl Iterator<Item = (&K, u64)> {
+        self.cycles.iter().map(|(key, &cycles)| (key, cycles))
+    }
+
+    /// The total number of cycles charged to any bucket.
+    pub(crate) fn total(&self) -> u64 {
+        self.cycles.values().sum()
+    }
+}
+
+impl SymbolStats {
+    /// Analyze `elf` and prepare to count cycles while it executes.
+    pub fn new(elf: &[u8]) -> Result<Self> {
+        Ok(Self {
+            analysis: Program::analyze(elf)?,
+            cycles: PcCycles::new(),
+        })
+    }
+
+    /// The static analysis of the guest.
+    pub fn analysis(&self) -> &GuestAnalysis {
+        &self.analysis
+    }
+
+    /// The flagged functions that were executed, along with the number of
+    /// cycles spent in each.
+    pub fn cycles(&self) -> impl Iterator<Item = (&FlaggedSymbol, u64)> {
+        self.cycles.iter().filter_map(|(&start, cycles)| {
+            let sym = self.analysis.symbol_at(start)?;
+            Some((sym, cycles))
+        })
+    }
+
+    /// The total number of cycles spent in flagged functions of the given
+    /// kind.
+    pub fn total_cycles(&self, kind: SymbolKind) -> u64 {
+        self.cycles()
+            .filter(|(sym, _)| sym.kind == kind)
+            .map(|(_, cycles)| cycles)
+            .sum()
+    }
+}
+
+impl TraceCallback for SymbolStats {
+    fn trace_callback(&mut self, event: TraceEvent) -> Result<()> {
+        let analysis = &self.analysis;
+        self.cycles
+            .record(event, |pc| Some(analysis.symbol_at(pc)?.start));
+        Ok(())
+    }
+}
+
+impl TraceCallback for &mut SymbolStats {
+    fn trace_callback(&mut self, event: TraceEvent) -> Result<()> {
+        (*self).trace_callback(event)
+    }
+}
@@ -0,0 +1,701 @@
+// Copyright 2023 RISC Zero, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! A compact binary file format for [TraceEvent]s.
+//!
+//! A trace file starts with a header:
+//!
+//! * the tag `R0TR`;
+//! * the format version, [TRACE_FILE_VERSION], as one byte;
+//! * the segment limit of the session, as a power of two, as one byte.
+//!
+//! The rest of the file is a sequence of blocks. Each block starts with a
+//! sync point of four little-endian `u32`s: the cycle and program counter of
+//! its first instruction, the index of the segment it is in, and the length
+//! in bytes of its body. A block holds the events of at most
+//! [SYNC_INTERVAL] instructions of one segment, so a new segment always
+//! starts a new block.
+//!
+//! The body is a sequence of records, each starting with a tag byte. Cycles
+//! and program counters are delta-encoded against the previous instruction
+//! of the block. Register values, memory addresses and memory values are
+//! encoded as the difference from a prediction: the previous value plus the
+//! previous change, so that counters and pointers stepping by a fixed stride
+//! cost nothing. Numbers are LEB128 varints, zigzag-encoded where they may
+//! be negative.
+//!
+//! * `0x80 | d`: an instruction at the program counter after the previous
+//!   one, `d < 0x80` cycles after it, whose encoding is already known.
+//! * `0x01`: an instruction: the cycle delta and the program counter delta
+//!   from the address after the previous instruction, of a known encoding.
+//! * `0x02`: as `0x01`, followed by the encoding as a little-endian `u32`.
+//! * `0x03`: a memory write: the address and the value.
+//! * `0x04`: a memory write to the predicted address: the value.
+//! * `0x40 | idx`: a write to register `idx`: the value.
+//! * `0x60 | idx`: a write of the predicted value to register `idx`.
+//!
+//! The encoding of an instruction is known once the block has had an
+//! instruction at the same address with that encoding.
+
+#[cfg(feature = "prove")]
+use std::io::{BufWriter, Write};
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::{BufReader, Read, Seek, SeekFrom},
+    path::Path,
+};
+
+use anyhow::{bail, ensure, Context, Result};
+
+#[cfg(feature = "prove")]
+use super::env::TraceCallback;
+use super::exec::TraceEvent;
+
+/// The version of the trace file format.
+pub const TRACE_FILE_VERSION: u8 = 1;
+
+/// The most instructions in a block of a trace file, and so the most that
+/// [TraceReader::seek_to_cycle] decodes to reach a cycle.
+pub const SYNC_INTERVAL: usize = 4096;
+
+const TRACE_FILE_TAG: &[u8; 4] = b"R0TR";
+const SYNC_POINT_BYTES: usize = 16;
+const REGISTERS: usize = 32;
+
+const TAG_STEP: u8 = 0x80;
+const TAG_INSN: u8 = 0x01;
+const TAG_INSN_NEW: u8 = 0x02;
+const TAG_MEMORY: u8 = 0x03;
+const TAG_MEMORY_NEXT: u8 = 0x04;
+const TAG_REGISTER: u8 = 0x40;
+const TAG_REGISTER_NEXT: u8 = 0x60;
+
+/// The kind of a [TraceEvent], for [TraceReader::with_kinds].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum TraceKind {
+    /// [TraceEvent::InstructionStart].
+    Instruction,
+    /// [TraceEvent::RegisterSet].
+    Register,
+    /// [TraceEvent::MemorySet].
+    Memory,
+}
+
+impl TraceKind {
+    /// The kind of `event`.
+    pub fn of(event: &TraceEvent) -> Self {
+        match event {
+            TraceEvent::InstructionStart { .. } => TraceKind::Instruction,
+            TraceEvent::RegisterSet { .. } => TraceKind::Register,
+            TraceEvent::MemorySet { .. } => TraceKind::Memory,
+        }
+    }
+}
+
+/// An event read from a trace file.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TraceRecord {
+    /// The index of the segment the event happened in.
+    pub segment: u32,
+
+    /// The cycle of the instruction that the event is part of.
+    pub cycle: u32,
+
+    /// The event.
+    pub event: TraceEvent,
+}
+
+#[derive(Clone, Copy, Debug)]
+struct SyncPoint {
+    cycle: u32,
+    pc: u32,
+    segment: u32,
+}
+
+// A value predicted to change by as much as it did last time.
+#[derive(Clone, Copy, Default)]
+struct Stride {
+    last: u32,
+    step: u32,
+}
+
+impl Stride {
+    // How far `value` is from the prediction, recording it as the latest.
+    #[cfg(feature = "prove")]
+    fn residual(&mut self, value: u32) -> u32 {
+        let residual = value.wrapping_sub(self.last.wrapping_add(self.step));
+        self.step = value.wrapping_sub(self.last);
+        self.last = value;
+        residual
+    }
+
+    // The value `residual` away from the prediction, recording it as the
+    // latest.
+    fn apply(&mut self, residual: u32) -> u32 {
+        let value = self.last.wrapping_add(self.step).wrapping_add(residual);
+        self.step = value.wrapping_sub(self.last);
+        self.last = value;
+        value
+    }
+}
+
+// The values that records are encoded against, reset at every sync point.
+struct DeltaState {
+    cycle: u32,
+    pc: u32,
+    registers: [Stride; REGISTERS],
+    addr: Stride,
+    value: Stride,
+    insns: HashMap<u32, u32>,
+}
+
+impl DeltaState {
+    fn new(sync: SyncPoint) -> Self {
+        Self {
+            cycle: sync.cycle,
+            // The first instruction of a block follows this one.
+            pc: sync.pc.wrapping_sub(4),
+            registers: Default::default(),
+            addr: Stride::default(),
+            value: Stride::default(),
+            insns: HashMap::new(),
+        }
+    }
+}
+
+/// Writes the events of a session to a trace file; see
+/// [ExecutorEnvBuilder::trace_file](crate::ExecutorEnvBuilder::trace_file).
+#[cfg(feature = "prove")]
+pub(crate) struct TraceFileWriter {
+    out: BufWriter<File>,
+    segment_limit_po2: u32,
+    sync: Option<SyncPoint>,
+    state: DeltaState,
+    body: Vec<u8>,
+    insns: usize,
+}
+
+#[cfg(feature = "prove")]
+impl TraceFileWriter {
+    pub(crate) fn create(path: &Path, segment_limit_po2: u32) -> Result<Self> {
+        let file =
+            File::create(path).with_context(|| format!("Failed to create trace file {path:?}"))?;
+        let mut out = BufWriter::new(file);
+        out.write_all(TRACE_FILE_TAG)?;
+        out.write_all(&[TRACE_FILE_VERSION, segment_limit_po2 as u8])?;
+        Ok(Self {
+            out,
+            segment_limit_po2,
+            sync: None,
+            state: DeltaState::new(SyncPoint {
+                cycle: 0,
+                pc: 0,
+                segment: 0,
+            }),
+            body: Vec::new(),
+            insns: 0,
+        })
+    }
+
+    fn flush_block(&mut self) -> Result<()> {
+        let Some(sync) = self.sync.take() else {
+            return Ok(());
+        };
+        for word in [sync.cycle, sync.pc, sync.segment, self.body.len() as u32] {
+            self.out.write_all(&word.to_le_bytes())?;
+        }
+        self.out.write_all(&self.body)?;
+        self.body.clear();
+        Ok(())
+    }
+
+    fn instruction(&mut self, cycle: u32, pc: u32, insn: u32) -> Result<()> {
+        let segment = cycle >> self.segment_limit_po2;
+        if !matches!(self.sync, Some(sync) if sync.segment == segment)
+            || self.insns == SYNC_INTERVAL
+        {
+            self.flush_block()?;
+            let sync = SyncPoint { cycle, pc, segment };
+            self.sync = Some(sync);
+            self.state = DeltaState::new(sync);
+            self.insns = 0;
+        }
+        self.insns += 1;
+
+        let cycle_delta = cycle.wrapping_sub(self.state.cycle);
+        let pc_delta = pc.wrapping_sub(self.state.pc.wrapping_add(4)) as i32;
+        let known = self.state.insns.insert(pc, insn) == Some(insn);
+        if known && pc_delta == 0 && cycle_delta < 0x80 {
+            self.body.push(TAG_STEP | cycle_delta as u8);
+        } else {
+            self.body.push(if known { TAG_INSN } else { TAG_INSN_NEW });
+            write_varint(&mut self.body, cycle_delta);
+            write_varint(&mut self.body, zigzag(pc_delta));
+            if !known {
+                self.body.extend_from_slice(&insn.to_le_bytes());
+            }
+        }
+        self.state.cycle = cycle;
+        self.state.pc = pc;
+        Ok(())
+    }
+}
+
+#[cfg(feature = "prove")]
+impl TraceCallback for TraceFileWriter {
+    fn trace_callback(&mut self, event: TraceEvent) -> Result<()> {
+        match event {
+            TraceEvent::InstructionStart { cycle, pc, insn } => {
+                self.instruction(cycle, pc, insn)?
+            }
+            TraceEvent::RegisterSet { idx, value } => {
+                ensure!(idx < REGISTERS, "invalid register index {idx}");
+                match self.state.registers[idx].residual(value) {
+                    0 => self.body.push(TAG_REGISTER_NEXT | idx as u8),
+                    residual => {
+                        self.body.push(TAG_REGISTER | idx as u8);
+                        write_varint(&mut self.body, zigzag(residual as i32));
+                    }
+                }
+            }
+            TraceEvent::MemorySet { addr, value } => {
+                match self.state.addr.residual(addr) {
+                    0 => self.body.push(TAG_MEMORY_NEXT),
+                    residual => {
+                        self.body.push(TAG_MEMORY);
+                        write_varint(&mut self.body, zigzag(residual as i32));
+                    }
+                }
+                let residual = self.state.value.residual(value);
+                write_varint(&mut self.body, zigzag(residual as i32));
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(feature = "prove")]
+impl Drop for TraceFileWriter {
+    fn drop(&mut self) {
+        if let Err(err) = self.flush_block().and_then(|_| Ok(self.out.flush()?)) {
+            tracing::error!("Failed to finish trace file: {err:?}");
+        }
+    }
+}
+
+/// Reads the events of a trace file written by the executor for
+/// [ExecutorEnvBuilder::trace_file](crate::ExecutorEnvBuilder::trace_file).
+///
+/// Events are decoded lazily, one block at a time, as the reader is iterated.
+///
+/// # Example
+///
+/// ```no_run
+/// use risc0_zkvm::{TraceKind, TraceReader};
+///
+/// # fn main() -> anyhow::Result<()> {
+/// let mut reader = TraceReader::open("guest.trace")?.with_kinds([TraceKind::Instruction]);
+/// reader.seek_to_cycle(1 << 20)?;
+/// for record in reader.take(10) {
+///     println!("{:?}", record?);
+/// }
+/// # Ok(())
+/// # }
+/// ```
+pub struct TraceReader<R> {
+    inner: R,
+    segment_limit_po2: u32,
+    kinds: Option<Vec<TraceKind>>,
+    sync: SyncPoint,
+    state: DeltaState,
+    body: Vec<u8>,
+    pos: usize,
+    // Events of instructions before this cycle are skipped.
+    skip_before: u32,
+}
+
+impl TraceReader<BufReader<File>> {
+    /// Open the trace file at `path`.
+    pub fn open(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let file =
+            File::open(path).with_context(|| format!("Failed to open trace file {path:?}"))?;
+        Self::new(BufReader::new(file))
+    }
+}
+
+impl<R: Read + Seek> TraceReader<R> {
+    /// Read a trace file from `inner`, which must be at its start.
+    pub fn new(mut inner: R) -> Result<Self> {
+        let mut header = [0u8; 6];
+        inner
+            .read_exact(&mut header)
+            .context("Trace file ends before its header")?;
+        ensure!(&header[..4] == TRACE_FILE_TAG, "Not a trace file");
+        ensure!(
+            header[4] == TRACE_FILE_VERSION,
+            "Unsupported trace file version: {}, expected {TRACE_FILE_VERSION}",
+            header[4]
+        );
+        let sync = SyncPoint {
+            cycle: 0,
+            pc: 0,
+            segment: 0,
+        };
+        Ok(Self {
+            inner,
+            segment_limit_po2: header[5] as u32,
+            kinds: None,
+            sync,
+            state: DeltaState::new(sync),
+            body: Vec::new(),
+            pos: 0,
+            skip_before: 0,
+        })
+    }
+
+    /// The segment limit of the traced session, as a power of two.
+    pub fn segment_limit_po2(&self) -> u32 {
+        self.segment_limit_po2
+    }
+
+    /// Only yield events of the given kinds.
+    pub fn with_kinds(mut self, kinds: impl IntoIterator<Item = TraceKind>) -> Self {
+        self.kinds = Some(kinds.into_iter().collect());
+        self
+    }
+
+    /// Move to the first instruction at or after `cycle`, so that the next
+    /// record is that instruction, or one of its events if instructions are
+    /// filtered out.
+    ///
+    /// Only the sync points of the blocks before `cycle` are read, and only
+    /// the block containing it is decoded.
+    pub fn seek_to_cycle(&mut self, cycle: u32) -> Result<()> {
+        let mut offset = self.inner.seek(SeekFrom::Start(6))?;
+        let mut found = None;
+        while let Some((sync, len)) = self.read_sync_point()? {
+            if sync.cycle > cycle {
+                break;
+            }
+            found = Some(offset);
+            offset = self.inner.seek(SeekFrom::Current(len as i64))?;
+        }
+        self.inner.seek(SeekFrom::Start(found.unwrap_or(6)))?;
+        self.body.clear();
+        self.pos = 0;
+        self.skip_before = cycle;
+        Ok(())
+    }
+
+    fn read_sync_point(&mut self) -> Result<Option<(SyncPoint, usize)>> {
+        let mut bytes = [0u8; SYNC_POINT_BYTES];
+        match self.inner.read_exact(&mut bytes) {
+            Ok(()) => {}
+            Err(err) if err.kind() == std::io::ErrorKind::UnexpectedEof => return Ok(None),
+            Err(err) => return Err(err.into()),
+        }
+        let word = |i: usize| u32::from_le_bytes(bytes[i * 4..i * 4 + 4].try_into().unwrap());
+        let sync = SyncPoint {
+            cycle: word(0),
+            pc: word(1),
+            segment: word(2),
+        };
+        Ok(Some((sync, word(3) as usize)))
+    }
+
+    fn next_block(&mut self) -> Result<bool> {
+        let Some((sync, len)) = self.read_sync_point()? else {
+            return Ok(false);
+        };
+        self.body.resize(len, 0);
+        self.inner
+            .read_exact(&mut self.body)
+            .context("Trace file ends in the middle of a block")?;
+        self.sync = sync;
+        self.state = DeltaState::new(sync);
+        self.pos = 0;
+        Ok(true)
+    }
+
+    fn next_record(&mut self) -> Result<Option<TraceRecord>> {
+        loop {
+            if self.pos == self.body.len() && !self.next_block()? {
+                return Ok(None);
+            }
+            let record = self.decode()?;
+            if record.cycle < self.skip_before {
+                continue;
+            }
+            if let Some(kinds) = &self.kinds {
+                if !kinds.contains(&TraceKind::of(&record.event)) {
+                    continue;
+                }
+            }
+            return Ok(Some(record));
+        }
+    }
+
+    fn decode(&mut self) -> Result<TraceRecord> {
+        let tag = self.byte()?;
+        let event = match tag {
+            _ if tag & TAG_STEP != 0 => {
+                let pc = self.state.pc.wrapping_add(4);
+                self.instruction((tag & !TAG_STEP) as u32, pc, None)?
+            }
+            TAG_INSN | TAG_INSN_NEW => {
+                let cycle_delta = self.varint()?;
+                let pc_delta = self.signed_varint()?;
+                let pc = self.state.pc.wrapping_add(4).wrapping_add(pc_delta);
+                let insn = if tag == TAG_INSN_NEW {
+                    let bytes = self.bytes(4)?;
+                    Some(u32::from_le_bytes(bytes.try_into().unwrap()))
+                } else {
+                    None
+                };
+                self.instruction(cycle_delta, pc, insn)?
+            }
+            TAG_MEMORY | TAG_MEMORY_NEXT => {
+                let residual = match tag {
+                    TAG_MEMORY => self.signed_varint()?,
+                    _ => 0,
+                };
+                let addr = self.state.addr.apply(residual);
+                let residual = self.signed_varint()?;
+                let value = self.state.value.apply(residual);
+                TraceEvent::MemorySet { addr, value }
+            }
+            _ if tag & !(REGISTERS as u8 - 1) == TAG_REGISTER => {
+                let idx = (tag & !TAG_REGISTER) as usize;
+                let residual = self.signed_varint()?;
+                let value = self.state.registers[idx].apply(residual);
+                TraceEvent::RegisterSet { idx, value }
+            }
+            _ if tag & !(REGISTERS as u8 - 1) == TAG_REGISTER_NEXT => {
+                let idx = (tag & !TAG_REGISTER_NEXT) as usize;
+                let value = self.state.registers[idx].apply(0);
+                TraceEvent::RegisterSet { idx, value }
+            }
+            _ => bail!("Invalid trace record tag 0x{tag:02x}"),
+        };
+        Ok(TraceRecord {
+            segment: self.sync.segment,
+            cycle: self.state.cycle,
+            event,
+        })
+    }
+
+    fn instruction(&mut self, cycle_delta: u32, pc: u32, insn: Option<u32>) -> Result<TraceEvent> {
+        let insn = match insn {
+            Some(insn) => {
+                self.state.insns.insert(pc, insn);
+                insn
+            }
+            None => *self
+                .state
+                .insns
+                .get(&pc)
+                .with_context(|| format!("Trace file has no encoding for pc 0x{pc:08x}"))?,
+        };
+        self.state.cycle = self.state.cycle.wrapping_add(cycle_delta);
+        self.state.pc = pc;
+        Ok(TraceEvent::InstructionStart {
+            cycle: self.state.cycle,
+            pc,
+            insn,
+        })
+    }
+
+    fn byte(&mut self) -> Result<u8> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    fn bytes(&mut self, len: usize) -> Result<&[u8]> {
+        let bytes = self
+            .body
+            .get(self.pos..self.pos + len)
+            .context("Trace record runs past the end of its block")?;
+        self.pos += len;
+        Ok(bytes)
+    }
+
+    fn varint(&mut self) -> Result<u32> {
+        let mut value = 0u32;
+        for shift in (0..35).step_by(7) {
+            let byte = self.byte()?;
+            value |= ((byte & 0x7f) as u32) << shift;
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+        bail!("Invalid varint in trace record")
+    }
+
+    fn signed_varint(&mut self) -> Result<u32> {
+        Ok(unzigzag(self.varint()?) as u32)
+    }
+}
+
+impl<R: Read + Seek> Iterator for TraceReader<R> {
+    type Item = Result<TraceRecord>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_record().transpose()
+    }
+}
+
+#[cfg(feature = "prove")]
+fn write_varint(out: &mut Vec<u8>, mut value: u32) {
+    while value >= 0x80 {
+        out.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    out.push(value as u8);
+}
+
+#[cfg(feature = "prove")]
+fn zigzag(value: i32) -> u32 {
+    ((value << 1) ^ (value >> 31)) as u32
+}
+
+fn unzigzag(value: u32) -> i32 {
+    (value >> 1) as i32 ^ -((value & 1) as i32)
+}
+
+#[cfg(all(test, feature = "prove"))]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+
+    // A loop summing an array, traced the way the executor does: each
+    // instruction followed by the register and memory writes it makes.
+    fn loop_trace(iterations: u32) -> Vec<TraceEvent> {
+        let mut events = Vec::new();
+        let mut cycle = 0;
+        let mut step = |events: &mut Vec<TraceEvent>, pc, insn, writes: &[TraceEvent]| {
+            events.push(TraceEvent::InstructionStart { cycle, pc, insn });
+            events.extend_from_slice(writes);
+            cycle += 1;
+        };
+        for i in 0..iterations {
+            let addr = 0x0001_0000 + i * 4;
+            step(
+                &mut events,
+                0x200000,
+                0x0002a303,
+                &[TraceEvent::RegisterSet {
+                    idx: 6,
+                    value: i * 3,
+                }],
+            );
+            step(
+                &mut events,
+                0x200004,
+                0x006282b3,
+                &[TraceEvent::RegisterSet {
+                    idx: 5,
+                    value: i * (i + 1),
+                }],
+            );
+            step(
+                &mut events,
+                0x200008,
+                0x00452023,
+                &[TraceEvent::MemorySet { addr, value: i }],
+            );
+            step(
+                &mut events,
+                0x20000c,
+                0x00450513,
+                &[TraceEvent::RegisterSet {
+                    idx: 10,
+                    value: addr + 4,
+                }],
+            );
+            step(&mut events, 0x200010, 0xfeb548e3, &[]);
+        }
+        events
+    }
+
+    fn write_trace(events: &[TraceEvent], segment_limit_po2: u32) -> Vec<u8> {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("trace");
+        let mut writer = TraceFileWriter::create(&path, segment_limit_po2).unwrap();
+        for event in events {
+            writer.trace_callback(event.clone()).unwrap();
+        }
+        drop(writer);
+        std::fs::read(path).unwrap()
+    }
+
+    #[test]
+    fn round_trip() {
+        let events = loop_trace(10_000);
+        let bytes = write_trace(&events, 14);
+        let records: Vec<_> = TraceReader::new(Cursor::new(&bytes))
+            .unwrap()
+            .collect::<Result<_>>()
+            .unwrap();
+        assert_eq!(
+            records.iter().map(|r| &r.event).collect::<Vec<_>>(),
+            events.iter().collect::<Vec<_>>()
+        );
+        // 50,000 cycles span four segments of 2^14 cycles.
+        assert_eq!(records.last().unwrap().segment, 3);
+        assert!(records.iter().all(|r| r.segment == r.cycle >> 14));
+
+        let naive = bincode::serialize(&events).unwrap();
+        assert!(
+            naive.len() >= 10 * bytes.len(),
+            "{} bytes, naive {}",
+            bytes.len(),
+            naive.len()
+        );
+    }
+
+    #[test]
+    fn seek_and_filter() {
+        let events = loop_trace(10_000);
+        let bytes = write_trace(&events, 14);
+
+        let mut reader = TraceReader::new(Cursor::new(&bytes)).unwrap();
+        reader.seek_to_cycle(30_002).unwrap();
+        let record = reader.next().unwrap().unwrap();
+        assert_eq!(record.cycle, 30_002);
+        assert_eq!(
+            record.event,
+            TraceEvent::InstructionStart {
+                cycle: 30_002,
+                pc: 0x200008,
+                insn: 0x00452023
+            }
+        );
+        assert_eq!(
+            reader.next().unwrap().unwrap().event,
+            TraceEvent::MemorySet {
+                addr: 0x0001_0000 + 6000 * 4,
+                value: 6000
+            }
+        );
+
+        // Seeking backwards works too.
+        let mut reader = reader.with_kinds([TraceKind::Memory]);
+        reader.seek_to_cycle(0).unwrap();
+        let writes: Vec<_> = reader.map(|r| r.unwrap().event).collect();
+        assert_eq!(writes.len(), 10_000);
+        assert!(writes.iter().all(|e| TraceKind::of(e) == TraceKind::Memory));
+    }
+}
@@ -0,0 +1,346 @@
+// Copyright 2023 RISC Zero, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Checking whether a receipt made by some version of the zkVM can be
+//! verified by this one.
+
+#[cfg(test)]
+mod tests;
+
+use std::{
+    fmt,
+    panic::{self, AssertUnwindSafe},
+};
+
+use risc0_zkp::verify::VerificationError;
+
+use super::{
+    api::convert::RECEIPT_BYTES_TAG, bundle::BUNDLE_BYTES_TAG, control_registry::control_id_for,
+    recursion::valid_control_ids,
+};
+use crate::{
+    sha::{Digest, Digestible},
+    InnerReceipt, Receipt, VerificationBundle, VerifierContext, RECEIPT_FORMAT_VERSION,
+    VERIFICATION_BUNDLE_VERSION,
+};
+
+/// The encoding of a receipt, as recognized by
+/// [check_receipt_compatibility].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ReceiptFormat {
+    /// A [VerificationBundle] encoded with [VerificationBundle::to_bytes].
+    Bundle,
+
+    /// A receipt encoded with [Receipt::to_bytes].
+    Bytes,
+
+    /// A receipt encoded with `Receipt::to_cbor`.
+    Cbor,
+
+    /// A receipt encoded with plain bincode, as before [Receipt::to_bytes]
+    /// added a tag and a format version.
+    Legacy,
+}
+
+impl ReceiptFormat {
+    fn detect(bytes: &[u8]) -> Self {
+        if bytes.starts_with(BUNDLE_BYTES_TAG) {
+            ReceiptFormat::Bundle
+        } else if bytes.starts_with(RECEIPT_BYTES_TAG) {
+            ReceiptFormat::Bytes
+        } else if bytes.starts_with(&[0xd9, 0xd9, 0xf7]) {
+            // The self-described CBOR tag, 55799.
+            ReceiptFormat::Cbor
+        } else {
+            ReceiptFormat::Legacy
+        }
+    }
+}
+
+/// Whether a receipt can be verified by this version of the zkVM.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CompatOutcome {
+    /// The receipt decoded and verified.
+    Verified,
+
+    /// The receipt is in a version of its encoding that this version cannot
+    /// decode.
+    IncompatibleFormat,
+
+    /// The receipt decoded, but was proven with circuits or a hash suite that
+    /// this version cannot verify.
+    IncompatibleCircuit,
+
+    /// The receipt is in a supported encoding, but could not be decoded or
+    /// failed to verify.
+    Corrupt,
+}
+
+/// The result of [check_receipt_compatibility].
+#[derive(Clone, Debug, PartialEq)]
+pub struct CompatReport {
+    /// The encoding the receipt was found to be in.
+    pub format: ReceiptFormat,
+
+    /// The version of that encoding, if it records one.
+    pub format_version: Option<u32>,
+
+    /// The version of `risc0-zkvm` the receipt was checked as having been made
+    /// by: the one recorded in a bundle or CBOR header, or else the expected
+    /// version given by the caller.
+    pub zkvm_version: String,
+
+    /// Whether the receipt can be verified.
+    pub outcome: CompatOutcome,
+
+    /// The image ID of the guest the receipt proves a run of, if it decoded.
+    pub image_id: Option<Digest>,
+
+    /// Why the receipt did not verify.
+    pub detail: Option<String>,
+}
+
+impl CompatReport {
+    /// Whether the receipt decoded and verified.
+    pub fn is_verified(&self) -> bool {
+        self.outcome == CompatOutcome::Verified
+    }
+}
+
+impl fmt::Display for CompatReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?} receipt", self.format)?;
+        if let Some(version) = self.format_version {
+            write!(f, " (format version {version})")?;
+        }
+        write!(f, " from {}: {:?}", self.zkvm_version, self.outcome)?;
+        if let Some(detail) = &self.detail {
+            write!(f, ": {detail}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Check whether the encoded receipt `receipt_bytes`, made by version
+/// `expected_version` of `risc0-zkvm`, can be decoded and verified by this
+/// version.
+///
+/// The receipt may be a [VerificationBundle] or a receipt in any encoding
+/// that [Receipt::from_bytes] or `Receipt::from_cbor` accepts. A bundle or
+/// CBOR receipt records the version that made it, which is reported in place
+/// of `expected_version`. The version is not used to decide the outcome: a
+/// receipt is compatible if the control IDs and hash suites of its seals are
+/// ones this version verifies. The integrity of the receipt is verified, and
+/// for a bundle its image ID is checked too; nothing else about the claim is.
+///
+/// This never panics: any input, however malformed, yields a
+/// [CompatReport].
+pub fn check_receipt_compatibility(receipt_bytes: &[u8], expected_version: &str) -> CompatReport {
+    check_receipt_compatibility_with_context(
+        receipt_bytes,
+        expected_version,
+        &VerifierContext::default(),
+    )
+}
+
+/// Like [check_receipt_compatibility], verifying the receipt with `ctx`.
+pub fn check_receipt_compatibility_with_context(
+    receipt_bytes: &[u8],
+    expected_version: &str,
+    ctx: &VerifierContext,
+) -> CompatReport {
+    let mut report = CompatReport {
+        format: ReceiptFormat::detect(receipt_bytes),
+        format_version: None,
+        zkvm_version: expected_version.to_string(),
+        outcome: CompatOutcome::Verified,
+        image_id: None,
+        detail: None,
+    };
+    // Decoding and verifying are not meant to panic on bad input, but neither
+    // was written expecting input this adversarial.
+    let result = panic::catch_unwind(AssertUnwindSafe(|| check(receipt_bytes, ctx, &mut report)));
+    let failure = match result {
+        Ok(Ok(())) => None,
+        Ok(Err(failure)) => Some(failure),
+        Err(_) => Some((CompatOutcome::Corrupt, "verifier panicked".to_string())),
+    };
+    if let Some((outcome, detail)) = failure {
+        report.outcome = outcome;
+        report.detail = Some(detail);
+    }
+    report
+}
+
+type Failure = (CompatOutcome, String);
+
+fn corrupt(err: impl fmt::Display) -> Failure {
+    (CompatOutcome::Corrupt, err.to_string())
+}
+
+// The fields of a bundle or CBOR header that describe the receipt.
+struct Header {
+    zkvm_version: String,
+    circuit: Option<String>,
+    hashfn: Option<String>,
+}
+
+fn check(bytes: &[u8], ctx: &VerifierContext, report: &mut CompatReport) -> Result<(), Failure> {
+    let mut image_id = None;
+    let (receipt, header) = match report.format {
+        ReceiptFormat::Bundle => {
+            check_format_version(bytes, VERIFICATION_BUNDLE_VERSION, report)?;
+            let bundle = VerificationBundle::from_bytes(bytes).map_err(corrupt)?;
+            image_id = Some(bundle.image_id);
+            let header = Header {
+                zkvm_version: bundle.zkvm_version,
+                circuit: bundle.circuit,
+                hashfn: bundle.hashfn,
+            };
+            (bundle.receipt, Some(header))
+        }
+        ReceiptFormat::Bytes => {
+            check_format_version(bytes, RECEIPT_FORMAT_VERSION, report)?;
+            (Receipt::from_bytes(bytes).map_err(corrupt)?, None)
+        }
+        ReceiptFormat::Cbor => {
+            let (receipt, header) = decode_cbor(bytes, report)?;
+            (receipt, Some(header))
+        }
+        ReceiptFormat::Legacy => (Receipt::from_bytes(bytes).map_err(corrupt)?, None),
+    };
+
+    if let Some(header) = header {
+        report.zkvm_version = header.zkvm_version;
+        if header.circuit.as_deref() != receipt.inner.circuit()
+            || header.hashfn.as_deref() != receipt.inner.hashfn()
+        {
+            return Err(corrupt("header does not match the receipt"));
+        }
+    }
+    report.image_id = receipt
+        .inner
+        .get_metadata()
+        .ok()
+        .map(|metadata| metadata.pre.digest());
+    check_control_ids(&receipt.inner)?;
+
+    receipt
+        .verify_integrity_with_context(ctx)
+        .map_err(|err| match err {
+            VerificationError::ControlVerificationError | VerificationError::InvalidHashSuite => {
+                (CompatOutcome::IncompatibleCircuit, err.to_string())
+            }
+            err => corrupt(err),
+        })?;
+    match image_id {
+        Some(image_id) if report.image_id != Some(image_id) => Err(corrupt(format!(
+            "receipt does not prove a run of {image_id}"
+        ))),
+        _ => Ok(()),
+    }
+}
+
+// Check that every seal in `inner` was made by circuits whose control IDs
+// this version knows. The control ID a segment seal commits to is checked
+// against the same list when the seal is verified.
+fn check_control_ids(inner: &InnerReceipt) -> Result<(), Failure> {
+    match inner {
+        InnerReceipt::Composite(receipt) => {
+            for segment in &receipt.segments {
+                control_id_for(&segment.hashfn).map_err(|_| {
+                    (
+                        CompatOutcome::IncompatibleCircuit,
+                        format!("no control IDs for hash suite {}", segment.hashfn),
+                    )
+                })?;
+            }
+            receipt.assumptions.iter().try_for_each(check_control_ids)
+        }
+        InnerReceipt::Succinct(receipt) => {
+            if valid_control_ids().contains(&receipt.control_id) {
+                Ok(())
+            } else {
+                Err((
+                    CompatOutcome::IncompatibleCircuit,
+                    format!("unknown control ID {}", receipt.control_id),
+                ))
+            }
+        }
+        InnerReceipt::Fake { .. } => Ok(()),
+    }
+}
+
+// Check the version byte that follows the 4-byte tag of a bundle or receipt.
+fn check_format_version(
+    bytes: &[u8],
+    supported: u8,
+    report: &mut CompatReport,
+) -> Result<(), Failure> {
+    let &version = bytes
+        .get(4)
+        .ok_or_else(|| corrupt("ends before the format version"))?;
+    report.format_version = Some(version.into());
+    if version != supported {
+        return Err((
+            CompatOutcome::IncompatibleFormat,
+            format!("unsupported format version {version}, expected {supported}"),
+        ));
+    }
+    Ok(())
+}
+
+#[cfg(feature = "cbor")]
+fn decode_cbor(bytes: &[u8], report: &mut CompatReport) -> Result<(Receipt, Header), Failure> {
+    use ciborium::{tag::Required, Value};
+
+    use super::{
+        cbor::{Envelope, SELF_DESCRIBED_TAG},
+        receipt::annotated,
+    };
+    use crate::RECEIPT_CBOR_VERSION;
+
+    // Decode the receipt only once its version is known to be supported.
+    let Required::<Envelope<Value>, SELF_DESCRIBED_TAG>(envelope) =
+        ciborium::from_reader(bytes).map_err(corrupt)?;
+    report.format_version = Some(envelope.version);
+    if envelope.version != RECEIPT_CBOR_VERSION {
+        return Err((
+            CompatOutcome::IncompatibleFormat,
+            format!(
+                "unsupported format version {}, expected {RECEIPT_CBOR_VERSION}",
+                envelope.version
+            ),
+        ));
+    }
+    let receipt = envelope
+        .receipt
+        .deserialized::<annotated::Owned>()
+        .map_err(corrupt)?
+        .into();
+    let header = Header {
+        zkvm_version: envelope.zkvm_version,
+        circuit: envelope.circuit,
+        hashfn: envelope.hashfn,
+    };
+    Ok((receipt, header))
+}
+
+#[cfg(not(feature = "cbor"))]
+fn decode_cbor(_bytes: &[u8], _report: &mut CompatReport) -> Result<(Receipt, Header), Failure> {
+    Err((
+        CompatOutcome::IncompatibleFormat,
+        "decoding CBOR receipts needs the cbor feature".to_string(),
+    ))
+}
@@ -0,0 +1,196 @@
+// Copyright 2023 RISC Zero, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+// The fixtures in testdata hold a fake receipt of a run of the guest whose
+// image ID is in testdata/image_id.hex, with an empty list of assumptions so
+// that its integrity can be verified:
+//
+// * receipt_fake.bin: the receipt encoded with Receipt::to_bytes.
+// * receipt_format_v2.bin: the same, claiming a format version that does not
+//   exist yet.
+
+use hex::FromHex;
+use test_log::test;
+
+use super::{check_receipt_compatibility_with_context, CompatOutcome, CompatReport, ReceiptFormat};
+use crate::{
+    sha::Digest, CompositeReceipt, InnerReceipt, Receipt, SecurityPolicy, SegmentReceipt,
+    SuccinctReceipt, VerificationBundle, VerifierContext, VERSION,
+};
+
+const RECEIPT_FAKE: &[u8] = include_bytes!("testdata/receipt_fake.bin");
+const RECEIPT_FORMAT_V2: &[u8] = include_bytes!("testdata/receipt_format_v2.bin");
+
+fn image_id() -> Digest {
+    Digest::from_hex(include_str!("testdata/image_id.hex").trim()).unwrap()
+}
+
+fn check(bytes: &[u8], expected_version: &str) -> CompatReport {
+    let ctx = VerifierContext::default().with_security_policy(SecurityPolicy::DevForced);
+    check_receipt_compatibility_with_context(bytes, expected_version, &ctx)
+}
+
+#[test]
+fn fixtures() {
+    let report = check(RECEIPT_FAKE, VERSION);
+    assert_eq!(report.outcome, CompatOutcome::Verified, "{report}");
+    assert_eq!(report.format, ReceiptFormat::Bytes);
+    assert_eq!(report.format_version, Some(1));
+    assert_eq!(report.image_id, Some(image_id()));
+    assert_eq!(report.detail, None);
+
+    // The expected version is only reported.
+    let report = check(RECEIPT_FAKE, "0.19.1");
+    assert_eq!(report.outcome, CompatOutcome::Verified, "{report}");
+    assert_eq!(report.zkvm_version, "0.19.1");
+
+    let report = check(RECEIPT_FORMAT_V2, VERSION);
+    assert_eq!(report.outcome, CompatOutcome::IncompatibleFormat);
+    assert_eq!(report.format_version, Some(2));
+    assert_eq!(report.image_id, None);
+}
+
+fn fake_receipt() -> Receipt {
+    Receipt::from_bytes(RECEIPT_FAKE).unwrap()
+}
+
+fn with_inner(inner: InnerReceipt) -> Receipt {
+    Receipt::new(inner, fake_receipt().journal.bytes)
+}
+
+#[test]
+fn control_ids() {
+    let InnerReceipt::Fake { metadata } = fake_receipt().inner else {
+        unreachable!()
+    };
+    let succinct = |control_id| {
+        with_inner(InnerReceipt::Succinct(SuccinctReceipt {
+            seal: vec![],
+            control_id,
+            meta: metadata.clone(),
+        }))
+    };
+
+    let report = check(&succinct(Digest::ZERO).to_bytes().unwrap(), VERSION);
+    assert_eq!(
+        report.outcome,
+        CompatOutcome::IncompatibleCircuit,
+        "{report}"
+    );
+
+    // A known control ID gets as far as the seal, which is empty.
+    let control_id = crate::host::recursion::valid_control_ids()[0];
+    let report = check(&succinct(control_id).to_bytes().unwrap(), VERSION);
+    assert_eq!(report.outcome, CompatOutcome::Corrupt, "{report}");
+
+    let composite = |hashfn: &str, assumptions| {
+        with_inner(InnerReceipt::Composite(CompositeReceipt {
+            segments: vec![SegmentReceipt {
+                seal: vec![],
+                index: 0,
+                hashfn: hashfn.to_string(),
+            }],
+            assumptions,
+            journal_digest: None,
+        }))
+    };
+
+    let report = check(&composite("keccak", vec![]).to_bytes().unwrap(), VERSION);
+    assert_eq!(
+        report.outcome,
+        CompatOutcome::IncompatibleCircuit,
+        "{report}"
+    );
+
+    let report = check(&composite("sha-256", vec![]).to_bytes().unwrap(), VERSION);
+    assert_eq!(report.outcome, CompatOutcome::Corrupt, "{report}");
+
+    // The control IDs of assumptions are checked too.
+    let assumption = succinct(Digest::ZERO).inner;
+    let report = check(
+        &composite("sha-256", vec![assumption]).to_bytes().unwrap(),
+        VERSION,
+    );
+    assert_eq!(
+        report.outcome,
+        CompatOutcome::IncompatibleCircuit,
+        "{report}"
+    );
+}
+
+#[test]
+fn current_bundle() {
+    let receipt = fake_receipt();
+
+    let bundle = VerificationBundle::new(receipt.clone(), image_id());
+    let report = check(&bundle.to_bytes().unwrap(), "0.19.1");
+    assert_eq!(report.outcome, CompatOutcome::Verified, "{report}");
+    assert_eq!(report.zkvm_version, VERSION);
+
+    let bundle = VerificationBundle::new(receipt, Digest::ZERO);
+    let report = check(&bundle.to_bytes().unwrap(), VERSION);
+    assert_eq!(report.outcome, CompatOutcome::Corrupt);
+    assert_eq!(report.image_id, Some(image_id()));
+}
+
+#[test]
+fn corrupt() {
+    for len in 0..RECEIPT_FAKE.len() {
+        let report = check(&RECEIPT_FAKE[..len], VERSION);
+        assert_eq!(report.outcome, CompatOutcome::Corrupt, "{len}: {report}");
+    }
+
+    // A flipped byte may leave a fake receipt valid, but must never panic.
+    let bundle = VerificationBundle::new(fake_receipt(), image_id())
+        .to_bytes()
+        .unwrap();
+    for fixture in [RECEIPT_FAKE, &bundle] {
+        for i in 0..fixture.len() {
+            let mut bytes = fixture.to_vec();
+            bytes[i] ^= 0xff;
+            check(&bytes, VERSION);
+        }
+    }
+
+    let report = check(b"not a receipt", VERSION);
+    assert_eq!(report.format, ReceiptFormat::Legacy);
+    assert_eq!(report.outcome, CompatOutcome::Corrupt);
+}
+
+#[cfg(feature = "cbor")]
+#[test]
+fn cbor() {
+    use ciborium::tag::Required;
+
+    use crate::host::cbor::{Envelope, SELF_DESCRIBED_TAG};
+
+    let receipt = fake_receipt();
+    let report = check(&receipt.to_cbor().unwrap(), "0.19.1");
+    assert_eq!(report.outcome, CompatOutcome::Verified, "{report}");
+    assert_eq!(report.format, ReceiptFormat::Cbor);
+    assert_eq!(report.zkvm_version, VERSION);
+
+    let envelope = Envelope {
+        version: 2,
+        zkvm_version: VERSION.to_string(),
+        circuit: None,
+        hashfn: None,
+        receipt,
+    };
+    let mut bytes = Vec::new();
+    ciborium::into_writer(&Required::<_, SELF_DESCRIBED_TAG>(envelope), &mut bytes).unwrap();
+    let report = check(&bytes, VERSION);
+    assert_eq!(report.outcome, CompatOutcome::IncompatibleFormat);
+    assert_eq!(report.format_version, Some(2));
+}
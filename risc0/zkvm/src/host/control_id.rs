@@ -14,6 +14,8 @@
 
 const CONTROL_ID_ENTRIES: usize = risc0_zkp::MAX_CYCLES_PO2 - risc0_zkp::MIN_CYCLES_PO2;
 
+/// The control IDs of the rv32im circuit for one hash function, as hex
+/// digests, one for each supported po2.
 pub type RawControlId = [&'static str; CONTROL_ID_ENTRIES];
 
 /// Control ID for SHA-256
@@ -60,3 +62,18 @@ pub const BLAKE2B_CONTROL_ID: RawControlId = [
     "bc131871045d7e6124b1b36fb3813b7dcf2af0bfe54332bd88d76cfdc4ce9825", //
     "ca2ec341980bc2c0e274d3e12c628413cf90a485bc00049a8b2d4e0918ca1cbd", //
 ];
+
+/// Control ID for Poseidon2
+pub const POSEIDON2_CONTROL_ID: RawControlId = [
+    "b190443ae6e6c80ff14a9d09ec21ec228a54fd5a6efdf171487e14698e964d27", //
+    "3b2ed62ea63e4e42c701e86c1e4b605342f31f6aca8f0711c08a694edfa5e575", //
+    "1f752e6f2705941771831c421be72f4d87fc1076847d513d356d755b84707c77", //
+    "902df62c57f7463e1efb161e67cd5f51cbfaa24edd5c190c60976b2746fed34c", //
+    "791a9777746931272dc6340e35c836542c51f5620cb5b51982a8052546975c1c", //
+    "e7f0f05f47914c24532f07312622d33c4a09ae5e1f9efb46545df81696339a62", //
+    "253293667a40e2660356de5adb661f2a0b1eda668b99f504e9281c00626e5745", //
+    "55cb3a7316a3864bf167b0000c33a1102d77da0bcb913a7372fd2d3387ea3630", //
+    "b56581457e52a2020fbb631af49b72192ed14056054be0216cf94172390daf29", //
+    "3cfcf45bc6a7825656bfce3ab2e84f58f5e424394488ed31668ac17351bf1777", //
+    "3a58ec4c9f07a606e37de3013f7b9e4f8b8a3c25a4945475db211e218e77071e", //
+];
@@ -0,0 +1,34 @@
+// Copyright 2023 RISC Zero, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use risc0_zkp::verify::VerificationError;
+
+use super::control_id::{
+    RawControlId, BLAKE2B_CONTROL_ID, POSEIDON2_CONTROL_ID, POSEIDON_CONTROL_ID, SHA256_CONTROL_ID,
+};
+
+/// Look up the control IDs of the rv32im circuit for the hash function named
+/// `hashfn`, e.g. `"sha-256"` or `"poseidon"`, as used by
+/// [ProverOpts::hashfn](crate::ProverOpts::hashfn).
+///
+/// Fails with [VerificationError::InvalidHashSuite] for any other name.
+pub fn control_id_for(hashfn: &str) -> Result<&'static RawControlId, VerificationError> {
+    match hashfn {
+        "blake2b" => Ok(&BLAKE2B_CONTROL_ID),
+        "poseidon" => Ok(&POSEIDON_CONTROL_ID),
+        "poseidon2" => Ok(&POSEIDON2_CONTROL_ID),
+        "sha-256" => Ok(&SHA256_CONTROL_ID),
+        _ => Err(VerificationError::InvalidHashSuite),
+    }
+}
@@ -0,0 +1,108 @@
+// Copyright 2023 RISC Zero, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Verification of receipts with a record of every check made on the seals.
+
+use risc0_zkp::verify::{Transcript, TranscriptStep, VerificationError};
+use serde::{Deserialize, Serialize};
+
+use crate::{sha::Digest, InnerReceipt, Receipt, VerifierContext};
+
+/// The checks made on one seal of a receipt by [Receipt::debug_verify].
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SealTrace {
+    /// Which seal of the receipt was checked, e.g. `segment 2`.
+    pub label: String,
+
+    /// The checks made on the seal, in the order they were made.
+    pub steps: Transcript,
+}
+
+/// The outcome of [Receipt::debug_verify].
+///
+/// A [VerifyTrace] can be serialized, e.g. to JSON, to attach to a bug report.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct VerifyTrace {
+    /// The checks made on each seal of the receipt, in order. Tracing stops at
+    /// the first seal that fails to verify.
+    pub seals: Vec<SealTrace>,
+
+    /// The error returned by verification, if the receipt is invalid.
+    pub error: Option<String>,
+}
+
+impl VerifyTrace {
+    /// Returns `true` if the receipt verified successfully.
+    pub fn is_ok(&self) -> bool {
+        self.error.is_none()
+    }
+
+    /// The first check that failed, along with the seal it was made on.
+    ///
+    /// Returns `None` if every seal verified, even if the receipt failed a
+    /// later check, e.g. of its image ID or journal; see
+    /// [VerifyTrace::error].
+    pub fn failure(&self) -> Option<(&SealTrace, &TranscriptStep)> {
+        self.seals.iter().find_map(|seal| {
+            seal.steps
+                .iter()
+                .find(|step| !step.passed)
+                .map(|step| (seal, step))
+        })
+    }
+
+    fn trace(
+        &mut self,
+        label: String,
+        verify: Result<(Result<(), VerificationError>, Transcript), VerificationError>,
+    ) -> Result<(), VerificationError> {
+        let (result, steps) = verify?;
+        self.seals.push(SealTrace { label, steps });
+        result
+    }
+}
+
+impl Receipt {
+    /// Verify this receipt as [Receipt::verify] does, recording each step of
+    /// the verification of its seals.
+    ///
+    /// On failure, [VerifyTrace::failure] pinpoints the first check that
+    /// failed, such as a Merkle root, a FRI query, or the evaluation of the
+    /// constraint polynomial, along with the expected and actual values and
+    /// the offset into the seal of the data that was checked.
+    pub fn debug_verify(&self, image_id: impl Into<Digest>) -> VerifyTrace {
+        let ctx = VerifierContext::default();
+        let mut trace = VerifyTrace::default();
+        let seals = match &self.inner {
+            InnerReceipt::Composite(inner) => inner.segments.iter().try_for_each(|segment| {
+                trace.trace(
+                    format!("segment {}", segment.index),
+                    segment.verify_with_transcript(&ctx),
+                )
+            }),
+            InnerReceipt::Succinct(inner) => {
+                trace.trace("succinct".to_string(), inner.verify_with_transcript(&ctx))
+            }
+            InnerReceipt::Fake { .. } => Ok(()),
+        };
+
+        // The remaining checks, on the claim and the journal, are cheap next to
+        // the seals, so run the full verification to make them.
+        trace.error = seals
+            .and_then(|()| self.verify_with_context(&ctx, image_id))
+            .err()
+            .map(|err| err.to_string());
+        trace
+    }
+}
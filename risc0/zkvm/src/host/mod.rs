@@ -14,15 +14,32 @@
 
 #[cfg(any(feature = "client", feature = "prove"))]
 pub(crate) mod api;
+#[cfg(feature = "std")]
+pub(crate) mod batch;
+#[cfg(feature = "bench")]
+pub(crate) mod bench;
+#[cfg(feature = "client")]
+pub(crate) mod bundle;
+#[cfg(feature = "cbor")]
+pub(crate) mod cbor;
 #[cfg(feature = "client")]
 pub(crate) mod client;
+#[cfg(feature = "client")]
+pub(crate) mod compat;
 pub(crate) mod control_id;
+pub(crate) mod control_registry;
+#[cfg(feature = "debug-verify")]
+pub(crate) mod debug_verify;
 #[cfg(feature = "profiler")]
 pub(crate) mod profiler;
 pub(crate) mod receipt;
 pub(crate) mod recursion;
 #[cfg(feature = "prove")]
 pub(crate) mod server;
+#[cfg(feature = "service")]
+pub(crate) mod service;
+#[cfg(feature = "std")]
+pub(crate) mod verify_cache;
 
 #[cfg(any(feature = "client", feature = "prove"))]
 mod protos {
@@ -17,16 +17,19 @@
 use alloc::{collections::BTreeMap, string::String, vec, vec::Vec};
 use core::fmt::Debug;
 
-use anyhow::Result;
+use anyhow::{bail, Result};
 use risc0_binfmt::SystemState;
-use risc0_circuit_rv32im::layout;
+use risc0_circuit_rv32im::{layout, CircuitImpl};
 use risc0_core::field::baby_bear::BabyBear;
+#[cfg(feature = "debug-verify")]
+use risc0_zkp::verify::Transcript;
 use risc0_zkp::{
+    adapter::CircuitInfo,
     core::{
         digest::Digest,
         hash::{
-            blake2b::Blake2bCpuHashSuite, poseidon::PoseidonHashSuite, sha::Sha256HashSuite,
-            HashSuite,
+            blake2b::Blake2bCpuHashSuite, poseidon::PoseidonHashSuite,
+            poseidon2::Poseidon2HashSuite, sha::Sha256HashSuite, HashSuite,
         },
     },
     layout::Buffer,
@@ -35,14 +38,14 @@ use risc0_zkp::{
 use risc0_zkvm_platform::WORD_SIZE;
 use serde::{de::DeserializeOwned, Deserialize, Serialize};
 
-use super::control_id::{BLAKE2B_CONTROL_ID, POSEIDON_CONTROL_ID, SHA256_CONTROL_ID};
+use super::control_registry::control_id_for;
 // Make succinct receipt available through this `receipt` module.
 pub use super::recursion::SuccinctReceipt;
 use crate::{
     receipt_metadata::{Assumptions, MaybePruned, Output},
-    serde::{from_slice, Error},
+    serde::{from_bytes, Error},
     sha::{Digestible, Sha256},
-    ExitCode, ReceiptMetadata,
+    ExitCode, ReceiptMetadata, SecurityPolicy,
 };
 
 /// A receipt attesting to the execution of a Session.
@@ -71,7 +74,7 @@ use crate::{
 /// # #[cfg(not(feature = "cuda"))]
 /// # #[cfg(feature = "prove")]
 /// # {
-/// let env = ExecutorEnv::builder().write_slice(&[20]).build().unwrap();
+/// let env = ExecutorEnv::builder().write_words(&[20]).build().unwrap();
 /// let receipt = default_prover().prove_elf(env, FIB_ELF).unwrap();
 /// # }
 /// ```
@@ -90,7 +93,7 @@ use crate::{
 /// # #[cfg(not(feature = "cuda"))]
 /// # #[cfg(feature = "prove")]
 /// # {
-/// # let env = ExecutorEnv::builder().write_slice(&[20]).build().unwrap();
+/// # let env = ExecutorEnv::builder().write_words(&[20]).build().unwrap();
 /// # let receipt = default_prover().prove_elf(env, FIB_ELF).unwrap();
 /// receipt.verify(FIB_ID).unwrap();
 /// # }
@@ -112,6 +115,91 @@ pub struct Receipt {
     /// This data is cryptographically authenticated in
     /// [Receipt::verify].
     pub journal: Journal,
+
+    /// Key-value annotations attached by the host, e.g. to identify the guest
+    /// build that produced this receipt. See [ProverOpts::with_annotation].
+    ///
+    /// Annotations are stored alongside the proven claim, not inside it: they
+    /// are NOT authenticated by [Receipt::verify], and anyone holding the
+    /// receipt can change them.
+    ///
+    /// They are left out of the serde encoding of a [Receipt], e.g. with
+    /// `bincode::serialize`, which stays the same as that of releases
+    /// without annotations. They are kept by the versioned encodings:
+    /// `Receipt::to_bytes`, `Receipt::to_cbor`, the protobuf encoding and
+    /// `VerificationBundle`.
+    ///
+    /// [ProverOpts::with_annotation]: crate::ProverOpts::with_annotation
+    #[serde(skip)]
+    pub annotations: BTreeMap<String, String>,
+}
+
+/// The encoding of a [Receipt] along with its annotations, used by the
+/// versioned encodings of receipts, e.g. as
+/// `#[serde(with = "crate::host::receipt::annotated")]`.
+///
+/// The annotations follow the fields of the serde encoding of [Receipt], so
+/// that a receipt without them can be decoded from a format that allows
+/// missing fields.
+#[cfg(any(feature = "client", feature = "cbor"))]
+pub(crate) mod annotated {
+    use alloc::{collections::BTreeMap, string::String};
+
+    use serde::{Deserialize, Serialize};
+    #[cfg(feature = "client")]
+    use serde::{Deserializer, Serializer};
+
+    use super::{InnerReceipt, Journal, Receipt};
+
+    #[derive(Serialize)]
+    pub(crate) struct Ref<'a> {
+        inner: &'a InnerReceipt,
+        journal: &'a Journal,
+        annotations: &'a BTreeMap<String, String>,
+    }
+
+    impl<'a> From<&'a Receipt> for Ref<'a> {
+        fn from(receipt: &'a Receipt) -> Self {
+            Self {
+                inner: &receipt.inner,
+                journal: &receipt.journal,
+                annotations: &receipt.annotations,
+            }
+        }
+    }
+
+    #[derive(Deserialize)]
+    pub(crate) struct Owned {
+        inner: InnerReceipt,
+        journal: Journal,
+        #[serde(default)]
+        annotations: BTreeMap<String, String>,
+    }
+
+    impl From<Owned> for Receipt {
+        fn from(receipt: Owned) -> Self {
+            Self {
+                inner: receipt.inner,
+                journal: receipt.journal,
+                annotations: receipt.annotations,
+            }
+        }
+    }
+
+    #[cfg(feature = "client")]
+    pub(crate) fn serialize<S: Serializer>(
+        receipt: &Receipt,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        Ref::from(receipt).serialize(serializer)
+    }
+
+    #[cfg(feature = "client")]
+    pub(crate) fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Receipt, D::Error> {
+        Owned::deserialize(deserializer).map(Receipt::from)
+    }
 }
 
 impl Receipt {
@@ -120,6 +208,7 @@ impl Receipt {
         Self {
             inner,
             journal: Journal::new(journal),
+            annotations: BTreeMap::new(),
         }
     }
 
@@ -148,44 +237,95 @@ impl Receipt {
         ctx: &VerifierContext,
         image_id: impl Into<Digest>,
     ) -> Result<(), VerificationError> {
-        self.inner.verify_integrity_with_context(ctx)?;
+        let image_id = image_id.into();
+        #[cfg(feature = "std")]
+        let metadata = match &ctx.cache {
+            Some(cache) => {
+                super::verify_cache::verify_cached(cache.as_ref(), ctx, &self.inner, image_id)?
+            }
+            None => {
+                self.inner.verify_integrity_with_context(ctx)?;
+                self.inner.get_metadata()?
+            }
+        };
+        #[cfg(not(feature = "std"))]
+        let metadata = {
+            self.inner.verify_integrity_with_context(ctx)?;
+            self.inner.get_metadata()?
+        };
 
         // NOTE: Post-state digest and input digest are unconstrained by this method.
-        let metadata = self.inner.get_metadata()?;
+        check_claim(&metadata, &self.journal, image_id)
+    }
 
-        if metadata.pre.digest() != image_id.into() {
-            return Err(VerificationError::ImageVerificationError);
+    /// Verify that this receipt proves a successful execution of the zkVM from
+    /// the given `image_id`, bound to `nonce`.
+    ///
+    /// In addition to the checks made by [Receipt::verify], this checks that
+    /// the journal begins with `nonce`, as committed by a guest that calls
+    /// `env::commit(&env::nonce())` before committing anything else. A
+    /// receipt whose journal is too short to hold a nonce fails with
+    /// [ReceiptPolicyError::MissingNonce].
+    pub fn verify_with_nonce(
+        &self,
+        image_id: impl Into<Digest>,
+        nonce: [u8; 32],
+    ) -> Result<(), ReceiptPolicyError> {
+        self.verify(image_id)?;
+        let committed: [u8; 32] = crate::serde::from_slice(&self.journal.bytes)
+            .map_err(|_| ReceiptPolicyError::MissingNonce)?;
+        if committed != nonce {
+            return Err(ReceiptPolicyError::NonceMismatch);
         }
+        Ok(())
+    }
 
-        // Check the exit code. This verification method requires execution to be
-        // successful.
-        let (ExitCode::Halted(0) | ExitCode::Paused(0)) = metadata.exit_code else {
-            return Err(VerificationError::UnexpectedExitCode);
-        };
+    /// Verify that this receipt proves a successful execution of the zkVM from
+    /// the given `image_id`, by a guest that committed `items` to a hashed
+    /// journal.
+    ///
+    /// In addition to the checks made by [Receipt::verify], this checks that
+    /// the journal is the hash chain of `items`, as committed by a guest that
+    /// calls `env::hashed_journal`; see [hashed_journal](crate::hashed_journal).
+    /// Otherwise it fails with [VerificationError::JournalDigestMismatch].
+    pub fn verify_with_preimages<I: AsRef<[u8]>>(
+        &self,
+        image_id: impl Into<Digest>,
+        items: impl IntoIterator<Item = I>,
+    ) -> Result<(), VerificationError> {
+        self.verify(image_id)?;
+        if self.journal.bytes != crate::hashed_journal::chain(items).as_bytes() {
+            return Err(VerificationError::JournalDigestMismatch);
+        }
+        Ok(())
+    }
 
-        // Finally check the output hash in the decoded metadata against the expected
-        // output.
-        let expected_output = Output {
-            journal: MaybePruned::Pruned(self.journal.digest()),
-            // It is expected that there are no (unresolved) assumptions.
-            assumptions: Assumptions(vec![]).into(),
+    /// Verify that this receipt proves a successful execution of the zkVM from
+    /// the given `image_id`, proven with the hash function named `hashfn`.
+    ///
+    /// In addition to the checks made by [Receipt::verify], this fails with
+    /// [VerificationError::InvalidHashSuite] if any seal of the receipt was
+    /// proven with another hash function, or if `hashfn` is not one that
+    /// [control_id_for](crate::control_id_for) knows. This lets a verifier that
+    /// picks the hash function at runtime, e.g. from its configuration, hold
+    /// the receipt to that choice rather than the one recorded in it.
+    pub fn verify_with_hashfn(
+        &self,
+        image_id: impl Into<Digest>,
+        hashfn: &str,
+    ) -> Result<(), VerificationError> {
+        control_id_for(hashfn)?;
+        let matches = match &self.inner {
+            InnerReceipt::Composite(inner) => inner
+                .segments
+                .iter()
+                .all(|segment| segment.hashfn == hashfn),
+            inner => inner.hashfn().map_or(true, |x| x == hashfn),
         };
-
-        if metadata.output.digest() != expected_output.digest() {
-            let empty_output = metadata.output.is_none() && self.journal.bytes.is_empty();
-            if !empty_output {
-                tracing::debug!(
-                    "journal: 0x{}, expected output digest: 0x{}, decoded output digest: 0x{}",
-                    hex::encode(&self.journal.bytes),
-                    hex::encode(expected_output.digest()),
-                    hex::encode(metadata.output.digest()),
-                );
-                return Err(VerificationError::JournalDigestMismatch);
-            }
-            tracing::debug!("accepting zero digest for output of receipt with empty journal");
+        if !matches {
+            return Err(VerificationError::InvalidHashSuite);
         }
-
-        Ok(())
+        self.verify(image_id)
     }
 
     /// Verify the integrity of this receipt, ensuring the metadata and jounral are attested to by
@@ -238,6 +378,171 @@ impl Receipt {
     pub fn get_metadata(&self) -> Result<ReceiptMetadata, VerificationError> {
         self.inner.get_metadata()
     }
+
+    /// Drop the seals from this receipt, keeping only the claim and journal.
+    ///
+    /// See [PrunedReceipt] for what is retained.
+    pub fn prune(&self) -> Result<PrunedReceipt, VerificationError> {
+        let segments = match &self.inner {
+            InnerReceipt::Composite(inner) => inner
+                .segments
+                .iter()
+                .map(|segment| Ok(segment.get_metadata()?.digest()))
+                .collect::<Result<_, VerificationError>>()?,
+            _ => Vec::new(),
+        };
+        Ok(PrunedReceipt {
+            metadata: self.get_metadata()?,
+            segments,
+            journal: self.journal.clone(),
+        })
+    }
+
+    /// The only [SegmentReceipt] of a receipt for an execution that fit in a
+    /// single segment.
+    ///
+    /// The journal is not part of a [SegmentReceipt]; keep [Receipt::journal]
+    /// to turn the result back into a [Receipt] with
+    /// [SegmentReceipt::into_receipt], which verifies exactly as this receipt
+    /// does. Fails rather than drop anything the segment receipt cannot
+    /// carry: further segments, assumptions, or a receipt that is not a
+    /// [CompositeReceipt].
+    pub fn flatten(&self) -> Result<SegmentReceipt> {
+        let InnerReceipt::Composite(inner) = &self.inner else {
+            bail!("only a composite receipt can be flattened into a segment receipt");
+        };
+        if !inner.assumptions.is_empty() {
+            bail!(
+                "receipt has {} assumptions, which a segment receipt cannot carry",
+                inner.assumptions.len()
+            );
+        }
+        match inner.segments.as_slice() {
+            [segment] => Ok(segment.clone()),
+            segments => bail!(
+                "receipt has {} segments, only a single-segment receipt can be flattened",
+                segments.len()
+            ),
+        }
+    }
+
+    /// Encode this receipt for use as an assumption inside a guest.
+    ///
+    /// The receipt is encoded with the zkVM [serde](crate::serde) format and
+    /// returned as little-endian bytes, such that a guest can reconstruct the
+    /// words and decode the receipt with [from_slice](crate::serde::from_slice).
+    /// A guest obtains these bytes for each proven assumption through
+    /// `env::read_assumption`.
+    pub fn into_assumption_bytes(self) -> Result<Vec<u8>, Error> {
+        Ok(crate::serde::to_vec(&self)?
+            .into_iter()
+            .flat_map(|word| word.to_le_bytes())
+            .collect())
+    }
+
+    /// Decode the journal as a `T`, after checking that the guest committed
+    /// it with the [InputSchema](crate::InputSchema) of `T`.
+    ///
+    /// The journal must start with the digest of the schema, as committed by
+    /// [commit_checked!](crate::commit_checked) with a trailing `prefixed`.
+    /// A different digest fails with a [SchemaMismatch], which the error can
+    /// be downcast to.
+    ///
+    /// This does not verify the receipt; call [Receipt::verify] first.
+    ///
+    /// [SchemaMismatch]: crate::SchemaMismatch
+    #[cfg(feature = "std")]
+    pub fn journal_decode_checked<T: DeserializeOwned + crate::InputSchema>(&self) -> Result<T> {
+        use risc0_zkp::core::digest::DIGEST_BYTES;
+
+        let bytes = &self.journal.bytes;
+        if bytes.len() < DIGEST_BYTES {
+            bail!("Journal is too short to start with a schema digest");
+        }
+        let expected = risc0_binfmt::input_schema_digest(T::SCHEMA);
+        let journal = Digest::try_from(&bytes[..DIGEST_BYTES])?;
+        if journal != expected {
+            return Err(crate::SchemaMismatch { expected, journal }.into());
+        }
+        Ok(from_bytes(&bytes[DIGEST_BYTES..])?)
+    }
+}
+
+// Check that the metadata claims a successful execution of `image_id` that
+// committed `journal`.
+fn check_claim(
+    metadata: &ReceiptMetadata,
+    journal: &Journal,
+    image_id: Digest,
+) -> Result<(), VerificationError> {
+    if metadata.pre.digest() != image_id {
+        return Err(VerificationError::ImageVerificationError);
+    }
+
+    // Check the exit code. This verification method requires execution to be
+    // successful.
+    let (ExitCode::Halted(0) | ExitCode::Paused(0)) = metadata.exit_code else {
+        return Err(VerificationError::UnexpectedExitCode);
+    };
+
+    // Finally check the output hash in the decoded metadata against the expected
+    // output.
+    let expected_output = Output {
+        journal: MaybePruned::Pruned(journal.digest()),
+        // It is expected that there are no (unresolved) assumptions.
+        assumptions: Assumptions(vec![]).into(),
+    };
+
+    if metadata.output.digest() != expected_output.digest() {
+        let empty_output = metadata.output.is_none() && journal.bytes.is_empty();
+        if !empty_output {
+            tracing::debug!(
+                "journal: 0x{}, expected output digest: 0x{}, decoded output digest: 0x{}",
+                hex::encode(&journal.bytes),
+                hex::encode(expected_output.digest()),
+                hex::encode(metadata.output.digest()),
+            );
+            return Err(VerificationError::JournalDigestMismatch);
+        }
+        tracing::debug!("accepting zero digest for output of receipt with empty journal");
+    }
+
+    Ok(())
+}
+
+/// A [Receipt] with its seals removed, as produced by [Receipt::prune].
+///
+/// This keeps what is needed to archive the result of a session: the journal
+/// and the [ReceiptMetadata] (which includes the image ID and exit code), plus
+/// the digest of each segment's metadata so that segment receipts recovered
+/// later can be matched back to it.
+///
+/// A [PrunedReceipt] is NOT a proof. Without the seals, nothing attests that
+/// its claim is true.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[cfg_attr(test, derive(PartialEq))]
+pub struct PrunedReceipt {
+    /// The [ReceiptMetadata] of the original receipt.
+    pub metadata: ReceiptMetadata,
+
+    /// The digests of the [ReceiptMetadata] of each [SegmentReceipt], in
+    /// order. Empty if the original receipt was not a [CompositeReceipt].
+    pub segments: Vec<Digest>,
+
+    /// The public commitment written by the guest.
+    pub journal: Journal,
+}
+
+impl PrunedReceipt {
+    /// Check that this pruned receipt claims a successful execution from the
+    /// given `image_id` that committed its journal.
+    ///
+    /// This only checks the internal consistency of the claim, which is taken
+    /// at face value. It is NOT a cryptographic verification; use
+    /// [Receipt::verify] on the original receipt for that.
+    pub fn verify_claim_only(&self, image_id: impl Into<Digest>) -> Result<(), VerificationError> {
+        check_claim(&self.metadata, &self.journal, image_id.into())
+    }
 }
 
 /// A journal is a record of all public commitments for a given proof session.
@@ -254,8 +559,10 @@ impl Journal {
     }
 
     /// Decode the journal bytes by using the risc0 deserializer.
+    ///
+    /// The journal need not be a whole number of words; see [from_bytes].
     pub fn decode<T: DeserializeOwned>(&self) -> Result<T, Error> {
-        from_slice(&self.bytes)
+        from_bytes(&self.bytes)
     }
 }
 
@@ -283,9 +590,10 @@ pub enum InnerReceipt {
 
     /// A fake receipt for testing and development.
     ///
-    /// This receipt is not valid and will fail verification unless the
-    /// environment variable `RISC0_DEV_MODE` is set to `true`, in which case a
-    /// pass-through 'verification' will be performed, but it *does not*
+    /// This receipt is not valid and will fail verification unless dev mode
+    /// is enabled by the [SecurityPolicy] of the [VerifierContext], by
+    /// default through the environment variable `RISC0_DEV_MODE`. In that case
+    /// a pass-through 'verification' will be performed, but it *does not*
     /// represent any meaningful attestation of receipt's integrity.
     ///
     /// This type solely exists to improve development experience, for further
@@ -308,8 +616,7 @@ impl InnerReceipt {
             InnerReceipt::Composite(x) => x.verify_integrity_with_context(ctx),
             InnerReceipt::Succinct(x) => x.verify_integrity_with_context(ctx),
             InnerReceipt::Fake { .. } => {
-                #[cfg(feature = "std")]
-                if crate::is_dev_mode() {
+                if ctx.security_policy.is_dev_mode() {
                     return Ok(());
                 }
                 Err(VerificationError::InvalidProof)
@@ -335,6 +642,45 @@ impl InnerReceipt {
         }
     }
 
+    /// Returns the name of the hash function used to prove this receipt.
+    ///
+    /// Verifiers can use this to select the matching hash suite from their
+    /// [VerifierContext]. Returns `None` for fake receipts, and for composite
+    /// receipts with no segments.
+    pub fn hashfn(&self) -> Option<&str> {
+        match self {
+            InnerReceipt::Composite(x) => x.segments.first().map(|x| x.hashfn.as_str()),
+            // All receipts from the recursion circuit use Poseidon.
+            InnerReceipt::Succinct(_) => Some("poseidon"),
+            InnerReceipt::Fake { .. } => None,
+        }
+    }
+
+    // The name of the circuit that produced the seals of this receipt, or
+    // `None` if it is fake.
+    #[cfg(any(feature = "cbor", feature = "client"))]
+    pub(crate) fn circuit(&self) -> Option<&'static str> {
+        match self {
+            InnerReceipt::Composite(_) => Some("rv32im"),
+            InnerReceipt::Succinct(_) => Some("recursion"),
+            InnerReceipt::Fake { .. } => None,
+        }
+    }
+
+    // The total size of the seals of this receipt and of its assumptions.
+    #[cfg(feature = "client")]
+    fn seal_words(&self) -> usize {
+        match self {
+            InnerReceipt::Composite(x) => {
+                let segments: usize = x.segments.iter().map(|x| x.seal.len()).sum();
+                let assumptions: usize = x.assumptions.iter().map(|x| x.seal_words()).sum();
+                segments + assumptions
+            }
+            InnerReceipt::Succinct(x) => x.seal.len(),
+            InnerReceipt::Fake { .. } => 0,
+        }
+    }
+
     /// Extract the [ReceiptMetadata] from this receipt.
     pub fn get_metadata(&self) -> Result<ReceiptMetadata, VerificationError> {
         match self {
@@ -343,6 +689,31 @@ impl InnerReceipt {
             InnerReceipt::Fake { metadata } => Ok(metadata.clone()),
         }
     }
+
+    // Check that this receipt, and each of its assumptions, was proven with
+    // `hashfn`, so that it can be an assumption of a session proven with it.
+    pub(crate) fn check_hashfn(&self, hashfn: &str) -> Result<()> {
+        let proven_with = match self {
+            InnerReceipt::Composite(receipt) => {
+                match receipt.segments.iter().find(|x| x.hashfn != hashfn) {
+                    Some(segment) => segment.hashfn.as_str(),
+                    None => {
+                        return receipt
+                            .assumptions
+                            .iter()
+                            .try_for_each(|assumption| assumption.check_hashfn(hashfn))
+                    }
+                }
+            }
+            // All receipts from the recursion circuit use Poseidon.
+            InnerReceipt::Succinct(_) => "poseidon",
+            InnerReceipt::Fake { .. } => return Ok(()),
+        };
+        if proven_with != hashfn {
+            bail!("assumption proven with hashfn {proven_with} cannot be used in a session proven with {hashfn}");
+        }
+        Ok(())
+    }
 }
 
 /// A receipt composed of one or more [SegmentReceipt] structs proving a single
@@ -572,12 +943,43 @@ impl SegmentReceipt {
         &self,
         ctx: &VerifierContext,
     ) -> Result<(), VerificationError> {
+        let (suite, check_code) = self.seal_verifier(ctx)?;
+        risc0_zkp::verify::verify(&super::CIRCUIT, suite, &self.seal, check_code)
+    }
+
+    /// Verify the seal as [SegmentReceipt::verify_integrity_with_context]
+    /// does, recording each check made.
+    #[cfg(feature = "debug-verify")]
+    pub(crate) fn verify_with_transcript(
+        &self,
+        ctx: &VerifierContext,
+    ) -> Result<(Result<(), VerificationError>, Transcript), VerificationError> {
+        let (suite, check_code) = self.seal_verifier(ctx)?;
+        Ok(risc0_zkp::verify::verify_with_transcript(
+            &super::CIRCUIT,
+            suite,
+            &self.seal,
+            check_code,
+        ))
+    }
+
+    // The hash suite and control ID check used to verify the seal.
+    fn seal_verifier<'a>(
+        &self,
+        ctx: &'a VerifierContext,
+    ) -> Result<
+        (
+            &'a HashSuite<BabyBear>,
+            impl Fn(u32, &Digest) -> Result<(), VerificationError>,
+        ),
+        VerificationError,
+    > {
         use hex::FromHex;
-        let check_code = |_, control_id: &Digest| -> Result<(), VerificationError> {
-            POSEIDON_CONTROL_ID
-                .into_iter()
-                .chain(SHA256_CONTROL_ID)
-                .chain(BLAKE2B_CONTROL_ID)
+        // Only accept control IDs generated with the hash function of this receipt.
+        let control_ids = control_id_for(&self.hashfn)?;
+        let check_code = move |_, control_id: &Digest| -> Result<(), VerificationError> {
+            control_ids
+                .iter()
                 .find(|x| Digest::from_hex(x).unwrap() == *control_id)
                 .map(|_| ())
                 .ok_or(VerificationError::ControlVerificationError)
@@ -586,7 +988,7 @@ impl SegmentReceipt {
             .suites
             .get(&self.hashfn)
             .ok_or(VerificationError::InvalidHashSuite)?;
-        risc0_zkp::verify::verify(&super::CIRCUIT, suite, &self.seal, check_code)
+        Ok((suite, check_code))
     }
 
     /// Returns the [ReceiptMetadata] for this receipt.
@@ -595,10 +997,41 @@ impl SegmentReceipt {
         decode_receipt_metadata_from_io(layout::OutBuffer(elems))
     }
 
+    /// Wrap this receipt for a whole execution in a [Receipt] with the given
+    /// `journal`, undoing [Receipt::flatten].
+    ///
+    /// The [Receipt] holds a [CompositeReceipt] of this one segment and
+    /// verifies exactly as this segment does, including the check that
+    /// `journal` is the one it committed to. Annotations are not carried by
+    /// a [SegmentReceipt], so the [Receipt] has none.
+    pub fn into_receipt(self, journal: Vec<u8>) -> Result<Receipt, VerificationError> {
+        let metadata = self.get_metadata()?;
+        let journal_digest = metadata.output.is_some().then(|| journal.digest());
+        let inner = InnerReceipt::Composite(CompositeReceipt {
+            segments: vec![self],
+            assumptions: Vec::new(),
+            journal_digest,
+        });
+        Ok(Receipt::new(inner, journal))
+    }
+
     /// Return the seal for this receipt, as a vector of bytes.
     pub fn get_seal_bytes(&self) -> Vec<u8> {
         self.seal.iter().flat_map(|x| x.to_le_bytes()).collect()
     }
+
+    /// Returns the po2 this segment was proven at, i.e. the log2 of the number
+    /// of cycles in the proof.
+    ///
+    /// This can be smaller than the [Segment](crate::Segment) po2 when
+    /// [ProverOpts::with_auto_po2_downshift](crate::ProverOpts::with_auto_po2_downshift)
+    /// is in effect.
+    pub fn po2(&self) -> Result<u32, VerificationError> {
+        self.seal
+            .get(CircuitImpl::OUTPUT_SIZE)
+            .copied()
+            .ok_or(VerificationError::ReceiptFormatError)
+    }
 }
 
 /// An assumption attached with a guest execution as a result of calling `env::verify` or
@@ -663,8 +1096,231 @@ impl From<ReceiptMetadata> for Assumption {
 pub struct VerifierContext {
     /// A registry of hash functions to be used by the verification process.
     pub suites: BTreeMap<String, HashSuite<BabyBear>>,
+
+    /// Whether fake receipts produced in dev mode are accepted.
+    pub security_policy: SecurityPolicy,
+
+    /// The largest journal, in bytes, accepted by
+    /// [VerifierContext::decode_receipt]. Defaults to 256 MiB.
+    pub max_journal_bytes: usize,
+
+    /// The largest seal, in words, accepted by
+    /// [VerifierContext::decode_receipt], summed over all the seals of a
+    /// receipt and of its assumptions. Defaults to 256 Mi words, i.e. 1 GiB.
+    pub max_seal_words: usize,
+
+    /// Where the results of verifying receipts are kept for reuse; see
+    /// [VerifierContext::with_cache].
+    #[cfg(feature = "std")]
+    pub(crate) cache: Option<alloc::rc::Rc<dyn super::verify_cache::VerifyCache>>,
 }
 
+impl VerifierContext {
+    /// Set the [SecurityPolicy] that decides whether fake receipts produced
+    /// in dev mode are accepted.
+    pub fn with_security_policy(mut self, security_policy: SecurityPolicy) -> Self {
+        self.security_policy = security_policy;
+        self
+    }
+
+    /// Set the largest journal, in bytes, accepted by
+    /// [VerifierContext::decode_receipt].
+    pub fn with_max_journal_bytes(mut self, max_journal_bytes: usize) -> Self {
+        self.max_journal_bytes = max_journal_bytes;
+        self
+    }
+
+    /// Set the largest seal, in words, accepted by
+    /// [VerifierContext::decode_receipt].
+    pub fn with_max_seal_words(mut self, max_seal_words: usize) -> Self {
+        self.max_seal_words = max_seal_words;
+        self
+    }
+
+    /// Accept only receipts proven with the hash functions named in `names`,
+    /// by removing every other hash suite from [VerifierContext::suites].
+    ///
+    /// Receipts proven with any other hash function fail with
+    /// [VerificationError::InvalidHashSuite]. Succinct receipts are always
+    /// proven with `poseidon`.
+    pub fn with_allowed_hash_suites<'a>(
+        mut self,
+        names: impl IntoIterator<Item = &'a str>,
+    ) -> Self {
+        let names: Vec<&str> = names.into_iter().collect();
+        self.suites.retain(|name, _| names.contains(&name.as_str()));
+        self
+    }
+
+    /// Keep the results of verifying receipts in `cache`, and reuse them when
+    /// the same receipt is verified again with [Receipt::verify_with_context].
+    ///
+    /// A result is looked up by a digest of the seals and claims of the
+    /// receipt, the image ID and the hash suites of this context. The claim
+    /// is still checked against the journal and image ID on every call, so
+    /// that only the verification of the seals is skipped.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use risc0_zkvm::{LruVerifyCache, VerifierContext};
+    ///
+    /// let ctx = VerifierContext::default().with_cache(LruVerifyCache::new(1024));
+    /// ```
+    #[cfg(feature = "std")]
+    pub fn with_cache(mut self, cache: impl super::verify_cache::VerifyCache + 'static) -> Self {
+        self.cache = Some(alloc::rc::Rc::new(cache));
+        self
+    }
+
+    /// Decode a [Receipt] encoded with `bincode::serialize` from `bytes`,
+    /// which may have been produced by an adversary, and check it against the
+    /// size limits of this context.
+    ///
+    /// Before anything is decoded, the length of `bytes` is checked against
+    /// the largest encoding of a receipt within
+    /// [VerifierContext::max_journal_bytes] and
+    /// [VerifierContext::max_seal_words], and a longer input fails with
+    /// [ReceiptPolicyError::EncodingTooLarge]. No length prefix inside the
+    /// encoding may claim more than the input holds, so decoding allocates no
+    /// more than that. The decoded receipt then fails with
+    /// [ReceiptPolicyError::JournalTooLarge] or
+    /// [ReceiptPolicyError::SealTooLarge] if it exceeds either limit.
+    ///
+    /// The receipt is not verified; call [Receipt::verify_with_context] on
+    /// the result.
+    #[cfg(feature = "client")]
+    pub fn decode_receipt(&self, bytes: &[u8]) -> Result<Receipt, ReceiptPolicyError> {
+        let max = self.max_encoded_bytes();
+        if bytes.len() > max {
+            return Err(ReceiptPolicyError::EncodingTooLarge {
+                bytes: bytes.len(),
+                max,
+            });
+        }
+        let limits = super::api::decode::DecodeLimits {
+            max_bytes: max as u64,
+            ..Default::default()
+        };
+        let receipt: Receipt = limits
+            .decode(bytes)
+            .map_err(|_| ReceiptPolicyError::Malformed)?;
+        self.check_journal_bytes(receipt.journal.bytes.len())?;
+        self.check_seal_words(receipt.inner.seal_words())?;
+        Ok(receipt)
+    }
+
+    // The largest encoding of a receipt within the limits of this context.
+    // Every byte of an encoded receipt is part of its journal, of a seal, or
+    // of the metadata around them, which takes up less than a word for each
+    // word of seal plus a fixed allowance for fake receipts.
+    #[cfg(feature = "client")]
+    fn max_encoded_bytes(&self) -> usize {
+        const METADATA_BYTES: usize = 64 * 1024;
+        self.max_journal_bytes
+            .saturating_add(self.max_seal_words.saturating_mul(2 * WORD_SIZE))
+            .saturating_add(METADATA_BYTES)
+    }
+
+    #[cfg(feature = "client")]
+    fn check_journal_bytes(&self, bytes: usize) -> Result<(), ReceiptPolicyError> {
+        if bytes > self.max_journal_bytes {
+            return Err(ReceiptPolicyError::JournalTooLarge {
+                bytes,
+                max: self.max_journal_bytes,
+            });
+        }
+        Ok(())
+    }
+
+    #[cfg(feature = "client")]
+    fn check_seal_words(&self, words: usize) -> Result<(), ReceiptPolicyError> {
+        if words > self.max_seal_words {
+            return Err(ReceiptPolicyError::SealTooLarge {
+                words,
+                max: self.max_seal_words,
+            });
+        }
+        Ok(())
+    }
+}
+
+/// An error from a check that the zkVM makes on a [Receipt] in addition to
+/// verifying it, such as [Receipt::verify_with_nonce] and the size limits of
+/// [VerifierContext::decode_receipt].
+#[derive(Debug, PartialEq)]
+#[non_exhaustive]
+pub enum ReceiptPolicyError {
+    /// The receipt itself failed to verify.
+    Verification(VerificationError),
+
+    /// The journal does not begin with a nonce.
+    MissingNonce,
+
+    /// The journal begins with a nonce other than the expected one.
+    NonceMismatch,
+
+    /// The encoded receipt is longer than any receipt within the limits of
+    /// the [VerifierContext].
+    EncodingTooLarge {
+        /// The length of the encoding, in bytes.
+        bytes: usize,
+        /// The longest encoding accepted, in bytes.
+        max: usize,
+    },
+
+    /// The encoded receipt is not a valid encoding of a [Receipt].
+    Malformed,
+
+    /// The journal is larger than [VerifierContext::max_journal_bytes].
+    JournalTooLarge {
+        /// The size of the journal, in bytes.
+        bytes: usize,
+        /// The value of [VerifierContext::max_journal_bytes].
+        max: usize,
+    },
+
+    /// The seals of the receipt and of its assumptions are larger in total
+    /// than [VerifierContext::max_seal_words].
+    SealTooLarge {
+        /// The total size of the seals, in words.
+        words: usize,
+        /// The value of [VerifierContext::max_seal_words].
+        max: usize,
+    },
+}
+
+impl From<VerificationError> for ReceiptPolicyError {
+    fn from(err: VerificationError) -> Self {
+        Self::Verification(err)
+    }
+}
+
+impl core::fmt::Display for ReceiptPolicyError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            ReceiptPolicyError::Verification(err) => write!(f, "{err}"),
+            ReceiptPolicyError::MissingNonce => write!(f, "Journal does not begin with a nonce"),
+            ReceiptPolicyError::NonceMismatch => write!(f, "Nonce mismatch"),
+            ReceiptPolicyError::EncodingTooLarge { bytes, max } => write!(
+                f,
+                "Encoded receipt of {bytes} bytes exceeds the limit of {max}"
+            ),
+            ReceiptPolicyError::Malformed => write!(f, "Malformed receipt encoding"),
+            ReceiptPolicyError::JournalTooLarge { bytes, max } => write!(
+                f,
+                "Journal of {bytes} bytes exceeds max_journal_bytes of {max}"
+            ),
+            ReceiptPolicyError::SealTooLarge { words, max } => {
+                write!(f, "Seal of {words} words exceeds max_seal_words of {max}")
+            }
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for ReceiptPolicyError {}
+
 fn decode_system_state_from_io(
     io: layout::OutBuffer,
     sys_state: &layout::SystemState,
@@ -727,8 +1383,14 @@ impl Default for VerifierContext {
             suites: BTreeMap::from([
                 ("blake2b".into(), Blake2bCpuHashSuite::new_suite()),
                 ("poseidon".into(), PoseidonHashSuite::new_suite()),
+                ("poseidon2".into(), Poseidon2HashSuite::new_suite()),
                 ("sha-256".into(), Sha256HashSuite::new_suite()),
             ]),
+            security_policy: SecurityPolicy::default(),
+            max_journal_bytes: 1 << 28,
+            max_seal_words: 1 << 28,
+            #[cfg(feature = "std")]
+            cache: None,
         }
     }
 }
@@ -16,8 +16,14 @@ use alloc::{collections::VecDeque, vec::Vec};
 
 use risc0_binfmt::read_sha_halfs;
 use risc0_circuit_recursion::{control_id::RECURSION_CONTROL_IDS, CircuitImpl};
-use risc0_core::field::baby_bear::BabyBearElem;
-use risc0_zkp::{adapter::CircuitInfo, core::digest::Digest, verify::VerificationError};
+use risc0_core::field::baby_bear::{BabyBear, BabyBearElem};
+#[cfg(feature = "debug-verify")]
+use risc0_zkp::verify::Transcript;
+use risc0_zkp::{
+    adapter::CircuitInfo,
+    core::{digest::Digest, hash::HashSuite},
+    verify::VerificationError,
+};
 use serde::{Deserialize, Serialize};
 
 use super::CIRCUIT;
@@ -64,23 +70,7 @@ impl SuccinctReceipt {
         &self,
         ctx: &VerifierContext,
     ) -> Result<(), VerificationError> {
-        // Assemble the list of control IDs, and therefore circuit variants, we will
-        // accept.
-        let valid_ids = valid_control_ids();
-        let check_code = |_, control_id: &Digest| -> Result<(), VerificationError> {
-            valid_ids
-                .iter()
-                .find(|x| *x == control_id)
-                .map(|_| ())
-                .ok_or(VerificationError::ControlVerificationError)
-        };
-
-        // All receipts from the recursion circuit use Poseidon as the FRI hash
-        // function.
-        let suite = ctx
-            .suites
-            .get("poseidon")
-            .ok_or(VerificationError::InvalidHashSuite)?;
+        let (suite, check_code) = seal_verifier(ctx)?;
 
         // Verify the receipt itself is correct, and therefore the encoded globals are
         // reliable.
@@ -105,8 +95,52 @@ impl SuccinctReceipt {
         Ok(())
     }
 
+    /// Verify the seal as [SuccinctReceipt::verify_integrity_with_context]
+    /// does, recording each check made. The metadata is not checked.
+    #[cfg(feature = "debug-verify")]
+    pub(crate) fn verify_with_transcript(
+        &self,
+        ctx: &VerifierContext,
+    ) -> Result<(Result<(), VerificationError>, Transcript), VerificationError> {
+        let (suite, check_code) = seal_verifier(ctx)?;
+        Ok(risc0_zkp::verify::verify_with_transcript(
+            &CIRCUIT, suite, &self.seal, check_code,
+        ))
+    }
+
     /// Return the seal for this receipt, as a vector of bytes.
     pub fn get_seal_bytes(&self) -> Vec<u8> {
         self.seal.iter().flat_map(|x| x.to_le_bytes()).collect()
     }
 }
+
+// The hash suite and control ID check used to verify the seal of a
+// [SuccinctReceipt].
+fn seal_verifier(
+    ctx: &VerifierContext,
+) -> Result<
+    (
+        &HashSuite<BabyBear>,
+        impl Fn(u32, &Digest) -> Result<(), VerificationError>,
+    ),
+    VerificationError,
+> {
+    // Assemble the list of control IDs, and therefore circuit variants, we will
+    // accept.
+    let valid_ids = valid_control_ids();
+    let check_code = move |_, control_id: &Digest| -> Result<(), VerificationError> {
+        valid_ids
+            .iter()
+            .find(|x| *x == control_id)
+            .map(|_| ())
+            .ok_or(VerificationError::ControlVerificationError)
+    };
+
+    // All receipts from the recursion circuit use Poseidon as the FRI hash
+    // function.
+    let suite = ctx
+        .suites
+        .get("poseidon")
+        .ok_or(VerificationError::InvalidHashSuite)?;
+    Ok((suite, check_code))
+}
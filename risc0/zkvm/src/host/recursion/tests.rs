@@ -18,8 +18,8 @@ use test_log::test;
 
 use super::{identity_p254, join, lift, prove::poseidon254_hal_pair, Prover, ProverOpts};
 use crate::{
-    get_prover_server, ExecutorEnv, ExecutorImpl, InnerReceipt, Receipt, SegmentReceipt, Session,
-    VerifierContext,
+    get_prover_server_for, ExecutorEnv, ExecutorImpl, InnerReceipt, ProveOpts, Receipt,
+    SegmentReceipt, Session, VerifierContext,
 };
 
 fn generate_segments(hashfn: &str) -> (Session, Vec<SegmentReceipt>) {
@@ -35,11 +35,8 @@ fn generate_segments(hashfn: &str) -> (Session, Vec<SegmentReceipt>) {
     let session = exec.run().unwrap();
     let segments = session.resolve().unwrap();
     tracing::info!("Got {} segments", segments.len());
-    let opts = crate::ProverOpts {
-        hashfn: hashfn.to_string(),
-        prove_guest_errors: false,
-    };
-    let prover = get_prover_server(&opts).unwrap();
+    let opts = ProveOpts::default().with_hashfn(hashfn);
+    let prover = get_prover_server_for(&opts).unwrap();
     tracing::info!("Proving rv32im");
     let ctx = VerifierContext::default();
     let segment_receipts = segments
@@ -0,0 +1,178 @@
+// Copyright 2023 RISC Zero, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Faster execution of the loops in the guest's `memcpy` and `memset`.
+//!
+//! The executor normally pays for each instruction's paging checks, undo
+//! record and trace events. Inside the copy and fill loops of `memcpy` and
+//! `memset`, once the pages a loop touches are resident, none of these have
+//! any effect, so whole iterations are run here directly against the resident
+//! pages and committed at once. Each instruction is still executed, so the
+//! resulting memory, registers and cycle counts are exactly those of the
+//! normal executor.
+
+use std::{collections::HashMap, ops::Range};
+
+use anyhow::Result;
+use risc0_binfmt::Program;
+use risc0_zkvm_platform::{syscall::reg_abi::REG_MAX, WORD_SIZE};
+use rrs_lib::{instruction_executor::InstructionExecutor, HartState};
+
+use super::monitor::MemoryMonitor;
+use crate::host::server::opcode::OpCode;
+
+/// The guest functions whose loops are accelerated.
+const FUNCTIONS: &[&str] = &["memcpy", "memset"];
+
+const OPCODE_BRANCH: u32 = 0x63;
+const OPCODE_JALR: u32 = 0x67;
+const OPCODE_JAL: u32 = 0x6f;
+const OPCODE_SYSTEM: u32 = 0x73;
+
+/// A loop whose body is a straight run of instructions ending in a branch
+/// back to its first instruction, so that every iteration takes the same
+/// number of cycles.
+#[derive(Clone, Copy)]
+struct Loop {
+    insns: u32,
+    cycles: usize,
+}
+
+/// The result of [Accelerator::run].
+pub(crate) struct Run {
+    pub registers: [u32; REG_MAX],
+    pub pc: u32,
+    pub insns: u32,
+    pub cycles: usize,
+}
+
+pub(crate) struct Accelerator {
+    ranges: Vec<Range<u32>>,
+    // The loops seen so far, by the address of their first instruction, or
+    // `None` for those that cannot be accelerated.
+    loops: HashMap<u32, Option<Loop>>,
+    pub insns: u64,
+}
+
+impl Accelerator {
+    pub fn new(elf: &[u8]) -> Result<Self> {
+        let ranges = Program::functions(elf)?
+            .into_iter()
+            .filter(|sym| FUNCTIONS.contains(&sym.name.as_str()))
+            .map(|sym| sym.start..sym.start + sym.size)
+            .collect();
+        Ok(Self {
+            ranges,
+            loops: HashMap::new(),
+            insns: 0,
+        })
+    }
+
+    fn contains(&self, pc: u32) -> bool {
+        self.ranges.iter().any(|range| range.contains(&pc))
+    }
+
+    /// Notes that the instruction at `from` jumped to `to`, which starts a
+    /// loop if it is a branch backwards.
+    pub fn jumped(&mut self, monitor: &mut MemoryMonitor, from: u32, to: u32) {
+        if to >= from || self.loops.contains_key(&to) || !self.contains(from) || !self.contains(to)
+        {
+            return;
+        }
+        let Some(opcodes) = (to..=from)
+            .step_by(WORD_SIZE)
+            .map(|pc| monitor.load_insn_paged_in(pc))
+            .collect::<Option<Vec<_>>>()
+        else {
+            return;
+        };
+        let (last, body) = opcodes.split_last().unwrap();
+        let straight = *last & 0x7f == OPCODE_BRANCH
+            && body.iter().all(|insn| {
+                !matches!(
+                    insn & 0x7f,
+                    OPCODE_BRANCH | OPCODE_JALR | OPCODE_JAL | OPCODE_SYSTEM
+                )
+            });
+        let cycles = opcodes
+            .iter()
+            .zip((to..).step_by(WORD_SIZE))
+            .map(|(&insn, pc)| OpCode::decode(insn, pc).map(|opcode| opcode.cycles))
+            .sum::<Result<usize>>();
+        let lp = match cycles {
+            Ok(cycles) if straight => Some(Loop {
+                insns: opcodes.len() as u32,
+                cycles,
+            }),
+            _ => None,
+        };
+        self.loops.insert(to, lp);
+    }
+
+    /// Runs whole iterations of the loop starting at `pc`, if any, for at
+    /// most `max_cycles` cycles.
+    ///
+    /// Stops early at the first iteration that would page memory in or out,
+    /// fault, or leave the loop, and undoes any part of it already run; the
+    /// normal executor then picks up from there. The memory writes of the
+    /// iterations run are not recorded for [MemoryMonitor::undo], so the
+    /// caller must commit the returned [Run].
+    pub fn run(&mut self, monitor: &mut MemoryMonitor, pc: u32, max_cycles: usize) -> Option<Run> {
+        let lp = (*self.loops.get(&pc)?)?;
+        // Writing a register marks the system page dirty.
+        if !monitor.registers_dirty() {
+            return None;
+        }
+
+        let mut hart = HartState {
+            registers: monitor.load_registers(),
+            pc,
+            last_register_write: None,
+        };
+        monitor.set_paged_in_only(true);
+        let mut iterations = 0;
+        while hart.pc == pc && (iterations + 1) * lp.cycles <= max_cycles {
+            let registers = hart.registers;
+            monitor.commit_paged_in();
+            let completed = (0..lp.insns).all(|_| {
+                InstructionExecutor {
+                    mem: &mut *monitor,
+                    hart_state: &mut hart,
+                }
+                .step()
+                .is_ok()
+            });
+            if !completed {
+                monitor.undo_paged_in();
+                hart.registers = registers;
+                hart.pc = pc;
+                break;
+            }
+            iterations += 1;
+        }
+        monitor.set_paged_in_only(false);
+        if iterations == 0 {
+            return None;
+        }
+
+        let insns = iterations as u32 * lp.insns;
+        self.insns += insns as u64;
+        Some(Run {
+            registers: hart.registers,
+            pc: hart.pc,
+            insns,
+            cycles: iterations * lp.cycles,
+        })
+    }
+}
@@ -0,0 +1,81 @@
+// Copyright 2023 RISC Zero, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Snapshots of the executor taken in the middle of a segment.
+
+use std::collections::BTreeMap;
+
+use risc0_zkvm_platform::syscall::reg_abi::REG_MAX;
+use serde::{Deserialize, Serialize};
+
+use super::executor::SyscallRecord;
+use crate::{
+    host::{api::FailedCheck, server::session::PageFaults},
+    sha::Digest,
+    Assumption,
+};
+
+/// The state of an [ExecutorImpl](crate::ExecutorImpl) part way through a
+/// segment, from which execution can be continued with
+/// [ExecutorImpl::resume_from_intrasegment_checkpoint](crate::ExecutorImpl::resume_from_intrasegment_checkpoint).
+///
+/// Checkpoints are taken with
+/// [ExecutorImpl::checkpoint_every](crate::ExecutorImpl::checkpoint_every).
+/// A checkpoint holds the registers and the pages written since the start of
+/// its segment, but not the rest of the segment's pre-image, which must be
+/// kept separately, e.g. as the post-image of the previous segment. It can be
+/// serialized, e.g. with bincode, to survive the host process.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct ExecutorCheckpoint {
+    /// The image ID of the pre-image of the segment the checkpoint was taken
+    /// in.
+    pub pre_image_id: Digest,
+
+    /// The index of that segment within the session.
+    pub segment: u32,
+
+    /// The cycle of the session at which the checkpoint was taken.
+    pub session_cycle: u64,
+
+    pub(crate) segment_limit: usize,
+    pub(crate) pc: u32,
+    pub(crate) insn_counter: u32,
+    pub(crate) body_cycles: usize,
+    #[serde(default)]
+    pub(crate) cycle_offset: u64,
+    pub(crate) segment_cycle: usize,
+    pub(crate) syscalls: Vec<SyscallRecord>,
+    pub(crate) output_digest: Option<Digest>,
+    pub(crate) memory: MemoryState,
+
+    // What the session has accumulated so far, which a resumed run carries on
+    // from.
+    pub(crate) journal: Vec<u8>,
+    pub(crate) journal_preimages: Vec<Vec<u8>>,
+    pub(crate) assumptions: Vec<Assumption>,
+    pub(crate) failed_checks: Vec<FailedCheck>,
+}
+
+/// The state of the memory monitor between two instructions.
+#[derive(Clone, Serialize, Deserialize)]
+pub(crate) struct MemoryState {
+    pub(crate) faults: PageFaults,
+    pub(crate) page_read_cycles: usize,
+    pub(crate) page_write_cycles: usize,
+    pub(crate) registers: [u32; REG_MAX],
+    pub(crate) heap_end: u32,
+    // The contents of the pages written since the start of the segment; any
+    // other page is unchanged from the pre-image.
+    pub(crate) dirty_pages: BTreeMap<u32, Vec<u8>>,
+}
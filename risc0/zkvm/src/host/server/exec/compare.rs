@@ -0,0 +1,168 @@
+// Copyright 2023 RISC Zero, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Compares what two versions of a guest do with the same inputs.
+
+use anyhow::{Context, Result};
+
+use super::{determinism::Run, syscall::AuditEntry};
+use crate::{ExecutorEnv, ExitCode};
+
+/// Options for [compare_guests].
+#[derive(Clone, Debug, Default)]
+pub struct CompareOpts {
+    /// The number of user cycles by which the two guests may differ on an
+    /// input without the difference being reported; see
+    /// [CompareOpts::with_cycle_tolerance].
+    pub cycle_tolerance: u64,
+}
+
+impl CompareOpts {
+    /// Ignore differences in user cycles of up to `cycles`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use risc0_zkvm::CompareOpts;
+    ///
+    /// let opts = CompareOpts::default().with_cycle_tolerance(1000);
+    /// assert_eq!(opts.cycle_tolerance, 1000);
+    /// ```
+    pub fn with_cycle_tolerance(mut self, cycles: u64) -> Self {
+        self.cycle_tolerance = cycles;
+        self
+    }
+}
+
+/// How two guests compared by [compare_guests] differed on one input.
+#[derive(Clone, Debug, PartialEq)]
+pub struct GuestDiff {
+    /// The index of the input in the order it was given.
+    pub input: usize,
+
+    /// The journals of the first and second guest, if they differ.
+    pub journals: Option<(Vec<u8>, Vec<u8>)>,
+
+    /// The user cycles of the first and second guest, over all segments.
+    pub cycles: (u64, u64),
+
+    /// The exit codes of the first and second guest, if they differ.
+    pub exit_codes: Option<(ExitCode, ExitCode)>,
+
+    /// The first difference between the syscall transcripts of the two
+    /// guests, if any.
+    pub syscall: Option<String>,
+}
+
+impl GuestDiff {
+    /// The number of user cycles the second guest took more than the first.
+    pub fn cycle_delta(&self) -> i64 {
+        self.cycles.1 as i64 - self.cycles.0 as i64
+    }
+
+    /// The offset of the first byte at which the journals differ, or the
+    /// length of the shorter journal if one is a prefix of the other.
+    pub fn journal_offset(&self) -> Option<usize> {
+        let (a, b) = self.journals.as_ref()?;
+        Some(
+            a.iter()
+                .zip(b)
+                .position(|(a, b)| a != b)
+                .unwrap_or(a.len().min(b.len())),
+        )
+    }
+}
+
+/// Execute `elf_a` and `elf_b`, without proving, on each of `inputs`, and
+/// report the inputs on which they did not do the same.
+///
+/// The guests are compared by their journals, their exit codes, the number of
+/// user cycles they took, and a transcript of the syscalls answered by the
+/// host with the data exchanged in each. Unlike
+/// [Prover::run_determinism_check](crate::Prover::run_determinism_check), the
+/// cycle at which each syscall is made is not compared, since it changes with
+/// any change to the code before it; differences in cycles are reported only
+/// beyond [CompareOpts::cycle_tolerance].
+///
+/// Each run is given the [ExecutorEnv] returned by a call to `make_env` with
+/// the input, so that no reader or syscall handler is shared between runs;
+/// e.g. `make_env` may write the input with
+/// [ExecutorEnvBuilder::write_bytes](crate::ExecutorEnvBuilder::write_bytes).
+/// The transcript is taken with
+/// [ExecutorEnvBuilder::syscall_audit](crate::ExecutorEnvBuilder::syscall_audit),
+/// replacing any audit writer set on the environments.
+pub fn compare_guests<'a>(
+    elf_a: &[u8],
+    elf_b: &[u8],
+    inputs: impl IntoIterator<Item = Vec<u8>>,
+    make_env: &mut dyn FnMut(&[u8]) -> Result<ExecutorEnv<'a>>,
+    opts: &CompareOpts,
+) -> Result<Vec<GuestDiff>> {
+    let mut diffs = Vec::new();
+    for (idx, input) in inputs.into_iter().enumerate() {
+        let a = Run::new(make_env(&input)?, elf_a)
+            .with_context(|| format!("The first guest failed on input {idx}"))?;
+        let b = Run::new(make_env(&input)?, elf_b)
+            .with_context(|| format!("The second guest failed on input {idx}"))?;
+        let diff = GuestDiff {
+            input: idx,
+            journals: (a.journal != b.journal).then(|| (a.journal.clone(), b.journal.clone())),
+            cycles: (a.cycles(), b.cycles()),
+            exit_codes: (a.exit_code != b.exit_code).then_some((a.exit_code, b.exit_code)),
+            syscall: diverge_syscalls(&a.syscalls, &b.syscalls),
+        };
+        if diff.journals.is_some()
+            || diff.exit_codes.is_some()
+            || diff.syscall.is_some()
+            || diff.cycle_delta().unsigned_abs() > opts.cycle_tolerance
+        {
+            diffs.push(diff);
+        }
+    }
+    Ok(diffs)
+}
+
+// The first syscall that differs between the transcripts, ignoring cycles.
+fn diverge_syscalls(a: &[AuditEntry], b: &[AuditEntry]) -> Option<String> {
+    let same = |a: &AuditEntry, b: &AuditEntry| {
+        a.syscall == b.syscall && a.from_guest == b.from_guest && a.to_guest == b.to_guest
+    };
+    let idx = (0..a.len().max(b.len())).find(|&idx| match (a.get(idx), b.get(idx)) {
+        (Some(a), Some(b)) => !same(a, b),
+        _ => true,
+    })?;
+    Some(match (a.get(idx), b.get(idx)) {
+        (Some(a), Some(b)) if a.syscall != b.syscall => format!(
+            "syscall #{idx} was {} in the second guest instead of {}",
+            b.syscall, a.syscall
+        ),
+        (Some(a), Some(b)) if a.from_guest != b.from_guest => format!(
+            "the guests passed different data to syscall #{idx} ({})",
+            a.syscall
+        ),
+        (Some(a), Some(_)) => format!(
+            "the host returned different data from syscall #{idx} ({})",
+            a.syscall
+        ),
+        (Some(a), None) => format!(
+            "syscall #{idx} ({}) was made only by the first guest",
+            a.syscall
+        ),
+        (None, Some(b)) => format!(
+            "syscall #{idx} ({}) was made only by the second guest",
+            b.syscall
+        ),
+        (None, None) => unreachable!(),
+    })
+}
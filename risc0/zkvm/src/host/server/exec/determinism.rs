@@ -0,0 +1,283 @@
+// Copyright 2023 RISC Zero, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Checks that a guest does the same thing each time it is run with the same
+//! input.
+
+use std::{cell::RefCell, fmt, rc::Rc};
+
+use anyhow::{ensure, Context, Result};
+use serde::Serialize;
+
+use super::{executor::ExecutorImpl, syscall::AuditEntry};
+use crate::{
+    host::api::server::EmptySegmentRef,
+    sha::{Digest, Impl, Sha256 as _},
+    ExecutorEnv, ExitCode,
+};
+
+/// The outcome of
+/// [Prover::run_determinism_check](crate::Prover::run_determinism_check).
+#[derive(Clone, Debug)]
+pub struct DeterminismReport {
+    /// A digest of the segment boundaries, journal and syscalls of each run,
+    /// in order. Runs are made until one diverges from the first.
+    pub digests: Vec<Digest>,
+
+    /// The first difference found between the first run and a later one.
+    pub divergence: Option<Divergence>,
+}
+
+impl DeterminismReport {
+    /// Whether every run did the same as the first.
+    pub fn is_deterministic(&self) -> bool {
+        self.divergence.is_none()
+    }
+}
+
+/// Where a run of a guest first differed from the first run.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Divergence {
+    /// The index of the run that differed; the first run is 0.
+    pub run: usize,
+
+    /// The number of user cycles the session had executed when the runs
+    /// differed, unless only their journals or exit codes did.
+    pub cycle: Option<u64>,
+
+    /// The name of the syscall whose transcript differed, if the runs first
+    /// differed in a syscall.
+    pub syscall: Option<String>,
+
+    /// What differed.
+    pub reason: String,
+}
+
+impl fmt::Display for Divergence {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Run {} diverged from run 0", self.run)?;
+        if let Some(cycle) = self.cycle {
+            write!(f, " at cycle {cycle}")?;
+        }
+        if let Some(syscall) = &self.syscall {
+            write!(f, " in syscall {syscall}")?;
+        }
+        write!(f, ": {}", self.reason)
+    }
+}
+
+// What a run did, as far as the check compares runs.
+#[derive(Serialize)]
+pub(super) struct Run {
+    pub(super) syscalls: Vec<AuditEntry>,
+    segments: Vec<Boundary>,
+    pub(super) journal: Vec<u8>,
+    pub(super) exit_code: ExitCode,
+}
+
+// Where a segment started and ended.
+#[derive(Serialize, PartialEq)]
+struct Boundary {
+    start_cycle: u64,
+    end_cycle: u64,
+    cycles: u32,
+    pre_image_id: Digest,
+    post_image_id: Digest,
+    exit_code: ExitCode,
+}
+
+/// Run `elf` `n` times, with an environment from `make_env` each time, and
+/// compare each run with the first.
+pub(crate) fn check<'a>(
+    n: usize,
+    make_env: &mut dyn FnMut() -> Result<ExecutorEnv<'a>>,
+    elf: &[u8],
+) -> Result<DeterminismReport> {
+    ensure!(n >= 2, "A determinism check needs at least 2 runs, not {n}");
+    let first = Run::new(make_env()?, elf).context("Run 0 failed")?;
+    let mut report = DeterminismReport {
+        digests: vec![first.digest()?],
+        divergence: None,
+    };
+    for idx in 1..n {
+        let run = Run::new(make_env()?, elf).with_context(|| format!("Run {idx} failed"))?;
+        report.digests.push(run.digest()?);
+        report.divergence = first.diverge(&run, idx);
+        if report.divergence.is_some() {
+            break;
+        }
+    }
+    Ok(report)
+}
+
+impl Run {
+    pub(super) fn new(mut env: ExecutorEnv<'_>, elf: &[u8]) -> Result<Self> {
+        let audit = Rc::new(RefCell::new(Vec::new()));
+        env.syscall_audit = Some(audit.clone());
+
+        // Segments routinely end before their limit, so each one starts where
+        // the one before it ended, on the same clock as the audit log.
+        let mut segments: Vec<Boundary> = Vec::new();
+        let session = ExecutorImpl::from_elf(env, elf)?.run_with_callback(|segment| {
+            let start_cycle = segments.last().map_or(0, |last| last.end_cycle);
+            segments.push(Boundary {
+                start_cycle,
+                end_cycle: start_cycle + segment.cycles as u64,
+                cycles: segment.cycles,
+                pre_image_id: segment.pre_image.compute_id(),
+                post_image_id: segment.post_image_id,
+                exit_code: segment.exit_code,
+            });
+            Ok(Box::new(EmptySegmentRef))
+        })?;
+
+        let audit = audit.borrow();
+        let syscalls = serde_json::Deserializer::from_slice(&audit)
+            .into_iter()
+            .collect::<Result<_, _>>()?;
+        Ok(Self {
+            syscalls,
+            segments,
+            journal: session
+                .journal
+                .map(|journal| journal.bytes)
+                .unwrap_or_default(),
+            exit_code: session.exit_code,
+        })
+    }
+
+    // The number of user cycles the run took, over all segments.
+    pub(super) fn cycles(&self) -> u64 {
+        self.segments
+            .iter()
+            .map(|segment| segment.cycles as u64)
+            .sum()
+    }
+
+    fn digest(&self) -> Result<Digest> {
+        Ok(*Impl::hash_bytes(&bincode::serialize(self)?))
+    }
+
+    // The first difference between this run and `other`, the run numbered
+    // `run`. A syscall that returned different data also changes the memory
+    // image at the end of its segment, so a syscall is reported in preference
+    // to the segment it is part of.
+    fn diverge(&self, other: &Self, run: usize) -> Option<Divergence> {
+        let divergence = |cycle, syscall, reason| {
+            Some(Divergence {
+                run,
+                cycle,
+                syscall,
+                reason,
+            })
+        };
+
+        let syscall = diverge_syscalls(&self.syscalls, &other.syscalls);
+        let segment = diverge_segments(&self.segments, &other.segments);
+        match (syscall, segment) {
+            (Some((cycle, name, reason)), Some((_, end, _))) if cycle < end => {
+                return divergence(Some(cycle), Some(name), reason)
+            }
+            (_, Some((start, _, reason))) => return divergence(Some(start), None, reason),
+            (Some((cycle, name, reason)), None) => {
+                return divergence(Some(cycle), Some(name), reason)
+            }
+            (None, None) => {}
+        }
+
+        if self.journal != other.journal {
+            return divergence(None, None, "the journals differ".to_string());
+        }
+        if self.exit_code != other.exit_code {
+            return divergence(
+                None,
+                None,
+                format!(
+                    "exited with {:?} instead of {:?}",
+                    other.exit_code, self.exit_code
+                ),
+            );
+        }
+        None
+    }
+}
+
+// The cycle, name and description of the first syscall that differs.
+fn diverge_syscalls(first: &[AuditEntry], other: &[AuditEntry]) -> Option<(u64, String, String)> {
+    let idx = (0..first.len().max(other.len())).find(|&idx| first.get(idx) != other.get(idx))?;
+    let (expected, actual) = match (first.get(idx), other.get(idx)) {
+        (Some(expected), Some(actual)) => (expected, actual),
+        (Some(entry), None) => {
+            let reason = format!("syscall #{idx} was not made");
+            return Some((entry.cycle, entry.syscall.clone(), reason));
+        }
+        (None, Some(entry)) => {
+            let reason = format!("syscall #{idx} was made, but not in run 0");
+            return Some((entry.cycle, entry.syscall.clone(), reason));
+        }
+        (None, None) => unreachable!(),
+    };
+
+    let reason = if expected.syscall != actual.syscall {
+        format!(
+            "syscall #{idx} was {} instead of {}",
+            actual.syscall, expected.syscall
+        )
+    } else if expected.cycle != actual.cycle {
+        format!(
+            "syscall #{idx} was made at cycle {} instead of {}",
+            actual.cycle, expected.cycle
+        )
+    } else if expected.from_guest != actual.from_guest {
+        format!("the guest passed different data to syscall #{idx}")
+    } else {
+        format!("the host returned different data from syscall #{idx}")
+    };
+    let cycle = expected.cycle.min(actual.cycle);
+    Some((cycle, expected.syscall.clone(), reason))
+}
+
+// The start and end cycles and description of the first segment that differs.
+fn diverge_segments(first: &[Boundary], other: &[Boundary]) -> Option<(u64, u64, String)> {
+    let idx = (0..first.len().max(other.len())).find(|&idx| first.get(idx) != other.get(idx))?;
+    let (expected, actual) = match (first.get(idx), other.get(idx)) {
+        (Some(expected), Some(actual)) => (expected, actual),
+        (Some(segment), None) | (None, Some(segment)) => {
+            let reason = format!(
+                "the session has {} segments instead of {}",
+                other.len(),
+                first.len()
+            );
+            return Some((segment.start_cycle, segment.end_cycle, reason));
+        }
+        (None, None) => unreachable!(),
+    };
+
+    let reason = if expected.pre_image_id != actual.pre_image_id {
+        format!("segment {idx} started from a different memory image")
+    } else if expected.cycles != actual.cycles {
+        format!(
+            "segment {idx} ran for {} cycles instead of {}",
+            actual.cycles, expected.cycles
+        )
+    } else if expected.exit_code != actual.exit_code {
+        format!(
+            "segment {idx} ended with {:?} instead of {:?}",
+            actual.exit_code, expected.exit_code
+        )
+    } else {
+        format!("segment {idx} ended with a different memory image")
+    };
+    Some((expected.start_cycle, expected.end_cycle, reason))
+}
@@ -14,43 +14,54 @@
 
 //! This module implements the Executor.
 
-use std::{cell::RefCell, fmt::Debug, io::Write, mem, rc::Rc};
+use std::{cell::RefCell, collections::VecDeque, fmt::Debug, io::Write, mem, rc::Rc};
 
 use addr2line::{
     fallible_iterator::FallibleIterator,
     gimli::{EndianRcSlice, RunTimeEndian},
     Frame, LookupResult, ObjectContext,
 };
-use anyhow::{anyhow, bail, Context, Result};
+use anyhow::{anyhow, bail, ensure, Context, Result};
 use crypto_bigint::{CheckedMul, Encoding, NonZero, U256, U512};
-use risc0_binfmt::{MemoryImage, Program};
+use risc0_binfmt::MemoryImage;
 use risc0_zkp::{
     core::{
         digest::{DIGEST_BYTES, DIGEST_WORDS},
         hash::sha::{BLOCK_BYTES, BLOCK_WORDS},
         log2_ceil,
     },
-    MAX_CYCLES_PO2, MIN_CYCLES_PO2, ZK_CYCLES,
+    ZK_CYCLES,
 };
 use risc0_zkvm_platform::{
     fileno,
     memory::{is_guest_memory, GUEST_MAX_MEM},
     syscall::{
-        bigint, ecall, halt,
-        reg_abi::{REG_A0, REG_A1, REG_A2, REG_A3, REG_A4, REG_T0},
+        bigint, ecall, halt, nr,
+        reg_abi::{REG_A0, REG_A1, REG_A2, REG_A3, REG_A4, REG_GP, REG_T0},
     },
-    PAGE_SIZE, WORD_SIZE,
+    WORD_SIZE,
 };
 use rrs_lib::{instruction_executor::InstructionExecutor, HartState};
 use serde::{Deserialize, Serialize};
 use sha2::digest::generic_array::GenericArray;
 use tempfile::tempdir;
 
-use super::{monitor::MemoryMonitor, syscall::SyscallTable};
+use super::{
+    accel::Accelerator,
+    checkpoint::ExecutorCheckpoint,
+    monitor::MemoryMonitor,
+    reference::{reference_step, ReferenceStep},
+    syscall::{AuditContext, HeapExhausted, SysDerivedRandom, SyscallTable},
+};
 use crate::{
-    align_up,
+    align_up, hashed_journal,
     host::{
-        client::exec::TraceEvent,
+        api::FailedCheck,
+        client::{
+            env::{check_segment_limit_po2, CheckPolicy},
+            exec::TraceEvent,
+            trace_file::TraceFileWriter,
+        },
         server::opcode::{MajorType, OpCode},
     },
     sha::Digest,
@@ -65,7 +76,14 @@ const BIGINT_CYCLES: usize = 9;
 
 /// The default segment limit specified in powers of 2 cycles. Choose this value
 /// to try and fit with 8GB of RAM.
-const DEFAULT_SEGMENT_LIMIT_PO2: u32 = 20; // 1M cycles
+pub(crate) const DEFAULT_SEGMENT_LIMIT_PO2: u32 = 20; // 1M cycles
+
+/// The number of cycles in every segment that are not available to the guest:
+/// the loader's initialization and finalization, the final SHA-256 compression
+/// and the cycles reserved for zero-knowledge.
+pub(crate) fn segment_overhead_cycles(loader: &Loader) -> usize {
+    loader.init_cycles() + loader.fini_cycles() + SHA_CYCLES + ZK_CYCLES
+}
 
 // Capture the journal output in a buffer that we can access afterwards.
 #[derive(Clone, Default)]
@@ -83,6 +101,34 @@ impl Write for Journal {
     }
 }
 
+// Forward journal writes to the callbacks and tees registered on the
+// [ExecutorEnv].
+struct JournalStream<'a> {
+    journal: Journal,
+    callbacks: Vec<Rc<RefCell<dyn FnMut(&[u8]) + 'a>>>,
+    tees: Vec<Rc<RefCell<dyn Write + 'a>>>,
+}
+
+impl<'a> Write for JournalStream<'a> {
+    fn write(&mut self, bytes: &[u8]) -> std::io::Result<usize> {
+        self.journal.write_all(bytes)?;
+        for callback in self.callbacks.iter() {
+            callback.borrow_mut()(bytes);
+        }
+        for tee in self.tees.iter() {
+            tee.borrow_mut().write_all(bytes)?;
+        }
+        Ok(bytes.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        for tee in self.tees.iter() {
+            tee.borrow_mut().flush()?;
+        }
+        self.journal.flush()
+    }
+}
+
 #[derive(Clone)]
 struct OpCodeResult {
     pc: u32,
@@ -106,6 +152,26 @@ pub struct SyscallRecord {
     pub regs: (u32, u32),
 }
 
+// The state needed to run an executed segment again, e.g. to split it into
+// smaller segments.
+struct Replay {
+    // The recorded syscalls not yet replayed.
+    syscalls: VecDeque<SyscallRecord>,
+
+    // The instruction at which the original segment was split, if it was.
+    split_insn: Option<u32>,
+
+    // The number of instructions executed by the segments produced so far.
+    insn_offset: u32,
+}
+
+// Where and how often to take an [ExecutorCheckpoint].
+struct Checkpoints<'a> {
+    interval: usize,
+    next: usize,
+    callback: Box<dyn FnMut(ExecutorCheckpoint) -> Result<()> + 'a>,
+}
+
 /// The Executor provides an implementation for the execution phase.
 ///
 /// The proving phase uses an execution trace generated by the Executor.
@@ -117,9 +183,13 @@ pub struct ExecutorImpl<'a> {
     pc: u32,
     init_cycles: usize,
     body_cycles: usize,
+    // The user cycles of the segments of the session before the current one.
+    cycle_offset: u64,
     segment_limit: usize,
     segment_cycle: usize,
     segments: Vec<Box<dyn SegmentRef>>,
+    // The number of segments produced by the current run.
+    segment_count: usize,
     insn_counter: u32,
     split_insn: Option<u32>,
     const_cycles: usize,
@@ -128,8 +198,34 @@ pub struct ExecutorImpl<'a> {
     exit_code: Option<ExitCode>,
     obj_ctx: Option<ObjectContext>,
     output_digest: Option<Digest>,
+    pending_yield: Option<u32>,
+    yield_journal: Option<Journal>,
+    replay: Option<Replay>,
+    accel: Option<Accelerator>,
+    failed_checks: Vec<FailedCheck>,
+    checkpoints: Option<Checkpoints<'a>>,
+    resume: Option<ExecutorCheckpoint>,
+    // The number of segments that came before the first one of this executor,
+    // when it was resumed from a checkpoint.
+    segment_offset: usize,
+    // The run being stepped through with next_segment, if any.
+    run: Option<Run>,
 }
 
+// A run of the executor that has been started, one segment at a time.
+struct Run {
+    start_time: std::time::Instant,
+    #[cfg(feature = "metrics")]
+    _timer: crate::host::server::metrics::PhaseTimer,
+    journal: Journal,
+    hints: Option<OutputSizeHints>,
+    // How the run ended, once its last segment has been produced.
+    end: Option<RunEnd>,
+}
+
+// The exit code, post-image and yield tag of a run that has ended.
+type RunEnd = (ExitCode, MemoryImage, Option<u32>);
+
 impl<'a> ExecutorImpl<'a> {
     /// Construct a new [ExecutorImpl] from a [MemoryImage] and entry point.
     ///
@@ -138,28 +234,45 @@ impl<'a> ExecutorImpl<'a> {
     /// work will be done in each segment. This is the execution phase:
     /// the guest program is executed to determine how its proof should be
     /// divided into subparts.
+    ///
+    /// Unless disabled with
+    /// [ExecutorEnvBuilder::image_integrity_check](crate::ExecutorEnvBuilder::image_integrity_check),
+    /// the image is checked with [MemoryImage::verify_integrity] first.
     pub fn new(env: ExecutorEnv<'a>, image: MemoryImage) -> Result<Self> {
+        if !env.skip_image_integrity_check {
+            image.verify_integrity()?;
+        }
         Self::with_obj_ctx(env, image, None)
     }
 
     fn with_obj_ctx(
-        env: ExecutorEnv<'a>,
+        mut env: ExecutorEnv<'a>,
         image: MemoryImage,
         obj_ctx: Option<ObjectContext>,
     ) -> Result<Self> {
         // Enforce segment_limit_po2 bounds
-        let segment_limit_po2 = env.segment_limit_po2.unwrap_or(DEFAULT_SEGMENT_LIMIT_PO2) as usize;
-        if segment_limit_po2 < MIN_CYCLES_PO2 || segment_limit_po2 > MAX_CYCLES_PO2 {
-            bail!("Invalid segment_limit_po2: {}", segment_limit_po2);
+        let segment_limit_po2 = env.segment_limit_po2.unwrap_or(DEFAULT_SEGMENT_LIMIT_PO2);
+        check_segment_limit_po2(segment_limit_po2)?;
+        if let Some(path) = env.trace_file.take() {
+            let writer = TraceFileWriter::create(&path, segment_limit_po2)?;
+            env.trace.push(Rc::new(RefCell::new(writer)));
         }
 
         let pc = image.pc;
-        let monitor = MemoryMonitor::new(image.clone(), !env.trace.is_empty());
+        let mut monitor = MemoryMonitor::new(image.clone(), !env.trace.is_empty());
+        let (heap_base, heap_limit) = env.layout.heap_bounds();
+        monitor.set_heap_bounds(heap_base, heap_limit);
         let loader = Loader::new();
         let init_cycles = loader.init_cycles();
-        let fini_cycles = loader.fini_cycles();
-        let const_cycles = init_cycles + fini_cycles + SHA_CYCLES + ZK_CYCLES;
-        let syscall_table = SyscallTable::new(&env);
+        let const_cycles = segment_overhead_cycles(&loader);
+        let mut syscall_table = SyscallTable::new(&env);
+        if env.derived_randomness {
+            let input_digest = env.input_digest().ok_or_else(|| {
+                anyhow!("derived randomness needs an input that can be digested, but the guest can read from a custom reader or a slice I/O handler")
+            })?;
+            let random = SysDerivedRandom::new(image.compute_id(), input_digest);
+            syscall_table.with_syscall(nr::SYS_RANDOM, random);
+        }
 
         Ok(Self {
             env,
@@ -169,9 +282,11 @@ impl<'a> ExecutorImpl<'a> {
             pc,
             init_cycles,
             body_cycles: 0,
+            cycle_offset: 0,
             segment_limit: 1 << segment_limit_po2,
             segment_cycle: init_cycles,
             segments: Vec::new(),
+            segment_count: 0,
             insn_counter: 0,
             split_insn: None,
             const_cycles,
@@ -180,9 +295,170 @@ impl<'a> ExecutorImpl<'a> {
             exit_code: None,
             obj_ctx,
             output_digest: None,
+            pending_yield: None,
+            yield_journal: None,
+            replay: None,
+            accel: None,
+            failed_checks: Vec::new(),
+            checkpoints: None,
+            resume: None,
+            segment_offset: 0,
+            run: None,
+        })
+    }
+
+    /// Construct an [ExecutorImpl] that continues an execution from an
+    /// [ExecutorCheckpoint] taken part way through one of its segments.
+    ///
+    /// `pre_image` is the pre-image of the segment the checkpoint was taken
+    /// in, e.g. [Segment::pre_image]. Running the executor then produces that
+    /// segment and all the ones after it, exactly as the original execution
+    /// did, with the same indices.
+    ///
+    /// The `env` must be built the same way as the original one, and its I/O
+    /// must pick up where the original was when the checkpoint was taken: the
+    /// executor only replays what the guest does after that point, so e.g. an
+    /// stdin reader must be positioned past what the guest had already read.
+    pub fn resume_from_intrasegment_checkpoint(
+        env: ExecutorEnv<'a>,
+        pre_image: MemoryImage,
+        checkpoint: ExecutorCheckpoint,
+    ) -> Result<Self> {
+        let image_id = pre_image.compute_id();
+        if image_id != checkpoint.pre_image_id {
+            bail!(
+                "checkpoint was taken in a segment with pre-image {}, not {image_id}",
+                checkpoint.pre_image_id
+            );
+        }
+        let mut exec = Self::new(env, pre_image)?;
+        if exec.segment_limit != checkpoint.segment_limit {
+            bail!(
+                "checkpoint was taken with a segment limit of {} cycles, not {}",
+                checkpoint.segment_limit,
+                exec.segment_limit
+            );
+        }
+        exec.resume = Some(checkpoint);
+        Ok(exec)
+    }
+
+    /// Take an [ExecutorCheckpoint] about every `interval` cycles of the
+    /// session and pass it to `callback`, e.g. to keep the last few in a ring
+    /// buffer or to write them out.
+    ///
+    /// Checkpoints are taken between instructions, at the first one on or
+    /// after each multiple of `interval`, and make it possible to resume a
+    /// long segment part way through with
+    /// [ExecutorImpl::resume_from_intrasegment_checkpoint]. Each one copies
+    /// the pages written so far in its segment, so an `interval` much smaller
+    /// than the segment limit slows execution down.
+    pub fn checkpoint_every<F>(&mut self, interval: usize, callback: F) -> &mut Self
+    where
+        F: FnMut(ExecutorCheckpoint) -> Result<()> + 'a,
+    {
+        let interval = interval.max(1);
+        self.checkpoints = Some(Checkpoints {
+            interval,
+            next: interval,
+            callback: Box::new(callback),
+        });
+        self
+    }
+
+    // Pick up the state of a checkpoint given to
+    // [ExecutorImpl::resume_from_intrasegment_checkpoint].
+    fn apply_resume(&mut self, checkpoint: ExecutorCheckpoint) -> Result<Journal> {
+        self.monitor.restore(checkpoint.memory)?;
+        self.segment_offset = checkpoint.segment as usize;
+        self.pc = checkpoint.pc;
+        self.insn_counter = checkpoint.insn_counter;
+        self.body_cycles = checkpoint.body_cycles;
+        self.cycle_offset = checkpoint.cycle_offset;
+        self.segment_cycle = checkpoint.segment_cycle;
+        self.syscalls = checkpoint.syscalls;
+        self.output_digest = checkpoint.output_digest;
+        self.failed_checks = checkpoint.failed_checks;
+        self.env.assumptions.borrow_mut().accessed = checkpoint.assumptions;
+        *self.env.journal_preimages.borrow_mut() = checkpoint.journal_preimages;
+        self.schedule_checkpoint();
+        self.monitor.commit(self.session_cycle());
+        Ok(Journal {
+            buf: Rc::new(RefCell::new(checkpoint.journal)),
         })
     }
 
+    // Take the next checkpoint at the first multiple of the interval after
+    // the current session cycle.
+    fn schedule_checkpoint(&mut self) {
+        let session_cycle = self.session_cycle();
+        if let Some(checkpoints) = &mut self.checkpoints {
+            checkpoints.next = (session_cycle / checkpoints.interval + 1) * checkpoints.interval;
+        }
+    }
+
+    fn take_checkpoint(&mut self, journal: &Journal) -> Result<()> {
+        let Some(next) = self
+            .checkpoints
+            .as_ref()
+            .map(|checkpoints| checkpoints.next)
+        else {
+            return Ok(());
+        };
+        // A replay has no host to resume against, and a pending yield ends the
+        // segment before the next instruction.
+        if self.session_cycle() < next || self.replay.is_some() || self.pending_yield.is_some() {
+            return Ok(());
+        }
+        let pre_image = self
+            .pre_image
+            .as_ref()
+            .ok_or_else(|| anyhow!("attempted to run the executor with no pre_image"))?;
+        let checkpoint = ExecutorCheckpoint {
+            pre_image_id: pre_image.compute_id(),
+            segment: self.segment_index()?,
+            session_cycle: self.session_cycle() as u64,
+            segment_limit: self.segment_limit,
+            pc: self.pc,
+            insn_counter: self.insn_counter,
+            body_cycles: self.body_cycles,
+            cycle_offset: self.cycle_offset,
+            segment_cycle: self.segment_cycle,
+            syscalls: self.syscalls.clone(),
+            output_digest: self.output_digest,
+            memory: self.monitor.snapshot(),
+            journal: journal.buf.borrow().clone(),
+            journal_preimages: self.env.journal_preimages.borrow().clone(),
+            assumptions: self.env.assumptions.borrow().accessed.clone(),
+            failed_checks: self.failed_checks.clone(),
+        };
+        self.schedule_checkpoint();
+        let checkpoints = self.checkpoints.as_mut().unwrap();
+        (checkpoints.callback)(checkpoint)
+    }
+
+    fn segment_index(&self) -> Result<u32> {
+        (self.segment_offset + self.segment_count)
+            .try_into()
+            .context("Too many segments to fit in u32")
+    }
+
+    /// Construct an [ExecutorImpl] that runs `segment` again from its
+    /// pre-image, splitting it into segments of at most `2^po2` cycles.
+    ///
+    /// The host is not consulted: syscalls are answered from the records kept
+    /// in `segment`, and execution stops where `segment` ended.
+    pub(crate) fn replay(segment: &Segment, po2: u32) -> Result<Self> {
+        let env = ExecutorEnv::builder().segment_limit_po2(po2).build()?;
+        let mut exec = Self::new(env, *segment.pre_image.clone())?;
+        exec.replay = Some(Replay {
+            syscalls: segment.syscalls.iter().cloned().collect(),
+            split_insn: segment.split_insn,
+            insn_offset: 0,
+        });
+        Ok(exec)
+    }
+
     /// Construct a new [ExecutorImpl] from the ELF binary of the guest program
     /// you want to run and an [ExecutorEnv] containing relevant
     /// environmental configuration details.
@@ -199,16 +475,29 @@ impl<'a> ExecutorImpl<'a> {
     ///     .unwrap();
     /// let mut exec = ExecutorImpl::from_elf(env, BENCH_ELF).unwrap();
     /// ```
+    ///
+    /// This is also the only constructor that supports
+    /// [ExecutorEnvBuilder::exec_acceleration](crate::ExecutorEnvBuilder::exec_acceleration),
+    /// which needs the symbol table of the ELF.
     pub fn from_elf(env: ExecutorEnv<'a>, elf: &[u8]) -> Result<Self> {
-        let program = Program::load_elf(elf, GUEST_MAX_MEM as u32)?;
-        let image = MemoryImage::new(&program, PAGE_SIZE as u32)?;
+        env.check_input_schema(elf)?;
+        let image = env.layout.load_elf(elf)?;
         let obj_ctx = if tracing::level_filters::LevelFilter::current().eq(&tracing::Level::TRACE) {
             let file = addr2line::object::read::File::parse(elf)?;
             Some(ObjectContext::new(&file)?)
         } else {
             None
         };
-        Self::with_obj_ctx(env, image, obj_ctx)
+        // Trace callbacks and the ISA audit see every instruction, so they
+        // rule out running several at once.
+        let accel = if env.exec_acceleration && env.trace.is_empty() && !env.isa_audit {
+            Some(Accelerator::new(elf)?)
+        } else {
+            None
+        };
+        let mut exec = Self::with_obj_ctx(env, image, obj_ctx)?;
+        exec.accel = accel;
+        Ok(exec)
     }
 
     /// This will run the executor to get a [Session] which contain the results
@@ -224,10 +513,134 @@ impl<'a> ExecutorImpl<'a> {
 
     /// Run the executor until [ExitCode::Halted], [ExitCode::Paused], or
     /// [ExitCode::Fault] is reached, producing a [Session] as a result.
+    ///
+    /// The `callback` is invoked with each [Segment] as soon as execution
+    /// reaches the end of it, in order, and returns the [SegmentRef] stored in
+    /// the resulting [Session]. This makes it possible to hand segments off for
+    /// proving, each one independently, while execution is still running.
+    ///
+    /// Note that the journal and the list of resolved assumptions are only
+    /// available from the [Session] returned once execution completes, and
+    /// that the callback runs on the same thread as the executor, blocking it.
+    ///
+    /// # Example
+    /// ```
+    /// use risc0_zkvm::{ExecutorEnv, ExecutorImpl, SimpleSegmentRef};
+    /// use risc0_zkvm_methods::{BENCH_ELF, bench::{BenchmarkSpec, SpecWithIters}};
+    ///
+    /// let env = ExecutorEnv::builder()
+    ///     .write(&SpecWithIters(BenchmarkSpec::SimpleLoop, 1))
+    ///     .unwrap()
+    ///     .build()
+    ///     .unwrap();
+    /// let mut exec = ExecutorImpl::from_elf(env, BENCH_ELF).unwrap();
+    /// let session = exec
+    ///     .run_with_callback(|segment| {
+    ///         println!("segment {}: {} cycles", segment.index, segment.cycles);
+    ///         Ok(Box::new(SimpleSegmentRef::new(segment)))
+    ///     })
+    ///     .unwrap();
+    /// ```
     pub fn run_with_callback<F>(&mut self, mut callback: F) -> Result<Session>
     where
         F: FnMut(Segment) -> Result<Box<dyn SegmentRef>>,
     {
+        ensure!(
+            self.run.is_none(),
+            "cannot run an executor part way through a run stepped with next_segment"
+        );
+        let run = self.start_run()?;
+        let mut run_loop = || -> Result<RunEnd> {
+            loop {
+                let (segment, end) = self.run_segment(&run.journal)?;
+                let segment_ref = callback(segment)?;
+                self.segments.push(segment_ref);
+                if let Some(end) = end {
+                    return Ok(end);
+                }
+            }
+        };
+        let result = run_loop();
+        self.finish_run(run, result)
+    }
+
+    /// Execute the guest until the end of its next [Segment], and return it.
+    ///
+    /// This is the step-wise form of [ExecutorImpl::run_with_callback], for
+    /// services that hand each segment off as soon as it is complete. The
+    /// guest's program counter is the `pc` of the [MemoryImage] the executor
+    /// was constructed with, and the options that configure it are those of
+    /// its [ExecutorEnv].
+    ///
+    /// Returns `None` once the last segment of the run has been returned.
+    /// Call [ExecutorImpl::finish] then to get the [Session], with its journal
+    /// and exit code. A run that fails returns the error and ends.
+    ///
+    /// The same limitations apply as to [ExecutorImpl::run_with_callback]:
+    /// the journal is only available from the finished [Session], and each
+    /// call blocks until the guest reaches the end of a segment.
+    ///
+    /// # Example
+    /// ```
+    /// use risc0_zkvm::{ExecutorEnv, ExecutorImpl};
+    /// use risc0_zkvm_methods::{BENCH_ELF, bench::{BenchmarkSpec, SpecWithIters}};
+    ///
+    /// let env = ExecutorEnv::builder()
+    ///     .write(&SpecWithIters(BenchmarkSpec::SimpleLoop, 1))
+    ///     .unwrap()
+    ///     .build()
+    ///     .unwrap();
+    /// let mut exec = ExecutorImpl::from_elf(env, BENCH_ELF).unwrap();
+    /// while let Some(segment) = exec.next_segment() {
+    ///     let segment = segment.unwrap();
+    ///     println!("segment {}: {} cycles", segment.index, segment.cycles);
+    /// }
+    /// let session = exec.finish().unwrap();
+    /// ```
+    pub fn next_segment(&mut self) -> Option<Result<Segment>> {
+        let run = match self.run.take() {
+            Some(run) => run,
+            None => match self.start_run() {
+                Ok(run) => run,
+                Err(err) => return Some(Err(err)),
+            },
+        };
+        if run.end.is_some() {
+            self.run = Some(run);
+            return None;
+        }
+        match self.run_segment(&run.journal) {
+            Ok((segment, end)) => {
+                self.run = Some(Run { end, ..run });
+                Some(Ok(segment))
+            }
+            Err(err) => match self.finish_run(run, Err(err)) {
+                Ok(_) => unreachable!("a failed run has no session"),
+                Err(err) => Some(Err(err)),
+            },
+        }
+    }
+
+    /// Finish a run stepped through with [ExecutorImpl::next_segment], once it
+    /// has returned `None`, and return its [Session].
+    ///
+    /// The segments of the [Session] are empty, since each was returned by
+    /// [ExecutorImpl::next_segment]. A paused guest can be resumed by calling
+    /// [ExecutorImpl::next_segment] again.
+    pub fn finish(&mut self) -> Result<Session> {
+        let run = self
+            .run
+            .take()
+            .ok_or_else(|| anyhow!("no run was started with next_segment"))?;
+        let Some(end) = run.end.clone() else {
+            self.run = Some(run);
+            bail!("the run has not ended: call next_segment until it returns None");
+        };
+        self.finish_run(run, Ok(end))
+    }
+
+    // Prepare to run the guest from its pre-image.
+    fn start_run(&mut self) -> Result<Run> {
         let (Some(ExitCode::SystemSplit | ExitCode::Paused(_)) | None) = self.exit_code else {
             return Err(anyhow!(
                 "cannot resume an execution which exited with {:?}",
@@ -237,87 +650,182 @@ impl<'a> ExecutorImpl<'a> {
         };
 
         let start_time = std::time::Instant::now();
+        #[cfg(feature = "metrics")]
+        let timer = crate::host::server::metrics::PhaseTimer::start("execute");
 
-        self.pc = self
+        let pre_image = self
             .pre_image
             .as_ref()
-            .ok_or_else(|| anyhow!("attempted to run the executor with no pre_image"))?
-            .pc;
+            .ok_or_else(|| anyhow!("attempted to run the executor with no pre_image"))?;
+        self.pc = pre_image.pc;
+        self.env.resolve_lazy_fds(pre_image.compute_id())?;
         self.monitor.clear_session()?;
+        if let Some(checkpoint) = self.resume.take() {
+            let journal = self.apply_resume(checkpoint)?;
+            self.yield_journal = Some(journal);
+        }
 
-        let journal = Journal::default();
+        // Commits made before a guest yield belong to the same journal.
+        let journal = self.yield_journal.take().unwrap_or_default();
+        let hints = self.env.posix_io.borrow().output_size_hints;
+        if let Some(hints) = hints {
+            let mut buf = journal.buf.borrow_mut();
+            let len = buf.len();
+            buf.reserve(hints.journal.saturating_sub(len));
+        }
+        let stream = JournalStream {
+            journal: journal.clone(),
+            callbacks: self.env.journal_callbacks.clone(),
+            tees: self.env.journal_tees.clone(),
+        };
         self.env
             .posix_io
             .borrow_mut()
-            .with_write_fd(fileno::JOURNAL, journal.clone());
+            .with_write_fd(fileno::JOURNAL, stream);
+        Ok(Run {
+            start_time,
+            #[cfg(feature = "metrics")]
+            _timer: timer,
+            journal,
+            hints,
+            end: None,
+        })
+    }
 
-        let mut run_loop = || -> Result<(ExitCode, MemoryImage)> {
-            loop {
-                if let Some(exit_code) = self.step()? {
-                    let total_cycles = self.total_cycles();
-                    tracing::debug!("exit_code: {exit_code:?}, total_cycles: {total_cycles}");
-                    assert!(total_cycles <= self.segment_limit);
-                    let pre_image = self.pre_image.take().ok_or_else(|| {
-                        anyhow!("attempted to run the executor with no pre_image")
-                    })?;
-                    let post_image = self.monitor.build_image(self.pc);
-                    let post_image_id = post_image.compute_id();
-                    let syscalls = mem::take(&mut self.syscalls);
-                    let faults = mem::take(&mut self.monitor.faults);
-                    let po2 = log2_ceil(total_cycles.next_power_of_two()).try_into()?;
-                    let cycles = self.body_cycles.try_into()?;
-                    let segment = Segment::new(
-                        pre_image,
-                        post_image_id,
-                        faults,
-                        syscalls,
-                        exit_code,
-                        self.split_insn,
-                        po2,
-                        self.segments
-                            .len()
-                            .try_into()
-                            .context("Too many segments to fit in u32")?,
-                        cycles,
-                    );
-                    let segment_ref = callback(segment)?;
-                    self.segments.push(segment_ref);
-                    match exit_code {
-                        ExitCode::SystemSplit => self.split(Some(post_image.into()))?,
-                        ExitCode::SessionLimit => bail!("Session limit exceeded"),
-                        ExitCode::Paused(inner) => {
-                            tracing::debug!("Paused({inner}): {}", self.segment_cycle);
-                            // Set the pre_image so that the Executor can be run again to resume.
-                            // Move the pc forward by WORD_SIZE because halt does not.
-                            let mut resume_pre_image = post_image.clone();
-                            resume_pre_image.pc += WORD_SIZE as u32;
-                            self.split(Some(resume_pre_image.into()))?;
-                            return Ok((exit_code, post_image));
-                        }
-                        ExitCode::Halted(inner) => {
-                            tracing::debug!("Halted({inner}): {}", self.segment_cycle);
-                            return Ok((exit_code, post_image));
-                        }
-                        ExitCode::Fault => {
-                            tracing::debug!("Fault: {}", self.segment_cycle);
-                            return Ok((exit_code, post_image));
-                        }
-                    };
-                };
+    // Execute the guest until the end of its next segment, and return the
+    // segment, along with how the run ended if it was the last one.
+    fn run_segment(&mut self, journal: &Journal) -> Result<(Segment, Option<RunEnd>)> {
+        loop {
+            let Some(exit_code) = self.step_accelerated()? else {
+                self.take_checkpoint(journal)?;
+                continue;
+            };
+            let total_cycles = self.total_cycles();
+            tracing::debug!("exit_code: {exit_code:?}, total_cycles: {total_cycles}");
+            assert!(total_cycles <= self.segment_limit);
+            let pre_image = self
+                .pre_image
+                .take()
+                .ok_or_else(|| anyhow!("attempted to run the executor with no pre_image"))?;
+            let post_image = self.monitor.build_image(self.pc);
+            let post_image_id = post_image.compute_id();
+            let syscalls = mem::take(&mut self.syscalls);
+            let faults = mem::take(&mut self.monitor.faults);
+            let po2 = log2_ceil(total_cycles.next_power_of_two()).try_into()?;
+            let cycles = self.body_cycles.try_into()?;
+            let segment = Segment::new(
+                pre_image,
+                post_image_id,
+                faults,
+                syscalls,
+                exit_code,
+                self.split_insn,
+                po2,
+                self.segment_index()?,
+                cycles,
+            );
+            self.segment_count += 1;
+            for segment_callback in &self.env.segment_callbacks {
+                segment_callback.borrow_mut()(&segment)?;
             }
-        };
+            let end = match exit_code {
+                ExitCode::SystemSplit => {
+                    if self.replay_done() {
+                        Some((exit_code, post_image, None))
+                    } else if let Some(tag) = self.pending_yield.take() {
+                        tracing::debug!("Yield({tag}): {}", self.segment_cycle);
+                        self.split(Some(post_image.clone().into()))?;
+                        Some((exit_code, post_image, Some(tag)))
+                    } else {
+                        self.split(Some(post_image.into()))?;
+                        None
+                    }
+                }
+                ExitCode::SessionLimit => bail!("Session limit exceeded"),
+                ExitCode::Paused(inner) => {
+                    tracing::debug!("Paused({inner}): {}", self.segment_cycle);
+                    // Set the pre_image so that the Executor can be run again to resume.
+                    // Move the pc forward by WORD_SIZE because halt does not.
+                    let mut resume_pre_image = post_image.clone();
+                    resume_pre_image.pc += WORD_SIZE as u32;
+                    self.split(Some(resume_pre_image.into()))?;
+                    Some((exit_code, post_image, None))
+                }
+                ExitCode::Halted(inner) => {
+                    tracing::debug!("Halted({inner}): {}", self.segment_cycle);
+                    Some((exit_code, post_image, None))
+                }
+                ExitCode::Fault => {
+                    tracing::debug!("Fault: {}", self.segment_cycle);
+                    Some((exit_code, post_image, None))
+                }
+            };
+            return Ok((segment, end));
+        }
+    }
 
-        let (exit_code, post_image) = run_loop()?;
+    // Wrap up a run that ended with `result`, and build its [Session].
+    fn finish_run(&mut self, run: Run, result: Result<RunEnd>) -> Result<Session> {
+        let Run {
+            start_time,
+            journal,
+            hints,
+            ..
+        } = run;
+        // The audit log covers a failed run as well.
+        if let Some(audit) = &self.env.syscall_audit {
+            audit.borrow_mut().flush()?;
+        }
+        // Deliver any output still buffered by a writer, such as a final line
+        // with no newline, even if the run failed, after reporting any log
+        // messages dropped. The error of a failed run takes precedence.
+        let flushed = {
+            let mut posix_io = self.env.posix_io.borrow_mut();
+            posix_io.report_dropped_logs().and_then(|()| {
+                posix_io
+                    .write_fds
+                    .values()
+                    .try_for_each(|writer| writer.borrow_mut().flush())
+            })
+        };
+        let (exit_code, post_image, yield_tag) = result.map_err(|err| {
+            // The guest allocator panics when the heap would grow past the end of
+            // guest memory.
+            if err.is::<HeapExhausted>() {
+                let context = self.monitor.heap_fault_context(GUEST_MAX_MEM as u32);
+                anyhow!("{err}{context}")
+            } else if let Some(context) = self
+                .env
+                .layout
+                .entry_context(self.pc, self.monitor.load_register(REG_GP))
+            {
+                err.context(context)
+            } else {
+                err
+            }
+        })?;
+        flushed?;
         let elapsed = start_time.elapsed();
 
         // Take (clear out) the list of accessed assumptions.
         // Leave the assumptions cache so it can be used if execution is resumed from pause.
         let assumptions = mem::take(&mut self.env.assumptions.borrow_mut().accessed);
 
+        if yield_tag.is_some() {
+            self.yield_journal = Some(journal.clone());
+        }
+
         // Set the session_journal to the committed data iff the the guest set a non-zero output.
         let session_journal = self
             .output_digest
-            .and_then(|output_digest| (output_digest != Digest::ZERO).then(|| journal.buf.take()));
+            .and_then(|output_digest| (output_digest != Digest::ZERO).then(|| journal.buf.take()))
+            .map(|mut buf| {
+                // A hint larger than the journal leaves capacity unused.
+                if hints.is_some() {
+                    buf.shrink_to_fit();
+                }
+                buf
+            });
         if !exit_code.expects_output() && session_journal.is_some() {
             tracing::debug!(
                 "dropping non-empty journal due to exit code {:?}: 0x{}",
@@ -330,21 +838,57 @@ impl<'a> ExecutorImpl<'a> {
         if tracing::level_filters::LevelFilter::current().ge(&tracing::Level::INFO) {
             tracing::info!("total_cycles = {}", self.total_cycles());
             tracing::info!("session_cycles = {}", self.session_cycle());
-            tracing::info!("segment_count = {}", self.segments.len());
+            tracing::info!("segment_count = {}", self.segment_count);
             tracing::info!("execution_time = {:?}", elapsed);
+            if let Some(accel) = &self.accel {
+                tracing::info!("accelerated_insns = {}", accel.insns);
+            }
         }
 
-        Ok(Session::new(
+        // Items committed to a hashed journal belong to the session that
+        // finalizes it.
+        let journal_preimages = if session_journal.is_some() {
+            mem::take(&mut *self.env.journal_preimages.borrow_mut())
+        } else {
+            Vec::new()
+        };
+        if self.env.hashed_journal {
+            let chain = hashed_journal::chain(&journal_preimages);
+            ensure!(
+                session_journal.as_deref() == Some(chain.as_bytes()),
+                "the journal is not the hash chain of the {} items committed by the guest; \
+                a guest executed with a hashed journal must call env::hashed_journal",
+                journal_preimages.len()
+            );
+        }
+        self.segment_count = 0;
+        let mut session = Session::new(
             mem::take(&mut self.segments),
             session_journal,
             exit_code,
             post_image,
             assumptions,
-        ))
+        );
+        session.yield_tag = yield_tag;
+        session.heap_stats = self.monitor.heap_stats();
+        session.failed_checks = mem::take(&mut self.failed_checks);
+        session.journal_preimages = journal_preimages;
+        Ok(session)
+    }
+
+    // Returns `true` once a replay has reached the end of the original segment.
+    fn replay_done(&self) -> bool {
+        self.replay.as_ref().map_or(false, |replay| {
+            replay.split_insn == Some(replay.insn_offset + self.insn_counter)
+        })
     }
 
     fn split(&mut self, pre_image: Option<Box<MemoryImage>>) -> Result<()> {
+        if let Some(replay) = &mut self.replay {
+            replay.insn_offset += self.insn_counter;
+        }
         self.pre_image = pre_image;
+        self.cycle_offset += self.body_cycles as u64;
         self.body_cycles = 0;
         self.split_insn = None;
         self.insn_counter = 0;
@@ -352,6 +896,54 @@ impl<'a> ExecutorImpl<'a> {
         self.monitor.clear_segment()
     }
 
+    // Execute a single instruction, or whole iterations of a loop in memcpy or
+    // memset if exec acceleration is enabled.
+    fn step_accelerated(&mut self) -> Result<Option<ExitCode>> {
+        if self.accel.is_none() {
+            return self.step();
+        }
+        let mut max_cycles = self.segment_limit - self.total_cycles();
+        if let Some(limit) = self.env.session_limit {
+            let remaining = (limit as usize).saturating_sub(self.session_cycle() + 1);
+            max_cycles = max_cycles.min(remaining);
+        }
+        let run = match &mut self.accel {
+            Some(accel) if self.replay.is_none() && self.pending_yield.is_none() => {
+                accel.run(&mut self.monitor, self.pc, max_cycles)
+            }
+            _ => None,
+        };
+        if let Some(run) = run {
+            for (idx, &value) in run.registers.iter().enumerate() {
+                if value != self.monitor.load_register(idx) {
+                    self.monitor.store_register(idx, value);
+                }
+            }
+            self.pc = run.pc;
+            self.insn_counter += run.insns;
+            self.body_cycles += run.cycles;
+            self.segment_cycle =
+                self.init_cycles + self.monitor.page_read_cycles + self.body_cycles;
+            self.monitor.commit(self.session_cycle());
+            return Ok(None);
+        }
+
+        let pc = self.pc;
+        let exit_code = self.step()?;
+        if exit_code.is_none() {
+            if let Some(accel) = &mut self.accel {
+                accel.jumped(&mut self.monitor, pc, self.pc);
+            }
+        }
+        Ok(exit_code)
+    }
+
+    /// The number of instructions run by exec acceleration so far.
+    #[cfg(test)]
+    pub(crate) fn accelerated_insns(&self) -> u64 {
+        self.accel.as_ref().map_or(0, |accel| accel.insns)
+    }
+
     /// Execute a single instruction.
     ///
     /// This can be directly used by debuggers.
@@ -361,6 +953,14 @@ impl<'a> ExecutorImpl<'a> {
                 return Ok(Some(ExitCode::SessionLimit));
             }
         }
+
+        // A replay ends where the original segment did, and a guest yield ends
+        // the segment before the next instruction executes.
+        if self.replay_done() || self.pending_yield.is_some() {
+            self.split_insn = Some(self.insn_counter);
+            return Ok(Some(ExitCode::SystemSplit));
+        }
+
         let pre_cycles = self.total_cycles();
 
         let insn = self.monitor.load_u32(self.pc)?;
@@ -407,6 +1007,7 @@ impl<'a> ExecutorImpl<'a> {
             self.ecall()?
         } else {
             let registers = self.monitor.load_registers();
+            let pre_registers = registers;
             let mut hart = HartState {
                 registers,
                 pc: self.pc,
@@ -433,6 +1034,10 @@ impl<'a> ExecutorImpl<'a> {
                 }
             }
 
+            if self.env.isa_audit {
+                self.audit(&opcode, &pre_registers, &hart)?;
+            }
+
             if let Some(idx) = hart.last_register_write {
                 self.monitor.store_register(idx, hart.registers[idx]);
             }
@@ -463,6 +1068,8 @@ impl<'a> ExecutorImpl<'a> {
             self.split_insn = Some(self.insn_counter);
             tracing::debug!("split: [{}] pc: 0x{:08x}", self.segment_cycle, self.pc,);
             self.monitor.undo()?;
+            // The syscall will be replayed in the next segment.
+            self.pending_yield = None;
             Some(ExitCode::SystemSplit)
         } else {
             self.advance(opcode, op_result)
@@ -470,6 +1077,30 @@ impl<'a> ExecutorImpl<'a> {
         Ok(exit_code)
     }
 
+    fn audit(&self, opcode: &OpCode, registers: &[u32; 32], hart: &HartState) -> Result<()> {
+        let Some(expected) = reference_step(opcode.insn, self.pc, registers) else {
+            return Ok(());
+        };
+        let actual = ReferenceStep {
+            rd: hart
+                .last_register_write
+                .filter(|&idx| idx != 0)
+                .map(|idx| (idx, hart.registers[idx])),
+            next_pc: hart.pc,
+        };
+        if actual != expected {
+            let rs1 = registers[((opcode.insn >> 15) & 0x1f) as usize];
+            let rs2 = registers[((opcode.insn >> 20) & 0x1f) as usize];
+            bail!(
+                "ISA audit divergence at pc 0x{:08x}: insn 0x{:08x} ({}), rs1 = 0x{rs1:08x}, rs2 = 0x{rs2:08x}: executor produced {actual:?}, reference produced {expected:?}",
+                self.pc,
+                opcode.insn,
+                opcode.mnemonic,
+            );
+        }
+        Ok(())
+    }
+
     fn advance(&mut self, opcode: OpCode, op_result: OpCodeResult) -> Option<ExitCode> {
         for trace in self.env.trace.iter() {
             trace
@@ -507,7 +1138,7 @@ impl<'a> ExecutorImpl<'a> {
     }
 
     fn session_cycle(&self) -> usize {
-        self.segments.len() * self.segment_limit + self.segment_cycle
+        (self.segment_offset + self.segment_count) * self.segment_limit + self.segment_cycle
     }
 
     fn ecall(&mut self) -> Result<OpCodeResult> {
@@ -659,6 +1290,22 @@ impl<'a> ExecutorImpl<'a> {
         ))
     }
 
+    fn failed_check(&mut self, code: u32) -> Result<()> {
+        let check = FailedCheck {
+            code,
+            pc: self.pc,
+            cycle: self.session_cycle() as u64,
+        };
+        tracing::debug!("{check}");
+        match self.env.check_policy {
+            CheckPolicy::Record => {
+                self.failed_checks.push(check);
+                Ok(())
+            }
+            CheckPolicy::FailFast => Err(check.into()),
+        }
+    }
+
     fn ecall_software(&mut self) -> Result<OpCodeResult> {
         let to_guest_ptr = self.monitor.load_register(REG_A0);
         if !is_guest_memory(to_guest_ptr) && to_guest_ptr != 0 {
@@ -673,19 +1320,48 @@ impl<'a> ExecutorImpl<'a> {
 
         let chunks = align_up(to_guest_words as usize, WORD_SIZE);
 
+        // A syscall that is replayed, rather than answered by the host, was
+        // already seen when it was first made.
+        let replayed = self.pending_syscall.is_some() || self.replay.is_some();
+        if !replayed && syscall_name == nr::SYS_CHECK.as_str() {
+            self.failed_check(self.monitor.load_register(REG_A3))?;
+        }
+
         let syscall = if let Some(syscall) = self.pending_syscall.clone() {
             tracing::debug!("Replay syscall: {syscall:?}");
             syscall
+        } else if let Some(replay) = &mut self.replay {
+            let syscall = replay
+                .syscalls
+                .pop_front()
+                .ok_or_else(|| anyhow!("No recorded result to replay syscall {syscall_name:?}"))?;
+            self.pending_syscall = Some(syscall.clone());
+            syscall
         } else {
             let mut to_guest = vec![0; to_guest_words as usize];
             let handler = self
                 .syscall_table
                 .get_syscall(&syscall_name)
                 .ok_or(anyhow!("Unknown syscall: {syscall_name:?}"))?;
-            let (a0, a1) =
-                handler
-                    .borrow_mut()
-                    .syscall(&syscall_name, &mut self.monitor, &mut to_guest)?;
+            #[cfg(feature = "metrics")]
+            crate::host::server::metrics::syscall(&syscall_name);
+            let (a0, a1) = match &self.env.syscall_audit {
+                Some(audit) => {
+                    let cycle = self.cycle_offset + self.body_cycles as u64;
+                    let mut ctx = AuditContext::new(&mut self.monitor, cycle);
+                    let regs =
+                        handler
+                            .borrow_mut()
+                            .syscall(&syscall_name, &mut ctx, &mut to_guest)?;
+                    ctx.write_entry(&mut *audit.borrow_mut(), &syscall_name, &to_guest)?;
+                    regs
+                }
+                None => {
+                    handler
+                        .borrow_mut()
+                        .syscall(&syscall_name, &mut self.monitor, &mut to_guest)?
+                }
+            };
             let syscall = SyscallRecord {
                 to_guest,
                 regs: (a0, a1),
@@ -694,6 +1370,10 @@ impl<'a> ExecutorImpl<'a> {
             syscall
         };
 
+        if syscall_name == nr::SYS_YIELD.as_str() {
+            self.pending_yield = Some(self.monitor.load_register(REG_A3));
+        }
+
         let (a0, a1) = syscall.regs;
         if to_guest_ptr != 0 {
             // the guest pointer is set to null for cases where the guest is
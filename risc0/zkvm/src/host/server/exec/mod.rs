@@ -18,8 +18,14 @@
 //! [crate::Session] contains one or more [crate::Segment]s, each of which
 //! contains an execution trace of the specified program.
 
+mod accel;
+pub(crate) mod checkpoint;
+pub(crate) mod compare;
+pub(crate) mod determinism;
 pub(crate) mod executor;
 mod monitor;
+pub(crate) mod reference;
 pub(crate) mod syscall;
 #[cfg(test)]
 mod tests;
+pub(crate) mod working_set;
@@ -18,14 +18,14 @@ use anyhow::{bail, Result};
 use risc0_binfmt::MemoryImage;
 use risc0_zkp::core::hash::sha::BLOCK_BYTES;
 use risc0_zkvm_platform::{
-    memory::{is_guest_memory, SYSTEM},
+    memory::{is_guest_memory, GUEST_MAX_MEM, SYSTEM, TEXT_START},
     syscall::reg_abi::REG_MAX,
     PAGE_SIZE, WORD_SIZE,
 };
 use rrs_lib::{MemAccessSize, Memory};
 
-use super::syscall::SyscallContext;
-use crate::host::{client::exec::TraceEvent, server::session::PageFaults};
+use super::{checkpoint::MemoryState, syscall::SyscallContext};
+use crate::host::{api::HeapStats, client::exec::TraceEvent, server::session::PageFaults};
 
 /// The number of blocks that fit within a single page.
 const BLOCKS_PER_PAGE: usize = PAGE_SIZE / BLOCK_BYTES;
@@ -38,6 +38,10 @@ const fn cycles_per_page(blocks_per_page: usize) -> usize {
     1 + SHA_INIT + (SHA_LOAD + SHA_MAIN) * blocks_per_page
 }
 
+/// The number of cycles taken to page a page other than the root of the page
+/// table in or out.
+pub(super) const PAGE_CYCLES: usize = cycles_per_page(BLOCKS_PER_PAGE);
+
 #[derive(Clone, Debug)]
 enum Action {
     PageRead(u32, usize),
@@ -79,6 +83,13 @@ pub struct MemoryMonitor {
     enable_trace: bool,
     pages: Vec<Option<Page>>,
     registers: [u32; REG_MAX],
+    heap_start: u32,
+    heap_end: u32,
+    // Writes at or above this address are not counted as heap usage.
+    heap_limit: u32,
+    // Set while [Memory] accesses are restricted to paged-in memory, along
+    // with the address, size and old value of each store made since.
+    paged_in_only: Option<Vec<(u32, u32, u32)>>,
 }
 
 impl MemoryMonitor {
@@ -87,6 +98,14 @@ impl MemoryMonitor {
         let resident = vec![false; num_pages];
         let dirty = vec![false; num_pages];
         let pages = vec![None; num_pages];
+        // The heap starts after the last page of the program.
+        let heap_start = image
+            .pages
+            .keys()
+            .map(|&page_idx| image.info.get_page_addr(page_idx + 1))
+            .filter(|&addr| addr as usize <= GUEST_MAX_MEM)
+            .max()
+            .unwrap_or(TEXT_START);
         Self {
             image,
             num_pages,
@@ -101,19 +120,57 @@ impl MemoryMonitor {
             enable_trace,
             pages,
             registers: [0; REG_MAX],
+            heap_start,
+            heap_end: heap_start,
+            heap_limit: GUEST_MAX_MEM as u32,
+            paged_in_only: None,
+        }
+    }
+
+    /// Replace the default bounds of the heap with those of a guest whose
+    /// layout was overridden, so that its stack is not counted as heap.
+    pub fn set_heap_bounds(&mut self, start: Option<u32>, limit: Option<u32>) {
+        if let Some(start) = start {
+            self.heap_start = start;
+            self.heap_end = start;
+        }
+        if let Some(limit) = limit {
+            self.heap_limit = limit;
         }
     }
 
-    fn check_guest_addr(addr: u32) -> Result<()> {
+    /// The heap usage of the guest so far.
+    pub fn heap_stats(&self) -> HeapStats {
+        HeapStats {
+            peak_bytes: (self.heap_end - self.heap_start) as u64,
+        }
+    }
+
+    /// Describes the heap usage for the error of a memory fault at `addr`, if
+    /// the fault is in the heap region.
+    pub fn heap_fault_context(&self, addr: u32) -> String {
+        if addr < self.heap_start {
+            return String::new();
+        }
+        format!(" (guest heap peak: {} bytes)", self.heap_stats().peak_bytes)
+    }
+
+    fn check_guest_addr(&self, addr: u32) -> Result<()> {
         if !is_guest_memory(addr) {
-            bail!("address 0x{addr:08x} is an invalid guest address");
+            bail!(
+                "address 0x{addr:08x} is an invalid guest address{}",
+                self.heap_fault_context(addr)
+            );
         }
         Ok(())
     }
 
-    fn check_guest_addr_range(start_addr: u32, end_addr: u32) -> Result<()> {
+    fn check_guest_addr_range(&self, start_addr: u32, end_addr: u32) -> Result<()> {
         if !is_guest_memory(start_addr) || !is_guest_memory(end_addr) {
-            bail!("address range 0x{start_addr:08x} - 0x{end_addr:08x} is outside of guest memory");
+            bail!(
+                "address range 0x{start_addr:08x} - 0x{end_addr:08x} is outside of guest memory{}",
+                self.heap_fault_context(end_addr)
+            );
         }
         Ok(())
     }
@@ -126,7 +183,7 @@ impl MemoryMonitor {
     }
 
     pub fn load_u8_from_guest_addr(&mut self, addr: u32) -> Result<u8> {
-        Self::check_guest_addr(addr)?;
+        self.check_guest_addr(addr)?;
         self.load_u8(addr)
     }
 
@@ -151,7 +208,7 @@ impl MemoryMonitor {
     }
 
     pub fn load_u32_from_guest_addr(&mut self, addr: u32) -> Result<u32> {
-        Self::check_guest_addr(addr)?;
+        self.check_guest_addr(addr)?;
         self.load_u32(addr)
     }
 
@@ -181,7 +238,7 @@ impl MemoryMonitor {
         } else {
             let entry_addr = info.get_page_entry_addr(page_idx);
             self.load_page(entry_addr)?;
-            cycles_per_page(BLOCKS_PER_PAGE)
+            PAGE_CYCLES
         };
 
         self.resident[page_idx as usize] = true;
@@ -206,7 +263,7 @@ impl MemoryMonitor {
         } else {
             let entry_addr = info.get_page_entry_addr(page_idx);
             self.mark_page(entry_addr);
-            cycles_per_page(BLOCKS_PER_PAGE)
+            PAGE_CYCLES
         };
 
         self.dirty[page_idx as usize] = true;
@@ -226,7 +283,7 @@ impl MemoryMonitor {
     }
 
     pub fn load_array_from_guest_addr<const N: usize>(&mut self, addr: u32) -> Result<[u8; N]> {
-        Self::check_guest_addr_range(addr, addr + u32::try_from(N)?)?;
+        self.check_guest_addr_range(addr, addr + u32::try_from(N)?)?;
         self.load_array(addr)
     }
 
@@ -238,7 +295,7 @@ impl MemoryMonitor {
     pub fn load_guest_addr_from_register(&self, idx: usize) -> Result<u32> {
         // tracing::trace!("load_register: x{idx}");
         let addr = self.registers[idx];
-        Self::check_guest_addr(addr)?;
+        self.check_guest_addr(addr)?;
         Ok(addr)
     }
 
@@ -293,7 +350,7 @@ impl MemoryMonitor {
             addr += 1;
             len += 1;
         }
-        Self::check_guest_addr_range(start_addr, start_addr + len)?;
+        self.check_guest_addr_range(start_addr, start_addr + len)?;
         self.load_string(start_addr)
     }
 
@@ -349,7 +406,7 @@ impl MemoryMonitor {
     }
 
     pub fn store_u32_to_guest_memory(&mut self, addr: u32, data: u32) -> Result<()> {
-        Self::check_guest_addr_range(addr, addr + 4)?;
+        self.check_guest_addr_range(addr, addr + 4)?;
         self.store_u32(addr, data)
     }
 
@@ -363,7 +420,7 @@ impl MemoryMonitor {
     }
 
     pub fn store_region_to_guest_memory(&mut self, addr: u32, slice: &[u8]) -> Result<()> {
-        Self::check_guest_addr_range(addr, addr + u32::try_from(slice.len())?)?;
+        self.check_guest_addr_range(addr, addr + u32::try_from(slice.len())?)?;
         self.store_region(addr, slice)
     }
 
@@ -380,6 +437,9 @@ impl MemoryMonitor {
     }
 
     fn store_bytes(&mut self, addr: u32, bytes: &[u8]) -> Result<()> {
+        if addr >= self.heap_start && addr < self.heap_limit {
+            self.heap_end = self.heap_end.max(addr + bytes.len() as u32);
+        }
         let info = &self.image.info;
         let page_idx = self.get_page_index(addr)?;
         let offset = addr % info.page_size;
@@ -391,6 +451,84 @@ impl MemoryMonitor {
         Ok(())
     }
 
+    // Returns the page holding the `size` bytes at `addr`, if they are guest
+    // memory within a single page that is already resident, and dirty if
+    // `write` is set, so that accessing them costs no paging cycles.
+    fn paged_in(&mut self, addr: u32, size: u32, write: bool) -> Option<&mut Page> {
+        if !is_guest_memory(addr) || addr % size != 0 {
+            return None;
+        }
+        let page_idx = self.image.info.get_page_index(addr) as usize;
+        if !self.resident[page_idx] || (write && !self.dirty[page_idx]) {
+            return None;
+        }
+        let image = &self.image;
+        Some(self.pages[page_idx].get_or_insert_with(|| Page {
+            buf: image.load_page(page_idx as u32),
+        }))
+    }
+
+    /// Load the `size` bytes at `addr` if that costs no paging cycles,
+    /// without recording the access.
+    fn load_paged_in(&mut self, addr: u32, size: u32) -> Option<u32> {
+        let offset = addr % self.image.info.page_size;
+        let page = self.paged_in(addr, size, false)?;
+        let mut bytes = [0_u8; WORD_SIZE];
+        page.load_bytes(offset, &mut bytes[..size as usize]);
+        Some(u32::from_le_bytes(bytes))
+    }
+
+    /// Store the low `size` bytes of `data` at `addr` if that costs no paging
+    /// cycles, without recording the store for [MemoryMonitor::undo].
+    fn store_paged_in(&mut self, addr: u32, size: u32, data: u32) -> bool {
+        let offset = addr % self.image.info.page_size;
+        let Some(page) = self.paged_in(addr, size, true) else {
+            return false;
+        };
+        page.store_bytes(offset, &data.to_le_bytes()[..size as usize]);
+        if addr >= self.heap_start && addr < self.heap_limit {
+            self.heap_end = self.heap_end.max(addr + size);
+        }
+        true
+    }
+
+    /// Returns `true` if writing a register costs no paging cycles.
+    pub fn registers_dirty(&self) -> bool {
+        let page_idx = self.image.info.get_page_index(SYSTEM.start() as u32);
+        self.dirty[page_idx as usize]
+    }
+
+    /// Load the instruction at `pc` if that costs no paging cycles, without
+    /// recording the access.
+    pub fn load_insn_paged_in(&mut self, pc: u32) -> Option<u32> {
+        self.load_paged_in(pc, WORD_SIZE as u32)
+    }
+
+    /// Restrict accesses made through [Memory] to those that cost no paging
+    /// cycles, which then fail for any other address. Stores made while
+    /// restricted are not recorded for [MemoryMonitor::undo], but can be
+    /// undone with [MemoryMonitor::undo_paged_in].
+    pub fn set_paged_in_only(&mut self, enable: bool) {
+        self.paged_in_only = enable.then(Vec::new);
+    }
+
+    /// Keep the stores made through [Memory] so far while restricted to
+    /// paged-in memory.
+    pub fn commit_paged_in(&mut self) {
+        if let Some(stores) = &mut self.paged_in_only {
+            stores.clear();
+        }
+    }
+
+    /// Undo the stores made through [Memory] since the last call to
+    /// [MemoryMonitor::commit_paged_in].
+    pub fn undo_paged_in(&mut self) {
+        let stores = self.paged_in_only.as_mut().map(take).unwrap_or_default();
+        for &(addr, size, data) in stores.iter().rev() {
+            self.store_paged_in(addr, size, data);
+        }
+    }
+
     pub fn undo(&mut self) -> Result<()> {
         let pending_actions = take(&mut self.pending_actions);
         for action in pending_actions.iter().rev() {
@@ -453,6 +591,64 @@ impl MemoryMonitor {
         Ok(())
     }
 
+    /// Capture the state of memory between two instructions. Only the dirty
+    /// pages are kept; every other page still matches the image.
+    pub fn snapshot(&self) -> MemoryState {
+        let dirty_pages = self
+            .faults
+            .writes
+            .iter()
+            .filter_map(|&page_idx| {
+                let page = self.pages[page_idx as usize].as_ref()?;
+                Some((page_idx, page.buf.clone()))
+            })
+            .collect();
+        MemoryState {
+            faults: self.faults.clone(),
+            page_read_cycles: self.page_read_cycles,
+            page_write_cycles: self.page_write_cycles,
+            registers: self.registers,
+            heap_end: self.heap_end,
+            dirty_pages,
+        }
+    }
+
+    /// Return to a state captured with [MemoryMonitor::snapshot] in a
+    /// segment whose pre-image is the current image.
+    pub fn restore(&mut self, state: MemoryState) -> Result<()> {
+        let page_size = self.image.info.page_size as usize;
+        let num_pages = self.num_pages;
+        let pages = state.faults.reads.iter().chain(state.faults.writes.iter());
+        if let Some(page_idx) = pages.into_iter().find(|&&idx| idx as usize >= num_pages) {
+            bail!("page index 0x{page_idx:08x} in checkpoint does not exist");
+        }
+        for (page_idx, buf) in state.dirty_pages.iter() {
+            if !state.faults.writes.contains(page_idx) || buf.len() != page_size {
+                bail!("page 0x{page_idx:08x} in checkpoint is not a valid dirty page");
+            }
+        }
+
+        self.resident.fill(false);
+        self.dirty.fill(false);
+        self.pages.fill(None);
+        for &page_idx in state.faults.reads.iter() {
+            self.resident[page_idx as usize] = true;
+        }
+        for &page_idx in state.faults.writes.iter() {
+            self.dirty[page_idx as usize] = true;
+        }
+        for (page_idx, buf) in state.dirty_pages {
+            self.pages[page_idx as usize] = Some(Page { buf });
+        }
+        self.faults = state.faults;
+        self.page_read_cycles = state.page_read_cycles;
+        self.page_write_cycles = state.page_write_cycles;
+        self.registers = state.registers;
+        self.heap_end = state.heap_end;
+        self.pending_actions.clear();
+        Ok(())
+    }
+
     pub fn build_image(&mut self, pc: u32) -> MemoryImage {
         // self.faults.dump();
 
@@ -483,9 +679,20 @@ impl MemoryMonitor {
     }
 }
 
+fn access_size(size: MemAccessSize) -> u32 {
+    match size {
+        MemAccessSize::Byte => 1,
+        MemAccessSize::HalfWord => 2,
+        MemAccessSize::Word => WORD_SIZE as u32,
+    }
+}
+
 impl Memory for MemoryMonitor {
     fn read_mem(&mut self, addr: u32, size: MemAccessSize) -> Option<u32> {
         // tracing::trace!("read_mem: 0x{addr:08x}");
+        if self.paged_in_only.is_some() {
+            return self.load_paged_in(addr, access_size(size));
+        }
         if !is_guest_memory(addr) {
             return None;
         }
@@ -498,6 +705,19 @@ impl Memory for MemoryMonitor {
 
     fn write_mem(&mut self, addr: u32, size: MemAccessSize, store_data: u32) -> bool {
         // tracing::trace!("write_mem: 0x{addr:08x} <= 0x{store_data:08x}");
+        if self.paged_in_only.is_some() {
+            let size = access_size(size);
+            let Some(old) = self.load_paged_in(addr, size) else {
+                return false;
+            };
+            if !self.store_paged_in(addr, size, store_data) {
+                return false;
+            }
+            if let Some(stores) = &mut self.paged_in_only {
+                stores.push((addr, size, old));
+            }
+            return true;
+        }
         if !is_guest_memory(addr) {
             return false;
         }
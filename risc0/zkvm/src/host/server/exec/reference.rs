@@ -0,0 +1,246 @@
+// Copyright 2023 RISC Zero, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! An independent reference implementation of the rv32im instruction
+//! semantics.
+//!
+//! This is deliberately kept separate from the instruction executor used by
+//! the [crate::ExecutorImpl] so that the two can be checked against each
+//! other, either by the executor's ISA audit mode or by differential testing
+//! harnesses.
+
+/// The effect of executing a single instruction.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ReferenceStep {
+    /// The register written by the instruction and its new value, if any.
+    ///
+    /// Writes to `x0` are discarded and reported as `None`.
+    pub rd: Option<(usize, u32)>,
+
+    /// The address of the next instruction to execute.
+    pub next_pc: u32,
+}
+
+/// Execute a single rv32im instruction against the given registers.
+///
+/// Only instructions whose effect is fully determined by the registers and
+/// the program counter are covered: arithmetic, logic, shifts, the M
+/// extension, branches, jumps, `lui` and `auipc`. Returns `None` for any
+/// other instruction (e.g. loads, stores, and system instructions).
+pub fn reference_step(insn: u32, pc: u32, registers: &[u32; 32]) -> Option<ReferenceStep> {
+    let opcode = insn & 0x7f;
+    let rd = ((insn >> 7) & 0x1f) as usize;
+    let funct3 = (insn >> 12) & 0x7;
+    let rs1 = registers[((insn >> 15) & 0x1f) as usize];
+    let rs2_idx = (insn >> 20) & 0x1f;
+    let rs2 = registers[rs2_idx as usize];
+    let funct7 = insn >> 25;
+
+    let imm_i = ((insn as i32) >> 20) as u32;
+    let imm_u = insn & 0xfffff000;
+    let imm_b = ((((insn as i32) >> 19) as u32) & !0xfff)
+        | ((insn << 4) & 0x800)
+        | ((insn >> 20) & 0x7e0)
+        | ((insn >> 7) & 0x1e);
+    let imm_j = ((((insn as i32) >> 11) as u32) & !0xfffff)
+        | (insn & 0xff000)
+        | ((insn >> 9) & 0x800)
+        | ((insn >> 20) & 0x7fe);
+
+    let next = pc.wrapping_add(4);
+    let (value, next_pc) = match opcode {
+        // lui
+        0x37 => (Some(imm_u), next),
+        // auipc
+        0x17 => (Some(pc.wrapping_add(imm_u)), next),
+        // jal
+        0x6f => (Some(next), pc.wrapping_add(imm_j)),
+        // jalr
+        0x67 if funct3 == 0 => (Some(next), rs1.wrapping_add(imm_i) & !1),
+        // branches
+        0x63 => {
+            let taken = match funct3 {
+                0 => rs1 == rs2,
+                1 => rs1 != rs2,
+                4 => (rs1 as i32) < (rs2 as i32),
+                5 => (rs1 as i32) >= (rs2 as i32),
+                6 => rs1 < rs2,
+                7 => rs1 >= rs2,
+                _ => return None,
+            };
+            (None, if taken { pc.wrapping_add(imm_b) } else { next })
+        }
+        // register-immediate operations
+        0x13 => {
+            let shamt = rs2_idx;
+            let value = match (funct3, funct7) {
+                (0, _) => rs1.wrapping_add(imm_i),
+                (2, _) => ((rs1 as i32) < (imm_i as i32)) as u32,
+                (3, _) => (rs1 < imm_i) as u32,
+                (4, _) => rs1 ^ imm_i,
+                (6, _) => rs1 | imm_i,
+                (7, _) => rs1 & imm_i,
+                (1, 0x00) => rs1 << shamt,
+                (5, 0x00) => rs1 >> shamt,
+                (5, 0x20) => ((rs1 as i32) >> shamt) as u32,
+                _ => return None,
+            };
+            (Some(value), next)
+        }
+        // register-register operations
+        0x33 => {
+            let value = match (funct3, funct7) {
+                (0, 0x00) => rs1.wrapping_add(rs2),
+                (0, 0x20) => rs1.wrapping_sub(rs2),
+                (1, 0x00) => rs1 << (rs2 & 0x1f),
+                (2, 0x00) => ((rs1 as i32) < (rs2 as i32)) as u32,
+                (3, 0x00) => (rs1 < rs2) as u32,
+                (4, 0x00) => rs1 ^ rs2,
+                (5, 0x00) => rs1 >> (rs2 & 0x1f),
+                (5, 0x20) => ((rs1 as i32) >> (rs2 & 0x1f)) as u32,
+                (6, 0x00) => rs1 | rs2,
+                (7, 0x00) => rs1 & rs2,
+                (funct3, 0x01) => muldiv(funct3, rs1, rs2),
+                _ => return None,
+            };
+            (Some(value), next)
+        }
+        _ => return None,
+    };
+
+    Some(ReferenceStep {
+        rd: value.filter(|_| rd != 0).map(|value| (rd, value)),
+        next_pc,
+    })
+}
+
+// The M extension, including the division corner cases defined by the spec:
+// division by zero yields all ones (quotient) or the dividend (remainder),
+// and `i32::MIN / -1` overflows to `i32::MIN` with a remainder of zero.
+fn muldiv(funct3: u32, rs1: u32, rs2: u32) -> u32 {
+    let (signed1, signed2) = (rs1 as i32, rs2 as i32);
+    match funct3 {
+        // mul
+        0 => rs1.wrapping_mul(rs2),
+        // mulh
+        1 => ((signed1 as i64 * signed2 as i64) >> 32) as u32,
+        // mulhsu
+        2 => ((signed1 as i64 * rs2 as i64) >> 32) as u32,
+        // mulhu
+        3 => ((rs1 as u64 * rs2 as u64) >> 32) as u32,
+        // div
+        4 => match (signed1, signed2) {
+            (_, 0) => u32::MAX,
+            (i32::MIN, -1) => i32::MIN as u32,
+            _ => (signed1 / signed2) as u32,
+        },
+        // divu
+        5 => rs1.checked_div(rs2).unwrap_or(u32::MAX),
+        // rem
+        6 => match (signed1, signed2) {
+            (_, 0) => rs1,
+            (i32::MIN, -1) => 0,
+            _ => (signed1 % signed2) as u32,
+        },
+        // remu
+        _ => rs1.checked_rem(rs2).unwrap_or(rs1),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{reference_step, ReferenceStep};
+
+    const RD: u32 = 3;
+    const RS1: u32 = 1;
+    const RS2: u32 = 2;
+
+    fn muldiv(funct3: u32, rs1: u32, rs2: u32) -> u32 {
+        let insn = (0x01 << 25) | (RS2 << 20) | (RS1 << 15) | (funct3 << 12) | (RD << 7) | 0x33;
+        let mut registers = [0; 32];
+        registers[RS1 as usize] = rs1;
+        registers[RS2 as usize] = rs2;
+        let step = reference_step(insn, 0x1000, &registers).unwrap();
+        assert_eq!(step.next_pc, 0x1004);
+        step.rd.unwrap().1
+    }
+
+    const DIV: u32 = 4;
+    const DIVU: u32 = 5;
+    const REM: u32 = 6;
+    const REMU: u32 = 7;
+
+    #[test]
+    fn division_by_zero() {
+        assert_eq!(muldiv(DIV, 7, 0), u32::MAX);
+        assert_eq!(muldiv(DIV, -7i32 as u32, 0), u32::MAX);
+        assert_eq!(muldiv(DIVU, 7, 0), u32::MAX);
+        assert_eq!(muldiv(REM, -7i32 as u32, 0), -7i32 as u32);
+        assert_eq!(muldiv(REMU, 7, 0), 7);
+    }
+
+    #[test]
+    fn division_overflow() {
+        let min = i32::MIN as u32;
+        let neg_one = -1i32 as u32;
+        assert_eq!(muldiv(DIV, min, neg_one), min);
+        assert_eq!(muldiv(REM, min, neg_one), 0);
+        assert_eq!(muldiv(DIVU, min, neg_one), 0);
+        assert_eq!(muldiv(REMU, min, neg_one), min);
+    }
+
+    #[test]
+    fn signed_division_rounds_towards_zero() {
+        assert_eq!(muldiv(DIV, -7i32 as u32, 2), -3i32 as u32);
+        assert_eq!(muldiv(REM, -7i32 as u32, 2), -1i32 as u32);
+        assert_eq!(muldiv(DIV, 7, -2i32 as u32), -3i32 as u32);
+        assert_eq!(muldiv(REM, 7, -2i32 as u32), 1);
+    }
+
+    #[test]
+    fn multiply_high() {
+        assert_eq!(muldiv(1, -1i32 as u32, -1i32 as u32), 0);
+        assert_eq!(muldiv(2, -1i32 as u32, u32::MAX), u32::MAX);
+        assert_eq!(muldiv(3, u32::MAX, u32::MAX), u32::MAX - 1);
+    }
+
+    #[test]
+    fn write_to_x0_is_discarded() {
+        // addi x0, x0, 1
+        let step = reference_step(0x00100013, 0, &[0; 32]).unwrap();
+        assert_eq!(
+            step,
+            ReferenceStep {
+                rd: None,
+                next_pc: 4
+            }
+        );
+    }
+
+    #[test]
+    fn branches_and_jumps() {
+        let mut registers = [0; 32];
+        registers[1] = 5;
+        registers[2] = 5;
+        // beq x1, x2, -8
+        let step = reference_step(0xfe208ce3, 0x100, &registers).unwrap();
+        assert_eq!(step.next_pc, 0xf8);
+        // jal x1, 16
+        let step = reference_step(0x010000ef, 0x100, &registers).unwrap();
+        assert_eq!(step.rd, Some((1, 0x104)));
+        assert_eq!(step.next_pc, 0x110);
+        // loads are not covered: lw x3, 0(x1)
+        assert!(reference_step(0x0000a183, 0x100, &registers).is_none());
+    }
+}
@@ -14,34 +14,246 @@
 
 //! Handlers for two-way private I/O between host and guest.
 
-use std::{cell::RefCell, cmp::min, collections::HashMap, rc::Rc, str::from_utf8};
+use std::{
+    cell::RefCell,
+    cmp::min,
+    collections::{BTreeSet, HashMap},
+    fmt,
+    io::Write,
+    rc::Rc,
+    str::from_utf8,
+    sync::mpsc::{self, RecvTimeoutError},
+    thread,
+    time::Duration,
+};
 
-use anyhow::{anyhow, bail, Result};
+use anyhow::{anyhow, bail, ensure, Context, Result};
 use bytes::Bytes;
+use num_bigint::BigUint;
+use num_traits::Zero;
 use risc0_zkvm_platform::{
     fileno,
     syscall::{
         nr::{
-            SYS_ARGC, SYS_ARGV, SYS_CYCLE_COUNT, SYS_GETENV, SYS_LOG, SYS_PANIC, SYS_RANDOM,
-            SYS_READ, SYS_READ_AVAIL, SYS_VERIFY, SYS_VERIFY_INTEGRITY, SYS_WRITE,
+            SYS_ARGC, SYS_ARGV, SYS_ASSUMPTION, SYS_BIGINT_MODMUL, SYS_CAPABILITIES, SYS_CHECK,
+            SYS_CYCLES_REMAINING, SYS_CYCLE_COUNT, SYS_EXEC, SYS_GETENV, SYS_GETENV_PAGE,
+            SYS_HOST_COMMITS, SYS_JOURNAL_PREIMAGE, SYS_LOG, SYS_NAMED_INPUT, SYS_NONCE, SYS_PANIC,
+            SYS_RANDOM, SYS_READ, SYS_READ_AVAIL, SYS_SHA256, SYS_VERIFY, SYS_VERIFY_INTEGRITY,
+            SYS_WRITE, SYS_YIELD,
         },
         reg_abi::{REG_A3, REG_A4, REG_A5},
-        SyscallName, DIGEST_BYTES, DIGEST_WORDS,
+        SyscallName, DIGEST_BYTES, DIGEST_WORDS, HEAP_EXHAUSTED_MSG,
     },
-    WORD_SIZE,
+    PAGE_SIZE, WORD_SIZE,
 };
+use serde::{Deserialize, Serialize};
 
 use crate::{
+    get_prover_server_for,
     host::client::{
-        env::{Assumptions, ExecutorEnv},
-        posix_io::PosixIo,
-        slice_io::SliceIo,
+        env::{Assumptions, ExecutorEnv, GuestRegistry},
+        posix_io::{write_with_backoff, BufferFull, LogPolicy, PosixIo, WriteTimedOut},
+        slice_io::{check_fill_len, SliceIo, SliceIoHandler, SyscallGuard, ThreadedCallback},
     },
     receipt_metadata::{MaybePruned, PrunedValueError},
-    sha::{Digest, Digestible},
-    Assumption, ExitCode, ReceiptMetadata,
+    serde::{from_bytes, to_vec},
+    sha::{Digest, Digestible, Impl, Sha256 as _},
+    sha_accel::{self, Sha256Stream},
+    Assumption, ExecutorImpl, ExitCode, MemoryImage, Program, ReceiptMetadata, VerifierContext,
+    GUEST_MAX_MEM,
 };
 
+/// An error in the I/O performed by a guest.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GuestIoError {
+    /// The guest asked to read more data than was left on a file descriptor.
+    UnexpectedEof {
+        /// The file descriptor being read.
+        fd: u32,
+        /// The number of bytes requested by the guest.
+        requested: u32,
+        /// The number of bytes that were available.
+        available: u32,
+        /// The cycle at which the read occurred.
+        cycle: usize,
+    },
+
+    /// The guest wrote more to a file descriptor registered with
+    /// [ExecutorEnvBuilder::write_fd_buffered](crate::ExecutorEnvBuilder::write_fd_buffered)
+    /// than its buffer could hold, under
+    /// [OverflowPolicy::Error](crate::OverflowPolicy::Error).
+    BackpressureExceeded {
+        /// The file descriptor being written.
+        fd: u32,
+        /// The capacity of its buffer, in bytes.
+        capacity: usize,
+        /// The cycle at which the write occurred.
+        cycle: usize,
+    },
+
+    /// The guest logged past the limits set with
+    /// [ExecutorEnvBuilder::log_limits](crate::ExecutorEnvBuilder::log_limits),
+    /// under [LogPolicy::Error](crate::LogPolicy::Error).
+    LogLimitExceeded {
+        /// The number of messages allowed.
+        max_messages: u64,
+        /// The total size of the messages allowed, in bytes.
+        max_total_bytes: u64,
+        /// The cycle at which the message was logged.
+        cycle: usize,
+    },
+
+    /// The guest kept polling a file descriptor with `SYS_READ_AVAIL` and
+    /// finding it empty, past the threshold set with
+    /// [ExecutorEnvBuilder::stall_threshold](crate::ExecutorEnvBuilder::stall_threshold).
+    Stalled {
+        /// The file descriptor being polled.
+        fd: u32,
+        /// The number of times in a row it was found empty.
+        polls: u32,
+        /// The cycle of the last poll.
+        cycle: usize,
+    },
+
+    /// The writer of a file descriptor was still not ready to accept a
+    /// write from the guest at the deadline set with
+    /// [ExecutorEnvBuilder::write_backoff](crate::ExecutorEnvBuilder::write_backoff).
+    WriteTimedOut {
+        /// The file descriptor being written.
+        fd: u32,
+        /// The number of bytes of the write that were accepted.
+        written: usize,
+        /// The number of bytes the guest wrote.
+        len: usize,
+        /// The cycle at which the write occurred.
+        cycle: usize,
+    },
+
+    /// The guest wrote more to the journal or to standard output than the
+    /// strict hint set with
+    /// [ExecutorEnvBuilder::output_size_hints](crate::ExecutorEnvBuilder::output_size_hints).
+    OutputSizeExceeded {
+        /// The file descriptor being written.
+        fd: u32,
+        /// The hint, in bytes.
+        hint: usize,
+        /// The number of bytes written to the descriptor including this
+        /// write, which was not made.
+        len: usize,
+        /// The cycle at which the write occurred.
+        cycle: usize,
+    },
+}
+
+impl fmt::Display for GuestIoError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GuestIoError::UnexpectedEof {
+                fd,
+                requested,
+                available,
+                cycle,
+            } => write!(
+                f,
+                "guest read {requested} bytes from fd {fd} at cycle {cycle}, but only {available} were available"
+            ),
+            GuestIoError::BackpressureExceeded {
+                fd,
+                capacity,
+                cycle,
+            } => write!(
+                f,
+                "guest write to fd {fd} at cycle {cycle} overflowed its buffer of {capacity} bytes"
+            ),
+            GuestIoError::LogLimitExceeded {
+                max_messages,
+                max_total_bytes,
+                cycle,
+            } => write!(
+                f,
+                "guest log at cycle {cycle} exceeded the limit of {max_messages} messages or {max_total_bytes} bytes"
+            ),
+            GuestIoError::Stalled { fd, polls, cycle } => write!(
+                f,
+                "guest stalled at cycle {cycle}: it found fd {fd} empty {polls} times in a row while waiting for input"
+            ),
+            GuestIoError::WriteTimedOut {
+                fd,
+                written,
+                len,
+                cycle,
+            } => write!(
+                f,
+                "guest write of {len} bytes to fd {fd} at cycle {cycle} timed out after {written} bytes were accepted"
+            ),
+            GuestIoError::OutputSizeExceeded {
+                fd,
+                hint,
+                len,
+                cycle,
+            } => write!(
+                f,
+                "guest write to fd {fd} at cycle {cycle} would bring its output to {len} bytes, past the hint of {hint} bytes"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for GuestIoError {}
+
+/// An I/O handler broke the limits of its
+/// [SyscallGuard](crate::SyscallGuard).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SyscallGuardError {
+    /// The handler did not return within its timeout.
+    Timeout {
+        /// The syscall the handler was registered for.
+        syscall: String,
+        /// The timeout it was given.
+        timeout: Duration,
+        /// The cycle at which the guest made the call.
+        cycle: usize,
+    },
+
+    /// The handler returned a larger response than it was allowed.
+    ResponseTooLarge {
+        /// The syscall the handler was registered for.
+        syscall: String,
+        /// The length of the response, in bytes.
+        len: usize,
+        /// The largest response allowed, in bytes.
+        limit: usize,
+        /// The cycle at which the guest made the call.
+        cycle: usize,
+    },
+}
+
+impl fmt::Display for SyscallGuardError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SyscallGuardError::Timeout {
+                syscall,
+                timeout,
+                cycle,
+            } => write!(
+                f,
+                "I/O handler for {syscall} did not return within {timeout:?} of the call at cycle {cycle}"
+            ),
+            SyscallGuardError::ResponseTooLarge {
+                syscall,
+                len,
+                limit,
+                cycle,
+            } => write!(
+                f,
+                "I/O handler for {syscall} returned {len} bytes at cycle {cycle}, more than its limit of {limit}"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for SyscallGuardError {}
+
 /// A host-side implementation of a system call.
 pub trait Syscall {
     /// Invokes the system call.
@@ -94,34 +306,79 @@ pub trait SyscallContext {
 #[derive(Clone)]
 pub(crate) struct SyscallTable<'a> {
     pub(crate) inner: HashMap<String, Rc<RefCell<dyn Syscall + 'a>>>,
+    // The names of all registered syscalls, as reported by SYS_CAPABILITIES.
+    names: Rc<RefCell<BTreeSet<String>>>,
 }
 
 impl<'a> SyscallTable<'a> {
     pub fn new(env: &ExecutorEnv<'a>) -> Self {
         let mut this = Self {
             inner: HashMap::new(),
+            names: Default::default(),
         };
 
         let sys_verify = SysVerify::new(env.assumptions.clone());
+        let sys_assumption = SysSliceIo::new(Rc::new(RefCell::new(SysAssumption(
+            env.assumptions.clone(),
+        ))));
+        let sys_bigint_modmul =
+            SysSliceIo::new(Rc::new(RefCell::new(SysBigIntModMul(env.bigint_accel))));
+        let sys_capabilities =
+            SysSliceIo::new(Rc::new(RefCell::new(SysCapabilities(this.names.clone()))));
+        let sys_exec = SysSliceIo::new(Rc::new(RefCell::new(SysExec {
+            registry: env.guest_registry.clone(),
+            assumptions: env.assumptions.clone(),
+        })));
+        let sys_named_input = SysSliceIo::new(Rc::new(RefCell::new(SysNamedInput(
+            env.named_inputs.clone(),
+        ))));
+        let sys_nonce = SysSliceIo::new(Rc::new(RefCell::new(SysNonce(env.nonce))));
+        let sys_host_commits = SysSliceIo::new(Rc::new(RefCell::new(env.host_commits.clone())));
+        let sys_journal_preimage = SysSliceIo::new(Rc::new(RefCell::new(SysJournalPreimage(
+            env.journal_preimages.clone(),
+        ))));
+        let sys_sha256 = SysSliceIo::new(Rc::new(RefCell::new(SysSha256::new(env.sha_accel))));
 
         let posix_io = env.posix_io.clone();
         this.with_syscall(SYS_CYCLE_COUNT, SysCycleCount)
+            .with_syscall(SYS_CYCLES_REMAINING, SysCyclesRemaining(env.session_limit))
+            .with_syscall(SYS_EXEC, sys_exec)
+            .with_syscall(SYS_HOST_COMMITS, sys_host_commits)
+            .with_syscall(SYS_JOURNAL_PREIMAGE, sys_journal_preimage)
             .with_syscall(SYS_LOG, posix_io.clone())
+            .with_syscall(SYS_NAMED_INPUT, sys_named_input)
+            .with_syscall(SYS_NONCE, sys_nonce)
             .with_syscall(SYS_PANIC, SysPanic)
             .with_syscall(SYS_RANDOM, SysRandom)
             .with_syscall(SYS_GETENV, SysGetenv(env.env_vars.clone()))
+            .with_syscall(SYS_GETENV_PAGE, SysGetenv(env.env_vars.clone()))
             .with_syscall(SYS_READ, posix_io.clone())
             .with_syscall(SYS_READ_AVAIL, posix_io.clone())
             .with_syscall(SYS_WRITE, posix_io)
             .with_syscall(SYS_VERIFY, sys_verify.clone())
             .with_syscall(SYS_VERIFY_INTEGRITY, sys_verify)
             .with_syscall(SYS_ARGC, Args(env.args.clone()))
-            .with_syscall(SYS_ARGV, Args(env.args.clone()));
-        for (syscall, handler) in env.slice_io.borrow().inner.iter() {
-            let handler = SysSliceIo::new(handler.clone());
-            this.inner
-                .insert(syscall.clone(), Rc::new(RefCell::new(handler)));
+            .with_syscall(SYS_ARGV, Args(env.args.clone()))
+            .with_syscall(SYS_ASSUMPTION, sys_assumption)
+            .with_syscall(SYS_BIGINT_MODMUL, sys_bigint_modmul)
+            .with_syscall(SYS_CAPABILITIES, sys_capabilities)
+            .with_syscall(SYS_CHECK, SysCheck)
+            .with_syscall(SYS_SHA256, sys_sha256)
+            .with_syscall(SYS_YIELD, SysYield);
+        if !env.sha_accel {
+            // Registered so that it stays a built-in, but not offered.
+            this.names.borrow_mut().remove(SYS_SHA256.as_str());
+        }
+        if !env.bigint_accel {
+            this.names.borrow_mut().remove(SYS_BIGINT_MODMUL.as_str());
         }
+        let slice_io = env.slice_io.borrow();
+        for (syscall, handler) in slice_io.inner.iter() {
+            let guard = slice_io.guards.get(syscall).copied();
+            let handler = SysSliceIo::from_handler(handler.clone(), guard);
+            this.insert(syscall, handler);
+        }
+        drop(slice_io);
 
         this
     }
@@ -131,16 +388,107 @@ impl<'a> SyscallTable<'a> {
         syscall: SyscallName,
         handler: impl Syscall + 'a,
     ) -> &mut Self {
-        self.inner
-            .insert(syscall.as_str().to_string(), Rc::new(RefCell::new(handler)));
+        self.insert(syscall.as_str(), handler);
         self
     }
 
+    fn insert(&mut self, name: &str, handler: impl Syscall + 'a) {
+        self.names.borrow_mut().insert(name.to_string());
+        self.inner
+            .insert(name.to_string(), Rc::new(RefCell::new(handler)));
+    }
+
     pub(crate) fn get_syscall(&self, name: &str) -> Option<&Rc<RefCell<(dyn Syscall + 'a)>>> {
         self.inner.get(name)
     }
 }
 
+/// A [SyscallContext] that records the guest memory read by a syscall
+/// handler, for the log written to
+/// [ExecutorEnvBuilder::syscall_audit](crate::ExecutorEnvBuilder::syscall_audit).
+pub(crate) struct AuditContext<'c> {
+    inner: &'c mut dyn SyscallContext,
+    cycle: u64,
+    from_guest: Vec<u8>,
+}
+
+/// One line of the syscall audit log.
+#[derive(Serialize, Deserialize, PartialEq)]
+pub(crate) struct AuditEntry {
+    pub cycle: u64,
+    pub syscall: String,
+    pub from_guest: AuditData,
+    pub to_guest: AuditData,
+}
+
+#[derive(Serialize, Deserialize, PartialEq)]
+pub(crate) struct AuditData {
+    pub bytes: usize,
+    pub sha256: String,
+}
+
+impl AuditData {
+    fn new(bytes: &[u8]) -> Self {
+        Self {
+            bytes: bytes.len(),
+            sha256: Impl::hash_bytes(bytes).to_string(),
+        }
+    }
+}
+
+impl<'c> AuditContext<'c> {
+    /// Audit a syscall made once the session has executed `cycle` user
+    /// cycles.
+    pub fn new(inner: &'c mut dyn SyscallContext, cycle: u64) -> Self {
+        Self {
+            inner,
+            cycle,
+            from_guest: Vec::new(),
+        }
+    }
+
+    /// Write the line for the syscall just handled, which returned `to_guest`
+    /// to the guest, to `writer`.
+    pub fn write_entry(
+        self,
+        writer: &mut dyn Write,
+        syscall: &str,
+        to_guest: &[u32],
+    ) -> Result<()> {
+        let entry = AuditEntry {
+            cycle: self.cycle,
+            syscall: syscall.to_string(),
+            from_guest: AuditData::new(&self.from_guest),
+            to_guest: AuditData::new(bytemuck::cast_slice(to_guest)),
+        };
+        serde_json::to_writer(&mut *writer, &entry)?;
+        writer.write_all(b"\n")?;
+        Ok(())
+    }
+}
+
+impl<'c> SyscallContext for AuditContext<'c> {
+    fn get_cycle(&self) -> usize {
+        self.inner.get_cycle()
+    }
+
+    fn load_register(&mut self, idx: usize) -> u32 {
+        self.inner.load_register(idx)
+    }
+
+    fn load_u32(&mut self, addr: u32) -> Result<u32> {
+        let word = self.inner.load_u32(addr)?;
+        self.from_guest.extend_from_slice(&word.to_le_bytes());
+        Ok(word)
+    }
+
+    fn load_u8(&mut self, addr: u32) -> Result<u8> {
+        let byte = self.inner.load_u8(addr)?;
+        self.from_guest.push(byte);
+        Ok(byte)
+    }
+}
+
 pub(crate) struct SysCycleCount;
 impl Syscall for SysCycleCount {
     fn syscall(
@@ -153,26 +501,83 @@ impl Syscall for SysCycleCount {
     }
 }
 
+// The cycles left before the session limit, split across both return
+// registers, or `u64::MAX` if there is no limit.
+pub(crate) struct SysCyclesRemaining(Option<u64>);
+impl Syscall for SysCyclesRemaining {
+    fn syscall(
+        &mut self,
+        _syscall: &str,
+        ctx: &mut dyn SyscallContext,
+        _to_guest: &mut [u32],
+    ) -> Result<(u32, u32)> {
+        let remaining = self.0.map_or(u64::MAX, |limit| {
+            limit.saturating_sub(ctx.get_cycle() as u64)
+        });
+        Ok((remaining as u32, (remaining >> 32) as u32))
+    }
+}
+
+// The executor itself records the failed check, since only it knows the pc;
+// see `ExecutorImpl::ecall_software`.
+pub(crate) struct SysCheck;
+impl Syscall for SysCheck {
+    fn syscall(
+        &mut self,
+        _syscall: &str,
+        _ctx: &mut dyn SyscallContext,
+        _to_guest: &mut [u32],
+    ) -> Result<(u32, u32)> {
+        Ok((0, 0))
+    }
+}
+
+// The executor itself ends the segment after a yield; see `ExecutorImpl::step`.
+pub(crate) struct SysYield;
+impl Syscall for SysYield {
+    fn syscall(
+        &mut self,
+        _syscall: &str,
+        _ctx: &mut dyn SyscallContext,
+        _to_guest: &mut [u32],
+    ) -> Result<(u32, u32)> {
+        Ok((0, 0))
+    }
+}
+
 pub(crate) struct SysGetenv(pub HashMap<String, String>);
 impl Syscall for SysGetenv {
     fn syscall(
         &mut self,
-        _syscall: &str,
+        syscall: &str,
         ctx: &mut dyn SyscallContext,
         to_guest: &mut [u32],
     ) -> Result<(u32, u32)> {
         let buf_ptr = ctx.load_register(REG_A3);
         let buf_len = ctx.load_register(REG_A4);
         let from_guest = ctx.load_region(buf_ptr, buf_len)?;
-        let msg = from_utf8(&from_guest)?;
+        // SYS_GETENV always reads from the start of the value; SYS_GETENV_PAGE
+        // reads from the offset in a5.
+        let offset = if syscall == SYS_GETENV_PAGE.as_str() {
+            ctx.load_register(REG_A5) as usize
+        } else {
+            0
+        };
 
-        match self.0.get(msg) {
+        // Names are validated to be UTF-8 by the host, so any other name is unset.
+        match from_utf8(&from_guest)
+            .ok()
+            .and_then(|name| self.0.get(name))
+        {
             None => Ok((u32::MAX, 0)),
+            // Report the full length even if the value is truncated, so the guest
+            // can retry with a large enough buffer, or read the rest in pages.
             Some(val) => {
-                let nbytes = min(to_guest.len() * WORD_SIZE, val.as_bytes().len());
+                let rest = val.as_bytes().get(offset..).unwrap_or_default();
+                let nbytes = min(to_guest.len() * WORD_SIZE, rest.len());
                 let to_guest_u8s: &mut [u8] = bytemuck::cast_slice_mut(to_guest);
-                to_guest_u8s[0..nbytes].clone_from_slice(&val.as_bytes()[0..nbytes]);
-                Ok((val.as_bytes().len() as u32, 0))
+                to_guest_u8s[0..nbytes].clone_from_slice(&rest[0..nbytes]);
+                Ok((val.as_bytes().len() as u32, nbytes as u32))
             }
         }
     }
@@ -190,10 +595,26 @@ impl Syscall for SysPanic {
         let buf_len = ctx.load_register(REG_A4);
         let from_guest = ctx.load_region(buf_ptr, buf_len)?;
         let msg = from_utf8(&from_guest)?;
+        if msg == HEAP_EXHAUSTED_MSG {
+            return Err(HeapExhausted.into());
+        }
         bail!("Guest panicked: {msg}");
     }
 }
 
+/// The error of a guest whose allocator panicked because the heap would grow
+/// past the end of guest memory.
+#[derive(Debug)]
+pub(crate) struct HeapExhausted;
+
+impl fmt::Display for HeapExhausted {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Guest panicked: {HEAP_EXHAUSTED_MSG}")
+    }
+}
+
+impl std::error::Error for HeapExhausted {}
+
 pub(crate) struct SysRandom;
 impl Syscall for SysRandom {
     fn syscall(
@@ -210,6 +631,54 @@ impl Syscall for SysRandom {
     }
 }
 
+// Answers SYS_RANDOM from a stream of SHA-256 blocks of a seed derived from
+// the image ID and input digest, for
+// [ExecutorEnvBuilder::derived_randomness](crate::ExecutorEnvBuilder::derived_randomness).
+pub(crate) struct SysDerivedRandom {
+    seed: Digest,
+    counter: u64,
+    // The bytes of the current block not yet handed to the guest.
+    block: Vec<u8>,
+}
+
+impl SysDerivedRandom {
+    const DOMAIN: &'static [u8] = b"risc0.derived_randomness";
+
+    pub(crate) fn new(image_id: Digest, input_digest: Digest) -> Self {
+        let seed = [Self::DOMAIN, image_id.as_bytes(), input_digest.as_bytes()].concat();
+        Self {
+            seed: *Impl::hash_bytes(&seed),
+            counter: 0,
+            block: Vec::new(),
+        }
+    }
+
+    fn fill(&mut self, bytes: &mut [u8]) {
+        for byte in bytes.iter_mut() {
+            if self.block.is_empty() {
+                let input = [self.seed.as_bytes(), &self.counter.to_le_bytes()].concat();
+                self.block = Impl::hash_bytes(&input).as_bytes().to_vec();
+                self.block.reverse();
+                self.counter += 1;
+            }
+            *byte = self.block.pop().unwrap();
+        }
+    }
+}
+
+impl Syscall for SysDerivedRandom {
+    fn syscall(
+        &mut self,
+        _syscall: &str,
+        _ctx: &mut dyn SyscallContext,
+        to_guest: &mut [u32],
+    ) -> Result<(u32, u32)> {
+        tracing::debug!("SYS_RANDOM (derived): {}", to_guest.len());
+        self.fill(bytemuck::cast_slice_mut(to_guest));
+        Ok((0, 0))
+    }
+}
+
 #[derive(Clone)]
 pub(crate) struct SysVerify {
     pub(crate) assumptions: Rc<RefCell<Assumptions>>,
@@ -392,6 +861,245 @@ impl Syscall for SysVerify {
     }
 }
 
+// Reports the names of all registered syscalls, separated by NUL bytes.
+// Syscalls registered after the table is built are included as well.
+pub(crate) struct SysCapabilities(Rc<RefCell<BTreeSet<String>>>);
+
+impl SliceIo for SysCapabilities {
+    fn handle_io(&mut self, _syscall: &str, _from_guest: Bytes) -> Result<Bytes> {
+        let names = self.0.borrow();
+        let names: Vec<&str> = names.iter().map(String::as_str).collect();
+        Ok(names.join("\0").into())
+    }
+}
+
+// Looks up the file descriptor of a named input, returning it as a single
+// little-endian word, or nothing if there is no input with that name.
+pub(crate) struct SysNamedInput(Vec<(String, u32)>);
+
+impl SliceIo for SysNamedInput {
+    fn handle_io(&mut self, _syscall: &str, from_guest: Bytes) -> Result<Bytes> {
+        let fd = self
+            .0
+            .iter()
+            .find(|(name, _)| name.as_bytes() == from_guest.as_ref())
+            .map(|(_, fd)| *fd);
+        tracing::debug!("SYS_NAMED_INPUT: {:?} => {fd:?}", from_utf8(&from_guest));
+        Ok(fd
+            .map(|fd| fd.to_le_bytes().to_vec())
+            .unwrap_or_default()
+            .into())
+    }
+}
+
+// Returns the nonce provided by the host, or nothing if there is none.
+pub(crate) struct SysNonce(Option<[u8; 32]>);
+
+impl SliceIo for SysNonce {
+    fn handle_io(&mut self, _syscall: &str, _from_guest: Bytes) -> Result<Bytes> {
+        Ok(self
+            .0
+            .map(|nonce| nonce.to_vec())
+            .unwrap_or_default()
+            .into())
+    }
+}
+
+// Records an item committed by a guest with a hashed journal.
+pub(crate) struct SysJournalPreimage(Rc<RefCell<Vec<Vec<u8>>>>);
+
+impl SliceIo for SysJournalPreimage {
+    fn handle_io(&mut self, _syscall: &str, from_guest: Bytes) -> Result<Bytes> {
+        self.0.borrow_mut().push(from_guest.to_vec());
+        Ok(Bytes::new())
+    }
+}
+
+// Hashes data streamed by the guest, for
+// [ExecutorEnvBuilder::sha_accel](crate::ExecutorEnvBuilder::sha_accel).
+// Always registered, so that the syscall stays a built-in, but fails every
+// request unless enabled.
+pub(crate) struct SysSha256 {
+    enabled: bool,
+    streams: HashMap<u32, Sha256Stream>,
+    next_id: u32,
+}
+
+impl SysSha256 {
+    fn new(enabled: bool) -> Self {
+        Self {
+            enabled,
+            streams: HashMap::new(),
+            next_id: 0,
+        }
+    }
+}
+
+impl SliceIo for SysSha256 {
+    fn handle_io(&mut self, _syscall: &str, from_guest: Bytes) -> Result<Bytes> {
+        ensure!(
+            self.enabled,
+            "SYS_SHA256 is not enabled; enable it with ExecutorEnvBuilder::sha_accel"
+        );
+        let (op, id, data) = sha_accel::decode_request(&from_guest)
+            .ok_or_else(|| anyhow!("malformed SYS_SHA256 request of {} bytes", from_guest.len()))?;
+        let unknown = || anyhow!("SYS_SHA256 request for unknown stream {id}");
+        match op {
+            sha_accel::OP_INIT => {
+                let id = self.next_id;
+                self.next_id = id.checked_add(1).context("too many SYS_SHA256 streams")?;
+                self.streams.insert(id, Sha256Stream::new());
+                Ok(id.to_le_bytes().to_vec().into())
+            }
+            sha_accel::OP_UPDATE => {
+                let stream = self.streams.get_mut(&id).ok_or_else(unknown)?;
+                Ok(stream.update(data).as_bytes().to_vec().into())
+            }
+            sha_accel::OP_FINAL => {
+                let stream = self.streams.remove(&id).ok_or_else(unknown)?;
+                Ok(stream.finalize().as_bytes().to_vec().into())
+            }
+            _ => bail!("unknown SYS_SHA256 operation {op}"),
+        }
+    }
+}
+
+// Runs a guest from the registry on behalf of the calling guest, returning the
+// serialized image ID and journal of the nested guest.
+pub(crate) struct SysExec {
+    registry: GuestRegistry,
+    assumptions: Rc<RefCell<Assumptions>>,
+}
+
+impl SliceIo for SysExec {
+    fn handle_io(&mut self, _syscall: &str, from_guest: Bytes) -> Result<Bytes> {
+        let (name, arg): (String, Vec<u8>) = from_bytes(&from_guest)?;
+        let elf = self
+            .registry
+            .guests
+            .get(&name)
+            .ok_or_else(|| anyhow!("sys_exec: guest {name:?} is not registered"))?;
+        if self.registry.depth >= self.registry.depth_limit {
+            bail!(
+                "sys_exec: nested execution depth limit of {} exceeded",
+                self.registry.depth_limit
+            );
+        }
+        tracing::debug!("SYS_EXEC: {name} at depth {}", self.registry.depth + 1);
+
+        let mut env = ExecutorEnv::builder()
+            .write_bytes(&arg)
+            .session_limit(self.registry.session_limit)
+            .build()?;
+        env.guest_registry = GuestRegistry {
+            depth: self.registry.depth + 1,
+            ..self.registry.clone()
+        };
+        let session = ExecutorImpl::from_elf(env, elf)
+            .and_then(|mut exec| exec.run())
+            .with_context(|| format!("sys_exec: guest {name:?} failed"))?;
+        if session.exit_code != ExitCode::Halted(0) {
+            bail!(
+                "sys_exec: guest {name:?} exited with {:?}",
+                session.exit_code
+            );
+        }
+
+        if self.registry.prove {
+            let prover = get_prover_server_for(&self.registry.prove_opts)?;
+            let receipt = prover.prove_session(&VerifierContext::default(), &session)?;
+            self.assumptions.borrow_mut().cached.push(receipt.into());
+        }
+
+        let program = Program::load_elf(elf, GUEST_MAX_MEM as u32)?;
+        let image_id = MemoryImage::new(&program, PAGE_SIZE as u32)?.compute_id();
+        let journal = session.journal.map(|journal| journal.bytes);
+        let words = to_vec(&(image_id, journal.unwrap_or_default()))?;
+        Ok(bytemuck::cast_slice(&words).to_vec().into())
+    }
+}
+
+/// Serves the encoded receipts of proven assumptions to the guest.
+pub(crate) struct SysAssumption(pub Rc<RefCell<Assumptions>>);
+
+impl SliceIo for SysAssumption {
+    fn handle_io(&mut self, _syscall: &str, from_guest: Bytes) -> Result<Bytes> {
+        let index: [u8; WORD_SIZE] = from_guest.as_ref().try_into().map_err(|_| {
+            anyhow!(
+                "sys_assumption call with input of length {} bytes; expected {}",
+                from_guest.len(),
+                WORD_SIZE
+            )
+        })?;
+        let index = u32::from_le_bytes(index) as usize;
+
+        let receipt = {
+            let assumptions = self.0.borrow();
+            let assumption = assumptions.cached.get(index).ok_or_else(|| {
+                anyhow!(
+                    "guest requested assumption {} of {}",
+                    index,
+                    assumptions.cached.len()
+                )
+            })?;
+            assumption.as_receipt()?.clone()
+        };
+
+        tracing::debug!("SYS_ASSUMPTION: {index}");
+        Ok(receipt.into_assumption_bytes()?.into())
+    }
+}
+
+/// Performs modular multiplication of arbitrary width big integers on behalf of
+/// the guest.
+///
+/// The guest sends `a`, `b` and `m` as little-endian `u32` limbs, each `n`
+/// limbs long, and receives the quotient (`2n` limbs) and remainder (`n`
+/// limbs) of `a * b` divided by `m`. The result is untrusted, and is checked by
+/// the guest.
+///
+/// Like [SysSha256], it is always registered but fails every request unless
+/// enabled with
+/// [ExecutorEnvBuilder::bigint_accel](crate::ExecutorEnvBuilder::bigint_accel).
+pub(crate) struct SysBigIntModMul(bool);
+
+impl SliceIo for SysBigIntModMul {
+    fn handle_io(&mut self, _syscall: &str, from_guest: Bytes) -> Result<Bytes> {
+        ensure!(
+            self.0,
+            "SYS_BIGINT_MODMUL is not enabled; enable it with ExecutorEnvBuilder::bigint_accel"
+        );
+        let n = from_guest.len() / (3 * WORD_SIZE);
+        if n == 0 || from_guest.len() != 3 * n * WORD_SIZE {
+            bail!(
+                "sys_bigint_modmul call with input of length {} bytes",
+                from_guest.len()
+            );
+        }
+        let mut operands = from_guest
+            .chunks_exact(n * WORD_SIZE)
+            .map(BigUint::from_bytes_le);
+        let (a, b, modulus) = (
+            operands.next().unwrap(),
+            operands.next().unwrap(),
+            operands.next().unwrap(),
+        );
+        if modulus.is_zero() {
+            bail!("sys_bigint_modmul called with a zero modulus");
+        }
+        tracing::debug!("SYS_BIGINT_MODMUL: {} limbs", n);
+
+        let product = a * b;
+        let mut to_guest = Vec::with_capacity(3 * n * WORD_SIZE);
+        for (value, limbs) in [(&product / &modulus, 2 * n), (&product % &modulus, n)] {
+            let mut bytes = value.to_bytes_le();
+            bytes.resize(limbs * WORD_SIZE, 0);
+            to_guest.extend_from_slice(&bytes);
+        }
+        Ok(to_guest.into())
+    }
+}
+
 #[derive(Clone)]
 pub(crate) struct Args(pub Vec<String>);
 
@@ -427,18 +1135,58 @@ impl Syscall for Args {
 
 /// A wrapper around a SliceIo that exposes it as a Syscall handler.
 pub struct SysSliceIo<'a> {
-    handler: Rc<RefCell<dyn SliceIo + 'a>>,
-    stored_result: RefCell<Option<Bytes>>,
+    handler: SliceIoHandler<'a>,
+    guard: SyscallGuard,
+    stored_result: RefCell<Option<StoredResult>>,
+}
+
+// The response to the first call of a pair, kept for the second.
+enum StoredResult {
+    Bytes(Bytes),
+    // The handler holds the response, of this many bytes.
+    Fill(usize),
 }
 
 impl<'a> SysSliceIo<'a> {
     /// Wraps the given [SliceIo] into a [SysSliceIo].
     pub fn new(handler: Rc<RefCell<dyn SliceIo + 'a>>) -> Self {
+        Self::from_handler(SliceIoHandler::Bytes(handler), None)
+    }
+
+    pub(crate) fn from_handler(handler: SliceIoHandler<'a>, guard: Option<SyscallGuard>) -> Self {
         Self {
             handler,
+            guard: guard.unwrap_or_default(),
             stored_result: RefCell::new(None),
         }
     }
+
+    // Runs the callback on a helper thread if it has a timeout, and inline
+    // otherwise.
+    fn call_threaded(
+        &self,
+        callback: &ThreadedCallback,
+        syscall: &str,
+        from_guest: Bytes,
+        cycle: usize,
+    ) -> Result<Bytes> {
+        let Some(timeout) = self.guard.timeout else {
+            return callback(from_guest);
+        };
+        let (tx, rx) = mpsc::channel();
+        let callback = callback.clone();
+        thread::spawn(move || tx.send(callback(from_guest)));
+        match rx.recv_timeout(timeout) {
+            Ok(result) => result,
+            Err(RecvTimeoutError::Timeout) => Err(SyscallGuardError::Timeout {
+                syscall: syscall.to_string(),
+                timeout,
+                cycle,
+            }
+            .into()),
+            Err(RecvTimeoutError::Disconnected) => bail!("I/O handler for {syscall} panicked"),
+        }
+    }
 }
 
 /// An implementation of a [Syscall] for a [SliceIo].
@@ -463,20 +1211,58 @@ impl<'a> Syscall for SysSliceIo<'a> {
                 // First call of pair. Send the data from the guest to the SliceIo
                 // and save what it returns.
                 assert_eq!(to_guest.len(), 0);
-                let mut handler = self.handler.borrow_mut();
-                let result = handler.handle_io(syscall, from_guest.into())?;
-                let len = result.len() as u32;
+                let cycle = ctx.get_cycle();
+                let result =
+                    match &self.handler {
+                        SliceIoHandler::Bytes(handler) => StoredResult::Bytes(
+                            handler.borrow_mut().handle_io(syscall, from_guest.into())?,
+                        ),
+                        SliceIoHandler::Fill(handler) => StoredResult::Fill(
+                            handler.borrow_mut().request(syscall, from_guest.into())?,
+                        ),
+                        SliceIoHandler::Threaded(callback) => StoredResult::Bytes(
+                            self.call_threaded(callback, syscall, from_guest.into(), cycle)?,
+                        ),
+                    };
+                let len = match &result {
+                    StoredResult::Bytes(bytes) => bytes.len(),
+                    StoredResult::Fill(len) => *len,
+                };
+                if let Some(limit) = self.guard.max_response_bytes {
+                    if len > limit {
+                        return Err(SyscallGuardError::ResponseTooLarge {
+                            syscall: syscall.to_string(),
+                            len,
+                            limit,
+                            cycle,
+                        }
+                        .into());
+                    }
+                }
                 *stored_result = Some(result);
-                (len, 0)
+                (len as u32, 0)
             }
             Some(stored) => {
                 // Second call of pair. We already have data to send
                 // to the guest; send it to the buffer that the guest
                 // allocated.
                 let to_guest_bytes: &mut [u8] = bytemuck::cast_slice_mut(to_guest);
-                assert!(stored.len() <= to_guest_bytes.len());
-                assert!(stored.len() + WORD_SIZE > to_guest_bytes.len());
-                to_guest_bytes[..stored.len()].clone_from_slice(&stored);
+                match stored {
+                    StoredResult::Bytes(stored) => {
+                        assert!(stored.len() <= to_guest_bytes.len());
+                        assert!(stored.len() + WORD_SIZE > to_guest_bytes.len());
+                        to_guest_bytes[..stored.len()].clone_from_slice(&stored);
+                    }
+                    StoredResult::Fill(len) => {
+                        let SliceIoHandler::Fill(handler) = &self.handler else {
+                            unreachable!("only fill handlers keep their response")
+                        };
+                        assert!(len <= to_guest_bytes.len());
+                        assert!(len + WORD_SIZE > to_guest_bytes.len());
+                        let written = handler.borrow_mut().fill(to_guest_bytes)?;
+                        check_fill_len(syscall, written, len)?;
+                    }
+                }
                 (0, 0)
             }
         })
@@ -526,6 +1312,25 @@ impl<'a> PosixIo<'a> {
             .ok_or(anyhow!("Bad read file descriptor {fd}"))?;
         let navail = reader.borrow_mut().fill_buf()?.len() as u32;
         tracing::debug!("navail: {navail}");
+
+        self.empty_polls = match (navail, self.stall_threshold, self.empty_polls) {
+            (0, Some(threshold), empty_polls) => {
+                let polls = match empty_polls {
+                    Some((last_fd, polls)) if last_fd == fd => polls + 1,
+                    _ => 1,
+                };
+                if polls > threshold {
+                    return Err(GuestIoError::Stalled {
+                        fd,
+                        polls,
+                        cycle: ctx.get_cycle(),
+                    }
+                    .into());
+                }
+                Some((fd, polls))
+            }
+            _ => None,
+        };
         Ok((navail, 0))
     }
 
@@ -536,6 +1341,7 @@ impl<'a> PosixIo<'a> {
     ) -> Result<(u32, u32)> {
         let fd = ctx.load_register(REG_A3);
         let nbytes = ctx.load_register(REG_A4) as usize;
+        self.empty_polls = None;
 
         tracing::debug!(
             "sys_read, attempting to read {nbytes} bytes from fd {fd}, to_guest: {}",
@@ -547,6 +1353,7 @@ impl<'a> PosixIo<'a> {
             "Word-aligned read buffer must be fully filled"
         );
 
+        let short_reads = self.short_reads;
         let reader = self
             .read_fds
             .get_mut(&fd)
@@ -567,35 +1374,43 @@ impl<'a> PosixIo<'a> {
             Ok(tot_nread)
         };
 
-        let to_guest_u8 = bytemuck::cast_slice_mut(to_guest);
+        let to_guest_u8: &mut [u8] = bytemuck::cast_slice_mut(to_guest);
         let nread_main = read_all(to_guest_u8)?;
-        assert_eq!(
-            nread_main,
-            to_guest_u8.len(),
-            "Guest requested more data than was available"
-        );
 
         tracing::debug!(
             "Main read got {nread_main} bytes out of requested {}",
             to_guest_u8.len()
         );
-        let unaligned_end = nbytes - nread_main;
+        let unaligned_end = nbytes - to_guest_u8.len();
         assert!(
             unaligned_end <= WORD_SIZE,
             "{unaligned_end} must be <= {WORD_SIZE}"
         );
 
-        // Fill unaligned word out.
+        // Fill unaligned word out, unless the input already ran out.
         let mut to_guest_end: [u8; WORD_SIZE] = [0; WORD_SIZE];
-        let nread_end = read_all(&mut to_guest_end[0..unaligned_end])?;
+        let nread_end = if nread_main == to_guest_u8.len() {
+            read_all(&mut to_guest_end[0..unaligned_end])?
+        } else {
+            0
+        };
 
-        Ok((
-            (nread_main + nread_end) as u32,
-            u32::from_le_bytes(to_guest_end),
-        ))
+        let nread = nread_main + nread_end;
+        if nread < nbytes && !short_reads {
+            return Err(GuestIoError::UnexpectedEof {
+                fd,
+                requested: nbytes as u32,
+                available: nread as u32,
+                cycle: ctx.get_cycle(),
+            }
+            .into());
+        }
+
+        Ok((nread as u32, u32::from_le_bytes(to_guest_end)))
     }
 
     fn sys_write(&mut self, ctx: &mut dyn SyscallContext) -> Result<(u32, u32)> {
+        self.empty_polls = None;
         let fd = ctx.load_register(REG_A3);
         let buf_ptr = ctx.load_register(REG_A4);
         let buf_len = ctx.load_register(REG_A5);
@@ -605,9 +1420,59 @@ impl<'a> PosixIo<'a> {
             .get_mut(&fd)
             .ok_or(anyhow!("Bad write file descriptor {fd}"))?;
 
+        let hint = self
+            .output_size_hints
+            .filter(|hints| hints.strict)
+            .and_then(|hints| match fd {
+                fileno::JOURNAL => Some(hints.journal),
+                fileno::STDOUT => Some(hints.stdout),
+                _ => None,
+            });
+        if let Some(hint) = hint {
+            let written = self.output_bytes.entry(fd).or_default();
+            let len = *written + from_guest_bytes.len();
+            if len > hint {
+                return Err(GuestIoError::OutputSizeExceeded {
+                    fd,
+                    hint,
+                    len,
+                    cycle: ctx.get_cycle(),
+                }
+                .into());
+            }
+            *written = len;
+        }
+
         tracing::debug!("Writing {buf_len} bytes to file descriptor {fd}");
 
-        writer.borrow_mut().write_all(from_guest_bytes.as_slice())?;
+        write_with_backoff(
+            &mut *writer.borrow_mut(),
+            &from_guest_bytes,
+            &self.write_backoff,
+        )
+        .map_err(|err| {
+            if let Some(full) = err.get_ref().and_then(|x| x.downcast_ref::<BufferFull>()) {
+                return GuestIoError::BackpressureExceeded {
+                    fd,
+                    capacity: full.capacity,
+                    cycle: ctx.get_cycle(),
+                }
+                .into();
+            }
+            if let Some(timeout) = err
+                .get_ref()
+                .and_then(|x| x.downcast_ref::<WriteTimedOut>())
+            {
+                return GuestIoError::WriteTimedOut {
+                    fd,
+                    written: timeout.written,
+                    len: from_guest_bytes.len(),
+                    cycle: ctx.get_cycle(),
+                }
+                .into();
+            }
+            anyhow::Error::from(err)
+        })?;
         Ok((0, 0))
     }
 
@@ -615,6 +1480,28 @@ impl<'a> PosixIo<'a> {
         let buf_ptr = ctx.load_register(REG_A3);
         let buf_len = ctx.load_register(REG_A4);
         let from_guest = ctx.load_region(buf_ptr, buf_len)?;
+        if let Some(limits) = self.log_limits {
+            if self.log_messages >= limits.max_messages
+                || self.log_bytes + buf_len as u64 > limits.max_total_bytes
+            {
+                match limits.on_exceed {
+                    LogPolicy::Drop => {
+                        self.log_dropped += 1;
+                        return Ok((0, 0));
+                    }
+                    LogPolicy::Error => {
+                        return Err(GuestIoError::LogLimitExceeded {
+                            max_messages: limits.max_messages,
+                            max_total_bytes: limits.max_total_bytes,
+                            cycle: ctx.get_cycle(),
+                        }
+                        .into())
+                    }
+                }
+            }
+            self.log_messages += 1;
+            self.log_bytes += buf_len as u64;
+        }
         // write to stdout, but be sure to point it to where the file descriptor is pointing
         let writer = self
             .write_fds
@@ -13,30 +13,55 @@
 // limitations under the License.
 
 use std::{
-    collections::{BTreeMap, HashSet},
+    borrow::Cow,
+    cell::{Cell, RefCell},
+    collections::{BTreeMap, HashMap, HashSet},
     io::Cursor,
+    rc::Rc,
     str::from_utf8,
-    sync::Mutex,
+    sync::{Arc, Mutex},
+    time::Duration,
 };
 
 use anyhow::Result;
 use bytes::Bytes;
+use num_bigint::BigUint;
+use risc0_binfmt::input_schema_digest;
 use risc0_zkvm_methods::{
-    multi_test::{MultiTestSpec, SYS_MULTI_TEST},
-    HELLO_COMMIT_ELF, MULTI_TEST_ELF, SLICE_IO_ELF, STANDARD_LIB_ELF,
+    multi_test::{
+        CheckedPoint, FramedHeader, FramedRecord, MultiTestSpec, CHECKED_POINT_SCHEMA,
+        SYS_MULTI_TEST,
+    },
+    HELLO_COMMIT_ELF, MULTI_TEST_ELF, MULTI_TEST_ID, SLICE_IO_ELF, STANDARD_LIB_ELF,
+};
+use risc0_zkvm_platform::{
+    fileno,
+    memory::{GUEST_MAX_MEM, SYSTEM, TEXT_START},
+    syscall::{
+        nr::{SYS_BIGINT_MODMUL, SYS_NONCE, SYS_RANDOM, SYS_READ, SYS_WRITE},
+        reg_abi::{REG_A0, REG_A1, REG_A2, REG_RA},
+        SyscallName, DIGEST_BYTES,
+    },
+    PAGE_SIZE, WORD_SIZE,
 };
-use risc0_zkvm_platform::{fileno, syscall::nr::SYS_RANDOM, PAGE_SIZE, WORD_SIZE};
+use serde::Serialize;
 use sha2::{Digest as _, Sha256};
 use test_log::test;
 
 use crate::{
+    cycles_for_po2,
     host::server::{
-        exec::syscall::{Syscall, SyscallContext},
+        exec::syscall::{Syscall, SyscallContext, SyscallTable},
         testutils,
     },
     serde::to_vec,
-    sha::Digest,
-    ExecutorEnv, ExecutorImpl, ExitCode, MemoryImage, Program,
+    sha::{Digest, Digestible, Impl, Sha256 as _},
+    CheckPolicy, CompareOpts, CycleAttributor, ExecutorEnv, ExecutorEnvBuilder, ExecutorImpl,
+    ExitCode, FailedCheck, FramedOutput, GuestEnvManifest, GuestIoError, GuestLayout, HostCommits,
+    ImageError, InputSchema, LocalProver, LogPolicy, MemoryImage, OptsError, OverflowPolicy,
+    Program, Prover, Segment, Session, SetupErrors, SliceIoFill, SymbolKind, SymbolStats,
+    SyscallGuard, SyscallGuardError, SyscallSource, TraceKind, TraceReader, TraceRecord,
+    WriteBackoff, MAX_SEGMENT_LIMIT_PO2, MIN_SEGMENT_LIMIT_PO2,
 };
 
 fn run_test(spec: MultiTestSpec) {
@@ -81,6 +106,70 @@ fn basic() {
     assert_eq!(segments[0].index, 0);
 }
 
+#[test]
+fn segment_limit_po2_bounds() {
+    let bounds = format!("must be between {MIN_SEGMENT_LIMIT_PO2} and {MAX_SEGMENT_LIMIT_PO2}");
+    for po2 in [0, MIN_SEGMENT_LIMIT_PO2 - 1, MAX_SEGMENT_LIMIT_PO2 + 1] {
+        let err = ExecutorEnv::builder()
+            .try_segment_limit_po2(po2)
+            .err()
+            .unwrap();
+        assert_eq!(
+            err.to_string(),
+            format!("Invalid segment_limit_po2: {po2}, {bounds}")
+        );
+
+        // The infallible setter is rejected when the executor starts.
+        let env = ExecutorEnv::builder()
+            .segment_limit_po2(po2)
+            .build()
+            .unwrap();
+        let err = ExecutorImpl::from_elf(env, MULTI_TEST_ELF).err().unwrap();
+        assert!(err.to_string().contains(&bounds));
+    }
+    for po2 in [MIN_SEGMENT_LIMIT_PO2, MAX_SEGMENT_LIMIT_PO2] {
+        ExecutorEnv::builder().try_segment_limit_po2(po2).unwrap();
+    }
+
+    let min_cycles = cycles_for_po2(MIN_SEGMENT_LIMIT_PO2);
+    assert!(min_cycles > 0 && min_cycles < 1 << MIN_SEGMENT_LIMIT_PO2);
+    assert_eq!(
+        cycles_for_po2(MIN_SEGMENT_LIMIT_PO2 + 1) - min_cycles,
+        1 << MIN_SEGMENT_LIMIT_PO2
+    );
+}
+
+#[test]
+fn image_integrity_check() {
+    let program = Program::load_elf(MULTI_TEST_ELF, GUEST_MAX_MEM as u32).unwrap();
+    let image = MemoryImage::new(&program, PAGE_SIZE as u32).unwrap();
+    let page_idx = image.info.get_page_index(program.entry);
+
+    // Flip a byte of the entry page in the serialized image.
+    let page = &image.pages[&page_idx];
+    let mut bytes = bincode::serialize(&image).unwrap();
+    let offset = bytes
+        .windows(page.len())
+        .position(|x| x == page.as_slice())
+        .unwrap();
+    bytes[offset + 5] ^= 0x80;
+    let corrupted: MemoryImage = bincode::deserialize(&bytes).unwrap();
+
+    let env = ExecutorEnv::builder().build().unwrap();
+    let err = ExecutorImpl::new(env, corrupted.clone()).err().unwrap();
+    match err.downcast_ref::<ImageError>().unwrap() {
+        ImageError::PageMismatch { page_idx: idx, .. } => assert_eq!(*idx, page_idx),
+        err => panic!("unexpected error: {err}"),
+    }
+
+    // The check can be turned off for images that are already trusted.
+    let env = ExecutorEnv::builder()
+        .image_integrity_check(false)
+        .build()
+        .unwrap();
+    ExecutorImpl::new(env, corrupted).unwrap();
+}
+
 #[test]
 fn system_split() {
     let entry = 0x4000;
@@ -122,11 +211,503 @@ fn system_split() {
     assert_eq!(segments[1].index, 1);
 }
 
+#[test]
+fn segment_delta() {
+    let spec = MultiTestSpec::BusyLoop { cycles: 1 << 16 };
+    let env = ExecutorEnv::builder()
+        .write(&spec)
+        .unwrap()
+        .segment_limit_po2(14) // 16K cycles
+        .build()
+        .unwrap();
+    let session = ExecutorImpl::from_elf(env, MULTI_TEST_ELF)
+        .unwrap()
+        .run()
+        .unwrap();
+    let segments = session.resolve().unwrap();
+    assert!(segments.len() > 2);
+
+    for pair in segments.windows(2) {
+        let (prev, segment) = (&pair[0], &pair[1]);
+        let mut full = Vec::new();
+        segment.write_to(&mut full).unwrap();
+        let mut delta = Vec::new();
+        segment.write_delta_to(prev, &mut delta).unwrap();
+        assert!(delta.len() * 10 < full.len());
+
+        let decoded = Segment::read_delta_from(prev, delta.as_slice()).unwrap();
+        assert_eq!(decoded.pre_image_id(), segment.pre_image_id());
+        assert_eq!(decoded.post_image_id, segment.post_image_id);
+        assert_eq!(decoded.index, segment.index);
+
+        // The delta only applies on top of the segment it was computed against.
+        if segment.index > 1 {
+            let err = Segment::read_delta_from(&segments[0], delta.as_slice())
+                .err()
+                .unwrap();
+            assert!(err.to_string().contains("Image root mismatch"));
+        }
+    }
+}
+
+#[test]
+fn resegment() {
+    let spec = MultiTestSpec::BusyLoop { cycles: 1 << 16 };
+    let env = ExecutorEnv::builder()
+        .write(&spec)
+        .unwrap()
+        .segment_limit_po2(16)
+        .build()
+        .unwrap();
+    let session = ExecutorImpl::from_elf(env, MULTI_TEST_ELF)
+        .unwrap()
+        .run()
+        .unwrap();
+    let segments = session.resolve().unwrap();
+    assert!(segments.len() > 1);
+
+    // The first segment replays the read of the spec from stdin, and the last
+    // one ends with the halt.
+    for segment in segments.iter() {
+        let split = segment.resegment(14).unwrap();
+        assert!(split.len() > 2);
+        assert!(split.iter().all(|x| x.po2 <= 14));
+        assert_eq!(split[0].pre_image_id(), segment.pre_image_id());
+        for pair in split.windows(2) {
+            assert_eq!(pair[0].exit_code, ExitCode::SystemSplit);
+            assert_eq!(pair[0].post_image_id, pair[1].pre_image_id());
+        }
+        let last = split.last().unwrap();
+        assert_eq!(last.exit_code, segment.exit_code);
+        assert_eq!(last.post_image_id, segment.post_image_id);
+        let cycles: u32 = split.iter().map(|x| x.cycles).sum();
+        assert_eq!(cycles, segment.cycles);
+    }
+}
+
+#[test]
+fn intrasegment_checkpoint() {
+    let spec = MultiTestSpec::BusyLoop { cycles: 1 << 16 };
+    let checkpoints = Rc::new(RefCell::new(Vec::new()));
+    let env = ExecutorEnv::builder()
+        .write(&spec)
+        .unwrap()
+        .segment_limit_po2(15)
+        .build()
+        .unwrap();
+    let mut exec = ExecutorImpl::from_elf(env, MULTI_TEST_ELF).unwrap();
+    let sink = checkpoints.clone();
+    exec.checkpoint_every(10_000, move |checkpoint| {
+        sink.borrow_mut().push(checkpoint);
+        Ok(())
+    });
+    let session = exec.run().unwrap();
+    let segments = session.resolve().unwrap();
+    assert!(segments.len() > 2);
+
+    let checkpoints = checkpoints.take();
+    assert!(checkpoints.len() > 4);
+    for pair in checkpoints.windows(2) {
+        assert!(pair[1].session_cycle - pair[0].session_cycle >= 10_000);
+    }
+
+    let digest = |segment: &Segment| *Impl::hash_bytes(&bincode::serialize(segment).unwrap());
+    // Resume part way through a segment after the first, from a checkpoint
+    // that has been through serialization.
+    let checkpoint = checkpoints
+        .iter()
+        .find(|checkpoint| checkpoint.segment == 1)
+        .unwrap();
+    let checkpoint = bincode::deserialize(&bincode::serialize(checkpoint).unwrap()).unwrap();
+    let pre_image = *segments[1].pre_image.clone();
+    let env = ExecutorEnv::builder()
+        .segment_limit_po2(15)
+        .build()
+        .unwrap();
+    let resumed = ExecutorImpl::resume_from_intrasegment_checkpoint(env, pre_image, checkpoint)
+        .unwrap()
+        .run()
+        .unwrap();
+    assert_eq!(resumed.exit_code, session.exit_code);
+    assert_eq!(resumed.journal, session.journal);
+    let resumed = resumed.resolve().unwrap();
+    assert_eq!(resumed.len(), segments.len() - 1);
+    for (segment, expected) in resumed.iter().zip(&segments[1..]) {
+        assert_eq!(segment.index, expected.index);
+        assert_eq!(digest(segment), digest(expected));
+    }
+
+    // A checkpoint only resumes on top of the pre-image of its own segment.
+    let env = ExecutorEnv::builder()
+        .segment_limit_po2(15)
+        .build()
+        .unwrap();
+    let err = ExecutorImpl::resume_from_intrasegment_checkpoint(
+        env,
+        *segments[0].pre_image.clone(),
+        checkpoints.last().unwrap().clone(),
+    )
+    .err()
+    .unwrap();
+    assert!(err
+        .to_string()
+        .contains("checkpoint was taken in a segment"));
+}
+
+#[test]
+fn nested_exec() {
+    let spec = MultiTestSpec::Exec {
+        name: "hello".into(),
+        arg: Vec::new(),
+    };
+    let env = ExecutorEnv::builder()
+        .write(&spec)
+        .unwrap()
+        .guest_registry([("hello".to_string(), HELLO_COMMIT_ELF.to_vec())])
+        .build()
+        .unwrap();
+    let session = ExecutorImpl::from_elf(env, MULTI_TEST_ELF)
+        .unwrap()
+        .run()
+        .unwrap();
+    let (image_id, journal): (Digest, Vec<u8>) = session.journal.unwrap().decode().unwrap();
+
+    let hello = ExecutorImpl::from_elf(ExecutorEnv::default(), HELLO_COMMIT_ELF)
+        .unwrap()
+        .run()
+        .unwrap();
+    assert_eq!(journal, hello.journal.as_ref().unwrap().bytes);
+    assert_eq!(image_id, hello.resolve().unwrap()[0].pre_image_id());
+}
+
+#[test]
+fn nested_exec_limits() {
+    let exec = |name: &str, spec: &MultiTestSpec| MultiTestSpec::Exec {
+        name: name.into(),
+        arg: bytemuck::cast_slice(&to_vec(spec).unwrap()).to_vec(),
+    };
+    let run = |spec: &MultiTestSpec, depth_limit: u32, session_limit: Option<u64>| {
+        let env = ExecutorEnv::builder()
+            .write(spec)
+            .unwrap()
+            .guest_registry([("multi".to_string(), MULTI_TEST_ELF.to_vec())])
+            .nested_depth_limit(depth_limit)
+            .nested_session_limit(session_limit)
+            .build()
+            .unwrap();
+        ExecutorImpl::from_elf(env, MULTI_TEST_ELF).unwrap().run()
+    };
+
+    // The parent runs a child, which in turn runs a grandchild.
+    let spec = exec("multi", &exec("multi", &MultiTestSpec::DoNothing));
+    assert!(run(&spec, 2, None).is_ok());
+    let err = run(&spec, 1, None).err().unwrap();
+    assert!(format!("{err:?}").contains("depth limit of 1 exceeded"));
+
+    let spec = exec("multi", &MultiTestSpec::BusyLoop { cycles: 1 << 16 });
+    assert!(run(&spec, 1, Some(1 << 17)).is_ok());
+    let err = run(&spec, 1, Some(1 << 15)).err().unwrap();
+    assert!(format!("{err:?}").contains("Session limit exceeded"));
+
+    let spec = exec("missing", &MultiTestSpec::DoNothing);
+    let err = run(&spec, 1, None).err().unwrap();
+    assert!(format!("{err:?}").contains("guest \"missing\" is not registered"));
+}
+
+// The methods crate implements InputSchema for the guest's copy of this crate,
+// not for the one under test.
+impl InputSchema for CheckedPoint {
+    const SCHEMA: &'static str = CHECKED_POINT_SCHEMA;
+}
+
+#[test]
+fn read_checked() {
+    struct OtherPoint;
+
+    impl Serialize for OtherPoint {
+        fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            (1u32, 2u32).serialize(serializer)
+        }
+    }
+
+    impl InputSchema for OtherPoint {
+        const SCHEMA: &'static str = "OtherPoint(u32, u32)";
+    }
+
+    let point = CheckedPoint { x: 1, y: 2 };
+    let env = ExecutorEnv::builder()
+        .write(&MultiTestSpec::ReadChecked)
+        .unwrap()
+        .write_checked(&point)
+        .unwrap()
+        .build()
+        .unwrap();
+    let session = ExecutorImpl::from_elf(env, MULTI_TEST_ELF)
+        .unwrap()
+        .run()
+        .unwrap();
+    assert_eq!(
+        session.journal.unwrap().decode::<CheckedPoint>().unwrap(),
+        point
+    );
+
+    // The same bytes under a different schema are refused before execution.
+    let env = ExecutorEnv::builder()
+        .write(&MultiTestSpec::ReadChecked)
+        .unwrap()
+        .write_checked(&OtherPoint)
+        .unwrap()
+        .build()
+        .unwrap();
+    let err = ExecutorImpl::from_elf(env, MULTI_TEST_ELF).err().unwrap();
+    let host = input_schema_digest(OtherPoint::SCHEMA);
+    let guest = input_schema_digest(CheckedPoint::SCHEMA);
+    assert_eq!(
+        err.to_string(),
+        format!("Input schema mismatch: host wrote {host} but the guest expects {guest}")
+    );
+
+    // Guests that do not declare a schema are refused as well.
+    let env = ExecutorEnv::builder()
+        .write_checked(&point)
+        .unwrap()
+        .build()
+        .unwrap();
+    let err = ExecutorImpl::from_elf(env, HELLO_COMMIT_ELF).err().unwrap();
+    assert!(err
+        .to_string()
+        .contains("the guest does not declare an input schema"));
+}
+
+#[test]
+fn commit_checked() {
+    use serde::Deserialize;
+
+    use crate::{InnerReceipt, Receipt, SchemaMismatch};
+
+    // The same words as a CheckedPoint, under a different schema.
+    #[derive(Deserialize, Debug)]
+    struct OtherPoint(u32, u32);
+
+    impl InputSchema for OtherPoint {
+        const SCHEMA: &'static str = "OtherPoint(u32, u32)";
+    }
+
+    let schema = Program::journal_schema(MULTI_TEST_ELF).unwrap().unwrap();
+    assert_eq!(schema.type_name, "CheckedPoint");
+    assert_eq!(schema.digest, input_schema_digest(CHECKED_POINT_SCHEMA));
+
+    let env = ExecutorEnv::builder()
+        .write(&MultiTestSpec::CommitChecked { x: 1, y: 2 })
+        .unwrap()
+        .build()
+        .unwrap();
+    let session = ExecutorImpl::from_elf(env, MULTI_TEST_ELF)
+        .unwrap()
+        .run()
+        .unwrap();
+    let receipt = Receipt::new(
+        InnerReceipt::Fake {
+            metadata: session.get_metadata().unwrap(),
+        },
+        session.journal.clone().unwrap().bytes,
+    );
+    assert_eq!(
+        receipt.journal_decode_checked::<CheckedPoint>().unwrap(),
+        CheckedPoint { x: 1, y: 2 }
+    );
+
+    let err = receipt.journal_decode_checked::<OtherPoint>().unwrap_err();
+    assert_eq!(
+        err.downcast::<SchemaMismatch>().unwrap(),
+        SchemaMismatch {
+            expected: input_schema_digest(OtherPoint::SCHEMA),
+            journal: schema.digest,
+        }
+    );
+
+    // A journal committed without the schema digest is refused.
+    let receipt = Receipt::new(
+        receipt.inner,
+        to_vec(&(1u32, 2u32))
+            .unwrap()
+            .iter()
+            .flat_map(|w| w.to_le_bytes())
+            .collect(),
+    );
+    assert!(receipt.journal_decode_checked::<CheckedPoint>().is_err());
+}
+
+#[test]
+fn write_framed() {
+    let output = FramedOutput::new();
+    let env = ExecutorEnv::builder()
+        .write(&MultiTestSpec::WriteFramed { count: 3 })
+        .unwrap()
+        .stdout_framed(&output)
+        .build()
+        .unwrap();
+    ExecutorImpl::from_elf(env, MULTI_TEST_ELF)
+        .unwrap()
+        .run()
+        .unwrap();
+
+    let (header, records): (FramedHeader, Vec<FramedRecord>) = output.decode().unwrap();
+    assert_eq!(
+        header,
+        FramedHeader {
+            version: 1,
+            count: 3
+        }
+    );
+    assert_eq!(
+        records.iter().map(|x| x.name.as_str()).collect::<Vec<_>>(),
+        ["record 0", "record 1", "record 2"]
+    );
+
+    // The number of slots must match the number of frames.
+    let err = output.decode::<(FramedHeader,)>().err().unwrap();
+    assert_eq!(err.to_string(), "Guest wrote 2 frames but 1 were expected");
+    let err = output
+        .decode::<(FramedHeader, Vec<FramedRecord>, u32)>()
+        .err()
+        .unwrap();
+    assert_eq!(err.to_string(), "Guest wrote 2 frames but 3 were expected");
+}
+
 #[test]
 fn libm_build() {
     run_test(MultiTestSpec::LibM);
 }
 
+#[test]
+fn symbol_stats() {
+    let mut stats = SymbolStats::new(MULTI_TEST_ELF).unwrap();
+    assert!(stats.analysis().has_soft_float());
+
+    let spec = MultiTestSpec::FormatFloat {
+        numerator: 22,
+        denominator: 7,
+    };
+    let env = ExecutorEnv::builder()
+        .write(&spec)
+        .unwrap()
+        .trace_callback(&mut stats)
+        .build()
+        .unwrap();
+    let session = ExecutorImpl::from_elf(env, MULTI_TEST_ELF)
+        .unwrap()
+        .run()
+        .unwrap();
+    let value: String = session.journal.unwrap().decode().unwrap();
+    assert_eq!(value, "3.143");
+
+    assert!(stats.total_cycles(SymbolKind::SoftFloat) > 0);
+    assert!(stats.total_cycles(SymbolKind::Formatting) > 0);
+    assert!(stats.cycles().any(|(sym, _)| sym.name == "__divdf3"));
+}
+
+#[test]
+fn cycle_attributor() {
+    let mut attributor = CycleAttributor::new(MULTI_TEST_ELF).unwrap();
+    let env = ExecutorEnv::builder()
+        .write(&MultiTestSpec::Multiply { iterations: 20_000 })
+        .unwrap()
+        .trace_callback(&mut attributor)
+        .build()
+        .unwrap();
+    ExecutorImpl::from_elf(env, MULTI_TEST_ELF)
+        .unwrap()
+        .run()
+        .unwrap();
+
+    let report = attributor.report(3);
+    assert_eq!(report.len(), 3);
+    assert!(report[0].name.contains("multiply"), "{report:?}");
+    assert!(report[0].percent > 50.0, "{report:?}");
+    assert!(report.windows(2).all(|x| x[0].cycles >= x[1].cycles));
+    assert!(report.iter().map(|x| x.cycles).sum::<u64>() <= attributor.total_cycles());
+}
+
+#[test]
+fn cycle_attributor_stripped() {
+    // Drop the section headers, and with them the symbol table. Loading only
+    // needs the program headers, so the guest still runs.
+    let mut elf = MULTI_TEST_ELF.to_vec();
+    elf[0x20..0x24].fill(0); // e_shoff
+    elf[0x30..0x34].fill(0); // e_shnum, e_shstrndx
+    assert!(Program::functions(&elf).unwrap().is_empty());
+
+    let mut attributor = CycleAttributor::new(&elf).unwrap();
+    let env = ExecutorEnv::builder()
+        .write(&MultiTestSpec::Multiply { iterations: 20_000 })
+        .unwrap()
+        .trace_callback(&mut attributor)
+        .build()
+        .unwrap();
+    ExecutorImpl::from_elf(env, &elf).unwrap().run().unwrap();
+
+    let report = attributor.report(usize::MAX);
+    assert!(!report.is_empty());
+    assert!(
+        report.iter().all(|x| x.name.starts_with("0x")),
+        "{report:?}"
+    );
+    assert_eq!(
+        report.iter().map(|x| x.cycles).sum::<u64>(),
+        attributor.total_cycles()
+    );
+}
+
+#[test]
+fn trace_file() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("multiply.trace");
+    let mut events = Vec::new();
+    let env = ExecutorEnv::builder()
+        .write(&MultiTestSpec::Multiply { iterations: 20_000 })
+        .unwrap()
+        .segment_limit_po2(14)
+        .trace_file(&path)
+        .trace_callback(|event| {
+            events.push(event);
+            Ok(())
+        })
+        .build()
+        .unwrap();
+    let session = ExecutorImpl::from_elf(env, MULTI_TEST_ELF)
+        .unwrap()
+        .run()
+        .unwrap();
+    assert!(session.segments.len() > 1);
+
+    let records: Vec<TraceRecord> = TraceReader::open(&path)
+        .unwrap()
+        .collect::<Result<_>>()
+        .unwrap();
+    assert_eq!(
+        records.iter().map(|r| &r.event).collect::<Vec<_>>(),
+        events.iter().collect::<Vec<_>>()
+    );
+    assert_eq!(
+        records.last().unwrap().segment as usize,
+        session.segments.len() - 1
+    );
+
+    let cycle = 3 << 13;
+    let mut reader = TraceReader::open(&path)
+        .unwrap()
+        .with_kinds([TraceKind::Instruction]);
+    reader.seek_to_cycle(cycle).unwrap();
+    let record = reader.next().unwrap().unwrap();
+    let expected = records
+        .iter()
+        .find(|r| r.cycle >= cycle && TraceKind::of(&r.event) == TraceKind::Instruction)
+        .unwrap();
+    assert_eq!(&record, expected);
+    assert_eq!(record.segment, 1);
+}
+
 #[test]
 fn host_syscall() {
     let expected: Vec<Bytes> = vec![
@@ -142,12 +723,460 @@ fn host_syscall() {
     };
     let actual: Mutex<Vec<Bytes>> = Vec::new().into();
     let env = ExecutorEnv::builder()
-        .write(&input)
-        .unwrap()
-        .io_callback(SYS_MULTI_TEST, |buf| {
-            let mut actual = actual.lock().unwrap();
-            actual.push(buf);
-            Ok(expected[actual.len()].clone())
+        .write(&input)
+        .unwrap()
+        .io_callback(SYS_MULTI_TEST, |buf| {
+            let mut actual = actual.lock().unwrap();
+            actual.push(buf);
+            Ok(expected[actual.len()].clone())
+        })
+        .build()
+        .unwrap();
+    let session = ExecutorImpl::from_elf(env, MULTI_TEST_ELF)
+        .unwrap()
+        .run()
+        .unwrap();
+    assert_eq!(session.exit_code, ExitCode::Halted(0));
+    assert_eq!(*actual.lock().unwrap(), expected[..expected.len() - 1]);
+}
+
+// Runs MultiTestSpec::Syscall, in which the guest sends back each response
+// with its next request, and returns every request the host received.
+fn echo_slices<'a>(
+    count: usize,
+    seen: &'a RefCell<Vec<Bytes>>,
+    register: impl FnOnce(&mut ExecutorEnvBuilder<'a>),
+) -> Result<Vec<Bytes>> {
+    let mut builder = ExecutorEnv::builder();
+    builder
+        .write(&MultiTestSpec::Syscall {
+            count: count as u32,
+        })
+        .unwrap();
+    register(&mut builder);
+    let session = ExecutorImpl::from_elf(builder.build().unwrap(), MULTI_TEST_ELF)?.run()?;
+    assert_eq!(session.exit_code, ExitCode::Halted(0));
+    Ok(seen.take())
+}
+
+// Writes each response in pieces of at most 1000 bytes.
+struct ChunkedFill<'a> {
+    responses: &'a [Vec<u8>],
+    seen: &'a RefCell<Vec<Bytes>>,
+    pending: &'a [u8],
+}
+
+impl SliceIoFill for ChunkedFill<'_> {
+    fn request(&mut self, _syscall: &str, from_guest: Bytes) -> Result<usize> {
+        let mut seen = self.seen.borrow_mut();
+        seen.push(from_guest);
+        self.pending = self
+            .responses
+            .get(seen.len() - 1)
+            .map_or(&[], Vec::as_slice);
+        Ok(self.pending.len())
+    }
+
+    fn fill(&mut self, to_guest: &mut [u8]) -> Result<usize> {
+        for (chunk, dst) in self.pending.chunks(1000).zip(to_guest.chunks_mut(1000)) {
+            dst[..chunk.len()].copy_from_slice(chunk);
+        }
+        Ok(self.pending.len())
+    }
+}
+
+#[test]
+fn slice_io_fill() {
+    // Responses that are empty, shorter than a word, not a whole number of
+    // words, and large.
+    let responses: Vec<Vec<u8>> = [0, 3, 5, (1 << 20) + 3]
+        .into_iter()
+        .map(|len: usize| (0..len).map(|i| (i * 7 + len) as u8).collect())
+        .collect();
+    let response = |seen: &RefCell<Vec<Bytes>>, from_guest: Bytes| {
+        let mut seen = seen.borrow_mut();
+        seen.push(from_guest);
+        responses.get(seen.len() - 1).map_or(&[][..], Vec::as_slice)
+    };
+    let count = responses.len() + 1;
+
+    let seen = RefCell::default();
+    let owned = echo_slices(count, &seen, |builder| {
+        builder.io_callback(SYS_MULTI_TEST, |from_guest| {
+            Ok(Bytes::copy_from_slice(response(&seen, from_guest)))
+        });
+    })
+    .unwrap();
+    assert_eq!(owned[0], Bytes::new());
+    assert_eq!(owned[1..], responses);
+
+    let borrowed = echo_slices(count, &seen, |builder| {
+        builder.io_callback_borrowed(SYS_MULTI_TEST, |from_guest| {
+            Ok(Cow::Borrowed(response(&seen, from_guest)))
+        });
+    })
+    .unwrap();
+    assert_eq!(borrowed, owned);
+
+    let filled = echo_slices(count, &seen, |builder| {
+        builder.slice_io_fill(
+            SYS_MULTI_TEST,
+            ChunkedFill {
+                responses: &responses,
+                seen: &seen,
+                pending: &[],
+            },
+        );
+    })
+    .unwrap();
+    assert_eq!(filled, owned);
+
+    // A handler must write the length it promised.
+    struct ShortFill;
+
+    impl SliceIoFill for ShortFill {
+        fn request(&mut self, _syscall: &str, _from_guest: Bytes) -> Result<usize> {
+            Ok(8)
+        }
+
+        fn fill(&mut self, _to_guest: &mut [u8]) -> Result<usize> {
+            Ok(4)
+        }
+    }
+
+    let err = echo_slices(1, &seen, |builder| {
+        builder.slice_io_fill(SYS_MULTI_TEST, ShortFill);
+    })
+    .unwrap_err();
+    assert!(format!("{err:?}").contains("wrote 4 bytes of a 8 byte response"));
+}
+
+// Make sure panics in the callback get propagated correctly.
+#[test]
+#[should_panic(expected = "I am panicking from here!")]
+fn host_syscall_callback_panic() {
+    let env = ExecutorEnv::builder()
+        .write(&MultiTestSpec::Syscall { count: 5 })
+        .unwrap()
+        .io_callback(SYS_MULTI_TEST, |_| {
+            panic!("I am panicking from here!");
+        })
+        .build()
+        .unwrap();
+    let session = ExecutorImpl::from_elf(env, MULTI_TEST_ELF)
+        .unwrap()
+        .run()
+        .unwrap();
+    assert_eq!(session.exit_code, ExitCode::Halted(0));
+}
+
+#[test]
+fn sha_accel() {
+    run_test(MultiTestSpec::ShaConforms);
+}
+
+#[test]
+fn sha_cycle_count() {
+    run_test(MultiTestSpec::ShaCycleCount);
+}
+
+#[test]
+fn rsa_compat() {
+    run_test(MultiTestSpec::RsaCompat);
+}
+
+#[test]
+fn bigint_accel() {
+    let cases = testutils::generate_bigint_test_cases(&mut rand::thread_rng(), 10);
+    for case in cases {
+        println!("Running BigInt circuit test case: {:x?}", case);
+        let input = MultiTestSpec::BigInt {
+            x: case.x,
+            y: case.y,
+            modulus: case.modulus,
+        };
+
+        let env = ExecutorEnv::builder()
+            .write(&input)
+            .unwrap()
+            .build()
+            .unwrap();
+        let mut exec = ExecutorImpl::from_elf(env, MULTI_TEST_ELF).unwrap();
+        let session = exec.run().unwrap();
+        assert_eq!(session.exit_code, ExitCode::Halted(0));
+        assert_eq!(
+            session.journal.unwrap().bytes.as_slice(),
+            bytemuck::cast_slice::<u32, u8>(case.expected().as_slice())
+        );
+    }
+}
+
+fn bigint_modexp_case() -> (MultiTestSpec, Vec<u32>) {
+    // 2048-bit operands with an RSA-style public exponent.
+    let mut modulus: Vec<u32> = (0..64).map(|_| rand::random()).collect();
+    modulus[63] |= 0x8000_0000;
+    let base: Vec<u32> = (0..64).map(|_| rand::random()).collect();
+    let exponent = vec![65537];
+
+    let expected = BigUint::from_slice(&base).modpow(
+        &BigUint::from_slice(&exponent),
+        &BigUint::from_slice(&modulus),
+    );
+    let mut expected = expected.to_u32_digits();
+    expected.resize(modulus.len(), 0);
+
+    let spec = MultiTestSpec::BigIntModExp {
+        base,
+        exponent,
+        modulus,
+    };
+    (spec, expected)
+}
+
+#[test]
+fn bigint_modexp() {
+    let (spec, expected) = bigint_modexp_case();
+    let env = ExecutorEnv::builder()
+        .write(&spec)
+        .unwrap()
+        .bigint_accel(true)
+        .build()
+        .unwrap();
+    let session = ExecutorImpl::from_elf(env, MULTI_TEST_ELF)
+        .unwrap()
+        .run()
+        .unwrap();
+    assert_eq!(session.exit_code, ExitCode::Halted(0));
+    assert_eq!(
+        session.journal.unwrap().bytes.as_slice(),
+        bytemuck::cast_slice::<u32, u8>(expected.as_slice())
+    );
+
+    // The syscall is off unless enabled.
+    let (spec, _) = bigint_modexp_case();
+    let env = ExecutorEnv::builder()
+        .write(&spec)
+        .unwrap()
+        .build()
+        .unwrap();
+    let err = ExecutorImpl::from_elf(env, MULTI_TEST_ELF)
+        .unwrap()
+        .run()
+        .err()
+        .unwrap();
+    assert!(
+        format!("{err:#}").contains("SYS_BIGINT_MODMUL is not enabled"),
+        "{err:#}"
+    );
+}
+
+#[test]
+fn bigint_modexp_malicious_host() {
+    // A host that returns a remainder that is off by one, with a quotient that
+    // is otherwise correct, must be caught by the guest.
+    let (spec, _) = bigint_modexp_case();
+    let env = ExecutorEnv::builder()
+        .write(&spec)
+        .unwrap()
+        .override_builtin(SYS_BIGINT_MODMUL)
+        .io_callback(SYS_BIGINT_MODMUL, |from_guest| {
+            let n = from_guest.len() / (3 * WORD_SIZE);
+            let mut operands = from_guest
+                .chunks_exact(n * WORD_SIZE)
+                .map(BigUint::from_bytes_le);
+            let a = operands.next().unwrap();
+            let b = operands.next().unwrap();
+            let modulus = operands.next().unwrap();
+            let product = a * b;
+            let remainder = (&product % &modulus + 1u32) % &modulus;
+
+            let mut to_guest = Vec::new();
+            for (value, limbs) in [(&product / &modulus, 2 * n), (remainder, n)] {
+                let mut bytes = value.to_bytes_le();
+                bytes.resize(limbs * WORD_SIZE, 0);
+                to_guest.extend_from_slice(&bytes);
+            }
+            Ok(to_guest.into())
+        })
+        .build()
+        .unwrap();
+    let err = ExecutorImpl::from_elf(env, MULTI_TEST_ELF)
+        .unwrap()
+        .run()
+        .err()
+        .unwrap();
+    assert!(err
+        .to_string()
+        .contains("host returned an incorrect bigint modmul result"));
+}
+
+#[test]
+fn env_stdio() {
+    const MSG: &str = "Hello world!  This is a test of standard input and output.";
+    const FD: u32 = 123;
+    let spec = to_vec(&MultiTestSpec::EchoStdout { nbytes: 9, fd: FD }).unwrap();
+    let mut stdout: Vec<u8> = Vec::new();
+    {
+        let env = ExecutorEnv::builder()
+            .read_fd(FD, MSG.as_bytes())
+            .stdin(bytemuck::cast_slice(&spec))
+            .stdout(&mut stdout)
+            .build()
+            .unwrap();
+        let session = ExecutorImpl::from_elf(env, MULTI_TEST_ELF)
+            .unwrap()
+            .run()
+            .unwrap();
+        assert_eq!(session.exit_code, ExitCode::Halted(0));
+    }
+    assert_eq!(MSG, from_utf8(&stdout).unwrap());
+}
+
+// Every byte value, and the line endings and end-of-file marker that a
+// Windows host reading in text mode would translate.
+fn stdin_bytes() -> Vec<u8> {
+    (0..=255u8).chain(*b"\r\n\r\x1a\n").collect()
+}
+
+// Run a guest that echoes `stdin`, after the spec it reads from it, to
+// standard output.
+fn echo_stdin(stdin: impl std::io::Read) -> Vec<u8> {
+    let mut stdout = Vec::new();
+    {
+        let env = ExecutorEnv::builder()
+            .stdin(stdin)
+            .stdout(&mut stdout)
+            .build()
+            .unwrap();
+        let session = ExecutorImpl::from_elf(env, MULTI_TEST_ELF)
+            .unwrap()
+            .run()
+            .unwrap();
+        assert_eq!(session.exit_code, ExitCode::Halted(0));
+    }
+    stdout
+}
+
+fn echo_spec() -> Vec<u8> {
+    let spec = to_vec(&MultiTestSpec::EchoStdout {
+        nbytes: 7,
+        fd: fileno::STDIN,
+    })
+    .unwrap();
+    bytemuck::cast_slice(&spec).to_vec()
+}
+
+#[test]
+fn stdin_bytes_unchanged() {
+    let stdin = [echo_spec(), stdin_bytes()].concat();
+    assert_eq!(echo_stdin(stdin.as_slice()), stdin_bytes());
+}
+
+// Write the input for `echo_stdin` to a file under a path joined with
+// `separator`, and echo it from the file.
+fn file_backed_stdin(separator: char) {
+    let tmp = tempfile::tempdir().unwrap();
+    let dir = tmp.path().to_str().unwrap();
+    let path = format!("{dir}{separator}inputs{separator}stdin.bin");
+    std::fs::create_dir(format!("{dir}{separator}inputs")).unwrap();
+    std::fs::write(&path, [echo_spec(), stdin_bytes()].concat()).unwrap();
+    let file = std::fs::File::open(&path).unwrap();
+    assert_eq!(echo_stdin(file), stdin_bytes());
+}
+
+#[cfg(windows)]
+#[test]
+fn file_backed_stdin_windows() {
+    file_backed_stdin('\\');
+    file_backed_stdin('/');
+}
+
+#[cfg(unix)]
+#[test]
+fn file_backed_stdin_unix() {
+    file_backed_stdin('/');
+}
+
+#[test]
+fn stdout_lines() {
+    const FD: u32 = 123;
+    // The guest writes 5 bytes at a time, so the first line takes three writes.
+    const MSG: &[u8] = b"first line\ninvalid \xff!\nno newline";
+    let spec = to_vec(&MultiTestSpec::EchoStdout { nbytes: 5, fd: FD }).unwrap();
+    let mut lines = Vec::new();
+    {
+        let env = ExecutorEnv::builder()
+            .read_fd(FD, MSG)
+            .stdin(bytemuck::cast_slice(&spec))
+            .stdout_lines(|line| lines.push(line.to_string()))
+            .build()
+            .unwrap();
+        let session = ExecutorImpl::from_elf(env, MULTI_TEST_ELF)
+            .unwrap()
+            .run()
+            .unwrap();
+        assert_eq!(session.exit_code, ExitCode::Halted(0));
+    }
+    assert_eq!(lines, ["first line", "invalid \u{fffd}!", "no newline"]);
+}
+
+#[test]
+fn log_limits() {
+    let run = |max_messages: u64, max_total_bytes: u64, policy: LogPolicy| {
+        let mut out = Vec::new();
+        let result = {
+            let env = ExecutorEnv::builder()
+                .write(&MultiTestSpec::LogMany { count: 10_000 })
+                .unwrap()
+                .stdout(&mut out)
+                .log_limits(max_messages, max_total_bytes, policy)
+                .build()
+                .unwrap();
+            ExecutorImpl::from_elf(env, MULTI_TEST_ELF)
+                .unwrap()
+                .run()
+                .map(|session| session.exit_code)
+        };
+        (result, String::from_utf8(out).unwrap())
+    };
+
+    let (result, out) = run(100, u64::MAX, LogPolicy::Drop);
+    assert_eq!(result.unwrap(), ExitCode::Halted(0));
+    let lines: Vec<_> = out.lines().collect();
+    assert_eq!(lines.len(), 101);
+    assert!(lines[99].ends_with("] message 99"));
+    assert_eq!(lines[100], "R0VM: 9900 log messages dropped");
+
+    // Each of the first ten messages is 10 bytes long.
+    let (result, out) = run(u64::MAX, 95, LogPolicy::Drop);
+    assert_eq!(result.unwrap(), ExitCode::Halted(0));
+    assert_eq!(out.lines().count(), 10);
+    assert!(out.ends_with("R0VM: 9991 log messages dropped\n"));
+
+    let (result, out) = run(100, u64::MAX, LogPolicy::Error);
+    let err = result.unwrap_err();
+    assert!(matches!(
+        err.downcast_ref::<GuestIoError>(),
+        Some(GuestIoError::LogLimitExceeded {
+            max_messages: 100,
+            ..
+        })
+    ));
+    assert_eq!(out.lines().count(), 100);
+}
+
+#[test]
+fn lazy_fds() {
+    const MSG: &str = "Hello from a lazy reader!";
+    const FD: u32 = 123;
+    let spec = to_vec(&MultiTestSpec::EchoStdout { nbytes: 9, fd: FD }).unwrap();
+    let dir = tempfile::tempdir().unwrap();
+    let env = ExecutorEnv::builder()
+        .stdin(bytemuck::cast_slice(&spec))
+        .lazy_read_fd(FD, |_| Ok(Box::new(MSG.as_bytes())))
+        .lazy_write_fd(fileno::STDOUT, |ctx| {
+            let path = dir.path().join(format!("{}.out", ctx.image_id()));
+            Ok(Box::new(std::fs::File::create(path)?))
         })
         .build()
         .unwrap();
@@ -156,89 +1185,493 @@ fn host_syscall() {
         .run()
         .unwrap();
     assert_eq!(session.exit_code, ExitCode::Halted(0));
-    assert_eq!(*actual.lock().unwrap(), expected[..expected.len() - 1]);
-}
 
-// Make sure panics in the callback get propagated correctly.
-#[test]
-#[should_panic(expected = "I am panicking from here!")]
-fn host_syscall_callback_panic() {
+    let path = dir
+        .path()
+        .join(format!("{}.out", Digest::from(MULTI_TEST_ID)));
+    assert_eq!(std::fs::read_to_string(path).unwrap(), MSG);
+
+    // Factories that fail or panic fail the run.
+    let run = |env: ExecutorEnv| {
+        ExecutorImpl::from_elf(env, MULTI_TEST_ELF)
+            .unwrap()
+            .run()
+            .err()
+            .unwrap()
+            .to_string()
+    };
     let env = ExecutorEnv::builder()
-        .write(&MultiTestSpec::Syscall { count: 5 })
-        .unwrap()
-        .io_callback(SYS_MULTI_TEST, |_| {
-            panic!("I am panicking from here!");
-        })
+        .stdin(bytemuck::cast_slice(&spec))
+        .lazy_read_fd(FD, |_| Err(anyhow::anyhow!("no input yet")))
         .build()
         .unwrap();
-    let session = ExecutorImpl::from_elf(env, MULTI_TEST_ELF)
-        .unwrap()
-        .run()
+    assert_eq!(run(env), "the factory for lazy read fd 123 failed");
+    let env = ExecutorEnv::builder()
+        .stdin(bytemuck::cast_slice(&spec))
+        .lazy_write_fd(fileno::STDOUT, |_| panic!("no output yet"))
+        .build()
         .unwrap();
-    assert_eq!(session.exit_code, ExitCode::Halted(0));
+    assert_eq!(run(env), "the factory for lazy write fd 1 panicked");
+
+    let err = ExecutorEnv::builder()
+        .lazy_write_fd(fileno::JOURNAL, |_| Ok(Box::new(Vec::new())))
+        .build()
+        .err()
+        .unwrap();
+    assert!(err.to_string().contains("journal file descriptor"));
 }
 
 #[test]
-fn sha_accel() {
-    run_test(MultiTestSpec::ShaConforms);
+fn fd_direction_conflicts() {
+    let err = |builder: &mut ExecutorEnvBuilder| builder.build().err().unwrap().to_string();
+    assert_eq!(
+        err(ExecutorEnv::builder()
+            .read_fd(5, b"".as_slice())
+            .write_fd(5, Vec::new())),
+        "file descriptor 5 is registered for both reading and writing; use duplex_fd if this is intended"
+    );
+    // Standard output is registered for writing by default, and the journal
+    // is always written to.
+    assert!(
+        err(ExecutorEnv::builder().read_fd(fileno::STDOUT, b"".as_slice()))
+            .contains("file descriptor 1 is registered")
+    );
+    assert!(
+        err(ExecutorEnv::builder().read_fd(fileno::JOURNAL, b"".as_slice()))
+            .contains("file descriptor 3 is registered")
+    );
+    assert!(err(ExecutorEnv::builder()
+        .read_fd(5, b"".as_slice())
+        .lazy_write_fd(5, |_| Ok(Box::new(Vec::new()))))
+    .contains("file descriptor 5 is registered"));
+
+    // The guest reads from and writes to the same descriptor.
+    const MSG: &str = "Hello from both directions!";
+    let spec = to_vec(&MultiTestSpec::EchoStdout {
+        nbytes: 9,
+        fd: fileno::STDOUT,
+    })
+    .unwrap();
+    let mut stdout: Vec<u8> = Vec::new();
+    {
+        let env = ExecutorEnv::builder()
+            .stdin(bytemuck::cast_slice(&spec))
+            .duplex_fd(fileno::STDOUT, MSG.as_bytes(), &mut stdout)
+            .build()
+            .unwrap();
+        let session = ExecutorImpl::from_elf(env, MULTI_TEST_ELF)
+            .unwrap()
+            .run()
+            .unwrap();
+        assert_eq!(session.exit_code, ExitCode::Halted(0));
+    }
+    assert_eq!(MSG, from_utf8(&stdout).unwrap());
 }
 
-#[test]
-fn sha_cycle_count() {
-    run_test(MultiTestSpec::ShaCycleCount);
+// A writer that accepts one byte per call, like a slow socket.
+#[derive(Default)]
+struct Throttled(Vec<u8>);
+
+impl std::io::Write for Throttled {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.extend(buf.first());
+        Ok(buf.len().min(1))
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
 }
 
 #[test]
-fn rsa_compat() {
-    run_test(MultiTestSpec::RsaCompat);
+fn write_fd_buffered() {
+    const MSG: &str = "Output that the guest writes faster than the host.";
+    const FD: u32 = 123;
+    let spec = to_vec(&MultiTestSpec::EchoStdout { nbytes: 9, fd: FD }).unwrap();
+    let run = |capacity, policy| -> (Result<Session>, Vec<u8>) {
+        let mut stdout = Throttled::default();
+        let result = ExecutorEnv::builder()
+            .read_fd(FD, MSG.as_bytes())
+            .stdin(bytemuck::cast_slice(&spec))
+            .write_fd_buffered(fileno::STDOUT, &mut stdout, capacity, policy)
+            .build()
+            .and_then(|env| ExecutorImpl::from_elf(env, MULTI_TEST_ELF)?.run());
+        (result, stdout.0)
+    };
+
+    // The guest writes 9 bytes at a time, which a buffer of 4 bytes cannot
+    // hold at all.
+    for capacity in [4, 16, 64] {
+        let (result, stdout) = run(capacity, OverflowPolicy::Block);
+        assert_eq!(result.unwrap().exit_code, ExitCode::Halted(0));
+        assert_eq!(from_utf8(&stdout).unwrap(), MSG);
+    }
+
+    let (result, stdout) = run(64, OverflowPolicy::Error);
+    assert_eq!(result.unwrap().exit_code, ExitCode::Halted(0));
+    assert_eq!(from_utf8(&stdout).unwrap(), MSG);
+
+    // The second write overflows the buffer, and the first is still
+    // delivered.
+    let (result, stdout) = run(16, OverflowPolicy::Error);
+    let err = result.err().unwrap();
+    let GuestIoError::BackpressureExceeded {
+        fd,
+        capacity,
+        cycle,
+    } = *err.downcast_ref::<GuestIoError>().unwrap()
+    else {
+        panic!("unexpected error: {err}");
+    };
+    assert_eq!((fd, capacity), (fileno::STDOUT, 16));
+    assert!(cycle > 0);
+    assert_eq!(from_utf8(&stdout).unwrap(), &MSG[..9]);
+
+    let (result, stdout) = run(16, OverflowPolicy::DropOldest);
+    assert_eq!(result.unwrap().exit_code, ExitCode::Halted(0));
+    assert_eq!(from_utf8(&stdout).unwrap(), &MSG[MSG.len() - 16..]);
+
+    let err = ExecutorEnv::builder()
+        .write_fd_buffered(fileno::JOURNAL, Vec::new(), 16, OverflowPolicy::DropOldest)
+        .build()
+        .err()
+        .unwrap();
+    assert!(err.to_string().contains("journal"));
+}
+
+// A non-blocking writer that accepts 7 bytes per call, and is intermittently
+// not ready or interrupted, until it has accepted `stuck_after` bytes, after
+// which it is never ready again.
+struct Choppy {
+    out: Vec<u8>,
+    calls: u32,
+    stuck_after: usize,
+}
+
+impl std::io::Write for Choppy {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.calls += 1;
+        if self.out.len() >= self.stuck_after || self.calls % 4 == 1 {
+            return Err(std::io::ErrorKind::WouldBlock.into());
+        }
+        if self.calls % 4 == 3 {
+            return Err(std::io::ErrorKind::Interrupted.into());
+        }
+        let n = buf.len().min(7);
+        self.out.extend(&buf[..n]);
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
 }
 
 #[test]
-fn bigint_accel() {
-    let cases = testutils::generate_bigint_test_cases(&mut rand::thread_rng(), 10);
-    for case in cases {
-        println!("Running BigInt circuit test case: {:x?}", case);
-        let input = MultiTestSpec::BigInt {
-            x: case.x,
-            y: case.y,
-            modulus: case.modulus,
+fn write_backoff() {
+    const MSG: &str = "Output that a non-blocking socket takes a few bytes at a time.";
+    const FD: u32 = 123;
+    let spec = to_vec(&MultiTestSpec::EchoStdout { nbytes: 20, fd: FD }).unwrap();
+    let run = |stuck_after| -> (Result<Session>, Vec<u8>) {
+        let mut stdout = Choppy {
+            out: Vec::new(),
+            calls: 0,
+            stuck_after,
         };
+        let result = ExecutorEnv::builder()
+            .read_fd(FD, MSG.as_bytes())
+            .stdin(bytemuck::cast_slice(&spec))
+            .stdout(&mut stdout)
+            .write_backoff(WriteBackoff {
+                initial: Duration::from_micros(10),
+                max: Duration::from_millis(1),
+                deadline: Duration::from_millis(50),
+            })
+            .build()
+            .and_then(|env| ExecutorImpl::from_elf(env, MULTI_TEST_ELF)?.run());
+        (result, stdout.out)
+    };
 
-        let env = ExecutorEnv::builder()
-            .write(&input)
+    // Every write of 20 bytes takes several calls, some of which are not
+    // accepted, and all of it is delivered.
+    let (result, stdout) = run(usize::MAX);
+    assert_eq!(result.unwrap().exit_code, ExitCode::Halted(0));
+    assert_eq!(from_utf8(&stdout).unwrap(), MSG);
+
+    // A writer that is not ready past the deadline fails the run, with the
+    // part of the write that was accepted.
+    let (result, stdout) = run(30);
+    let err = result.err().unwrap();
+    let GuestIoError::WriteTimedOut {
+        fd,
+        written,
+        len,
+        cycle,
+    } = *err.downcast_ref::<GuestIoError>().unwrap()
+    else {
+        panic!("unexpected error: {err}");
+    };
+    assert_eq!((fd, written, len), (fileno::STDOUT, 14, 20));
+    assert!(cycle > 0);
+    assert_eq!(from_utf8(&stdout).unwrap(), &MSG[..34]);
+}
+
+#[test]
+fn output_size_hints() {
+    const BYTES: usize = 1000;
+    let run = |fd, hint, strict| -> (Result<Session>, Vec<u8>) {
+        let mut stdout = Vec::new();
+        let (journal_hint, stdout_hint) = match fd {
+            fileno::JOURNAL => (hint, usize::MAX),
+            _ => (usize::MAX, hint),
+        };
+        let result = ExecutorEnv::builder()
+            .write(&MultiTestSpec::WriteZeros {
+                fd,
+                bytes: BYTES as u32,
+                chunk: 300,
+            })
             .unwrap()
+            .stdout(&mut stdout)
+            .output_size_hints(journal_hint, stdout_hint, strict)
             .build()
-            .unwrap();
-        let mut exec = ExecutorImpl::from_elf(env, MULTI_TEST_ELF).unwrap();
-        let session = exec.run().unwrap();
-        assert_eq!(session.exit_code, ExitCode::Halted(0));
-        assert_eq!(
-            session.journal.unwrap().bytes.as_slice(),
-            bytemuck::cast_slice::<u32, u8>(case.expected().as_slice())
-        );
+            .and_then(|env| ExecutorImpl::from_elf(env, MULTI_TEST_ELF)?.run());
+        (result, stdout)
+    };
+    let output = |fd, session: Session, stdout: Vec<u8>| match fd {
+        fileno::JOURNAL => session.journal.unwrap().bytes,
+        _ => stdout,
+    };
+
+    for fd in [fileno::JOURNAL, fileno::STDOUT] {
+        // Wrong hints do not change the output unless they are strict.
+        for hint in [BYTES - 1, BYTES, 2 * BYTES] {
+            let (result, stdout) = run(fd, hint, false);
+            assert_eq!(output(fd, result.unwrap(), stdout), [0; BYTES]);
+        }
+        for hint in [BYTES, 2 * BYTES] {
+            let (result, stdout) = run(fd, hint, true);
+            assert_eq!(output(fd, result.unwrap(), stdout), [0; BYTES]);
+        }
+
+        // The last write, of 100 bytes, is past a strict hint, and is not
+        // made.
+        let (result, stdout) = run(fd, BYTES - 1, true);
+        let err = result.err().unwrap();
+        let GuestIoError::OutputSizeExceeded {
+            fd: err_fd,
+            hint,
+            len,
+            cycle,
+        } = *err.downcast_ref::<GuestIoError>().unwrap()
+        else {
+            panic!("unexpected error: {err}");
+        };
+        assert_eq!((err_fd, hint, len), (fd, BYTES - 1, BYTES));
+        assert!(cycle > 0);
+        if fd == fileno::STDOUT {
+            assert_eq!(stdout, [0; 900]);
+        }
     }
 }
 
 #[test]
-fn env_stdio() {
-    const MSG: &str = "Hello world!  This is a test of standard input and output.";
+fn syscall_audit() {
+    const MSG: &str = "Every read and write is logged.";
     const FD: u32 = 123;
     let spec = to_vec(&MultiTestSpec::EchoStdout { nbytes: 9, fd: FD }).unwrap();
-    let mut stdout: Vec<u8> = Vec::new();
-    {
+    let mut audit = Vec::new();
+    let session = {
         let env = ExecutorEnv::builder()
             .read_fd(FD, MSG.as_bytes())
             .stdin(bytemuck::cast_slice(&spec))
-            .stdout(&mut stdout)
+            .stdout(Vec::new())
+            .syscall_audit(&mut audit)
+            .segment_limit_po2(14)
             .build()
             .unwrap();
-        let session = ExecutorImpl::from_elf(env, MULTI_TEST_ELF)
+        ExecutorImpl::from_elf(env, MULTI_TEST_ELF)
             .unwrap()
             .run()
-            .unwrap();
-        assert_eq!(session.exit_code, ExitCode::Halted(0));
+            .unwrap()
+    };
+
+    let entries: Vec<serde_json::Value> = from_utf8(&audit)
+        .unwrap()
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect();
+    // Each syscall is logged at a user cycle within the span of its segment.
+    let mut start = 0;
+    let syscalls: Vec<_> = session
+        .resolve()
+        .unwrap()
+        .into_iter()
+        .flat_map(|segment| {
+            let span = start..start + segment.cycles as u64;
+            start = span.end;
+            segment.syscalls.into_iter().map(move |x| (x, span.clone()))
+        })
+        .collect();
+    assert_eq!(entries.len(), syscalls.len());
+    for (entry, (syscall, span)) in entries.iter().zip(syscalls.iter()) {
+        assert!(span.contains(&entry["cycle"].as_u64().unwrap()), "{entry}");
+        assert_eq!(
+            entry["to_guest"]["bytes"],
+            syscall.to_guest.len() * WORD_SIZE
+        );
+        let digest = Impl::hash_bytes(bytemuck::cast_slice(&syscall.to_guest));
+        assert_eq!(entry["to_guest"]["sha256"], digest.to_string());
     }
-    assert_eq!(MSG, from_utf8(&stdout).unwrap());
+    assert!(entries
+        .windows(2)
+        .all(|pair| pair[0]["cycle"].as_u64() <= pair[1]["cycle"].as_u64()));
+
+    // The guest writes back everything it reads.
+    let from_guest = |name: SyscallName| -> u64 {
+        entries
+            .iter()
+            .filter(|entry| entry["syscall"] == name.as_str())
+            .map(|entry| entry["from_guest"]["bytes"].as_u64().unwrap())
+            .sum()
+    };
+    assert_eq!(from_guest(SYS_WRITE), MSG.len() as u64);
+    assert!(entries
+        .iter()
+        .any(|entry| entry["syscall"] == SYS_READ.as_str()));
+}
+
+#[test]
+fn determinism_check() {
+    let spec = MultiTestSpec::Syscall { count: 3 };
+    let prover = LocalProver::new("local");
+
+    let report = prover
+        .run_determinism_check(
+            3,
+            &mut || {
+                ExecutorEnv::builder()
+                    .write(&spec)?
+                    .io_callback(SYS_MULTI_TEST, |_| Ok(Bytes::from_static(b"same")))
+                    .build()
+            },
+            MULTI_TEST_ELF,
+        )
+        .unwrap();
+    assert!(report.is_deterministic());
+    assert_eq!(report.digests.len(), 3);
+    assert!(report.digests.windows(2).all(|pair| pair[0] == pair[1]));
+
+    // The second answer of this handler depends on how many runs came before
+    // it, as if it leaked state kept by the host.
+    let runs = Cell::new(0u8);
+    let report = prover
+        .run_determinism_check(
+            3,
+            &mut || {
+                runs.set(runs.get() + 1);
+                let run = runs.get();
+                let calls = Cell::new(0);
+                ExecutorEnv::builder()
+                    .write(&spec)?
+                    .io_callback(SYS_MULTI_TEST, move |_| {
+                        calls.set(calls.get() + 1);
+                        let reply = if calls.get() == 2 { run } else { 0 };
+                        Ok(Bytes::from(vec![reply]))
+                    })
+                    .build()
+            },
+            MULTI_TEST_ELF,
+        )
+        .unwrap();
+    assert_eq!(report.digests.len(), 2);
+    assert_ne!(report.digests[0], report.digests[1]);
+    let divergence = report.divergence.unwrap();
+    assert_eq!(divergence.run, 1);
+    assert_eq!(divergence.syscall.as_deref(), Some(SYS_MULTI_TEST.as_str()));
+    assert!(divergence.cycle.is_some());
+    assert!(
+        divergence.reason.contains("host returned different data"),
+        "{divergence}"
+    );
+
+    let err = prover
+        .run_determinism_check(1, &mut || ExecutorEnv::builder().build(), MULTI_TEST_ELF)
+        .unwrap_err();
+    assert!(err.to_string().contains("at least 2 runs"));
+}
+
+#[test]
+fn compare_guests() {
+    let inputs = || {
+        [b"abc".to_vec(), vec![0; 100]].map(|data| {
+            let words = to_vec(&MultiTestSpec::ShaDigest { data }).unwrap();
+            bytemuck::cast_slice::<u32, u8>(&words).to_vec()
+        })
+    };
+    let mut make_env = |input: &[u8]| ExecutorEnv::builder().write_bytes(input).build();
+    let opts = CompareOpts::default();
+
+    let diffs = crate::compare_guests(
+        MULTI_TEST_ELF,
+        MULTI_TEST_ELF,
+        inputs(),
+        &mut make_env,
+        &opts,
+    )
+    .unwrap();
+    assert_eq!(diffs, vec![]);
+
+    let diffs = crate::compare_guests(
+        MULTI_TEST_ELF,
+        HELLO_COMMIT_ELF,
+        inputs(),
+        &mut make_env,
+        &opts.with_cycle_tolerance(u64::MAX),
+    )
+    .unwrap();
+    assert_eq!(diffs.len(), 2);
+    for (idx, diff) in diffs.iter().enumerate() {
+        assert_eq!(diff.input, idx);
+        let (a, b) = diff.journals.as_ref().unwrap();
+        assert_eq!(a.len(), DIGEST_BYTES);
+        assert_ne!(a, b);
+        assert!(diff.journal_offset().unwrap() < a.len());
+        assert_eq!(diff.exit_codes, None);
+        assert!(diff.syscall.is_some());
+    }
+}
+
+#[test]
+fn recommend_segment_po2() {
+    // A working set of 64 pages, swept over and over, takes more cycles to
+    // page in and out than to sweep unless a segment holds several sweeps.
+    let env = ExecutorEnv::builder()
+        .write(&MultiTestSpec::SweepMemory {
+            bytes: 64 * PAGE_SIZE as u32,
+            passes: 16,
+        })
+        .unwrap()
+        .segment_limit_po2(16)
+        .build()
+        .unwrap();
+    let report = LocalProver::new("local")
+        .recommend_segment_po2(env, MULTI_TEST_ELF)
+        .unwrap();
+
+    let smallest = &report.estimates[0];
+    assert!(smallest.paging_overhead() > 0.5, "{report:?}");
+    assert!(report.recommended_po2 > smallest.po2);
+    for estimate in &report.estimates {
+        let overhead = estimate.paging_overhead();
+        if estimate.po2 < report.recommended_po2 {
+            assert!(overhead > crate::MAX_PAGING_OVERHEAD, "{report:?}");
+        }
+        if estimate.po2 == report.recommended_po2 {
+            assert!(overhead <= crate::MAX_PAGING_OVERHEAD, "{report:?}");
+        }
+    }
+    assert_eq!(report.estimates.last().unwrap().segments, 1);
 }
 
 // Tests sys_read into a buffer of bytes that may not be word aligned.
@@ -345,8 +1778,211 @@ fn large_io_words() {
     let session = exec.run().unwrap();
     assert_eq!(session.exit_code, ExitCode::Halted(0));
 
-    let actual: &[u32] = bytemuck::cast_slice(&session.journal.as_ref().unwrap().bytes);
-    assert_eq!(actual, expected);
+    let actual: &[u32] = bytemuck::cast_slice(&session.journal.as_ref().unwrap().bytes);
+    assert_eq!(actual, expected);
+}
+
+#[test]
+fn input_buffer() {
+    const FD: u32 = 123;
+    let buf: Vec<u32> = (0..1000).collect();
+    let input = MultiTestSpec::EchoWords {
+        fd: FD,
+        nwords: buf.len() as u32,
+    };
+    let input: Arc<[u8]> = bytemuck::cast_slice(&to_vec(&input).unwrap()).into();
+    let env = ExecutorEnv::builder()
+        .read_fd(FD, bytemuck::cast_slice(&buf))
+        .input_buffer(input)
+        .build()
+        .unwrap();
+    let mut exec = ExecutorImpl::from_elf(env, MULTI_TEST_ELF).unwrap();
+    let session = exec.run().unwrap();
+    assert_eq!(session.exit_code, ExitCode::Halted(0));
+
+    let actual: &[u32] = bytemuck::cast_slice(&session.journal.as_ref().unwrap().bytes);
+    assert_eq!(actual, buf);
+}
+
+#[test]
+fn input_buffer_mixed_with_write() {
+    let err = ExecutorEnv::builder()
+        .write_words(&[1, 2, 3])
+        .input_buffer(vec![1u8, 2, 3])
+        .build()
+        .err()
+        .unwrap();
+    assert!(err
+        .to_string()
+        .contains("input_buffer cannot be combined with write or the other write_* methods"));
+}
+
+// The guest sees the same input whatever the byte order of the host.
+#[test]
+fn input_byte_order() {
+    // The words 1, 2 and 0x01020304 as a big-endian host holds them in memory,
+    // and as the little-endian guest must see them.
+    let be_memory = [0, 0, 0, 1, 0, 0, 0, 2, 1, 2, 3, 4];
+    let guest_bytes = [1, 0, 0, 0, 2, 0, 0, 0, 4, 3, 2, 1];
+    let words: Vec<u32> = be_memory
+        .chunks_exact(WORD_SIZE)
+        .map(|bytes| u32::from_be_bytes(bytes.try_into().unwrap()))
+        .collect();
+
+    let env = ExecutorEnv::builder().write_words(&words).build().unwrap();
+    assert_eq!(env.input, guest_bytes[..]);
+    let env = ExecutorEnv::builder()
+        .write(&0x01020304u32)
+        .unwrap()
+        .build()
+        .unwrap();
+    assert_eq!(env.input, guest_bytes[8..]);
+
+    let env = ExecutorEnv::builder()
+        .write(&MultiTestSpec::ReadWords {
+            nwords: words.len() as u32,
+        })
+        .unwrap()
+        .write_words(&words)
+        .build()
+        .unwrap();
+    let session = ExecutorImpl::from_elf(env, MULTI_TEST_ELF)
+        .unwrap()
+        .run()
+        .unwrap();
+    assert_eq!(session.journal.unwrap().bytes, guest_bytes);
+}
+
+#[test]
+fn host_supports() {
+    let run = |register: bool| -> (bool, bool) {
+        let mut builder = ExecutorEnv::builder();
+        builder.write(&MultiTestSpec::HostSupports).unwrap();
+        if register {
+            builder.io_callback(SYS_MULTI_TEST, |_| Ok(Bytes::new()));
+        }
+        let env = builder.build().unwrap();
+        let session = ExecutorImpl::from_elf(env, MULTI_TEST_ELF)
+            .unwrap()
+            .run()
+            .unwrap();
+        session.journal.unwrap().decode().unwrap()
+    };
+
+    assert_eq!(run(true), (true, true));
+    assert_eq!(run(false), (false, true));
+}
+
+#[test]
+fn read_past_end_of_stdin() {
+    let run = |short_reads: bool| {
+        let env = ExecutorEnv::builder()
+            .write(&MultiTestSpec::ReadWords { nwords: 2 })
+            .unwrap()
+            .write_bytes(&[1, 2, 3, 4])
+            .short_reads(short_reads)
+            .session_limit(Some(1 << 20))
+            .build()
+            .unwrap();
+        ExecutorImpl::from_elf(env, MULTI_TEST_ELF)
+            .unwrap()
+            .run()
+            .err()
+            .unwrap()
+    };
+
+    let err = run(false);
+    let GuestIoError::UnexpectedEof {
+        fd,
+        requested,
+        available,
+        ..
+    } = *err.downcast_ref::<GuestIoError>().unwrap()
+    else {
+        panic!("unexpected error: {err}");
+    };
+    assert_eq!((fd, requested, available), (fileno::STDIN, 8, 4));
+
+    // With short reads allowed, the guest sees the short read and fails to
+    // deserialize instead.
+    let err = run(true);
+    assert!(err.downcast_ref::<GuestIoError>().is_none());
+    assert!(err.to_string().contains("DeserializeUnexpectedEnd"));
+}
+
+// A reader with nothing available for the first `empty` polls, and then a
+// single byte, like a socket that only receives data now and then.
+struct Trickle {
+    empty: u32,
+    polls: u32,
+}
+
+impl std::io::Read for Trickle {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        use std::io::BufRead as _;
+        let n = self.fill_buf()?.len().min(buf.len());
+        buf[..n].fill(7);
+        self.consume(n);
+        Ok(n)
+    }
+}
+
+impl std::io::BufRead for Trickle {
+    fn fill_buf(&mut self) -> std::io::Result<&[u8]> {
+        if self.polls < self.empty {
+            self.polls += 1;
+            return Ok(&[]);
+        }
+        Ok(&[7])
+    }
+
+    fn consume(&mut self, amt: usize) {
+        if amt > 0 {
+            self.polls = 0;
+        }
+    }
+}
+
+#[test]
+fn stalled_guest() {
+    const FD: u32 = 123;
+    let run = |rounds, reader: Trickle, threshold| {
+        ExecutorEnv::builder()
+            .write(&MultiTestSpec::PollReadAvail { fd: FD, rounds })
+            .unwrap()
+            .read_fd(FD, reader)
+            .stall_threshold(threshold)
+            .session_limit(Some(1 << 22))
+            .build()
+            .and_then(|env| ExecutorImpl::from_elf(env, MULTI_TEST_ELF)?.run())
+    };
+    let stalled = |err: anyhow::Error| match *err.downcast_ref::<GuestIoError>().unwrap() {
+        GuestIoError::Stalled { fd, polls, cycle } => (fd, polls, cycle),
+        _ => panic!("unexpected error: {err}"),
+    };
+
+    // Nothing ever arrives, so the guest stalls on the default threshold,
+    // long before the end of its first segment.
+    let never = || Trickle {
+        empty: u32::MAX,
+        polls: 0,
+    };
+    let (fd, polls, cycle) = stalled(run(1, never(), Some(1000)).err().unwrap());
+    assert_eq!((fd, polls), (FD, 1001));
+    assert!(cycle < 1 << 18, "{cycle}");
+    let (_, polls, _) = stalled(run(1, never(), Some(10)).err().unwrap());
+    assert_eq!(polls, 11);
+
+    // Without detection, the guest spins until the session limit.
+    let err = run(1, never(), None).err().unwrap();
+    assert!(err.downcast_ref::<GuestIoError>().is_none());
+    assert!(format!("{err:?}").contains("Session limit exceeded"));
+
+    // Data arriving after fewer polls than the threshold resets the count, no
+    // matter how many empty polls there are in total.
+    let trickle = Trickle { empty: 8, polls: 0 };
+    let session = run(5, trickle, Some(10)).unwrap();
+    assert_eq!(session.journal.unwrap().bytes, [7; 5]);
 }
 
 #[test]
@@ -377,11 +2013,13 @@ mod sys_verify {
     use risc0_zkvm_methods::{
         multi_test::MultiTestSpec, HELLO_COMMIT_ELF, HELLO_COMMIT_ID, MULTI_TEST_ELF, MULTI_TEST_ID,
     };
+    use sha2::{Digest as _, Sha256};
     use test_log::test;
 
     use crate::{
         receipt_metadata::MaybePruned, serde::to_vec, sha::Digestible, ExecutorEnv,
-        ExecutorEnvBuilder, ExecutorImpl, ExitCode, ReceiptMetadata, Session,
+        ExecutorEnvBuilder, ExecutorImpl, ExitCode, InnerReceipt, Receipt, ReceiptMetadata,
+        Session,
     };
 
     fn exec_hello_commit() -> Session {
@@ -671,83 +2309,664 @@ mod sys_verify {
             .build()
             .unwrap();
 
-        // Result of execution should be a guest panic resulting from the pruned input.
-        assert!(ExecutorImpl::from_elf(env, MULTI_TEST_ELF)
-            .unwrap()
-            .run()
-            .is_err());
-    }
+        // Result of execution should be a guest panic resulting from the pruned input.
+        assert!(ExecutorImpl::from_elf(env, MULTI_TEST_ELF)
+            .unwrap()
+            .run()
+            .is_err());
+    }
+
+    #[test]
+    fn read_assumption() {
+        let hello_commit_session = exec_hello_commit();
+        let receipt = Receipt::new(
+            InnerReceipt::Fake {
+                metadata: hello_commit_session.get_metadata().unwrap(),
+            },
+            hello_commit_session.journal.clone().unwrap().bytes,
+        );
+        let expected = Sha256::digest(receipt.clone().into_assumption_bytes().unwrap());
+
+        let spec = &MultiTestSpec::ReadAssumption { index: 0 };
+        let env = ExecutorEnv::builder()
+            .write(&spec)
+            .unwrap()
+            .add_assumption(receipt.clone().into())
+            .build()
+            .unwrap();
+        let session = ExecutorImpl::from_elf(env, MULTI_TEST_ELF)
+            .unwrap()
+            .run()
+            .unwrap();
+        assert_eq!(session.exit_code, ExitCode::Halted(0));
+        assert_eq!(session.journal.unwrap().bytes, expected.as_slice());
+
+        // Unresolved assumptions have no receipt to read.
+        let env = ExecutorEnv::builder()
+            .write(&spec)
+            .unwrap()
+            .add_assumption(hello_commit_session.get_metadata().unwrap().into())
+            .build()
+            .unwrap();
+        assert!(ExecutorImpl::from_elf(env, MULTI_TEST_ELF)
+            .unwrap()
+            .run()
+            .is_err());
+    }
+}
+
+#[test]
+fn large_sha() {
+    let data = vec![0u8; 100_000];
+    let expected = hex::encode(Sha256::digest(&data));
+    let env = ExecutorEnv::builder()
+        .write(&MultiTestSpec::ShaDigest { data })
+        .unwrap()
+        .build()
+        .unwrap();
+    let mut exec = ExecutorImpl::from_elf(env, MULTI_TEST_ELF).unwrap();
+    let session = exec.run().unwrap();
+    let actual = hex::encode(Digest::try_from(session.journal.unwrap().bytes).unwrap());
+    assert_eq!(expected, actual);
+}
+
+#[test]
+fn journal_callback() {
+    let slices: Vec<Vec<u8>> = (1..=5u8).map(|i| vec![i; 4 * i as usize]).collect();
+    let mut streamed: Vec<Vec<u8>> = Vec::new();
+    let env = ExecutorEnv::builder()
+        .write(&MultiTestSpec::CommitSlices {
+            slices: slices.clone(),
+        })
+        .unwrap()
+        .journal_callback(|bytes| streamed.push(bytes.to_vec()))
+        .build()
+        .unwrap();
+    let session = ExecutorImpl::from_elf(env, MULTI_TEST_ELF)
+        .unwrap()
+        .run()
+        .unwrap();
+    assert_eq!(session.journal.unwrap().bytes, slices.concat());
+    assert_eq!(streamed, slices);
+}
+
+#[test]
+fn journal_tee() {
+    let slices: Vec<Vec<u8>> = (1..=5u8).map(|i| vec![i; 4 * i as usize]).collect();
+    let mut hasher = Sha256::new();
+    let env = ExecutorEnv::builder()
+        .write(&MultiTestSpec::CommitSlices { slices })
+        .unwrap()
+        .journal_tee(&mut hasher)
+        .build()
+        .unwrap();
+    let session = ExecutorImpl::from_elf(env, MULTI_TEST_ELF)
+        .unwrap()
+        .run()
+        .unwrap();
+    let journal = session.journal.unwrap().bytes;
+    assert_eq!(hasher.finalize(), Sha256::digest(&journal));
+
+    // The journal cannot be taken over by another writer.
+    let err = ExecutorEnv::builder()
+        .write_fd(fileno::JOURNAL, Vec::new())
+        .build()
+        .err()
+        .unwrap();
+    assert!(err.to_string().contains("journal_tee"));
+}
+
+#[test]
+fn guest_checks() {
+    let run = |policy: CheckPolicy| {
+        let env = ExecutorEnv::builder()
+            .write(&MultiTestSpec::Check { codes: vec![7, 42] })
+            .unwrap()
+            .check_policy(policy)
+            .build()
+            .unwrap();
+        ExecutorImpl::from_elf(env, MULTI_TEST_ELF).unwrap().run()
+    };
+
+    // Failed checks are recorded and the guest runs to completion.
+    let session = run(CheckPolicy::Record).unwrap();
+    assert_eq!(session.exit_code, ExitCode::Halted(0));
+    assert_eq!(session.journal.unwrap().decode::<u32>().unwrap(), 2);
+    let checks = session.failed_checks;
+    assert_eq!(checks.iter().map(|x| x.code).collect::<Vec<_>>(), [7, 42]);
+    assert_ne!(checks[0].pc, 0);
+    assert!(checks[0].cycle < checks[1].cycle);
+
+    // Execution stops at the first failed check.
+    let err = run(CheckPolicy::FailFast).err().unwrap();
+    assert_eq!(err.downcast_ref::<FailedCheck>(), Some(&checks[0]));
+    let msg = err.to_string();
+    assert!(msg.contains("guest check 7 failed"));
+    assert!(msg.contains(&format!("pc 0x{:08x}", checks[0].pc)));
+}
+
+#[test]
+fn guest_yield() {
+    let env = ExecutorEnv::builder()
+        .write(&MultiTestSpec::Yield { tags: vec![7, 9] })
+        .unwrap()
+        .build()
+        .unwrap();
+    let mut exec = ExecutorImpl::from_elf(env, MULTI_TEST_ELF).unwrap();
+
+    let mut tags = Vec::new();
+    let session = loop {
+        let session = exec.run().unwrap();
+        let Some(tag) = session.yield_tag else {
+            break session;
+        };
+        assert_eq!(session.exit_code, ExitCode::SystemSplit);
+        tags.push(tag);
+    };
+    assert_eq!(tags, [7, 9]);
+    assert_eq!(session.exit_code, ExitCode::Halted(0));
+
+    // Commits made before each yield are kept in the final journal.
+    let journal: &[u32] = bytemuck::cast_slice(&session.journal.as_ref().unwrap().bytes);
+    assert_eq!(journal, [7, 9]);
+}
+
+#[test]
+fn isa_audit_div_rem() {
+    let run = |a: u32, b: u32| -> Vec<u32> {
+        let env = ExecutorEnv::builder()
+            .write(&MultiTestSpec::DivRem { a, b })
+            .unwrap()
+            .isa_audit(true)
+            .build()
+            .unwrap();
+        let session = ExecutorImpl::from_elf(env, MULTI_TEST_ELF)
+            .unwrap()
+            .run()
+            .unwrap();
+        assert_eq!(session.exit_code, ExitCode::Halted(0));
+        bytemuck::cast_slice(&session.journal.unwrap().bytes).to_vec()
+    };
+
+    // Division by zero.
+    assert_eq!(run(7, 0), [u32::MAX, u32::MAX, 7, 7]);
+    // Signed overflow.
+    let min = i32::MIN as u32;
+    assert_eq!(run(min, u32::MAX), [min, 0, 0, min]);
+    assert_eq!(run(7, 2), [3, 3, 1, 1]);
+}
+
+#[test]
+fn exec_acceleration() {
+    let run = |accelerate: bool| {
+        let env = ExecutorEnv::builder()
+            .write(&MultiTestSpec::CopyMemory { bytes: 1 << 20 })
+            .unwrap()
+            .segment_limit_po2(18)
+            .exec_acceleration(accelerate)
+            .build()
+            .unwrap();
+        let mut exec = ExecutorImpl::from_elf(env, MULTI_TEST_ELF).unwrap();
+        let session = exec.run().unwrap();
+        (session, exec.accelerated_insns())
+    };
+    let (plain, plain_insns) = run(false);
+    let (fast, fast_insns) = run(true);
+    assert_eq!(plain_insns, 0);
+    assert!(fast_insns > 0);
+
+    // Acceleration must not change anything about the session.
+    assert_eq!(fast.exit_code, plain.exit_code);
+    assert_eq!(fast.journal, plain.journal);
+    assert_eq!(fast.post_image.compute_id(), plain.post_image.compute_id());
+    let summarize = |segments: Vec<Segment>| -> Vec<_> {
+        segments
+            .into_iter()
+            .map(|segment| {
+                (
+                    segment.pre_image.compute_id(),
+                    segment.post_image_id,
+                    segment.cycles,
+                    segment.po2,
+                    segment.split_insn,
+                    segment.faults.reads,
+                    segment.faults.writes,
+                )
+            })
+            .collect()
+    };
+    let plain_segments = summarize(plain.resolve().unwrap());
+    assert!(plain_segments.len() > 1);
+    assert_eq!(summarize(fast.resolve().unwrap()), plain_segments);
+}
+
+#[test]
+fn std_stdio() {
+    const STDIN: &str = "Hello world from stdin!\n";
+    const EXPECTED_STDOUT: &str = "Hello world on stdout!\n";
+    const EXPECTED_STDERR: &str = "Hello world on stderr!\n";
+
+    fn expected_stdout() -> String {
+        format!("{EXPECTED_STDOUT}{STDIN}")
+    }
+
+    let mut stderr: Vec<u8> = Vec::new();
+    let mut stdout: Vec<u8> = Vec::new();
+    {
+        let env = ExecutorEnv::builder()
+            .env_var("TEST_MODE", "STDIO")
+            .stdin(STDIN.as_bytes())
+            .stderr(&mut stderr)
+            .stdout(&mut stdout)
+            .build()
+            .unwrap();
+        ExecutorImpl::from_elf(env, STANDARD_LIB_ELF)
+            .unwrap()
+            .run()
+            .unwrap();
+    }
+    assert_eq!(from_utf8(&stdout).unwrap(), expected_stdout());
+    assert_eq!(from_utf8(&stderr).unwrap(), EXPECTED_STDERR);
+}
+
+#[test]
+fn environment() {
+    let env = ExecutorEnv::builder()
+        .env_var("TEST_MODE", "ENV_VARS")
+        .env_var("ENV_VAR1", "val1")
+        .env_var("ENV_VAR2", "")
+        .read_fd(
+            fileno::STDIN,
+            Cursor::new(
+                r"ENV_VAR1
+ENV_VAR2
+ENV_VAR3",
+            ),
+        )
+        .build()
+        .unwrap();
+    let mut exec = ExecutorImpl::from_elf(env, STANDARD_LIB_ELF).unwrap();
+    let session = exec.run().unwrap();
+    let actual = &session.journal.as_ref().unwrap().bytes;
+    assert_eq!(
+        from_utf8(actual).unwrap(),
+        r"ENV_VAR1=val1
+ENV_VAR2=
+!ENV_VAR3
+"
+    );
+}
+
+#[test]
+fn env_var_long_value() {
+    let run = |name: &str| -> Option<String> {
+        let env = ExecutorEnv::builder()
+            .write(&MultiTestSpec::EnvVar {
+                name: name.to_string(),
+            })
+            .unwrap()
+            .env_vars([("LONG".to_string(), "x".repeat(10 * 1024))])
+            .build()
+            .unwrap();
+        let session = ExecutorImpl::from_elf(env, MULTI_TEST_ELF)
+            .unwrap()
+            .run()
+            .unwrap();
+        session.journal.unwrap().decode().unwrap()
+    };
+
+    // The value does not fit in the guest's first buffer and must be re-read.
+    assert_eq!(run("LONG"), Some("x".repeat(10 * 1024)));
+    assert_eq!(run("UNSET"), None);
+}
+
+#[test]
+fn env_var_large() {
+    let config: String = (0..64 * 1024)
+        .map(|i| (b'a' + (i % 26) as u8) as char)
+        .collect();
+    let run = |name: &str| -> Vec<u8> {
+        let env = ExecutorEnv::builder()
+            .write(&MultiTestSpec::EnvVarLarge {
+                name: name.to_string(),
+            })
+            .unwrap()
+            .env_var("CONFIG", &config)
+            .env_var("SMALL", "value")
+            .build()
+            .unwrap();
+        let session = ExecutorImpl::from_elf(env, MULTI_TEST_ELF)
+            .unwrap()
+            .run()
+            .unwrap();
+        session.journal.unwrap().bytes
+    };
+    let set = |value: &[u8]| [&[1, 0, 0, 0], value].concat();
+
+    // 64 KB are read through the guest's 1 KB buffer, one page at a time.
+    assert_eq!(run("CONFIG"), set(config.as_bytes()));
+    assert_eq!(run("SMALL"), set(b"value"));
+    assert_eq!(run("UNSET"), vec![0, 0, 0, 0]);
+}
+
+#[test]
+fn env_var_invalid_name() {
+    for name in ["", "A\0B"] {
+        let err = ExecutorEnv::builder()
+            .env_var(name, "value")
+            .build()
+            .err()
+            .unwrap();
+        assert!(err
+            .to_string()
+            .contains("invalid environment variable name"));
+    }
+}
+
+#[test]
+fn env_vars_replace_or_extend() {
+    let var = |name: &str, val: &str| (name.to_string(), val.to_string());
+
+    let env = ExecutorEnv::builder()
+        .env_var("A", "1")
+        .env_vars([var("B", "2")])
+        .build()
+        .unwrap();
+    assert_eq!(env.env_vars, HashMap::from([var("B", "2")]));
+
+    let env = ExecutorEnv::builder()
+        .env_var("A", "1")
+        .env_var("B", "1")
+        .extend_env_vars([var("B", "2")])
+        .build()
+        .unwrap();
+    assert_eq!(env.env_vars, HashMap::from([var("A", "1"), var("B", "2")]));
+}
+
+#[test]
+fn build_reports_all_problems() {
+    let err = ExecutorEnv::builder()
+        .env_var("", "value")
+        .read_fd(5, b"".as_slice())
+        .write_fd(5, Vec::new())
+        .named_input("config", &[][..])
+        .named_input("config", &[][..])
+        .build()
+        .err()
+        .unwrap();
+    let errs = &err.downcast_ref::<SetupErrors>().unwrap().0;
+    assert_eq!(
+        errs,
+        &[
+            OptsError::InvalidEnvVarName(String::new()),
+            OptsError::ConflictingFd(5),
+            OptsError::DuplicateNamedInput("config".to_string()),
+        ]
+    );
+    let msg = err.to_string();
+    assert!(msg.starts_with("3 problems with the setup:"), "{msg}");
+    for err in errs {
+        assert!(msg.contains(&format!("\n  - {err}")), "{msg}");
+    }
+}
+
+#[test]
+fn named_inputs() {
+    let config = to_vec(&7_u32).unwrap();
+    let witness = to_vec(&42_u32).unwrap();
+    let env = ExecutorEnv::builder()
+        .write(&MultiTestSpec::ReadNamed {
+            names: vec!["witness".to_string(), "config".to_string()],
+        })
+        .unwrap()
+        .named_input("config", bytemuck::cast_slice(&config))
+        .named_input("witness", bytemuck::cast_slice(&witness))
+        .build()
+        .unwrap();
+    let session = ExecutorImpl::from_elf(env, MULTI_TEST_ELF)
+        .unwrap()
+        .run()
+        .unwrap();
+    let values: Vec<u32> = session.journal.unwrap().decode().unwrap();
+    assert_eq!(values, [42, 7]);
+
+    let err = ExecutorEnv::builder()
+        .named_input("config", &[][..])
+        .named_input("config", &[][..])
+        .build()
+        .err()
+        .unwrap();
+    assert!(err.to_string().contains("duplicate named input"), "{err:?}");
 }
 
 #[test]
-fn large_sha() {
-    let data = vec![0u8; 100_000];
-    let expected = hex::encode(Sha256::digest(&data));
+fn syscall_namespace() {
+    let err = |builder: &mut ExecutorEnvBuilder| builder.build().err().unwrap().to_string();
+
+    // A handler that happens to be named after a built-in is rejected.
+    assert_eq!(
+        err(ExecutorEnv::builder().io_callback(SYS_READ, |_| Ok(Bytes::new()))),
+        format!(
+            "I/O handler registered for built-in syscall {}; use override_builtin to replace it",
+            SYS_READ.as_str()
+        )
+    );
+
+    // So is a second handler for the same syscall, whether or not it is a
+    // built-in.
+    assert_eq!(
+        err(ExecutorEnv::builder()
+            .io_callback(SYS_MULTI_TEST, |_| Ok(Bytes::new()))
+            .io_callback(SYS_MULTI_TEST, |_| Ok(Bytes::new()))),
+        format!(
+            "more than one I/O handler registered for {}",
+            SYS_MULTI_TEST.as_str()
+        )
+    );
+    assert!(err(ExecutorEnv::builder()
+        .override_builtin(SYS_NONCE)
+        .io_callback(SYS_NONCE, |_| Ok(Bytes::new()))
+        .io_callback(SYS_NONCE, |_| Ok(Bytes::new())))
+    .starts_with("more than one I/O handler"));
+
+    // An acknowledged override replaces the built-in.
     let env = ExecutorEnv::builder()
-        .write(&MultiTestSpec::ShaDigest { data })
+        .write(&MultiTestSpec::CommitNonce { value: 7 })
         .unwrap()
+        .nonce([1; 32])
+        .override_builtin(SYS_NONCE)
+        .io_callback(SYS_NONCE, |_| Ok(Bytes::from_static(&[2; 32])))
+        .io_callback(SYS_MULTI_TEST, |_| Ok(Bytes::new()))
         .build()
         .unwrap();
-    let mut exec = ExecutorImpl::from_elf(env, MULTI_TEST_ELF).unwrap();
-    let session = exec.run().unwrap();
-    let actual = hex::encode(Digest::try_from(session.journal.unwrap().bytes).unwrap());
-    assert_eq!(expected, actual);
+    let handlers = env.syscall_handlers();
+    assert_eq!(handlers[SYS_NONCE.as_str()], SyscallSource::Override);
+    assert_eq!(handlers[SYS_READ.as_str()], SyscallSource::Builtin);
+    assert_eq!(handlers[SYS_MULTI_TEST.as_str()], SyscallSource::User);
+    let session = ExecutorImpl::from_elf(env, MULTI_TEST_ELF)
+        .unwrap()
+        .run()
+        .unwrap();
+    let journal = session.journal.unwrap();
+    assert_eq!(&journal.bytes[..32], &[2; 32]);
+
+    // Every syscall reported as a built-in has a handler in the executor.
+    let env = ExecutorEnv::default();
+    let builtins: HashSet<_> = env
+        .syscall_handlers()
+        .into_iter()
+        .filter(|(_, source)| *source == SyscallSource::Builtin)
+        .map(|(name, _)| name)
+        .collect();
+    let table: HashSet<_> = SyscallTable::new(&env).inner.into_keys().collect();
+    assert_eq!(builtins, table);
 }
 
 #[test]
-fn std_stdio() {
-    const STDIN: &str = "Hello world from stdin!\n";
-    const EXPECTED_STDOUT: &str = "Hello world on stdout!\n";
-    const EXPECTED_STDERR: &str = "Hello world on stderr!\n";
+fn syscall_guard() {
+    let run = |builder: &mut ExecutorEnvBuilder<'static>| -> Result<Session> {
+        builder.write(&MultiTestSpec::Syscall { count: 1 }).unwrap();
+        ExecutorImpl::from_elf(builder.build()?, MULTI_TEST_ELF)?.run()
+    };
+    let timeout = Duration::from_millis(100);
+
+    // A handler that returns in time is unaffected.
+    let session = run(ExecutorEnv::builder()
+        .io_callback_send(SYS_MULTI_TEST, |_| Ok(Bytes::from_static(b"ok")))
+        .syscall_guard(
+            SYS_MULTI_TEST,
+            SyscallGuard {
+                timeout: Some(timeout),
+                max_response_bytes: Some(2),
+            },
+        ))
+    .unwrap();
+    assert_eq!(session.exit_code, ExitCode::Halted(0));
 
-    fn expected_stdout() -> String {
-        format!("{EXPECTED_STDOUT}{STDIN}")
+    // A handler that sleeps past its timeout fails the run instead of
+    // hanging it.
+    let err = run(ExecutorEnv::builder()
+        .io_callback_send(SYS_MULTI_TEST, |_| {
+            std::thread::sleep(Duration::from_secs(60));
+            Ok(Bytes::new())
+        })
+        .syscall_guard(
+            SYS_MULTI_TEST,
+            SyscallGuard {
+                timeout: Some(timeout),
+                ..Default::default()
+            },
+        ))
+    .err()
+    .unwrap();
+    let SyscallGuardError::Timeout {
+        syscall,
+        timeout: limit,
+        cycle,
+    } = err.downcast_ref::<SyscallGuardError>().unwrap().clone()
+    else {
+        panic!("unexpected error: {err}");
+    };
+    assert_eq!(syscall, SYS_MULTI_TEST.as_str());
+    assert_eq!(limit, timeout);
+    assert!(cycle > 0);
+
+    // So does a handler that returns more than it is allowed, whether or not
+    // it runs on a helper thread.
+    let guard = SyscallGuard {
+        max_response_bytes: Some(16),
+        ..Default::default()
+    };
+    let errs = [
+        run(ExecutorEnv::builder()
+            .io_callback(SYS_MULTI_TEST, |_| Ok(vec![0; 17].into()))
+            .syscall_guard(SYS_MULTI_TEST, guard))
+        .err()
+        .unwrap(),
+        run(ExecutorEnv::builder()
+            .io_callback_send(SYS_MULTI_TEST, |_| Ok(vec![0; 17].into()))
+            .syscall_guard(SYS_MULTI_TEST, guard))
+        .err()
+        .unwrap(),
+    ];
+    for err in errs {
+        let SyscallGuardError::ResponseTooLarge {
+            syscall,
+            len,
+            limit,
+            cycle,
+        } = err.downcast_ref::<SyscallGuardError>().unwrap().clone()
+        else {
+            panic!("unexpected error: {err}");
+        };
+        assert_eq!(syscall, SYS_MULTI_TEST.as_str());
+        assert_eq!((len, limit), (17, 16));
+        assert!(cycle > 0);
     }
 
-    let mut stderr: Vec<u8> = Vec::new();
-    let mut stdout: Vec<u8> = Vec::new();
-    {
-        let env = ExecutorEnv::builder()
-            .env_var("TEST_MODE", "STDIO")
-            .stdin(STDIN.as_bytes())
-            .stderr(&mut stderr)
-            .stdout(&mut stdout)
-            .build()
-            .unwrap();
-        ExecutorImpl::from_elf(env, STANDARD_LIB_ELF)
+    // A timeout needs a handler that can run on another thread.
+    let err = ExecutorEnv::builder()
+        .io_callback(SYS_MULTI_TEST, |_| Ok(Bytes::new()))
+        .syscall_guard(
+            SYS_MULTI_TEST,
+            SyscallGuard {
+                timeout: Some(timeout),
+                ..Default::default()
+            },
+        )
+        .build()
+        .err()
+        .unwrap();
+    assert!(err.to_string().contains("cannot be given a timeout"));
+    let err = ExecutorEnv::builder()
+        .syscall_guard(SYS_MULTI_TEST, guard)
+        .build()
+        .err()
+        .unwrap();
+    assert!(err.to_string().contains("which has no I/O handler"));
+}
+
+#[test]
+fn env_manifest() {
+    let inputs: BTreeMap<_, _> = [("config", 7_u32), ("witness", 42)]
+        .into_iter()
+        .map(|(name, value)| (name, to_vec(&value).unwrap()))
+        .collect();
+    let resolve = |name: &str| -> Option<Box<dyn std::io::Read>> {
+        let words = inputs.get(name)?;
+        Some(Box::new(Cursor::new(bytemuck::cast_slice::<u32, u8>(
+            words,
+        ))))
+    };
+    let run = |env: ExecutorEnv| {
+        let session = ExecutorImpl::from_elf(env, MULTI_TEST_ELF)
             .unwrap()
             .run()
             .unwrap();
+        session.journal.unwrap().bytes
+    };
+
+    let mut builder = ExecutorEnv::builder();
+    builder
+        .write(&MultiTestSpec::ReadNamed {
+            names: vec!["witness".to_string(), "config".to_string()],
+        })
+        .unwrap()
+        .env_var("RUST_LOG", "debug")
+        .args(&["prog".to_string(), "-v".to_string()])
+        .segment_limit_po2(16)
+        .session_limit(Some(1 << 24));
+    for name in ["config", "witness"] {
+        builder.named_input(name, resolve(name).unwrap());
     }
-    assert_eq!(from_utf8(&stdout).unwrap(), expected_stdout());
-    assert_eq!(from_utf8(&stderr).unwrap(), EXPECTED_STDERR);
-}
+    let env = builder.build().unwrap();
+    let manifest = env.manifest();
+    let journal = run(env);
+
+    let json = serde_json::to_string(&manifest).unwrap();
+    let decoded: GuestEnvManifest = serde_json::from_str(&json).unwrap();
+    assert_eq!(decoded, manifest);
+    assert_eq!(decoded.named_inputs, ["config", "witness"]);
 
-#[test]
-fn environment() {
     let env = ExecutorEnv::builder()
-        .env_var("TEST_MODE", "ENV_VARS")
-        .env_var("ENV_VAR1", "val1")
-        .env_var("ENV_VAR2", "")
-        .read_fd(
-            fileno::STDIN,
-            Cursor::new(
-                r"ENV_VAR1
-ENV_VAR2
-ENV_VAR3",
-            ),
-        )
+        .apply_manifest(&decoded, resolve)
+        .unwrap()
         .build()
         .unwrap();
-    let mut exec = ExecutorImpl::from_elf(env, STANDARD_LIB_ELF).unwrap();
-    let session = exec.run().unwrap();
-    let actual = &session.journal.as_ref().unwrap().bytes;
+    assert_eq!(env.manifest(), manifest);
+    assert_eq!(run(env), journal);
+
+    // Inputs and handlers that cannot be resolved are all reported.
+    let mut manifest = manifest;
+    manifest.slice_io.push("oracle".to_string());
+    let err = ExecutorEnv::builder()
+        .apply_manifest(&manifest, |_| None)
+        .err()
+        .unwrap();
     assert_eq!(
-        from_utf8(actual).unwrap(),
-        r"ENV_VAR1=val1
-ENV_VAR2=
-!ENV_VAR3
-"
+        err.to_string(),
+        "unresolved manifest entries: named input \"config\", named input \"witness\", \
+         slice I/O channel \"oracle\""
     );
 }
 
@@ -782,6 +3001,107 @@ fn args() {
     }
 }
 
+#[test]
+fn cycles_remaining() {
+    let run = |session_limit| {
+        let env = ExecutorEnv::builder()
+            .write(&MultiTestSpec::CyclesRemaining { threshold: 50_000 })
+            .unwrap()
+            .segment_limit_po2(16)
+            .session_limit(session_limit)
+            .build()
+            .unwrap();
+        let session = ExecutorImpl::from_elf(env, MULTI_TEST_ELF)
+            .unwrap()
+            .run()
+            .unwrap();
+        assert_eq!(session.exit_code, ExitCode::Halted(0));
+        session.journal.unwrap().decode::<u32>().unwrap()
+    };
+
+    // The guest stops on its own before the session limit is reached.
+    assert!(run(Some(1 << 18)) > 0);
+
+    // Without a limit, there is nothing to count down.
+    assert_eq!(run(None), 0);
+}
+
+#[test]
+fn host_commits() {
+    let run = |allow: bool, accept: bool| -> Result<Session> {
+        let commits = HostCommits::new();
+        let handler_commits = commits.clone();
+        let calls = Cell::new(0u8);
+        let mut builder = ExecutorEnv::builder();
+        builder
+            .write(&MultiTestSpec::HostCommit {
+                first: 1,
+                second: 2,
+                accept,
+            })
+            .unwrap()
+            .io_callback(SYS_MULTI_TEST, move |_| {
+                calls.set(calls.get() + 1);
+                handler_commits.commit(&[calls.get(); DIGEST_BYTES])?;
+                Ok(Bytes::new())
+            });
+        if allow {
+            builder.host_commits(&commits);
+        }
+        ExecutorImpl::from_elf(builder.build().unwrap(), MULTI_TEST_ELF)?.run()
+    };
+
+    // Each commit by the handler lands where the guest accepts it.
+    let session = run(true, true).unwrap();
+    assert_eq!(session.exit_code, ExitCode::Halted(0));
+    let expected = [
+        &1u32.to_le_bytes()[..],
+        &[1; DIGEST_BYTES],
+        &2u32.to_le_bytes(),
+        &[2; DIGEST_BYTES],
+    ]
+    .concat();
+    assert_eq!(session.journal.unwrap().bytes, expected);
+
+    // A guest that does not accept host data keeps its journal to itself,
+    // even when the host allows its handlers to commit.
+    let session = run(true, false).unwrap();
+    assert_eq!(session.exit_code, ExitCode::Halted(0));
+    let expected = [1u32.to_le_bytes(), 2u32.to_le_bytes()].concat();
+    assert_eq!(session.journal.unwrap().bytes, expected);
+
+    let Err(err) = run(false, true) else {
+        panic!("host commits were not refused");
+    };
+    assert!(
+        format!("{err:?}").contains("Host commits are not allowed"),
+        "{err:?}"
+    );
+}
+
+#[test]
+fn guest_env_args() {
+    let run = |args: &[String]| {
+        let env = ExecutorEnv::builder()
+            .write(&MultiTestSpec::Args)
+            .unwrap()
+            .args(args)
+            .build()
+            .unwrap();
+        let session = ExecutorImpl::from_elf(env, MULTI_TEST_ELF)
+            .unwrap()
+            .run()
+            .unwrap();
+        session.journal.unwrap().decode::<Vec<String>>().unwrap()
+    };
+
+    assert!(run(&[]).is_empty());
+
+    let mut args: Vec<String> = (0..100).map(|i| format!("--arg={i}")).collect();
+    args[50] = "x".repeat(8 * 1024);
+    assert_eq!(run(&args), args);
+}
+
 #[test]
 fn commit_hello_world() {
     ExecutorImpl::from_elf(ExecutorEnv::default(), HELLO_COMMIT_ELF)
@@ -795,12 +3115,87 @@ fn random() {
     run_test(MultiTestSpec::DoRandom);
 }
 
+#[test]
+fn derived_randomness() {
+    let run = |nonce: [u8; 32], derived: bool| {
+        let env = ExecutorEnv::builder()
+            .write(&MultiTestSpec::DoRandom)
+            .unwrap()
+            .nonce(nonce)
+            .derived_randomness(derived)
+            .build()
+            .unwrap();
+        let session = ExecutorImpl::from_elf(env, MULTI_TEST_ELF)
+            .unwrap()
+            .run()
+            .unwrap();
+        session.journal.unwrap().bytes
+    };
+
+    // The same input gives the same stream, and a different one diverges.
+    let journal = run([1; 32], true);
+    assert!(journal.len() > 32);
+    assert_eq!(run([1; 32], true), journal);
+    assert_ne!(run([2; 32], true), journal);
+    assert_ne!(run([1; 32], false), run([1; 32], false));
+
+    // The stream must be bound to the input, which a custom reader hides.
+    let words = to_vec(&MultiTestSpec::DoRandom).unwrap();
+    let env = ExecutorEnv::builder()
+        .stdin(bytemuck::cast_slice::<u32, u8>(&words))
+        .derived_randomness(true)
+        .build()
+        .unwrap();
+    let err = ExecutorImpl::from_elf(env, MULTI_TEST_ELF).err().unwrap();
+    assert!(err
+        .to_string()
+        .contains("derived randomness needs an input"));
+}
+
+#[test]
+fn sha_accel() {
+    let run = |len: u32, verify_one_in: u32, enable: bool| -> Result<(Digest, Option<Digest>)> {
+        let env = ExecutorEnv::builder()
+            .write(&MultiTestSpec::ShaAccel { len, verify_one_in })
+            .unwrap()
+            .sha_accel(enable)
+            .build()
+            .unwrap();
+        let session = ExecutorImpl::from_elf(env, MULTI_TEST_ELF)?.run()?;
+        Ok(session.journal.unwrap().decode().unwrap())
+    };
+    let expected = |len: u32| {
+        let pattern: Vec<u8> = (0..4099_u32).map(|i| (i * 31 % 251) as u8).collect();
+        let data: Vec<u8> = pattern.iter().cycle().take(len as usize).copied().collect();
+        Digest::try_from(Sha256::digest(&data).as_slice()).unwrap()
+    };
+
+    // Small inputs agree with the hash computed in the guest, whether or not
+    // the guest checks the host.
+    for len in [0, 1, 55, 56, 63, 64, 65, 4099, 10_000] {
+        for verify_one_in in [0, 1, 3] {
+            let (digest, reference) = run(len, verify_one_in, true).unwrap();
+            assert_eq!(digest, expected(len), "len {len}");
+            assert_eq!(reference, Some(digest), "len {len}");
+        }
+    }
+
+    // 4 MB streamed through the syscall.
+    let len = 4 << 20;
+    assert_eq!(run(len, 0, true).unwrap(), (expected(len), None));
+    assert_eq!(run(len, 16, true).unwrap().0, expected(len));
+
+    // The host must enable the syscall.
+    let err = run(64, 0, false).err().unwrap();
+    assert!(format!("{err:#}").contains("SYS_SHA256"), "{err:#}");
+}
+
 #[test]
 fn slice_io() {
     let run = |slice: &[u8]| {
         let env = ExecutorEnv::builder()
-            .write_slice(&[slice.len() as u32])
-            .write_slice(slice)
+            .write_words(&[slice.len() as u32])
+            .write_bytes(slice)
             .build()
             .unwrap();
         let mut exec = ExecutorImpl::from_elf(env, SLICE_IO_ELF).unwrap();
@@ -940,6 +3335,199 @@ fn oom() {
     let mut exec = ExecutorImpl::from_elf(env, MULTI_TEST_ELF).unwrap();
     let err = exec.run().err().unwrap();
     assert!(err.to_string().contains("Out of memory"), "{err:?}");
+    assert!(err.to_string().contains("guest heap peak"), "{err:?}");
+}
+
+#[test]
+fn heap_stats() {
+    const BYTES: u32 = 1024 * 1024;
+
+    let env = ExecutorEnv::builder()
+        .write(&MultiTestSpec::HeapAlloc { bytes: BYTES })
+        .unwrap()
+        .build()
+        .unwrap();
+    let session = ExecutorImpl::from_elf(env, MULTI_TEST_ELF)
+        .unwrap()
+        .run()
+        .unwrap();
+
+    // The heap starts at the end of the last page of the program, so up to a
+    // page of it may be missed, and the guest makes a few small allocations of
+    // its own.
+    let peak = session.heap_stats.peak_bytes;
+    let min = (BYTES as usize - PAGE_SIZE) as u64;
+    let max = (BYTES + 64 * 1024) as u64;
+    assert!((min..max).contains(&peak), "{peak}");
+}
+
+#[test]
+fn guest_layout() {
+    const STACK_SIZE: u32 = 16 * 1024 * 1024;
+    const STACK_USE: u32 = 15 * 1024 * 1024;
+    const FRAMES: u32 = STACK_USE / (64 * 1024);
+
+    // The default stack is too small.
+    let env = ExecutorEnv::builder()
+        .write(&MultiTestSpec::StackUsage { bytes: STACK_USE })
+        .unwrap()
+        .build()
+        .unwrap();
+    if let Ok(session) = ExecutorImpl::from_elf(env, MULTI_TEST_ELF).unwrap().run() {
+        assert_eq!(session.exit_code, ExitCode::Fault);
+    }
+
+    let env = ExecutorEnv::builder()
+        .write(&MultiTestSpec::StackUsage { bytes: STACK_USE })
+        .unwrap()
+        .stack_size(STACK_SIZE)
+        .build()
+        .unwrap();
+    let session = ExecutorImpl::from_elf(env, MULTI_TEST_ELF)
+        .unwrap()
+        .run()
+        .unwrap();
+    assert_eq!(session.exit_code, ExitCode::Halted(0));
+    let sum: u32 = session.journal.as_ref().unwrap().decode().unwrap();
+    assert_eq!(sum, FRAMES * (FRAMES + 1) / 2);
+
+    // The layout is part of the image.
+    let layout = GuestLayout {
+        stack_size: Some(STACK_SIZE),
+        ..Default::default()
+    };
+    let pre = session.get_metadata().unwrap().pre.digest();
+    assert_eq!(pre, layout.image_id(MULTI_TEST_ELF).unwrap());
+    assert_ne!(pre, Digest::from(MULTI_TEST_ID));
+
+    // The heap starts where the guest was told, below the stack.
+    const BYTES: u32 = 1024 * 1024;
+    let env = ExecutorEnv::builder()
+        .write(&MultiTestSpec::HeapAlloc { bytes: BYTES })
+        .unwrap()
+        .stack_size(STACK_SIZE)
+        .heap_base(GUEST_MAX_MEM as u32 / 2)
+        .build()
+        .unwrap();
+    let session = ExecutorImpl::from_elf(env, MULTI_TEST_ELF)
+        .unwrap()
+        .run()
+        .unwrap();
+    let peak = session.heap_stats.peak_bytes;
+    assert!(
+        (BYTES as u64..(BYTES + 64 * 1024) as u64).contains(&peak),
+        "{peak}"
+    );
+
+    let load = |layout: GuestLayout| layout.load_elf(MULTI_TEST_ELF).err().unwrap().to_string();
+    let err = load(GuestLayout {
+        stack_size: Some(GUEST_MAX_MEM as u32),
+        ..Default::default()
+    });
+    assert!(err.contains("overlaps the program"), "{err}");
+    let err = load(GuestLayout {
+        stack_size: Some(STACK_SIZE + 4),
+        ..Default::default()
+    });
+    assert!(err.contains("multiple of 16"), "{err}");
+    let err = load(GuestLayout {
+        heap_base: Some(TEXT_START),
+        ..Default::default()
+    });
+    assert!(err.contains("Heap base"), "{err}");
+    let err = load(GuestLayout {
+        stack_size: Some(STACK_SIZE),
+        heap_base: Some(GUEST_MAX_MEM as u32 - STACK_SIZE),
+        ..Default::default()
+    });
+    assert!(err.contains("Heap base"), "{err}");
+}
+
+#[test]
+fn custom_entry() {
+    let (a, b) = (6, 7);
+    let expected = a + b + (a ^ b) + a * b;
+    let halt = Program::symbol(MULTI_TEST_ELF, "multi_test_halt")
+        .unwrap()
+        .unwrap();
+    let run = |builder: &mut ExecutorEnvBuilder| -> Result<Session> {
+        ExecutorImpl::from_elf(builder.build()?, MULTI_TEST_ELF)?.run()
+    };
+
+    // Call the function directly, and read its result from the registers
+    // saved when the guest halts.
+    let session = run(ExecutorEnv::builder()
+        .entry("multi_test_leaf")
+        .initial_registers(&[(REG_A0, a), (REG_A1, b), (REG_RA, halt)]))
+    .unwrap();
+    assert_eq!(session.exit_code, ExitCode::Halted(0));
+    let mut result = [0; WORD_SIZE];
+    session
+        .post_image
+        .load_region_in_page((SYSTEM.start() + REG_A2 * WORD_SIZE) as u32, &mut result);
+    assert_eq!(u32::from_le_bytes(result), expected);
+
+    // The same function, called from the guest's main.
+    let session = run(ExecutorEnv::builder()
+        .write(&MultiTestSpec::CallLeaf { a, b })
+        .unwrap())
+    .unwrap();
+    assert_eq!(session.journal.unwrap().decode::<u32>().unwrap(), expected);
+
+    // The entry point is part of the image ID, however it is given.
+    let leaf = Program::symbol(MULTI_TEST_ELF, "multi_test_leaf")
+        .unwrap()
+        .unwrap();
+    let layout = GuestLayout {
+        entry: Some("multi_test_leaf".into()),
+        ..Default::default()
+    };
+    assert_eq!(layout.entry_point(MULTI_TEST_ELF).unwrap(), leaf);
+    assert_ne!(
+        layout.image_id(MULTI_TEST_ELF).unwrap(),
+        Digest::from(MULTI_TEST_ID)
+    );
+    let by_address = GuestLayout {
+        entry: Some(leaf.into()),
+        ..Default::default()
+    };
+    assert_eq!(
+        layout.image_id(MULTI_TEST_ELF).unwrap(),
+        by_address.image_id(MULTI_TEST_ELF).unwrap()
+    );
+
+    // Without a return address, the function returns to address 0.
+    let err = run(ExecutorEnv::builder()
+        .entry("multi_test_leaf")
+        .initial_registers(&[(REG_A0, a), (REG_A1, b)]))
+    .err()
+    .unwrap()
+    .to_string();
+    assert!(err.contains("entry point multi_test_leaf"), "{err}");
+    assert!(err.contains("returned to address 0"), "{err}");
+
+    let load = |layout: GuestLayout| layout.load_elf(MULTI_TEST_ELF).err().unwrap().to_string();
+    let err = load(GuestLayout {
+        entry: Some("no_such_symbol".into()),
+        ..Default::default()
+    });
+    assert!(err.contains("not a symbol"), "{err}");
+    let err = load(GuestLayout {
+        entry: Some((GUEST_MAX_MEM as u32 - 4).into()),
+        ..Default::default()
+    });
+    assert!(err.contains("not an instruction"), "{err}");
+    let err = load(GuestLayout {
+        heap_base: Some(GUEST_MAX_MEM as u32 / 2),
+        entry: Some(leaf.into()),
+        ..Default::default()
+    });
+    assert!(err.contains("custom entry point"), "{err}");
+    let err = load(GuestLayout {
+        registers: [(0, 1)].into(),
+        ..Default::default()
+    });
+    assert!(err.contains("x0"), "{err}");
 }
 
 #[test]
@@ -1151,3 +3739,56 @@ mod docker {
         assert!(run_session(1 << 16, 15, 16).is_ok());
     }
 }
+
+#[cfg(feature = "dev")]
+mod dev {
+    use risc0_zkvm_methods::{multi_test::MultiTestSpec, HELLO_COMMIT_ELF, MULTI_TEST_ELF};
+
+    use crate::{DevSession, ExecutorEnv, ExitCode, JournalDiff};
+
+    #[test]
+    fn reload() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("guest");
+        let env = ExecutorEnv::builder()
+            .write(&MultiTestSpec::DoNothing)
+            .unwrap()
+            .build()
+            .unwrap();
+        let mut session = DevSession::new(&path, env.manifest());
+
+        std::fs::write(&path, MULTI_TEST_ELF).unwrap();
+        let first = session.poll().unwrap().unwrap();
+        assert!(first.previous.is_none());
+        assert_eq!(first.run.exit_code, ExitCode::Halted(0));
+        assert!(first.run.journal.is_empty());
+        assert!(session.poll().unwrap().is_none());
+
+        // Swapping in another build reruns it with the same input.
+        std::fs::write(&path, HELLO_COMMIT_ELF).unwrap();
+        let second = session.poll().unwrap().unwrap();
+        assert_eq!(second.previous.as_ref(), Some(&first.run));
+        assert_ne!(second.run.image_id, first.run.image_id);
+        assert_eq!(
+            second.journal_diff(),
+            Some(JournalDiff {
+                offset: 0,
+                previous_len: 0,
+                len: second.run.journal.len(),
+            })
+        );
+        assert_eq!(
+            second.cycle_delta(),
+            Some(second.run.cycles as i64 - first.run.cycles as i64)
+        );
+
+        std::fs::write(&path, MULTI_TEST_ELF).unwrap();
+        let third = session.poll().unwrap().unwrap();
+        assert_eq!(third.run, first.run);
+        assert_eq!(third.journal_diff().unwrap().len, 0);
+        assert_eq!(
+            third.cycle_delta(),
+            second.cycle_delta().map(|delta| -delta)
+        );
+    }
+}
@@ -0,0 +1,249 @@
+// Copyright 2023 RISC Zero, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Estimates how much of each segment a guest would spend paging memory at
+//! each segment size, from the pages it touches over a run.
+
+use std::collections::BTreeSet;
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+
+use super::{executor::segment_overhead_cycles, monitor::PAGE_CYCLES};
+use crate::{
+    host::api::server::EmptySegmentRef, ExecutorEnv, ExecutorImpl, Loader, MAX_SEGMENT_LIMIT_PO2,
+};
+
+/// The size, in powers of 2 cycles, of the windows over which the pages
+/// touched by the guest are tracked, and so the smallest segment size
+/// estimated.
+const WINDOW_PO2: u32 = 15;
+
+/// The most windows tracked at once. Beyond this, adjacent windows are merged
+/// in pairs, doubling their size.
+const MAX_WINDOWS: usize = 1 << 12;
+
+/// The largest share of the cycles of a segment that may be spent paging at
+/// the segment size recommended by [SegmentPo2Report].
+pub const MAX_PAGING_OVERHEAD: f64 = 0.25;
+
+/// The estimated cost of running a guest with segments of one size.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct SegmentPo2Estimate {
+    /// The segment size, in powers of 2 cycles.
+    pub po2: u32,
+
+    /// The number of segments.
+    pub segments: u64,
+
+    /// The cycles spent paging memory in and out, over all segments.
+    pub paging_cycles: u64,
+
+    /// The cycles spent running the guest, over all segments.
+    pub user_cycles: u64,
+}
+
+impl SegmentPo2Estimate {
+    /// The share of the cycles of the segments, not counting the fixed
+    /// overhead of each segment, that are spent paging.
+    pub fn paging_overhead(&self) -> f64 {
+        let cycles = self.paging_cycles + self.user_cycles;
+        if cycles == 0 {
+            return 0.0;
+        }
+        self.paging_cycles as f64 / cycles as f64
+    }
+
+    /// The number of cycles proven, including the padding of each segment to
+    /// its full size.
+    pub fn proven_cycles(&self) -> u64 {
+        self.segments << self.po2
+    }
+}
+
+/// The estimated paging overhead of a guest at each segment size; see
+/// [Prover::recommend_segment_po2](crate::Prover::recommend_segment_po2).
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct SegmentPo2Report {
+    /// The smallest segment size at which at most [MAX_PAGING_OVERHEAD] of
+    /// the cycles are spent paging, or the largest size estimated if there
+    /// is none.
+    pub recommended_po2: u32,
+
+    /// The estimates, from the smallest segment size up to the first size at
+    /// which the whole run fits in one segment.
+    pub estimates: Vec<SegmentPo2Estimate>,
+}
+
+impl SegmentPo2Report {
+    /// The estimate for segments of `po2`, if it was made.
+    pub fn estimate(&self, po2: u32) -> Option<&SegmentPo2Estimate> {
+        self.estimates.iter().find(|estimate| estimate.po2 == po2)
+    }
+}
+
+// A set of page indices, kept as the nonzero words of a bitset so that its
+// size follows the number of pages rather than the size of memory.
+#[derive(Clone, Default)]
+struct PageSet(Vec<(u32, u64)>);
+
+impl PageSet {
+    fn new(pages: &BTreeSet<u32>) -> Self {
+        let mut words: Vec<(u32, u64)> = Vec::new();
+        for &page in pages {
+            let (idx, bit) = (page / 64, 1 << (page % 64));
+            match words.last_mut() {
+                Some((last, word)) if *last == idx => *word |= bit,
+                _ => words.push((idx, bit)),
+            }
+        }
+        Self(words)
+    }
+
+    fn union(&self, other: &Self) -> Self {
+        let (mut a, mut b) = (self.0.iter().peekable(), other.0.iter().peekable());
+        let mut words = Vec::with_capacity(self.0.len().max(other.0.len()));
+        loop {
+            let word = match (a.peek(), b.peek()) {
+                (Some(x), Some(y)) if x.0 == y.0 => (x.0, x.1 | y.1),
+                (Some(x), Some(y)) if x.0 < y.0 => **x,
+                (Some(_), Some(y)) => **y,
+                (Some(x), None) => **x,
+                (None, Some(y)) => **y,
+                (None, None) => return Self(words),
+            };
+            if a.peek().map_or(false, |x| x.0 == word.0) {
+                a.next();
+            }
+            if b.peek().map_or(false, |y| y.0 == word.0) {
+                b.next();
+            }
+            words.push(word);
+        }
+    }
+
+    fn len(&self) -> u64 {
+        self.0
+            .iter()
+            .map(|(_, word)| word.count_ones() as u64)
+            .sum()
+    }
+}
+
+// The pages touched over a stretch of the run.
+#[derive(Clone)]
+struct Window {
+    reads: PageSet,
+    writes: PageSet,
+    user_cycles: u64,
+}
+
+impl Window {
+    fn merge(&self, other: &Self) -> Self {
+        Self {
+            reads: self.reads.union(&other.reads),
+            writes: self.writes.union(&other.writes),
+            user_cycles: self.user_cycles + other.user_cycles,
+        }
+    }
+
+    fn paging_cycles(&self) -> u64 {
+        (self.reads.len() + self.writes.len()) * PAGE_CYCLES as u64
+    }
+}
+
+/// Execute `elf` in segments of [WINDOW_PO2] cycles, recording the pages each
+/// one touches, and estimate the paging overhead of larger segments by
+/// merging consecutive windows.
+pub(crate) fn recommend(mut env: ExecutorEnv<'_>, elf: &[u8]) -> Result<SegmentPo2Report> {
+    env.segment_limit_po2 = Some(WINDOW_PO2);
+    let mut window_po2 = WINDOW_PO2;
+    let mut windows = Vec::new();
+    ExecutorImpl::from_elf(env, elf)?.run_with_callback(|segment| {
+        windows.push(Window {
+            reads: PageSet::new(&segment.faults.reads),
+            writes: PageSet::new(&segment.faults.writes),
+            user_cycles: segment.cycles as u64,
+        });
+        if windows.len() > MAX_WINDOWS {
+            windows = windows
+                .chunks(2)
+                .map(|pair| match pair {
+                    [a, b] => a.merge(b),
+                    _ => pair[0].clone(),
+                })
+                .collect();
+            window_po2 += 1;
+        }
+        Ok(Box::new(EmptySegmentRef))
+    })?;
+
+    let overhead = segment_overhead_cycles(&Loader::new()) as u64;
+    let mut estimates = Vec::new();
+    for po2 in window_po2..=MAX_SEGMENT_LIMIT_PO2 {
+        let estimate = estimate(&windows, po2, overhead);
+        let done = estimate.segments <= 1;
+        estimates.push(estimate);
+        if done {
+            break;
+        }
+    }
+    let recommended_po2 = estimates
+        .iter()
+        .find(|estimate| estimate.paging_overhead() <= MAX_PAGING_OVERHEAD)
+        .or(estimates.last())
+        .map_or(window_po2, |estimate| estimate.po2);
+    Ok(SegmentPo2Report {
+        recommended_po2,
+        estimates,
+    })
+}
+
+// Split the windows into segments of `po2` cycles, each taking windows in
+// order for as long as their user cycles and the pages they touch between
+// them fit.
+fn estimate(windows: &[Window], po2: u32, overhead: u64) -> SegmentPo2Estimate {
+    let budget = (1u64 << po2).saturating_sub(overhead);
+    let mut estimate = SegmentPo2Estimate {
+        po2,
+        segments: 0,
+        paging_cycles: 0,
+        user_cycles: 0,
+    };
+    let mut add = |segment: &Window| {
+        estimate.segments += 1;
+        estimate.paging_cycles += segment.paging_cycles();
+        estimate.user_cycles += segment.user_cycles;
+    };
+
+    let mut segment: Option<Window> = None;
+    for window in windows {
+        segment = Some(match segment.take() {
+            None => window.clone(),
+            Some(current) => {
+                let merged = current.merge(window);
+                if merged.user_cycles + merged.paging_cycles() > budget {
+                    add(&current);
+                    window.clone()
+                } else {
+                    merged
+                }
+            }
+        });
+    }
+    if let Some(current) = &segment {
+        add(current);
+    }
+    estimate
+}
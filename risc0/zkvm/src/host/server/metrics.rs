@@ -0,0 +1,124 @@
+// Copyright 2023 RISC Zero, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Metrics recorded through the [metrics] facade when the `metrics` feature
+//! is enabled.
+//!
+//! Nothing is recorded until the application installs a recorder, for example
+//! the one from `metrics-exporter-prometheus`. The metrics are:
+//!
+//! | Name                           | Kind      | Labels                |
+//! |--------------------------------|-----------|-----------------------|
+//! | `risc0_proofs_started`         | counter   | `hashfn`              |
+//! | `risc0_proofs_completed`       | counter   | `hashfn`, `exit_code` |
+//! | `risc0_proofs_failed`          | counter   | `hashfn`              |
+//! | `risc0_cycles_total`           | counter   | `hashfn`              |
+//! | `risc0_segment_po2`            | histogram | `hashfn`              |
+//! | `risc0_seal_bytes`             | histogram | `hashfn`              |
+//! | `risc0_prove_duration_seconds` | histogram | `phase`               |
+//! | `risc0_syscall_total`          | counter   | `syscall`             |
+//!
+//! Every label takes one of a small, fixed set of values, so that the number
+//! of series stays bounded: `exit_code` is the name of the [ExitCode] variant
+//! without the user's exit code, `phase` is one of `execute`, `segment`,
+//! `verify`, `lift`, `join` or `identity_p254`, and `syscall` is the name of a
+//! syscall known to the executor.
+
+use std::time::Instant;
+
+use metrics::{counter, histogram};
+
+use crate::{ExitCode, Receipt};
+
+/// Counts a proof of a session, as failed unless [ProofMetrics::completed] is
+/// called before it is dropped.
+pub(crate) struct ProofMetrics {
+    hashfn: String,
+    done: bool,
+}
+
+impl ProofMetrics {
+    pub(crate) fn start(hashfn: &str) -> Self {
+        counter!("risc0_proofs_started", "hashfn" => hashfn.to_string()).increment(1);
+        Self {
+            hashfn: hashfn.to_string(),
+            done: false,
+        }
+    }
+
+    pub(crate) fn cycles(&self, cycles: u64) {
+        counter!("risc0_cycles_total", "hashfn" => self.hashfn.clone()).increment(cycles);
+    }
+
+    pub(crate) fn segment(&self, po2: u32) {
+        histogram!("risc0_segment_po2", "hashfn" => self.hashfn.clone()).record(po2 as f64);
+    }
+
+    pub(crate) fn completed(mut self, receipt: &Receipt, exit_code: ExitCode) {
+        self.done = true;
+        let seal_bytes = crate::host::client::prove::report::seal_bytes(receipt);
+        histogram!("risc0_seal_bytes", "hashfn" => self.hashfn.clone()).record(seal_bytes as f64);
+        counter!(
+            "risc0_proofs_completed",
+            "hashfn" => self.hashfn.clone(),
+            "exit_code" => exit_code_label(exit_code)
+        )
+        .increment(1);
+    }
+}
+
+impl Drop for ProofMetrics {
+    fn drop(&mut self) {
+        if !self.done {
+            counter!("risc0_proofs_failed", "hashfn" => self.hashfn.clone()).increment(1);
+        }
+    }
+}
+
+/// Records the time from its creation until it is dropped as the duration of
+/// `phase`.
+pub(crate) struct PhaseTimer {
+    phase: &'static str,
+    start: Instant,
+}
+
+impl PhaseTimer {
+    pub(crate) fn start(phase: &'static str) -> Self {
+        Self {
+            phase,
+            start: Instant::now(),
+        }
+    }
+}
+
+impl Drop for PhaseTimer {
+    fn drop(&mut self) {
+        histogram!("risc0_prove_duration_seconds", "phase" => self.phase)
+            .record(self.start.elapsed().as_secs_f64());
+    }
+}
+
+pub(crate) fn syscall(name: &str) {
+    counter!("risc0_syscall_total", "syscall" => name.to_string()).increment(1);
+}
+
+fn exit_code_label(exit_code: ExitCode) -> &'static str {
+    match exit_code {
+        ExitCode::SystemSplit => "system_split",
+        ExitCode::SessionLimit => "session_limit",
+        ExitCode::Paused(_) => "paused",
+        ExitCode::Halted(_) => "halted",
+        ExitCode::Fault => "fault",
+    }
+}
@@ -13,6 +13,8 @@
 // limitations under the License.
 
 pub(crate) mod exec;
+#[cfg(feature = "metrics")]
+pub(crate) mod metrics;
 pub(crate) mod opcode;
 #[cfg(feature = "prove")]
 pub(crate) mod prove;
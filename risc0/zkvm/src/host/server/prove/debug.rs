@@ -0,0 +1,178 @@
+// Copyright 2023 RISC Zero, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Tools for finding where two HALs disagree when proving the same segment.
+
+use std::fmt;
+
+use anyhow::{ensure, Result};
+use risc0_circuit_rv32im::{REGISTER_GROUP_ACCUM, REGISTER_GROUP_CODE, REGISTER_GROUP_DATA};
+use risc0_core::field::baby_bear::{BabyBear, Elem, ExtElem};
+use risc0_zkp::{
+    adapter::TapsProvider,
+    hal::{Buffer, CircuitHal, Hal},
+    prove::{adapter::ProveAdapter, Prover},
+};
+
+use super::{prover_impl::execute_segment, HalPair};
+use crate::{
+    host::CIRCUIT,
+    sha::{Digest, Impl, Sha256 as _},
+    Segment,
+};
+
+/// The digests of one artifact of proving, as made by each of two HALs; see
+/// [compare_hals].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct HalArtifact {
+    /// The name of the artifact: `"code"`, `"data"` or `"accum"` for the
+    /// Merkle root of a register group, `"check"` for the root of the check
+    /// polynomial, `"u"` for the hash of the evaluations at the DEEP-ALI
+    /// query point, or `"seal"` for the hash of the seal.
+    pub name: String,
+
+    /// The digest made by the first HAL.
+    pub a: Digest,
+
+    /// The digest made by the second HAL.
+    pub b: Digest,
+}
+
+impl HalArtifact {
+    /// Whether both HALs made the same artifact.
+    pub fn matches(&self) -> bool {
+        self.a == self.b
+    }
+}
+
+/// The result of [compare_hals].
+#[derive(Clone, Debug)]
+pub struct HalComparison {
+    /// The artifacts of proving, in the order they were made.
+    pub artifacts: Vec<HalArtifact>,
+}
+
+impl HalComparison {
+    /// The first artifact that the HALs disagree on. Every commitment feeds
+    /// the transcript, so later artifacts usually differ as well.
+    pub fn first_difference(&self) -> Option<&HalArtifact> {
+        self.artifacts.iter().find(|artifact| !artifact.matches())
+    }
+
+    /// Whether the HALs made the same seal, and the same commitments along
+    /// the way.
+    pub fn matches(&self) -> bool {
+        self.first_difference().is_none()
+    }
+}
+
+impl fmt::Display for HalComparison {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.first_difference() {
+            None => write!(f, "the HALs made the same seal"),
+            Some(artifact) => write!(
+                f,
+                "the HALs first differ at {}: {} != {}",
+                artifact.name, artifact.a, artifact.b
+            ),
+        }
+    }
+}
+
+/// Prove `segment` with the HALs of both `a` and `b`, and compare the
+/// commitments they make and the seals they produce.
+///
+/// This is for tracking down a HAL that produces invalid seals: rather than
+/// proving twice and comparing the seals, both HALs prove the same trace in
+/// lock step. The segment is executed once, and the accumulation trace,
+/// which depends on random noise, is computed once from the transcript of
+/// `a`. Proving is otherwise deterministic, so correct HALs agree on every
+/// artifact, and the first one they disagree on names the phase in which
+/// they diverge.
+///
+/// Both HALs must use the same hash suite.
+pub fn compare_hals<HA, CA, HB, CB>(
+    segment: &Segment,
+    a: &HalPair<HA, CA>,
+    b: &HalPair<HB, CB>,
+) -> Result<HalComparison>
+where
+    HA: Hal<Field = BabyBear, Elem = Elem, ExtElem = ExtElem>,
+    CA: CircuitHal<HA>,
+    HB: Hal<Field = BabyBear, Elem = Elem, ExtElem = ExtElem>,
+    CB: CircuitHal<HB>,
+{
+    let (suite_a, suite_b) = (a.hal.get_hash_suite(), b.hal.get_hash_suite());
+    ensure!(
+        suite_a.name == suite_b.name,
+        "HALs with different hash suites ({} and {}) cannot be compared",
+        suite_a.name,
+        suite_b.name
+    );
+
+    let mut executor = execute_segment(segment)?;
+    let mut adapter = ProveAdapter::new(&mut executor);
+    let (mut commits_a, mut commits_b) = (Vec::new(), Vec::new());
+    let mut prover_a = Prover::new(a.hal.as_ref(), CIRCUIT.get_taps());
+    prover_a.set_commit_hook(|name, digest| commits_a.push((name.to_string(), *digest)));
+    let mut prover_b = Prover::new(b.hal.as_ref(), CIRCUIT.get_taps());
+    prover_b.set_commit_hook(|name, digest| commits_b.push((name.to_string(), *digest)));
+
+    adapter.execute(prover_a.iop());
+    adapter.execute(prover_b.iop());
+    prover_a.set_po2(adapter.po2() as usize);
+    prover_b.set_po2(adapter.po2() as usize);
+
+    for (group, name, trace) in [
+        (REGISTER_GROUP_CODE, "code", adapter.get_code()),
+        (REGISTER_GROUP_DATA, "data", adapter.get_data()),
+    ] {
+        prover_a.commit_group(group, a.hal.copy_from_elem(name, &trace.as_slice()));
+        prover_b.commit_group(group, b.hal.copy_from_elem(name, &trace.as_slice()));
+    }
+
+    // Draw as many mix values from the transcript of `b` as the adapter draws
+    // from that of `a`, so the transcripts stay in step.
+    adapter.accumulate(prover_a.iop());
+    for _ in 0..adapter.get_mix().size() {
+        prover_b.iop().random_elem();
+    }
+    let accum = adapter.get_accum().as_slice();
+    prover_a.commit_group(REGISTER_GROUP_ACCUM, a.hal.copy_from_elem("accum", &accum));
+    prover_b.commit_group(REGISTER_GROUP_ACCUM, b.hal.copy_from_elem("accum", &accum));
+
+    let (mix, io) = (adapter.get_mix().as_slice(), adapter.get_io().as_slice());
+    let (mix_a, io_a) = (
+        a.hal.copy_from_elem("mix", &mix),
+        a.hal.copy_from_elem("out", &io),
+    );
+    let seal_a = prover_a.finalize(&[&mix_a, &io_a], a.circuit_hal.as_ref());
+    let (mix_b, io_b) = (
+        b.hal.copy_from_elem("mix", &mix),
+        b.hal.copy_from_elem("out", &io),
+    );
+    let seal_b = prover_b.finalize(&[&mix_b, &io_b], b.circuit_hal.as_ref());
+
+    let mut artifacts: Vec<_> = commits_a
+        .into_iter()
+        .zip(commits_b)
+        .map(|((name, a), (_, b))| HalArtifact { name, a, b })
+        .collect();
+    artifacts.push(HalArtifact {
+        name: "seal".to_string(),
+        a: *Impl::hash_words(&seal_a),
+        b: *Impl::hash_words(&seal_b),
+    });
+    Ok(HalComparison { artifacts })
+}
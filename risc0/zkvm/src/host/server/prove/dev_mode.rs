@@ -34,7 +34,10 @@ use crate::{
 /// whatsoever.
 ///
 /// How to enable and disable dev mode:
-/// Dev mode is only used when the environment variable `RISC0_DEV_MODE` is set.
+/// Dev mode is only used when the environment variable `RISC0_DEV_MODE` is set,
+/// or when forced by the [SecurityPolicy](crate::SecurityPolicy) of the
+/// [ProverOpts](crate::ProverOpts). [SecurityPolicy::Enforced](crate::SecurityPolicy::Enforced)
+/// turns it off regardless of the environment.
 /// It can be fully disabled at compile time, regardless of environment
 /// variables, by setting the feature flag `disable-dev-mode` on the
 /// `risc0_zkvm` crate.
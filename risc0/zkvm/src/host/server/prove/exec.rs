@@ -152,6 +152,11 @@ pub struct MachineContext {
     split_insn: Option<u32>,
 
     insn_counter: u32,
+
+    // The pc of the instruction decoded at each cycle, to locate a RAM plonk
+    // row that fails its check.
+    #[cfg(feature = "circuit-debug")]
+    pcs: BTreeMap<u32, u32>,
 }
 
 impl CircuitStepHandler<Elem> for MachineContext {
@@ -272,9 +277,27 @@ impl MachineContext {
             resident_words: BTreeSet::new(),
             split_insn: segment.split_insn,
             insn_counter: 0,
+            #[cfg(feature = "circuit-debug")]
+            pcs: BTreeMap::new(),
         }
     }
 
+    /// The first row of the RAM plonk table that was found inconsistent while
+    /// generating the witness, with the pc of the instruction it belongs to.
+    #[cfg(feature = "circuit-debug")]
+    pub(crate) fn plonk_error(&self) -> Option<plonk::PlonkRowError> {
+        let mut err = self.memory.ram_plonk.error()?.clone();
+        err.pc = self.pcs.range(..=err.cycle).next_back().map(|(_, pc)| *pc);
+        Some(err)
+    }
+
+    /// Corrupt the value of the `n`th RAM plonk row that is checked against
+    /// the row before it.
+    #[cfg(all(test, feature = "circuit-debug"))]
+    pub(crate) fn corrupt_ram_row(&mut self, n: usize) {
+        self.memory.ram_plonk.corrupt(n);
+    }
+
     fn halt(&mut self, cycle: usize, exit_code: Elem, pc: Elem) {
         if !self.is_halted {
             let exit_code = exit_code.into();
@@ -299,6 +322,8 @@ impl MachineContext {
     fn get_major(&mut self, cycle: Elem, pc: Elem) -> Result<Elem> {
         let cycle: u32 = cycle.into();
         let pc: u32 = pc.into();
+        #[cfg(feature = "circuit-debug")]
+        self.pcs.insert(cycle, pc);
         let insn = self.memory.load_u32(pc);
         let opcode = OpCode::decode(insn, pc)?;
 
@@ -14,19 +14,28 @@
 
 //! Run the zkVM guest and prove its results.
 
+pub(crate) mod debug;
 mod dev_mode;
 mod exec;
 pub(crate) mod loader;
-mod plonk;
+pub(crate) mod plonk;
 mod prover_impl;
+pub(crate) mod session_prover;
+// The tests also cover the deprecated ProverOpts facade.
 #[cfg(test)]
+#[allow(deprecated)]
 mod tests;
 
-use std::rc::Rc;
+use std::{
+    cell::RefCell,
+    collections::BTreeSet,
+    rc::Rc,
+    sync::{Arc, Mutex},
+};
 
-use anyhow::Result;
+use anyhow::{anyhow, bail, Context, Result};
 use cfg_if::cfg_if;
-use risc0_binfmt::{MemoryImage, Program};
+use risc0_binfmt::MemoryImage;
 use risc0_circuit_rv32im::CircuitImpl;
 use risc0_core::field::{
     baby_bear::{BabyBear, Elem, ExtElem},
@@ -37,14 +46,61 @@ use risc0_zkp::{
     core::digest::DIGEST_WORDS,
     hal::{CircuitHal, Hal},
 };
-use risc0_zkvm_platform::{memory::GUEST_MAX_MEM, PAGE_SIZE, WORD_SIZE};
+use risc0_zkvm_platform::WORD_SIZE;
 
 use self::{dev_mode::DevModeProver, prover_impl::ProverImpl};
 use crate::{
-    host::receipt::{SegmentReceipt, SuccinctReceipt},
-    is_dev_mode, ExecutorEnv, ExecutorImpl, ProverOpts, Receipt, Segment, Session, VerifierContext,
+    host::{
+        receipt::{SegmentReceipt, SuccinctReceipt},
+        server::exec::executor::segment_overhead_cycles,
+    },
+    ExecutorEnv, ExecutorImpl, Loader, ProveOpts, Receipt, Segment, SegmentAction, Session,
+    VerifierContext,
 };
 
+/// A callback run on each [Segment] as execution produces it; see
+/// [ProverOpts::with_segment_hook].
+pub(crate) type SegmentHook = Arc<Mutex<dyn FnMut(&Segment) -> Result<SegmentAction> + Send>>;
+
+/// A callback run before each [Segment] of a [Session] is proven, which
+/// lets the proving service report progress.
+pub(crate) type ProveObserver = Arc<dyn Fn(&Segment) + Send + Sync>;
+
+/// Execute `image` in `env`, running `segment_hook`, if any, on each
+/// [Segment] as soon as it is produced. The segments the hook skips are
+/// recorded in [Session::skipped_segments].
+pub(crate) fn execute_with_hook(
+    mut env: ExecutorEnv<'_>,
+    image: MemoryImage,
+    segment_hook: Option<&SegmentHook>,
+) -> Result<Session> {
+    let skipped = Rc::new(RefCell::new(BTreeSet::new()));
+    if let Some(segment_hook) = segment_hook {
+        let (segment_hook, skipped) = (segment_hook.clone(), skipped.clone());
+        env.segment_callbacks
+            .push(Rc::new(RefCell::new(move |segment: &Segment| {
+                let mut segment_hook = segment_hook
+                    .lock()
+                    .map_err(|_| anyhow!("segment hook panicked"))?;
+                let action = segment_hook(segment)
+                    .with_context(|| format!("segment hook failed on segment {}", segment.index))?;
+                match action {
+                    SegmentAction::Prove => (),
+                    SegmentAction::Skip => {
+                        skipped.borrow_mut().insert(segment.index);
+                    }
+                    SegmentAction::Abort => {
+                        bail!("segment hook aborted proving at segment {}", segment.index)
+                    }
+                }
+                Ok(())
+            })));
+    }
+    let mut session = ExecutorImpl::new(env, image)?.run()?;
+    session.skipped_segments = skipped.take();
+    Ok(session)
+}
+
 /// A ProverServer can execute a given [MemoryImage] and produce a [Receipt]
 /// that can be used to verify correct computation.
 pub trait ProverServer {
@@ -72,8 +128,8 @@ pub trait ProverServer {
         ctx: &VerifierContext,
         elf: &[u8],
     ) -> Result<Receipt> {
-        let program = Program::load_elf(elf, GUEST_MAX_MEM as u32)?;
-        let image = MemoryImage::new(&program, PAGE_SIZE as u32)?;
+        env.check_input_schema(elf)?;
+        let image = env.layout.load_elf(elf)?;
         self.prove(env, ctx, image)
     }
 
@@ -113,7 +169,13 @@ where
 impl Session {
     /// For each segment, call [Segment::prove] and collect the receipts.
     pub fn prove(&self) -> Result<Receipt> {
-        let prover = get_prover_server(&ProverOpts::default())?;
+        self.prove_with(&ProveOpts::default())
+    }
+
+    /// Prove each segment with the prover selected by `opts`, and collect the
+    /// receipts.
+    pub fn prove_with(&self, opts: &ProveOpts) -> Result<Receipt> {
+        let prover = get_prover_server_for(opts)?;
         prover.prove_session(&VerifierContext::default(), self)
     }
 }
@@ -121,7 +183,12 @@ impl Session {
 impl Segment {
     /// Call the ZKP system to produce a [SegmentReceipt].
     pub fn prove(&self, ctx: &VerifierContext) -> Result<SegmentReceipt> {
-        let prover = get_prover_server(&ProverOpts::default())?;
+        self.prove_with(ctx, &ProveOpts::default())
+    }
+
+    /// Produce a [SegmentReceipt] with the prover selected by `opts`.
+    pub fn prove_with(&self, ctx: &VerifierContext, opts: &ProveOpts) -> Result<SegmentReceipt> {
+        let prover = get_prover_server_for(opts)?;
         prover.prove_segment(ctx, self)
     }
 
@@ -162,30 +229,31 @@ mod cuda {
     use std::rc::Rc;
 
     use anyhow::{bail, Result};
-    use risc0_circuit_rv32im::cuda::{CudaCircuitHalPoseidon, CudaCircuitHalSha256};
-    use risc0_zkp::hal::cuda::{CudaHalPoseidon, CudaHalSha256};
+    use risc0_circuit_rv32im::cuda::{
+        CudaCircuitHalPoseidon, CudaCircuitHalPoseidon2, CudaCircuitHalSha256,
+    };
+    use risc0_zkp::hal::cuda::{CudaHalPoseidon, CudaHalPoseidon2, CudaHalSha256};
 
-    use super::{HalPair, ProverImpl, ProverServer};
-    use crate::ProverOpts;
+    use super::{prover_impl, HalPair, ProverServer};
+    use crate::ProveOpts;
 
-    pub fn get_prover_server(opts: &ProverOpts) -> Result<Rc<dyn ProverServer>> {
+    pub fn get_prover_server(opts: &ProveOpts) -> Result<Rc<dyn ProverServer>> {
         match opts.hashfn.as_str() {
-            "sha-256" => {
+            "sha-256" => Ok(prover_impl("cuda", opts, || {
                 let hal = Rc::new(CudaHalSha256::new());
                 let circuit_hal = Rc::new(CudaCircuitHalSha256::new(hal.clone()));
-                Ok(Rc::new(ProverImpl::new(
-                    "cuda",
-                    HalPair { hal, circuit_hal },
-                )))
-            }
-            "poseidon" => {
+                HalPair { hal, circuit_hal }
+            })),
+            "poseidon" => Ok(prover_impl("cuda", opts, || {
                 let hal = Rc::new(CudaHalPoseidon::new());
                 let circuit_hal = Rc::new(CudaCircuitHalPoseidon::new(hal.clone()));
-                Ok(Rc::new(ProverImpl::new(
-                    "cuda",
-                    HalPair { hal, circuit_hal },
-                )))
-            }
+                HalPair { hal, circuit_hal }
+            })),
+            "poseidon2" => Ok(prover_impl("cuda", opts, || {
+                let hal = Rc::new(CudaHalPoseidon2::new());
+                let circuit_hal = Rc::new(CudaCircuitHalPoseidon2::new(hal.clone()));
+                HalPair { hal, circuit_hal }
+            })),
             _ => bail!("Unsupported hashfn: {}", opts.hashfn),
         }
     }
@@ -198,30 +266,30 @@ mod metal {
     use anyhow::{bail, Result};
     use risc0_circuit_rv32im::metal::MetalCircuitHal;
     use risc0_zkp::hal::metal::{
-        MetalHalPoseidon, MetalHalSha256, MetalHashPoseidon, MetalHashSha256,
+        MetalHalPoseidon, MetalHalPoseidon2, MetalHalSha256, MetalHashPoseidon, MetalHashPoseidon2,
+        MetalHashSha256,
     };
 
-    use super::{HalPair, ProverImpl, ProverServer};
-    use crate::ProverOpts;
+    use super::{prover_impl, HalPair, ProverServer};
+    use crate::ProveOpts;
 
-    pub fn get_prover_server(opts: &ProverOpts) -> Result<Rc<dyn ProverServer>> {
+    pub fn get_prover_server(opts: &ProveOpts) -> Result<Rc<dyn ProverServer>> {
         match opts.hashfn.as_str() {
-            "sha-256" => {
+            "sha-256" => Ok(prover_impl("metal", opts, || {
                 let hal = Rc::new(MetalHalSha256::new());
                 let circuit_hal = Rc::new(MetalCircuitHal::<MetalHashSha256>::new(hal.clone()));
-                Ok(Rc::new(ProverImpl::new(
-                    "metal",
-                    HalPair { hal, circuit_hal },
-                )))
-            }
-            "poseidon" => {
+                HalPair { hal, circuit_hal }
+            })),
+            "poseidon" => Ok(prover_impl("metal", opts, || {
                 let hal = Rc::new(MetalHalPoseidon::new());
                 let circuit_hal = Rc::new(MetalCircuitHal::<MetalHashPoseidon>::new(hal.clone()));
-                Ok(Rc::new(ProverImpl::new(
-                    "metal",
-                    HalPair { hal, circuit_hal },
-                )))
-            }
+                HalPair { hal, circuit_hal }
+            })),
+            "poseidon2" => Ok(prover_impl("metal", opts, || {
+                let hal = Rc::new(MetalHalPoseidon2::new());
+                let circuit_hal = Rc::new(MetalCircuitHal::<MetalHashPoseidon2>::new(hal.clone()));
+                HalPair { hal, circuit_hal }
+            })),
             _ => bail!("Unsupported hashfn: {}", opts.hashfn),
         }
     }
@@ -234,30 +302,68 @@ mod cpu {
     use anyhow::{bail, Result};
     use risc0_circuit_rv32im::cpu::CpuCircuitHal;
     use risc0_zkp::{
-        core::hash::{poseidon::PoseidonHashSuite, sha::Sha256HashSuite},
+        core::hash::{
+            poseidon::PoseidonHashSuite, poseidon2::Poseidon2HashSuite, sha::Sha256HashSuite,
+        },
         hal::cpu::CpuHal,
     };
 
-    use super::{HalPair, ProverImpl, ProverServer};
-    use crate::{host::CIRCUIT, ProverOpts};
+    use super::{prover_impl, HalPair, ProverServer};
+    use crate::{host::CIRCUIT, ProveOpts};
 
-    pub fn get_prover_server(opts: &ProverOpts) -> Result<Rc<dyn ProverServer>> {
+    pub fn get_prover_server(opts: &ProveOpts) -> Result<Rc<dyn ProverServer>> {
         let suite = match opts.hashfn.as_str() {
             "sha-256" => Sha256HashSuite::new_suite(),
             "poseidon" => PoseidonHashSuite::new_suite(),
+            "poseidon2" => Poseidon2HashSuite::new_suite(),
             _ => bail!("Unsupported hashfn: {}", opts.hashfn),
         };
-        let hal = Rc::new(CpuHal::new(suite));
-        let circuit_hal = Rc::new(CpuCircuitHal::new(&CIRCUIT));
-        let hal_pair = HalPair { hal, circuit_hal };
-        Ok(Rc::new(ProverImpl::new("cpu", hal_pair)))
+        Ok(prover_impl("cpu", opts, move || HalPair {
+            hal: Rc::new(CpuHal::new(suite.clone())),
+            circuit_hal: Rc::new(CpuCircuitHal::new(&CIRCUIT)),
+        }))
     }
 }
 
-/// Select a [ProverServer] based on the specified [ProverOpts] and currently
+// Construct a [ProverImpl] configured by `opts` that proves with the HALs
+// made by `make_hals`, which also makes the fresh HALs for retried segments.
+fn prover_impl<H, C>(
+    name: &str,
+    opts: &ProveOpts,
+    make_hals: impl Fn() -> HalPair<H, C> + 'static,
+) -> Rc<dyn ProverServer>
+where
+    H: Hal<Field = BabyBear, Elem = Elem, ExtElem = ExtElem> + 'static,
+    C: CircuitHal<H> + 'static,
+{
+    Rc::new(
+        ProverImpl::new(name, make_hals())
+            .with_hal_factory(make_hals)
+            .with_segment_hook(opts.segment_hook.clone())
+            .with_prove_observer(opts.prove_observer.clone())
+            .with_cancel_token(opts.cancel_token.clone())
+            .with_auto_po2_downshift(opts.auto_po2_downshift)
+            .with_segment_retries(opts.segment_retries, opts.segment_retry_backoff)
+            .with_verify_policy(opts.verify_policy),
+    )
+}
+
+/// Select a [ProverServer] based on the specified
+/// [ProverOpts](crate::ProverOpts) and currently compiled features.
+///
+/// Only the proving options are used; see [get_prover_server_for], which
+/// takes them on their own.
+// ProverOpts is deprecated, but this entry point keeps accepting it until it
+// is removed.
+#[allow(deprecated)]
+pub fn get_prover_server(opts: &crate::ProverOpts) -> Result<Rc<dyn ProverServer>> {
+    get_prover_server_for(&opts.prove_opts())
+}
+
+/// Select a [ProverServer] based on the specified [ProveOpts] and currently
 /// compiled features.
-pub fn get_prover_server(opts: &ProverOpts) -> Result<Rc<dyn ProverServer>> {
-    if is_dev_mode() {
+pub fn get_prover_server_for(opts: &ProveOpts) -> Result<Rc<dyn ProverServer>> {
+    if opts.security_policy.is_dev_mode() {
         eprintln!("WARNING: proving in dev mode. This will not generate valid, secure proofs.");
         return Ok(Rc::new(DevModeProver));
     }
@@ -272,3 +378,12 @@ pub fn get_prover_server(opts: &ProverOpts) -> Result<Rc<dyn ProverServer>> {
         }
     }
 }
+
+/// The number of cycles a segment of `2^po2` cycles has available for the
+/// guest, after the fixed overhead of loading and finalizing the segment.
+///
+/// Paging memory in and out also takes a share of these cycles at runtime,
+/// so the guest gets at most this many cycles per segment.
+pub fn cycles_for_po2(po2: u32) -> usize {
+    (1usize << po2).saturating_sub(segment_overhead_cycles(&Loader::new()))
+}
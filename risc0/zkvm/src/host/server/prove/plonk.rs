@@ -17,9 +17,13 @@ use std::collections::VecDeque;
 use risc0_core::field::{Elem, ExtElem, Field};
 use risc0_zkp::MAX_CYCLES;
 
+// The memory op of a RAM plonk row that writes, as MemoryOp::Write in exec.rs.
+#[cfg(feature = "circuit-debug")]
+const WRITE_OP: u32 = 2;
+
 // Main RAM plonk rows have the following 7 plonk elements:
 // addr, cycle, isWrite, byte0, byte1, byte2, byte3
-#[derive(Ord, PartialOrd, Eq, PartialEq)]
+#[derive(Clone, Copy, Ord, PartialOrd, Eq, PartialEq)]
 struct MainRamPlonkRow {
     addr: u32,
     // (cycle << 2) | mem_op
@@ -29,6 +33,107 @@ struct MainRamPlonkRow {
 
 pub struct RamPlonk {
     main_ram: Vec<MainRamPlonkRow>,
+    #[cfg(feature = "circuit-debug")]
+    check: RamCheck,
+}
+
+/// A row of the RAM plonk table that does not agree with the row before it,
+/// found while proving with the `circuit-debug` feature.
+///
+/// The rows are read back sorted by address and then by cycle, and a row for
+/// the same address as the row before it must hold the same value unless it is
+/// a write. A row that does not is what makes the circuit's memory
+/// constraints, and so the proof, fail.
+#[cfg(feature = "circuit-debug")]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PlonkRowError {
+    /// The index of the row in sorted order.
+    pub row: usize,
+    /// The word address of the row, i.e. its byte address divided by 4.
+    pub addr: u32,
+    /// The cycle at which the row was written.
+    pub cycle: u32,
+    /// The memory op of the row: 0 for paging, 1 for a read and 2 for a
+    /// write.
+    pub op: u32,
+    /// The value of the row.
+    pub value: u32,
+    /// The value of the row before it.
+    pub expected: u32,
+    /// The pc of the guest instruction executed at or most recently before
+    /// [PlonkRowError::cycle], if any.
+    pub pc: Option<u32>,
+}
+
+#[cfg(feature = "circuit-debug")]
+impl std::fmt::Display for PlonkRowError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "RAM plonk row {} (address: 0x{:08x}, cycle: {}, op: {}) holds 0x{:08x}, but the row before it holds 0x{:08x}",
+            self.row,
+            self.addr * risc0_zkvm_platform::WORD_SIZE as u32,
+            self.cycle,
+            self.op,
+            self.value,
+            self.expected,
+        )?;
+        if let Some(pc) = self.pc {
+            write!(f, "; pc: 0x{pc:08x}")?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(feature = "circuit-debug")]
+impl std::error::Error for PlonkRowError {}
+
+// Checks each RAM plonk row as it is read back against the row before it.
+#[cfg(feature = "circuit-debug")]
+#[derive(Default)]
+struct RamCheck {
+    prev: Option<MainRamPlonkRow>,
+    rows: usize,
+    // The number of rows checked so far against a row for the same address.
+    checked: usize,
+    error: Option<PlonkRowError>,
+    // Flips the lowest bit of the value of the nth row checked, to simulate a
+    // corrupted witness.
+    #[cfg(test)]
+    corrupt: Option<usize>,
+}
+
+#[cfg(feature = "circuit-debug")]
+impl RamCheck {
+    #[cfg_attr(not(test), allow(unused_mut))]
+    fn check(&mut self, mut row: MainRamPlonkRow) -> MainRamPlonkRow {
+        let idx = self.rows;
+        self.rows += 1;
+        let op = row.cycle_and_write_flag & 3;
+        let prev = self
+            .prev
+            .filter(|prev| prev.addr == row.addr && op != WRITE_OP);
+        if let Some(prev) = prev {
+            #[cfg(test)]
+            if self.corrupt == Some(self.checked) {
+                row.val ^= 1;
+            }
+            self.checked += 1;
+            if row.val != prev.val && self.error.is_none() {
+                self.error = Some(PlonkRowError {
+                    row: idx,
+                    addr: row.addr,
+                    cycle: row.cycle_and_write_flag >> 2,
+                    op,
+                    value: row.val,
+                    expected: prev.val,
+                    pc: None,
+                });
+            }
+        }
+        self.prev = Some(row);
+        row
+    }
 }
 
 impl RamPlonk {
@@ -38,9 +143,24 @@ impl RamPlonk {
 
         RamPlonk {
             main_ram: Vec::new(),
+            #[cfg(feature = "circuit-debug")]
+            check: RamCheck::default(),
         }
     }
 
+    /// The first row read back that does not agree with the row before it.
+    #[cfg(feature = "circuit-debug")]
+    pub fn error(&self) -> Option<&PlonkRowError> {
+        self.check.error.as_ref()
+    }
+
+    /// Corrupt the value of the `n`th row read back that is checked against
+    /// the row before it.
+    #[cfg(all(test, feature = "circuit-debug"))]
+    pub fn corrupt(&mut self, n: usize) {
+        self.check.corrupt = Some(n);
+    }
+
     pub fn write<E: Elem>(&mut self, elems: &[E; 7])
     where
         u32: From<E>,
@@ -77,6 +197,8 @@ impl RamPlonk {
             elems[idx] = E::from_u64(val as u64);
         };
         let row = self.main_ram.pop().unwrap();
+        #[cfg(feature = "circuit-debug")]
+        let row = self.check.check(row);
         set_elem(0, row.addr);
         set_elem(1, row.cycle_and_write_flag >> 2);
         set_elem(2, row.cycle_and_write_flag & 3);
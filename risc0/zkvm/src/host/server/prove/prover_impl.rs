@@ -12,20 +12,33 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use anyhow::{bail, Result};
+use std::{
+    cell::RefCell,
+    panic::{self, AssertUnwindSafe},
+    rc::Rc,
+    time::Duration,
+};
+
+use anyhow::{bail, Context, Result};
+use risc0_binfmt::MemoryImage;
 use risc0_circuit_rv32im::{
     layout::{OutBuffer, LAYOUT},
-    REGISTER_GROUP_ACCUM, REGISTER_GROUP_CODE, REGISTER_GROUP_DATA,
+    CircuitImpl, REGISTER_GROUP_ACCUM, REGISTER_GROUP_CODE, REGISTER_GROUP_DATA,
 };
 use risc0_core::field::baby_bear::{BabyBear, Elem, ExtElem};
 use risc0_zkp::{
     adapter::TapsProvider,
-    hal::{CircuitHal, Hal},
+    hal::{is_out_of_memory, panic_message, CircuitHal, Hal, HalErrorKind},
     layout::Buffer,
-    prove::adapter::ProveAdapter,
+    prove::{adapter::ProveAdapter, executor::Executor},
+    MIN_CYCLES_PO2,
 };
 
-use super::{exec::MachineContext, HalPair, ProverServer};
+use super::{
+    exec::MachineContext, execute_with_hook, HalPair, ProveObserver, ProverServer, SegmentHook,
+};
+#[cfg(feature = "metrics")]
+use crate::host::server::metrics::{PhaseTimer, ProofMetrics};
 use crate::{
     host::{
         receipt::{CompositeReceipt, InnerReceipt, SegmentReceipt, SuccinctReceipt},
@@ -33,9 +46,38 @@ use crate::{
         CIRCUIT,
     },
     sha::Digestible,
-    Loader, Receipt, Segment, Session, VerifierContext,
+    CancelToken, ExecutorEnv, Loader, ProvingCancelled, Receipt, Segment, Session, SessionEvents,
+    VerifierContext, VerifyPolicy,
 };
 
+/// The trace of a segment executed on the circuit.
+pub(super) type SegmentExecutor = Executor<BabyBear, CircuitImpl, MachineContext>;
+
+/// Execute `segment` on the circuit, producing the trace that is proven.
+pub(super) fn execute_segment(segment: &Segment) -> Result<SegmentExecutor> {
+    execute_machine(segment, MachineContext::new(segment))
+}
+
+// Execute `segment` on the circuit with the given machine.
+fn execute_machine(segment: &Segment, machine: MachineContext) -> Result<SegmentExecutor> {
+    let io = segment.prepare_globals();
+    let po2 = segment.po2 as usize;
+    let mut executor = Executor::new(&CIRCUIT, machine, po2, po2, &io);
+
+    let loader = Loader::new();
+    loader.load(|chunk, fini| executor.step(chunk, fini))?;
+    executor.finalize();
+    #[cfg(feature = "circuit-debug")]
+    if let Some(err) = executor.handler.plonk_error() {
+        return Err(err.into());
+    }
+    Ok(executor)
+}
+
+/// Constructs the HALs a [ProverImpl] proves with, to replace those that hit
+/// a device fault.
+pub(crate) type HalFactory<H, C> = Rc<dyn Fn() -> HalPair<H, C>>;
+
 /// An implementation of a Prover that runs locally.
 pub struct ProverImpl<H, C>
 where
@@ -43,7 +85,17 @@ where
     C: CircuitHal<H>,
 {
     name: String,
-    hal_pair: HalPair<H, C>,
+    hal_pair: RefCell<HalPair<H, C>>,
+    hal_factory: Option<HalFactory<H, C>>,
+    segment_hook: Option<SegmentHook>,
+    prove_observer: Option<ProveObserver>,
+    cancel_token: Option<CancelToken>,
+    auto_po2_downshift: bool,
+    segment_retries: u32,
+    segment_retry_backoff: Duration,
+    verify_policy: VerifyPolicy,
+    #[cfg(all(test, feature = "circuit-debug"))]
+    corrupt_ram_row: Option<usize>,
 }
 
 impl<H, C> ProverImpl<H, C>
@@ -55,9 +107,178 @@ where
     pub fn new(name: &str, hal_pair: HalPair<H, C>) -> Self {
         Self {
             name: name.to_string(),
-            hal_pair,
+            hal_pair: RefCell::new(hal_pair),
+            hal_factory: None,
+            segment_hook: None,
+            prove_observer: None,
+            cancel_token: None,
+            auto_po2_downshift: false,
+            segment_retries: 0,
+            segment_retry_backoff: Duration::ZERO,
+            verify_policy: VerifyPolicy::Always,
+            #[cfg(all(test, feature = "circuit-debug"))]
+            corrupt_ram_row: None,
         }
     }
+
+    /// Install the hook to run on each [Segment] as it is executed by
+    /// [ProverServer::prove].
+    pub(crate) fn with_segment_hook(mut self, segment_hook: Option<SegmentHook>) -> Self {
+        self.segment_hook = segment_hook;
+        self
+    }
+
+    /// Install the callback to run before proving each [Segment].
+    pub(crate) fn with_prove_observer(mut self, prove_observer: Option<ProveObserver>) -> Self {
+        self.prove_observer = prove_observer;
+        self
+    }
+
+    /// Stop proving when `cancel_token` is cancelled; see
+    /// [ProverOpts::with_cancel_token](crate::ProverOpts::with_cancel_token).
+    pub(crate) fn with_cancel_token(mut self, cancel_token: Option<CancelToken>) -> Self {
+        self.cancel_token = cancel_token;
+        self
+    }
+
+    /// Split and retry segments that run out of device memory; see
+    /// [ProverOpts::with_auto_po2_downshift](crate::ProverOpts::with_auto_po2_downshift).
+    pub(crate) fn with_auto_po2_downshift(mut self, auto_po2_downshift: bool) -> Self {
+        self.auto_po2_downshift = auto_po2_downshift;
+        self
+    }
+
+    /// Prove segments that hit a device fault again; see
+    /// [ProverOpts::with_segment_retries](crate::ProverOpts::with_segment_retries).
+    pub(crate) fn with_segment_retries(mut self, retries: u32, backoff: Duration) -> Self {
+        self.segment_retries = retries;
+        self.segment_retry_backoff = backoff;
+        self
+    }
+
+    /// Construct the HALs for each retry of a segment with `factory`, rather
+    /// than retrying on the HALs that faulted.
+    pub(crate) fn with_hal_factory(
+        mut self,
+        factory: impl Fn() -> HalPair<H, C> + 'static,
+    ) -> Self {
+        self.hal_factory = Some(Rc::new(factory));
+        self
+    }
+
+    /// Choose which receipts are checked against their session; see
+    /// [ProverOpts::with_verify_policy](crate::ProverOpts::with_verify_policy).
+    pub(crate) fn with_verify_policy(mut self, verify_policy: VerifyPolicy) -> Self {
+        self.verify_policy = verify_policy;
+        self
+    }
+
+    /// Corrupt the value of the `n`th RAM plonk row of each segment that is
+    /// checked against the row before it.
+    #[cfg(all(test, feature = "circuit-debug"))]
+    pub(crate) fn with_corrupt_ram_row(mut self, n: usize) -> Self {
+        self.corrupt_ram_row = Some(n);
+        self
+    }
+
+    // Prove `segment`, splitting it into smaller segments whenever the HAL runs
+    // out of device memory and auto po2 downshift is enabled.
+    fn prove_segment_downshift(
+        &self,
+        ctx: &VerifierContext,
+        segment: &Segment,
+        hooks: &[Box<dyn SessionEvents>],
+    ) -> Result<Vec<SegmentReceipt>> {
+        let payload = match panic::catch_unwind(AssertUnwindSafe(|| {
+            self.prove_segment_retry(ctx, segment, hooks)
+        })) {
+            Ok(receipt) => return Ok(vec![receipt?]),
+            Err(payload) if is_out_of_memory(payload.as_ref()) => payload,
+            Err(payload) => panic::resume_unwind(payload),
+        };
+        let po2 = segment.po2 - 1;
+        if !self.auto_po2_downshift || (po2 as usize) < MIN_CYCLES_PO2 {
+            panic::resume_unwind(payload);
+        }
+        tracing::warn!(
+            "segment {} ran out of device memory at po2 {}, retrying at po2 {po2}",
+            segment.index,
+            segment.po2
+        );
+        let mut receipts = Vec::new();
+        for segment in segment
+            .resegment(po2)
+            .with_context(|| format!("failed to split segment {}", segment.index))?
+        {
+            receipts.extend(self.prove_segment_downshift(ctx, &segment, hooks)?);
+        }
+        Ok(receipts)
+    }
+
+    // Prove `segment`, and prove it again on fresh HALs whenever the HAL
+    // reports a device fault, up to the number of retries allowed.
+    fn prove_segment_retry(
+        &self,
+        ctx: &VerifierContext,
+        segment: &Segment,
+        hooks: &[Box<dyn SessionEvents>],
+    ) -> Result<SegmentReceipt> {
+        let mut retry = 0;
+        let mut backoff = self.segment_retry_backoff;
+        loop {
+            let payload =
+                match panic::catch_unwind(AssertUnwindSafe(|| self.prove_segment(ctx, segment))) {
+                    Ok(receipt) => return receipt,
+                    Err(payload) => payload,
+                };
+            if retry == self.segment_retries
+                || !HalErrorKind::of_panic(payload.as_ref()).is_retryable()
+            {
+                panic::resume_unwind(payload);
+            }
+            retry += 1;
+            tracing::warn!(
+                "segment {} failed: {}; retrying ({retry} of {})",
+                segment.index,
+                panic_message(payload.as_ref()).unwrap_or_default(),
+                self.segment_retries
+            );
+            for hook in hooks {
+                hook.on_segment_retry(segment, retry);
+            }
+            std::thread::sleep(backoff);
+            backoff = backoff.saturating_mul(2);
+            if let Some(factory) = &self.hal_factory {
+                self.hal_pair.replace(factory());
+            }
+        }
+    }
+
+    // Check `receipt` against the `session` it proves.
+    fn verify_session_receipt(
+        &self,
+        ctx: &VerifierContext,
+        session: &Session,
+        receipt: &Receipt,
+    ) -> Result<()> {
+        #[cfg(feature = "metrics")]
+        let _timer = PhaseTimer::start("verify");
+        receipt.verify_integrity_with_context(ctx)?;
+        if receipt.get_metadata()?.digest() != session.get_metadata()?.digest() {
+            tracing::debug!("receipt and session metadata do not match");
+            tracing::debug!("receipt metadata: {:#?}", receipt.get_metadata()?);
+            tracing::debug!("session metadata: {:#?}", session.get_metadata()?);
+            bail!(
+                "session and receipt metadata do not match: session {}, receipt {}",
+                hex::encode(&session.get_metadata()?.digest()),
+                hex::encode(&receipt.get_metadata()?.digest())
+            );
+        }
+        for hook in &session.hooks {
+            hook.on_post_verify_session(receipt);
+        }
+        Ok(())
+    }
 }
 
 impl<H, C> ProverServer for ProverImpl<H, C>
@@ -65,6 +286,16 @@ where
     H: Hal<Field = BabyBear, Elem = Elem, ExtElem = ExtElem>,
     C: CircuitHal<H>,
 {
+    fn prove(
+        &self,
+        env: ExecutorEnv<'_>,
+        ctx: &VerifierContext,
+        image: MemoryImage,
+    ) -> Result<Receipt> {
+        let session = execute_with_hook(env, image, self.segment_hook.as_ref())?;
+        self.prove_session(ctx, &session)
+    }
+
     fn prove_session(&self, ctx: &VerifierContext, session: &Session) -> Result<Receipt> {
         tracing::info!(
             "prove_session: {}, exit_code = {:?}, journal = {:?}",
@@ -72,13 +303,52 @@ where
             session.exit_code,
             session.journal.as_ref().map(|x| hex::encode(x))
         );
+        // Assumptions must be proven with the same hash suite as this session,
+        // otherwise the resulting composite receipt could not be verified as a whole.
+        let hashfn = self.hal_pair.borrow().hal.get_hash_suite().name.clone();
+        #[cfg(feature = "metrics")]
+        let proof_metrics = ProofMetrics::start(&hashfn);
+        for assumption in session.assumptions.iter() {
+            assumption.as_receipt()?.inner.check_hashfn(&hashfn)?;
+        }
+
         let mut segments = Vec::new();
+        let mut skipped = false;
+        let mut shifted = 0;
+        let mut cycles = 0;
         for segment_ref in session.segments.iter() {
+            if self
+                .cancel_token
+                .as_ref()
+                .map_or(false, |token| token.is_cancelled())
+            {
+                return Err(ProvingCancelled { cycles, segments }.into());
+            }
             let segment = segment_ref.resolve()?;
+            cycles += segment.cycles as u64;
+            if session.skipped_segments.contains(&segment.index) {
+                skipped = true;
+                continue;
+            }
+            if let Some(prove_observer) = &self.prove_observer {
+                prove_observer(&segment);
+            }
             for hook in &session.hooks {
                 hook.on_pre_prove_segment(&segment);
             }
-            segments.push(self.prove_segment(ctx, &segment)?);
+            #[cfg(feature = "metrics")]
+            proof_metrics.segment(segment.po2);
+            // Segments split after running out of memory produce several
+            // receipts, which shift the index of every receipt after them.
+            for (i, mut receipt) in self
+                .prove_segment_downshift(ctx, &segment, &session.hooks)?
+                .into_iter()
+                .enumerate()
+            {
+                shifted += (i > 0) as u32;
+                receipt.index = segment.index + shifted;
+                segments.push(receipt);
+            }
             for hook in &session.hooks {
                 hook.on_post_prove_segment(&segment);
             }
@@ -94,23 +364,26 @@ where
             journal_digest: session.journal.as_ref().map(|journal| journal.digest()),
         });
         let receipt = Receipt::new(inner, session.journal.clone().unwrap_or_default().bytes);
+        #[cfg(feature = "metrics")]
+        proof_metrics.cycles(cycles);
 
-        receipt.verify_integrity_with_context(ctx)?;
-        if receipt.get_metadata()?.digest() != session.get_metadata()?.digest() {
-            tracing::debug!("receipt and session metadata do not match");
-            tracing::debug!("receipt metadata: {:#?}", receipt.get_metadata()?);
-            tracing::debug!("session metadata: {:#?}", session.get_metadata()?);
-            bail!(
-                "session and receipt metadata do not match: session {}, receipt {}",
-                hex::encode(&session.get_metadata()?.digest()),
-                hex::encode(&receipt.get_metadata()?.digest())
-            );
+        // A receipt with skipped segments is incomplete by design and cannot be
+        // checked against the session.
+        if !skipped
+            && self
+                .verify_policy
+                .selects(session.get_metadata()?.pre.digest(), cycles)
+        {
+            self.verify_session_receipt(ctx, session, &receipt)?;
         }
+        #[cfg(feature = "metrics")]
+        proof_metrics.completed(&receipt, session.exit_code);
         Ok(receipt)
     }
 
     fn prove_segment(&self, ctx: &VerifierContext, segment: &Segment) -> Result<SegmentReceipt> {
-        use risc0_zkp::prove::executor::Executor;
+        #[cfg(feature = "metrics")]
+        let _timer = PhaseTimer::start("segment");
 
         tracing::debug!(
             "prove_segment[{}]: po2: {}, cycles: {}",
@@ -118,18 +391,21 @@ where
             segment.po2,
             segment.cycles,
         );
-        let (hal, circuit_hal) = (self.hal_pair.hal.as_ref(), &self.hal_pair.circuit_hal);
+        // A retry may replace the HALs, so hold on to the ones in use.
+        let (hal, circuit_hal) = {
+            let hal_pair = self.hal_pair.borrow();
+            (hal_pair.hal.clone(), hal_pair.circuit_hal.clone())
+        };
+        let hal = hal.as_ref();
         let hashfn = &hal.get_hash_suite().name;
 
-        let io = segment.prepare_globals();
-        let machine = MachineContext::new(segment);
-        let po2 = segment.po2 as usize;
-        let mut executor = Executor::new(&CIRCUIT, machine, po2, po2, &io);
-
-        let loader = Loader::new();
-        loader.load(|chunk, fini| executor.step(chunk, fini))?;
-        executor.finalize();
-
+        #[cfg_attr(not(all(test, feature = "circuit-debug")), allow(unused_mut))]
+        let mut machine = MachineContext::new(segment);
+        #[cfg(all(test, feature = "circuit-debug"))]
+        if let Some(n) = self.corrupt_ram_row {
+            machine.corrupt_ram_row(n);
+        }
+        let mut executor = execute_machine(segment, machine)?;
         let mut adapter = ProveAdapter::new(&mut executor);
         let mut prover = risc0_zkp::prove::Prover::new(hal, CIRCUIT.get_taps());
 
@@ -170,18 +446,24 @@ where
     }
 
     fn get_peak_memory_usage(&self) -> usize {
-        self.hal_pair.hal.get_memory_usage()
+        self.hal_pair.borrow().hal.get_memory_usage()
     }
 
     fn lift(&self, receipt: &SegmentReceipt) -> Result<SuccinctReceipt> {
+        #[cfg(feature = "metrics")]
+        let _timer = PhaseTimer::start("lift");
         lift(receipt)
     }
 
     fn join(&self, a: &SuccinctReceipt, b: &SuccinctReceipt) -> Result<SuccinctReceipt> {
+        #[cfg(feature = "metrics")]
+        let _timer = PhaseTimer::start("join");
         join(a, b)
     }
 
     fn identity_p254(&self, a: &SuccinctReceipt) -> Result<SuccinctReceipt> {
+        #[cfg(feature = "metrics")]
+        let _timer = PhaseTimer::start("identity_p254");
         identity_p254(a)
     }
 }
@@ -0,0 +1,80 @@
+// Copyright 2023 RISC Zero, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use anyhow::Result;
+
+use super::get_prover_server_for;
+use crate::{
+    host::client::provenance, ExecutorEnv, ExecutorImpl, ProveOpts, Receipt, Session,
+    VerifierContext,
+};
+
+/// Executes a guest once and proves the resulting [Session] as many times as
+/// needed, each time with its own [ProveOpts].
+///
+/// Everything that configures the guest is set on the [ExecutorEnv] passed
+/// to [SessionProver::new], and everything that configures the proof on the
+/// [ProveOpts] passed to [SessionProver::run]. This allows, for example,
+/// proving the same execution under several hash suites.
+///
+/// # Example
+///
+/// ```no_run
+/// use risc0_zkvm::{ExecutorEnv, ProveOpts, SessionProver};
+/// # let elf = &[];
+///
+/// let env = ExecutorEnv::builder().build().unwrap();
+/// let prover = SessionProver::new(elf, env).unwrap();
+/// let sha_receipt = prover.run(&ProveOpts::default().with_hashfn("sha-256")).unwrap();
+/// let poseidon_receipt = prover.run(&ProveOpts::default().with_hashfn("poseidon2")).unwrap();
+/// ```
+pub struct SessionProver {
+    session: Session,
+    ctx: VerifierContext,
+}
+
+impl SessionProver {
+    /// Execute `elf` in `env`.
+    pub fn new(elf: &[u8], env: ExecutorEnv<'_>) -> Result<Self> {
+        let session = ExecutorImpl::from_elf(env, elf)?.run()?;
+        Ok(Self::from_session(session))
+    }
+
+    /// Prove a [Session] that has already been executed.
+    pub fn from_session(session: Session) -> Self {
+        Self {
+            session,
+            ctx: VerifierContext::default(),
+        }
+    }
+
+    /// Set the [VerifierContext] the receipts are proven with.
+    pub fn with_verifier_context(mut self, ctx: VerifierContext) -> Self {
+        self.ctx = ctx;
+        self
+    }
+
+    /// The executed [Session].
+    pub fn session(&self) -> &Session {
+        &self.session
+    }
+
+    /// Prove the session with `opts`.
+    pub fn run(&self, opts: &ProveOpts) -> Result<Receipt> {
+        let prover = get_prover_server_for(opts)?;
+        let mut receipt = prover.prove_session(&self.ctx, &self.session)?;
+        provenance::annotate(&mut receipt, &opts.annotations);
+        Ok(receipt)
+    }
+}
@@ -12,32 +12,58 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use std::rc::Rc;
+use std::{
+    cell::Cell,
+    fmt::Debug,
+    rc::Rc,
+    sync::Arc,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
 
 use anyhow::Result;
+use bytemuck::Pod;
+use risc0_binfmt::{input_schema_digest, PLATFORM_VERSION_SECTION};
 use risc0_circuit_rv32im::cpu::CpuCircuitHal;
+use risc0_core::field::baby_bear::{BabyBear, BabyBearElem, BabyBearExtElem};
 use risc0_zkp::{
-    core::{digest::Digest, hash::blake2b::Blake2bCpuHashSuite},
-    hal::cpu::CpuHal,
+    core::{
+        digest::Digest,
+        hash::{blake2b::Blake2bCpuHashSuite, sha::Sha256HashSuite, HashSuite},
+    },
+    hal::{
+        cpu::{CpuBuffer, CpuHal},
+        Hal, DEVICE_FAULT, OUT_OF_MEMORY,
+    },
     verify::VerificationError,
 };
 use risc0_zkvm_methods::{multi_test::MultiTestSpec, MULTI_TEST_ELF, MULTI_TEST_ID};
-use risc0_zkvm_platform::{memory, WORD_SIZE};
+use risc0_zkvm_platform::{memory, PAGE_SIZE, WORD_SIZE};
 use serial_test::serial;
 use test_log::test;
 
 use super::{get_prover_server, HalPair, ProverImpl};
 use crate::{
+    control_id_for, dump_layout,
     host::{server::testutils, CIRCUIT},
+    prove::{buffer_pool_stats, set_buffer_pool_limit, thread_buffer_pool_stats, trim_memory},
+    receipt_metadata::MaybePruned,
     serde::{from_slice, to_vec},
-    ExecutorEnv, ExecutorImpl, ExitCode, ProverOpts, ProverServer, Receipt, Session,
-    VerifierContext,
+    sha::Digestible,
+    AnnotationError, BatchMode, CachedReceipt, CachedVerification, CancelToken, CompositeReceipt,
+    ExecutionReport, ExecutorEnv, ExecutorImpl, ExitCode, FileProofCache, InnerReceipt,
+    InputSchema, LocalProver, LruVerifyCache, MemoryImage, OptsError, PageKind, PlatformCheck,
+    Program, ProofCache, ProofCacheKey, ProveOpts, Prover, ProverOpts, ProverServer,
+    ProvingCancelled, PrunedReceipt, Receipt, ReceiptMetadata, ReceiptPolicyError, SecurityPolicy,
+    Segment, SegmentAction, SegmentReceipt, Session, SessionProver, SetupErrors, TouchedPages,
+    VerificationBundle, VerifierContext, VerifyCache, VerifyCacheKey, VerifyPolicy, REPORT_VERSION,
+    ZKVM_CREATED_AT, ZKVM_HOST_INFO, ZKVM_PRODUCER_ID,
 };
 
 fn prover_opts_fast() -> ProverOpts {
     ProverOpts {
         hashfn: "sha-256".to_string(),
         prove_guest_errors: false,
+        ..Default::default()
     }
 }
 
@@ -57,6 +83,7 @@ fn prove_nothing(hashfn: &str) -> Result<Receipt> {
     let opts = ProverOpts {
         hashfn: hashfn.to_string(),
         prove_guest_errors: false,
+        ..Default::default()
     };
     get_prover_server(&opts)
         .unwrap()
@@ -69,6 +96,102 @@ fn hashfn_poseidon() {
     prove_nothing("poseidon").unwrap();
 }
 
+#[test]
+#[cfg_attr(feature = "cuda", serial)]
+fn execute_once_prove_twice() {
+    let env = ExecutorEnv::builder()
+        .write(&MultiTestSpec::DoNothing)
+        .unwrap()
+        .build()
+        .unwrap();
+    let prover = SessionProver::new(MULTI_TEST_ELF, env).unwrap();
+    let segments = prover.session().segments.len();
+
+    let sha = prover
+        .run(&ProveOpts::default().with_hashfn("sha-256"))
+        .unwrap();
+    let poseidon2 = prover
+        .run(
+            &ProveOpts::default()
+                .with_hashfn("poseidon2")
+                .with_annotation("guest_version", "1.2.3"),
+        )
+        .unwrap();
+    assert_eq!(prover.session().segments.len(), segments);
+    assert_eq!(sha.inner.hashfn(), Some("sha-256"));
+    assert_eq!(poseidon2.inner.hashfn(), Some("poseidon2"));
+    assert_eq!(sha.journal, poseidon2.journal);
+    assert_eq!(poseidon2.annotations["guest_version"], "1.2.3");
+    sha.verify(MULTI_TEST_ID).unwrap();
+    poseidon2.verify(MULTI_TEST_ID).unwrap();
+}
+
+#[test]
+fn prover_opts_facade() {
+    // The guest commits random bytes, which are the same in every run
+    // because they are derived from its image ID and input. Each path must
+    // honor that guest option for the claims to match.
+    let env = || {
+        ExecutorEnv::builder()
+            .write(&MultiTestSpec::DoRandom)
+            .unwrap()
+            .derived_randomness(true)
+            .build()
+            .unwrap()
+    };
+    let opts = prover_opts_fast()
+        .with_segment_retries(2, Duration::from_millis(10))
+        .with_strict_platform_check(true);
+    let old = LocalProver::new("local")
+        .prove_elf_with_ctx(env(), &VerifierContext::default(), MULTI_TEST_ELF, &opts)
+        .unwrap();
+    let new = SessionProver::new(MULTI_TEST_ELF, env())
+        .unwrap()
+        .run(&opts.prove_opts())
+        .unwrap();
+    assert_eq!(old.journal, new.journal);
+    assert_eq!(old.get_metadata().unwrap(), new.get_metadata().unwrap());
+    assert_eq!(old.inner.hashfn(), new.inner.hashfn());
+    old.verify(MULTI_TEST_ID).unwrap();
+    new.verify(MULTI_TEST_ID).unwrap();
+
+    // They split into proving options, and back with guest options reset.
+    let prove_opts = opts.prove_opts();
+    assert_eq!(prove_opts.hashfn, "sha-256");
+    assert_eq!(prove_opts.segment_retries, 2);
+    assert_eq!(prove_opts.segment_retry_backoff, Duration::from_millis(10));
+    let opts = ProverOpts::from(prove_opts);
+    assert_eq!(opts.hashfn, "sha-256");
+    assert_eq!(opts.segment_retries, 2);
+    assert!(!opts.strict_platform_check);
+}
+
+#[test]
+#[cfg_attr(feature = "cuda", serial)]
+fn hashfn_poseidon2() {
+    let receipt = prove_nothing("poseidon2").unwrap();
+    assert_eq!(receipt.inner.hashfn(), Some("poseidon2"));
+
+    let encoded: Vec<u32> = to_vec(&receipt).unwrap();
+    let decoded: Receipt = from_slice(&encoded).unwrap();
+    assert_eq!(decoded, receipt);
+    assert_eq!(
+        decoded.get_metadata().unwrap(),
+        receipt.get_metadata().unwrap()
+    );
+    decoded.verify(MULTI_TEST_ID).unwrap();
+
+    // A verifier that only knows about Poseidon must reject the receipt.
+    let mut ctx = VerifierContext::default();
+    ctx.suites.retain(|name, _| name == "poseidon");
+    assert_eq!(
+        decoded
+            .verify_with_context(&ctx, MULTI_TEST_ID)
+            .unwrap_err(),
+        VerificationError::InvalidHashSuite
+    );
+}
+
 #[test]
 fn hashfn_blake2b() {
     let hal_pair = HalPair {
@@ -96,192 +219,2132 @@ fn receipt_serde() {
 
 #[test]
 #[cfg_attr(feature = "cuda", serial)]
-fn check_image_id() {
+fn verify_batch() {
     let receipt = prove_nothing("sha-256").unwrap();
-    let mut image_id: Digest = MULTI_TEST_ID.into();
-    for word in image_id.as_mut_words() {
-        *word = word.wrapping_add(1);
+    let mut corrupted = receipt.clone();
+    if let InnerReceipt::Composite(inner) = &mut corrupted.inner {
+        let seal = &mut inner.segments[0].seal;
+        let mid = seal.len() / 2;
+        seal[mid] ^= 1;
     }
+    let image_id: Digest = MULTI_TEST_ID.into();
+    let receipts = [
+        (receipt.clone(), image_id),
+        (corrupted, image_id),
+        (receipt.clone(), Digest::ZERO),
+        (receipt, image_id),
+    ];
+
+    let result = crate::verify_batch(&receipts, 4, BatchMode::CollectAll);
+    assert!(!result.is_ok());
+    assert!(matches!(result.results[0], Some(Ok(()))));
+    assert!(matches!(result.results[1], Some(Err(_))));
     assert_eq!(
-        receipt.verify(image_id).unwrap_err(),
-        VerificationError::ImageVerificationError
+        result.results[2],
+        Some(Err(VerificationError::ImageVerificationError))
     );
+    assert!(matches!(result.results[3], Some(Ok(()))));
+    let failed: Vec<_> = result.errors().map(|(idx, _)| idx).collect();
+    assert_eq!(failed, [1, 2]);
+
+    // With a single thread, nothing after the first failure is verified.
+    let result = crate::verify_batch(&receipts, 1, BatchMode::FailFast);
+    assert!(matches!(result.results[0], Some(Ok(()))));
+    assert!(matches!(result.results[1], Some(Err(_))));
+    assert!(result.results[2..].iter().all(Option::is_none));
+
+    let result = crate::verify_batch(&receipts[3..], 2, BatchMode::FailFast);
+    assert!(result.is_ok());
 }
 
 #[test]
-#[serial]
-fn sha_basics() {
-    fn run_sha(msg: &str) -> String {
+#[cfg_attr(feature = "cuda", serial)]
+fn verify_with_nonce() {
+    let nonce = [0x5a; 32];
+    let env = ExecutorEnv::builder()
+        .write(&MultiTestSpec::CommitNonce { value: 7 })
+        .unwrap()
+        .nonce(nonce)
+        .build()
+        .unwrap();
+    let receipt = get_prover_server(&prover_opts_fast())
+        .unwrap()
+        .prove_elf(env, MULTI_TEST_ELF)
+        .unwrap();
+    receipt.verify_with_nonce(MULTI_TEST_ID, nonce).unwrap();
+    assert_eq!(
+        receipt.verify_with_nonce(MULTI_TEST_ID, [0; 32]),
+        Err(ReceiptPolicyError::NonceMismatch)
+    );
+
+    // A receipt with an empty journal was not bound to any nonce.
+    let receipt = prove_nothing("sha-256").unwrap();
+    assert_eq!(
+        receipt.verify_with_nonce(MULTI_TEST_ID, nonce),
+        Err(ReceiptPolicyError::MissingNonce)
+    );
+}
+
+#[cfg(feature = "debug-verify")]
+#[test]
+#[cfg_attr(feature = "cuda", serial)]
+fn debug_verify() {
+    use crate::VerifyPhase;
+
+    let receipt = prove_nothing("sha-256").unwrap();
+    let trace = receipt.debug_verify(MULTI_TEST_ID);
+    assert!(trace.is_ok());
+    assert!(trace.failure().is_none());
+    assert!(serde_json::to_string(&trace)
+        .unwrap()
+        .contains("CodeMerkle"));
+
+    let steps = &trace.seals[0].steps;
+    let offset_of = |phase| steps.iter().find(|x| x.phase == phase).unwrap().offset;
+    let seal_len = receipt.inner.composite().unwrap().segments[0].seal.len();
+    for (phase, idx) in [
+        (VerifyPhase::CodeMerkle, offset_of(VerifyPhase::CodeMerkle)),
+        (
+            VerifyPhase::Constraints,
+            offset_of(VerifyPhase::Constraints),
+        ),
+        (VerifyPhase::FriQuery, seal_len - 1),
+    ] {
+        let mut corrupted = receipt.clone();
+        if let InnerReceipt::Composite(inner) = &mut corrupted.inner {
+            // Keep the word a valid field element.
+            let word = &mut inner.segments[0].seal[idx];
+            *word = if *word == 0 { 1 } else { *word - 1 };
+        }
+        let trace = corrupted.debug_verify(MULTI_TEST_ID);
+        assert!(!trace.is_ok());
+        let (seal, step) = trace.failure().unwrap();
+        assert_eq!(seal.label, "segment 0");
+        assert_eq!(step.phase, phase, "{step:?}");
+        assert_eq!(seal.steps.last(), Some(step));
+    }
+}
+
+#[test]
+fn verifier_limits() {
+    let ctx = VerifierContext::default()
+        .with_max_journal_bytes(1024)
+        .with_max_seal_words(1000);
+    let metadata = ReceiptMetadata {
+        pre: MaybePruned::Pruned(Digest::ZERO),
+        post: MaybePruned::Pruned(Digest::ZERO),
+        exit_code: ExitCode::Halted(0),
+        input: Digest::ZERO,
+        output: MaybePruned::Pruned(Digest::ZERO),
+    };
+    let decode = |inner, journal| {
+        let receipt = Receipt::new(inner, journal);
+        ctx.decode_receipt(&bincode::serialize(&receipt).unwrap())
+    };
+    let fake = || InnerReceipt::Fake {
+        metadata: metadata.clone(),
+    };
+    assert!(decode(fake(), vec![0; 1024]).is_ok());
+    assert_eq!(
+        decode(fake(), vec![0; 1025]),
+        Err(ReceiptPolicyError::JournalTooLarge {
+            bytes: 1025,
+            max: 1024,
+        })
+    );
+
+    // Seals are limited in total, including those of assumptions.
+    let segment = |words| SegmentReceipt {
+        seal: vec![0; words],
+        index: 0,
+        hashfn: "sha-256".to_string(),
+    };
+    let composite = |segments, assumptions| {
+        InnerReceipt::Composite(CompositeReceipt {
+            segments,
+            assumptions,
+            journal_digest: None,
+        })
+    };
+    let inner = composite(
+        vec![segment(600), segment(300)],
+        vec![composite(vec![segment(200)], vec![])],
+    );
+    assert_eq!(
+        decode(inner, vec![]),
+        Err(ReceiptPolicyError::SealTooLarge {
+            words: 1100,
+            max: 1000
+        })
+    );
+
+    // An input longer than any receipt within the limits is rejected before
+    // it is decoded.
+    let bytes = vec![0; 1024 + 2 * WORD_SIZE * 1000 + 64 * 1024 + 1];
+    assert!(matches!(
+        ctx.decode_receipt(&bytes),
+        Err(ReceiptPolicyError::EncodingTooLarge { .. })
+    ));
+
+    // A length prefix that claims more than the input holds is rejected
+    // without allocating it.
+    let mut bytes = bincode::serialize(&Receipt::new(fake(), vec![])).unwrap();
+    let len = bytes.len();
+    bytes[len - 8..].copy_from_slice(&(1u64 << 32).to_le_bytes());
+    assert_eq!(
+        ctx.decode_receipt(&bytes),
+        Err(ReceiptPolicyError::Malformed)
+    );
+
+    let ctx = VerifierContext::default().with_allowed_hash_suites(["poseidon"]);
+    assert_eq!(ctx.suites.keys().collect::<Vec<_>>(), ["poseidon"]);
+    assert_eq!(
+        segment(10).verify_integrity_with_context(&ctx),
+        Err(VerificationError::InvalidHashSuite)
+    );
+}
+
+#[test]
+#[cfg_attr(feature = "cuda", serial)]
+fn receipt_hashfn() {
+    let receipt = prove_nothing("sha-256").unwrap();
+    assert_eq!(receipt.inner.hashfn(), Some("sha-256"));
+
+    // A receipt claiming a hash function with no known control IDs is rejected.
+    let mut inner = receipt.inner.composite().unwrap().clone();
+    inner.segments[0].hashfn = "unknown".to_string();
+    assert_eq!(
+        inner
+            .verify_integrity_with_context(&VerifierContext::default())
+            .unwrap_err(),
+        VerificationError::InvalidHashSuite
+    );
+}
+
+#[test]
+#[cfg_attr(feature = "cuda", serial)]
+fn verify_with_hashfn() {
+    let receipt = prove_nothing("sha-256").unwrap();
+    control_id_for(receipt.inner.hashfn().unwrap()).unwrap();
+    assert_eq!(
+        control_id_for("unknown").unwrap_err(),
+        VerificationError::InvalidHashSuite
+    );
+    receipt
+        .verify_with_hashfn(MULTI_TEST_ID, "sha-256")
+        .unwrap();
+
+    // A verifier that expects another hash function rejects the receipt,
+    // even though it would verify with the one recorded in it.
+    assert_eq!(
+        receipt
+            .verify_with_hashfn(MULTI_TEST_ID, "poseidon")
+            .unwrap_err(),
+        VerificationError::InvalidHashSuite
+    );
+    assert_eq!(
+        receipt
+            .verify_with_hashfn(MULTI_TEST_ID, "unknown")
+            .unwrap_err(),
+        VerificationError::InvalidHashSuite
+    );
+}
+
+#[test]
+#[cfg_attr(feature = "cuda", serial)]
+fn receipt_annotations() {
+    let env = ExecutorEnv::builder()
+        .write(&MultiTestSpec::DoNothing)
+        .unwrap()
+        .build()
+        .unwrap();
+    let opts = prover_opts_fast().with_annotation("guest", "multi_test");
+    let mut receipt = LocalProver::new("local")
+        .prove_elf_with_ctx(env, &VerifierContext::default(), MULTI_TEST_ELF, &opts)
+        .unwrap();
+    assert_eq!(receipt.annotations["guest"], "multi_test");
+
+    // The versioned encoding keeps them; the serde encoding leaves them out.
+    let decoded = Receipt::from_bytes(&receipt.to_bytes().unwrap()).unwrap();
+    assert_eq!(decoded.annotations, receipt.annotations);
+    let decoded: Receipt = from_slice(&to_vec(&receipt).unwrap()).unwrap();
+    assert!(decoded.annotations.is_empty());
+
+    // Annotations are not part of the proven claim, so changing them does not
+    // affect verification.
+    receipt
+        .annotations
+        .insert("guest".to_string(), "tampered".to_string());
+    receipt.verify(MULTI_TEST_ID).unwrap();
+}
+
+#[test]
+#[cfg_attr(feature = "cuda", serial)]
+fn receipt_provenance() {
+    let env = ExecutorEnv::builder()
+        .write(&MultiTestSpec::DoNothing)
+        .unwrap()
+        .build()
+        .unwrap();
+    let opts = prover_opts_fast().with_provenance("ci");
+    let before = SystemTime::now() - Duration::from_secs(1);
+    let mut receipt = LocalProver::new("local")
+        .prove_elf_with_ctx(env, &VerifierContext::default(), MULTI_TEST_ELF, &opts)
+        .unwrap();
+    let after = SystemTime::now();
+    assert_eq!(receipt.producer_id(), Some("ci"));
+    assert!(receipt
+        .host_info()
+        .unwrap()
+        .contains(std::env::consts::ARCH));
+    let created_at = receipt.created_at().unwrap().unwrap();
+    assert!(before <= created_at && created_at <= after);
+    receipt
+        .check_annotations(&[ZKVM_CREATED_AT, ZKVM_PRODUCER_ID, ZKVM_HOST_INFO])
+        .unwrap();
+
+    let decoded: Receipt = from_slice(&to_vec(&receipt).unwrap()).unwrap();
+    assert_eq!(decoded.created_at().unwrap(), Some(created_at));
+
+    for (value, secs) in [
+        ("2023-11-14T22:13:20Z", 1_700_000_000i64),
+        ("2023-11-15T00:13:20.25+02:00", 1_700_000_000),
+        ("2024-02-29t12:00:00-00:30", 1_709_209_800),
+        ("1969-12-31T23:59:59Z", -1),
+    ] {
+        receipt
+            .annotations
+            .insert(ZKVM_CREATED_AT.to_string(), value.to_string());
+        let expected = match u64::try_from(secs) {
+            Ok(secs) => UNIX_EPOCH + Duration::from_secs(secs),
+            Err(_) => UNIX_EPOCH - Duration::from_secs(secs.unsigned_abs()),
+        };
+        let expected = match value.contains(".25") {
+            true => expected + Duration::from_millis(250),
+            false => expected,
+        };
+        assert_eq!(receipt.created_at().unwrap(), Some(expected), "{value}");
+    }
+
+    for value in [
+        "2023-02-29T00:00:00Z",
+        "2023-11-14 22:13:20",
+        "2023-11-14T24:00:00Z",
+        "2023-11-14T22:13:20.Z",
+        "2023-11-14T22:13:20+2:00",
+        "yesterday",
+    ] {
+        receipt
+            .annotations
+            .insert(ZKVM_CREATED_AT.to_string(), value.to_string());
+        let err = AnnotationError::MalformedTimestamp {
+            value: value.to_string(),
+        };
+        assert_eq!(receipt.created_at().unwrap_err(), err);
+        assert_eq!(receipt.check_annotations(&[]).unwrap_err(), err);
+    }
+
+    // Provenance is optional: a receipt without it still verifies, and only
+    // fails a check that requires it.
+    receipt.annotations.clear();
+    receipt.verify(MULTI_TEST_ID).unwrap();
+    assert_eq!(receipt.created_at().unwrap(), None);
+    receipt.check_annotations(&[]).unwrap();
+    assert_eq!(
+        receipt.check_annotations(&[ZKVM_PRODUCER_ID]).unwrap_err(),
+        AnnotationError::Missing {
+            key: ZKVM_PRODUCER_ID.to_string()
+        }
+    );
+}
+
+#[test]
+fn strict_platform_check() {
+    // Rename the platform version section, as if the guest predated it.
+    let name = PLATFORM_VERSION_SECTION.as_bytes();
+    let mut unknown = MULTI_TEST_ELF.to_vec();
+    let pos = unknown
+        .windows(name.len())
+        .rposition(|window| window == name)
+        .unwrap();
+    unknown[pos + name.len() - 1] = b'X';
+    assert_eq!(
+        Program::check_platform(MULTI_TEST_ELF).unwrap(),
+        PlatformCheck::Compatible
+    );
+    assert_eq!(
+        Program::check_platform(&unknown).unwrap(),
+        PlatformCheck::Unknown
+    );
+
+    let prove = |elf: &[u8], strict| {
         let env = ExecutorEnv::builder()
-            .write(&MultiTestSpec::ShaDigest { data: msg.into() })
+            .write(&MultiTestSpec::DoNothing)
             .unwrap()
             .build()
             .unwrap();
-        let mut exec = ExecutorImpl::from_elf(env, MULTI_TEST_ELF).unwrap();
-        let session = exec.run().unwrap();
-        let receipt = prove_session_fast(&session);
-        hex::encode(Digest::try_from(receipt.journal.bytes).unwrap())
+        let opts = prover_opts_fast().with_strict_platform_check(strict);
+        LocalProver::new("local").prove_elf_with_ctx(env, &VerifierContext::default(), elf, &opts)
+    };
+    let err = prove(&unknown, true).err().unwrap();
+    assert!(err.to_string().contains("unknown platform"), "{err:?}");
+    prove(&unknown, false).unwrap();
+    prove(MULTI_TEST_ELF, true).unwrap();
+}
+
+#[test]
+#[cfg_attr(feature = "cuda", serial)]
+fn verification_bundle() {
+    // DoNothing commits nothing, which decodes as a unit struct.
+    #[derive(Debug, serde::Deserialize)]
+    struct Empty;
+
+    impl InputSchema for Empty {
+        const SCHEMA: &'static str = "Empty";
     }
 
+    #[derive(Debug, serde::Deserialize)]
+    struct Unknown;
+
+    impl InputSchema for Unknown {
+        const SCHEMA: &'static str = "Unknown";
+    }
+
+    let receipt = prove_nothing("sha-256").unwrap();
+    let bundle = VerificationBundle::new(receipt, MULTI_TEST_ID).with_journal_schema::<Empty>();
+    let bytes = bundle.to_bytes().unwrap();
+    let bundle = VerificationBundle::from_bytes(&bytes).unwrap();
+    assert_eq!(bundle.hashfn.as_deref(), Some("sha-256"));
+    assert_eq!(bundle.circuit.as_deref(), Some("rv32im"));
+    let metadata = bundle.verify().unwrap();
+    assert_eq!(metadata.exit_code, ExitCode::Halted(0));
+    assert_eq!(metadata.pre.digest(), Digest::from(MULTI_TEST_ID));
+    bundle.decode_journal::<Empty>().unwrap();
+    let err = bundle.decode_journal::<Unknown>().unwrap_err();
+    assert!(err.to_string().contains("journal_schema"));
+
+    let corruptions: [(&str, fn(&mut VerificationBundle)); 7] = [
+        ("receipt", |bundle| {
+            let InnerReceipt::Composite(inner) = &mut bundle.receipt.inner else {
+                unreachable!()
+            };
+            let seal = &mut inner.segments[0].seal;
+            let mid = seal.len() / 2;
+            seal[mid] ^= 1;
+        }),
+        ("journal", |bundle| {
+            bundle.receipt.journal.bytes = vec![1, 2, 3, 4]
+        }),
+        ("image_id", |bundle| bundle.image_id = Digest::ZERO),
+        ("zkvm_version", |bundle| {
+            bundle.zkvm_version = "0.1.0".to_string()
+        }),
+        ("zkvm_version", |bundle| {
+            bundle.zkvm_version = "not a version".to_string()
+        }),
+        ("circuit", |bundle| {
+            bundle.circuit = Some("recursion".to_string())
+        }),
+        ("hashfn", |bundle| {
+            bundle.hashfn = Some("poseidon".to_string())
+        }),
+    ];
+    for (component, corrupt) in corruptions {
+        let mut corrupted = bundle.clone();
+        corrupt(&mut corrupted);
+        let err = VerificationBundle::from_bytes(&corrupted.to_bytes().unwrap())
+            .unwrap()
+            .verify()
+            .unwrap_err();
+        assert_eq!(err.component(), component, "{err}");
+        assert!(err.to_string().contains(component));
+    }
+
+    assert!(VerificationBundle::from_bytes(&bytes[1..]).is_err());
+    assert!(VerificationBundle::from_bytes(&bytes[..bytes.len() - 1]).is_err());
+}
+
+#[test]
+#[cfg_attr(feature = "cuda", serial)]
+fn proof_cache() {
+    let dir = tempfile::tempdir().unwrap();
+    let cache = Arc::new(FileProofCache::new(dir.path()));
+    let opts = prover_opts_fast().with_proof_cache(cache.clone());
+    let prove = |opts: &ProverOpts| {
+        let env = ExecutorEnv::builder()
+            .write(&MultiTestSpec::DoNothing)
+            .unwrap()
+            .build()
+            .unwrap();
+        LocalProver::new("local")
+            .prove_elf_with_ctx(env, &VerifierContext::default(), MULTI_TEST_ELF, opts)
+            .unwrap()
+    };
+
+    let first = prove(&opts);
+    assert_eq!(cache.hits(), 0);
+    let second = prove(&opts);
+    assert_eq!(cache.hits(), 1);
+    assert_eq!(first.journal, second.journal);
+    second.verify(MULTI_TEST_ID).unwrap();
+
+    // A receipt from another version of the zkVM is proven again.
+    let env = ExecutorEnv::builder()
+        .write(&MultiTestSpec::DoNothing)
+        .unwrap()
+        .build()
+        .unwrap();
+    let key = ProofCacheKey {
+        image_id: MULTI_TEST_ID.into(),
+        input_digest: env.input_digest().unwrap(),
+        hashfn: opts.hashfn.clone(),
+        version: crate::VERSION.to_string(),
+    };
+    let stale = CachedReceipt {
+        version: "0.0.0".to_string(),
+        receipt: first,
+    };
+    cache.put(&key, &stale).unwrap();
+    prove(&opts);
+    assert_eq!(cache.get(&key).unwrap().unwrap().version, crate::VERSION);
+}
+
+#[test]
+#[cfg_attr(feature = "cuda", serial)]
+fn verify_cache() {
+    // Counts the results stored, one for each time the seals are verified.
+    struct CountingCache {
+        inner: LruVerifyCache,
+        puts: Cell<u32>,
+    }
+
+    impl VerifyCache for CountingCache {
+        fn get(&self, key: &VerifyCacheKey) -> Result<Option<CachedVerification>> {
+            self.inner.get(key)
+        }
+
+        fn put(&self, key: &VerifyCacheKey, result: &CachedVerification) -> Result<()> {
+            self.puts.set(self.puts.get() + 1);
+            self.inner.put(key, result)
+        }
+    }
+
+    let receipt = prove_nothing("sha-256").unwrap();
+    let cache = Rc::new(CountingCache {
+        inner: LruVerifyCache::new(2),
+        puts: Cell::new(0),
+    });
+    let ctx = VerifierContext::default().with_cache(cache.clone());
+    receipt.verify_with_context(&ctx, MULTI_TEST_ID).unwrap();
+    receipt.verify_with_context(&ctx, MULTI_TEST_ID).unwrap();
+    assert_eq!(cache.puts.get(), 1);
+    assert_eq!(cache.inner.hits(), 1);
+
+    // The claim of a hit is still checked against the journal.
+    let mut tampered = receipt.clone();
+    tampered.journal.bytes.push(0);
     assert_eq!(
-        run_sha(""),
-        "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"
-    );
-    assert_eq!(
-        run_sha("a"),
-        "ca978112ca1bbdcafac231b39a23dc4da786eff8147c4e72b9807785afee48bb"
+        tampered.verify_with_context(&ctx, MULTI_TEST_ID),
+        Err(VerificationError::JournalDigestMismatch)
     );
+    assert_eq!((cache.puts.get(), cache.inner.hits()), (1, 2));
+
+    // Another seal misses, and its failure is stored.
+    let mut tampered = receipt.clone();
+    let InnerReceipt::Composite(inner) = &mut tampered.inner else {
+        unreachable!()
+    };
+    inner.segments[0].seal[100] ^= 1;
+    assert!(tampered.verify_with_context(&ctx, MULTI_TEST_ID).is_err());
     assert_eq!(
-        run_sha("abc"),
-        "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        tampered.verify_with_context(&ctx, MULTI_TEST_ID),
+        Err(VerificationError::InvalidProof)
     );
+    assert_eq!((cache.puts.get(), cache.inner.hits()), (2, 3));
+
+    // So does another set of hash suites, and the oldest result is evicted.
+    let ctx = VerifierContext::default()
+        .with_allowed_hash_suites(["sha-256"])
+        .with_cache(cache.clone());
+    receipt.verify_with_context(&ctx, MULTI_TEST_ID).unwrap();
+    assert_eq!((cache.puts.get(), cache.inner.len()), (3, 2));
+}
+
+#[test]
+#[cfg_attr(feature = "cuda", serial)]
+fn execution_report() {
+    let dir = tempfile::tempdir().unwrap();
+    let prover = LocalProver::new("local");
+    assert!(prover.execution_report().is_none());
+    let run = |spec: &MultiTestSpec| {
+        let env = ExecutorEnv::builder().write(spec).unwrap().build().unwrap();
+        prover.prove_elf_with_ctx(
+            env,
+            &VerifierContext::default(),
+            MULTI_TEST_ELF,
+            &prover_opts_fast(),
+        )
+    };
+    let read_json = |report: &ExecutionReport| {
+        let path = dir.path().join("report.json");
+        report.write_json(&path).unwrap();
+        serde_json::from_slice::<serde_json::Value>(&std::fs::read(&path).unwrap()).unwrap()
+    };
+
+    let receipt = run(&MultiTestSpec::DoNothing).unwrap();
+    let report = prover.execution_report().unwrap();
+    assert!(report.is_ok());
+    let json = read_json(&report);
+    assert_eq!(json["report_version"], REPORT_VERSION);
+    assert_eq!(json["version"], crate::VERSION);
+    assert_eq!(json["prover"], "local");
+    assert_eq!(json["hashfn"], "sha-256");
+    assert_eq!(json["exit_code"]["Halted"], 0);
+    assert_eq!(json["io"]["journal_bytes"], receipt.journal.bytes.len());
+    assert!(json["error"].is_null());
+    let segments = json["segments"].as_array().unwrap();
     assert_eq!(
-        run_sha("abcdbcdecdefdefgefghfghighijhijkijkljklmklmnlmnomnopnopq"),
-        "248d6a61d20638b8e5c026930c3e6039a33ce45964ff2167f6ecedd419db06c1"
+        segments.len(),
+        receipt.inner.composite().unwrap().segments.len()
     );
+    let cycles: u64 = segments.iter().map(|x| x["cycles"].as_u64().unwrap()).sum();
+    assert_eq!(json["total_cycles"], cycles);
+    assert!(segments.iter().all(|x| x["po2"].as_u64().is_some()));
+    assert!(json["seal_bytes"].as_u64().unwrap() > 0);
+    assert!(json["phases"]["prove"].is_object());
+
+    // A failed run still produces a report, which is also attached to the
+    // error.
+    let err = run(&MultiTestSpec::Panic).unwrap_err();
+    let attached = err.downcast_ref::<ExecutionReport>().unwrap();
+    let report = prover.execution_report().unwrap();
+    assert!(!report.is_ok());
+    assert_eq!(report.error, attached.error);
+    let json = read_json(&report);
+    assert_eq!(json["report_version"], REPORT_VERSION);
+    assert!(json["error"]
+        .as_str()
+        .unwrap()
+        .contains("MultiTestSpec::Panic invoked"));
+    assert!(json["exit_code"].is_null());
+    assert!(json["seal_bytes"].is_null());
+    assert!(json["phases"]["prove"].is_null());
 }
 
 #[test]
-#[serial]
-fn sha_iter() {
-    let input = MultiTestSpec::ShaDigestIter {
-        data: Vec::from([0u8; 32]),
-        num_iter: 1500,
+#[cfg_attr(feature = "cuda", serial)]
+fn touched_pages() {
+    let prover = LocalProver::new("local");
+    assert_eq!(prover.touched_pages(), TouchedPages::default());
+    let env = ExecutorEnv::builder()
+        .write(&MultiTestSpec::ShaDigest { data: vec![1; 100] })
+        .unwrap()
+        .build()
+        .unwrap();
+    prover
+        .prove_elf_with_ctx(
+            env,
+            &VerifierContext::default(),
+            MULTI_TEST_ELF,
+            &prover_opts_fast(),
+        )
+        .unwrap();
+    let touched = prover.touched_pages();
+    assert!(touched.written.is_subset(&touched.read));
+
+    let program = Program::load_elf(MULTI_TEST_ELF, memory::GUEST_MAX_MEM as u32).unwrap();
+    let image = MemoryImage::new(&program, PAGE_SIZE as u32).unwrap();
+    let page_idx = |addr: u32| image.info.get_page_index(addr);
+
+    // The stack and the statics of the guest are written, and its code is
+    // only read.
+    assert!(touched
+        .written
+        .contains(&page_idx(memory::STACK_TOP - WORD_SIZE as u32)));
+    assert!(image
+        .pages()
+        .any(|page| page.kind == PageKind::Program && touched.written.contains(&page.idx)));
+    assert!(touched
+        .read_only()
+        .any(|idx| idx == page_idx(program.entry)));
+
+    let mut layout = Vec::new();
+    dump_layout(&prover.post_image().unwrap(), Some(&touched), &mut layout).unwrap();
+    let layout = String::from_utf8(layout).unwrap();
+    let line = |kind: &str, access: &str| {
+        layout
+            .lines()
+            .any(|line| line.contains(kind) && line.ends_with(access))
     };
+    assert!(line("stack", "written"), "{layout}");
+    assert!(line("program", "read-only"), "{layout}");
+    assert!(line("program", "written"), "{layout}");
+    assert!(line("page table", "written"), "{layout}");
+}
+
+#[test]
+#[cfg_attr(feature = "cuda", serial)]
+fn with_bigint_accel() {
+    // 7^5 mod 1000003 = 16807, with one limb each.
     let env = ExecutorEnv::builder()
-        .write(&input)
+        .write(&MultiTestSpec::BigIntModExp {
+            base: vec![7],
+            exponent: vec![5],
+            modulus: vec![1000003],
+        })
         .unwrap()
         .build()
         .unwrap();
-    let mut exec = ExecutorImpl::from_elf(env, MULTI_TEST_ELF).unwrap();
-    let session = exec.run().unwrap();
-    let receipt = prove_session_fast(&session);
-    let digest = Digest::try_from(receipt.journal.bytes).unwrap();
-    assert_eq!(
-        hex::encode(digest),
-        "9d4d1940b5c0c6d09c10add9631806f9df9467884d3e9ce4a147113e27f5c02a"
-    )
+    let opts = prover_opts_fast().with_bigint_accel();
+    let receipt = LocalProver::new("local")
+        .prove_elf_with_ctx(env, &VerifierContext::default(), MULTI_TEST_ELF, &opts)
+        .unwrap();
+    receipt.verify(MULTI_TEST_ID).unwrap();
+    assert_eq!(receipt.journal.bytes, 16807u32.to_le_bytes());
 }
 
-#[test]
-fn bigint_accel() {
-    let cases = testutils::generate_bigint_test_cases(&mut rand::thread_rng(), 10);
-    for case in cases {
-        println!("Running BigInt circuit test case: {:08x?}", case);
-        let input = MultiTestSpec::BigInt {
-            x: case.x,
-            y: case.y,
-            modulus: case.modulus,
-        };
+#[test]
+#[cfg_attr(feature = "cuda", serial)]
+fn with_assumption() {
+    use risc0_zkvm_methods::HELLO_COMMIT_ELF;
+    use sha2::{Digest as _, Sha256};
+
+    use crate::SuccinctReceipt;
+
+    let env = ExecutorEnv::builder().build().unwrap();
+    let session = ExecutorImpl::from_elf(env, HELLO_COMMIT_ELF)
+        .unwrap()
+        .run()
+        .unwrap();
+    let metadata = session.get_metadata().unwrap();
+    let journal = session.journal.unwrap().bytes;
+    let prove = |receipt: Receipt| {
+        let env = ExecutorEnv::builder()
+            .write(&MultiTestSpec::ReadAssumption { index: 0 })
+            .unwrap()
+            .build()
+            .unwrap();
+        let opts = prover_opts_fast().with_assumption(receipt);
+        LocalProver::new("local").prove_elf_with_ctx(
+            env,
+            &VerifierContext::default(),
+            MULTI_TEST_ELF,
+            &opts,
+        )
+    };
+
+    // The guest commits the digest of the assumption it reads.
+    let receipt = Receipt::new(
+        InnerReceipt::Fake {
+            metadata: metadata.clone(),
+        },
+        journal.clone(),
+    );
+    let expected = Sha256::digest(receipt.clone().into_assumption_bytes().unwrap());
+    let rollup = prove(receipt).unwrap();
+    assert_eq!(rollup.journal.bytes, expected.as_slice());
+
+    // Succinct receipts are proven with Poseidon, not the SHA-256 of the session.
+    let succinct = Receipt::new(
+        InnerReceipt::Succinct(SuccinctReceipt {
+            seal: Vec::new(),
+            control_id: Digest::ZERO,
+            meta: metadata,
+        }),
+        journal,
+    );
+    let err = prove(succinct.clone()).unwrap_err();
+    assert!(err.to_string().contains("hashfn poseidon"), "{err}");
+
+    // The same applies to the assumptions of a composite receipt.
+    let composite = Receipt::new(
+        InnerReceipt::Composite(CompositeReceipt {
+            segments: Vec::new(),
+            assumptions: vec![succinct.inner],
+            journal_digest: None,
+        }),
+        Vec::new(),
+    );
+    let err = prove(composite).unwrap_err();
+    assert!(err.to_string().contains("hashfn poseidon"), "{err}");
+}
+
+#[test]
+#[cfg_attr(feature = "cuda", serial)]
+fn verify_policy() {
+    let prover = LocalProver::new("local");
+    let verified = |policy: VerifyPolicy, cycles: u32| {
+        let env = ExecutorEnv::builder()
+            .write(&MultiTestSpec::BusyLoop { cycles })
+            .unwrap()
+            .build()
+            .unwrap();
+        let opts = prover_opts_fast().with_verify_policy(policy);
+        prover
+            .prove_elf_with_ctx(env, &VerifierContext::default(), MULTI_TEST_ELF, &opts)
+            .unwrap();
+        let report = prover.execution_report().unwrap();
+        (report.verified, report.total_cycles)
+    };
+
+    assert!(verified(VerifyPolicy::Always, 0).0);
+    assert!(!verified(VerifyPolicy::Never, 0).0);
+
+    // Sampling depends only on the image ID and the cycle count.
+    let policy = VerifyPolicy::SampleOneIn(2);
+    for cycles in [1000, 2000, 3000, 4000] {
+        let (verified, total_cycles) = verified(policy, cycles);
+        assert_eq!(verified, policy.selects(MULTI_TEST_ID, total_cycles));
+    }
+    let sampled = (0..1000)
+        .filter(|&cycles| policy.selects(MULTI_TEST_ID, cycles))
+        .count();
+    assert!((400..600).contains(&sampled));
+    assert!(!VerifyPolicy::SampleOneIn(0).selects(MULTI_TEST_ID, 0));
+}
+
+#[test]
+#[cfg(feature = "metrics")]
+#[cfg_attr(feature = "cuda", serial)]
+fn metrics() {
+    use metrics_util::debugging::{DebugValue, DebuggingRecorder};
+
+    let recorder = DebuggingRecorder::new();
+    let snapshotter = recorder.snapshotter();
+    let receipt = ::metrics::with_local_recorder(&recorder, || prove_nothing("sha-256")).unwrap();
+    let snapshot = snapshotter.snapshot().into_vec();
+
+    // The values recorded under `name`, with the labels of each.
+    let values = |name: &str| -> Vec<(Vec<(String, String)>, &DebugValue)> {
+        snapshot
+            .iter()
+            .filter(|(key, ..)| key.key().name() == name)
+            .map(|(key, _, _, value)| {
+                let labels = key
+                    .key()
+                    .labels()
+                    .map(|label| (label.key().to_string(), label.value().to_string()))
+                    .collect();
+                (labels, value)
+            })
+            .collect()
+    };
+    let hashfn = vec![("hashfn".to_string(), "sha-256".to_string())];
+
+    assert_eq!(
+        values("risc0_proofs_started"),
+        vec![(hashfn.clone(), &DebugValue::Counter(1))]
+    );
+    let completed_labels = vec![
+        ("hashfn".to_string(), "sha-256".to_string()),
+        ("exit_code".to_string(), "halted".to_string()),
+    ];
+    assert_eq!(
+        values("risc0_proofs_completed"),
+        vec![(completed_labels, &DebugValue::Counter(1))]
+    );
+    assert!(values("risc0_proofs_failed").is_empty());
+
+    let segments = receipt.inner.composite().unwrap().segments.len();
+    let [(labels, DebugValue::Histogram(po2s))] = &values("risc0_segment_po2")[..] else {
+        panic!("expected one risc0_segment_po2 histogram");
+    };
+    assert_eq!((labels, po2s.len()), (&hashfn, segments));
+    let [(_, DebugValue::Histogram(seal_bytes))] = &values("risc0_seal_bytes")[..] else {
+        panic!("expected one risc0_seal_bytes histogram");
+    };
+    assert_eq!(seal_bytes.len(), 1);
+    let [(_, DebugValue::Counter(cycles))] = &values("risc0_cycles_total")[..] else {
+        panic!("expected one risc0_cycles_total counter");
+    };
+    assert!(*cycles > 0);
+
+    let mut phases: Vec<_> = values("risc0_prove_duration_seconds")
+        .into_iter()
+        .map(|(labels, _)| labels[0].1.clone())
+        .collect();
+    phases.sort();
+    assert_eq!(phases, ["execute", "segment", "verify"]);
+    assert!(values("risc0_syscall_total")
+        .iter()
+        .all(|(labels, value)| labels[0].0 == "syscall" && **value != DebugValue::Counter(0)));
+    assert!(!values("risc0_syscall_total").is_empty());
+}
+
+#[test]
+#[cfg_attr(feature = "cuda", serial)]
+fn prune_receipt() {
+    let receipt = prove_nothing("sha-256").unwrap();
+    let pruned = receipt.prune().unwrap();
+    assert_eq!(pruned.metadata, receipt.get_metadata().unwrap());
+    assert_eq!(
+        pruned.segments.len(),
+        receipt.inner.composite().unwrap().segments.len()
+    );
+    pruned.verify_claim_only(MULTI_TEST_ID).unwrap();
+    assert_eq!(
+        pruned.verify_claim_only(Digest::ZERO).unwrap_err(),
+        VerificationError::ImageVerificationError
+    );
+
+    let receipt_bytes = to_vec(&receipt).unwrap();
+    let pruned_bytes = to_vec(&pruned).unwrap();
+    assert!(pruned_bytes.len() * 100 < receipt_bytes.len());
+    let decoded: PrunedReceipt = from_slice(&pruned_bytes).unwrap();
+    assert_eq!(decoded, pruned);
+}
+
+#[test]
+#[cfg_attr(feature = "cuda", serial)]
+fn flatten_receipt() {
+    let receipt = prove_nothing("sha-256").unwrap();
+    receipt.verify(MULTI_TEST_ID).unwrap();
+
+    let segment = receipt.flatten().unwrap();
+    segment
+        .verify_integrity_with_context(&VerifierContext::default())
+        .unwrap();
+    let encoded: Vec<u32> = to_vec(&segment).unwrap();
+    let segment: SegmentReceipt = from_slice(&encoded).unwrap();
+
+    let rebuilt = segment
+        .clone()
+        .into_receipt(receipt.journal.bytes.clone())
+        .unwrap();
+    assert_eq!(rebuilt, receipt);
+    rebuilt.verify(MULTI_TEST_ID).unwrap();
+    #[cfg(feature = "cbor")]
+    Receipt::from_cbor(&rebuilt.to_cbor().unwrap())
+        .unwrap()
+        .verify(MULTI_TEST_ID)
+        .unwrap();
+
+    // The journal is still checked against the one the segment committed to.
+    let tampered = segment.clone().into_receipt(b"tampered".to_vec()).unwrap();
+    assert_eq!(
+        tampered.verify(MULTI_TEST_ID).unwrap_err(),
+        VerificationError::JournalDigestMismatch
+    );
+
+    let mut inner = receipt.inner.composite().unwrap().clone();
+    inner.segments.push(segment);
+    let receipt = Receipt::new(InnerReceipt::Composite(inner), receipt.journal.bytes);
+    let err = receipt.flatten().unwrap_err();
+    assert!(err.to_string().contains("receipt has 2 segments"));
+}
+
+#[test]
+#[cfg_attr(feature = "cuda", serial)]
+fn check_image_id() {
+    let receipt = prove_nothing("sha-256").unwrap();
+    let mut image_id: Digest = MULTI_TEST_ID.into();
+    for word in image_id.as_mut_words() {
+        *word = word.wrapping_add(1);
+    }
+    assert_eq!(
+        receipt.verify(image_id).unwrap_err(),
+        VerificationError::ImageVerificationError
+    );
+}
+
+#[test]
+#[cfg_attr(feature = "cuda", serial)]
+fn prove_image_file() {
+    let program = Program::load_elf(MULTI_TEST_ELF, memory::GUEST_MAX_MEM as u32).unwrap();
+    let image = MemoryImage::new(&program, PAGE_SIZE as u32).unwrap();
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("multi_test.image");
+    image.save(&path).unwrap();
+
+    let env = || {
+        ExecutorEnv::builder()
+            .write(&MultiTestSpec::DoNothing)
+            .unwrap()
+            .build()
+            .unwrap()
+    };
+    let ctx = VerifierContext::default();
+    let opts = prover_opts_fast();
+    let prover = LocalProver::new("local");
+    let from_elf = prover
+        .prove_elf_with_ctx(env(), &ctx, MULTI_TEST_ELF, &opts)
+        .unwrap();
+    let from_file = prover
+        .prove_image_file(env(), &ctx, &path, Some(MULTI_TEST_ID.into()), &opts)
+        .unwrap();
+    from_file.verify(MULTI_TEST_ID).unwrap();
+    assert_eq!(
+        from_file.get_metadata().unwrap(),
+        from_elf.get_metadata().unwrap()
+    );
+    assert_eq!(from_file.journal, from_elf.journal);
+
+    let err = prover
+        .prove_image_file(env(), &ctx, &path, Some(Digest::ZERO), &opts)
+        .unwrap_err();
+    assert!(err.to_string().contains("Image ID mismatch"));
+}
+
+#[test]
+fn expected_image_id() {
+    let env = || {
+        ExecutorEnv::builder()
+            .write(&MultiTestSpec::DoNothing)
+            .unwrap()
+            .build()
+            .unwrap()
+    };
+    let ctx = VerifierContext::default();
+    let wrong_id = Digest::from(MULTI_TEST_ID).as_bytes().digest();
+    let opts = prover_opts_fast().with_expected_image_id(wrong_id);
+    let prover = LocalProver::new("local");
+
+    // The mismatch is caught before the guest is executed.
+    let err = prover
+        .prove_elf_with_ctx(env(), &ctx, MULTI_TEST_ELF, &opts)
+        .unwrap_err();
+    let msg = err.to_string();
+    assert!(msg.contains("Image ID mismatch"));
+    assert!(msg.contains(&Digest::from(MULTI_TEST_ID).to_string()));
+    assert!(msg.contains(&wrong_id.to_string()));
+    assert!(prover.execution_report().is_none());
+
+    let program = Program::load_elf(MULTI_TEST_ELF, memory::GUEST_MAX_MEM as u32).unwrap();
+    let image = MemoryImage::new(&program, PAGE_SIZE as u32).unwrap();
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("multi_test.image");
+    image.save(&path).unwrap();
+    let err = prover
+        .prove_image_file(env(), &ctx, &path, None, &opts)
+        .unwrap_err();
+    assert!(err.to_string().contains("Image ID mismatch"));
+    let err = prover.prove(env(), &ctx, &opts, image).unwrap_err();
+    assert!(err.to_string().contains("Image ID mismatch"));
+}
+
+#[test]
+fn buffer_pool() {
+    // Restore the previous limit when the test ends, so that the other tests
+    // keep running with pooling off whatever their order.
+    struct RestoreLimit(usize);
+
+    impl Drop for RestoreLimit {
+        fn drop(&mut self) {
+            set_buffer_pool_limit(self.0);
+        }
+    }
+
+    let _limit = RestoreLimit(set_buffer_pool_limit(usize::MAX));
+    let first = prove_nothing("sha-256").unwrap();
+    first.verify(MULTI_TEST_ID).unwrap();
+    // The witness buffers of the segment were returned to the pool of this
+    // thread.
+    let pooled = thread_buffer_pool_stats().held_bytes;
+    assert!(pooled > 0);
+    assert!(buffer_pool_stats().held_bytes >= pooled);
+
+    // Proving again reuses them, rather than pooling a second set.
+    let second = prove_nothing("sha-256").unwrap();
+    second.verify(MULTI_TEST_ID).unwrap();
+    assert_eq!(
+        second.get_metadata().unwrap(),
+        first.get_metadata().unwrap()
+    );
+    assert!(thread_buffer_pool_stats().held_bytes < 2 * pooled);
+
+    assert!(trim_memory() >= pooled);
+    assert_eq!(thread_buffer_pool_stats().held_bytes, 0);
+}
+
+#[test]
+fn env_prefix() {
+    std::env::set_var("ENV_PREFIX_TEST_ALPHA", "1");
+    std::env::set_var("ENV_PREFIX_TEST_BETA", "two");
+    std::env::set_var("ENV_PREFIX_OTHER", "3");
+    #[cfg(unix)]
+    std::env::set_var(
+        "ENV_PREFIX_TEST_BAD",
+        <std::ffi::OsStr as std::os::unix::ffi::OsStrExt>::from_bytes(b"\xff"),
+    );
+    let captured = |opts: &ProverOpts| {
+        let mut env = ExecutorEnv::builder()
+            .env_var("ENV_PREFIX_TEST_BETA", "explicit")
+            .build()
+            .unwrap();
+        opts.capture_env(&mut env);
+        let mut vars: Vec<_> = env.env_vars.into_iter().collect();
+        vars.sort();
+        vars
+    };
+    let vars = |pairs: &[(&str, &str)]| -> Vec<(String, String)> {
+        pairs
+            .iter()
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect()
+    };
+
+    // Variables set on the environment win over those copied from the host.
+    let opts = prover_opts_fast()
+        .with_env_prefix("ENV_PREFIX_TEST_")
+        .with_env_filter(|name, value| name == "ENV_PREFIX_OTHER" && value == "3");
+    assert_eq!(
+        captured(&opts),
+        vars(&[
+            ("ENV_PREFIX_OTHER", "3"),
+            ("ENV_PREFIX_TEST_ALPHA", "1"),
+            ("ENV_PREFIX_TEST_BETA", "explicit"),
+        ])
+    );
+    let opts = prover_opts_fast().with_env_prefix_stripped("ENV_PREFIX_TEST_");
+    assert_eq!(
+        captured(&opts),
+        vars(&[
+            ("ALPHA", "1"),
+            ("BETA", "two"),
+            ("ENV_PREFIX_TEST_BETA", "explicit"),
+        ])
+    );
+
+    let env = ExecutorEnv::builder()
+        .write(&MultiTestSpec::EnvVar {
+            name: "ALPHA".to_string(),
+        })
+        .unwrap()
+        .build()
+        .unwrap();
+    let receipt = LocalProver::new("local")
+        .prove_elf_with_ctx(env, &VerifierContext::default(), MULTI_TEST_ELF, &opts)
+        .unwrap();
+    assert_eq!(
+        receipt
+            .journal
+            .decode::<Option<String>>()
+            .unwrap()
+            .as_deref(),
+        Some("1")
+    );
+}
+
+#[test]
+fn setup_errors() {
+    #[derive(serde::Serialize)]
+    struct Unknown;
+
+    impl InputSchema for Unknown {
+        const SCHEMA: &'static str = "Unknown";
+    }
+
+    let env = ExecutorEnv::builder()
+        .write(&MultiTestSpec::DoNothing)
+        .unwrap()
+        .write_checked(&Unknown)
+        .unwrap()
+        .segment_limit_po2(2)
+        .build()
+        .unwrap();
+    let opts = ProverOpts {
+        hashfn: "blake3".to_string(),
+        ..Default::default()
+    }
+    .with_expected_image_id(Digest::ZERO);
+    let prover = LocalProver::new("local");
+
+    // The problems are all reported before the ELF is loaded, so the image ID
+    // is not checked yet.
+    let err = prover
+        .prove_elf_with_ctx(env, &VerifierContext::default(), MULTI_TEST_ELF, &opts)
+        .unwrap_err();
+    let errs = &err.downcast_ref::<SetupErrors>().unwrap().0;
+    assert_eq!(errs.len(), 3, "{errs:?}");
+    assert_eq!(errs[0], OptsError::UnsupportedHashFn("blake3".to_string()));
+    assert_eq!(errs[1], OptsError::InvalidSegmentLimitPo2(2));
+    assert!(matches!(
+        errs[2],
+        OptsError::InputSchemaMismatch { host, .. } if host == input_schema_digest(Unknown::SCHEMA)
+    ));
+    let msg = err.to_string();
+    assert!(msg.starts_with("3 problems with the setup:"), "{msg}");
+    for err in errs {
+        assert!(msg.contains(&err.to_string()), "{msg}");
+    }
+    assert!(prover.execution_report().is_none());
+}
+
+#[test]
+#[cfg_attr(feature = "cuda", serial)]
+fn post_image_chain() {
+    let ctx = VerifierContext::default();
+    let opts = prover_opts_fast();
+    let prover = LocalProver::new("local");
+    assert!(prover.post_image().is_err());
+
+    let env = ExecutorEnv::builder()
+        .write(&MultiTestSpec::PauseContinue(0))
+        .unwrap()
+        .build()
+        .unwrap();
+    let stage1 = prover
+        .prove_elf_with_ctx(env, &ctx, MULTI_TEST_ELF, &opts)
+        .unwrap();
+    assert_eq!(
+        stage1.get_metadata().unwrap().exit_code,
+        ExitCode::Paused(0)
+    );
+    let post_image = prover.post_image().unwrap();
+    let post_image_id = post_image.compute_id();
+
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("post.image");
+    post_image.save(&path).unwrap();
+    let env = ExecutorEnv::builder().build().unwrap();
+    let stage2 = prover
+        .prove_image_file(env, &ctx, &path, Some(post_image_id), &opts)
+        .unwrap();
+    assert_eq!(
+        stage2.get_metadata().unwrap().exit_code,
+        ExitCode::Halted(0)
+    );
+
+    stage1.verify(MULTI_TEST_ID).unwrap();
+    stage2.verify(post_image_id).unwrap();
+    assert_eq!(
+        stage1.get_metadata().unwrap().post.digest(),
+        stage2.get_metadata().unwrap().pre.digest()
+    );
+}
+
+#[test]
+#[cfg_attr(feature = "cuda", serial)]
+fn sha_accel() {
+    let env = ExecutorEnv::builder()
+        .write(&MultiTestSpec::ShaAccel {
+            len: 1000,
+            verify_one_in: 1,
+        })
+        .unwrap()
+        .sha_accel(true)
+        .build()
+        .unwrap();
+    let receipt = LocalProver::new("local")
+        .prove_elf_with_ctx(
+            env,
+            &VerifierContext::default(),
+            MULTI_TEST_ELF,
+            &prover_opts_fast(),
+        )
+        .unwrap();
+    receipt.verify(MULTI_TEST_ID).unwrap();
+    let (digest, reference): (Digest, Option<Digest>) = receipt.journal.decode().unwrap();
+    assert_eq!(Some(digest), reference);
+}
+
+#[test]
+#[cfg_attr(feature = "cuda", serial)]
+fn hashed_journal() {
+    let ctx = VerifierContext::default();
+    let opts = prover_opts_fast();
+    let env = |spec| {
+        ExecutorEnv::builder()
+            .write(&spec)
+            .unwrap()
+            .hashed_journal(true)
+            .build()
+            .unwrap()
+    };
+    let prove = |prover: &LocalProver, spec| {
+        prover.prove_elf_with_ctx(env(spec), &ctx, MULTI_TEST_ELF, &opts)
+    };
+
+    // The guest commits 100 items, and the journal is just their hash.
+    let prover = LocalProver::new("local");
+    assert!(prover.journal_preimages().is_err());
+    let receipt = prove(&prover, MultiTestSpec::HashedJournal { count: 50 }).unwrap();
+    assert_eq!(receipt.journal.bytes.len(), 32);
+    let items = prover.journal_preimages().unwrap();
+    assert_eq!(items.len(), 100);
+    assert_eq!(items[2 * 7], 7_u32.to_le_bytes());
+    assert_eq!(items[2 * 7 + 1], vec![7; 7]);
+
+    // A verifier recomputes the chain from the items.
+    assert_eq!(
+        receipt.journal.bytes,
+        crate::hashed_journal::chain(&items).as_bytes()
+    );
+    receipt
+        .verify_with_preimages(MULTI_TEST_ID, &items)
+        .unwrap();
+    let mut tampered = items.clone();
+    tampered[99][0] ^= 1;
+    assert_eq!(
+        receipt.verify_with_preimages(MULTI_TEST_ID, &tampered),
+        Err(VerificationError::JournalDigestMismatch)
+    );
+    assert_eq!(
+        receipt.verify_with_preimages(MULTI_TEST_ID, &items[..99]),
+        Err(VerificationError::JournalDigestMismatch)
+    );
+
+    // A guest that commits to its journal directly is not proven.
+    let err = prove(&prover, MultiTestSpec::DoNothing).err().unwrap();
+    assert!(
+        format!("{err:#}").contains("must call env::hashed_journal"),
+        "{err:#}"
+    );
+    assert!(prover.journal_preimages().is_err());
+
+    // The check is made by the executor, so it holds for every prover.
+    let err = SessionProver::new(MULTI_TEST_ELF, env(MultiTestSpec::DoNothing))
+        .err()
+        .unwrap();
+    assert!(
+        format!("{err:#}").contains("must call env::hashed_journal"),
+        "{err:#}"
+    );
+}
+
+#[test]
+#[serial]
+fn sha_basics() {
+    fn run_sha(msg: &str) -> String {
+        let env = ExecutorEnv::builder()
+            .write(&MultiTestSpec::ShaDigest { data: msg.into() })
+            .unwrap()
+            .build()
+            .unwrap();
+        let mut exec = ExecutorImpl::from_elf(env, MULTI_TEST_ELF).unwrap();
+        let session = exec.run().unwrap();
+        let receipt = prove_session_fast(&session);
+        hex::encode(Digest::try_from(receipt.journal.bytes).unwrap())
+    }
+
+    assert_eq!(
+        run_sha(""),
+        "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"
+    );
+    assert_eq!(
+        run_sha("a"),
+        "ca978112ca1bbdcafac231b39a23dc4da786eff8147c4e72b9807785afee48bb"
+    );
+    assert_eq!(
+        run_sha("abc"),
+        "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+    );
+    assert_eq!(
+        run_sha("abcdbcdecdefdefgefghfghighijhijkijkljklmklmnlmnomnopnopq"),
+        "248d6a61d20638b8e5c026930c3e6039a33ce45964ff2167f6ecedd419db06c1"
+    );
+}
+
+#[test]
+#[serial]
+fn sha_iter() {
+    let input = MultiTestSpec::ShaDigestIter {
+        data: Vec::from([0u8; 32]),
+        num_iter: 1500,
+    };
+    let env = ExecutorEnv::builder()
+        .write(&input)
+        .unwrap()
+        .build()
+        .unwrap();
+    let mut exec = ExecutorImpl::from_elf(env, MULTI_TEST_ELF).unwrap();
+    let session = exec.run().unwrap();
+    let receipt = prove_session_fast(&session);
+    let digest = Digest::try_from(receipt.journal.bytes).unwrap();
+    assert_eq!(
+        hex::encode(digest),
+        "9d4d1940b5c0c6d09c10add9631806f9df9467884d3e9ce4a147113e27f5c02a"
+    )
+}
+
+#[test]
+fn bigint_accel() {
+    let cases = testutils::generate_bigint_test_cases(&mut rand::thread_rng(), 10);
+    for case in cases {
+        println!("Running BigInt circuit test case: {:08x?}", case);
+        let input = MultiTestSpec::BigInt {
+            x: case.x,
+            y: case.y,
+            modulus: case.modulus,
+        };
+
+        let env = ExecutorEnv::builder()
+            .write(&input)
+            .unwrap()
+            .build()
+            .unwrap();
+        let mut exec = ExecutorImpl::from_elf(env, MULTI_TEST_ELF).unwrap();
+        let session = exec.run().unwrap();
+        let receipt = prove_session_fast(&session);
+        let expected = case.expected();
+        let expected: &[u8] = bytemuck::cast_slice(expected.as_slice());
+        assert_eq!(receipt.journal.bytes, expected);
+    }
+}
+
+#[test]
+#[serial]
+fn memory_io() {
+    fn run_memio(pairs: &[(usize, usize)]) -> Result<ExitCode> {
+        let input = MultiTestSpec::ReadWriteMem {
+            values: pairs
+                .iter()
+                .cloned()
+                .map(|(addr, value)| (addr as u32, value as u32))
+                .collect(),
+        };
+        let env = ExecutorEnv::builder()
+            .write(&input)
+            .unwrap()
+            .build()
+            .unwrap();
+        let mut exec = ExecutorImpl::from_elf(env, MULTI_TEST_ELF)?;
+        let session = exec.run()?;
+        let receipt = prove_session_fast(&session);
+        receipt.verify_integrity_with_context(&VerifierContext::default())?;
+        Ok(receipt.get_metadata()?.exit_code)
+    }
+
+    // Pick a memory position in the middle of the memory space, which is unlikely
+    // to be touched by either the stack or heap:
+    const POS: usize = crate::align_up(
+        (memory::TEXT_START + memory::STACK_TOP) as usize / 2,
+        WORD_SIZE,
+    );
+
+    // Double writes are fine
+    assert_eq!(
+        run_memio(&[(POS, 1), (POS, 1)]).unwrap(),
+        ExitCode::Halted(0)
+    );
+
+    // Writes at different addresses are fine
+    assert_eq!(
+        run_memio(&[(POS, 1), (POS + 4, 2)]).unwrap(),
+        ExitCode::Halted(0)
+    );
+
+    // Aligned write is fine
+    assert_eq!(run_memio(&[(POS, 1)]).unwrap(), ExitCode::Halted(0));
+
+    // Unaligned write is bad
+    assert_eq!(
+        run_memio(&[(POS + 1001, 1)]).unwrap(),
+        ExitCode::SystemSplit
+    );
+
+    // Aligned read is fine
+    assert_eq!(run_memio(&[(POS, 0)]).unwrap(), ExitCode::Halted(0));
+
+    // Unaligned read is bad
+    assert_eq!(run_memio(&[(POS + 1, 0)]).unwrap(), ExitCode::SystemSplit);
+}
+
+#[test]
+fn session_events() {
+    use std::{cell::RefCell, rc::Rc};
+
+    use risc0_zkvm_methods::HELLO_COMMIT_ELF;
+
+    use crate::{Segment, SessionEvents};
+
+    struct Logger {
+        on_pre_prove_segment_flag: Rc<RefCell<bool>>,
+        on_post_prove_segment_flag: Rc<RefCell<bool>>,
+    }
+
+    impl SessionEvents for Logger {
+        fn on_pre_prove_segment(&self, _: &Segment) {
+            self.on_pre_prove_segment_flag.replace(true);
+        }
+
+        fn on_post_prove_segment(&self, _: &Segment) {
+            self.on_post_prove_segment_flag.replace(true);
+        }
+    }
+
+    let mut exec = ExecutorImpl::from_elf(ExecutorEnv::default(), HELLO_COMMIT_ELF).unwrap();
+    let mut session = exec.run().unwrap();
+    let on_pre_prove_segment_flag = Rc::new(RefCell::new(false));
+    let on_post_prove_segment_flag = Rc::new(RefCell::new(false));
+    let logger = Logger {
+        on_pre_prove_segment_flag: on_pre_prove_segment_flag.clone(),
+        on_post_prove_segment_flag: on_post_prove_segment_flag.clone(),
+    };
+    session.add_hook(logger);
+    prove_session_fast(&session);
+    assert_eq!(session.hooks.len(), 1);
+    assert_eq!(on_pre_prove_segment_flag.take(), true);
+    assert_eq!(on_post_prove_segment_flag.take(), true);
+}
+
+#[test]
+fn prove_segment_independently() {
+    use crate::sha::Digestible;
+
+    let segment_limit_po2 = 16; // 64k cycles
+    let env = ExecutorEnv::builder()
+        .write(&MultiTestSpec::BusyLoop {
+            cycles: 1 << segment_limit_po2,
+        })
+        .unwrap()
+        .segment_limit_po2(segment_limit_po2)
+        .build()
+        .unwrap();
+    let mut exec = ExecutorImpl::from_elf(env, MULTI_TEST_ELF).unwrap();
+    let mut segments = Vec::new();
+    while let Some(segment) = exec.next_segment() {
+        segments.push(segment.unwrap());
+    }
+    assert!(exec.next_segment().is_none());
+    let session = exec.finish().unwrap();
+    assert_eq!(session.exit_code, ExitCode::Halted(0));
+    assert!(session.segments.is_empty());
+    assert!(segments.len() > 1);
+
+    for (index, pair) in segments.windows(2).enumerate() {
+        assert_eq!(pair[0].index, index as u32);
+        assert_eq!(pair[0].exit_code(), ExitCode::SystemSplit);
+        assert_eq!(pair[0].post_image_id(), pair[1].pre_image_id());
+    }
+
+    // A segment can be handed to another process to be proven.
+    let segment: Segment =
+        bincode::deserialize(&bincode::serialize(&segments[0]).unwrap()).unwrap();
+    assert_eq!(segment.pre_image_id(), segments[0].pre_image_id());
+    assert_eq!(segment.cycles, segments[0].cycles);
+
+    // Prove a single segment without the rest of the session.
+    let segment = &segment;
+    let ctx = VerifierContext::default();
+    let receipt = get_prover_server(&prover_opts_fast())
+        .unwrap()
+        .prove_segment(&ctx, segment)
+        .unwrap();
+    let metadata = receipt.get_metadata().unwrap();
+    assert_eq!(metadata.pre.digest(), segment.pre_image_id());
+    assert_eq!(metadata.post.digest(), segment.post_image_id());
+}
+
+#[test]
+fn segment_hook() {
+    let segment_limit_po2 = 16; // 64k cycles
+    let env = ExecutorEnv::builder()
+        .write(&MultiTestSpec::BusyLoop {
+            cycles: 1 << segment_limit_po2,
+        })
+        .unwrap()
+        .segment_limit_po2(segment_limit_po2)
+        .build()
+        .unwrap();
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().to_path_buf();
+    let opts = prover_opts_fast().with_segment_hook(move |segment| {
+        let file = std::fs::File::create(path.join(format!("{}.bincode", segment.index)))?;
+        segment.write_to(file)?;
+        Ok(SegmentAction::Prove)
+    });
+    let receipt = get_prover_server(&opts)
+        .unwrap()
+        .prove_elf(env, MULTI_TEST_ELF)
+        .unwrap();
+    let segments = receipt.inner.composite().unwrap().segments.len();
+    assert!(segments > 1);
+    assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), segments);
+    receipt.verify(MULTI_TEST_ID).unwrap();
+
+    // Skipped segments are executed, but not proven.
+    let env = ExecutorEnv::builder()
+        .write(&MultiTestSpec::BusyLoop {
+            cycles: 1 << segment_limit_po2,
+        })
+        .unwrap()
+        .segment_limit_po2(segment_limit_po2)
+        .build()
+        .unwrap();
+    let opts = prover_opts_fast().with_segment_hook(|segment| {
+        Ok(match segment.index {
+            0 => SegmentAction::Skip,
+            _ => SegmentAction::Prove,
+        })
+    });
+    let receipt = get_prover_server(&opts)
+        .unwrap()
+        .prove_elf(env, MULTI_TEST_ELF)
+        .unwrap();
+    let indices: Vec<_> = receipt
+        .inner
+        .composite()
+        .unwrap()
+        .segments
+        .iter()
+        .map(|segment| segment.index)
+        .collect();
+    assert_eq!(indices, (1..segments as u32).collect::<Vec<_>>());
+}
+
+#[test]
+fn segment_hook_abort() {
+    let prove = |opts: ProverOpts| {
+        let env = ExecutorEnv::builder()
+            .write(&MultiTestSpec::DoNothing)
+            .unwrap()
+            .build()
+            .unwrap();
+        LocalProver::new("local")
+            .prove_elf_with_ctx(env, &VerifierContext::default(), MULTI_TEST_ELF, &opts)
+            .unwrap_err()
+    };
+
+    // The hook runs as the segment is executed, so an aborted run has no
+    // executed segments to report.
+    let opts = prover_opts_fast().with_segment_hook(|_| Ok(SegmentAction::Abort));
+    let err = prove(opts);
+    assert!(err
+        .downcast_ref::<ExecutionReport>()
+        .unwrap()
+        .segments
+        .is_empty());
+    assert_eq!(
+        err.root_cause().to_string(),
+        "segment hook aborted proving at segment 0"
+    );
+
+    let opts = prover_opts_fast().with_segment_hook(|_| Err(anyhow::anyhow!("upload failed")));
+    assert!(
+        format!("{:#}", prove(opts)).contains("segment hook failed on segment 0: upload failed")
+    );
+}
+
+#[test]
+fn prover_opts_send_sync() {
+    fn assert_send_sync<T: Send + Sync>() {}
+    assert_send_sync::<ProverOpts>();
+}
+
+#[test]
+fn cancel_token() {
+    let segment_limit_po2 = 16; // 64k cycles
+    let env = ExecutorEnv::builder()
+        .write(&MultiTestSpec::BusyLoop {
+            cycles: 2 << segment_limit_po2,
+        })
+        .unwrap()
+        .segment_limit_po2(segment_limit_po2)
+        .build()
+        .unwrap();
+    let token = CancelToken::new();
+    let hook_token = token.clone();
+    let mut opts = prover_opts_fast().with_cancel_token(token.clone());
+    opts.prove_observer = Some(Arc::new(move |segment: &Segment| {
+        // Cancel while the second segment is being proven, as if from
+        // another thread.
+        if segment.index == 1 {
+            hook_token.cancel();
+        }
+    }));
+    let prover = LocalProver::new("local");
+    let err = prover
+        .prove_elf_with_ctx(env, &VerifierContext::default(), MULTI_TEST_ELF, &opts)
+        .unwrap_err();
+    assert!(token.is_cancelled());
+
+    let report = err.downcast_ref::<ExecutionReport>().unwrap().clone();
+    assert!(report.segments.len() > 2);
+    assert!(report.error.unwrap().contains("proving was cancelled"));
+    let cancelled = err.downcast::<ProvingCancelled>().unwrap();
+    assert_eq!(cancelled.segments.len(), 2);
+    let cycles: u64 = report.segments[..2]
+        .iter()
+        .map(|segment| segment.cycles as u64)
+        .sum();
+    assert_eq!(cancelled.cycles, cycles);
+    let ctx = VerifierContext::default();
+    for (idx, receipt) in cancelled.segments.iter().enumerate() {
+        assert_eq!(receipt.index, idx as u32);
+        receipt.verify_integrity_with_context(&ctx).unwrap();
+    }
+}
+
+#[test]
+#[cfg(feature = "service")]
+#[cfg_attr(feature = "cuda", serial)]
+fn proving_service() {
+    use crate::{JobStatus, ProvingService};
+
+    let input: Vec<u8> = to_vec(&MultiTestSpec::BusyLoop { cycles: 1 << 16 })
+        .unwrap()
+        .into_iter()
+        .flat_map(u32::to_le_bytes)
+        .collect();
+    let service = ProvingService::new(1);
+    assert!(service
+        .submit(
+            MULTI_TEST_ELF,
+            input.clone(),
+            prover_opts_fast().with_cancel_token(CancelToken::new())
+        )
+        .is_err());
+    let jobs: Vec<_> = (0..3)
+        .map(|_| {
+            service
+                .submit(MULTI_TEST_ELF, input.clone(), prover_opts_fast())
+                .unwrap()
+        })
+        .collect();
+
+    // The single worker takes the jobs in order, so the last one is still
+    // queued while the first is being proven.
+    assert!(matches!(service.status(jobs[2]), Some(JobStatus::Queued)));
+    assert!(service.cancel(jobs[2]));
+    assert!(matches!(
+        service.status(jobs[2]),
+        Some(JobStatus::Cancelled)
+    ));
+    assert!(service.wait(jobs[2]).is_err());
+    assert!(!service.cancel(jobs[2]));
+
+    let rank = |status: &JobStatus| match status {
+        JobStatus::Queued => 0,
+        JobStatus::Executing { .. } => 1,
+        JobStatus::Proving { segment, total } => {
+            assert!(segment < total);
+            2
+        }
+        JobStatus::Done(_) => 3,
+        status => panic!("unexpected {status:?}"),
+    };
+    let mut seen = [vec![], vec![]];
+    loop {
+        let statuses = [jobs[0], jobs[1]].map(|job| service.status(job).unwrap());
+        for (seen, status) in seen.iter_mut().zip(&statuses) {
+            seen.push(rank(status));
+        }
+        // The second job only starts once the first is done.
+        if rank(&statuses[1]) > 0 {
+            assert_eq!(rank(&statuses[0]), 3);
+        }
+        if statuses[1].is_finished() {
+            break;
+        }
+        std::thread::sleep(Duration::from_millis(10));
+    }
+    for seen in seen {
+        assert!(seen.windows(2).all(|pair| pair[0] <= pair[1]), "{seen:?}");
+    }
+
+    for job in &jobs[..2] {
+        let receipt = service.wait(*job).unwrap();
+        receipt.verify(MULTI_TEST_ID).unwrap();
+        assert!(matches!(service.remove(*job), Some(JobStatus::Done(_))));
+        assert!(service.status(*job).is_none());
+    }
+}
+
+#[test]
+#[cfg(feature = "service")]
+fn proving_service_prover_panic() {
+    use crate::{JobStatus, ProvingService};
+
+    struct PanickingProver;
+
+    impl Prover for PanickingProver {
+        fn get_name(&self) -> String {
+            "panicking".to_string()
+        }
+
+        fn prove(
+            &self,
+            _env: ExecutorEnv<'_>,
+            _ctx: &VerifierContext,
+            _opts: &ProverOpts,
+            _image: MemoryImage,
+        ) -> Result<Receipt> {
+            panic!("the prover crashed")
+        }
+    }
+
+    // Each job fails with the panic, and the single worker survives it to
+    // take the next job.
+    let service = ProvingService::with_prover(1, || Box::new(PanickingProver));
+    let jobs: Vec<_> = (0..2)
+        .map(|_| {
+            service
+                .submit(MULTI_TEST_ELF, vec![], prover_opts_fast())
+                .unwrap()
+        })
+        .collect();
+    for job in jobs {
+        let err = service.wait(job).unwrap_err();
+        assert!(
+            format!("{err:#}").contains("panicked: the prover crashed"),
+            "{err:#}"
+        );
+        assert!(matches!(service.status(job), Some(JobStatus::Failed(_))));
+    }
+}
+
+#[test]
+fn auto_po2_downshift() {
+    let env = ExecutorEnv::builder()
+        .write(&MultiTestSpec::BusyLoop { cycles: 1 << 15 })
+        .unwrap()
+        .segment_limit_po2(16)
+        .build()
+        .unwrap();
+    let session = ExecutorImpl::from_elf(env, MULTI_TEST_ELF)
+        .unwrap()
+        .run()
+        .unwrap();
+    let segments = session.resolve().unwrap();
+    assert_eq!(segments.len(), 1);
+    assert_eq!(segments[0].po2, 16);
+
+    // Size the device so that every allocation made proving a segment of po2
+    // 15 fits, while the largest ones made at po2 16 do not.
+    let ctx = VerifierContext::default();
+    let hal = Rc::new(FlakyHal::new());
+    let hal_pair = HalPair {
+        hal: hal.clone(),
+        circuit_hal: Rc::new(CpuCircuitHal::new(&CIRCUIT)),
+    };
+    ProverImpl::new("cpu", hal_pair)
+        .prove_segment(&ctx, &segments[0].resegment(15).unwrap()[0])
+        .unwrap();
+    let max_alloc = hal.largest_alloc.get();
+
+    let prover = |auto_po2_downshift| {
+        let hal_pair = HalPair {
+            hal: Rc::new(FlakyHal {
+                max_alloc,
+                ..FlakyHal::new()
+            }),
+            circuit_hal: Rc::new(CpuCircuitHal::new(&CIRCUIT)),
+        };
+        ProverImpl::new("cpu", hal_pair).with_auto_po2_downshift(auto_po2_downshift)
+    };
+
+    // Without downshift, running out of memory is fatal.
+    let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+        prover(false).prove_session(&ctx, &session)
+    }));
+    assert!(result.is_err());
+
+    let receipt = prover(true).prove_session(&ctx, &session).unwrap();
+    let receipts = &receipt.inner.composite().unwrap().segments;
+    assert!(receipts.len() > 1);
+    for (i, receipt) in receipts.iter().enumerate() {
+        assert_eq!(receipt.index, i as u32);
+        assert!(receipt.po2().unwrap() <= 15);
+    }
+    receipt.verify(MULTI_TEST_ID).unwrap();
+}
+
+// A [CpuHal] whose first allocation fails with `fault` when `faulty`, and
+// which runs out of memory on allocations of more than `max_alloc` elements.
+struct FlakyHal {
+    inner: CpuHal<BabyBear>,
+    faulty: Cell<bool>,
+    fault: &'static str,
+    max_alloc: usize,
+    // The number of elements of the largest allocation made so far.
+    largest_alloc: Cell<usize>,
+}
+
+impl FlakyHal {
+    fn new() -> Self {
+        Self {
+            inner: CpuHal::new(Sha256HashSuite::new_suite()),
+            faulty: Cell::new(false),
+            fault: DEVICE_FAULT,
+            max_alloc: usize::MAX,
+            largest_alloc: Cell::new(0),
+        }
+    }
+
+    fn check(&self, size: usize) {
+        if self.faulty.replace(false) {
+            panic!("{}: simulated", self.fault);
+        }
+        if size > self.max_alloc {
+            panic!("{OUT_OF_MEMORY}: simulated allocation of {size} elements");
+        }
+        self.largest_alloc.set(self.largest_alloc.get().max(size));
+    }
+}
+
+impl Hal for FlakyHal {
+    type Field = BabyBear;
+    type Elem = BabyBearElem;
+    type ExtElem = BabyBearExtElem;
+    type Buffer<T: Clone + Debug + PartialEq + Pod> = CpuBuffer<T>;
+
+    fn has_unified_memory(&self) -> bool {
+        self.inner.has_unified_memory()
+    }
+
+    fn get_hash_suite(&self) -> &HashSuite<BabyBear> {
+        self.inner.get_hash_suite()
+    }
+
+    fn alloc_digest(&self, name: &'static str, size: usize) -> CpuBuffer<Digest> {
+        self.check(size);
+        self.inner.alloc_digest(name, size)
+    }
+
+    fn alloc_elem(&self, name: &'static str, size: usize) -> CpuBuffer<BabyBearElem> {
+        self.check(size);
+        self.inner.alloc_elem(name, size)
+    }
+
+    fn alloc_extelem(&self, name: &'static str, size: usize) -> CpuBuffer<BabyBearExtElem> {
+        self.check(size);
+        self.inner.alloc_extelem(name, size)
+    }
+
+    fn alloc_u32(&self, name: &'static str, size: usize) -> CpuBuffer<u32> {
+        self.check(size);
+        self.inner.alloc_u32(name, size)
+    }
 
-        let env = ExecutorEnv::builder()
-            .write(&input)
-            .unwrap()
-            .build()
-            .unwrap();
-        let mut exec = ExecutorImpl::from_elf(env, MULTI_TEST_ELF).unwrap();
-        let session = exec.run().unwrap();
-        let receipt = prove_session_fast(&session);
-        let expected = case.expected();
-        let expected: &[u8] = bytemuck::cast_slice(expected.as_slice());
-        assert_eq!(receipt.journal.bytes, expected);
+    fn copy_from_digest(&self, name: &'static str, slice: &[Digest]) -> CpuBuffer<Digest> {
+        self.check(slice.len());
+        self.inner.copy_from_digest(name, slice)
     }
-}
 
-#[test]
-#[serial]
-fn memory_io() {
-    fn run_memio(pairs: &[(usize, usize)]) -> Result<ExitCode> {
-        let input = MultiTestSpec::ReadWriteMem {
-            values: pairs
-                .iter()
-                .cloned()
-                .map(|(addr, value)| (addr as u32, value as u32))
-                .collect(),
-        };
-        let env = ExecutorEnv::builder()
-            .write(&input)
-            .unwrap()
-            .build()
-            .unwrap();
-        let mut exec = ExecutorImpl::from_elf(env, MULTI_TEST_ELF)?;
-        let session = exec.run()?;
-        let receipt = prove_session_fast(&session);
-        receipt.verify_integrity_with_context(&VerifierContext::default())?;
-        Ok(receipt.get_metadata()?.exit_code)
+    fn copy_from_elem(
+        &self,
+        name: &'static str,
+        slice: &[BabyBearElem],
+    ) -> CpuBuffer<BabyBearElem> {
+        self.check(slice.len());
+        self.inner.copy_from_elem(name, slice)
     }
 
-    // Pick a memory position in the middle of the memory space, which is unlikely
-    // to be touched by either the stack or heap:
-    const POS: usize = crate::align_up(
-        (memory::TEXT_START + memory::STACK_TOP) as usize / 2,
-        WORD_SIZE,
-    );
+    fn copy_from_extelem(
+        &self,
+        name: &'static str,
+        slice: &[BabyBearExtElem],
+    ) -> CpuBuffer<BabyBearExtElem> {
+        self.check(slice.len());
+        self.inner.copy_from_extelem(name, slice)
+    }
 
-    // Double writes are fine
-    assert_eq!(
-        run_memio(&[(POS, 1), (POS, 1)]).unwrap(),
-        ExitCode::Halted(0)
-    );
+    fn copy_from_u32(&self, name: &'static str, slice: &[u32]) -> CpuBuffer<u32> {
+        self.check(slice.len());
+        self.inner.copy_from_u32(name, slice)
+    }
 
-    // Writes at different addresses are fine
-    assert_eq!(
-        run_memio(&[(POS, 1), (POS + 4, 2)]).unwrap(),
-        ExitCode::Halted(0)
-    );
+    fn batch_expand_into_evaluate_ntt(
+        &self,
+        output: &CpuBuffer<BabyBearElem>,
+        input: &CpuBuffer<BabyBearElem>,
+        count: usize,
+        expand_bits: usize,
+    ) {
+        self.inner
+            .batch_expand_into_evaluate_ntt(output, input, count, expand_bits)
+    }
 
-    // Aligned write is fine
-    assert_eq!(run_memio(&[(POS, 1)]).unwrap(), ExitCode::Halted(0));
+    fn batch_interpolate_ntt(&self, io: &CpuBuffer<BabyBearElem>, count: usize) {
+        self.inner.batch_interpolate_ntt(io, count)
+    }
 
-    // Unaligned write is bad
-    assert_eq!(
-        run_memio(&[(POS + 1001, 1)]).unwrap(),
-        ExitCode::SystemSplit
-    );
+    fn batch_bit_reverse(&self, io: &CpuBuffer<BabyBearElem>, count: usize) {
+        self.inner.batch_bit_reverse(io, count)
+    }
 
-    // Aligned read is fine
-    assert_eq!(run_memio(&[(POS, 0)]).unwrap(), ExitCode::Halted(0));
+    fn batch_evaluate_any(
+        &self,
+        coeffs: &CpuBuffer<BabyBearElem>,
+        poly_count: usize,
+        which: &CpuBuffer<u32>,
+        xs: &CpuBuffer<BabyBearExtElem>,
+        out: &CpuBuffer<BabyBearExtElem>,
+    ) {
+        self.inner
+            .batch_evaluate_any(coeffs, poly_count, which, xs, out)
+    }
 
-    // Unaligned read is bad
-    assert_eq!(run_memio(&[(POS + 1, 0)]).unwrap(), ExitCode::SystemSplit);
-}
+    fn zk_shift(&self, io: &CpuBuffer<BabyBearElem>, count: usize) {
+        self.inner.zk_shift(io, count)
+    }
 
-#[test]
-fn session_events() {
-    use std::{cell::RefCell, rc::Rc};
+    fn mix_poly_coeffs(
+        &self,
+        out: &CpuBuffer<BabyBearExtElem>,
+        mix_start: &BabyBearExtElem,
+        mix: &BabyBearExtElem,
+        input: &CpuBuffer<BabyBearElem>,
+        combos: &CpuBuffer<u32>,
+        input_size: usize,
+        count: usize,
+    ) {
+        self.inner
+            .mix_poly_coeffs(out, mix_start, mix, input, combos, input_size, count)
+    }
 
-    use risc0_zkvm_methods::HELLO_COMMIT_ELF;
+    fn eltwise_add_elem(
+        &self,
+        output: &CpuBuffer<BabyBearElem>,
+        input1: &CpuBuffer<BabyBearElem>,
+        input2: &CpuBuffer<BabyBearElem>,
+    ) {
+        self.inner.eltwise_add_elem(output, input1, input2)
+    }
 
-    use crate::{Segment, SessionEvents};
+    fn eltwise_sum_extelem(
+        &self,
+        output: &CpuBuffer<BabyBearElem>,
+        input: &CpuBuffer<BabyBearExtElem>,
+    ) {
+        self.inner.eltwise_sum_extelem(output, input)
+    }
 
-    struct Logger {
-        on_pre_prove_segment_flag: Rc<RefCell<bool>>,
-        on_post_prove_segment_flag: Rc<RefCell<bool>>,
+    fn eltwise_copy_elem(&self, output: &CpuBuffer<BabyBearElem>, input: &CpuBuffer<BabyBearElem>) {
+        self.inner.eltwise_copy_elem(output, input)
     }
 
-    impl SessionEvents for Logger {
-        fn on_pre_prove_segment(&self, _: &Segment) {
-            self.on_pre_prove_segment_flag.replace(true);
-        }
+    fn fri_fold(
+        &self,
+        output: &CpuBuffer<BabyBearElem>,
+        input: &CpuBuffer<BabyBearElem>,
+        mix: &BabyBearExtElem,
+    ) {
+        self.inner.fri_fold(output, input, mix)
+    }
 
-        fn on_post_prove_segment(&self, _: &Segment) {
-            self.on_post_prove_segment_flag.replace(true);
+    fn hash_rows(&self, output: &CpuBuffer<Digest>, matrix: &CpuBuffer<BabyBearElem>) {
+        self.inner.hash_rows(output, matrix)
+    }
+
+    fn hash_fold(&self, io: &CpuBuffer<Digest>, input_size: usize, output_size: usize) {
+        self.inner.hash_fold(io, input_size, output_size)
+    }
+
+    fn gather_sample(
+        &self,
+        dst: &CpuBuffer<BabyBearElem>,
+        src: &CpuBuffer<BabyBearElem>,
+        idx: usize,
+        size: usize,
+        stride: usize,
+    ) {
+        self.inner.gather_sample(dst, src, idx, size, stride)
+    }
+}
+
+#[test]
+fn segment_retries() {
+    use std::cell::RefCell;
+
+    use crate::{Segment, SessionEvents};
+
+    struct RetryLogger(Rc<RefCell<Vec<(u32, u32)>>>);
+
+    impl SessionEvents for RetryLogger {
+        fn on_segment_retry(&self, segment: &Segment, retry: u32) {
+            self.0.borrow_mut().push((segment.index, retry));
         }
     }
 
-    let mut exec = ExecutorImpl::from_elf(ExecutorEnv::default(), HELLO_COMMIT_ELF).unwrap();
-    let mut session = exec.run().unwrap();
-    let on_pre_prove_segment_flag = Rc::new(RefCell::new(false));
-    let on_post_prove_segment_flag = Rc::new(RefCell::new(false));
-    let logger = Logger {
-        on_pre_prove_segment_flag: on_pre_prove_segment_flag.clone(),
-        on_post_prove_segment_flag: on_post_prove_segment_flag.clone(),
+    let env = ExecutorEnv::builder()
+        .write(&MultiTestSpec::DoNothing)
+        .unwrap()
+        .build()
+        .unwrap();
+    let mut session = ExecutorImpl::from_elf(env, MULTI_TEST_ELF)
+        .unwrap()
+        .run()
+        .unwrap();
+    let retries = Rc::new(RefCell::new(Vec::new()));
+    session.add_hook(RetryLogger(retries.clone()));
+
+    // The first HAL made fails with `fault`; the ones after it are healthy.
+    let prover = |fault: &'static str, segment_retries| {
+        let made = Rc::new(Cell::new(0));
+        let make = move || {
+            made.set(made.get() + 1);
+            HalPair {
+                hal: Rc::new(FlakyHal {
+                    faulty: Cell::new(made.get() == 1),
+                    fault,
+                    ..FlakyHal::new()
+                }),
+                circuit_hal: Rc::new(CpuCircuitHal::new(&CIRCUIT)),
+            }
+        };
+        ProverImpl::new("cpu", make())
+            .with_hal_factory(make)
+            .with_segment_retries(segment_retries, Duration::ZERO)
     };
-    session.add_hook(logger);
-    prove_session_fast(&session);
-    assert_eq!(session.hooks.len(), 1);
-    assert_eq!(on_pre_prove_segment_flag.take(), true);
-    assert_eq!(on_post_prove_segment_flag.take(), true);
+    let ctx = VerifierContext::default();
+    let fails = |fault, segment_retries| {
+        std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            prover(fault, segment_retries).prove_session(&ctx, &session)
+        }))
+        .is_err()
+    };
+
+    // Without retries, a device fault is fatal.
+    assert!(fails(DEVICE_FAULT, 0));
+    assert!(retries.borrow().is_empty());
+
+    // Other failures are not retried.
+    assert!(fails("boom", 3));
+    assert!(fails(OUT_OF_MEMORY, 3));
+    assert!(retries.borrow().is_empty());
+
+    // The faulty segment is proven again on a fresh HAL.
+    let receipt = prover(DEVICE_FAULT, 1)
+        .prove_session(&ctx, &session)
+        .unwrap();
+    receipt.verify(MULTI_TEST_ID).unwrap();
+    assert_eq!(*retries.borrow(), [(0, 1)]);
+}
+
+#[test]
+#[cfg(feature = "circuit-debug")]
+fn plonk_row_error() {
+    let env = ExecutorEnv::builder()
+        .write(&MultiTestSpec::DoNothing)
+        .unwrap()
+        .build()
+        .unwrap();
+    let session = ExecutorImpl::from_elf(env, MULTI_TEST_ELF)
+        .unwrap()
+        .run()
+        .unwrap();
+    let segments = session.resolve().unwrap();
+    let prover = || {
+        let hal_pair = HalPair {
+            hal: Rc::new(CpuHal::new(Sha256HashSuite::new_suite())),
+            circuit_hal: Rc::new(CpuCircuitHal::new(&CIRCUIT)),
+        };
+        ProverImpl::new("cpu", hal_pair)
+    };
+    let ctx = VerifierContext::default();
+
+    // An intact witness passes the row checks.
+    prover().prove_segment(&ctx, &segments[0]).unwrap();
+
+    // A corrupted row is reported before any proving is done, with the value
+    // of the row before it.
+    let err = prover()
+        .with_corrupt_ram_row(10)
+        .prove_segment(&ctx, &segments[0])
+        .err()
+        .unwrap();
+    let report = err.downcast_ref::<crate::PlonkRowError>().unwrap();
+    assert!(report.row > 0);
+    assert_ne!(report.op, 2);
+    assert_eq!(report.value, report.expected ^ 1);
+    if let Some(pc) = report.pc {
+        assert_eq!(pc % WORD_SIZE as u32, 0);
+    }
+    assert!(err
+        .to_string()
+        .starts_with(&format!("RAM plonk row {}", report.row)));
 }
 
 // These tests come from:
@@ -386,11 +2449,12 @@ mod riscv {
 
 #[cfg(feature = "docker")]
 mod docker {
-    use risc0_zkvm_methods::{multi_test::MultiTestSpec, MULTI_TEST_ELF};
+    use risc0_zkp::core::digest::Digest;
+    use risc0_zkvm_methods::{multi_test::MultiTestSpec, MULTI_TEST_ELF, MULTI_TEST_ID};
     use test_log::test;
 
     use super::prove_session_fast;
-    use crate::{ExecutorEnv, ExecutorImpl, ExitCode};
+    use crate::{sha::Digestible, ExecutorEnv, ExecutorImpl, ExitCode};
 
     #[test]
     fn pause_continue() {
@@ -416,6 +2480,35 @@ mod docker {
         prove_session_fast(&session);
     }
 
+    #[test]
+    fn guest_yield() {
+        let env = ExecutorEnv::builder()
+            .write(&MultiTestSpec::Yield { tags: vec![7, 9] })
+            .unwrap()
+            .build()
+            .unwrap();
+        let mut exec = ExecutorImpl::from_elf(env, MULTI_TEST_ELF).unwrap();
+
+        // Each yield is a pause point that does not affect the proof: the
+        // receipts chain together from the original image to the final halt.
+        let mut tags = Vec::new();
+        let mut image_id: Digest = MULTI_TEST_ID.into();
+        loop {
+            let session = exec.run().unwrap();
+            let metadata = prove_session_fast(&session).get_metadata().unwrap();
+            assert_eq!(metadata.pre.digest(), image_id);
+            image_id = metadata.post.digest();
+            match session.yield_tag {
+                Some(tag) => tags.push(tag),
+                None => {
+                    assert_eq!(metadata.exit_code, ExitCode::Halted(0));
+                    break;
+                }
+            }
+        }
+        assert_eq!(tags, [7, 9]);
+    }
+
     #[test]
     fn continuation() {
         const COUNT: usize = 2; // Number of total chunks to aim for.
@@ -461,8 +2554,10 @@ mod sys_verify {
 
     use super::{get_prover_server, prover_opts_fast};
     use crate::{
-        serde::to_vec, sha::Digestible, ExecutorEnv, ExecutorEnvBuilder, ExitCode, ProverOpts,
-        Receipt,
+        serde::to_vec,
+        sha::{Digest, Digestible},
+        ExecutorEnv, ExecutorEnvBuilder, ExitCode, LocalProver, ProveOpts, Prover, ProverOpts,
+        Receipt, VerifierContext,
     };
 
     fn prove_hello_commit() -> Receipt {
@@ -480,6 +2575,7 @@ mod sys_verify {
         let opts = ProverOpts {
             hashfn: "sha-256".to_string(),
             prove_guest_errors: true,
+            ..Default::default()
         };
 
         let env = ExecutorEnvBuilder::default()
@@ -506,6 +2602,7 @@ mod sys_verify {
         let opts = ProverOpts {
             hashfn: "sha-256".to_string(),
             prove_guest_errors: true,
+            ..Default::default()
         };
 
         let env = ExecutorEnvBuilder::default()
@@ -685,4 +2782,255 @@ mod sys_verify {
             .verify(MULTI_TEST_ID)
             .unwrap();
     }
+
+    #[test]
+    fn exec_verify_hashfn() {
+        // The nested guest must be proven with the hash function of the
+        // calling guest, or its receipt cannot be an assumption of the
+        // calling guest's session.
+        let spec = &MultiTestSpec::ExecVerify {
+            name: "hello".into(),
+            arg: Vec::new(),
+        };
+        let env = ExecutorEnv::builder()
+            .write(&spec)
+            .unwrap()
+            .guest_registry([("hello".to_string(), HELLO_COMMIT_ELF.to_vec())])
+            .prove_nested(true)
+            .build()
+            .unwrap();
+        let opts = ProveOpts::default().with_hashfn("sha-256");
+        let receipt = LocalProver::new("test")
+            .prove_elf_with_opts(env, &VerifierContext::default(), MULTI_TEST_ELF, &opts)
+            .unwrap();
+        receipt.verify(MULTI_TEST_ID).unwrap();
+        assert_eq!(receipt.inner.hashfn(), Some("sha-256"));
+
+        let (image_id, journal): (Digest, Vec<u8>) = receipt.journal.decode().unwrap();
+        assert_eq!(image_id, HELLO_COMMIT_ID.into());
+        assert_eq!(journal, HELLO_COMMIT_RECEIPT.journal.bytes);
+    }
+}
+
+mod compare_hals {
+    use std::rc::Rc;
+
+    use risc0_circuit_rv32im::cpu::CpuCircuitHal;
+    use risc0_core::field::{
+        baby_bear::{BabyBear, Elem, ExtElem},
+        Elem as _,
+    };
+    use risc0_zkp::{
+        core::{
+            digest::Digest,
+            hash::{sha::Sha256HashSuite, HashSuite},
+        },
+        hal::{
+            cpu::{CpuBuffer, CpuHal},
+            Hal,
+        },
+    };
+    use risc0_zkvm_methods::{multi_test::MultiTestSpec, MULTI_TEST_ELF};
+    use test_log::test;
+
+    use super::HalPair;
+    use crate::{
+        compare_hals, host::CIRCUIT, ExecutorEnv, ExecutorImpl, Segment, SimpleSegmentRef,
+    };
+
+    // A HAL that proves on the CPU, but corrupts the accum trace as it is
+    // copied to the device.
+    struct CorruptAccumHal(CpuHal<BabyBear>);
+
+    impl Hal for CorruptAccumHal {
+        type Field = BabyBear;
+        type Elem = Elem;
+        type ExtElem = ExtElem;
+        type Buffer<T: Clone + std::fmt::Debug + PartialEq + bytemuck::Pod> = CpuBuffer<T>;
+
+        fn has_unified_memory(&self) -> bool {
+            self.0.has_unified_memory()
+        }
+
+        fn get_hash_suite(&self) -> &HashSuite<BabyBear> {
+            self.0.get_hash_suite()
+        }
+
+        fn alloc_digest(&self, name: &'static str, size: usize) -> CpuBuffer<Digest> {
+            self.0.alloc_digest(name, size)
+        }
+
+        fn alloc_elem(&self, name: &'static str, size: usize) -> CpuBuffer<Elem> {
+            self.0.alloc_elem(name, size)
+        }
+
+        fn alloc_extelem(&self, name: &'static str, size: usize) -> CpuBuffer<ExtElem> {
+            self.0.alloc_extelem(name, size)
+        }
+
+        fn alloc_u32(&self, name: &'static str, size: usize) -> CpuBuffer<u32> {
+            self.0.alloc_u32(name, size)
+        }
+
+        fn copy_from_digest(&self, name: &'static str, slice: &[Digest]) -> CpuBuffer<Digest> {
+            self.0.copy_from_digest(name, slice)
+        }
+
+        fn copy_from_elem(&self, name: &'static str, slice: &[Elem]) -> CpuBuffer<Elem> {
+            let buf = self.0.copy_from_elem(name, slice);
+            if name == "accum" {
+                buf.as_slice_mut()[0] += Elem::ONE;
+            }
+            buf
+        }
+
+        fn copy_from_extelem(&self, name: &'static str, slice: &[ExtElem]) -> CpuBuffer<ExtElem> {
+            self.0.copy_from_extelem(name, slice)
+        }
+
+        fn copy_from_u32(&self, name: &'static str, slice: &[u32]) -> CpuBuffer<u32> {
+            self.0.copy_from_u32(name, slice)
+        }
+
+        fn batch_expand_into_evaluate_ntt(
+            &self,
+            output: &CpuBuffer<Elem>,
+            input: &CpuBuffer<Elem>,
+            count: usize,
+            expand_bits: usize,
+        ) {
+            self.0
+                .batch_expand_into_evaluate_ntt(output, input, count, expand_bits)
+        }
+
+        fn batch_interpolate_ntt(&self, io: &CpuBuffer<Elem>, count: usize) {
+            self.0.batch_interpolate_ntt(io, count)
+        }
+
+        fn batch_bit_reverse(&self, io: &CpuBuffer<Elem>, count: usize) {
+            self.0.batch_bit_reverse(io, count)
+        }
+
+        fn batch_evaluate_any(
+            &self,
+            coeffs: &CpuBuffer<Elem>,
+            poly_count: usize,
+            which: &CpuBuffer<u32>,
+            xs: &CpuBuffer<ExtElem>,
+            out: &CpuBuffer<ExtElem>,
+        ) {
+            self.0
+                .batch_evaluate_any(coeffs, poly_count, which, xs, out)
+        }
+
+        fn zk_shift(&self, io: &CpuBuffer<Elem>, count: usize) {
+            self.0.zk_shift(io, count)
+        }
+
+        fn mix_poly_coeffs(
+            &self,
+            out: &CpuBuffer<ExtElem>,
+            mix_start: &ExtElem,
+            mix: &ExtElem,
+            input: &CpuBuffer<Elem>,
+            combos: &CpuBuffer<u32>,
+            input_size: usize,
+            count: usize,
+        ) {
+            self.0
+                .mix_poly_coeffs(out, mix_start, mix, input, combos, input_size, count)
+        }
+
+        fn eltwise_add_elem(
+            &self,
+            output: &CpuBuffer<Elem>,
+            input1: &CpuBuffer<Elem>,
+            input2: &CpuBuffer<Elem>,
+        ) {
+            self.0.eltwise_add_elem(output, input1, input2)
+        }
+
+        fn eltwise_sum_extelem(&self, output: &CpuBuffer<Elem>, input: &CpuBuffer<ExtElem>) {
+            self.0.eltwise_sum_extelem(output, input)
+        }
+
+        fn eltwise_copy_elem(&self, output: &CpuBuffer<Elem>, input: &CpuBuffer<Elem>) {
+            self.0.eltwise_copy_elem(output, input)
+        }
+
+        fn fri_fold(&self, output: &CpuBuffer<Elem>, input: &CpuBuffer<Elem>, mix: &ExtElem) {
+            self.0.fri_fold(output, input, mix)
+        }
+
+        fn hash_rows(&self, output: &CpuBuffer<Digest>, matrix: &CpuBuffer<Elem>) {
+            self.0.hash_rows(output, matrix)
+        }
+
+        fn hash_fold(&self, io: &CpuBuffer<Digest>, input_size: usize, output_size: usize) {
+            self.0.hash_fold(io, input_size, output_size)
+        }
+
+        fn gather_sample(
+            &self,
+            dst: &CpuBuffer<Elem>,
+            src: &CpuBuffer<Elem>,
+            idx: usize,
+            size: usize,
+            stride: usize,
+        ) {
+            self.0.gather_sample(dst, src, idx, size, stride)
+        }
+    }
+
+    fn segment() -> Segment {
+        let env = ExecutorEnv::builder()
+            .write(&MultiTestSpec::DoNothing)
+            .unwrap()
+            .build()
+            .unwrap();
+        let mut segments = Vec::new();
+        ExecutorImpl::from_elf(env, MULTI_TEST_ELF)
+            .unwrap()
+            .run_with_callback(|segment| {
+                segments.push(segment.clone());
+                Ok(Box::new(SimpleSegmentRef::new(segment)))
+            })
+            .unwrap();
+        segments.remove(0)
+    }
+
+    fn cpu_hal() -> CpuHal<BabyBear> {
+        CpuHal::new(Sha256HashSuite::new_suite())
+    }
+
+    #[test]
+    fn cpu_matches_cpu() {
+        let pair = || HalPair {
+            hal: Rc::new(cpu_hal()),
+            circuit_hal: Rc::new(CpuCircuitHal::new(&CIRCUIT)),
+        };
+        let comparison = compare_hals(&segment(), &pair(), &pair()).unwrap();
+        assert!(comparison.matches(), "{comparison}");
+        let names: Vec<_> = comparison
+            .artifacts
+            .iter()
+            .map(|artifact| artifact.name.as_str())
+            .collect();
+        assert_eq!(names, ["code", "data", "accum", "check", "u", "seal"]);
+    }
+
+    #[test]
+    fn corrupt_accum() {
+        let cpu = HalPair {
+            hal: Rc::new(cpu_hal()),
+            circuit_hal: Rc::new(CpuCircuitHal::new(&CIRCUIT)),
+        };
+        let corrupt = HalPair {
+            hal: Rc::new(CorruptAccumHal(cpu_hal())),
+            circuit_hal: Rc::new(CpuCircuitHal::new(&CIRCUIT)),
+        };
+        let comparison = compare_hals(&segment(), &cpu, &corrupt).unwrap();
+        assert_eq!(comparison.first_difference().unwrap().name, "accum");
+        assert!(!comparison.artifacts.last().unwrap().matches());
+    }
 }
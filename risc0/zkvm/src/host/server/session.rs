@@ -27,10 +27,14 @@ use anyhow::{anyhow, ensure, Result};
 use serde::{Deserialize, Serialize};
 
 use crate::{
-    host::server::exec::executor::SyscallRecord,
+    host::{
+        api::{FailedCheck, HeapStats},
+        server::exec::executor::SyscallRecord,
+    },
     receipt_metadata::{Assumptions, Output},
     sha::Digest,
-    Assumption, ExitCode, Journal, MemoryImage, ReceiptMetadata, SystemState,
+    Assumption, ExecutorImpl, ExitCode, ImageDelta, Journal, MemoryImage, Receipt, ReceiptMetadata,
+    SystemState,
 };
 
 #[derive(Clone, Default, Serialize, Deserialize, Debug)]
@@ -65,6 +69,32 @@ pub struct Session {
     /// The list of assumptions made by the guest and resolved by the host.
     pub assumptions: Vec<Assumption>,
 
+    /// The tag passed by the guest to `env::yield_to_host`, if execution
+    /// stopped because the guest yielded. In that case the [ExitCode] is
+    /// [ExitCode::SystemSplit], and the executor can be run again to continue.
+    #[serde(default)]
+    pub yield_tag: Option<u32>,
+
+    /// How much of the heap the guest used.
+    #[serde(default)]
+    pub heap_stats: HeapStats,
+
+    /// The checks made by the guest with `env::check` that failed, in the
+    /// order they were reported.
+    #[serde(default)]
+    pub failed_checks: Vec<FailedCheck>,
+
+    /// The items committed by a guest that switched to a hashed journal with
+    /// `env::hashed_journal`, in order. The journal is the hash chain of
+    /// these items; see [hashed_journal](crate::hashed_journal).
+    #[serde(default)]
+    pub journal_preimages: Vec<Vec<u8>>,
+
+    /// The indices of the segments that a segment hook chose not to prove;
+    /// see [ProverOpts::with_segment_hook](crate::ProverOpts::with_segment_hook).
+    #[serde(default)]
+    pub skipped_segments: BTreeSet<u32>,
+
     /// The hooks to be called during the proving phase.
     #[serde(skip)]
     pub hooks: Vec<Box<dyn SessionEvents>>,
@@ -118,6 +148,33 @@ pub trait SessionEvents {
     /// Fired after the proving of a segment ends.
     #[allow(unused)]
     fn on_post_prove_segment(&self, segment: &Segment) {}
+
+    /// Fired when the proving of a segment failed with a device fault and is
+    /// about to be retried, with the number of the retry, starting from 1;
+    /// see [ProverOpts::with_segment_retries](crate::ProverOpts::with_segment_retries).
+    #[allow(unused)]
+    fn on_segment_retry(&self, segment: &Segment, retry: u32) {}
+
+    /// Fired after the receipt for the whole session has been checked against
+    /// it, which only happens for the receipts selected by the
+    /// [VerifyPolicy](crate::VerifyPolicy).
+    #[allow(unused)]
+    fn on_post_verify_session(&self, receipt: &Receipt) {}
+}
+
+/// The decision returned by a segment hook installed with
+/// [ProverOpts::with_segment_hook](crate::ProverOpts::with_segment_hook).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SegmentAction {
+    /// Prove the [Segment] and include its receipt.
+    Prove,
+
+    /// Do not prove the [Segment]. No receipt is produced for it, so the
+    /// resulting [Receipt](crate::Receipt) will not verify on its own.
+    Skip,
+
+    /// Stop proving the [Session] and return an error.
+    Abort,
 }
 
 impl Session {
@@ -135,6 +192,11 @@ impl Session {
             exit_code,
             post_image,
             assumptions,
+            yield_tag: None,
+            heap_stats: HeapStats::default(),
+            failed_checks: Vec::new(),
+            journal_preimages: Vec::new(),
+            skipped_segments: BTreeSet::new(),
             hooks: Vec::new(),
         }
     }
@@ -273,6 +335,110 @@ impl Segment {
             cycles,
         }
     }
+
+    /// The ImageID of the memory image at the start of this [Segment].
+    ///
+    /// When the preceding [Segment] ended with [ExitCode::SystemSplit], this
+    /// matches its [post_image_id](Segment::post_image_id).
+    pub fn pre_image_id(&self) -> Digest {
+        self.pre_image.compute_id()
+    }
+
+    /// The ImageID of the memory image at the end of this [Segment].
+    pub fn post_image_id(&self) -> Digest {
+        self.post_image_id
+    }
+
+    /// The [ExitCode] that terminated this [Segment].
+    pub fn exit_code(&self) -> ExitCode {
+        self.exit_code
+    }
+
+    /// Serialize this [Segment] into `writer`, in the same format used by
+    /// [FileSegmentRef].
+    pub fn write_to<W: Write>(&self, writer: W) -> Result<()> {
+        Ok(bincode::serialize_into(writer, self)?)
+    }
+
+    /// Serialize this [Segment] into `writer`, storing only the pages of its
+    /// pre-image that differ from the pre-image of `prev`.
+    ///
+    /// Consecutive segments usually touch a handful of pages, so this is far
+    /// smaller than [Segment::write_to]. Use [Segment::read_delta_from] with
+    /// the same `prev` to read it back.
+    pub fn write_delta_to<W: Write>(&self, prev: &Segment, writer: W) -> Result<()> {
+        let delta = SegmentDelta {
+            pre_image: prev.pre_image.diff(&self.pre_image),
+            post_image_id: self.post_image_id,
+            faults: self.faults.clone(),
+            syscalls: self.syscalls.clone(),
+            split_insn: self.split_insn,
+            exit_code: self.exit_code,
+            po2: self.po2,
+            index: self.index,
+            cycles: self.cycles,
+        };
+        Ok(bincode::serialize_into(writer, &delta)?)
+    }
+
+    /// Deserialize a [Segment] written by [Segment::write_delta_to].
+    ///
+    /// Returns an error if `prev` is not the [Segment] the delta was computed
+    /// against.
+    pub fn read_delta_from<R: Read>(prev: &Segment, reader: R) -> Result<Self> {
+        let delta: SegmentDelta = bincode::deserialize_from(reader)?;
+        let mut pre_image = prev.pre_image.clone();
+        pre_image.apply_delta(&delta.pre_image)?;
+        Ok(Self {
+            pre_image,
+            post_image_id: delta.post_image_id,
+            faults: delta.faults,
+            syscalls: delta.syscalls,
+            split_insn: delta.split_insn,
+            exit_code: delta.exit_code,
+            po2: delta.po2,
+            index: delta.index,
+            cycles: delta.cycles,
+        })
+    }
+
+    /// Split this [Segment] into segments of at most `2^po2` cycles.
+    ///
+    /// The work is run again from the pre-image of this [Segment], but the
+    /// guest is not re-executed from the start and the host is not consulted:
+    /// syscalls are answered from the records kept in this [Segment]. The
+    /// returned segments are indexed from zero and end with the same
+    /// [ExitCode] and post-image as this one.
+    pub fn resegment(&self, po2: u32) -> Result<Vec<Segment>> {
+        let mut segments = Vec::new();
+        ExecutorImpl::replay(self, po2)?.run_with_callback(|segment| {
+            segments.push(segment.clone());
+            Ok(Box::new(SimpleSegmentRef::new(segment)))
+        })?;
+        let last = segments
+            .last()
+            .ok_or_else(|| anyhow!("Resegmenting produced no segments"))?;
+        ensure!(
+            last.exit_code == self.exit_code && last.post_image_id == self.post_image_id,
+            "Resegmenting segment {} did not reproduce its post-image",
+            self.index
+        );
+        Ok(segments)
+    }
+}
+
+// The serialized form of a [Segment] written by [Segment::write_delta_to].
+#[derive(Serialize, Deserialize)]
+struct SegmentDelta {
+    pre_image: ImageDelta,
+    post_image_id: Digest,
+    faults: PageFaults,
+    syscalls: Vec<SyscallRecord>,
+    split_insn: Option<u32>,
+    exit_code: ExitCode,
+    po2: u32,
+    index: u32,
+    cycles: u32,
 }
 
 /// A very basic implementation of a [SegmentRef].
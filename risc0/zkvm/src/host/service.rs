@@ -0,0 +1,421 @@
+// Copyright 2023 RISC Zero, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! A queue of proving jobs served by a pool of threads, for applications that
+//! prove on behalf of their users.
+
+use std::{
+    any::Any,
+    cell::RefCell,
+    collections::{HashMap, VecDeque},
+    fmt,
+    panic::{self, AssertUnwindSafe},
+    rc::Rc,
+    sync::{
+        atomic::{AtomicU32, Ordering},
+        Arc, Condvar, Mutex, MutexGuard,
+    },
+    thread::{self, JoinHandle},
+};
+
+use anyhow::{bail, ensure, Result};
+use risc0_binfmt::MemoryImage;
+
+#[allow(deprecated)]
+use crate::ProverOpts;
+use crate::{CancelToken, ExecutorEnv, LocalProver, Prover, Receipt, Segment, VerifierContext};
+
+/// Identifies a job submitted to a [ProvingService].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct JobId(u64);
+
+impl fmt::Display for JobId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "job {}", self.0)
+    }
+}
+
+/// The guest proven by a job submitted to a [ProvingService].
+#[derive(Clone)]
+pub enum JobProgram {
+    /// A guest ELF, loaded with the default layout.
+    Elf(Vec<u8>),
+
+    /// A guest already loaded into memory.
+    Image(MemoryImage),
+}
+
+impl From<Vec<u8>> for JobProgram {
+    fn from(elf: Vec<u8>) -> Self {
+        Self::Elf(elf)
+    }
+}
+
+impl From<&[u8]> for JobProgram {
+    fn from(elf: &[u8]) -> Self {
+        Self::Elf(elf.to_vec())
+    }
+}
+
+impl From<MemoryImage> for JobProgram {
+    fn from(image: MemoryImage) -> Self {
+        Self::Image(image)
+    }
+}
+
+/// The state of a job submitted to a [ProvingService].
+#[derive(Clone, Debug)]
+pub enum JobStatus {
+    /// Waiting for a worker.
+    Queued,
+
+    /// Being executed; `cycles` is the number of user cycles in the segments
+    /// executed so far.
+    Executing {
+        /// The user cycles executed so far.
+        cycles: u64,
+    },
+
+    /// Being proven, one segment at a time.
+    Proving {
+        /// The index of the segment being proven.
+        segment: u32,
+        /// The number of segments in the execution.
+        total: u32,
+    },
+
+    /// Proven.
+    Done(Receipt),
+
+    /// Execution or proving failed, with the error. A prover that panics
+    /// fails the job with the panic message.
+    Failed(String),
+
+    /// Cancelled with [ProvingService::cancel] before it finished.
+    Cancelled,
+}
+
+impl JobStatus {
+    /// Whether the job has finished, successfully or not.
+    pub fn is_finished(&self) -> bool {
+        matches!(self, Self::Done(_) | Self::Failed(_) | Self::Cancelled)
+    }
+}
+
+/// Proves jobs in the order they are submitted on a pool of worker threads.
+///
+/// Each job is executed and proven by a [LocalProver] on one of the workers,
+/// and its progress can be polled with [ProvingService::status]. Finished jobs
+/// are kept until removed with [ProvingService::remove]. Dropping the service
+/// cancels the jobs being proven and abandons the queued ones.
+///
+/// # Example
+///
+/// ```no_run
+/// use risc0_zkvm::{ProverOpts, ProvingService};
+/// # let elf: &[u8] = &[];
+///
+/// let service = ProvingService::new(2);
+/// let job = service.submit(elf, vec![1, 2, 3], ProverOpts::default()).unwrap();
+/// println!("{:?}", service.status(job));
+/// let receipt = service.wait(job).unwrap();
+/// ```
+pub struct ProvingService {
+    shared: Arc<Shared>,
+    workers: Vec<JoinHandle<()>>,
+}
+
+impl ProvingService {
+    /// Start a service that proves up to `workers` jobs at a time, each on a
+    /// thread of its own.
+    pub fn new(workers: usize) -> Self {
+        Self::with_prover(workers, || Box::new(LocalProver::new("service")))
+    }
+
+    // Start a service whose workers prove with the provers made by
+    // `make_prover`, one for each job.
+    pub(crate) fn with_prover(workers: usize, make_prover: fn() -> Box<dyn Prover>) -> Self {
+        let shared = Arc::new(Shared::default());
+        let workers = (0..workers.max(1))
+            .map(|_| {
+                let shared = shared.clone();
+                thread::spawn(move || work(shared, make_prover))
+            })
+            .collect();
+        Self { shared, workers }
+    }
+
+    /// Queue a job to prove `program` on `input`, which the guest reads from
+    /// stdin as written by
+    /// [ExecutorEnvBuilder::write_bytes](crate::ExecutorEnvBuilder::write_bytes).
+    ///
+    /// The options that only exist in this process, such as
+    /// [ProverOpts::with_segment_hook] or [ProverOpts::with_cancel_token],
+    /// cannot be carried to a worker and are rejected; cancel a job with
+    /// [ProvingService::cancel] instead.
+    #[allow(deprecated)]
+    pub fn submit(
+        &self,
+        program: impl Into<JobProgram>,
+        input: Vec<u8>,
+        opts: ProverOpts,
+    ) -> Result<JobId> {
+        ensure!(
+            opts.proof_cache.is_none()
+                && opts.image_hasher.is_none()
+                && opts.env_filter.is_none()
+                && opts.segment_hook.is_none()
+                && opts.cancel_token.is_none(),
+            "a proof cache, image hasher, env filter, segment hook or cancel token cannot be used by a ProvingService"
+        );
+        #[cfg(feature = "signals")]
+        ensure!(
+            !opts.ctrlc_handling,
+            "Ctrl-C handling cannot be used by a ProvingService"
+        );
+        // The remaining options are carried to the worker serialized, so that
+        // each job proves with its own copy.
+        let job = Work {
+            program: program.into(),
+            input,
+            opts: bincode::serialize(&opts)?,
+        };
+
+        let mut state = self.shared.lock();
+        let id = JobId(state.next_id);
+        state.next_id += 1;
+        state.jobs.insert(
+            id,
+            Job {
+                status: JobStatus::Queued,
+                token: CancelToken::new(),
+            },
+        );
+        state.queue.push_back((id, job));
+        drop(state);
+        self.shared.changed.notify_all();
+        Ok(id)
+    }
+
+    /// The state of the job, or `None` if there is no such job.
+    pub fn status(&self, id: JobId) -> Option<JobStatus> {
+        self.shared
+            .lock()
+            .jobs
+            .get(&id)
+            .map(|job| job.status.clone())
+    }
+
+    /// Cancel the job. A queued job is cancelled at once; a running job stops
+    /// at the end of the segment being executed or before the next segment
+    /// is proven.
+    ///
+    /// Returns whether the job was queued or running.
+    pub fn cancel(&self, id: JobId) -> bool {
+        let mut state = self.shared.lock();
+        let State { queue, jobs, .. } = &mut *state;
+        let Some(job) = jobs.get_mut(&id) else {
+            return false;
+        };
+        match job.status {
+            JobStatus::Queued => {
+                queue.retain(|(queued, _)| *queued != id);
+                job.status = JobStatus::Cancelled;
+                drop(state);
+                self.shared.changed.notify_all();
+                true
+            }
+            JobStatus::Executing { .. } | JobStatus::Proving { .. } => {
+                job.token.cancel();
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Block until the job finishes, and return its receipt.
+    pub fn wait(&self, id: JobId) -> Result<Receipt> {
+        let mut state = self.shared.lock();
+        loop {
+            match state.jobs.get(&id).map(|job| &job.status) {
+                None => bail!("unknown {id}"),
+                Some(JobStatus::Done(receipt)) => return Ok(receipt.clone()),
+                Some(JobStatus::Failed(err)) => bail!("{id} failed: {err}"),
+                Some(JobStatus::Cancelled) => bail!("{id} was cancelled"),
+                Some(_) => state = self.shared.changed.wait(state).unwrap(),
+            }
+        }
+    }
+
+    /// Forget a finished job, returning its final state. Returns `None`, and
+    /// keeps the job, if it has not finished.
+    pub fn remove(&self, id: JobId) -> Option<JobStatus> {
+        let mut state = self.shared.lock();
+        if !state.jobs.get(&id)?.status.is_finished() {
+            return None;
+        }
+        state.jobs.remove(&id).map(|job| job.status)
+    }
+
+    /// The receipt of a finished job encoded with
+    /// [Receipt::to_cbor](crate::Receipt::to_cbor), e.g. to store it; `None`
+    /// if the job has not been proven.
+    #[cfg(feature = "cbor")]
+    pub fn receipt_cbor(&self, id: JobId) -> Result<Option<Vec<u8>>> {
+        match self.shared.lock().jobs.get(&id).map(|job| &job.status) {
+            Some(JobStatus::Done(receipt)) => Ok(Some(receipt.to_cbor()?)),
+            _ => Ok(None),
+        }
+    }
+}
+
+impl Drop for ProvingService {
+    fn drop(&mut self) {
+        let mut state = self.shared.lock();
+        state.shutdown = true;
+        for job in state.jobs.values() {
+            job.token.cancel();
+        }
+        drop(state);
+        self.shared.changed.notify_all();
+        for worker in self.workers.drain(..) {
+            let _ = worker.join();
+        }
+    }
+}
+
+#[derive(Default)]
+struct Shared {
+    state: Mutex<State>,
+    // Notified whenever a job is queued or changes state.
+    changed: Condvar,
+}
+
+#[derive(Default)]
+struct State {
+    next_id: u64,
+    queue: VecDeque<(JobId, Work)>,
+    jobs: HashMap<JobId, Job>,
+    shutdown: bool,
+}
+
+struct Job {
+    status: JobStatus,
+    token: CancelToken,
+}
+
+struct Work {
+    program: JobProgram,
+    input: Vec<u8>,
+    opts: Vec<u8>,
+}
+
+impl Shared {
+    fn lock(&self) -> MutexGuard<'_, State> {
+        self.state.lock().unwrap()
+    }
+
+    fn set_status(&self, id: JobId, status: JobStatus) {
+        if let Some(job) = self.lock().jobs.get_mut(&id) {
+            job.status = status;
+        }
+        self.changed.notify_all();
+    }
+}
+
+// Run queued jobs, one at a time, until the service shuts down.
+fn work(shared: Arc<Shared>, make_prover: fn() -> Box<dyn Prover>) {
+    loop {
+        let mut state = shared.lock();
+        let (id, job, token) = loop {
+            if state.shutdown {
+                return;
+            }
+            if let Some((id, job)) = state.queue.pop_front() {
+                let entry = state.jobs.get_mut(&id).unwrap();
+                entry.status = JobStatus::Executing { cycles: 0 };
+                break (id, job, entry.token.clone());
+            }
+            state = shared.changed.wait(state).unwrap();
+        };
+        drop(state);
+        shared.changed.notify_all();
+
+        // A panicking prover fails its job, and the worker goes on to the
+        // next one.
+        let result = panic::catch_unwind(AssertUnwindSafe(|| {
+            prove(&*make_prover(), &shared, id, job, &token)
+        }));
+        let status = match result {
+            Ok(Ok(receipt)) => JobStatus::Done(receipt),
+            Ok(Err(_)) if token.is_cancelled() => JobStatus::Cancelled,
+            Ok(Err(err)) => JobStatus::Failed(format!("{err:#}")),
+            Err(payload) => JobStatus::Failed(format!("panicked: {}", panic_message(&*payload))),
+        };
+        shared.set_status(id, status);
+    }
+}
+
+fn panic_message(payload: &(dyn Any + Send)) -> &str {
+    if let Some(msg) = payload.downcast_ref::<&str>() {
+        msg
+    } else if let Some(msg) = payload.downcast_ref::<String>() {
+        msg
+    } else {
+        "unknown panic"
+    }
+}
+
+#[allow(deprecated)]
+fn prove(
+    prover: &dyn Prover,
+    shared: &Arc<Shared>,
+    id: JobId,
+    job: Work,
+    token: &CancelToken,
+) -> Result<Receipt> {
+    let segments = Arc::new(AtomicU32::new(0));
+    let mut env = ExecutorEnv::builder().write_bytes(&job.input).build()?;
+    env.segment_callbacks.push(Rc::new(RefCell::new({
+        let (shared, token, segments) = (shared.clone(), token.clone(), segments.clone());
+        let mut cycles = 0;
+        move |segment: &Segment| {
+            ensure!(!token.is_cancelled(), "execution was cancelled");
+            cycles += segment.cycles as u64;
+            segments.store(segment.index + 1, Ordering::Relaxed);
+            shared.set_status(id, JobStatus::Executing { cycles });
+            Ok(())
+        }
+    })));
+
+    let opts: ProverOpts = bincode::deserialize(&job.opts)?;
+    let mut opts = opts.with_cancel_token(token.clone());
+    let observer_shared = shared.clone();
+    opts.prove_observer = Some(Arc::new(move |segment: &Segment| {
+        let total = segments.load(Ordering::Relaxed);
+        observer_shared.set_status(
+            id,
+            JobStatus::Proving {
+                segment: segment.index,
+                total,
+            },
+        );
+    }));
+
+    let ctx = VerifierContext::default();
+    match job.program {
+        JobProgram::Elf(elf) => prover.prove_elf_with_ctx(env, &ctx, &elf, &opts),
+        JobProgram::Image(image) => prover.prove(env, &ctx, &opts, image),
+    }
+}
@@ -0,0 +1,226 @@
+// Copyright 2023 RISC Zero, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Reuse of the results of verifying receipts that have been seen before.
+
+use std::{
+    cell::{Cell, RefCell},
+    collections::{BTreeMap, HashMap},
+    rc::Rc,
+};
+
+use anyhow::Result;
+use risc0_zkp::verify::VerificationError;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    serde::to_vec,
+    sha::{Digest, Digestible},
+    InnerReceipt, ReceiptMetadata, VerifierContext, VERSION,
+};
+
+/// Identifies the verification of a receipt in a [VerifyCache].
+///
+/// The key covers everything the verification of the seals depends on, so
+/// that a receipt can only hit an entry stored for a receipt with the same
+/// seals and claims, verified under the same hash suites.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct VerifyCacheKey {
+    /// A digest of the seals and claims of the receipt, including those of
+    /// its assumptions.
+    pub seal_digest: Digest,
+
+    /// The image ID the receipt was verified against.
+    pub image_id: Digest,
+
+    /// The hash function the receipt was proven with; see
+    /// [InnerReceipt::hashfn].
+    pub hashfn: Option<String>,
+
+    /// The names of the hash suites of the [VerifierContext], in order.
+    pub suites: Vec<String>,
+
+    /// The version of the zkVM, which determines the control IDs accepted.
+    pub version: String,
+}
+
+impl VerifyCacheKey {
+    fn new(ctx: &VerifierContext, inner: &InnerReceipt, image_id: Digest) -> Result<Self> {
+        let words = to_vec(inner)?;
+        Ok(Self {
+            seal_digest: bytemuck::cast_slice::<u32, u8>(&words).digest(),
+            image_id,
+            hashfn: inner.hashfn().map(str::to_string),
+            suites: ctx.suites.keys().cloned().collect(),
+            version: VERSION.to_string(),
+        })
+    }
+
+    /// A digest of all the fields of the key, e.g. to use as a key in an
+    /// external store.
+    pub fn digest(&self) -> Digest {
+        let words = to_vec(self).unwrap();
+        bytemuck::cast_slice::<u32, u8>(&words).digest()
+    }
+}
+
+/// The result of verifying the seals of a receipt, stored in a [VerifyCache].
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct CachedVerification {
+    /// The claim proven by the seals, or `None` if they failed to verify.
+    pub claim: Option<ReceiptMetadata>,
+}
+
+/// A store of the results of verifying receipts; see
+/// [VerifierContext::with_cache].
+///
+/// Entries are only ever written with the result of verifying a receipt, but
+/// a store shared with other processes must be trusted not to hold entries
+/// written by anyone else.
+pub trait VerifyCache {
+    /// Look up the result stored under `key`, if any.
+    fn get(&self, key: &VerifyCacheKey) -> Result<Option<CachedVerification>>;
+
+    /// Store `result` under `key`, replacing any previous result.
+    fn put(&self, key: &VerifyCacheKey, result: &CachedVerification) -> Result<()>;
+}
+
+impl<T: VerifyCache + ?Sized> VerifyCache for Rc<T> {
+    fn get(&self, key: &VerifyCacheKey) -> Result<Option<CachedVerification>> {
+        (**self).get(key)
+    }
+
+    fn put(&self, key: &VerifyCacheKey, result: &CachedVerification) -> Result<()> {
+        (**self).put(key, result)
+    }
+}
+
+/// A [VerifyCache] in memory that holds up to a fixed number of results,
+/// evicting the least recently used.
+pub struct LruVerifyCache {
+    capacity: usize,
+    lru: RefCell<Lru>,
+    hits: Cell<u64>,
+}
+
+#[derive(Default)]
+struct Lru {
+    // The results by key digest, with the tick at which each was last used.
+    entries: HashMap<Digest, (CachedVerification, u64)>,
+    // The key digests by the tick at which they were last used.
+    order: BTreeMap<u64, Digest>,
+    tick: u64,
+}
+
+impl Lru {
+    fn touch(&mut self, digest: Digest) -> Option<&CachedVerification> {
+        let (result, used) = self.entries.get_mut(&digest)?;
+        self.order.remove(used);
+        self.tick += 1;
+        *used = self.tick;
+        self.order.insert(self.tick, digest);
+        Some(result)
+    }
+}
+
+impl LruVerifyCache {
+    /// Construct an empty [LruVerifyCache] that holds up to `capacity`
+    /// results.
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity: capacity.max(1),
+            lru: RefCell::new(Lru::default()),
+            hits: Cell::new(0),
+        }
+    }
+
+    /// The number of results held.
+    pub fn len(&self) -> usize {
+        self.lru.borrow().entries.len()
+    }
+
+    /// Whether no results are held.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// The number of lookups that found a stored result.
+    pub fn hits(&self) -> u64 {
+        self.hits.get()
+    }
+}
+
+impl VerifyCache for LruVerifyCache {
+    fn get(&self, key: &VerifyCacheKey) -> Result<Option<CachedVerification>> {
+        let result = self.lru.borrow_mut().touch(key.digest()).cloned();
+        if result.is_some() {
+            self.hits.set(self.hits.get() + 1);
+        }
+        Ok(result)
+    }
+
+    fn put(&self, key: &VerifyCacheKey, result: &CachedVerification) -> Result<()> {
+        let digest = key.digest();
+        let mut lru = self.lru.borrow_mut();
+        if lru.touch(digest).is_none() {
+            if lru.entries.len() >= self.capacity {
+                if let Some((_, oldest)) = lru.order.pop_first() {
+                    lru.entries.remove(&oldest);
+                }
+            }
+            lru.tick += 1;
+            let tick = lru.tick;
+            lru.order.insert(tick, digest);
+            lru.entries.insert(digest, (result.clone(), tick));
+        } else {
+            lru.entries.get_mut(&digest).unwrap().0 = result.clone();
+        }
+        Ok(())
+    }
+}
+
+/// Verify the seals of `inner` and return the claim they prove, using the
+/// result stored in `cache` for a receipt seen before.
+///
+/// Errors from the cache are treated as misses. Fake receipts are not cached,
+/// since whether they pass depends on the [SecurityPolicy](crate::SecurityPolicy)
+/// rather than on the receipt.
+pub(crate) fn verify_cached(
+    cache: &dyn VerifyCache,
+    ctx: &VerifierContext,
+    inner: &InnerReceipt,
+    image_id: Digest,
+) -> Result<ReceiptMetadata, VerificationError> {
+    let verify = || {
+        inner.verify_integrity_with_context(ctx)?;
+        inner.get_metadata()
+    };
+    if matches!(inner, InnerReceipt::Fake { .. }) {
+        return verify();
+    }
+
+    let Ok(key) = VerifyCacheKey::new(ctx, inner, image_id) else {
+        return verify();
+    };
+    if let Ok(Some(cached)) = cache.get(&key) {
+        return cached.claim.ok_or(VerificationError::InvalidProof);
+    }
+
+    let result = verify();
+    let cached = CachedVerification {
+        claim: result.as_ref().ok().cloned(),
+    };
+    let _ = cache.put(&key, &cached);
+    result
+}
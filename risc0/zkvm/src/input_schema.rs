@@ -0,0 +1,183 @@
+// Copyright 2023 RISC Zero, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Checking that the host and the guest agree on the type of the input.
+//!
+//! A mismatch between the type the host serializes and the type the guest
+//! deserializes usually surfaces as a panic deep inside the guest's
+//! deserializer. To catch it before execution starts, describe the input type
+//! with [InputSchema], read it in the guest with [read_checked!](crate::read_checked),
+//! and write it on the host with
+//! [ExecutorEnvBuilder::write_checked](crate::ExecutorEnvBuilder::write_checked).
+//!
+//! The same descriptions cover the journal. A guest that commits its output
+//! with [commit_checked!](crate::commit_checked) declares the type in its ELF,
+//! where a verifier finds it with `Program::journal_schema`. Called as
+//! `commit_checked!(T, &value, prefixed)`, the guest also commits the digest
+//! of the description ahead of the value, and `Receipt::journal_decode_checked`
+//! refuses to decode the journal as any other type.
+
+use core::fmt;
+
+use crate::sha::Digest;
+
+/// A description of the serialized form of a type, shared by the host and the
+/// guest.
+///
+/// # Example
+///
+/// ```
+/// use risc0_zkvm::InputSchema;
+/// use serde::{Deserialize, Serialize};
+///
+/// #[derive(Serialize, Deserialize)]
+/// struct Point {
+///     x: u32,
+///     y: u32,
+/// }
+///
+/// impl InputSchema for Point {
+///     const SCHEMA: &'static str = "Point { x: u32, y: u32 }";
+/// }
+/// ```
+pub trait InputSchema {
+    /// The description of the type. The host and the guest consider two types
+    /// compatible exactly when their descriptions are equal, so it should
+    /// change whenever the serialized form does.
+    ///
+    /// Must not contain NUL bytes.
+    const SCHEMA: &'static str;
+}
+
+/// Returns the contents of an entry in the input schema section: the schema
+/// followed by a NUL byte.
+#[doc(hidden)]
+pub const fn input_schema_entry<const N: usize>(schema: &str) -> [u8; N] {
+    let bytes = schema.as_bytes();
+    let mut entry = [0; N];
+    let mut i = 0;
+    while i < bytes.len() {
+        entry[i] = bytes[i];
+        i += 1;
+    }
+    entry
+}
+
+/// Returns the contents of an entry in the journal schema section: the type
+/// name and the schema, each followed by a NUL byte.
+#[doc(hidden)]
+pub const fn journal_schema_entry<const N: usize>(type_name: &str, schema: &str) -> [u8; N] {
+    let name = type_name.as_bytes();
+    let bytes = schema.as_bytes();
+    let mut entry = [0; N];
+    let mut i = 0;
+    while i < name.len() {
+        entry[i] = name[i];
+        i += 1;
+    }
+    let mut j = 0;
+    while j < bytes.len() {
+        entry[name.len() + 1 + j] = bytes[j];
+        j += 1;
+    }
+    entry
+}
+
+/// Error returned when a journal was committed with a different
+/// [InputSchema] than the type it is decoded as.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SchemaMismatch {
+    /// The digest of the schema of the type the journal is decoded as.
+    pub expected: Digest,
+
+    /// The digest of the schema the guest committed.
+    pub journal: Digest,
+}
+
+impl fmt::Display for SchemaMismatch {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "Journal schema mismatch: expected {} but the journal holds {}",
+            self.expected, self.journal
+        )
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for SchemaMismatch {}
+
+/// Read the input from the host, like
+/// [env::read](crate::guest::env::read), and declare its [InputSchema] in the
+/// guest ELF so the host can check it before execution starts.
+///
+/// # Example
+///
+/// ```ignore
+/// let point = risc0_zkvm::read_checked!(Point);
+/// ```
+#[macro_export]
+macro_rules! read_checked {
+    ($ty:ty) => {{
+        // The section name must match risc0_binfmt::INPUT_SCHEMA_SECTION.
+        #[link_section = ".risc0.input_schema"]
+        static INPUT_SCHEMA: [u8; <$ty as $crate::InputSchema>::SCHEMA.len() + 1] =
+            $crate::input_schema::input_schema_entry(<$ty as $crate::InputSchema>::SCHEMA);
+        // Reference the entry so the linker keeps it.
+        let _ = core::hint::black_box(&INPUT_SCHEMA);
+        $crate::guest::env::read::<$ty>()
+    }};
+}
+
+/// Commit a value to the journal, like [env::commit](crate::guest::env::commit),
+/// and declare its type and [InputSchema] in the guest ELF so a verifier knows
+/// how to decode the journal.
+///
+/// With a trailing `prefixed`, the digest of the schema is committed first,
+/// so that `Receipt::journal_decode_checked` can check it. This changes the
+/// journal, and so the claim, of the guest; call it before committing
+/// anything else.
+///
+/// # Example
+///
+/// ```ignore
+/// // The journal holds `point` alone.
+/// risc0_zkvm::commit_checked!(Point, &point);
+///
+/// // The journal holds the digest of the schema of `Point`, then `point`.
+/// risc0_zkvm::commit_checked!(Point, &point, prefixed);
+/// ```
+#[macro_export]
+macro_rules! commit_checked {
+    (@commit $ty:ty, $value:expr, $prefixed:expr) => {{
+        const TYPE_NAME: &str = stringify!($ty);
+        // The section name must match risc0_binfmt::JOURNAL_SCHEMA_SECTION.
+        #[link_section = ".risc0.journal_schema"]
+        static JOURNAL_SCHEMA: [u8; TYPE_NAME.len()
+            + <$ty as $crate::InputSchema>::SCHEMA.len()
+            + 2] = $crate::input_schema::journal_schema_entry(
+            TYPE_NAME,
+            <$ty as $crate::InputSchema>::SCHEMA,
+        );
+        // Reference the entry so the linker keeps it.
+        let _ = core::hint::black_box(&JOURNAL_SCHEMA);
+        $crate::guest::env::commit_checked::<$ty>($value, $prefixed)
+    }};
+    ($ty:ty, $value:expr) => {
+        $crate::commit_checked!(@commit $ty, $value, false)
+    };
+    ($ty:ty, $value:expr, prefixed) => {
+        $crate::commit_checked!(@commit $ty, $value, true)
+    };
+}
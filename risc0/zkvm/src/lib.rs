@@ -28,15 +28,34 @@ mod fault_monitor;
 pub use self::fault_monitor::FaultCheckMonitor;
 
 pub mod guest;
+pub mod hashed_journal;
 #[cfg(not(target_os = "zkvm"))]
 mod host;
+pub mod input_schema;
 pub mod serde;
 pub mod sha;
+pub mod sha_accel;
 
 pub mod receipt_metadata;
+pub use input_schema::{InputSchema, SchemaMismatch};
 pub use receipt_metadata::{ExitCode, Output, ReceiptMetadata};
 use semver::Version;
 
+/// Harness for benchmarking execution, proving and verification.
+#[cfg(all(not(target_os = "zkvm"), feature = "bench"))]
+pub mod bench {
+    pub use super::host::bench::*;
+}
+
+/// Control over the memory the prover keeps between proofs.
+#[cfg(all(not(target_os = "zkvm"), feature = "prove"))]
+pub mod prove {
+    pub use risc0_zkp::hal::pool::{
+        buffer_pool_stats, set_buffer_pool_limit, thread_buffer_pool_stats, trim_memory,
+        BufferPoolStats,
+    };
+}
+
 /// Re-exports for recursion
 #[cfg(all(not(target_os = "zkvm"), feature = "prove"))]
 pub mod recursion {
@@ -49,43 +68,118 @@ pub use anyhow::Result;
 pub use bytes::Bytes;
 
 #[cfg(not(target_os = "zkvm"))]
-pub use risc0_binfmt::MemoryImage;
+pub use risc0_binfmt::{
+    default_image_hasher, AcceleratedImageHasher, FlaggedSymbol, GuestAnalysis, ImageDelta,
+    ImageError, ImageHasher, JournalSchema, MemoryImage, PageInfo, PageKind, PlatformCheck,
+    ScalarImageHasher, SymbolKind,
+};
 pub use risc0_binfmt::{Program, SystemState};
+#[cfg(all(not(target_os = "zkvm"), feature = "debug-verify"))]
+pub use risc0_zkp::verify::{TranscriptStep, VerifyPhase};
 pub use risc0_zkvm_platform::{declare_syscall, memory::GUEST_MAX_MEM, PAGE_SIZE};
 
+#[cfg(all(not(target_os = "zkvm"), feature = "std"))]
+pub use self::host::batch::{verify_batch, BatchMode, BatchResult};
+#[cfg(all(not(target_os = "zkvm"), feature = "client"))]
+pub use self::host::bundle::{BundleError, VerificationBundle, VERIFICATION_BUNDLE_VERSION};
+#[cfg(all(not(target_os = "zkvm"), feature = "cbor"))]
+pub use self::host::cbor::RECEIPT_CBOR_VERSION;
+#[cfg(all(not(target_os = "zkvm"), feature = "dev"))]
+pub use self::host::client::dev::{DevReport, DevRun, DevSession, JournalDiff};
+#[cfg(all(not(target_os = "zkvm"), feature = "client"))]
+#[allow(deprecated)]
+pub use self::host::client::prove::ProverOpts;
+#[cfg(all(not(target_os = "zkvm"), feature = "client"))]
+pub use self::host::compat::{
+    check_receipt_compatibility, check_receipt_compatibility_with_context, CompatOutcome,
+    CompatReport, ReceiptFormat,
+};
+#[cfg(all(not(target_os = "zkvm"), feature = "debug-verify"))]
+pub use self::host::debug_verify::{SealTrace, VerifyTrace};
 #[cfg(all(not(target_os = "zkvm"), feature = "profiler"))]
 pub use self::host::profiler::Profiler;
+#[cfg(all(not(target_os = "zkvm"), feature = "circuit-debug"))]
+pub use self::host::server::prove::plonk::PlonkRowError;
+#[cfg(all(not(target_os = "zkvm"), feature = "service"))]
+pub use self::host::service::{JobId, JobProgram, JobStatus, ProvingService};
+#[cfg(all(not(target_os = "zkvm"), feature = "std"))]
+pub use self::host::verify_cache::{
+    CachedVerification, LruVerifyCache, VerifyCache, VerifyCacheKey,
+};
 #[cfg(all(not(target_os = "zkvm"), feature = "prove"))]
 pub use self::host::{
     api::server::Server as ApiServer,
-    client::prove::local::LocalProver,
+    client::prove::{
+        cancel::{CancelToken, ProvingCancelled},
+        local::LocalProver,
+    },
     server::{
-        exec::executor::ExecutorImpl,
-        prove::{get_prover_server, loader::Loader, HalPair, ProverServer},
-        session::{FileSegmentRef, Segment, SegmentRef, Session, SessionEvents, SimpleSegmentRef},
+        exec::{
+            checkpoint::ExecutorCheckpoint,
+            compare::{compare_guests, CompareOpts, GuestDiff},
+            determinism::{DeterminismReport, Divergence},
+            executor::ExecutorImpl,
+            reference::{reference_step, ReferenceStep},
+            syscall::{GuestIoError, SyscallGuardError},
+            working_set::{SegmentPo2Estimate, SegmentPo2Report, MAX_PAGING_OVERHEAD},
+        },
+        prove::{
+            cycles_for_po2,
+            debug::{compare_hals, HalArtifact, HalComparison},
+            get_prover_server, get_prover_server_for,
+            loader::Loader,
+            session_prover::SessionProver,
+            HalPair, ProverServer,
+        },
+        session::{
+            FileSegmentRef, Segment, SegmentAction, SegmentRef, Session, SessionEvents,
+            SimpleSegmentRef,
+        },
     },
 };
 #[cfg(all(not(target_os = "zkvm"), feature = "client"))]
 pub use self::host::{
     api::{
-        client::Client as ApiClient, Asset, AssetRequest, Binary, Connector, SegmentInfo,
-        SessionInfo,
+        client::Client as ApiClient, convert::RECEIPT_FORMAT_VERSION, decode::DecodeLimits, Asset,
+        AssetRequest, Binary, Connector, FailedCheck, HeapStats, SegmentInfo, SessionInfo,
     },
     client::{
-        env::{ExecutorEnv, ExecutorEnvBuilder},
+        cycle_attributor::{CycleAttributor, CycleReportEntry},
+        env::{CheckPolicy, ExecutorEnv, ExecutorEnvBuilder},
         exec::TraceEvent,
+        framed::{FramedOutput, FromFrames},
+        host_commit::HostCommits,
+        layout::{EntryPoint, GuestLayout},
+        line_writer::LineWriter,
+        manifest::GuestEnvManifest,
+        page_map::{dump_layout, TouchedPages},
+        posix_io::{
+            LazyFdContext, LogLimits, LogPolicy, OutputSizeHints, OverflowPolicy, WriteBackoff,
+        },
         prove::{
-            bonsai::BonsaiProver, default_executor, default_prover, external::ExternalProver,
-            Executor, Prover, ProverOpts,
+            bonsai::BonsaiProver,
+            cache::{CachedReceipt, FileProofCache, ProofCache, ProofCacheKey},
+            default_executor, default_prover,
+            external::ExternalProver,
+            prove_opts::ProveOpts,
+            report::{ExecutionReport, IoReport, PhaseTimes, SegmentReport, REPORT_VERSION},
+            setup::{OptsError, SetupErrors},
+            EnvPrefix, Executor, Prover, VerifyPolicy, MAX_SEGMENT_LIMIT_PO2,
+            MIN_SEGMENT_LIMIT_PO2,
         },
+        provenance::{AnnotationError, ZKVM_CREATED_AT, ZKVM_HOST_INFO, ZKVM_PRODUCER_ID},
+        slice_io::{SliceIo, SliceIoFill, SyscallGuard, SyscallSource},
+        symbol_stats::SymbolStats,
+        trace_file::{TraceKind, TraceReader, TraceRecord, SYNC_INTERVAL, TRACE_FILE_VERSION},
     },
 };
 #[cfg(not(target_os = "zkvm"))]
 pub use self::host::{
-    control_id::POSEIDON_CONTROL_ID,
+    control_id::{RawControlId, POSEIDON_CONTROL_ID},
+    control_registry::control_id_for,
     receipt::{
-        Assumption, CompositeReceipt, InnerReceipt, Journal, Receipt, SegmentReceipt,
-        SuccinctReceipt, VerifierContext,
+        Assumption, CompositeReceipt, InnerReceipt, Journal, PrunedReceipt, Receipt,
+        ReceiptPolicyError, SegmentReceipt, SuccinctReceipt, VerifierContext,
     },
     recursion::ALLOWED_IDS_ROOT,
 };
@@ -122,3 +216,38 @@ pub fn is_dev_mode() -> bool {
 
     cfg!(not(feature = "disable-dev-mode")) && is_env_set
 }
+
+/// Whether dev mode may be used when proving and verifying.
+///
+/// By default, dev mode is controlled by the `RISC0_DEV_MODE` environment
+/// variable (see [is_dev_mode]). Applications that embed the zkVM and must not
+/// let the environment weaken proving or verification should set
+/// [SecurityPolicy::Enforced] on both the `ProverOpts` and the
+/// `VerifierContext` they use.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ::serde::Serialize, ::serde::Deserialize)]
+pub enum SecurityPolicy {
+    /// Never use dev mode, regardless of the environment.
+    Enforced,
+
+    /// Use dev mode if the `RISC0_DEV_MODE` environment variable is set.
+    #[default]
+    DevAllowed,
+
+    /// Always use dev mode, unless it is disabled with the `disable-dev-mode`
+    /// feature flag.
+    DevForced,
+}
+
+impl SecurityPolicy {
+    /// Returns `true` if dev mode is enabled under this policy.
+    pub fn is_dev_mode(&self) -> bool {
+        match self {
+            SecurityPolicy::Enforced => false,
+            #[cfg(feature = "std")]
+            SecurityPolicy::DevAllowed => is_dev_mode(),
+            #[cfg(not(feature = "std"))]
+            SecurityPolicy::DevAllowed => false,
+            SecurityPolicy::DevForced => cfg!(not(feature = "disable-dev-mode")),
+        }
+    }
+}
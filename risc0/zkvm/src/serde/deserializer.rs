@@ -56,12 +56,48 @@ impl WordRead for &[u32] {
     }
 
     fn read_padded_bytes(&mut self, out: &mut [u8]) -> Result<()> {
-        let bytes: &[u8] = bytemuck::cast_slice(self);
-        if out.len() > bytes.len() {
+        let words = align_up(out.len(), WORD_SIZE) / WORD_SIZE;
+        if words > self.len() {
             Err(Error::DeserializeUnexpectedEnd)
         } else {
-            out.clone_from_slice(&bytes[..out.len()]);
-            (_, *self) = self.split_at(align_up(out.len(), WORD_SIZE) / WORD_SIZE);
+            // Bytes are packed into words in little-endian order, whatever the
+            // byte order of the host.
+            for (bytes, word) in out.chunks_mut(WORD_SIZE).zip(self.iter()) {
+                bytes.clone_from_slice(&word.to_le_bytes()[..bytes.len()]);
+            }
+            (_, *self) = self.split_at(words);
+            Ok(())
+        }
+    }
+}
+
+/// Reads words from a little-endian byte stream.
+///
+/// The stream may end with a partial word, in which case the missing bytes
+/// are taken to be zero. This is only accepted when the partial word is the
+/// tail of a byte sequence (such as a `str`) whose padding was trimmed; a
+/// word read as a whole number that runs past the end of the stream is
+/// rejected with [Error::DeserializeUnexpectedEnd].
+impl WordRead for &[u8] {
+    fn read_words(&mut self, out: &mut [u32]) -> Result<()> {
+        let len = out.len() * WORD_SIZE;
+        if len > self.len() {
+            return Err(Error::DeserializeUnexpectedEnd);
+        }
+        for (word, bytes) in out.iter_mut().zip(self.chunks_exact(WORD_SIZE)) {
+            *word = u32::from_le_bytes(bytes.try_into().unwrap());
+        }
+        (_, *self) = self.split_at(len);
+        Ok(())
+    }
+
+    fn read_padded_bytes(&mut self, out: &mut [u8]) -> Result<()> {
+        if out.len() > self.len() {
+            Err(Error::DeserializeUnexpectedEnd)
+        } else {
+            out.clone_from_slice(&self[..out.len()]);
+            let padded = align_up(out.len(), WORD_SIZE).min(self.len());
+            (_, *self) = self.split_at(padded);
             Ok(())
         }
     }
@@ -72,22 +108,33 @@ impl WordRead for &[u32] {
 /// Deserialize `slice` into type `T`. Returns an `Err` if deserialization isn't
 /// possible, such as if `slice` is not the serialized form of an object of type
 /// `T`.
+///
+/// Slices of `u32` (or other word-aligned types) are read directly; anything
+/// else is read with [from_bytes].
 pub fn from_slice<T: DeserializeOwned, P: Pod>(slice: &[P]) -> Result<T> {
-    match bytemuck::try_cast_slice(slice) {
+    match bytemuck::try_cast_slice::<P, u32>(slice) {
         Ok(slice) => {
             let mut deserializer = Deserializer::new(slice);
             T::deserialize(&mut deserializer)
         }
-        // P is u8 or another value without word-alignment. Data must be copied.
-        Err(bytemuck::PodCastError::TargetAlignmentGreaterAndInputNotAligned) => {
-            let vec = bytemuck::allocation::pod_collect_to_vec::<P, u32>(slice);
-            let mut deserializer = Deserializer::new(vec.as_slice());
-            T::deserialize(&mut deserializer)
-        }
-        Err(ref e) => panic!("failed to cast or read slice as [u32]: {}", e),
+        // P is u8 or another value without word-alignment.
+        Err(_) => from_bytes(bytemuck::cast_slice(slice)),
     }
 }
 
+/// Deserialize a byte slice into the specified type.
+///
+/// The serialized form of a value is always a whole number of words, but
+/// byte streams such as a journal may have had the padding after a trailing
+/// byte sequence trimmed. `bytes` may therefore end with a partial word, which
+/// is zero-extended, as long as only padding is missing: a length that cuts
+/// into a word that is read as a number returns
+/// [Error::DeserializeUnexpectedEnd].
+pub fn from_bytes<T: DeserializeOwned>(bytes: &[u8]) -> Result<T> {
+    let mut deserializer = Deserializer::new(bytes);
+    T::deserialize(&mut deserializer)
+}
+
 /// Enables deserializing from a WordRead
 pub struct Deserializer<'de, R: WordRead + 'de> {
     reader: R,
@@ -42,7 +42,7 @@ mod deserializer;
 mod err;
 mod serializer;
 
-pub use deserializer::{from_slice, Deserializer, WordRead};
+pub use deserializer::{from_bytes, from_slice, Deserializer, WordRead};
 pub use err::{Error, Result};
 pub use serializer::{to_vec, to_vec_with_capacity, Serializer, WordWrite};
 
@@ -50,7 +50,7 @@ pub use serializer::{to_vec, to_vec_with_capacity, Serializer, WordWrite};
 mod tests {
     use alloc::{collections::BTreeMap, string::String, vec, vec::Vec};
 
-    use crate::serde::{from_slice, to_vec};
+    use crate::serde::{from_bytes, from_slice, to_vec, Error};
 
     #[test]
     fn test_vec_round_trip() {
@@ -60,6 +60,27 @@ mod tests {
         assert_eq!(input, output);
     }
 
+    #[test]
+    fn test_str_byte_order() {
+        // The bytes of a string are packed into words in little-endian order,
+        // so that the words hold the same values on a host of either byte
+        // order.
+        let words = to_vec("abcde").unwrap();
+        assert_eq!(words, [5, 0x6463_6261, 0x65]);
+        let output: String = from_slice(&words).unwrap();
+        assert_eq!(output, "abcde");
+
+        // The same words as a big-endian host holds them in memory, swapped
+        // by hand, read back as words.
+        let be_memory = [0, 0, 0, 5, 0x64, 0x63, 0x62, 0x61, 0, 0, 0, 0x65];
+        let words: Vec<u32> = be_memory
+            .chunks_exact(4)
+            .map(|bytes| u32::from_be_bytes(bytes.try_into().unwrap()))
+            .collect();
+        let output: String = from_slice(&words).unwrap();
+        assert_eq!(output, "abcde");
+    }
+
     #[test]
     fn test_map_round_trip() {
         let input: BTreeMap<String, u32> =
@@ -76,4 +97,38 @@ mod tests {
         let output: (u32, u64) = from_slice(data.as_slice()).unwrap();
         assert_eq!(input, output);
     }
+
+    #[test]
+    fn test_from_bytes_trimmed_padding() {
+        // Cover every possible remainder of the unpadded length modulo 4.
+        for len in 0..8 {
+            let input = (len as u32, "x".repeat(len));
+            let words = to_vec(&input).unwrap();
+            let bytes: &[u8] = bytemuck::cast_slice(&words);
+
+            let output: (u32, String) = from_bytes(bytes).unwrap();
+            assert_eq!(input, output);
+            let output: (u32, String) = from_slice(bytes).unwrap();
+            assert_eq!(input, output);
+
+            // Trim the padding after the string.
+            let trimmed = &bytes[..2 * 4 + len];
+            let output: (u32, String) = from_bytes(trimmed).unwrap();
+            assert_eq!(input, output);
+            let output: (u32, String) = from_slice(trimmed).unwrap();
+            assert_eq!(input, output);
+        }
+    }
+
+    #[test]
+    fn test_from_bytes_truncated() {
+        let words = to_vec(&(1u32, 2u64)).unwrap();
+        let bytes: &[u8] = bytemuck::cast_slice(&words);
+        for len in 0..bytes.len() {
+            assert_eq!(
+                from_bytes::<(u32, u64)>(&bytes[..len]),
+                Err(Error::DeserializeUnexpectedEnd)
+            );
+        }
+    }
 }
@@ -0,0 +1,191 @@
+// Copyright 2023 RISC Zero, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! SHA-256 hashes computed by the host on behalf of the guest.
+//!
+//! A guest that hashes a lot of data can send it to the host with the
+//! `SYS_SHA256` syscall, which the host enables with
+//! `ExecutorEnvBuilder::sha_accel(true)`, rather than pay the cycles of hashing it
+//! itself. The digest the host returns is asserted by the host, not proven;
+//! see `guest::sha_accel` for the guest side and how it can check the host.
+//!
+//! Each request is a little-endian operation code, a little-endian stream ID
+//! and the data for the operation; see [encode_request]. The host keeps a
+//! [Sha256Stream] per ID, so the guest sends its data in as many requests as
+//! it likes and never needs all of it in memory at once.
+
+use alloc::vec::Vec;
+
+use crate::sha::{Block, Digest, Impl, Sha256 as _, BLOCK_BYTES, SHA256_INIT};
+
+/// The most data the guest sends to the host in one request, in bytes.
+pub const MAX_CHUNK_BYTES: usize = 64 * 1024;
+
+/// Starts a stream. The host responds with the 4-byte little-endian ID of
+/// the new stream; the stream ID of the request is ignored.
+pub const OP_INIT: u32 = 0;
+
+/// Adds the data of the request to the stream. The host responds with the
+/// 32-byte [Sha256Stream::state] after the data.
+pub const OP_UPDATE: u32 = 1;
+
+/// Ends the stream. The host responds with the 32-byte digest of the data
+/// added to it.
+pub const OP_FINAL: u32 = 2;
+
+/// The request for `op` on the stream `id`, with `data`.
+pub fn encode_request(op: u32, id: u32, data: &[u8]) -> Vec<u8> {
+    let mut request = Vec::with_capacity(8 + data.len());
+    request.extend_from_slice(&op.to_le_bytes());
+    request.extend_from_slice(&id.to_le_bytes());
+    request.extend_from_slice(data);
+    request
+}
+
+/// The operation, stream ID and data of `request`, or `None` if it is too
+/// short to be one.
+pub fn decode_request(request: &[u8]) -> Option<(u32, u32, &[u8])> {
+    let op = request.get(0..4)?.try_into().ok()?;
+    let id = request.get(4..8)?.try_into().ok()?;
+    Some((
+        u32::from_le_bytes(op),
+        u32::from_le_bytes(id),
+        &request[8..],
+    ))
+}
+
+/// A SHA-256 hash of data added a piece at a time.
+///
+/// Only whole blocks go into the state; the bytes past the last whole block
+/// are held back until more data fills the block or the stream ends.
+#[derive(Clone, Debug)]
+pub struct Sha256Stream {
+    state: Digest,
+    tail: Vec<u8>,
+    len: u64,
+}
+
+impl Default for Sha256Stream {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Sha256Stream {
+    /// A stream with no data.
+    pub fn new() -> Self {
+        Self {
+            state: SHA256_INIT,
+            tail: Vec::new(),
+            len: 0,
+        }
+    }
+
+    /// The state of the hash after the whole blocks added so far.
+    pub fn state(&self) -> Digest {
+        self.state
+    }
+
+    /// Add `data` to the stream, and return the new [Sha256Stream::state].
+    pub fn update(&mut self, data: &[u8]) -> Digest {
+        let (first, rest) = self.split(data);
+        let blocks: Vec<Block> = first
+            .into_iter()
+            .chain(
+                rest.chunks_exact(BLOCK_BYTES)
+                    .map(bytemuck::pod_read_unaligned),
+            )
+            .collect();
+        if !blocks.is_empty() {
+            self.state = *Impl::compress_slice(&self.state, &blocks);
+        }
+        self.state
+    }
+
+    /// Add `data` to the stream as [Sha256Stream::update] does, but take
+    /// `state` as the result rather than computing it.
+    pub fn advance(&mut self, data: &[u8], state: Digest) {
+        self.split(data);
+        self.state = state;
+    }
+
+    /// The digest of the data added to the stream.
+    pub fn finalize(mut self) -> Digest {
+        // FIPS 180-4: an end marker, zeros up to 8 bytes short of a whole
+        // block, then the length in bits.
+        let bits = self.len * 8;
+        let zeros = (2 * BLOCK_BYTES - (self.tail.len() + 9)) % BLOCK_BYTES;
+        let mut trailer = Vec::with_capacity(zeros + 9);
+        trailer.push(0x80);
+        trailer.resize(zeros + 1, 0);
+        trailer.extend_from_slice(&bits.to_be_bytes());
+        self.update(&trailer)
+    }
+
+    // Add `data` to the stream, holding back the bytes past its last whole
+    // block. Returns the held-back block, if `data` completes it, and the
+    // whole blocks of the rest of `data`.
+    fn split<'d>(&mut self, data: &'d [u8]) -> (Option<Block>, &'d [u8]) {
+        self.len += data.len() as u64;
+        let mut data = data;
+        let mut first = None;
+        if !self.tail.is_empty() {
+            let n = (BLOCK_BYTES - self.tail.len()).min(data.len());
+            self.tail.extend_from_slice(&data[..n]);
+            data = &data[n..];
+            if self.tail.len() == BLOCK_BYTES {
+                first = Some(bytemuck::pod_read_unaligned(&self.tail));
+                self.tail.clear();
+            }
+        }
+        let whole = data.len() - data.len() % BLOCK_BYTES;
+        self.tail.extend_from_slice(&data[whole..]);
+        (first, &data[..whole])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stream_matches_one_shot() {
+        let data: Vec<u8> = (0..1000_u32).map(|i| (i * 31 % 251) as u8).collect();
+        for len in [0, 1, 55, 56, 63, 64, 65, 119, 128, 1000] {
+            let expected = *Impl::hash_bytes(&data[..len]);
+            for piece in [1, 7, 64, 100, 1000] {
+                let mut stream = Sha256Stream::new();
+                for chunk in data[..len].chunks(piece) {
+                    stream.update(chunk);
+                }
+                assert_eq!(stream.finalize(), expected, "len {len}, pieces of {piece}");
+            }
+
+            // A stream advanced to the right states gives the same digest.
+            let mut reference = Sha256Stream::new();
+            let mut advanced = Sha256Stream::new();
+            for chunk in data[..len].chunks(100) {
+                advanced.advance(chunk, reference.update(chunk));
+            }
+            assert_eq!(advanced.finalize(), expected);
+        }
+    }
+
+    #[test]
+    fn requests() {
+        let request = encode_request(OP_UPDATE, 7, b"abc");
+        assert_eq!(decode_request(&request), Some((OP_UPDATE, 7, &b"abc"[..])));
+        assert_eq!(decode_request(&request[..7]), None);
+    }
+}
@@ -14,7 +14,10 @@
 
 use clap::Parser;
 use risc0_zkp::{
-    core::hash::{blake2b::Blake2bCpuHashSuite, poseidon::PoseidonHashSuite, sha::Sha256HashSuite},
+    core::hash::{
+        blake2b::Blake2bCpuHashSuite, poseidon::PoseidonHashSuite, poseidon2::Poseidon2HashSuite,
+        sha::Sha256HashSuite,
+    },
     field::baby_bear::BabyBear,
     hal::cpu::CpuHal,
 };
@@ -32,6 +35,8 @@ impl Bootstrap {
             loader.compute_control_id(&CpuHal::new(PoseidonHashSuite::new_suite()));
         let control_id_blake2b =
             loader.compute_control_id(&CpuHal::new(Blake2bCpuHashSuite::new_suite()));
+        let control_id_poseidon2 =
+            loader.compute_control_id(&CpuHal::new(Poseidon2HashSuite::new_suite()));
         let contents = format!(
             include_str!("control_id.rs"),
             control_id_sha256[0],
@@ -67,6 +72,17 @@ impl Bootstrap {
             control_id_blake2b[8],
             control_id_blake2b[9],
             control_id_blake2b[10],
+            control_id_poseidon2[0],
+            control_id_poseidon2[1],
+            control_id_poseidon2[2],
+            control_id_poseidon2[3],
+            control_id_poseidon2[4],
+            control_id_poseidon2[5],
+            control_id_poseidon2[6],
+            control_id_poseidon2[7],
+            control_id_poseidon2[8],
+            control_id_poseidon2[9],
+            control_id_poseidon2[10],
         );
         println!("{contents}");
         std::fs::write("risc0/zkvm/src/host/control_id.rs", contents).unwrap();
@@ -13,7 +13,7 @@
 // limitations under the License.
 
 use clap::Parser;
-use risc0_zkvm::{get_prover_server, ExecutorEnv, ProverOpts};
+use risc0_zkvm::{get_prover_server_for, ExecutorEnv, ProveOpts};
 use risc0_zkvm_methods::{FIB_ELF, FIB_ID};
 
 #[derive(Parser)]
@@ -23,11 +23,11 @@ impl GenReceipt {
     pub fn run(&self) {
         let iterations = 100;
         let env = ExecutorEnv::builder()
-            .write_slice(&[iterations])
+            .write_words(&[iterations])
             .build()
             .unwrap();
-        let opts = ProverOpts::default();
-        let receipt = get_prover_server(&opts)
+        let opts = ProveOpts::default();
+        let receipt = get_prover_server_for(&opts)
             .unwrap()
             .prove_elf(env, FIB_ELF)
             .unwrap();